@@ -0,0 +1,14 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+
+// The loader only depends on the standard library, include it directly.
+#[path = "../../src/intern/image_load/mod.rs"]
+#[allow(dead_code)]
+mod image_load;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok((size, _color_max, pixels)) = image_load::from_bytes_any(data) {
+        assert!(size[0] * size[1] <= image_load::IMAGE_PIXELS_MAX);
+        assert!(size[0] * size[1] == pixels.len());
+    }
+});
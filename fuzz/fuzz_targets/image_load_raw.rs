@@ -0,0 +1,25 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+
+// The loader only depends on the standard library, include it directly.
+#[path = "../../src/intern/image_load/mod.rs"]
+#[allow(dead_code)]
+mod image_load;
+
+fuzz_target!(|data: &[u8]| {
+    // Raw data has no header, take the layout from the first bytes.
+    if data.len() < 5 {
+        return;
+    }
+    let raw = image_load::RawFormat {
+        size: [
+            ((data[0] as usize) << 8 | data[1] as usize) + 1,
+            ((data[2] as usize) << 8 | data[3] as usize) + 1,
+        ],
+        depth: if data[4] & 1 == 0 { 8 } else { 16 },
+    };
+    if let Ok((size, _color_max, pixels)) = image_load::from_bytes_raw(&data[5..], &raw) {
+        assert!(size == raw.size);
+        assert!(size[0] * size[1] == pixels.len());
+    }
+});
@@ -0,0 +1,125 @@
+///
+/// Split fitted curves at inflection points.
+///
+/// Some font formats & CAM post-processors expect the curvature of each segment
+/// to bend in one direction, so segments which change direction are split in two (or three).
+///
+
+const DIMS: usize = ::intern::math_vector::DIMS;
+
+use curve_types::{
+    CubicSegment,
+    Drawing,
+    Knot,
+    Path,
+};
+
+/// Ignore inflections this close to either end of a segment (as a factor of the segment).
+const INFLECTION_MARGIN: f64 = 1e-3;
+
+fn cross(a: &[f64; DIMS], b: &[f64; DIMS]) -> f64 {
+    a[0] * b[1] - a[1] * b[0]
+}
+
+/// Factors (in ascending order) where the curvature of the segment changes direction.
+pub fn segment_inflections(
+    seg: &CubicSegment,
+) -> Vec<f64> {
+    // With the derivatives written as: `B'(t) = 3(a + 2bt + ct^2)` & `B''(t) = 6(b + ct)`,
+    // the curvature changes sign at the roots of `cross(B'(t), B''(t))`.
+    let mut a = [0.0; DIMS];
+    let mut b = [0.0; DIMS];
+    let mut c = [0.0; DIMS];
+    for j in 0..DIMS {
+        a[j] = seg.p1[j] - seg.p0[j];
+        b[j] = seg.p2[j] - 2.0 * seg.p1[j] + seg.p0[j];
+        c[j] = seg.p3[j] - 3.0 * seg.p2[j] + 3.0 * seg.p1[j] - seg.p0[j];
+    }
+    let q2 = cross(&b, &c);
+    let q1 = cross(&a, &c);
+    let q0 = cross(&a, &b);
+
+    // Scale the tolerance by the size of the segment.
+    let scale = {
+        let v = [a[0].abs(), a[1].abs(), b[0].abs(), b[1].abs(), c[0].abs(), c[1].abs()];
+        v.iter().cloned().fold(0.0, f64::max)
+    };
+    let eps = scale * scale * 1e-12;
+
+    let mut roots: Vec<f64> = vec![];
+    if q2.abs() <= eps {
+        if q1.abs() > eps {
+            roots.push(-q0 / q1);
+        }
+    } else {
+        let d = q1 * q1 - 4.0 * q2 * q0;
+        // A double root touches zero without changing direction.
+        if d > 0.0 {
+            let d_sqrt = d.sqrt();
+            roots.push((-q1 - d_sqrt) / (2.0 * q2));
+            roots.push((-q1 + d_sqrt) / (2.0 * q2));
+        }
+    }
+    roots.retain(|t| *t > INFLECTION_MARGIN && *t < 1.0 - INFLECTION_MARGIN);
+    roots.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    return roots;
+}
+
+/// Split segments of a path at their inflection points,
+/// returns the number of knots added.
+pub fn path_split_inflections(
+    path: &mut Path,
+) -> usize {
+    let segments_len = path.segments_len();
+    let knots_len = path.knots.len();
+    let mut knots_src = path.knots.clone();
+    let mut knots_dst: Vec<Knot> = Vec::with_capacity(knots_len);
+    for i in 0..knots_len {
+        knots_dst.push(knots_src[i]);
+        if i >= segments_len {
+            continue;
+        }
+        // Splitting only changes handles of the knots either side of a segment,
+        // so the original segments can be used.
+        let seg = path.segment(i);
+        let factors = segment_inflections(&seg);
+        if factors.is_empty() {
+            continue;
+        }
+
+        let mut seg_rest = seg;
+        let mut t_prev = 0.0;
+        for t in factors {
+            let (seg_a, seg_b) = seg_rest.split((t - t_prev) / (1.0 - t_prev));
+            knots_dst.last_mut().unwrap().handle_next = seg_a.p1;
+            knots_dst.push(Knot {
+                handle_prev: seg_a.p2,
+                co: seg_a.p3,
+                handle_next: seg_b.p1,
+            });
+            seg_rest = seg_b;
+            t_prev = t;
+        }
+        // The remaining segment ends at the next knot.
+        if i + 1 != knots_len {
+            knots_src[i + 1].handle_prev = seg_rest.p2;
+        } else {
+            knots_dst[0].handle_prev = seg_rest.p2;
+        }
+    }
+    let added_len = knots_dst.len() - knots_len;
+    path.knots = knots_dst;
+    return added_len;
+}
+
+/// Apply `path_split_inflections` to all paths,
+/// returns the number of knots added.
+pub fn drawing_split_inflections(
+    drawing: &mut Drawing,
+) -> usize {
+    let mut added_len = 0;
+    for path in &mut drawing.paths {
+        added_len += path_split_inflections(path);
+    }
+    return added_len;
+}
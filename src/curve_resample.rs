@@ -0,0 +1,111 @@
+///
+/// Resample fitted curves to points spaced uniformly by arc length.
+///
+/// For consumers which need uniform sampling (animation paths, laser galvanometers... etc)
+/// rather than bezier curves.
+///
+
+const DIMS: usize = ::intern::math_vector::DIMS;
+
+use ::intern::math_vector::{
+    madd_vnvn_fl,
+    msub_vnvn_fl,
+    sub_vnvn,
+};
+
+use curve_types::{
+    Drawing,
+    Knot,
+    Path,
+};
+
+/// Points at `lengths` along the path (which must be sorted & within the path length).
+fn path_points_at_lengths(
+    path: &Path,
+    lengths: &[f64],
+) -> Vec<[f64; DIMS]> {
+    let mut points: Vec<[f64; DIMS]> = Vec::with_capacity(lengths.len());
+    let mut lengths_iter = lengths.iter().peekable();
+    let mut seg_length_start = 0.0;
+    for seg in path.segments() {
+        let seg_length = seg.length();
+        while let Some(&&length) = lengths_iter.peek() {
+            if length > seg_length_start + seg_length {
+                break;
+            }
+            points.push(seg.eval(seg.param_at_length(length - seg_length_start)));
+            lengths_iter.next();
+        }
+        seg_length_start += seg_length;
+    }
+    // Account for precision loss summing the segment lengths.
+    for _ in lengths_iter {
+        points.push(path.segment(path.segments_len() - 1).p3);
+    }
+    return points;
+}
+
+/// Resample the path to `points_len` points, returns false when the path has no length.
+///
+/// * `use_curve` - when true, handles make a smooth curve through the points
+///   (Catmull-Rom splines, converted to bezier curves), otherwise the points are joined by lines.
+pub fn path_resample(
+    path: &mut Path,
+    points_len: usize,
+    use_curve: bool,
+) -> bool {
+    debug_assert!(points_len >= 2);
+    let length = path.length();
+    if !(length > 0.0) {
+        return false;
+    }
+    // Cyclic paths don't repeat the first point at the end.
+    let step = length / (if path.cyclic { points_len } else { points_len - 1 }) as f64;
+    let lengths: Vec<f64> = (0..points_len).map(|i| i as f64 * step).collect();
+    let points = path_points_at_lengths(path, &lengths);
+
+    path.knots = points.iter().enumerate().map(|(i, co)| {
+        if !use_curve {
+            return Knot { handle_prev: *co, co: *co, handle_next: *co };
+        }
+        // Open ends use the point itself in place of the missing neighbor.
+        let co_prev = if i != 0 {
+            &points[i - 1]
+        } else if path.cyclic {
+            &points[points_len - 1]
+        } else {
+            co
+        };
+        let co_next = if i + 1 != points_len {
+            &points[i + 1]
+        } else if path.cyclic {
+            &points[0]
+        } else {
+            co
+        };
+        let tangent = sub_vnvn(co_next, co_prev);
+        Knot {
+            handle_prev: msub_vnvn_fl(co, &tangent, 1.0 / 6.0),
+            co: *co,
+            handle_next: madd_vnvn_fl(co, &tangent, 1.0 / 6.0),
+        }
+    }).collect();
+    // The points no longer match a shape.
+    path.meta.shape = None;
+    return true;
+}
+
+/// Resample all paths, see: `path_resample`, returns the number of paths resampled.
+pub fn drawing_resample(
+    drawing: &mut Drawing,
+    points_len: usize,
+    use_curve: bool,
+) -> usize {
+    let mut resample_len = 0;
+    for path in &mut drawing.paths {
+        if path_resample(path, points_len, use_curve) {
+            resample_len += 1;
+        }
+    }
+    return resample_len;
+}
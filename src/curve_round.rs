@@ -0,0 +1,202 @@
+///
+/// Round corners of fitted curves.
+///
+/// Corner knots are replaced by a pair of knots joined by a circular arc (a fillet),
+/// the segments either side are trimmed so the arc meets them tangentially.
+///
+
+const DIMS: usize = ::intern::math_vector::DIMS;
+
+use ::intern::math_vector::{
+    dot_vnvn,
+    len_vnvn,
+    normalized_vn,
+    sub_vnvn,
+};
+
+use curve_types::{
+    CubicSegment,
+    Drawing,
+    Knot,
+    Path,
+};
+
+/// Corners which turn less than this (in radians) are left as-is.
+const CORNER_ANGLE_MIN: f64 = 1e-3;
+
+/// Number of steps used to find a point at a distance along a segment.
+const LENGTH_STEPS: usize = 32;
+
+/// Non-zero direction of a segment at its start, falling back to the handles & end-point.
+fn segment_direction_start(
+    seg: &CubicSegment,
+) -> Option<[f64; DIMS]> {
+    for p in &[seg.p1, seg.p2, seg.p3] {
+        if *p != seg.p0 {
+            return Some(normalized_vn(&sub_vnvn(p, &seg.p0)));
+        }
+    }
+    return None;
+}
+
+fn segment_direction_end(
+    seg: &CubicSegment,
+) -> Option<[f64; DIMS]> {
+    return segment_direction_start(&seg.reversed()).map(|d| [-d[0], -d[1]]);
+}
+
+/// The factor along `seg` where the length from the start is `length`.
+fn segment_factor_at_length(
+    seg: &CubicSegment,
+    length: f64,
+) -> f64 {
+    let mut t_min = 0.0;
+    let mut t_max = 1.0;
+    for _ in 0..LENGTH_STEPS {
+        let t = (t_min + t_max) * 0.5;
+        if seg.split(t).0.length() < length {
+            t_min = t;
+        } else {
+            t_max = t;
+        }
+    }
+    return (t_min + t_max) * 0.5;
+}
+
+/// The part of `seg` between the factors `t_start` & `t_end`.
+fn segment_trim(
+    seg: &CubicSegment,
+    t_start: f64,
+    t_end: f64,
+) -> CubicSegment {
+    let seg = if t_end < 1.0 { seg.split(t_end).0 } else { *seg };
+    if t_start > 0.0 {
+        return seg.split(t_start / t_end).1;
+    }
+    return seg;
+}
+
+/// Handle length for a circular arc from `a` to `b`, leaving & arriving in these directions.
+fn arc_handle_length(
+    a: &[f64; DIMS], dir_a: &[f64; DIMS],
+    b: &[f64; DIMS], dir_b: &[f64; DIMS],
+) -> f64 {
+    let chord = len_vnvn(a, b);
+    let angle = dot_vnvn(dir_a, dir_b).max(-1.0).min(1.0).acos();
+    if angle < CORNER_ANGLE_MIN {
+        return chord / 3.0;
+    }
+    let radius = chord / (2.0 * (angle / 2.0).sin());
+    return (4.0 / 3.0) * (angle / 4.0).tan() * radius;
+}
+
+/// Replace corners of a path with arcs of `radius`, returns the number of corners rounded.
+///
+/// The radius is reduced where the segments either side are too short,
+/// so at most half of each segment is used by a corner.
+pub fn path_round_corners(
+    path: &mut Path,
+    radius: f64,
+) -> usize {
+    let knots_len = path.knots.len();
+    let segments_len = path.segments_len();
+    if segments_len == 0 {
+        return 0;
+    }
+    let segments: Vec<CubicSegment> = (0..segments_len).map(|i| path.segment(i)).collect();
+    let lengths: Vec<f64> = segments.iter().map(|seg| seg.length()).collect();
+
+    // The distance to trim from the segments either side of each knot.
+    let trim: Vec<f64> = (0..knots_len).map(|i| {
+        if !path.cyclic && (i == 0 || i + 1 == knots_len) {
+            return 0.0;
+        }
+        if path.knots[i].is_smooth() {
+            return 0.0;
+        }
+        let i_prev = if i != 0 { i - 1 } else { knots_len - 1 };
+        let dirs = (segment_direction_end(&segments[i_prev]),
+                    segment_direction_start(&segments[i]));
+        if let (Some(dir_in), Some(dir_out)) = dirs {
+            let angle = dot_vnvn(&dir_in, &dir_out).max(-1.0).min(1.0).acos();
+            // Cusps (turning back on themselves) can't be rounded.
+            if angle < CORNER_ANGLE_MIN || angle > ::std::f64::consts::PI - CORNER_ANGLE_MIN {
+                return 0.0;
+            }
+            let d = radius * (angle / 2.0).tan();
+            return d.min(lengths[i_prev] / 2.0).min(lengths[i] / 2.0);
+        }
+        return 0.0;
+    }).collect();
+
+    // Trim segments at both ends.
+    let segments_trim: Vec<CubicSegment> = segments.iter().enumerate().map(|(i, seg)| {
+        let i_next = if i + 1 != knots_len { i + 1 } else { 0 };
+        let t_start = if trim[i] != 0.0 {
+            segment_factor_at_length(seg, trim[i])
+        } else {
+            0.0
+        };
+        let t_end = if trim[i_next] != 0.0 {
+            1.0 - segment_factor_at_length(&seg.reversed(), trim[i_next])
+        } else {
+            1.0
+        };
+        segment_trim(seg, t_start, t_end)
+    }).collect();
+
+    let mut rounded_len = 0;
+    let mut knots_dst: Vec<Knot> = Vec::with_capacity(knots_len);
+    for i in 0..knots_len {
+        let k = &path.knots[i];
+        let seg_prev = if path.cyclic || i != 0 {
+            Some(&segments_trim[if i != 0 { i - 1 } else { knots_len - 1 }])
+        } else {
+            None
+        };
+        let seg_next = if i < segments_len { Some(&segments_trim[i]) } else { None };
+
+        if trim[i] != 0.0 {
+            let (seg_prev, seg_next) = (seg_prev.unwrap(), seg_next.unwrap());
+            let a = seg_prev.p3;
+            let b = seg_next.p0;
+            let dir_a = segment_direction_end(seg_prev).unwrap_or_else(
+                || normalized_vn(&sub_vnvn(&b, &a)));
+            let dir_b = segment_direction_start(seg_next).unwrap_or_else(
+                || normalized_vn(&sub_vnvn(&b, &a)));
+            let handle_len = arc_handle_length(&a, &dir_a, &b, &dir_b);
+            knots_dst.push(Knot {
+                handle_prev: seg_prev.p2,
+                co: a,
+                handle_next: [a[0] + dir_a[0] * handle_len, a[1] + dir_a[1] * handle_len],
+            });
+            knots_dst.push(Knot {
+                handle_prev: [b[0] - dir_b[0] * handle_len, b[1] - dir_b[1] * handle_len],
+                co: b,
+                handle_next: seg_next.p1,
+            });
+            rounded_len += 1;
+        } else {
+            knots_dst.push(Knot {
+                handle_prev: seg_prev.map_or(k.handle_prev, |seg| seg.p2),
+                co: k.co,
+                handle_next: seg_next.map_or(k.handle_next, |seg| seg.p1),
+            });
+        }
+    }
+    path.knots = knots_dst;
+    return rounded_len;
+}
+
+/// Apply `path_round_corners` to all paths,
+/// returns the number of corners rounded.
+pub fn drawing_round_corners(
+    drawing: &mut Drawing,
+    radius: f64,
+) -> usize {
+    let mut rounded_len = 0;
+    for path in &mut drawing.paths {
+        rounded_len += path_round_corners(path, radius);
+    }
+    return rounded_len;
+}
@@ -0,0 +1,327 @@
+///
+/// Recognize fitted paths as geometric primitives.
+///
+/// Paths which match a circle, ellipse, rectangle or line (within the error threshold)
+/// are replaced by an exact curve of the shape, which is stored so writers can use
+/// native elements in place of the curves.
+///
+
+const DIMS: usize = ::intern::math_vector::DIMS;
+
+use ::intern::math_vector::{
+    len_vnvn,
+};
+
+use curve_types::{
+    Drawing,
+    Knot,
+    Path,
+    Shape,
+};
+
+/// Flatten paths with this factor of the error threshold.
+const FLATTEN_FACTOR: f64 = 0.1;
+
+/// Handle length (as a factor of the radius) for a quarter circle.
+const QUARTER_ARC_HANDLE: f64 = 0.552_284_749_830_793_4;
+
+fn cross(a: &[f64; DIMS], b: &[f64; DIMS]) -> f64 {
+    a[0] * b[1] - a[1] * b[0]
+}
+
+/// Area, centroid & covariance `[xx, yy, xy]` of the region a polygon bounds.
+fn poly_area_moments(
+    poly: &[[f64; DIMS]],
+) -> (f64, [f64; DIMS], [f64; 3]) {
+    let mut area_x2 = 0.0;
+    let mut c = [0.0; DIMS];
+    // Second moments about the origin.
+    let mut m = [0.0; 3];
+    let mut v_prev = &poly[poly.len() - 1];
+    for v_curr in poly {
+        let (x0, y0, x1, y1) = (v_prev[0], v_prev[1], v_curr[0], v_curr[1]);
+        let a = x0 * y1 - x1 * y0;
+        area_x2 += a;
+        c[0] += (x0 + x1) * a;
+        c[1] += (y0 + y1) * a;
+        m[0] += (x0 * x0 + x0 * x1 + x1 * x1) * a;
+        m[1] += (y0 * y0 + y0 * y1 + y1 * y1) * a;
+        m[2] += (x0 * y1 + 2.0 * x0 * y0 + 2.0 * x1 * y1 + x1 * y0) * a;
+        v_prev = v_curr;
+    }
+    let area = area_x2 / 2.0;
+    if area == 0.0 {
+        return (0.0, c, [0.0; 3]);
+    }
+    c[0] /= 6.0 * area;
+    c[1] /= 6.0 * area;
+    let cov = [
+        m[0] / (12.0 * area) - c[0] * c[0],
+        m[1] / (12.0 * area) - c[1] * c[1],
+        m[2] / (24.0 * area) - c[0] * c[1],
+    ];
+    return (area, c, cov);
+}
+
+/// Match a closed polygon to an ellipse (a circle when possible).
+fn poly_detect_ellipse(
+    poly: &[[f64; DIMS]],
+    center: &[f64; DIMS],
+    cov: &[f64; 3],
+    error_threshold: f64,
+) -> Option<Shape> {
+    // Circle: the average distance from the center.
+    let radius = poly.iter().map(|v| len_vnvn(v, center)).sum::<f64>() / poly.len() as f64;
+    if radius > 0.0 &&
+       poly.iter().all(|v| (len_vnvn(v, center) - radius).abs() <= error_threshold)
+    {
+        return Some(Shape::Ellipse { center: *center, axes: [[radius, 0.0], [0.0, radius]] });
+    }
+
+    // Ellipse: the axes of the covariance, an ellipse with semi-axes `a` & `b`
+    // has a variance of `a^2 / 4` & `b^2 / 4` along its axes.
+    let half_diff = (cov[0] - cov[1]) / 2.0;
+    let d = (half_diff * half_diff + cov[2] * cov[2]).sqrt();
+    let mid = (cov[0] + cov[1]) / 2.0;
+    let (l_major, l_minor) = (mid + d, mid - d);
+    if !(l_minor > 0.0) {
+        return None;
+    }
+    let angle = 0.5 * (2.0 * cov[2]).atan2(cov[0] - cov[1]);
+    let dir = [angle.cos(), angle.sin()];
+    let (a, b) = (2.0 * l_major.sqrt(), 2.0 * l_minor.sqrt());
+    for v in poly {
+        let h = [v[0] - center[0], v[1] - center[1]];
+        let x = h[0] * dir[0] + h[1] * dir[1];
+        let y = h[1] * dir[0] - h[0] * dir[1];
+        let s = ((x / a) * (x / a) + (y / b) * (y / b)).sqrt();
+        // Distance from the ellipse along the ray from its center
+        // (close to the nearest distance).
+        let h_len = (x * x + y * y).sqrt();
+        if s == 0.0 || (h_len - h_len / s).abs() > error_threshold {
+            return None;
+        }
+    }
+    return Some(Shape::Ellipse {
+        center: *center,
+        axes: [[dir[0] * a, dir[1] * a], [-dir[1] * b, dir[0] * b]],
+    });
+}
+
+/// Match a closed polygon to a rectangle aligned to one of `directions`.
+fn poly_detect_rect(
+    poly: &[[f64; DIMS]],
+    directions: &[[f64; DIMS]],
+    error_threshold: f64,
+) -> Option<Shape> {
+    let mut best: Option<(f64, Shape)> = None;
+    for u in directions {
+        let n = [-u[1], u[0]];
+        let mut range = [::std::f64::MAX, -::std::f64::MAX, ::std::f64::MAX, -::std::f64::MAX];
+        for v in poly {
+            let a = v[0] * u[0] + v[1] * u[1];
+            let b = v[0] * n[0] + v[1] * n[1];
+            range = [range[0].min(a), range[1].max(a), range[2].min(b), range[3].max(b)];
+        }
+        // The distance of each point to the nearest side.
+        let mut error_max = 0.0_f64;
+        for v in poly {
+            let a = v[0] * u[0] + v[1] * u[1];
+            let b = v[0] * n[0] + v[1] * n[1];
+            let error = (a - range[0]).min(range[1] - a).min(b - range[2]).min(range[3] - b);
+            error_max = error_max.max(error);
+        }
+        if error_max <= error_threshold && best.as_ref().map_or(true, |b| error_max < b.0) {
+            let w = range[1] - range[0];
+            let h = range[3] - range[2];
+            if w > 0.0 && h > 0.0 {
+                best = Some((error_max, Shape::Rect {
+                    corner: [u[0] * range[0] + n[0] * range[2], u[1] * range[0] + n[1] * range[2]],
+                    edges: [[u[0] * w, u[1] * w], [n[0] * h, n[1] * h]],
+                }));
+            }
+        }
+    }
+    return best.map(|b| b.1);
+}
+
+/// Match an open polygon to a line.
+fn poly_detect_line(
+    poly: &[[f64; DIMS]],
+    error_threshold: f64,
+) -> Option<Shape> {
+    let p0 = poly[0];
+    let p1 = poly[poly.len() - 1];
+    let d = [p1[0] - p0[0], p1[1] - p0[1]];
+    let d_len = len_vnvn(&p0, &p1);
+    if d_len == 0.0 {
+        return None;
+    }
+    for v in poly {
+        let h = [v[0] - p0[0], v[1] - p0[1]];
+        let along = (h[0] * d[0] + h[1] * d[1]) / d_len;
+        if cross(&d, &h).abs() / d_len > error_threshold ||
+           along < -error_threshold || along > d_len + error_threshold
+        {
+            return None;
+        }
+    }
+    return Some(Shape::Line { p0: p0, p1: p1 });
+}
+
+/// Knots which exactly represent the shape.
+pub fn shape_knots(
+    shape: &Shape,
+) -> Vec<Knot> {
+    match *shape {
+        Shape::Ellipse { center, axes } => {
+            let [u, v] = axes;
+            // Points & tangents at each end of the axes.
+            let (u_neg, v_neg) = ([-u[0], -u[1]], [-v[0], -v[1]]);
+            let co_tan = [(u, v), (v, u_neg), (u_neg, v_neg), (v_neg, u)];
+            co_tan.iter().map(|&(e, t)| {
+                let co = [center[0] + e[0], center[1] + e[1]];
+                let h = [t[0] * QUARTER_ARC_HANDLE, t[1] * QUARTER_ARC_HANDLE];
+                Knot {
+                    handle_prev: [co[0] - h[0], co[1] - h[1]],
+                    co: co,
+                    handle_next: [co[0] + h[0], co[1] + h[1]],
+                }
+            }).collect()
+        },
+        Shape::Rect { corner, edges } => {
+            let [e0, e1] = edges;
+            let poly = [
+                corner,
+                [corner[0] + e0[0], corner[1] + e0[1]],
+                [corner[0] + e0[0] + e1[0], corner[1] + e0[1] + e1[1]],
+                [corner[0] + e1[0], corner[1] + e1[1]],
+            ];
+            Path::from_poly(true, &poly).knots
+        },
+        Shape::Line { p0, p1 } => {
+            Path::from_poly(false, &[p0, p1]).knots
+        },
+    }
+}
+
+/// The shape with its curve winding in the direction given by the sign of `area`.
+fn shape_with_winding(
+    shape: Shape,
+    area: f64,
+) -> Shape {
+    match shape {
+        Shape::Ellipse { center, axes } => {
+            if (cross(&axes[0], &axes[1]) < 0.0) != (area < 0.0) {
+                let axis_neg = [-axes[1][0], -axes[1][1]];
+                return Shape::Ellipse { center: center, axes: [axes[0], axis_neg] };
+            }
+        },
+        Shape::Rect { corner, edges } => {
+            if (cross(&edges[0], &edges[1]) < 0.0) != (area < 0.0) {
+                return Shape::Rect { corner: corner, edges: [edges[1], edges[0]] };
+            }
+        },
+        Shape::Line { .. } => {},
+    }
+    return shape;
+}
+
+/// Add points along edges longer than `step`,
+/// since straight segments are flattened to their end-points.
+fn poly_subdivide(
+    poly: &[[f64; DIMS]],
+    is_cyclic: bool,
+    step: f64,
+) -> Vec<[f64; DIMS]> {
+    let mut poly_dst: Vec<[f64; DIMS]> = Vec::with_capacity(poly.len());
+    for i in 0..poly.len() {
+        let v_curr = &poly[i];
+        poly_dst.push(*v_curr);
+        if i + 1 == poly.len() && !is_cyclic {
+            break;
+        }
+        let v_next = &poly[(i + 1) % poly.len()];
+        let steps = (len_vnvn(v_curr, v_next) / step).ceil() as usize;
+        for j in 1..steps {
+            let t = j as f64 / steps as f64;
+            poly_dst.push([
+                v_curr[0] + (v_next[0] - v_curr[0]) * t,
+                v_curr[1] + (v_next[1] - v_curr[1]) * t,
+            ]);
+        }
+    }
+    return poly_dst;
+}
+
+/// Return the shape the path matches within `error_threshold`.
+pub fn path_detect_shape(
+    path: &Path,
+    error_threshold: f64,
+) -> Option<Shape> {
+    let poly = poly_subdivide(
+        &path.flatten(error_threshold * FLATTEN_FACTOR), path.cyclic, error_threshold);
+    if !path.cyclic {
+        if poly.len() < 2 {
+            return None;
+        }
+        return poly_detect_line(&poly, error_threshold);
+    }
+    if poly.len() < 3 {
+        return None;
+    }
+
+    let (area, center, cov) = poly_area_moments(&poly);
+    if area == 0.0 {
+        return None;
+    }
+    let mut shape = poly_detect_ellipse(&poly, &center, &cov, error_threshold);
+    if shape.is_none() {
+        // Candidate orientations from the segments (rectangle sides are fitted as segments).
+        let directions: Vec<[f64; DIMS]> = path.segments().filter_map(|seg| {
+            let len = len_vnvn(&seg.p0, &seg.p3);
+            if len > error_threshold {
+                Some([(seg.p3[0] - seg.p0[0]) / len, (seg.p3[1] - seg.p0[1]) / len])
+            } else {
+                None
+            }
+        }).collect();
+        shape = poly_detect_rect(&poly, &directions, error_threshold);
+    }
+    return shape.map(|shape| shape_with_winding(shape, area));
+}
+
+/// Replace paths which match a shape with the shape's curve, setting `PathMeta::shape`,
+/// returns the number of shapes detected.
+///
+/// Holes & the paths which contain them are skipped,
+/// since writing a separate element would fill the hole.
+pub fn drawing_detect_shapes(
+    drawing: &mut Drawing,
+    error_threshold: f64,
+) -> usize {
+    let bounds_contain = |a: &([f64; DIMS], [f64; DIMS]), b: &([f64; DIMS], [f64; DIMS])| {
+        a.0[0] <= b.0[0] && a.0[1] <= b.0[1] && a.1[0] >= b.1[0] && a.1[1] >= b.1[1]
+    };
+    let hole_bounds: Vec<([f64; DIMS], [f64; DIMS])> = drawing.paths.iter().filter(
+        |path| path.meta.is_hole).map(|path| path.bounds()).collect();
+
+    let mut shapes_len = 0;
+    for path in &mut drawing.paths {
+        if path.meta.is_hole || path.knots.is_empty() {
+            continue;
+        }
+        if !hole_bounds.is_empty() {
+            let bounds = path.bounds();
+            if hole_bounds.iter().any(|b| bounds_contain(&bounds, b)) {
+                continue;
+            }
+        }
+        if let Some(shape) = path_detect_shape(path, error_threshold) {
+            path.knots = shape_knots(&shape);
+            path.meta.shape = Some(shape);
+            shapes_len += 1;
+        }
+    }
+    return shapes_len;
+}
@@ -0,0 +1,284 @@
+///
+/// Post-fit simplification of curves.
+///
+/// Knots are removed from fitted paths when a single segment can replace
+/// the segments on either side without deviating more than a threshold,
+/// using Douglas-Peucker style recursive splitting on the knots.
+///
+
+const DIMS: usize = ::intern::math_vector::DIMS;
+
+use ::intern::math_vector::{
+    dot_vnvn,
+    len_squared_vnvn,
+    len_vnvn,
+    normalized_vn,
+    sub_vnvn,
+};
+
+use curve_types::{
+    CubicSegment,
+    Drawing,
+    Knot,
+    Path,
+};
+
+/// Tolerance used to flatten curves when measuring deviation (relative to the threshold).
+const FLATTEN_FAC: f64 = 0.1;
+
+/// Direction of the handle, falling back to the direction of `co_other`.
+fn handle_direction(
+    co: &[f64; DIMS],
+    handle: &[f64; DIMS],
+    co_other: &[f64; DIMS],
+) -> [f64; DIMS] {
+    if handle != co {
+        return normalized_vn(&sub_vnvn(handle, co));
+    }
+    return normalized_vn(&sub_vnvn(co_other, co));
+}
+
+/// Fit a single segment to `points` keeping the end-points & tangent directions,
+/// (least squares fit of the handle lengths).
+fn segment_fit(
+    p0: &[f64; DIMS], t0: &[f64; DIMS],
+    p3: &[f64; DIMS], t3: &[f64; DIMS],
+    points: &[[f64; DIMS]],
+    params: &[f64],
+) -> CubicSegment {
+    let mut c = [[0.0; 2]; 2];
+    let mut x = [0.0; 2];
+    for (p, u) in points.iter().zip(params) {
+        let s = 1.0 - u;
+        let b = [s * s * s, 3.0 * s * s * u, 3.0 * s * u * u, u * u * u];
+        let mut a1 = [0.0; DIMS];
+        let mut a2 = [0.0; DIMS];
+        let mut tmp = [0.0; DIMS];
+        for j in 0..DIMS {
+            a1[j] = t0[j] * b[1];
+            a2[j] = t3[j] * b[2];
+            tmp[j] = p[j] - (p0[j] * (b[0] + b[1]) + p3[j] * (b[2] + b[3]));
+        }
+        c[0][0] += dot_vnvn(&a1, &a1);
+        c[0][1] += dot_vnvn(&a1, &a2);
+        c[1][1] += dot_vnvn(&a2, &a2);
+        x[0] += dot_vnvn(&a1, &tmp);
+        x[1] += dot_vnvn(&a2, &tmp);
+    }
+
+    let chord = len_vnvn(p0, p3);
+    let det = c[0][0] * c[1][1] - c[0][1] * c[0][1];
+    let mut alpha = [chord / 3.0; 2];
+    if det.abs() > ::std::f64::EPSILON {
+        let alpha_fit = [
+            (x[0] * c[1][1] - x[1] * c[0][1]) / det,
+            (c[0][0] * x[1] - c[0][1] * x[0]) / det,
+        ];
+        // Negative or tiny handles flip or flatten the curve, use the fallback.
+        let alpha_min = chord * 1e-6;
+        if alpha_fit[0] > alpha_min && alpha_fit[1] > alpha_min {
+            alpha = alpha_fit;
+        }
+    }
+
+    let mut seg = CubicSegment { p0: *p0, p1: *p0, p2: *p3, p3: *p3 };
+    for j in 0..DIMS {
+        seg.p1[j] += t0[j] * alpha[0];
+        seg.p2[j] += t3[j] * alpha[1];
+    }
+    return seg;
+}
+
+fn dist_squared_to_segment(
+    p: &[f64; DIMS], l1: &[f64; DIMS], l2: &[f64; DIMS],
+) -> f64 {
+    let u = sub_vnvn(l2, l1);
+    let h = sub_vnvn(p, l1);
+    let u_len_sq = dot_vnvn(&u, &u);
+    let lambda = if u_len_sq != 0.0 { dot_vnvn(&u, &h) / u_len_sq } else { 0.0 };
+    if !(lambda > 0.0) {
+        return len_squared_vnvn(p, l1);
+    } else if !(lambda < 1.0) {
+        return len_squared_vnvn(p, l2);
+    }
+    let mut closest = *l1;
+    for j in 0..DIMS {
+        closest[j] += u[j] * lambda;
+    }
+    return len_squared_vnvn(p, &closest);
+}
+
+fn dist_squared_to_poly(
+    p: &[f64; DIMS], poly: &[[f64; DIMS]],
+) -> f64 {
+    if poly.len() == 1 {
+        return len_squared_vnvn(p, &poly[0]);
+    }
+    let mut d_best = ::std::f64::MAX;
+    for i in 1..poly.len() {
+        d_best = d_best.min(dist_squared_to_segment(p, &poly[i - 1], &poly[i]));
+    }
+    return d_best;
+}
+
+/// Knots between two kept knots, `i_b` may exceed the number of knots for cyclic paths.
+struct Run<'a> {
+    path: &'a Path,
+    i_a: usize,
+    i_b: usize,
+}
+
+impl<'a> Run<'a> {
+    fn knot(&self, i: usize) -> &'a Knot {
+        return &self.path.knots[i % self.path.knots.len()];
+    }
+
+    /// Fit a single segment replacing all segments in the run,
+    /// returns the segment & the deviation squared of each interior knot
+    /// (`None` when the segment deviates more than `threshold`).
+    fn fit(
+        &self,
+        threshold: f64,
+    ) -> (CubicSegment, Option<Vec<f64>>) {
+        let tolerance = threshold * FLATTEN_FAC;
+        let mut points: Vec<[f64; DIMS]> = vec![self.knot(self.i_a).co];
+        for i in self.i_a..self.i_b {
+            self.path.segment(i % self.path.knots.len()).flatten_into(tolerance, &mut points);
+        }
+
+        // Chord length parameterization.
+        let mut params: Vec<f64> = Vec::with_capacity(points.len());
+        let mut length = 0.0;
+        params.push(length);
+        for i in 1..points.len() {
+            length += len_vnvn(&points[i - 1], &points[i]);
+            params.push(length);
+        }
+        if length > 0.0 {
+            for u in &mut params {
+                *u /= length;
+            }
+        }
+
+        let k_a = self.knot(self.i_a);
+        let k_b = self.knot(self.i_b);
+        let seg = segment_fit(
+            &k_a.co, &handle_direction(&k_a.co, &k_a.handle_next, &self.knot(self.i_a + 1).co),
+            &k_b.co, &handle_direction(&k_b.co, &k_b.handle_prev, &self.knot(self.i_b - 1).co),
+            &points, &params,
+        );
+
+        let mut poly_new: Vec<[f64; DIMS]> = vec![seg.p0];
+        seg.flatten_into(tolerance, &mut poly_new);
+
+        let threshold_sq = threshold * threshold;
+        let is_valid = length > 0.0 &&
+            points.iter().all(|p| dist_squared_to_poly(p, &poly_new) <= threshold_sq) &&
+            poly_new.iter().all(|p| dist_squared_to_poly(p, &points) <= threshold_sq);
+        if is_valid {
+            return (seg, None);
+        }
+        let deviation = ((self.i_a + 1)..self.i_b).map(
+            |i| dist_squared_to_poly(&self.knot(i).co, &poly_new)).collect();
+        return (seg, Some(deviation));
+    }
+}
+
+fn path_simplify_knots_run(
+    path: &Path,
+    i_a: usize,
+    i_b: usize,
+    threshold: f64,
+    keep: &mut Vec<bool>,
+) {
+    if i_b - i_a < 2 {
+        return;
+    }
+    let run = Run { path: path, i_a: i_a, i_b: i_b };
+    if let (_, Some(deviation)) = run.fit(threshold) {
+        // Split at the knot furthest from the replacement segment.
+        let mut i_split = 0;
+        for i in 1..deviation.len() {
+            if deviation[i] > deviation[i_split] {
+                i_split = i;
+            }
+        }
+        let i_split = i_a + 1 + i_split;
+        keep[i_split % path.knots.len()] = true;
+        path_simplify_knots_run(path, i_a, i_split, threshold, keep);
+        path_simplify_knots_run(path, i_split, i_b, threshold, keep);
+    } else {
+        for i in (i_a + 1)..i_b {
+            keep[i % path.knots.len()] = false;
+        }
+    }
+}
+
+/// Remove knots which can be removed without the path deviating more than `threshold`,
+/// corners and the end-points of open paths are always kept.
+///
+/// Returns the number of knots removed.
+pub fn path_simplify_knots(
+    path: &mut Path,
+    threshold: f64,
+) -> usize {
+    let knots_len = path.knots.len();
+    if knots_len < 3 {
+        return 0;
+    }
+
+    let mut keep: Vec<bool> = path.knots.iter().map(|k| !k.is_smooth()).collect();
+    if !path.cyclic {
+        keep[0] = true;
+        keep[knots_len - 1] = true;
+    } else if keep.iter().filter(|k| **k).count() < 2 {
+        // Cyclic paths need two knots to split into runs.
+        keep[0] = true;
+        keep[knots_len / 2] = true;
+    }
+
+    let fixed: Vec<usize> = (0..knots_len).filter(|i| keep[*i]).collect();
+    let runs_len = if path.cyclic { fixed.len() } else { fixed.len() - 1 };
+    for i in 0..runs_len {
+        let i_a = fixed[i];
+        let i_b = if i + 1 < fixed.len() { fixed[i + 1] } else { fixed[0] + knots_len };
+        path_simplify_knots_run(path, i_a, i_b, threshold, &mut keep);
+    }
+
+    let removed_len = keep.iter().filter(|k| !**k).count();
+    if removed_len == 0 {
+        return 0;
+    }
+
+    // Update the handles of knots on either side of removed knots,
+    // using the same segments as were checked for deviation.
+    let kept: Vec<usize> = (0..knots_len).filter(|i| keep[*i]).collect();
+    let mut knots_dst: Vec<Knot> = kept.iter().map(|i| path.knots[*i]).collect();
+    let segments_len = if path.cyclic { kept.len() } else { kept.len() - 1 };
+    for i in 0..segments_len {
+        let i_a = kept[i];
+        let i_b = if i + 1 < kept.len() { kept[i + 1] } else { kept[0] + knots_len };
+        if i_b - i_a < 2 {
+            continue;
+        }
+        let (seg, _) = Run { path: path, i_a: i_a, i_b: i_b }.fit(threshold);
+        let i_next = (i + 1) % knots_dst.len();
+        knots_dst[i].handle_next = seg.p1;
+        knots_dst[i_next].handle_prev = seg.p2;
+    }
+    path.knots = knots_dst;
+
+    return removed_len;
+}
+
+/// Apply `path_simplify_knots` to all paths, returns the number of knots removed.
+pub fn drawing_simplify_knots(
+    drawing: &mut Drawing,
+    threshold: f64,
+) -> usize {
+    let mut removed_len = 0;
+    for path in &mut drawing.paths {
+        removed_len += path_simplify_knots(path, threshold);
+    }
+    return removed_len;
+}
@@ -0,0 +1,119 @@
+///
+/// Snap fitted curves to a grid.
+///
+/// Knots are moved to the nearest grid point, unless this causes the curve
+/// to deviate more than the error threshold, in which case the knot is left as-is.
+///
+
+use curve_types::{
+    CubicSegment,
+    Drawing,
+    Knot,
+    Path,
+};
+
+/// Number of steps used to measure the deviation of each segment.
+const DEVIATION_STEPS: usize = 16;
+
+fn snap_value(v: f64, grid: f64) -> f64 {
+    (v / grid).round() * grid
+}
+
+fn snap_co(co: &[f64; 2], grid: f64) -> [f64; 2] {
+    [snap_value(co[0], grid), snap_value(co[1], grid)]
+}
+
+/// Compare segments at matching factors, an upper bound of the deviation between them.
+fn segment_deviation_exceeds(
+    seg_a: &CubicSegment,
+    seg_b: &CubicSegment,
+    threshold: f64,
+) -> bool {
+    let threshold_sq = threshold * threshold;
+    for i in 0..(DEVIATION_STEPS + 1) {
+        let t = i as f64 / DEVIATION_STEPS as f64;
+        let a = seg_a.eval(t);
+        let b = seg_b.eval(t);
+        if (a[0] - b[0]) * (a[0] - b[0]) + (a[1] - b[1]) * (a[1] - b[1]) > threshold_sq {
+            return true;
+        }
+    }
+    return false;
+}
+
+/// Snap knots (and optionally handles) of a path,
+/// returns the number of knots snapped and the number left as-is.
+pub fn path_snap(
+    path: &mut Path,
+    grid: f64,
+    use_handles: bool,
+    error_threshold: f64,
+) -> (usize, usize) {
+    let path_orig = path.clone();
+    let knots_len = path.knots.len();
+    let mut snapped_len = 0;
+    let mut rejected_len = 0;
+    for i in 0..knots_len {
+        let k_prev = path.knots[i];
+        let k_snap = {
+            let co = snap_co(&k_prev.co, grid);
+            if use_handles {
+                Knot {
+                    handle_prev: snap_co(&k_prev.handle_prev, grid),
+                    co: co,
+                    handle_next: snap_co(&k_prev.handle_next, grid),
+                }
+            } else {
+                // Keep the handles relative to the knot.
+                let offset = [co[0] - k_prev.co[0], co[1] - k_prev.co[1]];
+                Knot {
+                    handle_prev: [k_prev.handle_prev[0] + offset[0],
+                                  k_prev.handle_prev[1] + offset[1]],
+                    co: co,
+                    handle_next: [k_prev.handle_next[0] + offset[0],
+                                  k_prev.handle_next[1] + offset[1]],
+                }
+            }
+        };
+        if k_snap == k_prev {
+            continue;
+        }
+        path.knots[i] = k_snap;
+
+        // Check segments on either side against the original path.
+        let mut segments: Vec<usize> = vec![];
+        if path.cyclic || i != 0 {
+            segments.push(if i != 0 { i - 1 } else { knots_len - 1 });
+        }
+        if path.cyclic || i + 1 != knots_len {
+            segments.push(i);
+        }
+        if segments.iter().any(|j| {
+            segment_deviation_exceeds(&path_orig.segment(*j), &path.segment(*j), error_threshold)
+        }) {
+            path.knots[i] = k_prev;
+            rejected_len += 1;
+        } else {
+            snapped_len += 1;
+        }
+    }
+    return (snapped_len, rejected_len);
+}
+
+/// Apply `path_snap` to all paths,
+/// returns the number of knots snapped and the number left as-is.
+pub fn drawing_snap(
+    drawing: &mut Drawing,
+    grid: f64,
+    use_handles: bool,
+    error_threshold: f64,
+) -> (usize, usize) {
+    let mut snapped_len = 0;
+    let mut rejected_len = 0;
+    for path in &mut drawing.paths {
+        let (a, b) = path_snap(path, grid, use_handles, error_threshold);
+        snapped_len += a;
+        rejected_len += b;
+    }
+    return (snapped_len, rejected_len);
+}
@@ -0,0 +1,116 @@
+///
+/// Measure the width of strokes at each knot of center-lines,
+/// so traced handwriting (for example) keeps its thick & thin strokes.
+///
+/// Widths are measured with a distance transform of the binary image,
+/// center-lines are in the middle of the stroke, so the width is twice the distance
+/// to the nearest empty pixel (less half a pixel on either side, for the pixel centers).
+///
+
+const DIMS: usize = ::intern::math_vector::DIMS;
+
+use curve_types::{
+    Drawing,
+    Path,
+};
+
+/// The largest of the 4 pixels around `co` (pixel centers are at integer coordinates),
+/// since center-lines follow the ridge of the distance field,
+/// clamped to the image bounds.
+fn sample_max(
+    values: &[f64],
+    size: &[usize; 2],
+    co: &[f64; DIMS],
+) -> f64 {
+    let x = co[0].max(0.0).min((size[0] - 1) as f64);
+    let y = co[1].max(0.0).min((size[1] - 1) as f64);
+    let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+    let (x1, y1) = (::std::cmp::min(x0 + 1, size[0] - 1), ::std::cmp::min(y0 + 1, size[1] - 1));
+    let value = |x: usize, y: usize| values[x + (y * size[0])];
+    return value(x0, y0).max(value(x1, y0)).max(value(x0, y1)).max(value(x1, y1));
+}
+
+/// Set `PathMeta::widths` for every path in the drawing,
+/// where `image` is the binary image the center-lines were extracted from (before skeletonizing).
+pub fn drawing_measure_widths(
+    drawing: &mut Drawing,
+    image: &[bool],
+    size: &[usize; 2],
+) {
+    use image_hatching::chamfer_distance;
+
+    if size[0] == 0 || size[1] == 0 {
+        return;
+    }
+    let distance = chamfer_distance(image, size, true);
+    for path in drawing.paths.iter_mut() {
+        path.meta.widths = Some(path.knots.iter().map(|k| {
+            // Knots may be slightly outside the stroke, never write widths below a pixel.
+            ((sample_max(&distance, size, &k.co) * 2.0) - 1.0).max(1.0)
+        }).collect());
+    }
+}
+
+/// Return the outline of the stroke, interpolating `widths` between knots.
+///
+/// Open paths return a single polygon (without caps),
+/// cyclic paths return the outer & inner polygons in opposite directions.
+pub fn path_outline(
+    path: &Path,
+    widths: &[f64],
+    tolerance: f64,
+) -> Vec<Vec<[f64; DIMS]>> {
+    use ::intern::math_vector::{
+        madd_vnvn_fl,
+        normalize_vn,
+        sub_vnvn,
+    };
+
+    debug_assert!(widths.len() == path.knots.len());
+    let mut side_l: Vec<[f64; DIMS]> = vec![];
+    let mut side_r: Vec<[f64; DIMS]> = vec![];
+    for (i, seg) in path.segments().enumerate() {
+        let i_next = if i + 1 != path.knots.len() { i + 1 } else { 0 };
+        let steps = seg.flatten_steps(tolerance);
+        // Include the end of the last segment of open paths (otherwise it starts the next).
+        let is_last = !path.cyclic && i_next == path.knots.len() - 1;
+        for step in 0..(if is_last { steps + 1 } else { steps }) {
+            let t = step as f64 / steps as f64;
+            let co = seg.eval(t);
+            let mut tan = seg.eval_derivative(t);
+            // Zero length handles, use the direction of the segment.
+            if tan[0] == 0.0 && tan[1] == 0.0 {
+                tan = sub_vnvn(&seg.p3, &seg.p0);
+            }
+            if normalize_vn(&mut tan) == 0.0 {
+                continue;
+            }
+            let no = [-tan[1], tan[0]];
+            let radius = ((widths[i] * (1.0 - t)) + (widths[i_next] * t)) / 2.0;
+            side_l.push(madd_vnvn_fl(&co, &no, radius));
+            side_r.push(madd_vnvn_fl(&co, &no, -radius));
+        }
+    }
+    side_r.reverse();
+    if path.cyclic {
+        return vec![side_l, side_r];
+    }
+    side_l.extend(side_r);
+    return vec![side_l];
+}
+
+/// Measure widths after fitting, see: `drawing_measure_widths`.
+pub struct StrokeWidthHooks {
+    /// The binary image before it's skeletonized.
+    pub image: Vec<bool>,
+    pub size: [usize; 2],
+}
+
+impl ::TraceHooks for StrokeWidthHooks {
+    fn fit(
+        &self,
+        drawing: &mut Drawing,
+    ) {
+        drawing_measure_widths(drawing, &self.image, &self.size);
+    }
+}
@@ -0,0 +1,113 @@
+///
+/// Make fitted curves symmetrical along a mirror axis.
+///
+/// Each knot is matched with the nearest knot on the opposite side of the axis,
+/// matching pairs are averaged with each others mirrored position,
+/// so matched knots end up mirror images of each other.
+///
+
+use std::collections::HashMap;
+
+use curve_types::{
+    Drawing,
+    Knot,
+};
+use image_symmetry::Axis;
+
+fn mid(a: &[f64; 2], b: &[f64; 2]) -> [f64; 2] {
+    [(a[0] + b[0]) * 0.5, (a[1] + b[1]) * 0.5]
+}
+
+fn sub(a: &[f64; 2], b: &[f64; 2]) -> [f64; 2] {
+    [a[0] - b[0], a[1] - b[1]]
+}
+
+fn len_squared(a: &[f64; 2], b: &[f64; 2]) -> f64 {
+    (a[0] - b[0]) * (a[0] - b[0]) + (a[1] - b[1]) * (a[1] - b[1])
+}
+
+/// Average knots with their mirrored counterpart within `distance`,
+/// returns the number of knots changed.
+///
+/// Only knots which are each others nearest match are changed,
+/// knots on the axis may match themselves.
+pub fn drawing_symmetrize(
+    drawing: &mut Drawing,
+    axis: &Axis,
+    distance: f64,
+) -> usize {
+    // Flat list of knots, `(path_index, knot_index)`.
+    let mut knot_index: Vec<(usize, usize)> = vec![];
+    let mut knots: Vec<Knot> = vec![];
+    for (i, path) in drawing.paths.iter().enumerate() {
+        for (j, k) in path.knots.iter().enumerate() {
+            knot_index.push((i, j));
+            knots.push(*k);
+        }
+    }
+
+    // Bucket knots into a grid, so only nearby knots are checked.
+    let cell_key = |co: &[f64; 2]| {
+        ((co[0] / distance).floor() as i64, (co[1] / distance).floor() as i64)
+    };
+    let mut grid: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+    for (i, k) in knots.iter().enumerate() {
+        grid.entry(cell_key(&k.co)).or_insert_with(|| vec![]).push(i);
+    }
+
+    let distance_sq = distance * distance;
+    let partner: Vec<Option<usize>> = knots.iter().map(|k| {
+        let co_mirror = axis.mirror(&k.co);
+        let key = cell_key(&co_mirror);
+        let mut best: Option<(f64, usize)> = None;
+        for x in (key.0 - 1)..(key.0 + 2) {
+            for y in (key.1 - 1)..(key.1 + 2) {
+                if let Some(cell) = grid.get(&(x, y)) {
+                    for i_other in cell {
+                        let d_sq = len_squared(&co_mirror, &knots[*i_other].co);
+                        if d_sq <= distance_sq && best.map_or(true, |b| d_sq < b.0) {
+                            best = Some((d_sq, *i_other));
+                        }
+                    }
+                }
+            }
+        }
+        best.map(|b| b.1)
+    }).collect();
+
+    let mut knots_dst: Vec<Option<Knot>> = vec![None; knots.len()];
+    for (i, k) in knots.iter().enumerate() {
+        if let Some(i_other) = partner[i] {
+            if partner[i_other] == Some(i) {
+                let k_other = &knots[i_other];
+                let (handle_prev_other, handle_next_other) = {
+                    // Mirroring reverses the direction of outlines, so handles are swapped
+                    // (open paths may run in either direction).
+                    let tan = sub(&k.handle_next, &k.handle_prev);
+                    let tan_other = sub(
+                        &axis.mirror(&k_other.handle_next), &axis.mirror(&k_other.handle_prev));
+                    if tan[0] * tan_other[0] + tan[1] * tan_other[1] <= 0.0 {
+                        (k_other.handle_next, k_other.handle_prev)
+                    } else {
+                        (k_other.handle_prev, k_other.handle_next)
+                    }
+                };
+                knots_dst[i] = Some(Knot {
+                    handle_prev: mid(&k.handle_prev, &axis.mirror(&handle_prev_other)),
+                    co: mid(&k.co, &axis.mirror(&k_other.co)),
+                    handle_next: mid(&k.handle_next, &axis.mirror(&handle_next_other)),
+                });
+            }
+        }
+    }
+
+    let mut changed_len = 0;
+    let mut paths: Vec<_> = drawing.paths.iter_mut().collect();
+    for (k_dst, &(i, j)) in knots_dst.into_iter().zip(&knot_index) {
+        if let Some(k_dst) = k_dst {
+            paths[i].knots[j] = k_dst;
+            changed_len += 1;
+        }
+    }
+    return changed_len;
+}
@@ -0,0 +1,1003 @@
+///
+/// Public types for fitted curves.
+///
+/// Used to pass curves from fitting, through post-processing, to writers.
+///
+
+const DIMS: usize = ::intern::math_vector::DIMS;
+
+use std::collections::LinkedList;
+
+use ::intern::curve_fit_nd::{
+    cubic_calc_point,
+    Cubic,
+};
+use ::intern::math_vector::{
+    interp_vnvn,
+    len_squared_vn,
+    len_vnvn,
+    sub_vnvn,
+};
+
+/// Maximum iterations finding the parameter at a length along a segment.
+const PARAM_AT_LENGTH_ITER_MAX: usize = 32;
+
+/// When `p` is within `tolerance` of the line from `l0` to `l1`,
+/// without extending past either end by more than `tolerance`.
+pub fn is_point_near_line_segment(
+    p: &[f64; DIMS],
+    l0: &[f64; DIMS],
+    l1: &[f64; DIMS],
+    tolerance: f64,
+) -> bool {
+    let dir = sub_vnvn(l1, l0);
+    let dir_len = len_vnvn(l0, l1);
+    if dir_len <= tolerance {
+        return len_vnvn(l0, p) <= tolerance || len_vnvn(l1, p) <= tolerance;
+    }
+    let h = sub_vnvn(p, l0);
+    // Distance along & perpendicular to the line.
+    let along = (h[0] * dir[0] + h[1] * dir[1]) / dir_len;
+    let perp = (h[0] * dir[1] - h[1] * dir[0]) / dir_len;
+    return perp.abs() <= tolerance && along >= -tolerance && along <= dir_len + tolerance;
+}
+
+/// A single cubic bezier segment.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CubicSegment {
+    pub p0: [f64; DIMS],
+    pub p1: [f64; DIMS],
+    pub p2: [f64; DIMS],
+    pub p3: [f64; DIMS],
+}
+
+#[allow(dead_code)]
+impl CubicSegment {
+    pub fn eval(
+        &self, t: f64,
+    ) -> [f64; DIMS] {
+        return cubic_calc_point(&Cubic { p0: self.p0, p1: self.p1, p2: self.p2, p3: self.p3 }, t);
+    }
+
+    /// First derivative.
+    pub fn eval_derivative(
+        &self, t: f64,
+    ) -> [f64; DIMS] {
+        let s = 1.0 - t;
+        let mut v_out = [0.0; DIMS];
+        for j in 0..DIMS {
+            v_out[j] = 3.0 * ((self.p1[j] - self.p0[j]) * s * s +
+                              (self.p2[j] - self.p1[j]) * 2.0 * s * t +
+                              (self.p3[j] - self.p2[j]) * t * t);
+        }
+        return v_out;
+    }
+
+    /// Split at `t`, returning both halves.
+    pub fn split(
+        &self, t: f64,
+    ) -> (CubicSegment, CubicSegment) {
+        let p01 = interp_vnvn(&self.p0, &self.p1, t);
+        let p12 = interp_vnvn(&self.p1, &self.p2, t);
+        let p23 = interp_vnvn(&self.p2, &self.p3, t);
+        let p012 = interp_vnvn(&p01, &p12, t);
+        let p123 = interp_vnvn(&p12, &p23, t);
+        let p0123 = interp_vnvn(&p012, &p123, t);
+        return (
+            CubicSegment { p0: self.p0, p1: p01, p2: p012, p3: p0123 },
+            CubicSegment { p0: p0123, p1: p123, p2: p23, p3: self.p3 },
+        );
+    }
+
+    pub fn reversed(
+        &self,
+    ) -> CubicSegment {
+        CubicSegment { p0: self.p3, p1: self.p2, p2: self.p1, p3: self.p0 }
+    }
+
+    /// Tight bounds `(min, max)` of the curve (not the control points).
+    pub fn bounds(
+        &self,
+    ) -> ([f64; DIMS], [f64; DIMS]) {
+        let mut min = self.p0;
+        let mut max = self.p0;
+        for j in 0..DIMS {
+            min[j] = min[j].min(self.p3[j]);
+            max[j] = max[j].max(self.p3[j]);
+
+            // Roots of the derivative, (a * t^2) + (b * t) + c.
+            let a = -self.p0[j] + 3.0 * self.p1[j] - 3.0 * self.p2[j] + self.p3[j];
+            let b = 2.0 * (self.p0[j] - 2.0 * self.p1[j] + self.p2[j]);
+            let c = self.p1[j] - self.p0[j];
+            let mut roots: [f64; 2] = [-1.0, -1.0];
+            if a.abs() < 1e-12 {
+                if b.abs() > 1e-12 {
+                    roots[0] = -c / b;
+                }
+            } else {
+                let d = b * b - 4.0 * a * c;
+                if d >= 0.0 {
+                    let d_sqrt = d.sqrt();
+                    roots[0] = (-b + d_sqrt) / (2.0 * a);
+                    roots[1] = (-b - d_sqrt) / (2.0 * a);
+                }
+            }
+            for t in &roots {
+                if *t > 0.0 && *t < 1.0 {
+                    let v = self.eval(*t)[j];
+                    min[j] = min[j].min(v);
+                    max[j] = max[j].max(v);
+                }
+            }
+        }
+        return (min, max);
+    }
+
+    /// Arc length, using Gauss-Legendre quadrature.
+    pub fn length(
+        &self,
+    ) -> f64 {
+        return self.length_to(1.0);
+    }
+
+    /// Arc length from the start to `t`, see: `length`.
+    pub fn length_to(
+        &self, t: f64,
+    ) -> f64 {
+        const GAUSS: [(f64, f64); 5] = [
+            (0.0, 0.5688888888888889),
+            (-0.5384693101056831, 0.4786286704993665),
+            (0.5384693101056831, 0.4786286704993665),
+            (-0.9061798459386640, 0.2369268850561891),
+            (0.9061798459386640, 0.2369268850561891),
+        ];
+        let mut length = 0.0;
+        for &(x, w) in &GAUSS {
+            let d = self.eval_derivative((x + 1.0) * 0.5 * t);
+            let mut d_len_sq = 0.0;
+            for j in 0..DIMS {
+                d_len_sq += d[j] * d[j];
+            }
+            length += w * d_len_sq.sqrt();
+        }
+        return length * 0.5 * t;
+    }
+
+    /// The parameter at `length` along the segment (clamped to the end-points),
+    /// the inverse of `length_to`.
+    pub fn param_at_length(
+        &self, length: f64,
+    ) -> f64 {
+        let length_total = self.length();
+        if !(length > 0.0) {
+            return 0.0;
+        }
+        if length >= length_total {
+            return 1.0;
+        }
+        // Newton's method, bisecting when a step leaves the range known to contain the result.
+        let mut t = length / length_total;
+        let mut t_range = [0.0, 1.0];
+        for _ in 0..PARAM_AT_LENGTH_ITER_MAX {
+            let error = self.length_to(t) - length;
+            if error.abs() <= length_total * 1e-9 {
+                break;
+            }
+            t_range[if error > 0.0 { 1 } else { 0 }] = t;
+            let t_next = t - error / len_squared_vn(&self.eval_derivative(t)).sqrt();
+            t = if t_next > t_range[0] && t_next < t_range[1] {
+                t_next
+            } else {
+                (t_range[0] + t_range[1]) * 0.5
+            };
+        }
+        return t;
+    }
+
+    /// When the handles are within `tolerance` of the line between the end-points
+    /// (without extending past them), so the segment can be written as a line.
+    pub fn is_linear(
+        &self, tolerance: f64,
+    ) -> bool {
+        return is_point_near_line_segment(&self.p1, &self.p0, &self.p3, tolerance) &&
+               is_point_near_line_segment(&self.p2, &self.p0, &self.p3, tolerance);
+    }
+
+    /// Number of steps needed so a polyline doesn't deviate more than `tolerance`.
+    pub fn flatten_steps(
+        &self, tolerance: f64,
+    ) -> usize {
+        let d1 = len_vnvn(&sub_vnvn(&self.p0, &self.p1), &sub_vnvn(&self.p1, &self.p2));
+        let d2 = len_vnvn(&sub_vnvn(&self.p1, &self.p2), &sub_vnvn(&self.p2, &self.p3));
+        let steps = (0.75 * d1.max(d2) / tolerance).sqrt().ceil();
+        if steps.is_finite() && steps >= 1.0 {
+            return steps as usize;
+        }
+        return 1;
+    }
+
+    /// Add points to `poly` (excluding `p0`), so segments don't deviate more than `tolerance`.
+    pub fn flatten_into(
+        &self, tolerance: f64, poly: &mut Vec<[f64; DIMS]>,
+    ) {
+        let steps = self.flatten_steps(tolerance);
+        for i in 1..steps {
+            poly.push(self.eval(i as f64 / steps as f64));
+        }
+        poly.push(self.p3);
+    }
+}
+
+/// A point on a path with both its handles.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Knot {
+    pub handle_prev: [f64; DIMS],
+    pub co: [f64; DIMS],
+    pub handle_next: [f64; DIMS],
+}
+
+impl Knot {
+    /// Both handles are aligned (opposite directions), so the curve has no corner here.
+    ///
+    /// Knots with zero length handles are never considered smooth.
+    pub fn is_smooth(
+        &self,
+    ) -> bool {
+        let a = sub_vnvn(&self.co, &self.handle_prev);
+        let b = sub_vnvn(&self.handle_next, &self.co);
+        let cross = a[0] * b[1] - a[1] * b[0];
+        let dot = a[0] * b[0] + a[1] * b[1];
+        let len_sq = (a[0] * a[0] + a[1] * a[1]) * (b[0] * b[0] + b[1] * b[1]);
+        return dot > 0.0 && cross * cross <= len_sq * 1e-12;
+    }
+}
+
+/// Information about the region a path was extracted from,
+/// passed through to the writers unchanged.
+///
+/// Measurements are in pixels (before the output transform is applied).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PathMeta {
+    /// Index of the polygon when extracted from the image.
+    pub region: usize,
+    /// Area enclosed by the polygon (zero for open polygons).
+    pub area: f64,
+    /// The polygon bounds an empty region within a filled region.
+    pub is_hole: bool,
+    /// Hash of the polygon as extracted (before simplifying & fitting),
+    /// stable between runs so paths can be matched between traces,
+    /// see: `polys_utils::poly_hash`.
+    pub hash: Option<u64>,
+    /// Average width of the region when known.
+    pub width: Option<f64>,
+    /// The `[dash, gap]` lengths when the path was joined from a dashed line.
+    pub dash: Option<[f64; 2]>,
+    /// The width of the stroke at each knot (center-lines only),
+    /// measured after fitting, see: `curve_stroke_width`.
+    pub widths: Option<Vec<f64>>,
+    /// The primitive the path was recognized as (see `curve_shapes`),
+    /// the knots are kept as an exact curve of the shape.
+    pub shape: Option<Shape>,
+}
+
+/// Primitives which writers may use in place of a path's curves.
+///
+/// Shapes are stored as points & vectors so affine transforms can be applied to them.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Shape {
+    /// An ellipse (or circle) with the end-points of its axes at `center +/- axes[i]`.
+    Ellipse { center: [f64; DIMS], axes: [[f64; DIMS]; 2] },
+    /// A rectangle with corners at `corner`, `corner + edges[0]`,
+    /// `corner + edges[0] + edges[1]` & `corner + edges[1]`.
+    Rect { corner: [f64; DIMS], edges: [[f64; DIMS]; 2] },
+    /// A straight line.
+    Line { p0: [f64; DIMS], p1: [f64; DIMS] },
+}
+
+impl Shape {
+    pub fn transform_affine(
+        &self, t: &Transform,
+    ) -> Shape {
+        // Vectors are transformed without the translation.
+        let apply_vec = |v: &[f64; DIMS]| [
+            t.m[0][0] * v[0] + t.m[0][1] * v[1],
+            t.m[1][0] * v[0] + t.m[1][1] * v[1],
+        ];
+        match *self {
+            Shape::Ellipse { center, axes } => {
+                Shape::Ellipse {
+                    center: t.apply(&center),
+                    axes: [apply_vec(&axes[0]), apply_vec(&axes[1])],
+                }
+            },
+            Shape::Rect { corner, edges } => {
+                Shape::Rect {
+                    corner: t.apply(&corner),
+                    edges: [apply_vec(&edges[0]), apply_vec(&edges[1])],
+                }
+            },
+            Shape::Line { p0, p1 } => {
+                Shape::Line { p0: t.apply(&p0), p1: t.apply(&p1) }
+            },
+        }
+    }
+}
+
+impl PathMeta {
+    /// Calculate meta-data from an extracted polygon,
+    /// (expects the winding of outlines extracted from the image).
+    pub fn from_poly(
+        region: usize,
+        is_cyclic: bool,
+        poly: &Vec<[f64; DIMS]>,
+    ) -> PathMeta {
+        let mut meta = PathMeta {
+            region: region,
+            .. PathMeta::default()
+        };
+        if is_cyclic && poly.len() >= 3 {
+            let mut area_x2 = 0.0;
+            let mut perimeter = 0.0;
+            let mut v_prev = &poly[poly.len() - 1];
+            for v_curr in poly {
+                area_x2 += v_prev[0] * v_curr[1] - v_curr[0] * v_prev[1];
+                perimeter += len_vnvn(v_prev, v_curr);
+                v_prev = v_curr;
+            }
+            // Filled regions wind counter-clockwise (with Y pointing down).
+            meta.is_hole = area_x2 > 0.0;
+            meta.area = area_x2.abs() / 2.0;
+            // Exact for long strips, an approximation for other shapes.
+            if perimeter > 0.0 {
+                meta.width = Some(2.0 * meta.area / perimeter);
+            }
+        }
+        return meta;
+    }
+}
+
+/// Measurements of an extracted polygon, see: `--export-measurements`.
+///
+/// Measurements are in pixels (before the output transform is applied).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RegionMeasurement {
+    /// Index of the polygon when extracted from the image, matching `PathMeta::region`.
+    pub region: usize,
+    pub is_cyclic: bool,
+    pub is_hole: bool,
+    /// Area enclosed by the polygon (zero for open polygons).
+    pub area: f64,
+    /// Length of the polygon (the perimeter for cyclic polygons).
+    pub perimeter: f64,
+    /// Center of the enclosed area (the center of the polygon's length for open polygons).
+    pub centroid: [f64; DIMS],
+    pub bounds_min: [f64; DIMS],
+    pub bounds_max: [f64; DIMS],
+}
+
+impl RegionMeasurement {
+    /// Measure an extracted polygon,
+    /// (expects the winding of outlines extracted from the image).
+    pub fn from_poly(
+        region: usize,
+        is_cyclic: bool,
+        poly: &Vec<[f64; DIMS]>,
+    ) -> RegionMeasurement {
+        let mut measure = RegionMeasurement {
+            region: region,
+            is_cyclic: is_cyclic,
+            bounds_min: [::std::f64::MAX; DIMS],
+            bounds_max: [-::std::f64::MAX; DIMS],
+            .. RegionMeasurement::default()
+        };
+        if poly.is_empty() {
+            measure.bounds_min = [0.0; DIMS];
+            measure.bounds_max = [0.0; DIMS];
+            return measure;
+        }
+
+        for v in poly {
+            for j in 0..DIMS {
+                measure.bounds_min[j] = measure.bounds_min[j].min(v[j]);
+                measure.bounds_max[j] = measure.bounds_max[j].max(v[j]);
+            }
+        }
+
+        let mut area_x2 = 0.0;
+        let mut centroid_area = [0.0; DIMS];
+        let mut centroid_length = [0.0; DIMS];
+        let (mut v_prev, v_iter) = if is_cyclic {
+            (&poly[poly.len() - 1], poly.iter().skip(0))
+        } else {
+            (&poly[0], poly.iter().skip(1))
+        };
+        for v_curr in v_iter {
+            let cross = v_prev[0] * v_curr[1] - v_curr[0] * v_prev[1];
+            let length = len_vnvn(v_prev, v_curr);
+            area_x2 += cross;
+            measure.perimeter += length;
+            for j in 0..DIMS {
+                centroid_area[j] += (v_prev[j] + v_curr[j]) * cross;
+                centroid_length[j] += (v_prev[j] + v_curr[j]) * 0.5 * length;
+            }
+            v_prev = v_curr;
+        }
+
+        if is_cyclic {
+            // Filled regions wind counter-clockwise (with Y pointing down).
+            measure.is_hole = area_x2 > 0.0;
+            measure.area = area_x2.abs() / 2.0;
+        }
+        measure.centroid = if area_x2 != 0.0 && is_cyclic {
+            [centroid_area[0] / (3.0 * area_x2), centroid_area[1] / (3.0 * area_x2)]
+        } else if measure.perimeter > 0.0 {
+            [centroid_length[0] / measure.perimeter, centroid_length[1] / measure.perimeter]
+        } else {
+            poly[0]
+        };
+        return measure;
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Path {
+    pub cyclic: bool,
+    pub knots: Vec<Knot>,
+    pub meta: PathMeta,
+}
+
+pub struct PathSegmentIter<'a> {
+    path: &'a Path,
+    index: usize,
+}
+
+impl<'a> Iterator for PathSegmentIter<'a> {
+    type Item = CubicSegment;
+
+    fn next(&mut self) -> Option<CubicSegment> {
+        if self.index < self.path.segments_len() {
+            self.index += 1;
+            return Some(self.path.segment(self.index - 1));
+        }
+        return None;
+    }
+}
+
+#[allow(dead_code)]
+impl Path {
+    /// Create from the `[handle_prev, co, handle_next]` arrays used by the curve fitter.
+    pub fn from_cubic_array(
+        cyclic: bool,
+        cubic_array: &[[[f64; DIMS]; 3]],
+    ) -> Path {
+        Path {
+            cyclic: cyclic,
+            knots: cubic_array.iter().map(|v| {
+                Knot {
+                    handle_prev: v[0],
+                    co: v[1],
+                    handle_next: v[2],
+                }
+            }).collect(),
+            meta: PathMeta::default(),
+        }
+    }
+
+    /// Create a path of straight segments (handles placed on their knots).
+    pub fn from_poly(
+        cyclic: bool,
+        poly: &[[f64; DIMS]],
+    ) -> Path {
+        Path {
+            cyclic: cyclic,
+            knots: poly.iter().map(|v| {
+                Knot {
+                    handle_prev: *v,
+                    co: *v,
+                    handle_next: *v,
+                }
+            }).collect(),
+            meta: PathMeta::default(),
+        }
+    }
+
+    pub fn segments_len(
+        &self,
+    ) -> usize {
+        if self.cyclic {
+            self.knots.len()
+        } else {
+            self.knots.len().saturating_sub(1)
+        }
+    }
+
+    /// Segment between knot `i` and the next knot (wrapping for cyclic paths).
+    pub fn segment(
+        &self, i: usize,
+    ) -> CubicSegment {
+        let k_curr = &self.knots[i];
+        let k_next = &self.knots[if i + 1 != self.knots.len() { i + 1 } else { 0 }];
+        return CubicSegment {
+            p0: k_curr.co,
+            p1: k_curr.handle_next,
+            p2: k_next.handle_prev,
+            p3: k_next.co,
+        };
+    }
+
+    pub fn segments<'a>(
+        &'a self,
+    ) -> PathSegmentIter<'a> {
+        PathSegmentIter {
+            path: self,
+            index: 0,
+        }
+    }
+
+    pub fn bounds(
+        &self,
+    ) -> ([f64; DIMS], [f64; DIMS]) {
+        let mut min = [::std::f64::MAX; DIMS];
+        let mut max = [-::std::f64::MAX; DIMS];
+        for k in &self.knots {
+            for j in 0..DIMS {
+                min[j] = min[j].min(k.co[j]);
+                max[j] = max[j].max(k.co[j]);
+            }
+        }
+        for seg in self.segments() {
+            let (seg_min, seg_max) = seg.bounds();
+            for j in 0..DIMS {
+                min[j] = min[j].min(seg_min[j]);
+                max[j] = max[j].max(seg_max[j]);
+            }
+        }
+        return (min, max);
+    }
+
+    pub fn length(
+        &self,
+    ) -> f64 {
+        self.segments().map(|seg| seg.length()).sum()
+    }
+
+    /// The point at `length` along the path (clamped to the ends).
+    ///
+    /// The path must have at least one knot.
+    pub fn eval_at_length(
+        &self, length: f64,
+    ) -> [f64; DIMS] {
+        let mut length_remaining = length;
+        let mut co_end = self.knots[0].co;
+        for seg in self.segments() {
+            let seg_length = seg.length();
+            if length_remaining <= seg_length {
+                return seg.eval(seg.param_at_length(length_remaining));
+            }
+            length_remaining -= seg_length;
+            co_end = seg.p3;
+        }
+        return co_end;
+    }
+
+    /// The point at `t` (from 0 to 1), parameterized by arc length,
+    /// so evenly spaced values give evenly spaced points, see: `eval_at_length`.
+    pub fn eval(
+        &self, t: f64,
+    ) -> [f64; DIMS] {
+        return self.eval_at_length(t * self.length());
+    }
+
+    /// Return a polygon which doesn't deviate from the curve more than `tolerance`.
+    ///
+    /// For cyclic paths, the first point isn't repeated at the end.
+    pub fn flatten(
+        &self, tolerance: f64,
+    ) -> Vec<[f64; DIMS]> {
+        let mut poly: Vec<[f64; DIMS]> = Vec::with_capacity(self.knots.len() * 4);
+        if let Some(k) = self.knots.first() {
+            poly.push(k.co);
+        }
+        for seg in self.segments() {
+            seg.flatten_into(tolerance, &mut poly);
+        }
+        if self.cyclic && poly.len() > 1 {
+            poly.pop();
+        }
+        return poly;
+    }
+
+    /// Apply a function to every knot and handle.
+    ///
+    /// The shape is cleared since the function may not preserve it.
+    pub fn transform<F>(
+        &mut self, f: F,
+    ) where F: Fn(&[f64; DIMS]) -> [f64; DIMS] {
+        for k in &mut self.knots {
+            k.handle_prev = f(&k.handle_prev);
+            k.co = f(&k.co);
+            k.handle_next = f(&k.handle_next);
+        }
+        self.meta.shape = None;
+    }
+
+    pub fn transform_affine(
+        &mut self, t: &Transform,
+    ) {
+        let shape = self.meta.shape.map(|shape| shape.transform_affine(t));
+        self.transform(|v| t.apply(v));
+        self.meta.shape = shape;
+        let scale = t.determinant().abs().sqrt();
+        if let Some(ref mut dash) = self.meta.dash {
+            *dash = [dash[0] * scale, dash[1] * scale];
+        }
+        if let Some(ref mut widths) = self.meta.widths {
+            for w in widths.iter_mut() {
+                *w *= scale;
+            }
+        }
+    }
+
+    /// Replace segments with non-finite handles by straight segments
+    /// (matching the curve fitters fallback), knots with non-finite positions are removed.
+    ///
+    /// Returns the number of segments which were changed.
+    pub fn validate_finite(
+        &mut self,
+    ) -> usize {
+        use ::intern::math_vector::is_finite_vn;
+
+        let knots_len_orig = self.knots.len();
+        self.knots.retain(|k| is_finite_vn(&k.co));
+        let mut changed = knots_len_orig - self.knots.len();
+
+        for i in 0..self.segments_len() {
+            let i_next = if i + 1 != self.knots.len() { i + 1 } else { 0 };
+            if !is_finite_vn(&self.knots[i].handle_next) ||
+               !is_finite_vn(&self.knots[i_next].handle_prev)
+            {
+                let p0 = self.knots[i].co;
+                let p3 = self.knots[i_next].co;
+                self.knots[i].handle_next = interp_vnvn(&p0, &p3, 1.0 / 3.0);
+                self.knots[i_next].handle_prev = interp_vnvn(&p0, &p3, 2.0 / 3.0);
+                changed += 1;
+            }
+        }
+
+        // End handles of open paths aren't part of any segment.
+        if !self.cyclic {
+            if let Some(k) = self.knots.first_mut() {
+                if !is_finite_vn(&k.handle_prev) {
+                    k.handle_prev = k.co;
+                }
+            }
+            if let Some(k) = self.knots.last_mut() {
+                if !is_finite_vn(&k.handle_next) {
+                    k.handle_next = k.co;
+                }
+            }
+        }
+        return changed;
+    }
+
+    /// Reverse the direction of the path.
+    pub fn reverse(
+        &mut self,
+    ) {
+        self.knots.reverse();
+        for k in &mut self.knots {
+            ::std::mem::swap(&mut k.handle_prev, &mut k.handle_next);
+        }
+        if let Some(ref mut widths) = self.meta.widths {
+            widths.reverse();
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Drawing {
+    pub paths: LinkedList<Path>,
+}
+
+#[allow(dead_code)]
+impl Drawing {
+    pub fn new() -> Drawing {
+        Drawing {
+            paths: LinkedList::new(),
+        }
+    }
+
+    /// Create from the curve list returned by the curve fitter.
+    pub fn from_curve_list(
+        curve_list: LinkedList<(bool, Vec<[[f64; DIMS]; 3]>)>,
+    ) -> Drawing {
+        Drawing {
+            paths: curve_list.into_iter().map(|(is_cyclic, cubic_array)| {
+                Path::from_cubic_array(is_cyclic, &cubic_array)
+            }).collect(),
+        }
+    }
+
+    /// Create from curves with meta-data for each curve (in the same order).
+    pub fn from_curve_list_with_meta(
+        curve_list: LinkedList<(bool, Vec<[[f64; DIMS]; 3]>)>,
+        meta_list: Vec<PathMeta>,
+    ) -> Drawing {
+        assert!(curve_list.len() == meta_list.len());
+        Drawing {
+            paths: curve_list.into_iter().zip(meta_list).map(|((is_cyclic, cubic_array), meta)| {
+                let mut path = Path::from_cubic_array(is_cyclic, &cubic_array);
+                path.meta = meta;
+                path
+            }).collect(),
+        }
+    }
+
+    pub fn bounds(
+        &self,
+    ) -> ([f64; DIMS], [f64; DIMS]) {
+        let mut min = [::std::f64::MAX; DIMS];
+        let mut max = [-::std::f64::MAX; DIMS];
+        for path in &self.paths {
+            let (path_min, path_max) = path.bounds();
+            for j in 0..DIMS {
+                min[j] = min[j].min(path_min[j]);
+                max[j] = max[j].max(path_max[j]);
+            }
+        }
+        return (min, max);
+    }
+
+    pub fn length(
+        &self,
+    ) -> f64 {
+        self.paths.iter().map(|path| path.length()).sum()
+    }
+
+    pub fn knots_len(
+        &self,
+    ) -> usize {
+        self.paths.iter().map(|path| path.knots.len()).sum()
+    }
+
+    /// Flatten all paths into polygons, see: `Path::flatten`.
+    pub fn flatten(
+        &self, tolerance: f64,
+    ) -> LinkedList<(bool, Vec<[f64; DIMS]>)> {
+        self.paths.iter().map(|path| (path.cyclic, path.flatten(tolerance))).collect()
+    }
+
+    pub fn transform<F>(
+        &mut self, f: F,
+    ) where F: Fn(&[f64; DIMS]) -> [f64; DIMS] {
+        for path in &mut self.paths {
+            path.transform(&f);
+        }
+    }
+
+    pub fn transform_affine(
+        &mut self, t: &Transform,
+    ) {
+        for path in &mut self.paths {
+            path.transform_affine(t);
+        }
+    }
+
+    /// See: `Path::validate_finite`.
+    pub fn validate_finite(
+        &mut self,
+    ) -> usize {
+        self.paths.iter_mut().map(|path| path.validate_finite()).sum()
+    }
+
+    pub fn reverse(
+        &mut self,
+    ) {
+        for path in &mut self.paths {
+            path.reverse();
+        }
+    }
+
+    /// Reverse open paths as needed so they all follow `direction`,
+    /// returns the number of paths reversed.
+    ///
+    /// * `center` - paths start nearest this point for `StrokeDirection::Outward`.
+    pub fn orient_open_paths(
+        &mut self,
+        direction: StrokeDirection,
+        center: &[f64; DIMS],
+    ) -> usize {
+        let mut reversed_len = 0;
+        for path in &mut self.paths {
+            if path.cyclic || path.knots.len() < 2 {
+                continue;
+            }
+            let co_first = path.knots.first().unwrap().co;
+            let co_last = path.knots.last().unwrap().co;
+            // Compare along the main axis, then the other axis for ties.
+            let is_forward = |axis: usize| {
+                if co_first[axis] != co_last[axis] {
+                    co_first[axis] < co_last[axis]
+                } else {
+                    co_first[axis ^ 1] <= co_last[axis ^ 1]
+                }
+            };
+            let is_correct = match direction {
+                StrokeDirection::LeftToRight => is_forward(0),
+                StrokeDirection::TopToBottom => is_forward(1),
+                StrokeDirection::Outward => {
+                    len_vnvn(center, &co_first) <= len_vnvn(center, &co_last)
+                },
+            };
+            if !is_correct {
+                path.reverse();
+                reversed_len += 1;
+            }
+        }
+        return reversed_len;
+    }
+}
+
+/// Direction for open paths (strokes), in image space (where Y points down).
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum StrokeDirection {
+    LeftToRight,
+    TopToBottom,
+    /// Start at the end nearest the center.
+    Outward,
+}
+
+/// 2D affine transform, stored as a 2x3 matrix
+/// (matching SVG's `matrix(a, b, c, d, e, f)` as `[[a, c, e], [b, d, f]]`).
+///
+/// Only the first two dimensions are transformed.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub m: [[f64; 3]; 2],
+}
+
+#[allow(dead_code)]
+impl Transform {
+    pub const IDENTITY: Transform = Transform {
+        m: [[1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0]],
+    };
+
+    pub fn translate(x: f64, y: f64) -> Transform {
+        Transform {
+            m: [[1.0, 0.0, x],
+                [0.0, 1.0, y]],
+        }
+    }
+
+    pub fn scale(x: f64, y: f64) -> Transform {
+        Transform {
+            m: [[x, 0.0, 0.0],
+                [0.0, y, 0.0]],
+        }
+    }
+
+    /// Rotate counter-clockwise (in Y-up space), by `angle` in radians.
+    pub fn rotate(angle: f64) -> Transform {
+        let (s, c) = angle.sin_cos();
+        Transform {
+            m: [[c, -s, 0.0],
+                [s, c, 0.0]],
+        }
+    }
+
+    /// Return the transform which applies `other` first, then `self`.
+    pub fn multiply(&self, other: &Transform) -> Transform {
+        let a = &self.m;
+        let b = &other.m;
+        Transform {
+            m: [[a[0][0] * b[0][0] + a[0][1] * b[1][0],
+                 a[0][0] * b[0][1] + a[0][1] * b[1][1],
+                 a[0][0] * b[0][2] + a[0][1] * b[1][2] + a[0][2]],
+                [a[1][0] * b[0][0] + a[1][1] * b[1][0],
+                 a[1][0] * b[0][1] + a[1][1] * b[1][1],
+                 a[1][0] * b[0][2] + a[1][1] * b[1][2] + a[1][2]]],
+        }
+    }
+
+    pub fn apply(&self, v: &[f64; DIMS]) -> [f64; DIMS] {
+        let mut v_out = *v;
+        v_out[0] = self.m[0][0] * v[0] + self.m[0][1] * v[1] + self.m[0][2];
+        v_out[1] = self.m[1][0] * v[0] + self.m[1][1] * v[1] + self.m[1][2];
+        return v_out;
+    }
+
+    /// The area scale of the transform (negative when it mirrors).
+    pub fn determinant(&self) -> f64 {
+        self.m[0][0] * self.m[1][1] - self.m[0][1] * self.m[1][0]
+    }
+
+    /// Format as an SVG `matrix(..)` transform.
+    pub fn to_svg_string(&self) -> String {
+        format!(
+            "matrix({} {} {} {} {} {})",
+            self.m[0][0], self.m[1][0],
+            self.m[0][1], self.m[1][1],
+            self.m[0][2], self.m[1][2],
+        )
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Transform::IDENTITY
+    }
+
+    /// Parse an SVG style transform list,
+    /// supporting: `matrix`, `translate`, `scale`, `rotate` (in degrees), `skewX` & `skewY`.
+    ///
+    /// As with SVG, the right-most transform is applied first.
+    pub fn from_svg_str(text: &str) -> Result<Transform, String> {
+        use std::str::FromStr;
+
+        let mut t = Transform::IDENTITY;
+        let mut text = text.trim();
+        while text.len() != 0 {
+            let (name, rest) = match text.find('(') {
+                Some(i) => (text[..i].trim(), &text[(i + 1)..]),
+                None => {
+                    return Err(format!("Expected '(' in '{}'", text));
+                }
+            };
+            let (args_str, rest) = match rest.find(')') {
+                Some(i) => (&rest[..i], &rest[(i + 1)..]),
+                None => {
+                    return Err(format!("Expected ')' after '{}'", name));
+                }
+            };
+            let mut args: Vec<f64> = vec![];
+            for arg in args_str.split(|c: char| c == ',' || c.is_whitespace()) {
+                if arg.len() != 0 {
+                    match f64::from_str(arg) {
+                        Ok(v) => { args.push(v); }
+                        Err(e) => { return Err(format!("'{}': {}", arg, e.to_string())); }
+                    }
+                }
+            }
+
+            let t_step = match (name, args.len()) {
+                ("matrix", 6) => Transform {
+                    m: [[args[0], args[2], args[4]],
+                        [args[1], args[3], args[5]]],
+                },
+                ("translate", 1) => Transform::translate(args[0], 0.0),
+                ("translate", 2) => Transform::translate(args[0], args[1]),
+                ("scale", 1) => Transform::scale(args[0], args[0]),
+                ("scale", 2) => Transform::scale(args[0], args[1]),
+                ("rotate", 1) => Transform::rotate(args[0].to_radians()),
+                ("rotate", 3) => {
+                    Transform::translate(args[1], args[2]).multiply(
+                        &Transform::rotate(args[0].to_radians()).multiply(
+                            &Transform::translate(-args[1], -args[2])))
+                }
+                ("skewX", 1) => Transform {
+                    m: [[1.0, args[0].to_radians().tan(), 0.0],
+                        [0.0, 1.0, 0.0]],
+                },
+                ("skewY", 1) => Transform {
+                    m: [[1.0, 0.0, 0.0],
+                        [args[0].to_radians().tan(), 1.0, 0.0]],
+                },
+                _ => {
+                    return Err(format!(
+                        "Unknown transform '{}' with {} argument(s)",
+                        name, args.len(),
+                    ));
+                }
+            };
+
+            t = t.multiply(&t_step);
+            text = rest.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+        }
+        return Ok(t);
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform::IDENTITY
+    }
+}
@@ -5,53 +5,231 @@
 
 const DIMS: usize = ::intern::math_vector::DIMS;
 
+/// Segments which deviate less than this from a line are written as lines (in output units).
+const LINEAR_TOLERANCE: f64 = 0.01;
+
+fn xml_escape(text: &str) -> String {
+    let mut text_escape = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => text_escape.push_str("&amp;"),
+            '<' => text_escape.push_str("&lt;"),
+            '>' => text_escape.push_str("&gt;"),
+            '\'' => text_escape.push_str("&apos;"),
+            '"' => text_escape.push_str("&quot;"),
+            _ => text_escape.push(c),
+        }
+    }
+    return text_escape;
+}
+
+/// A number for writing to files, always with a '.' decimal separator (independent of the locale)
+/// & never in scientific notation, which some CAM & DXF applications can't read.
+///
+/// Values which round to zero are written without a sign (`0.00` instead of `-0.00`).
+#[derive(Copy, Clone, Debug)]
+pub struct Num {
+    value: f64,
+    /// Digits after the decimal point, when unset use the fewest digits which read back exactly.
+    precision: Option<usize>,
+}
+
+impl Num {
+    pub fn new(
+        value: f64,
+        precision: usize,
+    ) -> Num {
+        return Num {
+            value: value,
+            precision: Some(precision),
+        };
+    }
+
+    /// Write the value with the fewest digits which read back as the same value.
+    pub fn exact(
+        value: f64,
+    ) -> Num {
+        return Num {
+            value: value,
+            precision: None,
+        };
+    }
+}
+
+impl ::std::fmt::Display for Num {
+    fn fmt(
+        &self,
+        f: &mut ::std::fmt::Formatter,
+    ) -> ::std::fmt::Result {
+        // Formatting floats never uses the locale or scientific notation
+        // (unlike `{:e}` or C's `%g`), so only the sign needs to be handled.
+        let text = match self.precision {
+            Some(precision) => format!("{:.*}", precision, self.value),
+            None => format!("{}", self.value),
+        };
+        if text.starts_with('-') && text[1..].bytes().all(|b| b == b'0' || b == b'.') {
+            return f.write_str(&text[1..]);
+        }
+        return f.write_str(&text);
+    }
+}
+
 pub mod svg {
 
     use super::{
         DIMS,
+        LINEAR_TOLERANCE,
+        Num,
+        xml_escape,
+    };
+    use curve_stroke_width;
+    use curve_types::{
+        is_point_near_line_segment,
+        CubicSegment,
+        Drawing,
+        Path,
+        Shape,
     };
     use std::collections::LinkedList;
     use std::io::prelude::Write;
 
-    pub fn write_header(
-        mut f: &::std::fs::File,
+    /// Coordinates & sizes are written with 2 digits after the decimal point.
+    fn num(
+        value: f64,
+    ) -> Num {
+        return Num::new(value, 2);
+    }
+
+    /// * `view_min` - the top-left corner of the view (in pixels),
+    ///   non-zero when the output is moved to a different origin.
+    /// * `use_layers` - declare the Inkscape namespace, needed for `write_layer_begin`
+    ///   & paths with widths (see `write_drawing_centerline`).
+    pub fn write_header<W: Write>(
+        mut f: W,
         size: &[usize; 2],
+        view_min: &[f64; DIMS],
         scale: f64,
+        use_layers: bool,
     ) -> Result<(), ::std::io::Error> {
         writeln!(f, "<?xml version='1.0' encoding='UTF-8'?>")?;
         writeln!(f, concat!(
             "<svg version='1.1' ",
             "width='{}' height='{}' ",
-            "viewBox='0 0 {} {}' ",
+            "viewBox='{} {} {} {}' ",
             "xmlns='http://www.w3.org/2000/svg' ",
             "xmlns:xlink='http://www.w3.org/1999/xlink' ",
+            "{}",
             ">"),
-            scale * size[0] as f64,
-            scale * size[1] as f64,
-            scale * size[0] as f64,
-            scale * size[1] as f64,
+            Num::exact(scale * size[0] as f64),
+            Num::exact(scale * size[1] as f64),
+            Num::exact(scale * view_min[0]),
+            Num::exact(scale * view_min[1]),
+            Num::exact(scale * size[0] as f64),
+            Num::exact(scale * size[1] as f64),
+            if use_layers {
+                "xmlns:inkscape='http://www.inkscape.org/namespaces/inkscape' "
+            } else {
+                ""
+            },
         )?;
 
         Ok(())
     }
 
-    pub fn write_poly_list_filled(
-        mut f: &::std::fs::File,
+    /// Write the version and parameters used to create the file.
+    pub fn write_metadata<W: Write>(
+        mut f: W,
+        params: &Vec<(&str, String)>,
+    ) -> Result<(), ::std::io::Error> {
+        writeln!(f, "  <metadata>")?;
+        writeln!(f,
+            "    <{} version='{}' xmlns='{}'>",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+            env!("CARGO_PKG_HOMEPAGE"),
+        )?;
+        for &(name, ref value) in params {
+            writeln!(f,
+                "      <param name='{}' value='{}' />",
+                xml_escape(name), xml_escape(value),
+            )?;
+        }
+        writeln!(f, "    </{}>", env!("CARGO_PKG_NAME"))?;
+        writeln!(f, "  </metadata>")?;
+        Ok(())
+    }
+
+    /// Begin a group which Inkscape shows as a layer,
+    /// must be followed by `write_layer_end`.
+    pub fn write_layer_begin<W: Write>(
+        mut f: W,
+        label: &str,
+    ) -> Result<(), ::std::io::Error> {
+        writeln!(f,
+            "  <g inkscape:groupmode='layer' inkscape:label='{}' id='layer-{}'>",
+            label, label.to_lowercase(),
+        )?;
+        Ok(())
+    }
+
+    pub fn write_layer_end<W: Write>(
+        mut f: W,
+    ) -> Result<(), ::std::io::Error> {
+        writeln!(f, "  </g>")?;
+        Ok(())
+    }
+
+    /// Begin a group offset by `offset`,
+    /// must be followed by `write_group_end`.
+    pub fn write_group_begin<W: Write>(
+        mut f: W,
+        id: &str,
+        offset: &[f64; 2],
+    ) -> Result<(), ::std::io::Error> {
+        writeln!(f,
+            "  <g id='{}' transform='translate({} {})'>",
+            xml_escape(id), Num::exact(offset[0]), Num::exact(offset[1]),
+        )?;
+        Ok(())
+    }
+
+    /// Begin a group for an animation frame, hidden unless `is_visible`,
+    /// must be followed by `write_group_end`.
+    pub fn write_frame_begin<W: Write>(
+        mut f: W,
+        id: &str,
+        is_visible: bool,
+    ) -> Result<(), ::std::io::Error> {
+        writeln!(f,
+            "  <g id='{}'{}>",
+            xml_escape(id), if is_visible { "" } else { " display='none'" },
+        )?;
+        Ok(())
+    }
+
+    pub fn write_group_end<W: Write>(
+        mut f: W,
+    ) -> Result<(), ::std::io::Error> {
+        writeln!(f, "  </g>")?;
+        Ok(())
+    }
+
+    pub fn write_poly_list_filled<W: Write>(
+        mut f: W,
         _size: &[usize; 2],
         scale: f64,
         poly_list: &LinkedList<(bool, Vec<[f64; DIMS]>)>,
         pass_scale: f64,
     ) -> Result<(), ::std::io::Error> {
-        use std::io::prelude::Write;
 
         f.write_fmt(format_args!(concat!("  ",
             "<g stroke='white' ",
             "stroke-opacity='0.5' ",
-            "stroke-width='{:.2}' ",
+            "stroke-width='{}' ",
             "fill='black' ",
             "fill-opacity='0.5' ",
             ">"),
-            0.5 * pass_scale,
+            num(0.5 * pass_scale),
         ))?;
 
         f.write(b"    <path d='")?;
@@ -59,9 +237,9 @@ pub mod svg {
             f.write(b"M ")?;
             for v in p {
                 f.write_fmt(format_args!(
-                    "{:.2},{:.2} ",
-                    v[0] * scale,
-                    v[1] * scale,
+                    "{},{} ",
+                    num(v[0] * scale),
+                    num(v[1] * scale),
                 ))?;
             }
             f.write(b" Z\n")?;
@@ -73,22 +251,21 @@ pub mod svg {
         Ok(())
     }
 
-    pub fn write_poly_list_centerline(
-        mut f: &::std::fs::File,
+    pub fn write_poly_list_centerline<W: Write>(
+        mut f: W,
         _size: &[usize; 2],
         scale: f64,
         poly_list: &LinkedList<(bool, Vec<[f64; DIMS]>)>,
         pass_scale: f64,
     ) -> Result<(), ::std::io::Error> {
-        use std::io::prelude::Write;
 
         f.write_fmt(format_args!(concat!("  ",
             "<g stroke='grey' ",
             "stroke-opacity='0.75' ",
-            "stroke-width='{:.2}' ",
+            "stroke-width='{}' ",
             "fill='none' ",
             ">"),
-            0.5 * pass_scale,
+            num(0.5 * pass_scale),
         ))?;
 
         f.write(b"    <path d='")?;
@@ -97,9 +274,9 @@ pub mod svg {
             f.write(b"M ")?;
             for v in p {
                 f.write_fmt(format_args!(
-                    "{:.2},{:.2} ",
-                    v[0] * scale,
-                    v[1] * scale,
+                    "{},{} ",
+                    num(v[0] * scale),
+                    num(v[1] * scale),
                 ))?;
             }
         }
@@ -110,10 +287,10 @@ pub mod svg {
         Ok(())
     }
 
-    pub fn write_curve_list_with_tangent_info(
-        mut f: &::std::fs::File,
+    pub fn write_drawing_with_tangent_info<W: Write>(
+        mut f: W,
         scale: f64,
-        poly_list: &LinkedList<(bool, Vec<[[f64; DIMS]; 3]>)>,
+        drawing: &Drawing,
         pass_scale: f64,
     ) -> Result<(), ::std::io::Error> {
         // handle segments
@@ -121,21 +298,21 @@ pub mod svg {
             f.write_fmt(format_args!(concat!("  ",
                 "<g stroke='black' ",
                 "stroke-opacity='0.5' ",
-                "stroke-width='{:.2}' ",
+                "stroke-width='{}' ",
                 ">"),
-                2.0 * pass_scale,
+                num(2.0 * pass_scale),
             ))?;
-            for &(_is_cyclic, ref p) in poly_list {
-                for v in p {
+            for path in &drawing.paths {
+                for k in &path.knots {
                     f.write_fmt(format_args!(
-                        "<line x1='{:.2}' y1='{:.2}' x2='{:.2}' y2='{:.2}' />",
-                        v[0][0] * scale, v[0][1] * scale,
-                        v[1][0] * scale, v[1][1] * scale,
+                        "<line x1='{}' y1='{}' x2='{}' y2='{}' />",
+                        num(k.handle_prev[0] * scale), num(k.handle_prev[1] * scale),
+                        num(k.co[0] * scale), num(k.co[1] * scale),
                     ))?;
                     f.write_fmt(format_args!(
-                        "<line x1='{:.2}' y1='{:.2}' x2='{:.2}' y2='{:.2}' />",
-                        v[1][0] * scale, v[1][1] * scale,
-                        v[2][0] * scale, v[2][1] * scale,
+                        "<line x1='{}' y1='{}' x2='{}' y2='{}' />",
+                        num(k.co[0] * scale), num(k.co[1] * scale),
+                        num(k.handle_next[0] * scale), num(k.handle_next[1] * scale),
                     ))?;
                 }
             }
@@ -147,49 +324,423 @@ pub mod svg {
             f.write_fmt(format_args!(concat!("  ",
                 "<g stroke='white' ",
                 "stroke-opacity='1.0' ",
-                "stroke-width='{:.2}' ",
+                "stroke-width='{}' ",
                 "fill='black' ",
                 "fill-opacity='0.5' ",
                 ">"),
-                1.0 * pass_scale,
+                num(1.0 * pass_scale),
             ))?;
 
-            for &(_is_cyclic, ref p) in poly_list {
-                for v in p {
-                    for h in v {
+            for path in &drawing.paths {
+                for k in &path.knots {
+                    for h in &[k.handle_prev, k.co, k.handle_next] {
                         f.write_fmt(format_args!(
-                            "<circle cx='{:.2}' cy='{:.2}' r='{:.2}'/>",
-                            h[0] * scale,
-                            h[1] * scale,
-                            2.0 * pass_scale,
+                            "<circle cx='{}' cy='{}' r='{}'/>",
+                            num(h[0] * scale),
+                            num(h[1] * scale),
+                            num(2.0 * pass_scale),
                         ))?;
                     }
 
                     f.write_fmt(format_args!(
-                        "<line x1='{:.2}' y1='{:.2}' x2='{:.2}' y2='{:.2}' />",
-                        v[0][0] * scale, v[0][1] * scale,
-                        v[1][0] * scale, v[1][1] * scale,
+                        "<line x1='{}' y1='{}' x2='{}' y2='{}' />",
+                        num(k.handle_prev[0] * scale), num(k.handle_prev[1] * scale),
+                        num(k.co[0] * scale), num(k.co[1] * scale),
+                    ))?;
+                    f.write_fmt(format_args!(
+                        "<line x1='{}' y1='{}' x2='{}' y2='{}' />",
+                        num(k.co[0] * scale), num(k.co[1] * scale),
+                        num(k.handle_next[0] * scale), num(k.handle_next[1] * scale),
+                    ))?;
+                }
+            }
+            writeln!(f, "  </g>")?;
+        }
+
+        Ok(())
+    }
+
+    /// Write an arrow-head in the middle of each segment (pointing along the path)
+    /// & a circle at the start of each path.
+    pub fn write_drawing_with_direction_info<W: Write>(
+        mut f: W,
+        scale: f64,
+        drawing: &Drawing,
+        pass_scale: f64,
+    ) -> Result<(), ::std::io::Error> {
+        use ::intern::math_vector::{
+            madd_vnvn_fl,
+            normalize_vn,
+        };
+
+        let arrow_len = 6.0 * pass_scale;
+        writeln!(f, "  <g fill='blue' fill-opacity='0.75' stroke='none'>")?;
+        for path in &drawing.paths {
+            for seg in path.segments() {
+                let mut dir = seg.eval_derivative(0.5);
+                // Zero length handles at both ends, use the direction of the segment.
+                if dir[0] == 0.0 && dir[1] == 0.0 {
+                    dir = [seg.p3[0] - seg.p0[0], seg.p3[1] - seg.p0[1]];
+                }
+                if normalize_vn(&mut dir) == 0.0 {
+                    continue;
+                }
+                let co = seg.eval(0.5);
+                let co = [co[0] * scale, co[1] * scale];
+                let no = [-dir[1], dir[0]];
+                let tip = madd_vnvn_fl(&co, &dir, arrow_len * 0.5);
+                let base = madd_vnvn_fl(&co, &dir, arrow_len * -0.5);
+                let base_l = madd_vnvn_fl(&base, &no, arrow_len * 0.4);
+                let base_r = madd_vnvn_fl(&base, &no, arrow_len * -0.4);
+                writeln!(f, "    <polygon points='{},{} {},{} {},{}'/>",
+                         num(tip[0]), num(tip[1]),
+                         num(base_l[0]), num(base_l[1]),
+                         num(base_r[0]), num(base_r[1]))?;
+            }
+        }
+        writeln!(f, "  </g>")?;
+
+        writeln!(f, "  <g fill='red' fill-opacity='0.75' stroke='none'>")?;
+        for path in &drawing.paths {
+            if let Some(k) = path.knots.first() {
+                writeln!(f, "    <circle cx='{}' cy='{}' r='{}'/>",
+                         num(k.co[0] * scale), num(k.co[1] * scale), num(3.0 * pass_scale))?;
+            }
+        }
+        writeln!(f, "  </g>")?;
+
+        Ok(())
+    }
+
+    /// Mark corner knots (see: `debug_pass::kind::POST_CORNER`).
+    pub fn write_corners<W: Write>(
+        mut f: W,
+        scale: f64,
+        corners: &[[f64; DIMS]],
+        pass_scale: f64,
+    ) -> Result<(), ::std::io::Error> {
+        writeln!(f, "  <g fill='orange' fill-opacity='0.75' stroke='none'>")?;
+        for co in corners {
+            writeln!(f, "    <circle cx='{}' cy='{}' r='{}'/>",
+                     num(co[0] * scale), num(co[1] * scale), num(3.0 * pass_scale))?;
+        }
+        writeln!(f, "  </g>")?;
+
+        Ok(())
+    }
+
+    /// Return an element for the path's shape (without a trailing new-line),
+    /// `None` when it has no shape or the shape was sheared by the output transform.
+    fn path_shape_element(
+        scale: f64,
+        path: &Path,
+    ) -> Option<String> {
+        // Relative tolerance for axes to be considered perpendicular or of equal length.
+        const EPS: f64 = 1e-6;
+        let len = |v: &[f64; DIMS]| (v[0] * v[0] + v[1] * v[1]).sqrt();
+        let is_perpendicular = |a: &[f64; DIMS], b: &[f64; DIMS]| {
+            (a[0] * b[0] + a[1] * b[1]).abs() <= EPS * len(a) * len(b)
+        };
+        let rotate = |v: &[f64; DIMS], co: &[f64; DIMS]| {
+            let angle = v[1].atan2(v[0]).to_degrees();
+            if angle.abs() <= EPS {
+                String::new()
+            } else {
+                format!(" transform='rotate({} {} {})'",
+                        Num::new(angle, 4), num(co[0]), num(co[1]))
+            }
+        };
+        match path.meta.shape? {
+            Shape::Ellipse { center, axes } => {
+                if !is_perpendicular(&axes[0], &axes[1]) {
+                    return None;
+                }
+                let c = [center[0] * scale, center[1] * scale];
+                let (rx, ry) = (len(&axes[0]) * scale, len(&axes[1]) * scale);
+                if (rx - ry).abs() <= EPS * rx.max(ry) {
+                    return Some(format!(
+                        "<circle cx='{}' cy='{}' r='{}' />", num(c[0]), num(c[1]), num(rx)));
+                }
+                return Some(format!(
+                    "<ellipse cx='{}' cy='{}' rx='{}' ry='{}'{} />",
+                    num(c[0]), num(c[1]), num(rx), num(ry), rotate(&axes[0], &c)));
+            },
+            Shape::Rect { corner, edges } => {
+                let (mut corner, mut edges) = (corner, edges);
+                if !is_perpendicular(&edges[0], &edges[1]) {
+                    return None;
+                }
+                // Order the edges so the rectangle is rotated from the X & Y axes.
+                if edges[0][0] * edges[1][1] - edges[0][1] * edges[1][0] < 0.0 {
+                    corner = [corner[0] + edges[1][0], corner[1] + edges[1][1]];
+                    edges[1] = [0.0 - edges[1][0], 0.0 - edges[1][1]];
+                }
+                let is_aligned =
+                    edges[0][1].abs() <= EPS * len(&edges[0]) ||
+                    edges[0][0].abs() <= EPS * len(&edges[0]);
+                if is_aligned {
+                    let mut min = corner;
+                    let mut max = corner;
+                    for e in &edges {
+                        for j in 0..DIMS {
+                            min[j] = min[j].min(corner[j] + e[j]);
+                            max[j] = max[j].max(corner[j] + e[j]);
+                        }
+                    }
+                    return Some(format!(
+                        "<rect x='{}' y='{}' width='{}' height='{}' />",
+                        num(min[0] * scale), num(min[1] * scale),
+                        num((max[0] - min[0]) * scale), num((max[1] - min[1]) * scale)));
+                }
+                let c = [corner[0] * scale, corner[1] * scale];
+                return Some(format!(
+                    "<rect x='{}' y='{}' width='{}' height='{}'{} />",
+                    num(c[0]), num(c[1]),
+                    num(len(&edges[0]) * scale), num(len(&edges[1]) * scale),
+                    rotate(&edges[0], &c)));
+            },
+            Shape::Line { p0, p1 } => {
+                return Some(format!(
+                    "<line x1='{}' y1='{}' x2='{}' y2='{}' />",
+                    num(p0[0] * scale), num(p0[1] * scale),
+                    num(p1[0] * scale), num(p1[1] * scale)));
+            },
+        }
+    }
+
+    /// Write the path data (the contents of the `d` attribute).
+    fn write_path_data<W: Write>(
+        mut f: W,
+        scale: f64,
+        path: &Path,
+    ) -> Result<(), ::std::io::Error> {
+        use intern::math_vector::{
+            is_finite_vn,
+        };
+
+        let tolerance = LINEAR_TOLERANCE / scale;
+        let segments: Vec<CubicSegment> = path.segments().collect();
+        let mut i = 0;
+        while i < segments.len() {
+            let seg = &segments[i];
+            debug_assert!(is_finite_vn(&seg.p1));
+            debug_assert!(is_finite_vn(&seg.p2));
+            debug_assert!(is_finite_vn(&seg.p3));
+
+            if i == 0 {
+                f.write_fmt(format_args!(
+                    "M {},{} ",
+                    num(seg.p0[0] * scale),
+                    num(seg.p0[1] * scale),
+                ))?;
+            }
+            if seg.is_linear(tolerance) {
+                // Merge following segments which continue along the same line.
+                let mut i_end = i;
+                while i_end + 1 < segments.len() && segments[i_end + 1].is_linear(tolerance) {
+                    // Each segment must lie on the line & continue in the same direction.
+                    let p_end = &segments[i_end + 1].p3;
+                    let dir = [p_end[0] - seg.p0[0], p_end[1] - seg.p0[1]];
+                    if !segments[i..(i_end + 2)].iter().all(|s| {
+                        is_point_near_line_segment(&s.p3, &seg.p0, p_end, tolerance) &&
+                        (s.p3[0] - s.p0[0]) * dir[0] + (s.p3[1] - s.p0[1]) * dir[1] > 0.0
+                    }) {
+                        break;
+                    }
+                    i_end += 1;
+                }
+                let p = &segments[i_end].p3;
+                // The closing line of cyclic paths is implied by `Z`.
+                if !(path.cyclic && i_end + 1 == segments.len()) {
+                    f.write_fmt(format_args!(
+                        "L {},{} ",
+                        num(p[0] * scale), num(p[1] * scale),
                     ))?;
+                }
+                i = i_end + 1;
+                continue;
+            }
+            f.write_fmt(format_args!(
+                "C {},{} {},{} {},{} ",
+                num(seg.p1[0] * scale), num(seg.p1[1] * scale),
+                num(seg.p2[0] * scale), num(seg.p2[1] * scale),
+                num(seg.p3[0] * scale), num(seg.p3[1] * scale),
+            ))?;
+            i += 1;
+        }
+        if path.cyclic {
+            f.write(b" Z")?;
+        }
+        Ok(())
+    }
+
+    /// Write an animation stepping through each stage of the tracing process,
+    /// showing each polygon list in turn, followed by the curves of the `drawing`.
+    ///
+    /// * `step_duration` - seconds to show each stage.
+    pub fn write_anim_stages<W: Write>(
+        mut f: W,
+        scale: f64,
+        stages: &[(&str, &LinkedList<(bool, Vec<[f64; DIMS]>)>)],
+        drawing: &Drawing,
+        pass_scale: f64,
+        step_duration: f64,
+    ) -> Result<(), ::std::io::Error> {
+        let steps_len = stages.len() + 1;
+
+        // Each stage is only visible for its own step, using discrete animation
+        // so the loop doesn't depend on the number of stages.
+        let write_stage_begin = |
+            f: &mut W,
+            step: usize,
+            label: &str,
+        | -> Result<(), ::std::io::Error> {
+            let mut values: Vec<&str> = vec!["0"; steps_len];
+            values[step] = "1";
+            let key_times: Vec<String> = (0..steps_len).map(
+                |i| format!("{}", Num::new(i as f64 / steps_len as f64, 4))).collect();
+            writeln!(f, concat!("  ",
+                "<g opacity='{}' stroke='black' stroke-width='{}' fill='none'>"),
+                if step == 0 { 1 } else { 0 },
+                num(0.5 * pass_scale),
+            )?;
+            writeln!(f, concat!("    ",
+                "<animate attributeName='opacity' calcMode='discrete' ",
+                "values='{}' keyTimes='{}' dur='{}s' repeatCount='indefinite' />"),
+                values.join(";"), key_times.join(";"), step_duration * steps_len as f64,
+            )?;
+            writeln!(f, concat!("    ",
+                "<text x='{}' y='{}' font-size='{}' font-family='sans-serif' ",
+                "stroke='none' fill='red'>{}: {}</text>"),
+                num(2.0 * pass_scale), num(10.0 * pass_scale), num(8.0 * pass_scale),
+                step + 1, xml_escape(label),
+            )?;
+            Ok(())
+        };
+
+        for (step, &(label, poly_list)) in stages.iter().enumerate() {
+            write_stage_begin(&mut f, step, label)?;
+            f.write(b"    <path d='")?;
+            for &(is_cyclic, ref p) in poly_list {
+                f.write(b"M ")?;
+                for v in p {
                     f.write_fmt(format_args!(
-                        "<line x1='{:.2}' y1='{:.2}' x2='{:.2}' y2='{:.2}' />",
-                        v[1][0] * scale, v[1][1] * scale,
-                        v[2][0] * scale, v[2][1] * scale,
+                        "{},{} ",
+                        num(v[0] * scale),
+                        num(v[1] * scale),
                     ))?;
                 }
+                if is_cyclic {
+                    f.write(b"Z ")?;
+                }
             }
+            writeln!(f, "' />")?;
             writeln!(f, "  </g>")?;
         }
 
+        write_stage_begin(&mut f, stages.len(), "Curves")?;
+        f.write(b"    <path d='")?;
+        for path in &drawing.paths {
+            write_path_data(&mut f, scale, path)?;
+            f.write(b" ")?;
+        }
+        writeln!(f, "' />")?;
+        writeln!(f, "  </g>")?;
+
+        Ok(())
+    }
+
+    /// The `id` & `data-hash` attributes (with a leading space) for paths with a hash,
+    /// so paths can be matched between traces, see: `PathMeta::hash`.
+    fn path_id_attrs(
+        hash: Option<u64>,
+    ) -> String {
+        match hash {
+            Some(hash) => format!(" id='path-{:016x}' data-hash='{:016x}'", hash, hash),
+            None => String::new(),
+        }
+    }
+
+    /// Insert attributes after the tag name of an element, see: `path_shape_element`.
+    fn element_with_attrs(
+        elem: &str,
+        attrs: &str,
+    ) -> String {
+        let i = elem.find(' ').unwrap_or(elem.len());
+        return format!("{}{}{}", &elem[..i], attrs, &elem[i..]);
+    }
+
+    /// Write each filled path as its own element (including the holes it contains),
+    /// with the hash of the path & its holes as the `id`, see: `path_id_attrs`.
+    ///
+    /// Holes belong to the smallest path which bounds them.
+    fn write_paths_filled_with_ids<W: Write>(
+        mut f: W,
+        scale: f64,
+        paths: &[&Path],
+    ) -> Result<(), ::std::io::Error> {
+        use extract_cache::hash_fnv1a;
+
+        let bounds_contain = |a: &([f64; DIMS], [f64; DIMS]), b: &([f64; DIMS], [f64; DIMS])| {
+            a.0[0] <= b.0[0] && a.0[1] <= b.0[1] && a.1[0] >= b.1[0] && a.1[1] >= b.1[1]
+        };
+        let bounds_area = |b: &([f64; DIMS], [f64; DIMS])| {
+            (b.1[0] - b.0[0]) * (b.1[1] - b.0[1])
+        };
+        let bounds: Vec<([f64; DIMS], [f64; DIMS])> = paths.iter().map(
+            |path| path.bounds()).collect();
+        let mut holes: Vec<Vec<usize>> = vec![vec![]; paths.len()];
+        let mut is_contained = vec![false; paths.len()];
+        for (i, path) in paths.iter().enumerate() {
+            if !path.meta.is_hole {
+                continue;
+            }
+            let mut parent: Option<usize> = None;
+            for (j, path_other) in paths.iter().enumerate() {
+                if !path_other.meta.is_hole && bounds_contain(&bounds[j], &bounds[i]) &&
+                   parent.map_or(true, |p| bounds_area(&bounds[j]) < bounds_area(&bounds[p]))
+                {
+                    parent = Some(j);
+                }
+            }
+            if let Some(j) = parent {
+                holes[j].push(i);
+                is_contained[i] = true;
+            }
+        }
+
+        for (i, path) in paths.iter().enumerate() {
+            if is_contained[i] {
+                continue;
+            }
+            let mut hash = path.meta.hash;
+            for &i_hole in &holes[i] {
+                hash = match (hash, paths[i_hole].meta.hash) {
+                    (Some(hash), Some(hash_hole)) => {
+                        Some(hash_fnv1a(hash, &hash_hole.to_le_bytes()))
+                    }
+                    _ => None,
+                };
+            }
+            write!(f, "    <path{} d='", path_id_attrs(hash))?;
+            for path in Some(i).into_iter().chain(holes[i].iter().cloned()).map(|i| paths[i]) {
+                write_path_data(&mut f, scale, path)?;
+                f.write(b"\n")?;
+            }
+            writeln!(f, "' />")?;
+        }
         Ok(())
     }
 
-    pub fn write_curve_list_filled(
-        mut f: &::std::fs::File,
+    /// With `use_path_ids`, each path is written as its own element with an `id`
+    /// (holes are written with the path which contains them), see: `path_id_attrs`.
+    pub fn write_drawing_filled<W: Write>(
+        mut f: W,
         _size: &[usize; 2],
         scale: f64,
-        poly_list: &LinkedList<(bool, Vec<[[f64; DIMS]; 3]>)>,
+        drawing: &Drawing,
+        use_path_ids: bool,
     ) -> Result<(), ::std::io::Error> {
-        use std::io::prelude::Write;
 
         writeln!(f, concat!("  ",
             "<g stroke='black' ",
@@ -200,60 +751,117 @@ pub mod svg {
             ">",
         ))?;
 
-        f.write(b"    <path d='")?;
-        for &(_is_cyclic, ref p) in poly_list {
-            let mut v_prev = p.last().unwrap();
-            let mut is_first = true;
-            for v_curr in p {
+        // Paths with shapes are written as their own elements, after the other paths.
+        let shape_elements: Vec<Option<String>> = drawing.paths.iter().map(
+            |path| path_shape_element(scale, path)).collect();
 
-                use intern::math_vector::{
-                    is_finite_vn
+        // Empty path data is an error in SVG 1.1, skip the element (when nothing was traced).
+        if use_path_ids {
+            let paths: Vec<&Path> = drawing.paths.iter().zip(&shape_elements).filter(
+                |&(path, elem)| path.cyclic && elem.is_none()).map(|(path, _)| path).collect();
+            write_paths_filled_with_ids(&mut f, scale, &paths)?;
+        } else if drawing.paths.iter().zip(&shape_elements).any(
+            |(path, elem)| path.cyclic && elem.is_none())
+        {
+            f.write(b"    <path d='")?;
+            for (path, _) in drawing.paths.iter().zip(&shape_elements).filter(
+                |&(path, elem)| path.cyclic && elem.is_none())
+            {
+                write_path_data(&mut f, scale, path)?;
+                f.write(b"\n")?;
+            }
+            writeln!(f, "' />")?;
+        }
+        for (path, elem) in drawing.paths.iter().zip(&shape_elements).filter(
+            |&(path, _)| path.cyclic)
+        {
+            if let Some(ref elem) = *elem {
+                let attrs = if use_path_ids {
+                    path_id_attrs(path.meta.hash)
+                } else {
+                    String::new()
                 };
-                debug_assert!(is_finite_vn(&v_curr[0]));
-                debug_assert!(is_finite_vn(&v_curr[1]));
-                debug_assert!(is_finite_vn(&v_curr[2]));
-
-                let k0 = v_prev[1];
-                let h0 = v_prev[2];
+                writeln!(f, "    {}", element_with_attrs(elem, &attrs))?;
+            }
+        }
 
-                let h1 = v_curr[0];
-                let k1 = v_curr[1];
+        writeln!(f, "  </g>")?;
 
-                // Could optimize this, but keep now for simplicity
-                if is_first {
-                    f.write_fmt(format_args!(
-                        "M {:.2},{:.2} ",
-                        k0[0] * scale,
-                        k0[1] * scale,
-                    ))?;
+        // Open paths don't bound a region (outlines opened at the image border),
+        // write them as strokes.
+        if drawing.paths.iter().any(|path| !path.cyclic) {
+            writeln!(f, concat!("  ",
+                "<g stroke='black' ",
+                "stroke-opacity='1.0' ",
+                "stroke-width='1' ",
+                "fill='none' ",
+                ">",
+            ))?;
+            for (path, elem) in drawing.paths.iter().zip(&shape_elements).filter(
+                |&(path, _)| !path.cyclic)
+            {
+                let attrs = if use_path_ids {
+                    path_id_attrs(path.meta.hash)
+                } else {
+                    String::new()
+                };
+                if let Some(ref elem) = *elem {
+                    writeln!(f, "    {}", element_with_attrs(elem, &attrs))?;
+                    continue;
                 }
-                f.write_fmt(format_args!(
-                    "C {:.2},{:.2} {:.2},{:.2} {:.2},{:.2} ",
-                    h0[0] * scale, h0[1] * scale,
-                    h1[0] * scale, h1[1] * scale,
-                    k1[0] * scale, k1[1] * scale,
-                ))?;
-                v_prev = v_curr;
-                is_first = false;
+                write!(f, "    <path{} d='", attrs)?;
+                write_path_data(&mut f, scale, path)?;
+                writeln!(f, "' />")?;
             }
-
-            f.write(b" Z\n")?;
-
+            writeln!(f, "  </g>")?;
         }
-        writeln!(f, "' />")?;
-
-        writeln!(f, "  </g>")?;
 
         Ok(())
     }
 
-    pub fn write_curve_list_centerline(
-        mut f: &::std::fs::File,
+    /// The widths of paths written as power-strokes, see: `write_drawing_centerline`.
+    fn path_widths(
+        path: &Path,
+    ) -> Option<&Vec<f64>> {
+        // Shapes are written as their own elements.
+        if path.meta.shape.is_some() {
+            return None;
+        }
+        return path.meta.widths.as_ref();
+    }
+
+    /// Paths with widths (see `PathMeta::widths`) are written as Inkscape power-stroke
+    /// path effects, with the outline of the stroke as the path data (for other applications),
+    /// Inkscape recalculates the outline from the center-line when it's edited.
+    ///
+    /// With `use_path_ids`, each path has an `id`, see: `path_id_attrs`.
+    pub fn write_drawing_centerline<W: Write>(
+        mut f: W,
         _size: &[usize; 2],
         scale: f64,
-        poly_list: &LinkedList<(bool, Vec<[[f64; DIMS]; 3]>)>,
+        drawing: &Drawing,
+        use_path_ids: bool,
     ) -> Result<(), ::std::io::Error> {
-        use std::io::prelude::Write;
+        if drawing.paths.iter().any(|path| path_widths(path).is_some()) {
+            writeln!(f, "  <defs>")?;
+            for (i, path) in drawing.paths.iter().enumerate() {
+                if let Some(widths) = path_widths(path) {
+                    // Offset points are the knot index & half the width.
+                    let offset_points = widths.iter().enumerate().map(|(j, w)| {
+                        format!("{},{}", j, num(w * scale / 2.0))
+                    }).collect::<Vec<String>>().join(" | ");
+                    writeln!(f, concat!("    ",
+                        "<inkscape:path-effect effect='powerstroke' id='powerstroke-{}' ",
+                        "is_visible='true' offset_points='{}' sort_points='true' ",
+                        "interpolator_type='CentripetalCatmullRom' interpolator_beta='0.2' ",
+                        "scale_width='1' linejoin_type='round' miter_limit='4' ",
+                        "start_linecap_type='round' end_linecap_type='round' />"),
+                        i, offset_points,
+                    )?;
+                }
+            }
+            writeln!(f, "  </defs>")?;
+        }
 
         writeln!(f, concat!("  ",
             "<g stroke='black' ",
@@ -263,112 +871,833 @@ pub mod svg {
             ">",
         ))?;
 
-        for &(is_cyclic, ref p) in poly_list {
-            if is_cyclic {
-                f.write(b"    <path d='")?;
-                let mut v_prev = p.last().unwrap();
-                let mut is_first = true;
-                for v_curr in p {
+        for (i, path) in drawing.paths.iter().enumerate() {
+            let attrs = if use_path_ids { path_id_attrs(path.meta.hash) } else { String::new() };
+            if let Some(elem) = path_shape_element(scale, path) {
+                writeln!(f, "    {}", element_with_attrs(&elem, &attrs))?;
+                continue;
+            }
+            if let Some(widths) = path_widths(path) {
+                write!(f, "    <path{} d='", attrs)?;
+                for poly in curve_stroke_width::path_outline(
+                    path, widths, LINEAR_TOLERANCE / scale)
+                {
+                    for (j, v) in poly.iter().enumerate() {
+                        write!(f, "{} {},{} ",
+                               if j == 0 { "M" } else { "L" },
+                               num(v[0] * scale), num(v[1] * scale))?;
+                    }
+                    f.write(b"Z ")?;
+                }
+                write!(f, concat!(
+                    "' fill='black' stroke='none' ",
+                    "inkscape:path-effect='#powerstroke-{}' inkscape:original-d='"), i)?;
+                write_path_data(&mut f, scale, path)?;
+                writeln!(f, "' />")?;
+                continue;
+            }
+            write!(f, "    <path{} d='", attrs)?;
+            write_path_data(&mut f, scale, path)?;
+            if let Some(dash) = path.meta.dash {
+                writeln!(f, "' stroke-dasharray='{} {}' />",
+                         num(dash[0] * scale), num(dash[1] * scale))?;
+            } else {
+                writeln!(f, "' />")?;
+            }
+        }
 
-                    use intern::math_vector::{
-                        is_finite_vn,
-                    };
-                    debug_assert!(is_finite_vn(&v_curr[0]));
-                    debug_assert!(is_finite_vn(&v_curr[1]));
-                    debug_assert!(is_finite_vn(&v_curr[2]));
+        writeln!(f, "  </g>")?;
 
-                    let k0 = v_prev[1];
-                    let h0 = v_prev[2];
+        Ok(())
+    }
 
-                    let h1 = v_curr[0];
-                    let k1 = v_curr[1];
+    pub fn write_dots<W: Write>(
+        mut f: W,
+        scale: f64,
+        dots: &Vec<[f64; DIMS]>,
+        radius: f64,
+    ) -> Result<(), ::std::io::Error> {
 
-                    // Could optimize this, but keep now for simplicity
-                    if is_first {
-                        f.write_fmt(format_args!(
-                            "M {:.2},{:.2} ",
-                            k0[0] * scale,
-                            k0[1] * scale,
-                        ))?;
+        writeln!(f, concat!("  ",
+            "<g stroke='none' ",
+            "fill='black' ",
+            "fill-opacity='1' ",
+            ">",
+        ))?;
+
+        for v in dots {
+            writeln!(f,
+                "    <circle cx='{}' cy='{}' r='{}' />",
+                num(v[0] * scale),
+                num(v[1] * scale),
+                num(radius * scale),
+            )?;
+        }
+
+        writeln!(f, "  </g>")?;
+
+        Ok(())
+    }
+
+    pub fn write_footer<W: Write>(
+        mut f: W,
+    ) -> Result<(), ::std::io::Error> {
+        writeln!(f, "</svg>")?;
+        Ok(())
+    }
+
+    /// Insert `content` into an SVG `template` (a string level splice, the template isn't parsed).
+    ///
+    /// * `selector` - `#id` inserts the content at the start of the element with this id,
+    ///   otherwise an empty selector inserts the content before the closing `</svg>`.
+    pub fn template_splice(
+        template: &str,
+        selector: &str,
+        content: &str,
+    ) -> Result<String, String> {
+        if selector.is_empty() {
+            let i = match template.rfind("</svg>") {
+                Some(i) => i,
+                None => return Err("Template has no closing '</svg>'".to_string()),
+            };
+            return Ok(format!("{}{}{}", &template[..i], content, &template[i..]));
+        }
+        if !selector.starts_with('#') || selector.len() == 1 {
+            return Err(format!("Expected a selector '#id', not '{}'", selector));
+        }
+        let id = &selector[1..];
+        // The start of the attribute (after the whitespace before it).
+        let i_attr = {
+            let mut i_attr = None;
+            for quote in &['"', '\''] {
+                let attr = format!("id={}{}{}", quote, id, quote);
+                for (i, _) in template.match_indices(&attr[..]) {
+                    if i > 0 && template[..i].ends_with(char::is_whitespace) {
+                        i_attr = Some(i_attr.map_or(i, |i_prev: usize| i_prev.min(i)));
+                        break;
                     }
-                    f.write_fmt(format_args!(
-                        "C {:.2},{:.2} {:.2},{:.2} {:.2},{:.2} ",
-                        h0[0] * scale, h0[1] * scale,
-                        h1[0] * scale, h1[1] * scale,
-                        k1[0] * scale, k1[1] * scale,
-                    ))?;
-                    v_prev = v_curr;
-                    is_first = false;
                 }
-                f.write(b" Z\n")?;
-                writeln!(f, "' />")?;
+            }
+            match i_attr {
+                Some(i_attr) => i_attr,
+                None => return Err(format!("Template has no element matching '{}'", selector)),
+            }
+        };
+        let i_tag_begin = template[..i_attr].rfind('<').unwrap_or(0);
+        let i_tag_end = match template[i_attr..].find('>') {
+            Some(i) => i_attr + i,
+            None => return Err(format!("Template element '{}' isn't closed", selector)),
+        };
+        if template[..i_tag_end].ends_with('/') {
+            // Expand the self-closing element to contain the content.
+            let tag_name: String = template[i_tag_begin + 1..].chars().take_while(
+                |c| !c.is_whitespace() && *c != '/' && *c != '>').collect();
+            return Ok(format!(
+                "{}>\n{}</{}>{}",
+                template[..i_tag_end - 1].trim_end(), content, tag_name,
+                &template[i_tag_end + 1..],
+            ));
+        }
+        return Ok(format!(
+            "{}\n{}{}",
+            &template[..i_tag_end + 1], content, &template[i_tag_end + 1..],
+        ));
+    }
+
+/*
+    pub fn write_full<W: Write>(
+        mut f: W,
+        size: &[usize; 2],
+        scale: f64,
+        poly_list: &LinkedList<(bool, Vec<[f64; DIMS]>)>,
+    ) -> Result<(), ::std::io::Error> {
+        write_header(&mut f, size, &[0.0, 0.0], scale, false)?;
+        write_poly_list_filled(&mut f, size, scale, poly_list)?;
+        write_footer(&mut f)?;
+        Ok(())
+    }
+*/
+}
+
+/// UFO glyph output (for type design),
+/// see: http://unifiedfontobject.org/versions/ufo3/glyphs/glif/
+pub mod glif {
+
+    use super::{
+        DIMS,
+        xml_escape,
+    };
+    use curve_types::{
+        CubicSegment,
+        Drawing,
+        Knot,
+    };
+    use std::io::prelude::Write;
+
+    /// Maps pixel coordinates to font units.
+    struct FontSpace {
+        scale: f64,
+        baseline: f64,
+    }
+
+    impl FontSpace {
+        fn co(&self, v: &[f64; DIMS]) -> (i64, i64) {
+            (
+                (v[0] * self.scale).round() as i64,
+                ((v[1] - self.baseline) * self.scale).round() as i64,
+            )
+        }
+    }
+
+    fn write_point<W: Write>(
+        mut f: W,
+        space: &FontSpace,
+        v: &[f64; DIMS],
+        point_type: Option<&str>,
+        is_smooth: bool,
+    ) -> Result<(), ::std::io::Error> {
+        let (x, y) = space.co(v);
+        write!(f, "      <point x=\"{}\" y=\"{}\"", x, y)?;
+        if let Some(point_type) = point_type {
+            write!(f, " type=\"{}\"", point_type)?;
+        }
+        if is_smooth {
+            write!(f, " smooth=\"yes\"")?;
+        }
+        writeln!(f, "/>")?;
+        Ok(())
+    }
+
+    /// Write the segment ending at `k_next`, using a line when the handles are on the line
+    /// (within half a font unit).
+    fn write_segment<W: Write>(
+        mut f: W,
+        space: &FontSpace,
+        k_prev: &Knot,
+        k_next: &Knot,
+    ) -> Result<(), ::std::io::Error> {
+        let seg = CubicSegment {
+            p0: k_prev.co,
+            p1: k_prev.handle_next,
+            p2: k_next.handle_prev,
+            p3: k_next.co,
+        };
+        if seg.is_linear(0.5 / space.scale) {
+            write_point(&mut f, space, &k_next.co, Some("line"), false)?;
+        } else {
+            write_point(&mut f, space, &k_prev.handle_next, None, false)?;
+            write_point(&mut f, space, &k_next.handle_prev, None, false)?;
+            write_point(&mut f, space, &k_next.co, Some("curve"), k_next.is_smooth())?;
+        }
+        Ok(())
+    }
+
+    /// Write the drawing as a glyph.
+    ///
+    /// The drawing is written as-is, where the Y axis points up (flipped from the image),
+    /// see: `OutputFormat::is_y_up`.
+    ///
+    /// * `upm` - units per em, the image height is scaled to this size.
+    /// * `baseline` - the Y coordinate of the baseline in the drawing.
+    /// * `params` - optional parameters, written into the glyphs `lib`.
+    pub fn write_glyph<W: Write>(
+        mut f: W,
+        name: &str,
+        size: &[usize; 2],
+        drawing: &Drawing,
+        upm: f64,
+        baseline: f64,
+        params: Option<&Vec<(&str, String)>>,
+    ) -> Result<(), ::std::io::Error> {
+        let space = FontSpace {
+            scale: upm / size[1] as f64,
+            baseline: baseline,
+        };
+
+        writeln!(f, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        writeln!(f, "<glyph name=\"{}\" format=\"2\">", xml_escape(name))?;
+        writeln!(f, "  <advance width=\"{}\"/>",
+                 (size[0] as f64 * space.scale).round() as i64)?;
+        writeln!(f, "  <outline>")?;
+        for path in &drawing.paths {
+            let knots = &path.knots;
+            if knots.len() == 0 {
+                continue;
+            }
+            writeln!(f, "    <contour>")?;
+            if path.cyclic {
+                // The last point closes the contour.
+                let mut k_prev = &knots[knots.len() - 1];
+                for k_curr in knots {
+                    write_segment(&mut f, &space, k_prev, k_curr)?;
+                    k_prev = k_curr;
+                }
             } else {
-                f.write(b"    <path d='")?;
+                write_point(&mut f, &space, &knots[0].co, Some("move"), false)?;
+                for i in 1..knots.len() {
+                    write_segment(&mut f, &space, &knots[i - 1], &knots[i])?;
+                }
+            }
+            writeln!(f, "    </contour>")?;
+        }
+        writeln!(f, "  </outline>")?;
 
-                let mut v_prev = &p[0];
-                let mut is_first = true;
-                for v_curr in &p[1..p.len()] {
+        if let Some(params) = params {
+            writeln!(f, "  <lib>")?;
+            writeln!(f, "    <dict>")?;
+            writeln!(f, "      <key>com.github.ideasman42.{}</key>", env!("CARGO_PKG_NAME"))?;
+            writeln!(f, "      <dict>")?;
+            writeln!(f, "        <key>version</key>")?;
+            writeln!(f, "        <string>{}</string>", env!("CARGO_PKG_VERSION"))?;
+            for &(name, ref value) in params {
+                writeln!(f, "        <key>{}</key>", xml_escape(name))?;
+                writeln!(f, "        <string>{}</string>", xml_escape(value))?;
+            }
+            writeln!(f, "      </dict>")?;
+            writeln!(f, "    </dict>")?;
+            writeln!(f, "  </lib>")?;
+        }
 
-                    use intern::math_vector::{
-                        is_finite_vn,
-                    };
-                    debug_assert!(is_finite_vn(&v_curr[0]));
-                    debug_assert!(is_finite_vn(&v_curr[1]));
-                    debug_assert!(is_finite_vn(&v_curr[2]));
+        writeln!(f, "</glyph>")?;
+        Ok(())
+    }
+}
 
-                    let k0 = v_prev[1];
-                    let h0 = v_prev[2];
+/// PDF output, streams are uncompressed since there is no compression library,
+/// see: https://opensource.adobe.com/dc-acrobat-sdk-docs/pdfstandards/PDF32000_2008.pdf
+pub mod pdf {
 
-                    let h1 = v_curr[0];
-                    let k1 = v_curr[1];
+    use super::{
+        DIMS,
+        Num,
+    };
+    use curve_types::{
+        Drawing,
+        Path,
+    };
+    use std::io::prelude::Write;
 
-                    // Could optimize this, but keep now for simplicity
-                    if is_first {
-                        f.write_fmt(format_args!(
-                            "M {:.2},{:.2} ",
-                            k0[0] * scale,
-                            k0[1] * scale,
-                        ))?;
-                    }
-                    f.write_fmt(format_args!(
-                        "C {:.2},{:.2} {:.2},{:.2} {:.2},{:.2} ",
-                        h0[0] * scale, h0[1] * scale,
-                        h1[0] * scale, h1[1] * scale,
-                        k1[0] * scale, k1[1] * scale,
-                    ))?;
-                    v_prev = v_curr;
-                    is_first = false;
+    /// Coordinates are written with 2 digits after the decimal point (in points).
+    fn num(
+        value: f64,
+    ) -> Num {
+        return Num::new(value, 2);
+    }
+
+    /// Approximate a quarter circle with a cubic curve.
+    const CIRCLE_HANDLE: f64 = 0.5522847498;
+
+    /// A page of a document, see: `write_document`.
+    pub struct Page {
+        /// The page size (in output units, written as points).
+        pub size: [f64; DIMS],
+        /// Drawing operators, see: `write_page_content`.
+        pub content: Vec<u8>,
+    }
+
+    /// Maps drawing coordinates to the page.
+    struct PageSpace {
+        view_min: [f64; DIMS],
+        scale: f64,
+    }
+
+    impl PageSpace {
+        fn co(&self, v: &[f64; DIMS]) -> [f64; DIMS] {
+            [
+                (v[0] - self.view_min[0]) * self.scale,
+                (v[1] - self.view_min[1]) * self.scale,
+            ]
+        }
+    }
+
+    fn write_path_data<W: Write>(
+        mut f: W,
+        space: &PageSpace,
+        path: &Path,
+    ) -> Result<(), ::std::io::Error> {
+        for (i, seg) in path.segments().enumerate() {
+            if i == 0 {
+                let p0 = space.co(&seg.p0);
+                writeln!(f, "{} {} m", num(p0[0]), num(p0[1]))?;
+            }
+            let (p1, p2, p3) = (space.co(&seg.p1), space.co(&seg.p2), space.co(&seg.p3));
+            writeln!(f, "{} {} {} {} {} {} c",
+                     num(p1[0]), num(p1[1]), num(p2[0]), num(p2[1]), num(p3[0]), num(p3[1]))?;
+        }
+        if path.cyclic {
+            writeln!(f, "h")?;
+        }
+        Ok(())
+    }
+
+    /// Write the drawing as the content of a page.
+    ///
+    /// The drawing is written as-is, where the Y axis points up (flipped from the image),
+    /// see: `OutputFormat::is_y_up`.
+    ///
+    /// * `view_min` - the drawing coordinates at the bottom left of the page.
+    /// * `is_filled` - fill cyclic paths (stroking open paths), otherwise stroke all paths.
+    pub fn write_page_content<W: Write>(
+        mut f: W,
+        view_min: &[f64; DIMS],
+        scale: f64,
+        drawing: &Drawing,
+        is_filled: bool,
+        dots: &[[f64; DIMS]],
+        dot_radius: f64,
+    ) -> Result<(), ::std::io::Error> {
+        let space = PageSpace {
+            view_min: *view_min,
+            scale: scale,
+        };
+        writeln!(f, "0 g 0 G 1 w 1 j 1 J")?;
+        if is_filled {
+            let mut is_empty = true;
+            for path in drawing.paths.iter().filter(|path| path.cyclic) {
+                write_path_data(&mut f, &space, path)?;
+                is_empty = false;
+            }
+            if !is_empty {
+                writeln!(f, "f")?;
+            }
+        }
+        for path in drawing.paths.iter().filter(|path| !(is_filled && path.cyclic)) {
+            if let Some(dash) = path.meta.dash {
+                writeln!(f, "[{} {}] 0 d", num(dash[0] * scale), num(dash[1] * scale))?;
+            }
+            write_path_data(&mut f, &space, path)?;
+            writeln!(f, "S")?;
+            if path.meta.dash.is_some() {
+                writeln!(f, "[] 0 d")?;
+            }
+        }
+        if !dots.is_empty() {
+            let r = dot_radius * scale;
+            let k = CIRCLE_HANDLE;
+            // Quarter circles (two handles & end point), as offsets from the center.
+            let arcs: [[[f64; DIMS]; 3]; 4] = [
+                [[1.0, k], [k, 1.0], [0.0, 1.0]],
+                [[-k, 1.0], [-1.0, k], [-1.0, 0.0]],
+                [[-1.0, -k], [-k, -1.0], [0.0, -1.0]],
+                [[k, -1.0], [1.0, -k], [1.0, 0.0]],
+            ];
+            for v in dots {
+                let c = space.co(v);
+                writeln!(f, "{} {} m", num(c[0] + r), num(c[1]))?;
+                for arc in &arcs {
+                    writeln!(f, "{} {} {} {} {} {} c",
+                             num(c[0] + arc[0][0] * r), num(c[1] + arc[0][1] * r),
+                             num(c[0] + arc[1][0] * r), num(c[1] + arc[1][1] * r),
+                             num(c[0] + arc[2][0] * r), num(c[1] + arc[2][1] * r))?;
+                }
+                writeln!(f, "h")?;
+            }
+            writeln!(f, "f")?;
+        }
+        Ok(())
+    }
+
+    fn string_escape(
+        text: &str,
+    ) -> String {
+        let mut text_escape = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                '(' | ')' | '\\' => {
+                    text_escape.push('\\');
+                    text_escape.push(c);
                 }
+                // Non-ASCII text would need a text string encoding.
+                c if c.is_ascii() && !c.is_ascii_control() => text_escape.push(c),
+                _ => text_escape.push('?'),
+            }
+        }
+        return text_escape;
+    }
 
-                writeln!(f, "' />")?;
+    fn name_escape(
+        text: &str,
+    ) -> String {
+        let mut text_escape = String::with_capacity(text.len());
+        for b in text.bytes() {
+            if b.is_ascii_alphanumeric() || b == b'-' || b == b'_' {
+                text_escape.push(b as char);
+            } else {
+                text_escape.push_str(&format!("#{:02X}", b));
             }
         }
+        return text_escape;
+    }
 
-        writeln!(f, "  </g>")?;
+    /// Write a document of pages.
+    ///
+    /// * `params` - optional parameters, written into the document information.
+    pub fn write_document<W: Write>(
+        mut f: W,
+        pages: &[Page],
+        params: Option<&Vec<(&str, String)>>,
+    ) -> Result<(), ::std::io::Error> {
+        // Objects are written into memory first, since the offset of each is needed.
+        let mut data: Vec<u8> = vec![];
+        let mut offsets: Vec<usize> = vec![];
+        data.extend_from_slice(b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n");
 
+        // Catalog, pages & information, followed by a page & its content for each page.
+        let page_object = |i: usize| 4 + i * 2;
+        offsets.push(data.len());
+        writeln!(data, "1 0 obj\n<< /Type /Catalog /Pages 2 0 R >>\nendobj")?;
+        offsets.push(data.len());
+        writeln!(data, "2 0 obj\n<< /Type /Pages /Count {} /Kids [{}] >>\nendobj",
+                 pages.len(),
+                 (0..pages.len()).map(|i| format!("{} 0 R", page_object(i)))
+                     .collect::<Vec<String>>().join(" "))?;
+        offsets.push(data.len());
+        write!(data, "3 0 obj\n<< /Producer ({} {})",
+               env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))?;
+        if let Some(params) = params {
+            for &(name, ref value) in params {
+                write!(data, " /{} ({})", name_escape(name), string_escape(value))?;
+            }
+        }
+        writeln!(data, " >>\nendobj")?;
+        for (i, page) in pages.iter().enumerate() {
+            offsets.push(data.len());
+            writeln!(data, concat!("{} 0 obj\n<< /Type /Page /Parent 2 0 R ",
+                                   "/MediaBox [0 0 {} {}] /Contents {} 0 R >>\nendobj"),
+                     page_object(i), num(page.size[0]), num(page.size[1]), page_object(i) + 1)?;
+            offsets.push(data.len());
+            writeln!(data, "{} 0 obj\n<< /Length {} >>\nstream",
+                     page_object(i) + 1, page.content.len())?;
+            data.extend_from_slice(&page.content);
+            writeln!(data, "\nendstream\nendobj")?;
+        }
+
+        let xref_offset = data.len();
+        writeln!(data, "xref\n0 {}\n0000000000 65535 f ", offsets.len() + 1)?;
+        for offset in &offsets {
+            writeln!(data, "{:010} 00000 n ", offset)?;
+        }
+        writeln!(data, "trailer\n<< /Size {} /Root 1 0 R /Info 3 0 R >>", offsets.len() + 1)?;
+        writeln!(data, "startxref\n{}\n%%EOF", xref_offset)?;
+        f.write_all(&data)?;
         Ok(())
     }
+}
+
+/// JSON output, for data which isn't drawn.
+pub mod json {
 
-    pub fn write_footer(
-        mut f: &::std::fs::File,
+    use super::{
+        DIMS,
+        Num,
+    };
+    use curve_types::Drawing;
+    use intern::curve_fit_nd::{
+        RefineStage,
+        RefineStep,
+    };
+    use std::io::prelude::Write;
+
+    /// Numbers are written exactly, for applications which use the values directly.
+    fn num(
+        value: f64,
+    ) -> Num {
+        return Num::exact(value);
+    }
+
+    /// Escape a string for use within double quotes.
+    pub fn string_escape(text: &str) -> String {
+        let mut text_escape = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                '"' => text_escape.push_str("\\\""),
+                '\\' => text_escape.push_str("\\\\"),
+                '\n' => text_escape.push_str("\\n"),
+                '\r' => text_escape.push_str("\\r"),
+                '\t' => text_escape.push_str("\\t"),
+                c if (c as u32) < 0x20 => text_escape.push_str(&format!("\\u{:04x}", c as u32)),
+                _ => text_escape.push(c),
+            }
+        }
+        return text_escape;
+    }
+
+    /// Write the paths & dots as a single line, for applications which use the curves directly.
+    ///
+    /// Each knot is `[handle_prev_x, handle_prev_y, x, y, handle_next_x, handle_next_y]`,
+    /// coordinates are in output units (pixels multiplied by `scale`, Y down).
+    pub fn write_drawing<W: Write>(
+        mut f: W,
+        size: &[usize; 2],
+        scale: f64,
+        drawing: &Drawing,
+        dots: &[[f64; DIMS]],
+        dot_radius: f64,
     ) -> Result<(), ::std::io::Error> {
-        use std::io::prelude::Write;
-        writeln!(f, "</svg>")?;
+        write!(f, "{{\"size\": [{}, {}], \"paths\": [",
+               num(size[0] as f64 * scale), num(size[1] as f64 * scale))?;
+        for (i, path) in drawing.paths.iter().enumerate() {
+            write!(f, "{}{{\"cyclic\": {}, \"hole\": {}, \"knots\": [",
+                   if i != 0 { ", " } else { "" }, path.cyclic, path.meta.is_hole)?;
+            for (j, knot) in path.knots.iter().enumerate() {
+                write!(f, "{}[{}, {}, {}, {}, {}, {}]",
+                       if j != 0 { ", " } else { "" },
+                       num(knot.handle_prev[0] * scale), num(knot.handle_prev[1] * scale),
+                       num(knot.co[0] * scale), num(knot.co[1] * scale),
+                       num(knot.handle_next[0] * scale), num(knot.handle_next[1] * scale))?;
+            }
+            write!(f, "]}}")?;
+        }
+        write!(f, "], \"dots\": [")?;
+        for (i, v) in dots.iter().enumerate() {
+            write!(f, "{}[{}, {}]",
+                   if i != 0 { ", " } else { "" }, num(v[0] * scale), num(v[1] * scale))?;
+        }
+        write!(f, "], \"dot_radius\": {}}}", num(dot_radius * scale))?;
         Ok(())
     }
 
-/*
-    pub fn write_full(
-        f: &::std::fs::File,
+    /// Write the paths with widths (see `PathMeta::widths`) & dots as a single line,
+    /// for drawing applications which support variable width strokes.
+    ///
+    /// Knots match `write_drawing`, with the stroke width at each knot in `widths`,
+    /// dots are strokes with a single knot (where the width is the dot diameter).
+    pub fn write_strokes<W: Write>(
+        mut f: W,
         size: &[usize; 2],
         scale: f64,
-        poly_list: &LinkedList<(bool, Vec<[f64; DIMS]>)>,
+        drawing: &Drawing,
+        dots: &[[f64; DIMS]],
+        dot_radius: f64,
+    ) -> Result<(), ::std::io::Error> {
+        write!(f, "{{\"size\": [{}, {}], \"strokes\": [",
+               num(size[0] as f64 * scale), num(size[1] as f64 * scale))?;
+        let mut is_first = true;
+        for path in &drawing.paths {
+            let widths = match path.meta.widths {
+                Some(ref widths) => widths,
+                None => continue,
+            };
+            write!(f, "{}{{\"cyclic\": {}, \"knots\": [",
+                   if !is_first { ", " } else { "" }, path.cyclic)?;
+            for (j, knot) in path.knots.iter().enumerate() {
+                write!(f, "{}[{}, {}, {}, {}, {}, {}]",
+                       if j != 0 { ", " } else { "" },
+                       num(knot.handle_prev[0] * scale), num(knot.handle_prev[1] * scale),
+                       num(knot.co[0] * scale), num(knot.co[1] * scale),
+                       num(knot.handle_next[0] * scale), num(knot.handle_next[1] * scale))?;
+            }
+            write!(f, "], \"widths\": [{}]}}", widths.iter().map(
+                |w| num(w * scale).to_string()).collect::<Vec<String>>().join(", "))?;
+            is_first = false;
+        }
+        for v in dots {
+            let (x, y) = (num(v[0] * scale), num(v[1] * scale));
+            write!(f, "{}{{\"cyclic\": false, \"knots\": [[{}, {}, {}, {}, {}, {}]], ",
+                   if !is_first { ", " } else { "" }, x, y, x, y, x, y)?;
+            write!(f, "\"widths\": [{}]}}", num(dot_radius * 2.0 * scale))?;
+            is_first = false;
+        }
+        write!(f, "]}}")?;
+        Ok(())
+    }
+
+    /// Write the knot removals for each path, in the order they were performed.
+    pub fn write_refine_log<W: Write>(
+        mut f: W,
+        refine_log: &[Vec<RefineStep>],
     ) -> Result<(), ::std::io::Error> {
-        write_header(f, size, scale)?;
-        write_poly_list_filled(f, size, scale, poly_list)?;
-        write_footer(f)?;
+        writeln!(f, "{{")?;
+        writeln!(f, "  \"paths\": [")?;
+        for (i, steps) in refine_log.iter().enumerate() {
+            writeln!(f, "    [")?;
+            for (j, step) in steps.iter().enumerate() {
+                write!(f, "      {{\"stage\": \"{}\", \"co\": [{}, {}]",
+                       match step.stage {
+                           RefineStage::Remove => "remove",
+                           RefineStage::Refit => "refit",
+                       },
+                       num(step.co[0]), num(step.co[1]))?;
+                if let Some(co_refit) = step.co_refit {
+                    write!(f, ", \"co_refit\": [{}, {}]", num(co_refit[0]), num(co_refit[1]))?;
+                }
+                writeln!(f, ", \"error\": {}}}{}",
+                         num(step.error), if j + 1 != steps.len() { "," } else { "" })?;
+            }
+            writeln!(f, "    ]{}", if i + 1 != refine_log.len() { "," } else { "" })?;
+        }
+        writeln!(f, "  ]")?;
+        writeln!(f, "}}")?;
+        Ok(())
+    }
+}
+
+/// CSV output, for measurements.
+pub mod csv {
+
+    use super::Num;
+    use curve_types::RegionMeasurement;
+    use std::io::prelude::Write;
+
+    /// Numbers are written exactly, see: `json::num`.
+    fn num(
+        value: f64,
+    ) -> Num {
+        return Num::exact(value);
+    }
+
+    /// Write one row for each region,
+    /// areas are written in pixels as well as scaled units, other columns in scaled units.
+    pub fn write_measurements<W: Write>(
+        mut f: W,
+        measurements: &[RegionMeasurement],
+        scale: f64,
+    ) -> Result<(), ::std::io::Error> {
+        writeln!(f, "region,cyclic,hole,area_pixels,area,perimeter,\
+                     centroid_x,centroid_y,min_x,min_y,max_x,max_y")?;
+        for m in measurements {
+            writeln!(f, "{},{},{},{},{},{},{},{},{},{},{},{}",
+                     m.region, m.is_cyclic, m.is_hole,
+                     num(m.area), num(m.area * scale * scale), num(m.perimeter * scale),
+                     num(m.centroid[0] * scale), num(m.centroid[1] * scale),
+                     num(m.bounds_min[0] * scale), num(m.bounds_min[1] * scale),
+                     num(m.bounds_max[0] * scale), num(m.bounds_max[1] * scale))?;
+        }
         Ok(())
     }
-*/
 }
 
+pub mod html {
+
+    use super::{
+        Num,
+        xml_escape,
+    };
+    use std::io::prelude::Write;
+
+    /// Bins of the fit error histogram (from zero to the error threshold).
+    const HISTOGRAM_BINS: usize = 20;
+
+    /// Encode `data` as base64 (for embedding files as data URIs).
+    pub fn base64_encode(data: &[u8]) -> String {
+        const TABLE: &[u8; 64] =
+            b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut text = String::with_capacity(((data.len() + 2) / 3) * 4);
+        for chunk in data.chunks(3) {
+            let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+            let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | (b[2] as u32);
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    text.push(TABLE[((n >> (18 - (i * 6))) & 63) as usize] as char);
+                } else {
+                    text.push('=');
+                }
+            }
+        }
+        return text;
+    }
+
+    /// The fit errors as an SVG bar chart, errors above the threshold are counted in the last bar.
+    fn write_error_histogram<W: Write>(
+        mut f: W,
+        errors: &[f64],
+        error_threshold: f64,
+    ) -> Result<(), ::std::io::Error> {
+        const BAR_WIDTH: usize = 16;
+        const HEIGHT: f64 = 120.0;
+        let mut histogram = [0_usize; HISTOGRAM_BINS];
+        for &error in errors {
+            let bin = (error / error_threshold * HISTOGRAM_BINS as f64) as usize;
+            histogram[::std::cmp::min(bin, HISTOGRAM_BINS - 1)] += 1;
+        }
+        let count_max = ::std::cmp::max(1, histogram.iter().cloned().max().unwrap_or(0));
+        writeln!(f, "<svg xmlns='http://www.w3.org/2000/svg' width='{}' height='{}'>",
+                 HISTOGRAM_BINS * BAR_WIDTH, HEIGHT)?;
+        for (i, &count) in histogram.iter().enumerate() {
+            let height = HEIGHT * (count as f64 / count_max as f64);
+            let error_min = error_threshold * i as f64 / HISTOGRAM_BINS as f64;
+            writeln!(f, concat!("  <rect x='{}' y='{}' width='{}' height='{}' fill='#444'>",
+                                "<title>{} - {}: {}</title></rect>"),
+                     i * BAR_WIDTH, Num::new(HEIGHT - height, 2), BAR_WIDTH - 1,
+                     Num::new(height, 2),
+                     Num::new(error_min, 3),
+                     Num::new(error_min + (error_threshold / HISTOGRAM_BINS as f64), 3),
+                     count)?;
+        }
+        writeln!(f, "</svg>")?;
+        Ok(())
+    }
+
+    /// Write a single HTML file summarizing a trace (for sharing & checking results).
+    ///
+    /// * `image_png` - the input image (as traced), embedded as a PNG.
+    /// * `svg` - the output, embedded as an image.
+    /// * `params` - the parameters used for tracing, see: `svg::write_metadata`.
+    /// * `timings` - the time taken by each stage (in seconds).
+    /// * `errors` - the distance from each point of the extracted polygons to the curves.
+    pub fn write_report<W: Write>(
+        mut f: W,
+        title: &str,
+        image_png: &[u8],
+        svg: &[u8],
+        params: &[(&str, String)],
+        timings: &[(&str, f64)],
+        errors: &[f64],
+        error_threshold: f64,
+    ) -> Result<(), ::std::io::Error> {
+        writeln!(f, "<!DOCTYPE html>")?;
+        writeln!(f, "<html>")?;
+        writeln!(f, "<head>")?;
+        writeln!(f, "<meta charset='UTF-8'>")?;
+        writeln!(f, "<title>{}</title>", xml_escape(title))?;
+        writeln!(f, concat!(
+            "<style>",
+            "body {{ font-family: sans-serif; }} ",
+            "img {{ max-width: 45%; border: 1px solid #ccc; background: white; }} ",
+            "table {{ border-collapse: collapse; }} ",
+            "td, th {{ border: 1px solid #ccc; padding: 2px 8px; text-align: left; }}",
+            "</style>"))?;
+        writeln!(f, "</head>")?;
+        writeln!(f, "<body>")?;
+        writeln!(f, "<h1>{}</h1>", xml_escape(title))?;
+        writeln!(f, "<p>{} {}</p>", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))?;
+
+        writeln!(f, "<h2>Input &amp; Output</h2>")?;
+        writeln!(f, "<img alt='Input' src='data:image/png;base64,{}'>",
+                 base64_encode(image_png))?;
+        writeln!(f, "<img alt='Output' src='data:image/svg+xml;base64,{}'>",
+                 base64_encode(svg))?;
+
+        writeln!(f, "<h2>Timings</h2>")?;
+        writeln!(f, "<table>")?;
+        for &(name, seconds) in timings {
+            writeln!(f, "<tr><th>{}</th><td>{} ms</td></tr>",
+                     xml_escape(name), Num::new(seconds * 1000.0, 2))?;
+        }
+        let seconds_total: f64 = timings.iter().map(|&(_, seconds)| seconds).sum();
+        writeln!(f, "<tr><th>total</th><td>{} ms</td></tr>", Num::new(seconds_total * 1000.0, 2))?;
+        writeln!(f, "</table>")?;
+
+        writeln!(f, "<h2>Fit Error</h2>")?;
+        let error_max = errors.iter().cloned().fold(0.0, f64::max);
+        let error_mean = if errors.is_empty() {
+            0.0
+        } else {
+            errors.iter().sum::<f64>() / errors.len() as f64
+        };
+        writeln!(f, "<p>Points: {}, mean: {}, max: {} (threshold: {})</p>",
+                 errors.len(), Num::new(error_mean, 3), Num::new(error_max, 3),
+                 Num::exact(error_threshold))?;
+        write_error_histogram(&mut f, errors, error_threshold)?;
+
+        writeln!(f, "<h2>Parameters</h2>")?;
+        writeln!(f, "<table>")?;
+        for &(name, ref value) in params {
+            writeln!(f, "<tr><th>{}</th><td>{}</td></tr>", xml_escape(name), xml_escape(value))?;
+        }
+        writeln!(f, "</table>")?;
+        writeln!(f, "</body>")?;
+        writeln!(f, "</html>")?;
+        Ok(())
+    }
+}
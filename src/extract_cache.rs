@@ -0,0 +1,377 @@
+///
+/// Cache polygons extracted from images on disk, see: `TraceParams::cache_dirpath`.
+///
+/// Files are named from a hash of the image & the parameters used for extraction,
+/// so running again with only fitting parameters changed skips extraction entirely.
+/// Debug passes aren't stored.
+///
+
+use std::collections::LinkedList;
+use std::io::{
+    Error,
+    ErrorKind,
+};
+use std::path::PathBuf;
+use std::sync::atomic::{
+    AtomicUsize,
+    Ordering,
+};
+
+use curve_types::{
+    PathMeta,
+    RegionMeasurement,
+    Shape,
+};
+use {
+    TraceExtract,
+    TraceParams,
+};
+
+/// Changed whenever the file layout changes, so older files are never read.
+const CACHE_MAGIC: &[u8; 8] = b"RRCACHE2";
+
+/// Parameters (using the command line argument names) which change extraction,
+/// see: `TraceParams::extract_eq`.
+const EXTRACT_PARAMS: &[&str] = &[
+    "mode", "turnpolicy", "edge-policy", "contours", "hull", "join-dashes", "dash-array", "dots",
+    "min-feature", "remove-hatching", "fill-holes", "silhouette",
+    "simplify", "simplify-small", "simplify-large", "simplify-cutoff",
+    "deterministic-fit", "resample-adaptive",
+];
+
+pub const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// Files are written under a temporary name, then renamed,
+/// so threads tracing identical inputs never read a partially written file.
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// 64 bit FNV-1a, stable between runs & platforms (unlike the standard library hasher).
+pub fn hash_fnv1a(
+    mut hash: u64,
+    data: &[u8],
+) -> u64 {
+    for b in data {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    return hash;
+}
+
+/// Identifies the cache file for an image & parameters.
+pub struct Key {
+    /// The parameters & image size, stored in the file to detect hash collisions.
+    text: String,
+    pub filepath: PathBuf,
+}
+
+impl Key {
+    pub fn new(
+        trace_params: &TraceParams,
+        image: &[bool],
+        size: &[usize; 2],
+        length_threshold: f64,
+    ) -> Key {
+        let mut text = format!("size={}x{} length-threshold={}",
+                               size[0], size[1], length_threshold);
+        for (name, value) in trace_params.metadata_items() {
+            if EXTRACT_PARAMS.contains(&name) {
+                text.push_str(&format!(" {}={}", name, value));
+            }
+        }
+        let mut hash = hash_fnv1a(FNV_OFFSET, text.as_bytes());
+        hash = hash_fnv1a(hash, &image_pack(image));
+        return Key {
+            text: text,
+            filepath: PathBuf::from(&trace_params.cache_dirpath).join(
+                format!("{:016x}.cache", hash)),
+        };
+    }
+}
+
+/// Pixels as bits, most significant first.
+fn image_pack(
+    image: &[bool],
+) -> Vec<u8> {
+    return image.chunks(8).map(|pixels| {
+        pixels.iter().enumerate().fold(0, |b, (i, &is_fill)| {
+            if is_fill { b | (0x80 >> i) } else { b }
+        })
+    }).collect();
+}
+
+// Writing, values are little endian, `f64` values are stored exactly.
+
+fn write_u64(data: &mut Vec<u8>, v: u64) {
+    data.extend_from_slice(&v.to_le_bytes());
+}
+
+fn write_f64(data: &mut Vec<u8>, v: f64) {
+    write_u64(data, v.to_bits());
+}
+
+fn write_bool(data: &mut Vec<u8>, v: bool) {
+    data.push(v as u8);
+}
+
+fn write_co(data: &mut Vec<u8>, v: &[f64; 2]) {
+    write_f64(data, v[0]);
+    write_f64(data, v[1]);
+}
+
+fn write_bytes(data: &mut Vec<u8>, v: &[u8]) {
+    write_u64(data, v.len() as u64);
+    data.extend_from_slice(v);
+}
+
+fn write_path_meta(data: &mut Vec<u8>, meta: &PathMeta) {
+    write_u64(data, meta.region as u64);
+    write_f64(data, meta.area);
+    write_bool(data, meta.is_hole);
+    write_bool(data, meta.hash.is_some());
+    write_u64(data, meta.hash.unwrap_or(0));
+    write_bool(data, meta.width.is_some());
+    write_f64(data, meta.width.unwrap_or(0.0));
+    write_bool(data, meta.dash.is_some());
+    write_co(data, &meta.dash.unwrap_or([0.0, 0.0]));
+    match meta.shape {
+        None => {
+            data.push(0);
+        }
+        Some(Shape::Ellipse { center, axes }) => {
+            data.push(1);
+            for v in &[center, axes[0], axes[1]] {
+                write_co(data, v);
+            }
+        }
+        Some(Shape::Rect { corner, edges }) => {
+            data.push(2);
+            for v in &[corner, edges[0], edges[1]] {
+                write_co(data, v);
+            }
+        }
+        Some(Shape::Line { p0, p1 }) => {
+            data.push(3);
+            for v in &[p0, p1] {
+                write_co(data, v);
+            }
+        }
+    }
+}
+
+fn write_measurement(data: &mut Vec<u8>, measurement: &RegionMeasurement) {
+    write_u64(data, measurement.region as u64);
+    write_bool(data, measurement.is_cyclic);
+    write_bool(data, measurement.is_hole);
+    write_f64(data, measurement.area);
+    write_f64(data, measurement.perimeter);
+    for v in &[measurement.centroid, measurement.bounds_min, measurement.bounds_max] {
+        write_co(data, v);
+    }
+}
+
+// Reading, any invalid data returns an error (never panics).
+
+fn read_invalid() -> Error {
+    return Error::new(ErrorKind::InvalidData, "Invalid cache file");
+}
+
+fn read_slice<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8], Error> {
+    if data.len() < len {
+        return Err(read_invalid());
+    }
+    let (head, tail) = data.split_at(len);
+    *data = tail;
+    return Ok(head);
+}
+
+fn read_u64(data: &mut &[u8]) -> Result<u64, Error> {
+    let mut buf: [u8; 8] = [0; 8];
+    buf.copy_from_slice(read_slice(data, 8)?);
+    return Ok(u64::from_le_bytes(buf));
+}
+
+/// A length, which can't exceed the remaining data (with at least one byte per item).
+fn read_len(data: &mut &[u8]) -> Result<usize, Error> {
+    let len = read_u64(data)?;
+    if len > data.len() as u64 {
+        return Err(read_invalid());
+    }
+    return Ok(len as usize);
+}
+
+fn read_f64(data: &mut &[u8]) -> Result<f64, Error> {
+    return Ok(f64::from_bits(read_u64(data)?));
+}
+
+fn read_bool(data: &mut &[u8]) -> Result<bool, Error> {
+    match read_slice(data, 1)?[0] {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(read_invalid()),
+    }
+}
+
+fn read_co(data: &mut &[u8]) -> Result<[f64; 2], Error> {
+    return Ok([read_f64(data)?, read_f64(data)?]);
+}
+
+fn read_path_meta(data: &mut &[u8]) -> Result<PathMeta, Error> {
+    let region = read_u64(data)? as usize;
+    let area = read_f64(data)?;
+    let is_hole = read_bool(data)?;
+    let hash = if read_bool(data)? { Some(read_u64(data)?) } else { read_u64(data)?; None };
+    let width = if read_bool(data)? { Some(read_f64(data)?) } else { read_f64(data)?; None };
+    let dash = if read_bool(data)? { Some(read_co(data)?) } else { read_co(data)?; None };
+    let shape = match read_slice(data, 1)?[0] {
+        0 => None,
+        1 => Some(Shape::Ellipse {
+            center: read_co(data)?,
+            axes: [read_co(data)?, read_co(data)?],
+        }),
+        2 => Some(Shape::Rect {
+            corner: read_co(data)?,
+            edges: [read_co(data)?, read_co(data)?],
+        }),
+        3 => Some(Shape::Line {
+            p0: read_co(data)?,
+            p1: read_co(data)?,
+        }),
+        _ => {
+            return Err(read_invalid());
+        }
+    };
+    return Ok(PathMeta {
+        region: region,
+        area: area,
+        is_hole: is_hole,
+        hash: hash,
+        width: width,
+        dash: dash,
+        shape: shape,
+        // Measured after fitting, so never cached.
+        widths: None,
+    });
+}
+
+fn read_measurement(data: &mut &[u8]) -> Result<RegionMeasurement, Error> {
+    return Ok(RegionMeasurement {
+        region: read_u64(data)? as usize,
+        is_cyclic: read_bool(data)?,
+        is_hole: read_bool(data)?,
+        area: read_f64(data)?,
+        perimeter: read_f64(data)?,
+        centroid: read_co(data)?,
+        bounds_min: read_co(data)?,
+        bounds_max: read_co(data)?,
+    });
+}
+
+fn extract_from_bytes(
+    mut data: &[u8],
+    key: &Key,
+    image_len: usize,
+) -> Result<TraceExtract, Error> {
+    let data = &mut data;
+    if read_slice(data, CACHE_MAGIC.len())? != CACHE_MAGIC {
+        return Err(read_invalid());
+    }
+    let text_len = read_len(data)?;
+    if read_slice(data, text_len)? != key.text.as_bytes() {
+        return Err(Error::new(ErrorKind::InvalidData, "Cache file is for other parameters"));
+    }
+
+    let image_packed = read_slice(data, (image_len + 7) / 8)?;
+    let image: Vec<bool> = (0..image_len).map(|i| {
+        (image_packed[i / 8] & (0x80 >> (i % 8))) != 0
+    }).collect();
+
+    let mut dots = vec![];
+    for _ in 0..read_len(data)? {
+        dots.push(read_co(data)?);
+    }
+    let mut meta_list = vec![];
+    for _ in 0..read_len(data)? {
+        meta_list.push(read_path_meta(data)?);
+    }
+    let mut measurements = vec![];
+    for _ in 0..read_len(data)? {
+        measurements.push(read_measurement(data)?);
+    }
+    let mut poly_list_to_fit = LinkedList::new();
+    for _ in 0..read_len(data)? {
+        let is_cyclic = read_bool(data)?;
+        let mut poly = vec![];
+        for _ in 0..read_len(data)? {
+            poly.push(read_co(data)?);
+        }
+        poly_list_to_fit.push_back((is_cyclic, poly));
+    }
+    if !data.is_empty() || poly_list_to_fit.len() != meta_list.len() {
+        return Err(read_invalid());
+    }
+
+    return Ok(TraceExtract {
+        image: image,
+        dots: dots,
+        meta_list: meta_list,
+        measurements: measurements,
+        poly_list_to_fit: poly_list_to_fit,
+        pass_items: LinkedList::new(),
+        anim_items: LinkedList::new(),
+    });
+}
+
+/// Read the extracted polygons,
+/// returns None when there is no cache file (or it can't be used).
+///
+/// * `image_len` - the number of pixels in the image.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read(
+    key: &Key,
+    image_len: usize,
+) -> Option<TraceExtract> {
+    let data = ::std::fs::read(&key.filepath).ok()?;
+    return extract_from_bytes(&data, key, image_len).ok();
+}
+
+/// Write the extracted polygons, creating the directory when needed.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write(
+    key: &Key,
+    extract: &TraceExtract,
+) -> Result<(), Error> {
+    let mut data: Vec<u8> = vec![];
+    data.extend_from_slice(CACHE_MAGIC);
+    write_bytes(&mut data, key.text.as_bytes());
+    data.extend_from_slice(&image_pack(&extract.image));
+    write_u64(&mut data, extract.dots.len() as u64);
+    for v in &extract.dots {
+        write_co(&mut data, v);
+    }
+    write_u64(&mut data, extract.meta_list.len() as u64);
+    for meta in &extract.meta_list {
+        write_path_meta(&mut data, meta);
+    }
+    write_u64(&mut data, extract.measurements.len() as u64);
+    for measurement in &extract.measurements {
+        write_measurement(&mut data, measurement);
+    }
+    write_u64(&mut data, extract.poly_list_to_fit.len() as u64);
+    for &(is_cyclic, ref poly) in &extract.poly_list_to_fit {
+        write_bool(&mut data, is_cyclic);
+        write_u64(&mut data, poly.len() as u64);
+        for v in poly {
+            write_co(&mut data, v);
+        }
+    }
+
+    if let Some(dirpath) = key.filepath.parent() {
+        ::std::fs::create_dir_all(dirpath)?;
+    }
+    let mut filepath_temp = key.filepath.clone().into_os_string();
+    filepath_temp.push(format!(".{}.{}.tmp",
+                               ::std::process::id(), TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)));
+    ::std::fs::write(&filepath_temp, &data)?;
+    return ::std::fs::rename(&filepath_temp, &key.filepath);
+}
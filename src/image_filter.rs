@@ -0,0 +1,225 @@
+///
+/// Filters operating on the binary image before tracing.
+///
+
+/// Remove isolated pixels (filled pixels without any filled 8-neighbors),
+/// returning their positions.
+pub fn remove_isolated_pixels(
+    image: &mut [bool],
+    size: &[usize; 2],
+) -> Vec<[usize; 2]> {
+    let mut pixels = vec![];
+    for y in 0..size[1] {
+        for x in 0..size[0] {
+            if !image[x + y * size[0]] {
+                continue;
+            }
+            let mut is_isolated = true;
+            'outer: for y_test in y.saturating_sub(1)..::std::cmp::min(y + 2, size[1]) {
+                for x_test in x.saturating_sub(1)..::std::cmp::min(x + 2, size[0]) {
+                    if (x_test != x || y_test != y) && image[x_test + y_test * size[0]] {
+                        is_isolated = false;
+                        break 'outer;
+                    }
+                }
+            }
+            if is_isolated {
+                pixels.push([x, y]);
+            }
+        }
+    }
+    // Removing isolated pixels can't create new ones, so this can be done afterwards.
+    for p in &pixels {
+        image[p[0] + p[1] * size[0]] = false;
+    }
+    return pixels;
+}
+
+/// Offsets before & after each pixel for a window of `width` pixels.
+fn window_offsets(width: usize) -> (usize, usize) {
+    let before = (width - 1) / 2;
+    return (before, (width - 1) - before);
+}
+
+/// Set each pixel to `value` when any pixel within the square window
+/// (offset by `before`, `after`) is `value`, pixels outside the image are ignored.
+///
+/// Operates on rows then columns, since a square window is separable.
+fn window_any(
+    image: &mut [bool],
+    size: &[usize; 2],
+    before: usize,
+    after: usize,
+    value: bool,
+) {
+    let mut line: Vec<bool> = vec![];
+    let mut line_src: Vec<bool> = vec![];
+    // Rows then columns: `(lines_len, line_len, line_stride, step)`.
+    for &(lines_len, line_len, line_stride, step) in &[
+        (size[1], size[0], size[0], 1),
+        (size[0], size[1], 1, size[0]),
+    ] {
+        for l in 0..lines_len {
+            line_src.clear();
+            line_src.extend((0..line_len).map(|i| image[l * line_stride + i * step]));
+            line.clear();
+            line.resize(line_len, !value);
+            // Spread `value` pixels forwards (covering `before`) & backwards (covering `after`).
+            let mut count = 0;
+            for (i, v) in line_src.iter().enumerate() {
+                if *v == value {
+                    count = before + 1;
+                }
+                if count != 0 {
+                    line[i] = value;
+                    count -= 1;
+                }
+            }
+            count = 0;
+            for (i, v) in line_src.iter().enumerate().rev() {
+                if *v == value {
+                    count = after + 1;
+                }
+                if count != 0 {
+                    line[i] = value;
+                    count -= 1;
+                }
+            }
+            for (i, v) in line.iter().enumerate() {
+                image[l * line_stride + i * step] = *v;
+            }
+        }
+    }
+}
+
+/// Apply `window_any` for each `(value, reflect)` in `ops`,
+/// on a copy of the image padded with empty pixels,
+/// so shapes touching the image bounds are handled as if the image was larger.
+fn window_any_padded(
+    image: &mut [bool],
+    size: &[usize; 2],
+    width: usize,
+    ops: &[(bool, bool)],
+) {
+    let (before, after) = window_offsets(width);
+    let pad = width;
+    let size_pad = [size[0] + pad * 2, size[1] + pad * 2];
+    let mut image_pad = vec![false; size_pad[0] * size_pad[1]];
+    for y in 0..size[1] {
+        let row_pad = (y + pad) * size_pad[0] + pad;
+        image_pad[row_pad..(row_pad + size[0])]
+            .copy_from_slice(&image[(y * size[0])..((y + 1) * size[0])]);
+    }
+    for &(value, reflect) in ops {
+        if reflect {
+            window_any(&mut image_pad, &size_pad, after, before, value);
+        } else {
+            window_any(&mut image_pad, &size_pad, before, after, value);
+        }
+    }
+    for y in 0..size[1] {
+        let row_pad = (y + pad) * size_pad[0] + pad;
+        image[(y * size[0])..((y + 1) * size[0])]
+            .copy_from_slice(&image_pad[row_pad..(row_pad + size[0])]);
+    }
+}
+
+/// Morphological opening with a square of `width` pixels,
+/// removes filled features narrower than `width`.
+pub fn open(
+    image: &mut [bool],
+    size: &[usize; 2],
+    width: usize,
+) {
+    if width <= 1 {
+        return;
+    }
+    // Erode, then dilate using the reflected window.
+    window_any_padded(image, size, width, &[(false, false), (true, true)]);
+}
+
+/// Morphological closing with a square of `width` pixels,
+/// fills empty features (holes) narrower than `width`.
+pub fn close(
+    image: &mut [bool],
+    size: &[usize; 2],
+    width: usize,
+) {
+    if width <= 1 {
+        return;
+    }
+    // Dilate, then erode using the reflected window.
+    window_any_padded(image, size, width, &[(true, false), (false, true)]);
+}
+
+/// Clear pixels which aren't filled in `mask` (the same size as the image),
+/// returning the number of pixels cleared.
+pub fn mask(
+    image: &mut [bool],
+    mask: &[bool],
+) -> usize {
+    debug_assert!(image.len() == mask.len());
+    let mut cleared_len = 0;
+    for (p, is_mask) in image.iter_mut().zip(mask) {
+        if *p && !*is_mask {
+            *p = false;
+            cleared_len += 1;
+        }
+    }
+    return cleared_len;
+}
+
+/// Clear pixels which are filled in `other` (the same size as the image),
+/// returning the number of pixels cleared.
+pub fn subtract(
+    image: &mut [bool],
+    other: &[bool],
+) -> usize {
+    debug_assert!(image.len() == other.len());
+    let mut cleared_len = 0;
+    for (p, is_other) in image.iter_mut().zip(other) {
+        if *p && *is_other {
+            *p = false;
+            cleared_len += 1;
+        }
+    }
+    return cleared_len;
+}
+
+/// Fill all empty pixels which aren't connected to the image border (holes),
+/// returning the number of pixels filled.
+pub fn fill_enclosed(
+    image: &mut [bool],
+    size: &[usize; 2],
+) -> usize {
+    // Flood fill empty pixels from the border, anything not reached is enclosed.
+    let mut is_outside = vec![false; image.len()];
+    let mut stack: Vec<[usize; 2]> = vec![];
+    for y in 0..size[1] {
+        for x in 0..size[0] {
+            if x == 0 || y == 0 || x + 1 == size[0] || y + 1 == size[1] {
+                stack.push([x, y]);
+            }
+        }
+    }
+    while let Some([x, y]) = stack.pop() {
+        let index = x + y * size[0];
+        if image[index] || is_outside[index] {
+            continue;
+        }
+        is_outside[index] = true;
+        if x != 0 { stack.push([x - 1, y]); }
+        if y != 0 { stack.push([x, y - 1]); }
+        if x + 1 != size[0] { stack.push([x + 1, y]); }
+        if y + 1 != size[1] { stack.push([x, y + 1]); }
+    }
+
+    let mut filled_len = 0;
+    for (p, is_outside) in image.iter_mut().zip(&is_outside) {
+        if !*p && !*is_outside {
+            *p = true;
+            filled_len += 1;
+        }
+    }
+    return filled_len;
+}
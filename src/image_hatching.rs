@@ -0,0 +1,288 @@
+///
+/// Remove hatching (dense parallel thin strokes) from the binary image.
+///
+/// Stroke width is measured with a distance transform,
+/// thin strokes are removed where the surrounding area is densely covered
+/// by thin strokes which follow one or two dominant directions (cross-hatching).
+///
+
+/// Number of orientations (over 180 degrees) used to find dominant directions.
+const ORIENTATION_BINS: usize = 8;
+
+/// The fraction of the area around a pixel covered by thin strokes, to be considered hatching.
+const DENSITY_MIN: f64 = 0.15;
+
+/// The fraction of thin stroke edges around a pixel which must share its direction
+/// (neighboring orientations count as half), enough for both directions of cross-hatching
+/// while strokes in all directions (at 0.25) are kept.
+const ORIENTATION_MIN: f64 = 0.28;
+
+/// The number of parallel strokes to be considered hatching,
+/// so single strokes next to hatching are kept.
+const STROKES_MIN: usize = 3;
+
+/// Agreement of gradients around a pixel (from zero to one) for it to have an orientation.
+const COHERENCE_MIN: f64 = 0.5;
+
+/// Chamfer (3-4) distance from each `value` pixel to the nearest pixel which isn't `value`,
+/// pixels outside the image are empty.
+pub fn chamfer_distance(
+    image: &[bool],
+    size: &[usize; 2],
+    value: bool,
+) -> Vec<f64> {
+    const INF: u32 = ::std::u32::MAX / 2;
+    let outside = if value { 0 } else { INF };
+    let (w, h) = (size[0] as isize, size[1] as isize);
+    let mut dist: Vec<u32> = image.iter().map(|&v| if v == value { INF } else { 0 }).collect();
+    let get = |dist: &[u32], x: isize, y: isize| -> u32 {
+        if x < 0 || y < 0 || x >= w || y >= h { outside } else { dist[(x + y * w) as usize] }
+    };
+    // Forward, then backward (`(dx, dy, weight)` offsets already visited).
+    for &(is_forward, offsets) in &[
+        (true, &[(-1, 0, 3), (-1, -1, 4), (0, -1, 3), (1, -1, 4)]),
+        (false, &[(1, 0, 3), (1, 1, 4), (0, 1, 3), (-1, 1, 4)]),
+    ] {
+        for i in 0..(w * h) {
+            let i = if is_forward { i } else { w * h - 1 - i };
+            if dist[i as usize] == 0 {
+                continue;
+            }
+            let (x, y) = (i % w, i / w);
+            let mut d = dist[i as usize];
+            for &(dx, dy, weight) in offsets.iter() {
+                d = d.min(get(&dist, x + dx, y + dy) + weight);
+            }
+            dist[i as usize] = d;
+        }
+    }
+    return dist.iter().map(|&d| d as f64 / 3.0).collect();
+}
+
+/// The maximum value within a square window of `radius` around each pixel.
+fn window_max(
+    values: &[f64],
+    size: &[usize; 2],
+    radius: usize,
+) -> Vec<f64> {
+    let mut values_dst = values.to_vec();
+    // Rows then columns: `(lines_len, line_len, line_stride, step)`.
+    for &(lines_len, line_len, line_stride, step) in &[
+        (size[1], size[0], size[0], 1),
+        (size[0], size[1], 1, size[0]),
+    ] {
+        let values_src = values_dst.clone();
+        for l in 0..lines_len {
+            for i in 0..line_len {
+                let i_min = i.saturating_sub(radius);
+                let i_max = ::std::cmp::min(i + radius + 1, line_len);
+                values_dst[l * line_stride + i * step] = (i_min..i_max).map(
+                    |j| values_src[l * line_stride + j * step]).fold(0.0, f64::max);
+            }
+        }
+    }
+    return values_dst;
+}
+
+/// Summed area table, with an extra row & column of zeros before the image.
+fn integral_image<F>(
+    size: &[usize; 2],
+    value: F,
+) -> Vec<u32> where F: Fn(usize) -> bool {
+    let stride = size[0] + 1;
+    let mut table = vec![0; stride * (size[1] + 1)];
+    for y in 0..size[1] {
+        let mut row_sum = 0;
+        for x in 0..size[0] {
+            if value(x + y * size[0]) {
+                row_sum += 1;
+            }
+            table[(x + 1) + (y + 1) * stride] = table[(x + 1) + y * stride] + row_sum;
+        }
+    }
+    return table;
+}
+
+/// Remove hatching made of strokes up to `width` pixels wide,
+/// returning the number of pixels cleared.
+pub fn remove_hatching(
+    image: &mut [bool],
+    size: &[usize; 2],
+    width: usize,
+) -> usize {
+    if width == 0 || image.is_empty() {
+        return 0;
+    }
+    let (w, h) = (size[0], size[1]);
+    let dist_inside = chamfer_distance(image, size, true);
+    let dist_outside = chamfer_distance(image, size, false);
+
+    // Pixels of strokes no wider than `width`,
+    // the window is large enough to reach past the middle of wider strokes.
+    let dist_inside_max = window_max(&dist_inside, size, (width + 1) / 2 + 1);
+    let is_thin: Vec<bool> = (0..image.len()).map(
+        |i| image[i] && dist_inside_max[i] <= width as f64 / 2.0 + 0.5).collect();
+
+    // The orientation of stroke edges, from the gradient of the signed distance.
+    // Gradients are combined with their neighbors as doubled angles (so opposite edges agree),
+    // pixels where they disagree (ends & crossings of strokes) have no orientation.
+    let signed = |x: usize, y: usize| dist_inside[x + y * w] - dist_outside[x + y * w];
+    let gradient: Vec<[f64; 2]> = (0..image.len()).map(|i| {
+        let (x, y) = (i % w, i / w);
+        if x == 0 || y == 0 || x + 1 == w || y + 1 == h {
+            return [0.0, 0.0];
+        }
+        return [signed(x + 1, y) - signed(x - 1, y), signed(x, y + 1) - signed(x, y - 1)];
+    }).collect();
+    let angles: Vec<Option<f64>> = (0..image.len()).map(|i| {
+        if !is_thin[i] || gradient[i] == [0.0, 0.0] {
+            return None;
+        }
+        let (x, y) = (i % w, i / w);
+        let mut tensor = [0.0, 0.0];
+        let mut energy = 0.0;
+        for y_test in y.saturating_sub(1)..::std::cmp::min(y + 2, h) {
+            for x_test in x.saturating_sub(1)..::std::cmp::min(x + 2, w) {
+                let g = gradient[x_test + y_test * w];
+                tensor[0] += g[0] * g[0] - g[1] * g[1];
+                tensor[1] += 2.0 * g[0] * g[1];
+                energy += g[0] * g[0] + g[1] * g[1];
+            }
+        }
+        if (tensor[0] * tensor[0] + tensor[1] * tensor[1]).sqrt() < COHERENCE_MIN * energy {
+            return None;
+        }
+        let mut angle = tensor[1].atan2(tensor[0]) / 2.0;
+        if angle < 0.0 {
+            angle += ::std::f64::consts::PI;
+        }
+        return Some(angle);
+    }).collect();
+    let orientation: Vec<Option<usize>> = angles.iter().map(|angle| angle.map(|angle| {
+        ((angle / ::std::f64::consts::PI * ORIENTATION_BINS as f64) as usize) % ORIENTATION_BINS
+    })).collect();
+
+    // The number of thin strokes crossed (including its own) from a pixel along `angle`
+    // (across its stroke) in both directions, stopping at wider strokes.
+    let strokes_across = |i: usize, angle: f64, radius: usize| {
+        let (x, y) = ((i % w) as f64 + 0.5, (i / w) as f64 + 0.5);
+        let mut strokes_len = 1;
+        for &dir in &[1.0, -1.0] {
+            let step = [angle.cos() * dir, angle.sin() * dir];
+            let mut is_prev_thin = true;
+            for j in 1..(radius + 1) {
+                let co = [x + step[0] * j as f64, y + step[1] * j as f64];
+                if co[0] < 0.0 || co[1] < 0.0 || co[0] >= w as f64 || co[1] >= h as f64 {
+                    break;
+                }
+                let i_test = co[0] as usize + (co[1] as usize) * w;
+                if image[i_test] && !is_thin[i_test] {
+                    break;
+                }
+                if is_thin[i_test] && !is_prev_thin {
+                    strokes_len += 1;
+                }
+                is_prev_thin = is_thin[i_test];
+            }
+        }
+        return strokes_len;
+    };
+
+    let thin_table = integral_image(size, |i| is_thin[i]);
+    let bin_tables: Vec<Vec<u32>> = (0..ORIENTATION_BINS).map(
+        |bin| integral_image(size, |i| orientation[i] == Some(bin))).collect();
+
+    // Large enough to include several strokes of hatching.
+    let radius = width * 4 + 4;
+    let stride = w + 1;
+    let window = |i: usize| {
+        let (x, y) = (i % w, i / w);
+        let x_max = ::std::cmp::min(x + radius + 1, w);
+        let y_max = ::std::cmp::min(y + radius + 1, h);
+        (x.saturating_sub(radius), y.saturating_sub(radius), x_max, y_max)
+    };
+    let window_sum = |table: &[u32], (x_min, y_min, x_max, y_max): (usize, usize, usize, usize)| {
+        table[x_max + y_max * stride] + table[x_min + y_min * stride] -
+        table[x_min + y_max * stride] - table[x_max + y_min * stride]
+    };
+    // Strokes following `bin` (neighboring orientations count as half), doubled.
+    let bin_share_x2 = |bins: &[u32], bin: usize| {
+        let bin_prev = (bin + ORIENTATION_BINS - 1) % ORIENTATION_BINS;
+        let bin_next = (bin + 1) % ORIENTATION_BINS;
+        bins[bin] * 2 + bins[bin_prev] + bins[bin_next]
+    };
+
+    // Thin pixels surrounded by enough thin strokes to be hatching.
+    let is_dense: Vec<bool> = (0..image.len()).map(|i| {
+        if !is_thin[i] {
+            return false;
+        }
+        let win = window(i);
+        let area = (win.2 - win.0) * (win.3 - win.1);
+        return window_sum(&thin_table, win) as f64 >= DENSITY_MIN * area as f64;
+    }).collect();
+
+    // Pixels of dense strokes which follow a dominant direction.
+    let mut is_hatching: Vec<bool> = (0..image.len()).map(|i| {
+        let bin = match orientation[i] {
+            Some(bin) if is_dense[i] => bin,
+            _ => return false,
+        };
+        let win = window(i);
+        let bins: Vec<u32> = bin_tables.iter().map(|table| window_sum(table, win)).collect();
+        let total: u32 = bins.iter().sum();
+        return bin_share_x2(&bins, bin) as f64 >= ORIENTATION_MIN * 2.0 * total as f64 &&
+               strokes_across(i, angles[i].unwrap(), radius) >= STROKES_MIN;
+    }).collect();
+
+    // Strokes near the edges of hatched areas aren't dense,
+    // include them when they're near hatching which follows the same direction.
+    let hatching_tables: Vec<Vec<u32>> = (0..ORIENTATION_BINS).map(
+        |bin| integral_image(size, |i| is_hatching[i] && orientation[i] == Some(bin))).collect();
+    for i in 0..image.len() {
+        let bin = match orientation[i] {
+            Some(bin) if !is_hatching[i] => bin,
+            _ => continue,
+        };
+        let win = window(i);
+        let bins: Vec<u32> = hatching_tables.iter().map(
+            |table| window_sum(table, win)).collect();
+        // At least one stroke crossing the window, in a dominant direction.
+        let share_x2 = bin_share_x2(&bins, bin);
+        let total: u32 = bins.iter().sum();
+        if share_x2 as usize >= radius * 2 &&
+           share_x2 as f64 >= ORIENTATION_MIN * 2.0 * total as f64
+        {
+            is_hatching[i] = true;
+        }
+    }
+
+    // Spread into connected thin pixels which have no orientation
+    // (in the middle of wider strokes) or are dense (where strokes cross),
+    // so hatching is removed up to its edges.
+    let mut stack: Vec<usize> = (0..image.len()).filter(|&i| is_hatching[i]).collect();
+    while let Some(i) = stack.pop() {
+        let (x, y) = (i % w, i / w);
+        for y_test in y.saturating_sub(1)..::std::cmp::min(y + 2, h) {
+            for x_test in x.saturating_sub(1)..::std::cmp::min(x + 2, w) {
+                let i_test = x_test + y_test * w;
+                if !is_thin[i_test] || is_hatching[i_test] ||
+                   (orientation[i_test].is_some() && !is_dense[i_test])
+                {
+                    continue;
+                }
+                is_hatching[i_test] = true;
+                stack.push(i_test);
+            }
+        }
+    }
+
+    let mut removed_len = 0;
+    for i in 0..image.len() {
+        if is_hatching[i] {
+            image[i] = false;
+            removed_len += 1;
+        }
+    }
+    return removed_len;
+}
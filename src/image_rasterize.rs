@@ -0,0 +1,177 @@
+///
+/// Rasterize polygons into anti-aliased coverage (used for previews).
+///
+/// The signed area each edge covers is accumulated into the pixels it crosses,
+/// summing each row gives the coverage, so holes (with the opposite winding) are cut out.
+///
+
+const DIMS: usize = ::intern::math_vector::DIMS;
+
+use std::collections::LinkedList;
+
+/// Accumulated signed area, to be converted into coverage by `Raster::coverage`.
+pub struct Raster {
+    size: [usize; 2],
+    /// Two extra columns, for edges on (or clamped to) the right of the image.
+    stride: usize,
+    acc: Vec<f64>,
+}
+
+impl Raster {
+    pub fn new(
+        size: &[usize; 2],
+    ) -> Raster {
+        let stride = size[0] + 2;
+        return Raster {
+            size: *size,
+            stride: stride,
+            acc: vec![0.0; stride * size[1]],
+        };
+    }
+
+    /// Accumulate the area to the right of the edge from `p0` to `p1`.
+    fn add_edge(
+        &mut self,
+        p0: &[f64; DIMS],
+        p1: &[f64; DIMS],
+    ) {
+        if p0[1] == p1[1] {
+            return;
+        }
+        let (dir, p0, p1) = if p0[1] < p1[1] { (1.0, p0, p1) } else { (-1.0, p1, p0) };
+        let dxdy = (p1[0] - p0[0]) / (p1[1] - p0[1]);
+        // Area left of the image is accumulated into the first column.
+        let x_max = self.size[0] as f64;
+        let x_clamp = |x: f64| x.max(0.0).min(x_max);
+
+        let y_min = p0[1].max(0.0);
+        let y_max = p1[1].min(self.size[1] as f64);
+        if !(y_min < y_max) {
+            return;
+        }
+        let mut x = p0[0] + (y_min - p0[1]) * dxdy;
+        for y in (y_min.floor() as usize)..(y_max.ceil() as usize) {
+            let row = &mut self.acc[y * self.stride..(y + 1) * self.stride];
+            let dy = ((y + 1) as f64).min(y_max) - (y as f64).max(y_min);
+            let x_next = x + dxdy * dy;
+            let d = dy * dir;
+            let (x0, x1) = if x < x_next { (x, x_next) } else { (x_next, x) };
+            let (x0, x1) = (x_clamp(x0), x_clamp(x1));
+            let x0_floor = x0.floor();
+            let x0i = x0_floor as usize;
+            let x1_ceil = x1.ceil();
+            let x1i = x1_ceil as usize;
+            if x1i <= x0i + 1 {
+                // Within a single pixel, split at the middle of the edge.
+                let x_mid = 0.5 * (x0 + x1) - x0_floor;
+                row[x0i] += d - d * x_mid;
+                row[x0i + 1] += d * x_mid;
+            } else {
+                // Spread over multiple pixels, the area is triangular at both ends.
+                let s = 1.0 / (x1 - x0);
+                let x0_fract = x0 - x0_floor;
+                let a0 = 0.5 * s * (1.0 - x0_fract) * (1.0 - x0_fract);
+                let x1_fract = x1 - x1_ceil + 1.0;
+                let a_end = 0.5 * s * x1_fract * x1_fract;
+                row[x0i] += d * a0;
+                if x1i == x0i + 2 {
+                    row[x0i + 1] += d * (1.0 - a0 - a_end);
+                } else {
+                    let a1 = s * (1.5 - x0_fract);
+                    row[x0i + 1] += d * (a1 - a0);
+                    for xi in (x0i + 2)..(x1i - 1) {
+                        row[xi] += d * s;
+                    }
+                    let a2 = a1 + (x1i - x0i - 3) as f64 * s;
+                    row[x1i - 1] += d * (1.0 - a2 - a_end);
+                }
+                row[x1i] += d * a_end;
+            }
+            x = x_next;
+        }
+    }
+
+    /// Add a closed polygon.
+    pub fn add_poly(
+        &mut self,
+        poly: &[[f64; DIMS]],
+    ) {
+        let mut v_prev = match poly.last() {
+            Some(v) => v,
+            None => return,
+        };
+        for v in poly {
+            self.add_edge(v_prev, v);
+            v_prev = v;
+        }
+    }
+
+    /// Add polygons as lines `width` wide (with round joins),
+    /// wound consistently so overlapping parts don't cancel each other out.
+    pub fn add_poly_list_stroke(
+        &mut self,
+        poly_list: &LinkedList<(bool, Vec<[f64; DIMS]>)>,
+        width: f64,
+    ) {
+        let radius = width / 2.0;
+        for &(is_cyclic, ref poly) in poly_list {
+            for (i, v) in poly.iter().enumerate() {
+                self.add_poly(&circle_poly(v, radius));
+                if i + 1 == poly.len() && !is_cyclic {
+                    break;
+                }
+                let v_next = &poly[(i + 1) % poly.len()];
+                let dir = [v_next[0] - v[0], v_next[1] - v[1]];
+                let len = (dir[0] * dir[0] + dir[1] * dir[1]).sqrt();
+                if len == 0.0 {
+                    continue;
+                }
+                let n = [-dir[1] / len * radius, dir[0] / len * radius];
+                self.add_poly(&[
+                    [v[0] + n[0], v[1] + n[1]],
+                    [v[0] - n[0], v[1] - n[1]],
+                    [v_next[0] - n[0], v_next[1] - n[1]],
+                    [v_next[0] + n[0], v_next[1] + n[1]],
+                ]);
+            }
+        }
+    }
+
+    /// Add circles (wound consistently).
+    pub fn add_circles(
+        &mut self,
+        centers: &[[f64; DIMS]],
+        radius: f64,
+    ) {
+        for v in centers {
+            self.add_poly(&circle_poly(v, radius));
+        }
+    }
+
+    /// The coverage of each pixel (from zero to one).
+    pub fn coverage(
+        &self,
+    ) -> Vec<f64> {
+        let mut coverage = Vec::with_capacity(self.size[0] * self.size[1]);
+        for row in self.acc.chunks(self.stride) {
+            let mut acc = 0.0;
+            for value in &row[..self.size[0]] {
+                acc += *value;
+                coverage.push(acc.abs().min(1.0));
+            }
+        }
+        return coverage;
+    }
+}
+
+/// A polygon approximating a circle, used for dots & line joins.
+fn circle_poly(
+    center: &[f64; DIMS],
+    radius: f64,
+) -> Vec<[f64; DIMS]> {
+    const SEGMENTS: usize = 16;
+    return (0..SEGMENTS).map(|i| {
+        let a = i as f64 * ::std::f64::consts::PI * 2.0 / SEGMENTS as f64;
+        [center[0] + a.cos() * radius, center[1] + a.sin() * radius]
+    }).collect();
+}
@@ -0,0 +1,87 @@
+///
+/// Run-length representation of the binary image,
+/// storing the filled runs of each row.
+///
+/// Scans typically have long horizontal runs,
+/// so this is much smaller than a value for each pixel.
+///
+
+/// The filled runs for each row of an image.
+pub struct ImageRuns {
+    pub size: [usize; 2],
+    /// `[x_start, x_end)` of each run, ordered by row, then by `x_start`.
+    runs: Vec<[u32; 2]>,
+    /// The first run of each row in `runs`, with an extra item for the end of the last row.
+    row_offsets: Vec<usize>,
+}
+
+impl ImageRuns {
+    pub fn from_image(
+        image: &[bool],
+        size: &[usize; 2],
+    ) -> ImageRuns {
+        let mut runs: Vec<[u32; 2]> = vec![];
+        let mut row_offsets: Vec<usize> = Vec::with_capacity(size[1] + 1);
+        for y in 0..size[1] {
+            let row = &image[(y * size[0])..((y + 1) * size[0])];
+            row_offsets.push(runs.len());
+            let mut x_start: Option<usize> = None;
+            for (x, &is_fill) in row.iter().enumerate() {
+                match (is_fill, x_start) {
+                    (true, None) => {
+                        x_start = Some(x);
+                    }
+                    (false, Some(x_start_run)) => {
+                        runs.push([x_start_run as u32, x as u32]);
+                        x_start = None;
+                    }
+                    _ => {}
+                }
+            }
+            if let Some(x_start_run) = x_start {
+                runs.push([x_start_run as u32, size[0] as u32]);
+            }
+        }
+        row_offsets.push(runs.len());
+        return ImageRuns {
+            size: *size,
+            runs: runs,
+            row_offsets: row_offsets,
+        };
+    }
+
+    /// The runs of row `y`.
+    pub fn row(&self, y: usize) -> &[[u32; 2]] {
+        return &self.runs[self.row_offsets[y]..self.row_offsets[y + 1]];
+    }
+
+    /// The index of the run containing the pixel (in `0..self.runs_len()`),
+    /// None when the pixel is outside the image or not filled.
+    pub fn run_index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x as usize >= self.size[0] || y as usize >= self.size[1] {
+            return None;
+        }
+        let row = self.row(y as usize);
+        // The number of runs starting at or before `x`, the last of these may contain `x`.
+        let i = row.partition_point(|run| run[0] <= x as u32);
+        if i != 0 && (x as u32) < row[i - 1][1] {
+            return Some(self.row_offsets[y as usize] + i - 1);
+        }
+        return None;
+    }
+
+    /// Get pixel (false outside the image).
+    pub fn pixel_get(&self, x: i32, y: i32) -> bool {
+        return self.run_index(x, y).is_some();
+    }
+
+    pub fn runs_len(&self) -> usize {
+        return self.runs.len();
+    }
+
+    /// The number of filled pixels.
+    #[allow(dead_code)]
+    pub fn area(&self) -> usize {
+        return self.runs.iter().map(|run| (run[1] - run[0]) as usize).sum();
+    }
+}
@@ -6,9 +6,17 @@ struct Bitmap<'a> {
     size: [i32; 2],
 }
 
-pub fn calculate(
+// Minimum number of rows scanned by each thread,
+// so small images don't pay the cost of spawning threads.
+const SCAN_BAND_ROWS_MIN: i32 = 64;
+
+/// Thin the image, scanning for border points using up to `threads_len` threads.
+///
+/// The result doesn't depend on the number of threads.
+pub fn calculate_with_threads(
     data: &mut Vec<bool>,
     size: &[usize; 2],
+    threads_len: usize,
 ) {
     compute_thin_image(
         &mut Bitmap {
@@ -17,15 +25,85 @@ pub fn calculate(
                 size[0] as i32,
                 size[1] as i32,
             ],
-        }
+        },
+        threads_len,
     );
 }
 
+/// Add all simple border points of type `current_border` within `x_range`, `y_range`
+/// to `simple_border_points`.
+fn border_points_scan(
+    image: &Bitmap,
+    current_border: u8,
+    x_range: ::std::ops::Range<i32>,
+    y_range: ::std::ops::Range<i32>,
+    simple_border_points: &mut Vec<[i32; 2]>,
+) {
+    // Loop over each pixel
+    for y in y_range {
+        for x in x_range.clone() {
+            // check if point is foreground
+            if pixel_get_no_check(image, x, y) == false {
+                // current point is already background
+                continue;
+            }
+
+            // check 4-neighbors if point is a border point of type current_border
+            if match current_border {
+                0 => pixel_get(image, x, y - 1),
+                1 => pixel_get(image, x, y + 1),
+                2 => pixel_get(image, x + 1, y),
+                3 => pixel_get(image, x - 1, y),
+                _ => unreachable!() }
+            {
+                // current point is not deletable
+                continue;
+            }
+
+            if pixel_is_endpoint(image, x, y) {
+                continue;
+            }
+
+            let neighborhood = neighborhood_get_no_center(image, x, y);
+
+            // Check if point is Euler invariant (condition 1 in Lee[94])
+            if !is_euler_invariant(neighborhood) {
+                // current point is not deletable
+                continue;
+            }
+
+            // Check if point is simple
+            // (deletion does not change connectivity in the 3x3 neighborhood)
+            // (conditions 2 and 3 in Lee[94])
+            if !is_simple_point(neighborhood) {
+                // current point is not deletable
+                continue;
+            }
+            // add all simple border points to a list for sequential re-checking
+            simple_border_points.push([x, y]);
+        }
+    }
+}
+
 fn compute_thin_image(
     image: &mut Bitmap,
+    threads_len: usize,
 ) {
     let mut simple_border_points: Vec<[i32; 2]> = Vec::new();
 
+    let bands_len = ::std::cmp::min(
+        threads_len as i32,
+        image.size[1] / SCAN_BAND_ROWS_MIN,
+    ).max(1);
+
+    // Region which changed since the last scan of each border type `[x_min, y_min, x_max, y_max]`
+    // (inclusive). Pixels outside this region (expanded by one for the neighborhood)
+    // give the same result as the previous scan, so they don't need to be checked again.
+    let mut dirty: [Option<[i32; 4]>; 4] = {
+        let full = Some([0, 0, image.size[0] - 1, image.size[1] - 1]);
+        [full, full, full, full]
+    };
+
     // Loop through the image several times until there is no change.
     let mut unchanged_borders = 0;
 
@@ -35,49 +113,50 @@ fn compute_thin_image(
         for current_border in 0..4 {
             let mut no_change: bool = true;
 
-            // Loop over each pixel
-            for y in 0..image.size[1] {
-                for x in 0..image.size[0] {
-                    // check if point is foreground
-                    if pixel_get_no_check(image, x, y) == false {
-                        // current point is already background
-                        continue;
-                    }
-
-                    // check 4-neighbors if point is a border point of type current_border
-                    if match current_border {
-                        0 => pixel_get(image, x, y - 1),
-                        1 => pixel_get(image, x, y + 1),
-                        2 => pixel_get(image, x + 1, y),
-                        3 => pixel_get(image, x - 1, y),
-                        _ => unreachable!() }
-                    {
-                        // current point is not deletable
-                        continue;
-                    }
-
-                    if pixel_is_endpoint(image, x, y) {
-                        continue;
-                    }
-
-                    let neighborhood = neighborhood_get_no_center(image, x, y);
-
-                    // Check if point is Euler invariant (condition 1 in Lee[94])
-                    if !is_euler_invariant(neighborhood) {
-                        // current point is not deletable
-                        continue;
-                    }
-
-                    // Check if point is simple
-                    // (deletion does not change connectivity in the 3x3 neighborhood)
-                    // (conditions 2 and 3 in Lee[94])
-                    if !is_simple_point(neighborhood) {
-                        // current point is not deletable
-                        continue;
-                    }
-                    // add all simple border points to a list for sequential re-checking
-                    simple_border_points.push([x, y]);
+            let (x_range, y_min, y_max) = match dirty[current_border as usize].take() {
+                Some(r) => {
+                    (
+                        ::std::cmp::max(r[0] - 1, 0)..::std::cmp::min(r[2] + 2, image.size[0]),
+                        ::std::cmp::max(r[1] - 1, 0),
+                        ::std::cmp::min(r[3] + 2, image.size[1]),
+                    )
                 }
+                None => {
+                    unchanged_borders += 1;
+                    continue;
+                }
+            };
+
+            // Detection only reads the image, so it can be split into bands of rows.
+            // Bands are joined in order, giving the same points as a single threaded scan.
+            let bands_len_range = ::std::cmp::min(
+                bands_len,
+                (y_max - y_min) / SCAN_BAND_ROWS_MIN,
+            ).max(1);
+            if bands_len_range == 1 {
+                border_points_scan(
+                    image, current_border, x_range, y_min..y_max, &mut simple_border_points);
+            } else {
+                let image_ref: &Bitmap = image;
+                let band_rows = ((y_max - y_min) + bands_len_range - 1) / bands_len_range;
+                ::std::thread::scope(|scope| {
+                    let join_handles: Vec<_> = (0..bands_len_range).map(|band| {
+                        let band_y_min = y_min + band * band_rows;
+                        let band_y_max = ::std::cmp::min(band_y_min + band_rows, y_max);
+                        let x_range = x_range.clone();
+                        scope.spawn(move || {
+                            let mut points = Vec::new();
+                            border_points_scan(
+                                image_ref, current_border, x_range, band_y_min..band_y_max,
+                                &mut points);
+                            points
+                        })
+                    }).collect();
+
+                    for child in join_handles {
+                        simple_border_points.extend(child.join().unwrap());
+                    }
+                });
             }
 
             // sequential re-checking to preserve connectivity when
@@ -88,6 +167,20 @@ fn compute_thin_image(
                     // we can delete the current point
                     pixel_set(image, index[0], index[1], false);
                     no_change = false;
+
+                    for r in &mut dirty {
+                        *r = Some(match *r {
+                            Some(r) => {
+                                [
+                                    ::std::cmp::min(r[0], index[0]),
+                                    ::std::cmp::min(r[1], index[1]),
+                                    ::std::cmp::max(r[2], index[0]),
+                                    ::std::cmp::max(r[3], index[1]),
+                                ]
+                            }
+                            None => [index[0], index[1], index[0], index[1]],
+                        });
+                    }
                 }
             }
 
@@ -103,19 +196,11 @@ fn compute_thin_image(
 /// Check if a point in the given stack is at the end of an arc.
 /// return true if the point has exactly one neighbor
 fn pixel_is_endpoint(image: &Bitmap, x: i32, y: i32) -> bool {
-    let mut number_of_neighbors: u32 = 0;
     let neighbors = neighborhood_get_no_center(image, x, y);
-    for i in 0..DIR_FLAG_NUM_NO_CENTER {
-        if neighbors & (1 << i) != 0 {
-            number_of_neighbors += 1;
-        }
-    }
-    // 2 and not 1 because the center pixel will be counted as well
-    return number_of_neighbors == 1;
+    return neighbors.count_ones() == 1;
 }
 
 // const DIR_FLAG_NUM:           u32 = 9;
-const DIR_FLAG_NUM_NO_CENTER: u32 = 8;
 
 const DIR_SW: u32 = 1 <<  0;
 const DIR_S:  u32 = 1 << 1;
@@ -198,10 +283,29 @@ const INDEX_LUT: [i32; 32] = [
     0, -1, 0,  1, 0,  1, 0, -1, 0,  3, 0,  1, 0,  1, 0, -1,
 ];
 
+/// Euler invariant points for each neighborhood (one bit per neighbor),
+/// see: `is_euler_invariant_calc`.
+static EULER_INVARIANT_LUT: [bool; 256] = euler_invariant_lut();
+
+const fn euler_invariant_lut() -> [bool; 256] {
+    let mut lut = [false; 256];
+    let mut i = 0;
+    while i < 256 {
+        lut[i] = is_euler_invariant_calc(i as u32);
+        i += 1;
+    }
+    return lut;
+}
+
 /// Check if a point is Euler invariant
 ///
 /// return true or false if the point is Euler invariant or not
 fn is_euler_invariant(neighbors: u32) -> bool {
+    return EULER_INVARIANT_LUT[neighbors as usize];
+}
+
+/// Calculate if a point is Euler invariant, used to initialize `EULER_INVARIANT_LUT`.
+const fn is_euler_invariant_calc(neighbors: u32) -> bool {
     // Calculate Euler characteristic for each quadrant and sum up
     let mut euler_char: i32 = 0;
 
@@ -213,7 +317,7 @@ fn is_euler_invariant(neighbors: u32) -> bool {
     return euler_char == 0;
 }
 
-fn index_quadrant_ne(neighbors: u32) -> u8 {
+const fn index_quadrant_ne(neighbors: u32) -> u8 {
     return
         if neighbors & DIR_S != 0 { (1 << 4) } else { 0 } |
         if neighbors & DIR_E != 0 { (1 << 1) } else { 0 } |
@@ -221,7 +325,7 @@ fn index_quadrant_ne(neighbors: u32) -> u8 {
     ;
 }
 
-fn index_quadrant_nw(neighbors: u32) -> u8 {
+const fn index_quadrant_nw(neighbors: u32) -> u8 {
     return
         if neighbors & DIR_W != 0 { (1 << 4) } else { 0 } |
         if neighbors & DIR_S != 0 { (1 << 2) } else { 0 } |
@@ -229,7 +333,7 @@ fn index_quadrant_nw(neighbors: u32) -> u8 {
     ;
 }
 
-fn index_quadrant_se(neighbors: u32) -> u8 {
+const fn index_quadrant_se(neighbors: u32) -> u8 {
     return
         if neighbors & DIR_N != 0 { (1 << 4) } else { 0 } |
         if neighbors & DIR_E != 0 { (1 << 1) } else { 0 } |
@@ -237,7 +341,7 @@ fn index_quadrant_se(neighbors: u32) -> u8 {
     ;
 }
 
-fn index_quadrant_sw(neighbors: u32) -> u8 {
+const fn index_quadrant_sw(neighbors: u32) -> u8 {
     return
         if neighbors & DIR_N != 0 { (1 << 4) } else { 0 } |
         if neighbors & DIR_W != 0 { (1 << 2) } else { 0 } |
@@ -245,121 +349,74 @@ fn index_quadrant_sw(neighbors: u32) -> u8 {
     ;
 }
 
+/// Neighbors in each quadrant (2x2 block containing the center pixel),
+/// neighbors sharing a quadrant are connected.
+const QUADRANTS: [u32; 4] = [
+    DIR_SW | DIR_S | DIR_W,
+    DIR_S | DIR_SE | DIR_E,
+    DIR_W | DIR_NW | DIR_N,
+    DIR_N | DIR_NE | DIR_E,
+];
+
+/// Simple points for each neighborhood (one bit per neighbor), see: `is_simple_point_calc`.
+static SIMPLE_POINT_LUT: [bool; 256] = simple_point_lut();
+
+const fn simple_point_lut() -> [bool; 256] {
+    let mut lut = [false; 256];
+    let mut i = 0;
+    while i < 256 {
+        lut[i] = is_simple_point_calc(i as u32);
+        i += 1;
+    }
+    return lut;
+}
+
 /// Check if current point is a Simple Point.
-/// This method is named 'N(v)_labeling' in [Lee94].
-/// Outputs the number of connected objects in a neighborhood of a point
-/// after this point would have been removed.
 ///
 /// * `neighbors` - neighbors neighbor pixels of the point.
 ///
 /// Return true or false if the point is simple or not.
 fn is_simple_point(neighbors: u32) -> bool {
+    return SIMPLE_POINT_LUT[neighbors as usize];
+}
+
+/// Calculate if a point is simple, used to initialize `SIMPLE_POINT_LUT`.
+/// This method is named 'N(v)_labeling' in [Lee94].
+/// Counts the number of connected objects in a neighborhood of a point
+/// after this point would have been removed.
+///
+/// Each object is labeled iteratively, adding the neighbors in quadrants
+/// which contain any of its pixels until it stops growing.
+const fn is_simple_point_calc(neighbors: u32) -> bool {
     let mut quad: u32 = neighbors;
 
     // set initial label
     let mut label: u32 = 0;
-    // for all points in the neighborhood
-    for i in 0..DIR_FLAG_NUM_NO_CENTER {
-        // voxel has not been labeled yet
-        let flag = 1 << i;
-        if (quad & flag) != 0 {
-            // Start recursion with any quadrant that contains the point 'i'.
-            //
-            // set points in this quadrant to current label
-            // and recursive labeling of adjacent quadrants.
-            match flag {
-                DIR_SW | DIR_S | DIR_W => {
-                    quadtree_labeling_sw(&mut quad);
-                }
-                DIR_SE | DIR_E => {
-                    quadtree_labeling_se(&mut quad);
-                }
-                DIR_NW | DIR_N => {
-                    quadtree_labeling_nw(&mut quad);
-                }
-                DIR_NE => {
-                    quadtree_labeling_ne(&mut quad);
-                }
-                _ => {
-                    unreachable!();
+    // for all points in the neighborhood which have not been labeled yet
+    while quad != 0 {
+        // Start with the lowest remaining point.
+        let mut object: u32 = quad & quad.wrapping_neg();
+        loop {
+            let mut object_grow = object;
+            let mut i = 0;
+            while i < QUADRANTS.len() {
+                if (object & QUADRANTS[i]) != 0 {
+                    object_grow |= QUADRANTS[i] & neighbors;
                 }
+                i += 1;
             }
-            label += 1;
-            if label >= 2 {
-                return false;
+            if object_grow == object {
+                break;
             }
+            object = object_grow;
+        }
+        quad &= !object;
+
+        label += 1;
+        if label >= 2 {
+            return false;
         }
     }
     // return label-2; in [Lee94] if the number of connected components would be needed
     return true;
 }
-
-/// This is a recursive method that calculates the number of connected
-/// components in the 2D neighborhood after the center pixel would
-/// have been removed.
-
-fn quadtree_labeling_sw(quad: &mut u32) {
-    if *quad & DIR_SW != 0 {
-        *quad &= !DIR_SW;
-    }
-    if *quad & DIR_S != 0 {
-        *quad &= !DIR_S;
-        quadtree_labeling_se(quad);
-    }
-    if *quad & DIR_W != 0 {
-        *quad &= !DIR_W;
-        quadtree_labeling_nw(quad);
-    }
-}
-
-fn quadtree_labeling_se(quad: &mut u32) {
-    if *quad & DIR_S != 0 {
-        *quad &= !DIR_S;
-        quadtree_labeling_sw(quad);
-        quadtree_labeling_se(quad);
-    }
-    if *quad & DIR_SE != 0 {
-        *quad &= !DIR_SE;
-        quadtree_labeling_se(quad);
-    }
-    if *quad & DIR_E != 0 {
-        *quad &= !DIR_E;
-        quadtree_labeling_ne(quad);
-        quadtree_labeling_se(quad);
-    }
-}
-
-fn quadtree_labeling_nw(quad: &mut u32) {
-    if *quad & DIR_W != 0 {
-        *quad &= !DIR_W;
-        quadtree_labeling_sw(quad);
-        quadtree_labeling_nw(quad);
-    }
-    if *quad & DIR_NW != 0 {
-        *quad &= !DIR_NW;
-        quadtree_labeling_nw(quad);
-    }
-    if *quad & DIR_N != 0 {
-        *quad &= !DIR_N;
-        quadtree_labeling_ne(quad);
-        quadtree_labeling_nw(quad);
-    }
-}
-
-fn quadtree_labeling_ne(quad: &mut u32) {
-    if *quad & DIR_E != 0 {
-        *quad &= !DIR_E;
-        quadtree_labeling_se(quad);
-        quadtree_labeling_ne(quad);
-    }
-    if *quad & DIR_N != 0 {
-        *quad &= !DIR_N;
-        quadtree_labeling_nw(quad);
-        quadtree_labeling_ne(quad);
-    }
-    if *quad & DIR_NE != 0 {
-        *quad &= !DIR_NE;
-        quadtree_labeling_ne(quad);
-    }
-}
-
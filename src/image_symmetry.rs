@@ -0,0 +1,135 @@
+///
+/// Detect a mirror axis in the binary image.
+///
+
+/// Mirror axes to consider when detecting symmetry.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Symmetry {
+    /// Left & right halves match (mirrored along the X axis).
+    X,
+    /// Top & bottom halves match (mirrored along the Y axis).
+    Y,
+    /// Use whichever of `X` or `Y` matches best (when it matches well enough).
+    Auto,
+}
+
+/// A mirror axis, in pixels.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Axis {
+    /// The coordinate which is mirrored (0 for X, 1 for Y).
+    pub axis: usize,
+    /// Position of the mirror line along `axis`.
+    pub position: f64,
+    /// Fraction of filled pixels which are also filled when mirrored.
+    pub score: f64,
+}
+
+impl Axis {
+    pub fn mirror(
+        &self,
+        co: &[f64; 2],
+    ) -> [f64; 2] {
+        let mut co_mirror = *co;
+        co_mirror[self.axis] = (2.0 * self.position) - co[self.axis];
+        return co_mirror;
+    }
+}
+
+/// The minimum score for `Symmetry::Auto` to use an axis.
+const AUTO_SCORE_MIN: f64 = 0.9;
+
+/// Axis positions to test on either side of the centroid (in half pixel steps).
+const SEARCH_STEPS: i64 = 4;
+
+/// Score the mirror line at `position_x2` (twice the position, keeping pixel alignment).
+fn axis_score(
+    image: &[bool],
+    size: &[usize; 2],
+    axis: usize,
+    position_x2: i64,
+    filled_len: usize,
+) -> f64 {
+    let mut overlap_len = 0;
+    for y in 0..size[1] {
+        for x in 0..size[0] {
+            if !image[x + y * size[0]] {
+                continue;
+            }
+            let mut co = [x as i64, y as i64];
+            // Pixel centers are offset by a half, so `2 * position - (i + 0.5) - 0.5`.
+            co[axis] = position_x2 - co[axis] - 1;
+            if co[axis] >= 0 && co[axis] < size[axis] as i64 &&
+               image[co[0] as usize + co[1] as usize * size[0]]
+            {
+                overlap_len += 1;
+            }
+        }
+    }
+    return overlap_len as f64 / filled_len as f64;
+}
+
+/// Return the best mirror line for `axis`, searching near the centroid of filled pixels.
+fn axis_detect_single(
+    image: &[bool],
+    size: &[usize; 2],
+    axis: usize,
+) -> Option<Axis> {
+    let mut filled_len = 0;
+    let mut center_sum = 0.0;
+    for y in 0..size[1] {
+        for x in 0..size[0] {
+            if image[x + y * size[0]] {
+                filled_len += 1;
+                center_sum += [x, y][axis] as f64 + 0.5;
+            }
+        }
+    }
+    if filled_len == 0 {
+        return None;
+    }
+
+    // The centroid of a symmetrical image is on the axis,
+    // search nearby in case the symmetry is only approximate.
+    let centroid_x2 = ((center_sum / filled_len as f64) * 2.0).round() as i64;
+    let mut best: Option<Axis> = None;
+    for step in -SEARCH_STEPS..(SEARCH_STEPS + 1) {
+        let position_x2 = centroid_x2 + step;
+        let score = axis_score(image, size, axis, position_x2, filled_len);
+        if best.map_or(true, |b| score > b.score) {
+            best = Some(Axis {
+                axis: axis,
+                position: position_x2 as f64 / 2.0,
+                score: score,
+            });
+        }
+    }
+    return best;
+}
+
+/// Detect a mirror axis in the image,
+/// returns `None` for empty images or when `Symmetry::Auto` finds no good match.
+pub fn axis_detect(
+    image: &[bool],
+    size: &[usize; 2],
+    symmetry: Symmetry,
+) -> Option<Axis> {
+    match symmetry {
+        Symmetry::X => {
+            return axis_detect_single(image, size, 0);
+        },
+        Symmetry::Y => {
+            return axis_detect_single(image, size, 1);
+        },
+        Symmetry::Auto => {
+            let mut best: Option<Axis> = None;
+            for axis in 0..2 {
+                if let Some(a) = axis_detect_single(image, size, axis) {
+                    if a.score >= AUTO_SCORE_MIN && best.map_or(true, |b| a.score > b.score) {
+                        best = Some(a);
+                    }
+                }
+            }
+            return best;
+        },
+    }
+}
@@ -0,0 +1,111 @@
+///
+/// Module for writing images to files.
+///
+
+/// PNG output, using uncompressed (stored) blocks since there is no compression library,
+/// see: https://www.w3.org/TR/png/
+pub mod png {
+    use std::io::prelude::Write;
+
+    /// The largest block of a stored (uncompressed) deflate stream.
+    const STORED_BLOCK_LEN_MAX: usize = 0xffff;
+
+    fn crc32(
+        data: &[u8],
+        crc: u32,
+    ) -> u32 {
+        let mut crc = !crc;
+        for byte in data {
+            crc ^= *byte as u32;
+            for _ in 0..8 {
+                crc = if (crc & 1) != 0 { 0xedb88320 ^ (crc >> 1) } else { crc >> 1 };
+            }
+        }
+        return !crc;
+    }
+
+    fn adler32(
+        data: &[u8],
+    ) -> u32 {
+        let (mut a, mut b) = (1u32, 0u32);
+        for byte in data {
+            a = (a + *byte as u32) % 65521;
+            b = (b + a) % 65521;
+        }
+        return (b << 16) | a;
+    }
+
+    fn write_chunk<W: Write>(
+        mut f: W,
+        chunk_type: &[u8; 4],
+        data: &[u8],
+    ) -> Result<(), ::std::io::Error> {
+        f.write_all(&(data.len() as u32).to_be_bytes())?;
+        f.write_all(chunk_type)?;
+        f.write_all(data)?;
+        f.write_all(&crc32(data, crc32(chunk_type, 0)).to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Write 8 bit RGBA pixels (rows top to bottom).
+    pub fn write_rgba<W: Write>(
+        mut f: W,
+        size: &[usize; 2],
+        pixels: &[[u8; 4]],
+    ) -> Result<(), ::std::io::Error> {
+        debug_assert!(pixels.len() == size[0] * size[1]);
+        f.write_all(b"\x89PNG\r\n\x1a\n")?;
+
+        let mut header: Vec<u8> = vec![];
+        header.extend_from_slice(&(size[0] as u32).to_be_bytes());
+        header.extend_from_slice(&(size[1] as u32).to_be_bytes());
+        // Bit depth, RGBA, deflate, adaptive filtering, no interlace.
+        header.extend_from_slice(&[8, 6, 0, 0, 0]);
+        write_chunk(&mut f, b"IHDR", &header)?;
+
+        // Each row starts with its filter type (none).
+        let mut rows: Vec<u8> = Vec::with_capacity((size[0] * 4 + 1) * size[1]);
+        for y in 0..size[1] {
+            rows.push(0);
+            for pixel in &pixels[y * size[0]..(y + 1) * size[0]] {
+                rows.extend_from_slice(pixel);
+            }
+        }
+
+        // A zlib stream of stored blocks.
+        let mut data: Vec<u8> = Vec::with_capacity(rows.len() + rows.len() / 8 + 16);
+        data.extend_from_slice(&[0x78, 0x01]);
+        let blocks_len = ::std::cmp::max(1, (rows.len() + STORED_BLOCK_LEN_MAX - 1) /
+                                            STORED_BLOCK_LEN_MAX);
+        for i in 0..blocks_len {
+            let block = &rows[(i * STORED_BLOCK_LEN_MAX)..
+                              ::std::cmp::min((i + 1) * STORED_BLOCK_LEN_MAX, rows.len())];
+            data.push(if i + 1 == blocks_len { 1 } else { 0 });
+            data.extend_from_slice(&(block.len() as u16).to_le_bytes());
+            data.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+            data.extend_from_slice(block);
+        }
+        data.extend_from_slice(&adler32(&rows).to_be_bytes());
+        write_chunk(&mut f, b"IDAT", &data)?;
+
+        write_chunk(&mut f, b"IEND", &[])?;
+        Ok(())
+    }
+}
+
+/// PGM output (binary), see: http://netpbm.sourceforge.net/doc/pgm.html
+pub mod pgm {
+    use std::io::prelude::Write;
+
+    /// Write 8 bit grayscale pixels (rows top to bottom).
+    pub fn write_gray<W: Write>(
+        mut f: W,
+        size: &[usize; 2],
+        pixels: &[u8],
+    ) -> Result<(), ::std::io::Error> {
+        debug_assert!(pixels.len() == size[0] * size[1]);
+        write!(f, "P5\n{} {}\n255\n", size[0], size[1])?;
+        f.write_all(pixels)?;
+        Ok(())
+    }
+}
@@ -4,6 +4,9 @@
 
 pub const ARGDEF_REQUIRED: u32 = (1 << 0);
 pub const ARGDEF_VARARGS: u32 =  (1 << 1);
+/// When this argument is used, required arguments may be omitted
+/// (for arguments which replace them).
+pub const ARGDEF_SKIP_REQUIRED: u32 = 1 << 2;
 
 pub const ARGDEF_DEFAULT: u32 = 0;
 
@@ -33,9 +36,33 @@ struct ArgumentDef<T> {
     group: Option<ArgGroup>,
 }
 
+/// A constraint between arguments, checked after parsing,
+/// see: `ArgumentParser.add_requires` & `ArgumentParser.add_conflicts`.
+struct ArgumentRule {
+    id: &'static str,
+    /// Any of these arguments must be used (for requirements) or none (for conflicts).
+    id_others: &'static [&'static str],
+    /// When set, the rule only applies when the other argument is passed this value.
+    value: Option<&'static str>,
+    is_conflict: bool,
+}
+
+/// When an argument has an effect, checked after parsing (warning when it doesn't),
+/// see: `ArgumentParser.add_applies`.
+struct ArgumentApplies<T> {
+    id: &'static str,
+    test: fn(&T) -> bool,
+    /// Why the argument has no effect, e.g. `without --snap`.
+    reason: &'static str,
+}
+
 pub struct ArgumentParser<'a, T: 'a> {
     arg_handlers: Vec<ArgumentDef<T>>,
     arg_groups: Vec<ArgGroupData>,
+    arg_rules: Vec<ArgumentRule>,
+    arg_applies: Vec<ArgumentApplies<T>>,
+    /// Arguments which were used but have no effect (set by `parse`).
+    warnings: Vec<String>,
     descr: &'static str,
 
     /// Generic data that Argument callbacks can store their data in.
@@ -118,9 +145,86 @@ impl <'a, T> ArgumentParser<'a, T> {
             );
     }
 
+    /// Argument `id` may only be used when one of `id_others` is used,
+    /// when `value` is set, only when the other argument is passed this value
+    /// (for arguments which only apply to some modes).
+    ///
+    /// Arguments are identified by their long (or short) flag name.
+    pub fn add_requires(
+        &mut self,
+        id: &'static str,
+        id_others: &'static [&'static str],
+        value: Option<&'static str>,
+    ) {
+        self.add_rule(id, id_others, value, false);
+    }
+
+    /// Argument `id` can't be used with any of `id_others`,
+    /// when `value` is set, only when the other argument is passed this value
+    /// (for arguments which don't apply to some modes).
+    pub fn add_conflicts(
+        &mut self,
+        id: &'static str,
+        id_others: &'static [&'static str],
+        value: Option<&'static str>,
+    ) {
+        self.add_rule(id, id_others, value, true);
+    }
+
+    /// Argument `id` only has an effect when `test` returns true for the parsed data,
+    /// otherwise `parse` adds a warning, see: `ArgumentParser.warnings`.
+    ///
+    /// Unlike rules, this is for arguments which are harmless (although likely a mistake),
+    /// such as an argument which depends on the output format.
+    pub fn add_applies(
+        &mut self,
+        id: &'static str,
+        reason: &'static str,
+        test: fn(&T) -> bool,
+    ) {
+        debug_assert!(self.arg_handler_search(id).is_some(),
+                      "Unknown argument {:?} (must be added after arguments)", id);
+        self.arg_applies.push(
+            ArgumentApplies::<T> {
+                id: id,
+                test: test,
+                reason: reason,
+            }
+        );
+    }
+
+    /// Warnings for arguments which have no effect, from the last call to `parse`.
+    pub fn warnings(&self) -> &[String] {
+        return &self.warnings;
+    }
+
+    fn add_rule(
+        &mut self,
+        id: &'static str,
+        id_others: &'static [&'static str],
+        value: Option<&'static str>,
+        is_conflict: bool,
+    ) {
+        if cfg!(debug_assertions) {
+            for id_test in Some(&id).into_iter().chain(id_others) {
+                if self.arg_handler_search(id_test).is_none() {
+                    panic!("Unknown argument {:?} (rules must be added after arguments)", id_test);
+                }
+            }
+        }
+        self.arg_rules.push(
+            ArgumentRule {
+                id: id,
+                id_others: id_others,
+                value: value,
+                is_conflict: is_conflict,
+            }
+        );
+    }
+
     fn arg_handler_search(
         &self,
-        arg: &String,
+        arg: &str,
     ) -> Option<usize> {
 
         for (i, arg_handler) in (&self.arg_handlers).iter().enumerate() {
@@ -139,6 +243,9 @@ impl <'a, T> ArgumentParser<'a, T> {
         args: &[String],
     ) -> Result<(), String> {
         let mut arg_handlers_used = vec![false; self.arg_handlers.len()];
+        self.warnings.clear();
+        // The parameters of each argument (when used more than once, the last).
+        let mut arg_handlers_params: Vec<&[String]> = vec![&[]; self.arg_handlers.len()];
 
         let mut i: usize = 0;
         while i < args.len() {
@@ -151,7 +258,8 @@ impl <'a, T> ArgumentParser<'a, T> {
                 if (arg_handler.flag & ARGDEF_VARARGS) != 0 {
                     args_for_handler = &args[i..];
                 } else {
-                    args_for_handler = &args[i..(i + arg_handler.nparams)];
+                    args_for_handler =
+                        &args[i..::std::cmp::min(i + arg_handler.nparams, args.len())];
                     if args_for_handler.len() != arg_handler.nparams {
                         return Err(format!(
                             "Error '{}' expected {} parameters, received {}!",
@@ -167,6 +275,7 @@ impl <'a, T> ArgumentParser<'a, T> {
                     Ok(nparams_used) => {
                         debug_assert!((arg_handler.flag & ARGDEF_VARARGS) != 0 ||
                                       arg_handler.nparams as usize >= nparams_used);
+                        arg_handlers_params[arg_handler_index] = &args[i..(i + nparams_used)];
                         i += nparams_used;
                     }
                     Err(e) => {
@@ -185,8 +294,11 @@ impl <'a, T> ArgumentParser<'a, T> {
         }
 
         // check all required args were used
+        let use_required = !(&self.arg_handlers).iter().zip(&arg_handlers_used).any(
+            |(arg_handler, &is_used)| is_used && (arg_handler.flag & ARGDEF_SKIP_REQUIRED) != 0);
         for (i, arg_handler) in (&self.arg_handlers).iter().enumerate() {
-            if (arg_handler.flag & ARGDEF_REQUIRED) != 0 &&
+            if use_required &&
+               (arg_handler.flag & ARGDEF_REQUIRED) != 0 &&
                (arg_handlers_used[i] == false)
             {
                 return Err(format!(
@@ -203,49 +315,123 @@ impl <'a, T> ArgumentParser<'a, T> {
             }
         }
 
+        for arg_rule in &self.arg_rules {
+            if !arg_handlers_used[self.arg_handler_search(arg_rule.id).unwrap()] {
+                continue;
+            }
+            let id_other_used = arg_rule.id_others.iter().find(|id_other| {
+                let index = self.arg_handler_search(id_other).unwrap();
+                arg_handlers_used[index] && arg_rule.value.map_or(
+                    true, |value| arg_handlers_params[index].first().map_or(
+                        false, |param| param == value))
+            });
+            match (arg_rule.is_conflict, id_other_used) {
+                (true, Some(id_other)) => {
+                    return Err(format!(
+                        "Error: '{}' can't be used with '{}{}{}'!",
+                        arg_rule.id, id_other,
+                        if arg_rule.value.is_some() { " " } else { "" },
+                        arg_rule.value.unwrap_or(""),
+                        ));
+                }
+                (false, None) => {
+                    return Err(format!(
+                        "Error: '{}' requires '{}'!",
+                        arg_rule.id,
+                        arg_rule.id_others.iter().map(|id_other| match arg_rule.value {
+                            Some(value) => format!("{} {}", id_other, value),
+                            None => id_other.to_string(),
+                        }).collect::<Vec<String>>().join("' or '"),
+                        ));
+                }
+                _ => {}
+            }
+        }
+
+        for arg_applies in &self.arg_applies {
+            if arg_handlers_used[self.arg_handler_search(arg_applies.id).unwrap()] &&
+               !(arg_applies.test)(&self.dest_data)
+            {
+                self.warnings.push(format!(
+                    "Warning: '{}' has no effect {}",
+                    arg_applies.id, arg_applies.reason,
+                    ));
+            }
+        }
+
         Ok(())
     }
 
     pub fn print_help(&self) {
         println!("{}\n", self.descr);
+        let width = help_width();
+        for (i, arg_indices) in self.arg_group_indices().iter().enumerate() {
+            self.print_help_group_index(i, arg_indices, width);
+        }
+    }
 
-        let arg_group_indices =
-            if self.arg_groups.len() == 0 {
-                vec![vec![0; self.arg_handlers.len()]]
-            } else {
-                let mut arg_group_indices: Vec<Vec<usize>> = vec![vec![]; self.arg_groups.len() + 1];
-                for (i, arg_handler) in (&self.arg_handlers).iter().enumerate() {
-                    let index = {
-                        if let Some(group_id) = arg_handler.group {
-                            group_id.0 + 1
-                        } else {
-                            0
-                        }
-                    };
-
-                    arg_group_indices[index].push(i);
+    /// Print help for the groups which start with `name` (case insensitive),
+    /// e.g. `output` for "Output Options".
+    pub fn print_help_group(
+        &self,
+        name: &str,
+    ) -> Result<(), String> {
+        let name_lower = name.to_lowercase();
+        let width = help_width();
+        let mut is_found = false;
+        for (i, arg_indices) in self.arg_group_indices().iter().enumerate() {
+            if i != 0 && self.arg_groups[i - 1].name.to_lowercase().starts_with(&name_lower) {
+                self.print_help_group_index(i, arg_indices, width);
+                is_found = true;
+            }
+        }
+        if !is_found {
+            return Err(format!(
+                "Error: '{}' unknown help group, expected one of [{}]!",
+                name,
+                self.arg_groups.iter().map(
+                    |arg_group| arg_group.name).collect::<Vec<&str>>().join(", "),
+                ));
+        }
+        Ok(())
+    }
+
+    /// The arguments in each group, arguments without a group are first.
+    fn arg_group_indices(&self) -> Vec<Vec<usize>> {
+        let mut arg_group_indices: Vec<Vec<usize>> = vec![vec![]; self.arg_groups.len() + 1];
+        for (i, arg_handler) in (&self.arg_handlers).iter().enumerate() {
+            let index = {
+                if let Some(group_id) = arg_handler.group {
+                    group_id.0 + 1
+                } else {
+                    0
                 }
-                arg_group_indices
             };
 
-        for (i, arg_indices) in arg_group_indices.iter().enumerate() {
-            if i == 0 {
-                println!("Options:");
-            } else {
-                let arg_group = &self.arg_groups[i - 1];
-                println!("{}:\n", arg_group.name);
-                if arg_group.descr.len() != 0 {
-                    println!("    {}\n", arg_group.descr);
+            arg_group_indices[index].push(i);
+        }
+        return arg_group_indices;
+    }
+
+    fn print_help_group_index(&self, i: usize, arg_indices: &[usize], width: usize) {
+        if i == 0 {
+            println!("Options:");
+        } else {
+            let arg_group = &self.arg_groups[i - 1];
+            println!("{}:\n", arg_group.name);
+            if arg_group.descr.len() != 0 {
+                for line in text_wrap(arg_group.descr, width - HELP_INDENT) {
+                    println!("{:indent$}{}", "", line, indent = HELP_INDENT);
                 }
+                println!("");
             }
-            self.print_help_arg_indices(&arg_indices);
-            println!("\n");
         }
+        self.print_help_arg_indices(arg_indices, width);
+        println!("\n");
     }
 
-    fn print_help_arg_indices(&self, arg_indices: &[usize]) {
+    fn print_help_arg_indices(&self, arg_indices: &[usize], width: usize) {
 
-        let mut options_max_len = 0;
         let mut options = vec![];
         for i in arg_indices {
             let arg_handler = &self.arg_handlers[*i];
@@ -261,32 +447,118 @@ impl <'a, T> ArgumentParser<'a, T> {
                 arg_handler.id_long,
                 arg_handler.metavar,
                 );
-
-            if options_max_len < option_str.len() {
-                options_max_len = option_str.len();
-            }
-
             options.push(option_str);
         }
 
-        // align, not optimal but doesn't really matter here,
-        // could use string formatting?
-        for option_str in &mut options {
-            while option_str.len() < options_max_len {
-                option_str.push(' ');
-            }
-        }
+        // Align by characters (not bytes), options which don't fit the column
+        // are written on their own line.
+        let options_max_len = options.iter().map(|option_str| option_str.chars().count()).filter(
+            |&len| len <= HELP_OPTION_WIDTH_MAX).max().unwrap_or(0);
+        let descr_indent = HELP_INDENT + options_max_len + 2;
+        let descr_width = ::std::cmp::max(width.saturating_sub(descr_indent), HELP_DESCR_WIDTH_MIN);
 
-        // for i in arg_indices 
         for (i, option_str) in (arg_indices).iter().zip(options) {
             let arg_handler = &self.arg_handlers[*i];
-            println!(
-                "    {}  {}",
-                option_str,
-                arg_handler.descr,
-                );
+            let option_len = option_str.chars().count();
+            let mut lines = text_wrap(arg_handler.descr, descr_width).into_iter();
+            if option_len > options_max_len {
+                println!("{:indent$}{}", "", option_str, indent = HELP_INDENT);
+            } else {
+                println!(
+                    "{:indent$}{}{:pad$}  {}",
+                    "", option_str, "", lines.next().unwrap_or_default(),
+                    indent = HELP_INDENT, pad = options_max_len - option_len,
+                    );
+            }
+            for line in lines {
+                println!("{:indent$}{}", "", line, indent = descr_indent);
+            }
+        }
+    }
+}
+
+/// Indentation of options & group descriptions in the help text.
+const HELP_INDENT: usize = 4;
+/// Longer options are written on their own line, so descriptions have room.
+const HELP_OPTION_WIDTH_MAX: usize = 32;
+/// Wrap descriptions at this width, even when the terminal is narrower.
+const HELP_DESCR_WIDTH_MIN: usize = 20;
+
+/// The width to wrap help text, from the size of the terminal (when writing to one),
+/// otherwise the `COLUMNS` environment variable (defaults to 80).
+fn help_width() -> usize {
+    return terminal_columns().or_else(|| {
+        ::std::env::var("COLUMNS").ok().and_then(|columns| columns.parse::<usize>().ok())
+    }).filter(|&columns| columns != 0).unwrap_or(80);
+}
+
+/// The number of columns of the terminal the standard output is written to,
+/// `None` when it's not a terminal (or unsupported on this platform).
+#[cfg(any(target_os = "linux", target_os = "android",
+          target_os = "macos", target_os = "ios",
+          target_os = "freebsd", target_os = "dragonfly",
+          target_os = "openbsd", target_os = "netbsd"))]
+fn terminal_columns() -> Option<usize> {
+    use std::os::raw::{
+        c_int,
+        c_ulong,
+    };
+
+    #[repr(C)]
+    struct WinSize {
+        ws_row: u16,
+        ws_col: u16,
+        ws_xpixel: u16,
+        ws_ypixel: u16,
+    }
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    const TIOCGWINSZ: c_ulong = 0x5413;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    const TIOCGWINSZ: c_ulong = 0x40087468;
+    const STDOUT_FILENO: c_int = 1;
+
+    extern "C" {
+        fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+    }
+
+    let mut size = WinSize { ws_row: 0, ws_col: 0, ws_xpixel: 0, ws_ypixel: 0 };
+    if unsafe { ioctl(STDOUT_FILENO, TIOCGWINSZ, &mut size as *mut WinSize) } != 0 {
+        return None;
+    }
+    return Some(size.ws_col as usize);
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android",
+              target_os = "macos", target_os = "ios",
+              target_os = "freebsd", target_os = "dragonfly",
+              target_os = "openbsd", target_os = "netbsd")))]
+fn terminal_columns() -> Option<usize> {
+    return None;
+}
+
+/// Split `text` into lines no wider than `width` characters (where possible),
+/// breaking at spaces.
+pub fn text_wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    let mut line = String::new();
+    let mut line_len = 0;
+    for word in text.split(' ').filter(|word| !word.is_empty()) {
+        let word_len = word.chars().count();
+        if line_len != 0 && line_len + 1 + word_len > width {
+            lines.push(::std::mem::replace(&mut line, String::new()));
+            line_len = 0;
         }
+        if line_len != 0 {
+            line.push(' ');
+            line_len += 1;
+        }
+        line.push_str(word);
+        line_len += word_len;
+    }
+    if line_len != 0 {
+        lines.push(line);
     }
+    return lines;
 }
 
 pub fn new<'a, T>(
@@ -296,6 +568,9 @@ pub fn new<'a, T>(
     ArgumentParser::<T> {
         arg_handlers: vec![],
         arg_groups: vec![],
+        arg_rules: vec![],
+        arg_applies: vec![],
+        warnings: vec![],
         descr: descr,
         dest_data: dest_data,
     }
@@ -17,20 +17,33 @@ use ::intern::math_vector::{
     add_vnvn, sub_vnvn,
     copy_vnvn,
     dot_vnvn,
+    len_vnvn,
     madd_vnvn_fl,
     normalize_vn,
+    normalized_vnvn,
     normalized_vnvn_with_len,
     sq,
-    zero_vn,
 };
 
 use ::intern::min_heap;
 
 const DIMS: usize = ::intern::math_vector::DIMS;
 
+use std::cell::RefCell;
 use std::collections::LinkedList;
+use std::sync::Arc;
 
 use super::curve_fit_single;
+use super::curve_fit_single::{
+    SOLVER_LEN,
+    CacheReal,
+    ErrorEval,
+    Reparameterize,
+    Solver,
+    SolverStats,
+    WrapSlice,
+};
+use super::curve_fit_single::types::Cubic;
 
 #[derive(Copy, Clone, PartialEq)]
 pub enum TraceMode {
@@ -38,9 +51,210 @@ pub enum TraceMode {
     Centerline,
 }
 
+/// Endpoint tangent calculation for open (non-cyclic) polygons.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum EndTangent {
+    /// Use the direction of the adjacent segment.
+    Natural,
+    /// Use the direction over multiple points (`END_TANGENT_CLAMP_POINTS`),
+    /// so small steps at the ends of strokes don't cause them to flare.
+    Clamped,
+    /// Zero length handles at endpoints.
+    Zero,
+}
+
+/// The refinement pass which removed a knot, see: `RefineStep`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum RefineStage {
+    /// Removed while the error stayed below the threshold.
+    Remove,
+    /// Removed by refitting, the knot is replaced by `RefineStep::co_refit`
+    /// (or removed when that's unset).
+    Refit,
+}
+
+/// A single knot removal, logged in the order knots are removed.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct RefineStep {
+    pub stage: RefineStage,
+    /// The location of the removed knot.
+    pub co: [f64; DIMS],
+    pub co_refit: Option<[f64; DIMS]>,
+    /// The fitting error of the segments replacing the knot.
+    pub error: f64,
+}
+
+/// The refinement pass a `FitSnapshot` was taken after.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum FitStage {
+    /// After removing knots while the error stayed below the threshold.
+    PostRemove,
+    /// After splitting corners (only when corners are detected).
+    PostCorner,
+}
+
+/// A knot remaining after a refinement pass.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct KnotSnapshot {
+    pub co: [f64; DIMS],
+    pub is_corner: bool,
+}
+
+/// The knots remaining after a refinement pass, in order.
+#[derive(Clone, PartialEq, Debug)]
+pub struct FitSnapshot {
+    pub stage: FitStage,
+    pub knots: Vec<KnotSnapshot>,
+}
+
+/// Receives the knots remaining after each refinement pass,
+/// for inspecting how fitting arrives at its result.
+///
+/// Polygons may be fit on multiple threads, so snapshots arrive in any order,
+/// `index` is the polygon's position in the input.
+pub trait FitObserver: Sync {
+    fn snapshot(
+        &self,
+        index: usize,
+        is_cyclic: bool,
+        snapshot: &FitSnapshot,
+    );
+}
+
+/// Number of points used to calculate clamped endpoint tangents.
+const END_TANGENT_CLAMP_POINTS: usize = 4;
+
+/// Scale the error threshold by the size of each polygon,
+/// so small regions are fit more precisely than large ones.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ErrorRelative {
+    /// Multiplied by the diagonal of the polygons bounding box.
+    pub factor: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl ErrorRelative {
+    /// Return the error threshold to use for `poly`.
+    pub fn error_threshold_for_poly(
+        &self,
+        poly: &Vec<[f64; DIMS]>,
+    ) -> f64 {
+        let mut min = [::std::f64::MAX; DIMS];
+        let mut max = [-::std::f64::MAX; DIMS];
+        for v in poly {
+            for j in 0..DIMS {
+                min[j] = min[j].min(v[j]);
+                max[j] = max[j].max(v[j]);
+            }
+        }
+        let diagonal = if poly.len() != 0 { len_vnvn(&min, &max) } else { 0.0 };
+        return (diagonal * self.factor).max(self.min).min(self.max);
+    }
+}
+
+/// Early termination for exhaustive refitting (see: `use_optimize_exhaustive`).
+///
+/// Candidate knots are tested in order of their distance from the point of greatest error,
+/// stopping once `steps` consecutive candidates improve the error by less than `epsilon`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ExhaustivePrune {
+    /// Improvements (in pixels) below this count towards `steps`.
+    pub epsilon: f64,
+    /// The number of consecutive candidates without improvement before stopping
+    /// (zero to test every candidate).
+    pub steps: usize,
+}
+
+pub const EXHAUSTIVE_PRUNE_DEFAULT: ExhaustivePrune = ExhaustivePrune {
+    epsilon: 0.01,
+    steps: 16,
+};
+
+/// Settings for fitting curves to polygons,
+/// see: `fit_poly_single_with_params` & `fit_poly_list_with_params`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct FitParams {
+    /// The maximum distance between the curve & the polygon.
+    pub error_threshold: f64,
+    /// When set, `error_threshold` is calculated for each polygon.
+    pub error_relative: Option<ErrorRelative>,
+    /// Knots sharper than this angle (in radians) are corners, (PI or more to disable).
+    pub corner_angle: f64,
+    /// Test every remaining knot when refitting, instead of only the neighbors.
+    pub use_optimize_exhaustive: bool,
+    pub end_tangent: EndTangent,
+    /// Limit the length of each handle to this factor of the distance between the knots,
+    /// errors are measured after limiting (zero to disable).
+    pub max_handle_ratio: f64,
+    /// Average the initial tangents along the polygon within this length,
+    /// reducing wobble from noisy tangents (stair-steps for example), see: `tangents_smooth`
+    /// (zero to disable).
+    pub tangent_smooth: f64,
+    /// Methods used to fit each segment (in order), see: `Solver`.
+    pub solvers: Vec<Solver>,
+    /// Refinement of the least squares solution, see: `Reparameterize`.
+    pub reparameterize: Reparameterize,
+    /// Stop testing candidates early when `use_optimize_exhaustive` is set,
+    /// see: `ExhaustivePrune`.
+    pub exhaustive_prune: ExhaustivePrune,
+    /// Order knots which are equally suitable for removal by their index,
+    /// so the result doesn't depend on the order knots are added to the heap.
+    pub use_deterministic: bool,
+    /// Store point lengths & tangents as `f32` while fitting,
+    /// the result is checked against the points & the error threshold,
+    /// fitting again with `f64` precision when it's exceeded.
+    pub use_precision_f32: bool,
+    /// Log knot removals for each polygon (lists of polygons only).
+    pub use_refine_log: bool,
+    /// The maximum number of threads to use, (lists of polygons only),
+    /// zero to use all available threads.
+    pub threads_len_max: usize,
+}
+
+impl Default for FitParams {
+    fn default() -> FitParams {
+        return FitParams {
+            error_threshold: 1.0,
+            error_relative: None,
+            corner_angle: ::std::f64::consts::PI,
+            use_optimize_exhaustive: false,
+            end_tangent: EndTangent::Natural,
+            max_handle_ratio: 0.0,
+            tangent_smooth: 0.0,
+            solvers: curve_fit_single::SOLVERS_DEFAULT.to_vec(),
+            reparameterize: curve_fit_single::REPARAMETERIZE_DEFAULT,
+            exhaustive_prune: EXHAUSTIVE_PRUNE_DEFAULT,
+            use_deterministic: false,
+            use_precision_f32: false,
+            use_refine_log: false,
+            threads_len_max: 0,
+        };
+    }
+}
+
+impl FitParams {
+    /// Return the error threshold to use for `poly`, see: `error_relative`.
+    pub fn error_threshold_for_poly(
+        &self,
+        poly: &Vec<[f64; DIMS]>,
+    ) -> f64 {
+        return match self.error_relative {
+            Some(e) => e.error_threshold_for_poly(poly),
+            None => self.error_threshold,
+        };
+    }
+}
+
 mod types {
     use super::{
+        CacheReal,
         DIMS,
+        ExhaustivePrune,
+        RefCell,
+        Reparameterize,
+        Solver,
+        curve_fit_single,
     };
 
     pub struct Knot {
@@ -64,21 +278,53 @@ mod types {
         ///
         /// This is the error between this knot and the next.
         pub fit_error_sq_next: f64,
+        /// The solver used for the segment to the next knot (unset when it wasn't fit).
+        pub solver_next: Option<Solver>,
 
         /// Initially point to contiguous memory, however we may re-assign.
         pub tan: [usize; 2],
     }
 
-    pub struct PointData<'a> {
-        /// note, can't use points.len(),
-        /// since this may be doubled for cyclic curves
-        pub points: &'a Vec<[f64; DIMS]>,
+    pub struct PointData<'a, R: 'a + CacheReal> {
+        /// Segments of cyclic polygons may wrap past the end of these arrays,
+        /// see: `WrapSlice`.
+        pub points: &'a [[f64; DIMS]],
         pub points_len: usize,
 
-        /// This array may be doubled as well.
-        pub points_length_cache: &'a Vec<f64>,
+        /// The length from the previous point to each point.
+        pub points_length_cache: &'a [R],
+
+        /// The weight of each point when fitting.
+        pub weights: Option<&'a [f64]>,
+
+        /// Access using `tangent` (stored as `R`).
+        pub tangents: &'a [[R; DIMS]],
 
-        pub tangents: &'a Vec<[f64; DIMS]>,
+        /// Order knots with equal error by their index, see: `min_heap::value_with_index`.
+        pub use_deterministic: bool,
+
+        /// Limit handle lengths to this factor of the segment length (zero to disable).
+        pub max_handle_ratio: f64,
+
+        /// Solvers used to fit each segment (in order).
+        pub solvers: &'a [Solver],
+
+        pub reparameterize: Reparameterize,
+
+        pub exhaustive_prune: ExhaustivePrune,
+
+        /// Scratch memory for fitting each segment.
+        pub fit_scratch: &'a RefCell<curve_fit_single::FitScratch>,
+    }
+
+    impl<'a, R: CacheReal> PointData<'a, R> {
+        pub fn tangent(&self, i: usize) -> [f64; DIMS] {
+            let mut t = [0.0; DIMS];
+            for j in 0..DIMS {
+                t[j] = self.tangents[i][j].to_f64();
+            }
+            return t;
+        }
     }
 }
 
@@ -87,12 +333,103 @@ use self::types::{
     PointData,
 };
 
+/// Lengths & tangents calculated from the points, see: `PointData`.
+#[derive(Default)]
+struct FitCaches<R> {
+    points_length_cache: Vec<R>,
+    tangents: Vec<[R; DIMS]>,
+}
+
+impl<R> FitCaches<R> {
+    fn bytes(&self) -> usize {
+        use std::mem::size_of;
+        return
+            self.points_length_cache.capacity() * size_of::<R>() +
+            self.tangents.capacity() * size_of::<[R; DIMS]>();
+    }
+}
+
+/// Memory used while fitting a polygon,
+/// reused between polygons so fitting many small polygons doesn't allocate for each one.
+///
+/// Each thread uses its own arena.
+#[derive(Default)]
+pub struct FitArena {
+    caches: FitCaches<f64>,
+    /// Used instead of `caches` when fitting with `f32` precision.
+    caches_f32: FitCaches<f32>,
+    knots: Vec<Knot>,
+    knots_handle: Vec<min_heap::NodeHandle>,
+    fit_scratch: RefCell<curve_fit_single::FitScratch>,
+
+    /// The largest value of `bytes` after fitting a polygon.
+    bytes_peak: usize,
+
+    /// Segments fit by each solver, for all polygons fit using the arena.
+    solver_segments_len: [usize; SOLVER_LEN],
+}
+
+impl FitArena {
+    pub fn new() -> FitArena {
+        return Default::default();
+    }
+
+    /// An arena which measures the error of each fit using `error_eval`, see: `ErrorEval`.
+    pub fn with_error_eval(
+        error_eval: Option<Arc<dyn ErrorEval>>,
+    ) -> FitArena {
+        let arena = FitArena::new();
+        arena.fit_scratch.borrow_mut().error_eval = error_eval;
+        return arena;
+    }
+
+    /// Memory allocated by the arena (in bytes).
+    pub fn bytes(&self) -> usize {
+        use std::mem::size_of;
+        return
+            self.caches.bytes() +
+            self.caches_f32.bytes() +
+            self.knots.capacity() * size_of::<Knot>() +
+            self.knots_handle.capacity() * size_of::<min_heap::NodeHandle>() +
+            self.fit_scratch.borrow().bytes();
+    }
+
+    /// The most memory used by the arena (in bytes).
+    pub fn bytes_peak(&self) -> usize {
+        return self.bytes_peak;
+    }
+
+    /// Segments of the resulting curves fit by each solver (indexed by `Solver as usize`).
+    pub fn solver_segments_len(&self) -> [usize; SOLVER_LEN] {
+        return self.solver_segments_len;
+    }
+
+    /// Statistics for all fits (not only the resulting segments), see: `SolverStats`.
+    pub fn solver_stats(&self) -> SolverStats {
+        return self.fit_scratch.borrow().stats();
+    }
+}
+
+/// Statistics from fitting a list of polygons.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct FitStats {
+    /// Number of threads used.
+    pub threads_len: usize,
+    /// The sum of `FitArena::bytes_peak` for all threads.
+    pub arena_bytes_peak: usize,
+    /// Segments of the resulting curves fit by each solver (indexed by `Solver as usize`),
+    /// segments without points between their knots aren't fit & aren't counted.
+    pub solver_segments_len: [usize; SOLVER_LEN],
+    /// Statistics for all fits, see: `SolverStats`.
+    pub solver_stats: SolverStats,
+}
+
 const INVALID: usize = ::std::usize::MAX;
 
 /// Find the knot furthest from the line between \a knot_l & \a knot_r.
 /// This is to be used as a split point.
-fn knot_find_split_point_on_axis(
-    pd: &PointData,
+fn knot_find_split_point_on_axis<R: CacheReal>(
+    pd: &PointData<R>,
     knots: &Vec<Knot>,
     k_prev: &Knot,
     k_next: &Knot,
@@ -127,30 +464,52 @@ fn knot_find_split_point_on_axis(
 }
 
 
-fn knot_remove_error_value(
+fn knot_remove_error_value<R: CacheReal>(
     tan_l: &[f64; DIMS],
     tan_r: &[f64; DIMS],
-    points_offset: &[[f64; DIMS]],
-    points_offset_length_cache: &[f64],
-) -> (f64, usize, [f64; 2]) {
-    let ((error_sq, error_index), handle_factor_l, handle_factor_r) =
-        curve_fit_single::curve_fit_cubic_to_points_single(
-            points_offset, points_offset_length_cache,
+    points_offset: WrapSlice<[f64; DIMS]>,
+    points_offset_length_cache: WrapSlice<R>,
+    weights_offset: Option<WrapSlice<f64>>,
+    max_handle_ratio: f64,
+    solvers: &[Solver],
+    reparameterize: Reparameterize,
+    fit_scratch: &mut curve_fit_single::FitScratch,
+) -> (f64, usize, [f64; 2], Solver) {
+    let ((error_sq, error_index), handle_factor_l, handle_factor_r, solver) =
+        curve_fit_single::curve_fit_cubic_to_points_single_wrap(
+            points_offset, points_offset_length_cache, weights_offset,
             tan_l, tan_r,
+            max_handle_ratio,
+            solvers,
+            reparameterize,
+            fit_scratch,
             );
     return (
         error_sq, error_index,
         [dot_vnvn(tan_l, &sub_vnvn(&handle_factor_l, &points_offset[0])),
          dot_vnvn(tan_r, &sub_vnvn(&handle_factor_r, &points_offset[points_offset.len() - 1]))],
+        solver,
     );
 }
 
-fn knot_calc_curve_error_value_and_index(
-    pd: &PointData,
+/// Handle length for segments without points between the knots.
+fn knot_handle_len_fallback<R: CacheReal>(
+    pd: &PointData<R>,
+    len: f64,
+) -> f64 {
+    let handle_len = len / 3.0;
+    if pd.max_handle_ratio != 0.0 {
+        return handle_len.min(len * pd.max_handle_ratio);
+    }
+    return handle_len;
+}
+
+fn knot_calc_curve_error_value_and_index<R: CacheReal>(
+    pd: &PointData<R>,
     knot_l: &Knot, knot_r: &Knot,
     tan_l: &[f64; DIMS],
     tan_r: &[f64; DIMS],
-) -> (f64, usize, [f64; 2]) {
+) -> (f64, usize, [f64; 2], Option<Solver>) {
     let points_offset_len =
         if knot_l.index < knot_r.index {
             knot_r.index - knot_l.index
@@ -159,11 +518,17 @@ fn knot_calc_curve_error_value_and_index(
         } + 1;
 
     if points_offset_len != 2 {
-        let points_offset_end = knot_l.index + points_offset_len;
         let mut result = knot_remove_error_value(
             tan_l, tan_r,
-            &pd.points[knot_l.index..points_offset_end],
-            &pd.points_length_cache[knot_l.index..points_offset_end],
+            WrapSlice::from_range(pd.points, knot_l.index, points_offset_len),
+            WrapSlice::from_range(pd.points_length_cache, knot_l.index, points_offset_len),
+            pd.weights.map(|weights| {
+                WrapSlice::from_range(weights, knot_l.index, points_offset_len)
+            }),
+            pd.max_handle_ratio,
+            pd.solvers,
+            pd.reparameterize,
+            &mut pd.fit_scratch.borrow_mut(),
             );
 
         // Adjust the offset index to the global index & wrap if needed.
@@ -171,21 +536,22 @@ fn knot_calc_curve_error_value_and_index(
         if result.1 >= pd.points_len {
             result.1 -= pd.points_len;
         }
-        return result;
+        return (result.0, result.1, result.2, Some(result.3));
     } else {
         // No points between, use 1/3 handle length with no error as a fallback.
         debug_assert!(points_offset_len == 2);
-        let handle_len = pd.points_length_cache[knot_l.index] / 3.0;
-        return (0.0, knot_l.index, [handle_len, handle_len]);
+        let handle_len = knot_handle_len_fallback(
+            pd, pd.points_length_cache[knot_l.index].to_f64());
+        return (0.0, knot_l.index, [handle_len, handle_len], None);
     }
 }
 
-fn knot_calc_curve_error_value(
-    pd: &PointData,
+fn knot_calc_curve_error_value<R: CacheReal>(
+    pd: &PointData<R>,
     knot_l: &Knot, knot_r: &Knot,
     tan_l: &[f64; DIMS],
     tan_r: &[f64; DIMS],
-) -> (f64, [f64; 2]) {
+) -> (f64, [f64; 2], Option<Solver>) {
     let points_offset_len =
         if knot_l.index < knot_r.index {
             knot_r.index - knot_l.index
@@ -194,24 +560,35 @@ fn knot_calc_curve_error_value(
         } + 1;
 
     if points_offset_len != 2 {
-        let points_offset_end = knot_l.index + points_offset_len;
         let result = knot_remove_error_value(
             tan_l, tan_r,
-            &pd.points[knot_l.index..points_offset_end],
-            &pd.points_length_cache[knot_l.index..points_offset_end],
+            WrapSlice::from_range(pd.points, knot_l.index, points_offset_len),
+            WrapSlice::from_range(pd.points_length_cache, knot_l.index, points_offset_len),
+            pd.weights.map(|weights| {
+                WrapSlice::from_range(weights, knot_l.index, points_offset_len)
+            }),
+            pd.max_handle_ratio,
+            pd.solvers,
+            pd.reparameterize,
+            &mut pd.fit_scratch.borrow_mut(),
             );
-        return (result.0, result.2);
+        return (result.0, result.2, Some(result.3));
     } else {
         // No points between, use 1/3 handle length with no error as a fallback.
         debug_assert!(points_offset_len == 2);
-        let handle_len = pd.points_length_cache[knot_l.index] / 3.0;
-        return (0.0, [handle_len, handle_len]);
+        let handle_len = knot_handle_len_fallback(
+            pd, pd.points_length_cache[knot_l.index].to_f64());
+        return (0.0, [handle_len, handle_len], None);
     }
 }
 
 mod refine_remove {
     use super::{
+        CacheReal,
         INVALID,
+        RefineStage,
+        RefineStep,
+        Solver,
         knot_calc_curve_error_value,
     };
     use super::types::{
@@ -228,11 +605,12 @@ mod refine_remove {
         // handles for prev/next knots
         index: usize,
         handles: [f64; 2],
+        solver: Option<Solver>,
     }
 
-    fn knot_remove_error_recalculate(
-        pd: &PointData,
-        heap: &mut min_heap::MinHeap<f64, KnotRemoveState>,
+    fn knot_remove_error_recalculate<R: CacheReal>(
+        pd: &PointData<R>,
+        heap: &mut min_heap::MinHeap<min_heap::ValueIndex, KnotRemoveState>,
         knots: &Vec<Knot>,
         knots_handle: &mut Vec<min_heap::NodeHandle>,
         k_curr: &Knot,
@@ -240,24 +618,25 @@ mod refine_remove {
     ) {
         debug_assert!(k_curr.no_remove == false);
 
-        let (fit_error_max_sq, handles) = {
+        let (fit_error_max_sq, handles, solver) = {
             let k_prev = &knots[k_curr.prev];
             let k_next = &knots[k_curr.next];
 
             knot_calc_curve_error_value(
                 pd, k_prev, k_next,
-                &pd.tangents[k_prev.tan[1]],
-                &pd.tangents[k_next.tan[0]])
+                &pd.tangent(k_prev.tan[1]),
+                &pd.tangent(k_next.tan[0]))
         };
 
         let k_curr_heap_node = &mut knots_handle[k_curr.index];
         if fit_error_max_sq < error_max_sq {
             heap.insert_or_update(
                 k_curr_heap_node,
-                fit_error_max_sq,
+                min_heap::value_with_index(fit_error_max_sq, k_curr.index, pd.use_deterministic),
                 KnotRemoveState {
                     index: k_curr.index,
                     handles: handles,
+                    solver: solver,
                 },
             );
         } else {
@@ -268,14 +647,16 @@ mod refine_remove {
         }
     }
 
-    pub fn curve_incremental_simplify(
-        pd: &PointData,
+    pub fn curve_incremental_simplify<R: CacheReal>(
+        pd: &PointData<R>,
         knots: &mut Vec<Knot>,
         knots_handle: &mut Vec<min_heap::NodeHandle>,
         knots_len_remaining: &mut usize,
         error_max_sq: f64,
+        mut refine_log: Option<&mut Vec<RefineStep>>,
     ) {
-        let mut heap = min_heap::MinHeap::<f64, KnotRemoveState>::with_capacity(knots.len());
+        let mut heap =
+            min_heap::MinHeap::<min_heap::ValueIndex, KnotRemoveState>::with_capacity(knots.len());
 
         for k_index in 0..knots.len() {
             let k_curr = &knots[k_index];
@@ -288,7 +669,7 @@ mod refine_remove {
             }
         }
 
-        while let Some((error_sq, r)) = heap.pop_min_with_value() {
+        while let Some(((error_sq, _), r)) = heap.pop_min_with_value() {
             knots_handle[r.index] = min_heap::NodeHandle::INVALID;
 
             let k_next_index;
@@ -316,7 +697,17 @@ mod refine_remove {
 
             debug_assert!(error_sq <= error_max_sq);
 
+            if let Some(ref mut refine_log) = refine_log {
+                refine_log.push(RefineStep {
+                    stage: RefineStage::Remove,
+                    co: pd.points[r.index],
+                    co_refit: None,
+                    error: error_sq.sqrt(),
+                });
+            }
+
             knots[k_prev_index].fit_error_sq_next = error_sq;
+            knots[k_prev_index].solver_next = r.solver;
             // Remove ourselves
             knots[k_next_index].prev = k_prev_index;
             knots[k_prev_index].next = k_next_index;
@@ -345,7 +736,11 @@ mod refine_remove {
 mod refine_refit {
 
     use super::{
+        CacheReal,
         INVALID,
+        RefineStage,
+        RefineStep,
+        Solver,
         USE_REFIT_REMOVE,
         knot_calc_curve_error_value,
         knot_calc_curve_error_value_and_index,
@@ -366,11 +761,12 @@ mod refine_refit {
         handle_pair: [[f64; 2]; 2],
 
         fit_error_max_sq_pair: [f64; 2],
+        solver_pair: [Option<Solver>; 2],
     }
 
-    fn knot_refit_error_recalculate(
-        pd: &PointData,
-        heap: &mut min_heap::MinHeap<f64, KnotRefitState>,
+    fn knot_refit_error_recalculate<R: CacheReal>(
+        pd: &PointData<R>,
+        heap: &mut min_heap::MinHeap<min_heap::ValueIndex, KnotRefitState>,
         knots: &Vec<Knot>,
         knots_handle: &mut Vec<min_heap::NodeHandle>,
         k_curr: &Knot,
@@ -389,11 +785,11 @@ mod refine_refit {
         // Support re-fitting to remove points
         {
 
-            let (fit_error_max_sq, fit_error_index, handles) =
+            let (fit_error_max_sq, fit_error_index, handles, solver) =
                 knot_calc_curve_error_value_and_index(
                     pd, k_prev, k_next,
-                    &pd.tangents[k_prev.tan[1]],
-                    &pd.tangents[k_next.tan[0]],
+                    &pd.tangent(k_prev.tan[1]),
+                    &pd.tangent(k_next.tan[0]),
                     );
 
             if USE_REFIT_REMOVE && fit_error_max_sq < error_max_sq {
@@ -401,7 +797,8 @@ mod refine_refit {
                 heap.insert_or_update(
                     k_curr_heap_node,
                     // Weight for the greatest improvement
-                    fit_error_max_sq - error_max_sq,
+                    min_heap::value_with_index(
+                        fit_error_max_sq - error_max_sq, k_curr.index, pd.use_deterministic),
                     KnotRefitState {
                         index: k_curr.index,
                         // INVALID == remove
@@ -409,6 +806,7 @@ mod refine_refit {
                         // 0.0 == unused
                         handle_pair: [[handles[0], 0.0], [0.0, handles[1]]],
                         fit_error_max_sq_pair: [fit_error_max_sq, fit_error_max_sq],
+                        solver_pair: [solver, solver],
                     }
                 );
                 return;
@@ -433,27 +831,27 @@ mod refine_refit {
         debug_assert!(cost_sq_src_max <= error_max_sq);
 
         // Specialized function to avoid duplicate code
-        fn knot_calc_curve_error_value_pair_above_error_or_none(
-            pd: &PointData, k_prev: &Knot, k_refit: &Knot, k_next: &Knot, error_max_sq: f64,
-        ) -> Option<([f64; 2], f64, [f64; 2], f64)> {
-            let (fit_error_prev, handles_prev) =
+        fn knot_calc_curve_error_value_pair_above_error_or_none<R: CacheReal>(
+            pd: &PointData<R>, k_prev: &Knot, k_refit: &Knot, k_next: &Knot, error_max_sq: f64,
+        ) -> Option<([f64; 2], f64, Option<Solver>, [f64; 2], f64, Option<Solver>)> {
+            let (fit_error_prev, handles_prev, solver_prev) =
                 knot_calc_curve_error_value(
                     pd, k_prev, k_refit,
-                    &pd.tangents[k_prev.tan[1]],
-                    &pd.tangents[k_refit.tan[0]],
+                    &pd.tangent(k_prev.tan[1]),
+                    &pd.tangent(k_refit.tan[0]),
                 );
 
             if fit_error_prev < error_max_sq {
-                let (fit_error_next, handles_next) =
+                let (fit_error_next, handles_next, solver_next) =
                     knot_calc_curve_error_value(
                         pd, k_refit, k_next,
-                        &pd.tangents[k_refit.tan[1]],
-                        &pd.tangents[k_next.tan[0]],
+                        &pd.tangent(k_refit.tan[1]),
+                        &pd.tangent(k_next.tan[0]),
                     );
                 if fit_error_next < error_max_sq {
                     return Some((
-                        handles_prev, fit_error_prev,
-                        handles_next, fit_error_next,
+                        handles_prev, fit_error_prev, solver_prev,
+                        handles_next, fit_error_next, solver_next,
                     ));
                 }
             }
@@ -467,39 +865,76 @@ mod refine_refit {
         // Nevertheless its interesting to have a way to attempt the best possible result.
 
         // cache result of 'knot_calc_curve_error_value_pair_above_error_or_none'
-        let mut refit_result_or_none: Option<([f64; 2], f64, [f64; 2], f64)> = None;
+        let mut refit_result_or_none:
+            Option<([f64; 2], f64, Option<Solver>, [f64; 2], f64, Option<Solver>)> = None;
 
         if use_optimize_exhaustive {
-
-            // loop over inner knots
-            let mut k_test_index = k_prev.index + 1;
+            let prune = pd.exhaustive_prune;
+
+            // Inner knots are tested by their offset from `k_prev` (in `1..offset_end`).
+            let knots_len = knots.len();
+            let offset_from_prev = |index: usize| (index + knots_len - k_prev.index) % knots_len;
+            let offset_end = match offset_from_prev(k_next.index) {
+                0 => knots_len,
+                offset => offset,
+            };
+
+            // Without pruning, test in order (starting after `k_prev`),
+            // otherwise test outwards from the point of greatest error,
+            // since the best knot is most likely to be nearby.
+            let offset_center = if prune.steps != 0 && k_refit_index != INVALID {
+                ::std::cmp::max(1, ::std::cmp::min(offset_from_prev(k_refit_index), offset_end - 1))
+            } else {
+                1
+            };
 
             // start with current state
             let mut cost_sq_best = cost_sq_src_max;
-
-            loop {
-                if k_test_index == knots.len() {
-                    k_test_index = 0;
-                }
-                if k_test_index == k_next.index {
-                    break;
+            // Consecutive candidates which didn't improve the error by `prune.epsilon`.
+            let mut prune_steps = 0;
+
+            for i in 0..(offset_end * 2) {
+                // Alternate either side of the center: `c, c + 1, c - 1, c + 2, ...`.
+                let offset = if (i & 1) == 0 {
+                    offset_center + (i / 2)
+                } else if offset_center > (i + 1) / 2 {
+                    offset_center - ((i + 1) / 2)
+                } else {
+                    continue;
+                };
+                if offset >= offset_end {
+                    continue;
                 }
+                let k_test_index = (k_prev.index + offset) % knots_len;
 
                 if k_test_index != k_curr.index {
+                    let mut improvement = 0.0;
                     if let Some(fit_result_test) =
                         knot_calc_curve_error_value_pair_above_error_or_none(
                             pd, k_prev, &knots[k_test_index], k_next, cost_sq_best)
                     {
                         let cost_sq_test_prev = fit_result_test.1;
-                        let cost_sq_test_next = fit_result_test.3;
-                        cost_sq_best = cost_sq_test_prev.max(cost_sq_test_next);
+                        let cost_sq_test_next = fit_result_test.4;
+                        let cost_sq_test = cost_sq_test_prev.max(cost_sq_test_next);
+                        improvement = cost_sq_best.sqrt() - cost_sq_test.sqrt();
+                        cost_sq_best = cost_sq_test;
                         k_refit_index = k_test_index;
 
                         // Result for re-use if this is the best fit.
                         refit_result_or_none = Some(fit_result_test);
                     }
+
+                    if prune.steps != 0 {
+                        if improvement < prune.epsilon {
+                            prune_steps += 1;
+                            if prune_steps == prune.steps {
+                                break;
+                            }
+                        } else {
+                            prune_steps = 0;
+                        }
+                    }
                 }
-                k_test_index += 1;
             }
         } else {
             refit_result_or_none =
@@ -509,8 +944,8 @@ mod refine_refit {
         // end exhaustive test
 
         if let Some((
-            handles_prev, fit_error_dst_prev,
-            handles_next, fit_error_dst_next,
+            handles_prev, fit_error_dst_prev, solver_prev,
+            handles_next, fit_error_dst_next, solver_next,
         )) = refit_result_or_none {
             let fit_error_dst_max_sq =
                 fit_error_dst_prev.max(fit_error_dst_next);
@@ -518,12 +953,14 @@ mod refine_refit {
             heap.insert_or_update(
                 k_curr_heap_node,
                 // Weight for the greatest improvement.
-                cost_sq_src_max - fit_error_dst_max_sq,
+                min_heap::value_with_index(
+                    cost_sq_src_max - fit_error_dst_max_sq, k_curr.index, pd.use_deterministic),
                 KnotRefitState {
                     index: k_curr.index,
                     index_refit: k_refit_index,
                     handle_pair: [handles_prev, handles_next],
                     fit_error_max_sq_pair: [fit_error_dst_prev, fit_error_dst_next],
+                    solver_pair: [solver_prev, solver_next],
                 }
             );
             return;
@@ -535,16 +972,18 @@ mod refine_refit {
         }
     }
 
-    pub fn curve_incremental_simplify_refit(
-        pd: &PointData,
+    pub fn curve_incremental_simplify_refit<R: CacheReal>(
+        pd: &PointData<R>,
         knots: &mut Vec<Knot>,
         knots_handle: &mut Vec<min_heap::NodeHandle>,
         knots_len_remaining: &mut usize,
         error_max_sq: f64,
         use_optimize_exhaustive: bool,
+        mut refine_log: Option<&mut Vec<RefineStep>>,
     ) {
         let mut heap =
-            min_heap::MinHeap::<f64, KnotRefitState>::with_capacity(*knots_len_remaining);
+            min_heap::MinHeap::<min_heap::ValueIndex, KnotRefitState>::with_capacity(
+                *knots_len_remaining);
 
         for k_index in 0..knots.len() {
             let k_curr = &knots[k_index];
@@ -589,6 +1028,19 @@ mod refine_refit {
                 continue;
             }
 
+            if let Some(ref mut refine_log) = refine_log {
+                refine_log.push(RefineStep {
+                    stage: RefineStage::Refit,
+                    co: pd.points[r.index],
+                    co_refit: if r.index_refit == INVALID {
+                        None
+                    } else {
+                        Some(pd.points[r.index_refit])
+                    },
+                    error: r.fit_error_max_sq_pair[0].max(r.fit_error_max_sq_pair[1]).sqrt(),
+                });
+            }
+
             {
                 let k_old = &mut knots[r.index];
                 k_old.next = INVALID;
@@ -601,6 +1053,7 @@ mod refine_refit {
                 knots[k_prev_index].next = k_next_index;
 
                 knots[k_prev_index].fit_error_sq_next = r.fit_error_max_sq_pair[0];
+                knots[k_prev_index].solver_next = r.solver_pair[0];
 
                 *knots_len_remaining -= 1;
             } else {
@@ -609,12 +1062,14 @@ mod refine_refit {
                 knots[k_prev_index].next = r.index_refit;
 
                 knots[k_prev_index].fit_error_sq_next = r.fit_error_max_sq_pair[0];
+                knots[k_prev_index].solver_next = r.solver_pair[0];
 
                 let k_refit = &mut knots[r.index_refit];
                 k_refit.prev = k_prev_index;
                 k_refit.next = k_next_index;
 
                 k_refit.fit_error_sq_next = r.fit_error_max_sq_pair[1];
+                k_refit.solver_next = r.solver_pair[1];
 
                 k_refit.is_remove = false;
             }
@@ -640,7 +1095,9 @@ mod refine_refit {
 
 mod refine_corner {
     use super::{
+        CacheReal,
         INVALID,
+        Solver,
         knot_calc_curve_error_value,
         knot_find_split_point_on_axis,
     };
@@ -667,12 +1124,13 @@ mod refine_corner {
         handle_pair: [[f64; 2]; 2],
 
         fit_error_max_sq_pair: [f64; 2],
+        solver_pair: [Option<Solver>; 2],
     }
 
     /// (Re)calculate the error incurred from turning this into a corner.
-    fn knot_corner_error_recalculate(
-        pd: &PointData,
-        heap: &mut min_heap::MinHeap<f64, KnotCornerState>,
+    fn knot_corner_error_recalculate<R: CacheReal>(
+        pd: &PointData<R>,
+        heap: &mut min_heap::MinHeap<min_heap::ValueIndex, KnotCornerState>,
         knots_handle: &mut Vec<min_heap::NodeHandle>,
         k_split: &Knot,
         k_prev: &Knot,
@@ -688,31 +1146,35 @@ mod refine_corner {
 
         // Test skipping 'k_prev' by using points (k_prev.prev to k_split).
         {
-            let (fit_error_dst_prev, handles_prev) =
+            let (fit_error_dst_prev, handles_prev, solver_prev) =
                 knot_calc_curve_error_value(
                     pd, k_prev, k_split,
-                    &pd.tangents[k_prev.tan[1]],
-                    &pd.tangents[k_prev.tan[1]],
+                    &pd.tangent(k_prev.tan[1]),
+                    &pd.tangent(k_prev.tan[1]),
                     );
             if fit_error_dst_prev < error_max_sq {
-                let (fit_error_dst_next, handles_next) =
+                let (fit_error_dst_next, handles_next, solver_next) =
                     knot_calc_curve_error_value(
                         pd, k_split, k_next,
-                        &pd.tangents[k_next.tan[0]],
-                        &pd.tangents[k_next.tan[0]],
+                        &pd.tangent(k_next.tan[0]),
+                        &pd.tangent(k_next.tan[0]),
                         );
                 if fit_error_dst_next < error_max_sq {
                     // _must_ be assigned to k_split, later
                     heap.insert_or_update(
                         k_split_heap_node,
                         // Weight for the greatest improvement.
-                        fit_error_dst_prev.max(fit_error_dst_next),
+                        min_heap::value_with_index(
+                            fit_error_dst_prev.max(fit_error_dst_next),
+                            k_split.index,
+                            pd.use_deterministic),
                         KnotCornerState {
                             index: k_split.index,
                             // Need to store handle lengths for both sides
                             index_pair: [k_prev.index, k_next.index],
                             handle_pair: [handles_prev, handles_next],
                             fit_error_max_sq_pair: [fit_error_dst_prev, fit_error_dst_next],
+                            solver_pair: [solver_prev, solver_next],
                         }
                     );
 
@@ -729,8 +1191,8 @@ mod refine_corner {
 
     // Attempt to collapse close knots into corners,
     // as long as they fall below the error threshold.
-    pub fn curve_incremental_simplify_corners(
-        pd: &PointData,
+    pub fn curve_incremental_simplify_corners<R: CacheReal>(
+        pd: &PointData<R>,
         knots: &mut Vec<Knot>,
         knots_handle: &mut Vec<min_heap::NodeHandle>,
         knots_len_remaining: &mut usize,
@@ -739,7 +1201,7 @@ mod refine_corner {
         corner_angle: f64,
     ) {
         // don't pre-allocate, since its likely there are no corners
-        let mut heap = min_heap::MinHeap::<f64, KnotCornerState>::with_capacity(0);
+        let mut heap = min_heap::MinHeap::<min_heap::ValueIndex, KnotCornerState>::with_capacity(0);
 
         let corner_angle_cos = corner_angle.cos();
 
@@ -760,14 +1222,14 @@ mod refine_corner {
             {
                 // Angle outside threshold
                 if dot_vnvn(
-                    &pd.tangents[k_prev.tan[0]],
-                    &pd.tangents[k_next.tan[1]]) < corner_angle_cos
+                    &pd.tangent(k_prev.tan[0]),
+                    &pd.tangent(k_next.tan[1])) < corner_angle_cos
                 {
                     // Measure distance projected onto a plane,
                     //since the points may be offset along their own tangents.
                     let plane_no = sub_vnvn(
-                        &pd.tangents[k_next.tan[0]],
-                        &pd.tangents[k_prev.tan[1]],
+                        &pd.tangent(k_next.tan[0]),
+                        &pd.tangent(k_prev.tan[1]),
                         );
 
                     // Compare 2x so as to allow both to be changed
@@ -786,17 +1248,17 @@ mod refine_corner {
                         let co_split = &pd.points[k_split_index];
 
                         let k_proj_ref = project_vnvn_normalized(
-                            co_prev, &pd.tangents[k_prev.tan[1]]);
+                            co_prev, &pd.tangent(k_prev.tan[1]));
                         let k_proj_split = project_vnvn_normalized(
-                            co_split, &pd.tangents[k_prev.tan[1]]);
+                            co_split, &pd.tangent(k_prev.tan[1]));
 
                         if len_squared_vnvn(
                             &k_proj_ref, &k_proj_split) < error_sq_collapse_max
                         {
                             let k_proj_ref = project_vnvn_normalized(
-                                co_next, &pd.tangents[k_next.tan[0]]);
+                                co_next, &pd.tangent(k_next.tan[0]));
                             let k_proj_split = project_vnvn_normalized(
-                                co_split, &pd.tangents[k_next.tan[0]]);
+                                co_split, &pd.tangent(k_next.tan[0]));
 
                             if len_squared_vnvn(
                                 &k_proj_ref, &k_proj_split) < error_sq_collapse_max
@@ -836,6 +1298,7 @@ mod refine_corner {
 
                 debug_assert!(c.fit_error_max_sq_pair[0] <= error_max_sq);
                 k_prev.fit_error_sq_next = c.fit_error_max_sq_pair[0];
+                k_prev.solver_next = c.solver_pair[0];
             }
 
             {
@@ -867,6 +1330,7 @@ mod refine_corner {
 
                 debug_assert!(c.fit_error_max_sq_pair[1] <= error_max_sq);
                 k_split.fit_error_sq_next = c.fit_error_max_sq_pair[1];
+                k_split.solver_next = c.solver_pair[1];
             }
 
             *knots_len_remaining += 1;
@@ -880,6 +1344,60 @@ mod refine_corner {
 // end refine_corner
 
 
+/// Polygons too small to fit (less than 3 points or no length),
+/// fitting asserts at least 3 points, so use straight segments instead.
+fn poly_is_degenerate(
+    points: &[[f64; DIMS]],
+) -> bool {
+    if points.len() < 3 {
+        return true;
+    }
+    return points.iter().all(|p| *p == points[0]);
+}
+
+/// Straight segments between `points`, handles are placed a third of the way to their neighbors.
+/// Coincident points are collapsed into a single knot.
+fn poly_as_lines(
+    points: &[[f64; DIMS]],
+    is_cyclic: bool,
+) -> Vec<[[f64; DIMS]; 3]> {
+    use ::intern::math_vector::{
+        interp_vnvn,
+    };
+
+    let points = if points.iter().all(|p| *p == points[0]) {
+        &points[..::std::cmp::min(points.len(), 1)]
+    } else {
+        points
+    };
+    let points_len = points.len();
+    let mut cubic_array: Vec<[[f64; DIMS]; 3]> = Vec::with_capacity(points_len);
+    for i in 0..points_len {
+        let p = &points[i];
+        let p_prev = if i != 0 {
+            Some(&points[i - 1])
+        } else if is_cyclic {
+            Some(&points[points_len - 1])
+        } else {
+            None
+        };
+        let p_next = if i + 1 != points_len {
+            Some(&points[i + 1])
+        } else if is_cyclic {
+            Some(&points[0])
+        } else {
+            None
+        };
+        cubic_array.push([
+            p_prev.map_or(*p, |p_prev| interp_vnvn(p, p_prev, 1.0 / 3.0)),
+            *p,
+            p_next.map_or(*p, |p_next| interp_vnvn(p, p_next, 1.0 / 3.0)),
+        ]);
+    }
+    return cubic_array;
+}
+
+#[allow(dead_code)]
 pub fn fit_poly_single(
     // points_orig: &[[f64; 2]],
     points_orig: &Vec<[f64; DIMS]>,
@@ -887,29 +1405,236 @@ pub fn fit_poly_single(
     error_threshold: f64,
     corner_angle: f64,
     use_optimize_exhaustive: bool,
+    end_tangent: EndTangent,
+) -> Vec<[[f64; DIMS]; 3]> {
+    let params = FitParams {
+        error_threshold: error_threshold,
+        corner_angle: corner_angle,
+        use_optimize_exhaustive: use_optimize_exhaustive,
+        end_tangent: end_tangent,
+        .. Default::default()
+    };
+    return fit_poly_single_with_params(
+        points_orig, is_cyclic, &params, None, None, None, &[], &mut FitArena::new());
+}
+
+/// Fit a single polygon, see: `fit_poly_single`.
+///
+/// * `params` - settings for fitting, see: `FitParams`.
+/// * `refine_log` - when set, knot removals are logged in the order they're performed.
+/// * `snapshots` - when set, the knots remaining after each refinement pass are appended,
+///   see: `FitSnapshot`.
+/// * `weights` - when set, the weight of each point,
+///   higher weights prioritize matching points (corners for example) when fitting.
+/// * `anchors` - indices of points which are always kept as knots.
+/// * `arena` - memory to use while fitting, see: `FitArena`.
+pub fn fit_poly_single_with_params(
+    points_orig: &Vec<[f64; DIMS]>,
+    is_cyclic: bool,
+    params: &FitParams,
+    mut refine_log: Option<&mut Vec<RefineStep>>,
+    mut snapshots: Option<&mut Vec<FitSnapshot>>,
+    weights: Option<&Vec<f64>>,
+    anchors: &[usize],
+    arena: &mut FitArena,
 ) -> Vec<[[f64; DIMS]; 3]> {
+    if poly_is_degenerate(points_orig) {
+        return poly_as_lines(points_orig, is_cyclic);
+    }
+
+    let error_threshold = params.error_threshold_for_poly(points_orig);
+    let mut cubic_array = None;
+    if params.use_precision_f32 {
+        let refine_log_len = refine_log.as_ref().map_or(0, |l| l.len());
+        let snapshots_len = snapshots.as_ref().map_or(0, |l| l.len());
+        let mut caches = ::std::mem::take(&mut arena.caches_f32);
+        cubic_array = fit_poly_single_with_caches(
+            points_orig, is_cyclic, params, error_threshold,
+            refine_log.as_mut().map(|l| &mut **l),
+            snapshots.as_mut().map(|l| &mut **l),
+            weights, anchors, true, &mut caches, arena);
+        arena.caches_f32 = caches;
+        // Discard removals & snapshots logged by the `f32` fit.
+        if cubic_array.is_none() {
+            if let Some(ref mut l) = refine_log {
+                l.truncate(refine_log_len);
+            }
+            if let Some(ref mut l) = snapshots {
+                l.truncate(snapshots_len);
+            }
+        }
+    }
+    let cubic_array = match cubic_array {
+        Some(cubic_array) => cubic_array,
+        None => {
+            let mut caches = ::std::mem::take(&mut arena.caches);
+            let cubic_array = fit_poly_single_with_caches(
+                points_orig, is_cyclic, params, error_threshold, refine_log, snapshots,
+                weights, anchors, false, &mut caches, arena).unwrap();
+            arena.caches = caches;
+            cubic_array
+        }
+    };
+
+    arena.bytes_peak = ::std::cmp::max(arena.bytes_peak, arena.bytes());
+
+    return cubic_array;
+}
+
+/// Store `f64` tangents in the precision used for fitting.
+fn tangent_store<R: CacheReal>(
+    t_dst: &mut [R; DIMS],
+    t_src: &[f64; DIMS],
+) {
+    for j in 0..DIMS {
+        t_dst[j] = R::from_f64(t_src[j]);
+    }
+}
+
+/// Replace the tangent of each knot with the average direction of the polygon
+/// within `length` (measured along the polygon), weighted to fall off linearly with distance.
+///
+/// Unlike the tangents of each knot, this accounts for the length of segments,
+/// so stair-steps average to the direction of the line they approximate.
+/// Averaging stops at segments which differ from the knot's tangent by more than
+/// `corner_angle`, so tangents aren't averaged across corners.
+/// The end-points of open polygons are left as-is, see: `EndTangent`.
+fn tangents_smooth<R: CacheReal>(
+    tangents: &mut [[R; DIMS]],
+    points: &[[f64; DIMS]],
+    points_length_cache: &[R],
+    is_cyclic: bool,
+    length: f64,
+    corner_angle: f64,
+) {
+    let knots_len = points.len();
+    let corner_dot = corner_angle.cos();
+
+    // Cyclic polygons don't walk past the opposite side, so no segment is included twice.
+    let steps_max = if is_cyclic { knots_len / 2 } else { knots_len };
+    let knots_range = if is_cyclic { 0..knots_len } else { 1..(knots_len - 1) };
+    for i in knots_range {
+        // Both tangents match, corners are only split after smoothing.
+        let mut t_orig = [0.0; DIMS];
+        for j in 0..DIMS {
+            t_orig[j] = tangents[i * 2][j].to_f64();
+        }
+        let mut t = [0.0; DIMS];
+        for &forward in &[false, true] {
+            let mut i_curr = i;
+            let mut len_accum = 0.0;
+            for _ in 0..steps_max {
+                let i_next = if forward {
+                    if i_curr + 1 != knots_len {
+                        i_curr + 1
+                    } else if is_cyclic {
+                        0
+                    } else {
+                        break;
+                    }
+                } else {
+                    if i_curr != 0 {
+                        i_curr - 1
+                    } else if is_cyclic {
+                        knots_len - 1
+                    } else {
+                        break;
+                    }
+                };
+                // The segment as `prev - next` (matching the natural tangents),
+                // the length cache stores the length to each point from the previous point.
+                let (seg, seg_len) = if forward {
+                    (sub_vnvn(&points[i_curr], &points[i_next]),
+                     points_length_cache[i_next].to_f64())
+                } else {
+                    (sub_vnvn(&points[i_next], &points[i_curr]),
+                     points_length_cache[i_curr].to_f64())
+                };
+                // Weight by the distance to the middle of the segment.
+                let weight = 1.0 - ((len_accum + (seg_len / 2.0)) / length);
+                if weight <= 0.0 {
+                    break;
+                }
+                if seg_len != 0.0 && dot_vnvn(&t_orig, &seg) < corner_dot * seg_len {
+                    break;
+                }
+                t = madd_vnvn_fl(&t, &seg, weight);
+                len_accum += seg_len;
+                i_curr = i_next;
+            }
+        }
+        // When no segments are within `length` (or they cancel out), keep the original.
+        if dot_vnvn(&t, &t) != 0.0 {
+            normalize_vn(&mut t);
+            tangent_store(&mut tangents[i * 2], &t);
+            tangent_store(&mut tangents[i * 2 + 1], &t);
+        }
+    }
+}
+
+/// The knots which haven't been removed, in order, see: `FitSnapshot`.
+fn knots_snapshot(
+    knots: &[Knot],
+    points: &[[f64; DIMS]],
+    knots_len_remaining: usize,
+    stage: FitStage,
+) -> FitSnapshot {
+    let mut snapshot = FitSnapshot {
+        stage: stage,
+        knots: Vec::with_capacity(knots_len_remaining),
+    };
+    if let Some(mut k_index) = knots.iter().position(|k| k.is_remove == false) {
+        for _ in 0..knots_len_remaining {
+            let k = &knots[k_index];
+            snapshot.knots.push(KnotSnapshot {
+                co: points[k.index],
+                is_corner: k.is_corner,
+            });
+            k_index = k.next;
+        }
+    }
+    return snapshot;
+}
+
+/// Fit a single polygon using `caches` to store point lengths & tangents,
+/// see: `fit_poly_single_with_params`.
+///
+/// * `error_threshold` - used instead of `params.error_threshold`,
+///   see: `FitParams::error_threshold_for_poly`.
+/// * `use_verify` - measure each segment against the points,
+///   returning None when any exceed `error_threshold` (for caches which lose precision).
+fn fit_poly_single_with_caches<R: CacheReal>(
+    points_orig: &Vec<[f64; DIMS]>,
+    is_cyclic: bool,
+    params: &FitParams,
+    error_threshold: f64,
+    mut refine_log: Option<&mut Vec<RefineStep>>,
+    mut snapshots: Option<&mut Vec<FitSnapshot>>,
+    weights: Option<&Vec<f64>>,
+    anchors: &[usize],
+    use_verify: bool,
+    caches: &mut FitCaches<R>,
+    arena: &mut FitArena,
+) -> Option<Vec<[[f64; DIMS]; 3]>> {
     use ::intern::math_vector::{
         is_finite_vn,
     };
 
-    // Double size to allow extracting wrapped contiguous slices across start/end boundaries.
+    // Segments wrapping past the end of cyclic polygons use `WrapSlice`,
+    // so the points don't need to be copied.
     let knots_len = points_orig.len();
     let points_len = points_orig.len();
-    let points = if is_cyclic {
-        [points_orig.as_slice(), points_orig.as_slice()].concat()
-    } else {
-        // TODO, we don't need to duplicate here,
-        // find a way to use the original array!
-        [points_orig.as_slice()].concat()
-    };
-
-    // del_var!(points_orig);  // TODO
+    let points: &[[f64; DIMS]] = points_orig;
 
-    let mut knots: Vec<Knot> =
-        Vec::with_capacity(knots_len);
-    let mut knots_handle: Vec<min_heap::NodeHandle> =
-        vec![min_heap::NodeHandle::INVALID; knots_len];
+    let knots = &mut arena.knots;
+    knots.clear();
+    knots.reserve(knots_len);
+    let knots_handle = &mut arena.knots_handle;
+    knots_handle.clear();
+    knots_handle.resize(knots_len, min_heap::NodeHandle::INVALID);
 
+    let corner_angle = params.corner_angle;
+    let reparameterize = params.reparameterize;
     let use_corner = corner_angle < ::std::f64::consts::PI;
 
     for i in 0..knots_len {
@@ -923,6 +1648,7 @@ pub fn fit_poly_single(
             is_corner: false,
             handles: [-1.0, -1.0], // dummy
             fit_error_sq_next: 0.0,
+            solver_next: None,
             tan: [i * 2, i * 2 + 1],
         });
     }
@@ -939,21 +1665,28 @@ pub fn fit_poly_single(
         knots[0].no_remove = true;
         knots[i_last].no_remove = true;
     }
+    for &i in anchors {
+        knots[i].no_remove = true;
+    }
 
     // All values will be written to, simplest to initialize to dummy values for now.
-    let mut points_length_cache: Vec<f64> = vec![-1.0; points_len * if is_cyclic { 2 } else { 1 }];
-    let mut tangents: Vec<[f64; DIMS]> = vec![[-1.0; DIMS]; knots_len * 2];
+    let points_length_cache = &mut caches.points_length_cache;
+    points_length_cache.clear();
+    points_length_cache.resize(points_len, R::from_f64(-1.0));
+    let tangents = &mut caches.tangents;
+    tangents.clear();
+    tangents.resize(knots_len * 2, [R::from_f64(-1.0); DIMS]);
 
     // Initialize tangents,
     // also set the values for knot handles since some may not collapse.
 
     if knots_len < 2 {
-        for (i, k) in (&mut knots).iter_mut().enumerate() {
-            zero_vn(&mut tangents[k.tan[0]]);
-            zero_vn(&mut tangents[k.tan[1]]);
+        for (i, k) in knots.iter_mut().enumerate() {
+            tangent_store(&mut tangents[k.tan[0]], &[0.0; DIMS]);
+            tangent_store(&mut tangents[k.tan[1]], &[0.0; DIMS]);
             k.handles[0] = 0.0;
             k.handles[1] = 0.0;
-            points_length_cache[i] = 0.0;
+            points_length_cache[i] = R::from_f64(0.0);
         }
     } else if is_cyclic {
         let (mut tan_prev, mut len_prev) = normalized_vnvn_with_len(
@@ -965,13 +1698,13 @@ pub fn fit_poly_single(
 
             let (tan_next, len_next) = normalized_vnvn_with_len(
                 &points[i_curr], &points[i_next]);
-            points_length_cache[i_next] = len_next;
+            points_length_cache[i_next] = R::from_f64(len_next);
 
             let mut t = add_vnvn(&tan_prev, &tan_next);
             normalize_vn(&mut t);
             assert!(is_finite_vn(&t));
-            copy_vnvn(&mut tangents[k.tan[0]], &t);
-            copy_vnvn(&mut tangents[k.tan[1]], &t);
+            tangent_store(&mut tangents[k.tan[0]], &t);
+            tangent_store(&mut tangents[k.tan[1]], &t);
 
             k.handles[0] = len_prev /  3.0;
             k.handles[1] = len_next / -3.0;
@@ -981,13 +1714,13 @@ pub fn fit_poly_single(
             i_curr = i_next;
         }
     } else {
-        points_length_cache[0] = 0.0;
+        points_length_cache[0] = R::from_f64(0.0);
         let (mut tan_prev, mut len_prev) = normalized_vnvn_with_len(
             &points[0], &points[1]);
-        points_length_cache[1] = len_prev;
+        points_length_cache[1] = R::from_f64(len_prev);
 
-        copy_vnvn(&mut tangents[knots[0].tan[0]], &tan_prev);
-        copy_vnvn(&mut tangents[knots[0].tan[1]], &tan_prev);
+        tangent_store(&mut tangents[knots[0].tan[0]], &tan_prev);
+        tangent_store(&mut tangents[knots[0].tan[1]], &tan_prev);
         knots[0].handles[0] = len_prev /  3.0;
         knots[0].handles[1] = len_prev / -3.0;
 
@@ -996,13 +1729,13 @@ pub fn fit_poly_single(
             let k = &mut knots[i_curr];
             let (tan_next, len_next) = normalized_vnvn_with_len(
                 &points[i_curr], &points[i_next]);
-            points_length_cache[i_next] = len_next;
+            points_length_cache[i_next] = R::from_f64(len_next);
 
             let mut t = add_vnvn(&tan_prev, &tan_next);
             normalize_vn(&mut t);
             assert!(is_finite_vn(&t));
-            copy_vnvn(&mut tangents[k.tan[0]], &t);
-            copy_vnvn(&mut tangents[k.tan[1]], &t);
+            tangent_store(&mut tangents[k.tan[0]], &t);
+            tangent_store(&mut tangents[k.tan[1]], &t);
 
             k.handles[0] = len_prev /  3.0;
             k.handles[1] = len_next / -3.0;
@@ -1012,67 +1745,102 @@ pub fn fit_poly_single(
             i_curr = i_next;
         }
         // use prev as next since they're copied above
-        copy_vnvn(&mut tangents[knots[knots_len - 1].tan[0]], &tan_prev);
-        copy_vnvn(&mut tangents[knots[knots_len - 1].tan[1]], &tan_prev);
+        tangent_store(&mut tangents[knots[knots_len - 1].tan[0]], &tan_prev);
+        tangent_store(&mut tangents[knots[knots_len - 1].tan[1]], &tan_prev);
 
         knots[knots_len - 1].handles[0] = len_prev /  3.0;
         knots[knots_len - 1].handles[1] = len_prev / -3.0;
-    }
 
-    if is_cyclic {
-        // TODO, perhaps this can be done more elegantly?
-        for i in 0..points_len {
-            points_length_cache[i + points_len] = points_length_cache[i];
+        if params.end_tangent == EndTangent::Clamped {
+            // Tangents are `prev - next`, matching the natural tangents.
+            let i_end = ::std::cmp::min(END_TANGENT_CLAMP_POINTS, knots_len - 1);
+            for &(i_knot, i_prev, i_next) in &[
+                (0, 0, i_end),
+                (knots_len - 1, knots_len - 1 - i_end, knots_len - 1),
+            ] {
+                let t = normalized_vnvn(&points[i_prev], &points[i_next]);
+                // Zero length (overlapping points), keep the natural tangent.
+                if is_finite_vn(&t) && dot_vnvn(&t, &t) != 0.0 {
+                    tangent_store(&mut tangents[knots[i_knot].tan[0]], &t);
+                    tangent_store(&mut tangents[knots[i_knot].tan[1]], &t);
+                }
+            }
         }
     }
 
+    if params.tangent_smooth != 0.0 && knots_len > 2 {
+        tangents_smooth(
+            tangents, points, points_length_cache, is_cyclic, params.tangent_smooth, corner_angle);
+    }
+
+    let weights = weights.map(|weights_src| {
+        debug_assert!(weights_src.len() == points_len);
+        &weights_src[..]
+    });
+
     let mut knots_len_remaining = knots.len();
     let pd = PointData {
-        points: &points,
+        points: points,
         points_len: points_len,
-        points_length_cache: &points_length_cache,
-        tangents: &tangents,
+        points_length_cache: points_length_cache,
+        weights: weights,
+        tangents: tangents,
+        use_deterministic: params.use_deterministic,
+        max_handle_ratio: params.max_handle_ratio,
+        solvers: &params.solvers,
+        reparameterize: reparameterize,
+        exhaustive_prune: params.exhaustive_prune,
+        fit_scratch: &arena.fit_scratch,
     };
 
     // `curve_incremental_simplify_refit` can be called here, but its very slow
     // just remove all within the threshold first.
     refine_remove::curve_incremental_simplify(
-        &pd, &mut knots, &mut knots_handle, &mut knots_len_remaining,
-        sq(error_threshold));
+        &pd, knots, knots_handle, &mut knots_len_remaining,
+        sq(error_threshold), refine_log.as_mut().map(|l| &mut **l));
+
+    if let Some(ref mut snapshots) = snapshots {
+        snapshots.push(knots_snapshot(knots, points, knots_len_remaining, FitStage::PostRemove));
+    }
 
     if use_corner {
         refine_corner::curve_incremental_simplify_corners(
-            &pd, &mut knots, &mut knots_handle, &mut knots_len_remaining,
+            &pd, knots, knots_handle, &mut knots_len_remaining,
             sq(error_threshold), sq(error_threshold * CORNER_SCALE),
             corner_angle,
             );
+
+        if let Some(ref mut snapshots) = snapshots {
+            snapshots.push(
+                knots_snapshot(knots, points, knots_len_remaining, FitStage::PostCorner));
+        }
     }
 
     debug_assert!(knots_len_remaining >= 2);
 
     if USE_REFIT {
         refine_refit::curve_incremental_simplify_refit(
-            &pd, &mut knots, &mut knots_handle, &mut knots_len_remaining,
-            sq(error_threshold), use_optimize_exhaustive);
+            &pd, knots, knots_handle, &mut knots_len_remaining,
+            sq(error_threshold), params.use_optimize_exhaustive, refine_log);
     }
 
     debug_assert!(knots_len_remaining >= 2);
 
     let mut cubic_array: Vec<[[f64; DIMS]; 3]> = Vec::with_capacity(knots_len_remaining);
 
-    {
-        let k_first_index: usize = {
-            let mut i_search = INVALID;
-            for (i, k) in knots.iter().enumerate() {
-                if k.is_remove == false {
-                    i_search = i;
-                    break;
-                }
+    let k_first_index: usize = {
+        let mut i_search = INVALID;
+        for (i, k) in knots.iter().enumerate() {
+            if k.is_remove == false {
+                i_search = i;
+                break;
             }
-            debug_assert!(i_search != INVALID);
-            i_search
-        };
+        }
+        debug_assert!(i_search != INVALID);
+        i_search
+    };
 
+    {
         let mut k_index = k_first_index;
         for _ in 0..knots_len_remaining {
             let k = &knots[k_index];
@@ -1082,65 +1850,229 @@ pub fn fit_poly_single(
             // assert!(k.handles[1].is_finite());
 
             cubic_array.push([
-                madd_vnvn_fl(p, &tangents[k.tan[0]], k.handles[0]),
+                madd_vnvn_fl(p, &pd.tangent(k.tan[0]), k.handles[0]),
                 *p,
-                madd_vnvn_fl(p, &tangents[k.tan[1]], k.handles[1]),
+                madd_vnvn_fl(p, &pd.tangent(k.tan[1]), k.handles[1]),
             ]);
 
             k_index = k.next;
         }
     }
 
-    return cubic_array;
+    if use_verify {
+        let fit_scratch = &mut pd.fit_scratch.borrow_mut();
+        let mut k_index = k_first_index;
+        for i in 0..cubic_array.len() {
+            let k = &knots[k_index];
+            if k.next == INVALID {
+                break;
+            }
+            let k_next = &knots[k.next];
+            let points_offset_len =
+                if k.index < k_next.index {
+                    k_next.index - k.index
+                } else {
+                    (k_next.index + points_len) - k.index
+                } + 1;
+            let i_next = if i + 1 != cubic_array.len() { i + 1 } else { 0 };
+            let cubic = Cubic {
+                p0: cubic_array[i][1],
+                p1: cubic_array[i][2],
+                p2: cubic_array[i_next][0],
+                p3: cubic_array[i_next][1],
+            };
+            let error_sq = curve_fit_single::cubic_calc_error_sq_wrap(
+                &cubic, WrapSlice::from_range(points, k.index, points_offset_len),
+                reparameterize, fit_scratch);
+            if error_sq > sq(error_threshold) {
+                return None;
+            }
+            k_index = k.next;
+        }
+    }
+
+    for k in knots.iter() {
+        if k.is_remove == false {
+            if let Some(solver) = k.solver_next {
+                arena.solver_segments_len[solver as usize] += 1;
+            }
+        }
+    }
+
+    if !is_cyclic && params.end_tangent == EndTangent::Zero {
+        for i in &[0, cubic_array.len() - 1] {
+            let c = &mut cubic_array[*i];
+            c[0] = c[1];
+            c[2] = c[1];
+        }
+    }
+
+    return Some(cubic_array);
 }
 
 
+/// Curves are returned in the same order as `poly_list_src`.
+///
+/// * `error_relative` - when set, `error_threshold` is calculated for each polygon.
+#[allow(dead_code)]
 pub fn fit_poly_list(
     poly_list_src: LinkedList<(bool, Vec<[f64; DIMS]>)>,
     error_threshold: f64,
+    error_relative: Option<ErrorRelative>,
     corner_angle: f64,
     use_optimize_exhaustive: bool,
+    end_tangent: EndTangent,
 ) -> LinkedList<(bool, Vec<[[f64; DIMS]; 3]>)> {
+    let params = FitParams {
+        error_threshold: error_threshold,
+        error_relative: error_relative,
+        corner_angle: corner_angle,
+        use_optimize_exhaustive: use_optimize_exhaustive,
+        end_tangent: end_tangent,
+        .. Default::default()
+    };
+    return fit_poly_list_with_params(poly_list_src, &params, None, None, None, None).0;
+}
+
+/// Fit polygons, see: `fit_poly_list`.
+///
+/// * `params` - settings for fitting, see: `FitParams`,
+///   when `use_refine_log` is set, also return the knot removals for each polygon.
+/// * `weight_list` - when set, weights for the points of each polygon,
+///   see: `fit_poly_single_with_params`.
+/// * `anchor_list` - when set, anchors for each polygon,
+///   see: `fit_poly_single_with_params`.
+/// * `error_eval` - when set, measures the error of each fit, see: `ErrorEval`.
+/// * `observer` - when set, receives the knots remaining after each refinement pass
+///   of each polygon, see: `FitObserver`.
+pub fn fit_poly_list_with_params(
+    poly_list_src: LinkedList<(bool, Vec<[f64; DIMS]>)>,
+    params: &FitParams,
+    weight_list: Option<&[Vec<f64>]>,
+    anchor_list: Option<&[Vec<usize>]>,
+    error_eval: Option<Arc<dyn ErrorEval>>,
+    observer: Option<&dyn FitObserver>,
+) -> (LinkedList<(bool, Vec<[[f64; DIMS]; 3]>)>, Vec<Vec<RefineStep>>, FitStats) {
     let mut curve_list_dst: LinkedList<(bool, Vec<[[f64; DIMS]; 3]>)> = LinkedList::new();
+    let mut refine_log_dst: Vec<Vec<RefineStep>> = vec![];
+    let mut stats: FitStats = Default::default();
+
+    let fit_poly = |index: usize,
+                    is_cyclic: bool,
+                    poly_src: &Vec<[f64; DIMS]>,
+                    arena: &mut FitArena| {
+        let mut refine_log = vec![];
+        let mut snapshots = vec![];
+        let poly_dst = fit_poly_single_with_params(
+            poly_src, is_cyclic, params,
+            if params.use_refine_log { Some(&mut refine_log) } else { None },
+            if observer.is_some() { Some(&mut snapshots) } else { None },
+            weight_list.map(|weight_list| &weight_list[index]),
+            anchor_list.map_or(&[], |anchor_list| &anchor_list[index][..]),
+            arena);
+        if let Some(observer) = observer {
+            for snapshot in &snapshots {
+                observer.snapshot(index, is_cyclic, snapshot);
+            }
+        }
+        ((is_cyclic, poly_dst), refine_log)
+    };
 
-    // Single threaded (we may want to allow users to force this).
-    if poly_list_src.len() <= 1 {
-        for (is_cyclic, poly_src) in poly_list_src {
-            let poly_dst = fit_poly_single(
-                &poly_src, is_cyclic, error_threshold,
-                corner_angle, use_optimize_exhaustive);
-            println!("{} -> {}", poly_src.len(), poly_dst.len());
-            curve_list_dst.push_back((is_cyclic, poly_dst));
+    // Single threaded when requested or there is only one polygon (WASM has no threads).
+    let threads_len = if poly_list_src.len() <= 1 || cfg!(target_arch = "wasm32") {
+        1
+    } else {
+        ::std::cmp::min(
+            poly_list_src.len(),
+            if params.threads_len_max != 0 {
+                params.threads_len_max
+            } else {
+                ::std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+            },
+        )
+    };
+    stats.threads_len = threads_len;
+
+    if threads_len == 1 {
+        let mut arena = FitArena::with_error_eval(error_eval);
+        for (index, (is_cyclic, poly_src)) in poly_list_src.into_iter().enumerate() {
+            let (curve, refine_log) = fit_poly(index, is_cyclic, &poly_src, &mut arena);
+            curve_list_dst.push_back(curve);
+            refine_log_dst.push(refine_log);
         }
+        stats.arena_bytes_peak = arena.bytes_peak();
+        stats.solver_segments_len = arena.solver_segments_len();
+        stats.solver_stats = arena.solver_stats();
     } else {
+        use std::sync::Mutex;
         use std::thread;
 
-        let mut join_handles = Vec::with_capacity(poly_list_src.len());
         let mut poly_vec_src = Vec::with_capacity(poly_list_src.len());
 
-        for poly_src in poly_list_src {
-            poly_vec_src.push(poly_src);
+        for (index, poly_src) in poly_list_src.into_iter().enumerate() {
+            poly_vec_src.push((index, poly_src));
         }
 
         // sort length for more even threading
         // and so larger at the end so they are popped off and handled first,
         // smaller ones can be handled when other processors are free.
-        poly_vec_src.sort_by(|a, b| a.1.len().cmp(&b.1.len()));
-
-        while let Some((is_cyclic, poly_src_clone)) = poly_vec_src.pop() {
-            join_handles.push(thread::spawn(move || {
-                let poly_dst = fit_poly_single(
-                    &poly_src_clone, is_cyclic, error_threshold,
-                    corner_angle, use_optimize_exhaustive);
-                println!("{} -> {}", poly_src_clone.len(), poly_dst.len());
-                (is_cyclic, poly_dst)
-            }));
+        poly_vec_src.sort_by(|a, b| (a.1).1.len().cmp(&(b.1).1.len()));
+
+        // Return curves in the same order as the input polygons.
+        let mut curve_vec_dst: Vec<Option<((bool, Vec<[[f64; DIMS]; 3]>), Vec<RefineStep>)>> =
+            (0..poly_vec_src.len()).map(|_| None).collect();
+
+        // Each thread fits polygons until none remain, reusing its arena.
+        let poly_vec_src = Mutex::new(poly_vec_src);
+        let fit_poly = &fit_poly;
+        let results: Vec<(
+            Vec<(usize, ((bool, Vec<[[f64; DIMS]; 3]>), Vec<RefineStep>))>,
+            usize,
+            [usize; SOLVER_LEN],
+            SolverStats,
+        )> =
+            thread::scope(|scope| {
+                let join_handles: Vec<_> = (0..threads_len).map(|_| {
+                    let poly_vec_src = &poly_vec_src;
+                    let error_eval = error_eval.clone();
+                    scope.spawn(move || {
+                        let mut arena = FitArena::with_error_eval(error_eval);
+                        let mut result = vec![];
+                        loop {
+                            let item = poly_vec_src.lock().unwrap().pop();
+                            if let Some((index, (is_cyclic, poly_src))) = item {
+                                result.push(
+                                    (index, fit_poly(index, is_cyclic, &poly_src, &mut arena)));
+                            } else {
+                                break;
+                            }
+                        }
+                        (
+                            result,
+                            arena.bytes_peak(),
+                            arena.solver_segments_len(),
+                            arena.solver_stats(),
+                        )
+                    })
+                }).collect();
+                join_handles.into_iter().map(|child| child.join().unwrap()).collect()
+            });
+
+        for (result, bytes_peak, solver_segments_len, solver_stats) in results {
+            for (index, item) in result {
+                curve_vec_dst[index] = Some(item);
+            }
+            stats.arena_bytes_peak += bytes_peak;
+            for (a, b) in stats.solver_segments_len.iter_mut().zip(&solver_segments_len) {
+                *a += *b;
+            }
+            stats.solver_stats.add(&solver_stats);
         }
-
-        for child in join_handles {
-            curve_list_dst.push_back(child.join().unwrap());
+        for (curve, refine_log) in curve_vec_dst.into_iter().map(|c| c.unwrap()) {
+            curve_list_dst.push_back(curve);
+            refine_log_dst.push(refine_log);
         }
     }
 
-    return curve_list_dst;
+    return (curve_list_dst, refine_log_dst, stats);
 }
@@ -1,4 +1,6 @@
 
+use std::sync::Arc;
+
 use ::intern::math_vector::{
     len_squared_vnvn,
     len_vnvn,
@@ -9,7 +11,182 @@ use ::intern::math_vector::{
 // weak?
 const DIMS: usize = ::intern::math_vector::DIMS;
 
-mod types {
+/// Parameter arrays used while fitting,
+/// kept between calls so each fit doesn't need to allocate them.
+#[derive(Default)]
+pub struct FitScratch {
+    u: Vec<f64>,
+    u_prime: Vec<f64>,
+    stats: SolverStats,
+    /// Measures the error of each fit (`ErrorEvalScalar` when unset).
+    pub error_eval: Option<Arc<dyn ErrorEval>>,
+}
+
+impl FitScratch {
+    /// Memory used (in bytes).
+    pub fn bytes(&self) -> usize {
+        return (self.u.capacity() + self.u_prime.capacity()) * ::std::mem::size_of::<f64>();
+    }
+
+    /// Statistics for all fits using this scratch memory.
+    pub fn stats(&self) -> SolverStats {
+        return self.stats;
+    }
+}
+
+/// Statistics for fitting cubics, counting every fit
+/// (including fits which are discarded because they exceed the error threshold).
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct SolverStats {
+    /// Fits won by each solver (indexed by `Solver as usize`).
+    pub wins_len: [usize; SOLVER_LEN],
+    /// Least squares fits, which may be refined by re-parameterizing.
+    pub least_square_len: usize,
+    /// Re-parameterization iterations for all least squares fits.
+    pub reparameterize_iterations_len: usize,
+}
+
+impl SolverStats {
+    pub fn add(&mut self, other: &SolverStats) {
+        for (a, b) in self.wins_len.iter_mut().zip(&other.wins_len) {
+            *a += *b;
+        }
+        self.least_square_len += other.least_square_len;
+        self.reparameterize_iterations_len += other.reparameterize_iterations_len;
+    }
+}
+
+/// Floating point type used to store the point lengths & tangents cached while fitting,
+/// `f32` halves the memory these use, calculations are always performed using `f64`.
+pub trait CacheReal: Copy + PartialEq {
+    fn from_f64(v: f64) -> Self;
+    fn to_f64(self) -> f64;
+}
+
+impl CacheReal for f64 {
+    fn from_f64(v: f64) -> f64 { return v; }
+    fn to_f64(self) -> f64 { return self; }
+}
+
+impl CacheReal for f32 {
+    fn from_f64(v: f64) -> f32 { return v as f32; }
+    fn to_f64(self) -> f64 { return self as f64; }
+}
+
+/// A range of a cyclic array which may wrap past its end,
+/// made of two contiguous parts (the end of the array, followed by its start).
+///
+/// This avoids doubling cyclic arrays to take contiguous slices across the start/end boundary.
+pub struct WrapSlice<'a, T: 'a> {
+    head: &'a [T],
+    tail: &'a [T],
+}
+
+// Derive would require `T: Copy`.
+impl<'a, T> Clone for WrapSlice<'a, T> {
+    fn clone(&self) -> Self {
+        return *self;
+    }
+}
+impl<'a, T> Copy for WrapSlice<'a, T> {}
+
+impl<'a, T> WrapSlice<'a, T> {
+    /// `len` items of `data` from `start`, wrapping past the end (at most once).
+    pub fn from_range(
+        data: &'a [T],
+        start: usize,
+        len: usize,
+    ) -> WrapSlice<'a, T> {
+        let end = start + len;
+        if end <= data.len() {
+            return WrapSlice {
+                head: &data[start..end],
+                tail: &[],
+            };
+        } else {
+            return WrapSlice {
+                head: &data[start..],
+                tail: &data[..(end - data.len())],
+            };
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        return self.head.len() + self.tail.len();
+    }
+
+    /// Both contiguous parts (the second is empty when the range doesn't wrap).
+    #[allow(dead_code)]
+    pub fn parts(&self) -> (&'a [T], &'a [T]) {
+        return (self.head, self.tail);
+    }
+
+    pub fn iter(&self) -> ::std::iter::Chain<::std::slice::Iter<'a, T>, ::std::slice::Iter<'a, T>> {
+        return self.head.iter().chain(self.tail.iter());
+    }
+}
+
+impl<'a, T> From<&'a [T]> for WrapSlice<'a, T> {
+    fn from(data: &'a [T]) -> WrapSlice<'a, T> {
+        return WrapSlice {
+            head: data,
+            tail: &[],
+        };
+    }
+}
+
+impl<'a, T> ::std::ops::Index<usize> for WrapSlice<'a, T> {
+    type Output = T;
+    fn index(&self, i: usize) -> &T {
+        if i < self.head.len() {
+            return &self.head[i];
+        } else {
+            return &self.tail[i - self.head.len()];
+        }
+    }
+}
+
+/// Methods to calculate the handles of a cubic fit to points,
+/// the handles with the lowest error are used.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Solver {
+    /// Handles a third of the distance between the end-points,
+    /// always calculated, used when no other solver does better.
+    Fallback,
+    /// Least squares, refined by re-parameterizing the points.
+    LeastSquare,
+    /// Handles for a circular arc, scaled by the length of the points.
+    Circle,
+    /// Handles which reach the furthest offset of the points from the end-points.
+    Offset,
+}
+
+/// The number of `Solver` variants (for arrays indexed by `Solver as usize`).
+pub const SOLVER_LEN: usize = 4;
+
+/// Solvers tried (in order) when none are given,
+/// `Solver::Fallback` is always used so it's not included.
+pub const SOLVERS_DEFAULT: &'static [Solver] = &[
+    Solver::Circle,
+    Solver::Offset,
+    Solver::LeastSquare,
+];
+
+/// Newton-Raphson re-parameterization of the points, refining the least squares solution.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Reparameterize {
+    /// The maximum number of iterations.
+    pub iterations: usize,
+    /// Stop once the error improves by less than this (zero to always run all iterations).
+    pub epsilon: f64,
+}
+
+pub const REPARAMETERIZE_DEFAULT: Reparameterize = Reparameterize {
+    iterations: 4,
+    epsilon: 0.0,
+};
+
+pub mod types {
     use super::{
         DIMS,
     };
@@ -25,6 +202,7 @@ mod types {
 mod cubic_solve_fallback {
     use super::{
         types,
+        WrapSlice,
         DIMS,
     };
     use ::intern::math_vector::{
@@ -33,7 +211,7 @@ mod cubic_solve_fallback {
     };
 
     pub fn calc(
-        points: &[[f64; DIMS]],
+        points: WrapSlice<[f64; DIMS]>,
         tan_l: &[f64; DIMS],
         tan_r: &[f64; DIMS],
     ) -> types::Cubic {
@@ -53,6 +231,7 @@ mod cubic_solve_fallback {
 mod cubic_solve_least_square {
     use super::{
         types,
+        WrapSlice,
         DIMS,
     };
     use ::intern::math_vector::{
@@ -62,8 +241,10 @@ mod cubic_solve_least_square {
     };
 
 
+    /// `weights` (when set) scale the contribution of each point.
     pub fn calc(
-        points: &[[f64; DIMS]],
+        points: WrapSlice<[f64; DIMS]>,
+        weights: Option<WrapSlice<f64>>,
         tan_l: &[f64; DIMS],
         tan_r: &[f64; DIMS],
         u_prime: &[f64],
@@ -75,7 +256,8 @@ mod cubic_solve_least_square {
             let mut x: [f64; 2] = [0.0, 0.0];
             let mut c: [[f64; 2]; 2] = [[0.0, 0.0], [0.0, 0.0]];
 
-            for (pt, u) in points.iter().zip(u_prime) {
+            for (i, (pt, u)) in points.iter().zip(u_prime).enumerate() {
+                let w = weights.map_or(1.0, |weights| weights[i]);
                 let a: [[f64; DIMS]; 2] = [
                     mul_vn_fl(tan_l, bezier::b1(*u)),
                     mul_vn_fl(tan_r, bezier::b2(*u)),
@@ -88,12 +270,12 @@ mod cubic_solve_least_square {
                 for j in 0..DIMS {
                     let tmp = (pt[j] - (p0[j] * b0_plus_b1)) + (p3[j] * b2_plus_b3);
 
-                    x[0] += a[0][j] * tmp;
-                    x[1] += a[1][j] * tmp;
+                    x[0] += w * a[0][j] * tmp;
+                    x[1] += w * a[1][j] * tmp;
 
-                    c[0][0] += a[0][j] * a[0][j];
-                    c[0][1] += a[0][j] * a[1][j];
-                    c[1][1] += a[1][j] * a[1][j];
+                    c[0][0] += w * a[0][j] * a[0][j];
+                    c[0][1] += w * a[0][j] * a[1][j];
+                    c[1][1] += w * a[1][j] * a[1][j];
                 }
 
                 c[1][0] = c[0][1];
@@ -155,6 +337,7 @@ mod cubic_solve_least_square {
 mod cubic_solve_circle {
     use super::{
         types,
+        WrapSlice,
         DIMS,
     };
     use ::intern::math_vector::{
@@ -165,7 +348,7 @@ mod cubic_solve_circle {
     };
 
     pub fn calc(
-        points: &[[f64; DIMS]],
+        points: WrapSlice<[f64; DIMS]>,
         tan_l: &[f64; DIMS],
         tan_r: &[f64; DIMS],
         points_coords_length: f64,
@@ -287,6 +470,7 @@ mod cubic_solve_circle {
 mod cubic_solve_offset {
     use super::{
         types,
+        WrapSlice,
         DIMS,
     };
     use ::intern::math_vector::{
@@ -301,7 +485,7 @@ mod cubic_solve_offset {
     };
 
     pub fn calc(
-        points: &[[f64; DIMS]],
+        points: WrapSlice<[f64; DIMS]>,
         tan_l: &[f64; DIMS],
         tan_r: &[f64; DIMS],
     ) -> Option<types::Cubic> {
@@ -329,7 +513,7 @@ mod cubic_solve_offset {
             return None;
         }
 
-        for pt in &points[1..(points.len() - 1)] {
+        for pt in points.iter().skip(1).take(points.len() - 2) {
             for k in 0..2 {
                 let tmp = project_vnvn_normalized(&sub_vnvn(p0, pt), &a[k]);
                 dists[k] = dists[k].max(dot_vnvn(&tmp, &a[k]));
@@ -382,7 +566,7 @@ fn cubic_find_root(
 /// Given set of points and their parameterization, try to find a better parameterization.
 fn cubic_reparameterize(
     cubic: &types::Cubic,
-    points: &[[f64; DIMS]],
+    points: WrapSlice<[f64; DIMS]>,
     u_prime_src: &[f64],
 
     u_prime_dst: &mut [f64]
@@ -391,7 +575,7 @@ fn cubic_reparameterize(
     debug_assert!(points.len() == u_prime_dst.len());
 
     // Recalculate the values of u[] based on the Newton Raphson method.
-    for ((u_src, u_dst), pt) in u_prime_src.iter().zip(&mut *u_prime_dst).zip(points) {
+    for ((u_src, u_dst), pt) in u_prime_src.iter().zip(&mut *u_prime_dst).zip(points.iter()) {
         *u_dst = cubic_find_root(cubic, pt, *u_src);
         if !(*u_dst).is_finite() {
             return false;
@@ -412,18 +596,20 @@ fn cubic_reparameterize(
     return true;
 }
 
-fn points_calc_coord_length(
-    points: &[[f64; DIMS]],
-    points_length_cache: &[f64],
-) -> (Vec<f64>, f64) {
-    let mut u: Vec<f64> = Vec::with_capacity(points.len());
+/// Calculate the parameters of `points` into `u`, returning the length.
+fn points_calc_coord_length<R: CacheReal>(
+    points: WrapSlice<[f64; DIMS]>,
+    points_length_cache: WrapSlice<R>,
+    u: &mut Vec<f64>,
+) -> f64 {
+    u.clear();
     u.push(0.0);
 
     let mut pt_prev = &points[0];
     let mut l_prev = 0.0;
-    for (pt, l) in points.iter().zip(points_length_cache).skip(1) {
-        debug_assert!(len_vnvn(pt, pt_prev) == *l);
-        let l_curr = l + l_prev;
+    for (pt, l) in points.iter().zip(points_length_cache.iter()).skip(1) {
+        debug_assert!(R::from_f64(len_vnvn(pt, pt_prev)) == *l);
+        let l_curr = l.to_f64() + l_prev;
         u.push(l_curr);
 
         pt_prev = pt;
@@ -437,10 +623,11 @@ fn points_calc_coord_length(
         *u_step /= w;
     }
 
-    return (u, w);
+    return w;
 }
 
-fn cubic_calc_point(
+/// Evaluate the cubic at `t` (from 0 to 1).
+pub fn cubic_calc_point(
     cubic: &types::Cubic, t: f64,
 ) -> [f64; DIMS] {
     let p0 = &cubic.p0;
@@ -498,31 +685,78 @@ struct FitError {
     pub index: usize,
 }
 
+/// Measures the error of a cubic against the points it's fit to.
+///
+/// Fitting spends most of its time here (exhaustive optimization especially),
+/// so alternative implementations (using SIMD or a GPU for example) can be used
+/// instead of `ErrorEvalScalar`, see: `FitArena::with_error_eval`.
+pub trait ErrorEval: Send + Sync {
+    /// Return the largest squared distance between each point & the cubic evaluated at
+    /// the point's parameter in `u`, with the index of the point
+    /// (the end-points aren't measured since they always match).
+    ///
+    /// Return None once any distance exceeds `error_max_sq_limit`,
+    /// since the fit won't be used (infinity when all points are measured).
+    fn cubic_error_max_sq(
+        &self,
+        cubic: &types::Cubic,
+        points: WrapSlice<[f64; DIMS]>,
+        u: &[f64],
+        error_max_sq_limit: f64,
+    ) -> Option<(f64, usize)>;
+}
+
+/// Evaluate the cubic for each point in turn.
+pub struct ErrorEvalScalar;
+
+impl ErrorEval for ErrorEvalScalar {
+    fn cubic_error_max_sq(
+        &self,
+        cubic: &types::Cubic,
+        points: WrapSlice<[f64; DIMS]>,
+        u: &[f64],
+        error_max_sq_limit: f64,
+    ) -> Option<(f64, usize)> {
+        let mut error_max_sq = -1.0;
+
+        // no need to measure first & last points
+        let mut index = 1;
+        let mut error_index = 1;
+        for (pt_real, u_step) in
+            points.iter().zip(u).skip(1).take(points.len() - 2)
+        {
+            let pt_eval = cubic_calc_point(cubic, *u_step);
+            let err_sq = len_squared_vnvn(pt_real, &pt_eval);
+            if err_sq > error_max_sq {
+                if err_sq > error_max_sq_limit {
+                    return None;
+                }
+                error_max_sq = err_sq;
+                error_index = index;
+            }
+            index += 1;
+        }
+
+        debug_assert!(error_max_sq != -1.0);
+        return Some((error_max_sq, error_index));
+    }
+}
+
+/// The error evaluation set for `FitScratch::error_eval` or the default.
+fn error_eval_or_default(
+    error_eval: &Option<Arc<dyn ErrorEval>>,
+) -> &dyn ErrorEval {
+    return error_eval.as_ref().map_or(&ErrorEvalScalar, |error_eval| &**error_eval);
+}
+
 fn cubic_calc_error(
+    error_eval: &dyn ErrorEval,
     cubic: &types::Cubic,
-    points: &[[f64; DIMS]],
+    points: WrapSlice<[f64; DIMS]>,
     u: &[f64],
 ) -> FitError {
-    let mut error_max_sq = -1.0;
-
-    // no need to measure first & last points
-    let skip_endpoints = 1..(points.len() - 1);
-    let mut index = 1;
-    let mut error_index = 1;
-    for (pt_real, u_step) in
-        points[skip_endpoints.clone()].iter().zip(
-            &u[skip_endpoints.clone()])
-    {
-        let pt_eval = cubic_calc_point(cubic, *u_step);
-        let err_sq = len_squared_vnvn(pt_real, &pt_eval);
-        if err_sq > error_max_sq {
-            error_max_sq = err_sq;
-            error_index = index;
-        }
-        index += 1;
-    }
-
-    debug_assert!(error_max_sq != -1.0);
+    let (error_max_sq, error_index) = error_eval.cubic_error_max_sq(
+        cubic, points, u, ::std::f64::INFINITY).unwrap();
     return FitError {
         max_sq: error_max_sq,
         index: error_index,
@@ -532,66 +766,81 @@ fn cubic_calc_error(
 /// Like `cubic_calc_error` but return None
 /// in the case we can't improve on `error_max_sq_limit`.
 fn cubic_calc_error_limit(
+    error_eval: &dyn ErrorEval,
     cubic: &types::Cubic,
-    points: &[[f64; DIMS]],
+    points: WrapSlice<[f64; DIMS]>,
     u: &[f64],
     error_max_sq_limit: f64,
 ) -> Option<FitError> {
-    let mut error_max_sq = -1.0;
-
-    // no need to measure first & last points
-    let skip_endpoints = 1..(points.len() - 1);
-    let mut index = 1;
-    let mut error_index = 1;
-    for (pt_real, u_step) in
-        points[skip_endpoints.clone()].iter().zip(
-            &u[skip_endpoints.clone()])
-    {
-        let pt_eval = cubic_calc_point(cubic, *u_step);
-        let err_sq = len_squared_vnvn(pt_real, &pt_eval);
-        if err_sq > error_max_sq {
-            if err_sq > error_max_sq_limit {
-                return None;
+    return error_eval.cubic_error_max_sq(cubic, points, u, error_max_sq_limit).map(
+        |(error_max_sq, error_index)| {
+            FitError {
+                max_sq: error_max_sq,
+                index: error_index,
+            }
+        });
+}
+
+/// Limit the length of both handles to `ratio` times the distance between the end-points,
+/// (zero to disable).
+fn cubic_clamp_handles(
+    cubic: &mut types::Cubic,
+    ratio: f64,
+) {
+    if ratio == 0.0 {
+        return;
+    }
+    let len_max = len_vnvn(&cubic.p0, &cubic.p3) * ratio;
+    for &mut (p, ref mut h) in &mut [(cubic.p0, &mut cubic.p1), (cubic.p3, &mut cubic.p2)] {
+        let len = len_vnvn(&p, h);
+        if len > len_max {
+            let fac = len_max / len;
+            for j in 0..DIMS {
+                h[j] = p[j] + (h[j] - p[j]) * fac;
             }
-            error_max_sq = err_sq;
-            error_index = index;
         }
-        index += 1;
     }
-
-    // println!("~~ {}", points.len());
-    debug_assert!(error_max_sq != -1.0);
-    return Some(FitError {
-        max_sq: error_max_sq,
-        index: error_index,
-    });
 }
 
-fn fit_cubic_to_points(
-    points: &[[f64; DIMS]],
-    points_length_cache: &[f64],
+fn fit_cubic_to_points<R: CacheReal>(
+    points: WrapSlice<[f64; DIMS]>,
+    points_length_cache: WrapSlice<R>,
+    weights: Option<WrapSlice<f64>>,
     tan_l: &[f64; DIMS],
     tan_r: &[f64; DIMS],
-) -> (types::Cubic, FitError) {
-    let iteration_max = 4;
-
+    max_handle_ratio: f64,
+    solvers: &[Solver],
+    reparameterize: Reparameterize,
+    scratch: &mut FitScratch,
+) -> (types::Cubic, FitError, Solver) {
     assert!(points.len() > 2);
 
-    let cubic_fallback = cubic_solve_fallback::calc(points, tan_l, tan_r);
+    // Clamp each solution before measuring its error,
+    // so the error of clamped handles is used when picking the best solution.
+    let clamp = |mut cubic: types::Cubic| {
+        cubic_clamp_handles(&mut cubic, max_handle_ratio);
+        cubic
+    };
+
+    let cubic_fallback = clamp(cubic_solve_fallback::calc(points, tan_l, tan_r));
 
-    let (mut u, points_length) = points_calc_coord_length(points, points_length_cache);
-    let error_fallback = cubic_calc_error(&cubic_fallback, points, &u);
+    let FitScratch { ref mut u, ref mut u_prime, ref mut stats, ref error_eval } = *scratch;
+    let error_eval = error_eval_or_default(error_eval);
+    let points_length = points_calc_coord_length(points, points_length_cache, u);
+    let error_fallback = cubic_calc_error(error_eval, &cubic_fallback, points, u);
     let mut error_best = error_fallback;
     let mut cubic_best = cubic_fallback;
+    let mut solver_best = Solver::Fallback;
 
     macro_rules! cubic_test_error {
-        ($cubic_test:expr) => {
+        ($cubic_test:expr, $solver:expr) => {
             {
                 let error_test = cubic_calc_error(
-                    $cubic_test, points, &u);
+                    error_eval, $cubic_test, points, u);
                 if error_best.max_sq > error_test.max_sq {
                     cubic_best = *$cubic_test;
                     error_best = error_test;
+                    solver_best = $solver;
                 }
                 error_test
             }
@@ -599,88 +848,201 @@ fn fit_cubic_to_points(
     }
 
     macro_rules! cubic_test_error_limit {
-        ($cubic_test:expr) => {
+        ($cubic_test:expr, $solver:expr) => {
             {
                 if let Some(error_test) = cubic_calc_error_limit(
-                    $cubic_test, points, &u, error_best.max_sq)
+                    error_eval, $cubic_test, points, u, error_best.max_sq)
                 {
                     cubic_best = *$cubic_test;
                     error_best = error_test;
+                    solver_best = $solver;
                 }
             }
         }
     }
 
-    if let Some(cubic_test) = cubic_solve_circle::calc(points, tan_l, tan_r, points_length) {
-        cubic_test_error_limit!(&cubic_test);
-    }
-
-    if let Some(cubic_test) = cubic_solve_offset::calc(points, tan_l, tan_r) {
-        cubic_test_error_limit!(&cubic_test);
-    }
-
-    {
-        let mut cubic_least_square;
-        let mut error_least_square;
-
-        if let Some(cubic_test) = cubic_solve_least_square::calc(points, tan_l, tan_r, &u) {
-            // we want the result so we can refine it (even if its currently not the best)
-            error_least_square = cubic_test_error!(&cubic_test);
-            cubic_least_square = cubic_test;
-        } else {
-            error_least_square = error_fallback;
-            cubic_least_square = cubic_fallback;
-        }
-
-        let mut u_prime: Vec<f64> = vec![0.0; u.len()];
-        for _ in 0..iteration_max {
-            if !cubic_reparameterize(&cubic_least_square, points, &u, &mut u_prime) {
-                break;
+    for (i, solver) in solvers.iter().enumerate() {
+        match *solver {
+            Solver::Fallback => {
+                // Always used (above).
             }
+            Solver::Circle => {
+                if let Some(cubic_test) =
+                    cubic_solve_circle::calc(points, tan_l, tan_r, points_length)
+                {
+                    cubic_test_error_limit!(&clamp(cubic_test), Solver::Circle);
+                }
+            }
+            Solver::Offset => {
+                if let Some(cubic_test) = cubic_solve_offset::calc(points, tan_l, tan_r) {
+                    cubic_test_error_limit!(&clamp(cubic_test), Solver::Offset);
+                }
+            }
+            Solver::LeastSquare => {
+                let mut cubic_least_square;
+                let mut error_least_square;
 
-            if let Some(cubic_test) =
-                cubic_solve_least_square::calc(points, tan_l, tan_r, &u_prime)
-            {
-                let error_test = cubic_calc_error(&cubic_test, points, &u_prime);
-
-                if error_least_square.max_sq > error_test.max_sq {
-                    error_least_square = error_test;
+                if let Some(cubic_test) =
+                    cubic_solve_least_square::calc(points, weights, tan_l, tan_r, u)
+                {
+                    let cubic_test = clamp(cubic_test);
+                    // we want the result so we can refine it
+                    // (even if its currently not the best)
+                    error_least_square = cubic_test_error!(&cubic_test, Solver::LeastSquare);
                     cubic_least_square = cubic_test;
                 } else {
-                    // break if we're getting worse
-                    // break;
+                    error_least_square = error_fallback;
+                    cubic_least_square = cubic_fallback;
                 }
-                ::std::mem::swap(&mut u, &mut u_prime);
-            } else {
-                break;
-            }
 
-        }
-        drop(u_prime);
-        drop(u);
+                stats.least_square_len += 1;
+                u_prime.clear();
+                u_prime.resize(u.len(), 0.0);
+                for _ in 0..reparameterize.iterations {
+                    if !cubic_reparameterize(&cubic_least_square, points, u, u_prime) {
+                        break;
+                    }
+                    stats.reparameterize_iterations_len += 1;
+
+                    if let Some(cubic_test) =
+                        cubic_solve_least_square::calc(points, weights, tan_l, tan_r, u_prime)
+                    {
+                        let cubic_test = clamp(cubic_test);
+                        let error_test = cubic_calc_error(
+                            error_eval, &cubic_test, points, u_prime);
+
+                        let improvement =
+                            error_least_square.max_sq.sqrt() - error_test.max_sq.sqrt();
+                        if error_least_square.max_sq > error_test.max_sq {
+                            error_least_square = error_test;
+                            cubic_least_square = cubic_test;
+                        }
+                        ::std::mem::swap(u, u_prime);
+                        // Converged (or getting worse).
+                        if reparameterize.epsilon != 0.0 && improvement < reparameterize.epsilon {
+                            break;
+                        }
+                    } else {
+                        break;
+                    }
 
-        if error_best.max_sq > error_least_square.max_sq {
-            error_best = error_least_square;
-            cubic_best = cubic_least_square;
+                }
+
+                if error_best.max_sq > error_least_square.max_sq {
+                    error_best = error_least_square;
+                    cubic_best = cubic_least_square;
+                    solver_best = Solver::LeastSquare;
+                }
+
+                // Re-parameterizing changed `u`, restore it for the solvers which follow.
+                if i + 1 != solvers.len() {
+                    points_calc_coord_length(points, points_length_cache, u);
+                }
+            }
         }
     }
 
-    return (cubic_best, error_best);
+    stats.wins_len[solver_best as usize] += 1;
+
+    return (cubic_best, error_best, solver_best);
 }
 
 //
 // Return error squared, and both handle locations
 //
+// `max_handle_ratio` limits the handle length relative to the segment (zero to disable).
+// `solvers` are tried in order, also returning the solver used.
+// `reparameterize` refines the least squares solution.
+// `weights` (when set) prioritize matching points with higher weights (least squares only).
+//
+#[allow(dead_code)]
 pub fn curve_fit_cubic_to_points_single(
     points: &[[f64; DIMS]],
     points_length_cache: &[f64],
+    weights: Option<&[f64]>,
     tan_l: &[f64; DIMS],
     tan_r: &[f64; DIMS],
-) -> ((f64, usize), [f64; DIMS], [f64; DIMS]) {
-    let (cubic, fit_error) = fit_cubic_to_points(
+    max_handle_ratio: f64,
+    solvers: &[Solver],
+    reparameterize: Reparameterize,
+    scratch: &mut FitScratch,
+) -> ((f64, usize), [f64; DIMS], [f64; DIMS], Solver) {
+    return curve_fit_cubic_to_points_single_wrap(
+        WrapSlice::from(points),
+        WrapSlice::from(points_length_cache),
+        weights.map(WrapSlice::from),
+        tan_l, tan_r,
+        max_handle_ratio,
+        solvers,
+        reparameterize,
+        scratch);
+}
+
+/// Like `curve_fit_cubic_to_points_single`, for points which may wrap past the end of
+/// a cyclic polygon, so the polygon doesn't need to be doubled.
+pub fn curve_fit_cubic_to_points_single_wrap<R: CacheReal>(
+    points: WrapSlice<[f64; DIMS]>,
+    points_length_cache: WrapSlice<R>,
+    weights: Option<WrapSlice<f64>>,
+    tan_l: &[f64; DIMS],
+    tan_r: &[f64; DIMS],
+    max_handle_ratio: f64,
+    solvers: &[Solver],
+    reparameterize: Reparameterize,
+    scratch: &mut FitScratch,
+) -> ((f64, usize), [f64; DIMS], [f64; DIMS], Solver) {
+    let (cubic, fit_error, solver) = fit_cubic_to_points(
         points,
         points_length_cache,
-        tan_l, tan_r);
+        weights,
+        tan_l, tan_r,
+        max_handle_ratio,
+        solvers,
+        reparameterize,
+        scratch);
+
+    return ((fit_error.max_sq, fit_error.index), cubic.p1, cubic.p2, solver);
+}
+
+/// Return the error (squared) of an existing cubic,
+/// measured from the chord-length parameters of the points,
+/// refined by `reparameterize` (using the smallest error).
+///
+/// Used to check fits calculated from `f32` caches against the original points.
+pub fn cubic_calc_error_sq_wrap(
+    cubic: &types::Cubic,
+    points: WrapSlice<[f64; DIMS]>,
+    reparameterize: Reparameterize,
+    scratch: &mut FitScratch,
+) -> f64 {
+    // The end-points are always matched.
+    if points.len() < 3 {
+        return 0.0;
+    }
+
+    let FitScratch { ref mut u, ref mut u_prime, ref error_eval, .. } = *scratch;
+    let error_eval = error_eval_or_default(error_eval);
+    u.clear();
+    u.push(0.0);
+    let mut l = 0.0;
+    for (pt_prev, pt) in points.iter().zip(points.iter().skip(1)) {
+        l += len_vnvn(pt_prev, pt);
+        u.push(l);
+    }
+    let l_inv = if l != 0.0 { 1.0 / l } else { 0.0 };
+    for u_step in &mut u[1..] {
+        *u_step *= l_inv;
+    }
 
-    return ((fit_error.max_sq, fit_error.index), cubic.p1, cubic.p2);
+    let mut error_sq = cubic_calc_error(error_eval, cubic, points, u).max_sq;
+    u_prime.clear();
+    u_prime.resize(u.len(), 0.0);
+    for _ in 0..reparameterize.iterations {
+        if !cubic_reparameterize(cubic, points, u, u_prime) {
+            break;
+        }
+        error_sq = error_sq.min(cubic_calc_error(error_eval, cubic, points, u_prime).max_sq);
+        ::std::mem::swap(u, u_prime);
+    }
+    return error_sq;
 }
@@ -7,7 +7,33 @@ pub use ::intern::math_vector;
 
 pub use self::curve_fit_from_polys::{
     TraceMode,
+    ErrorRelative,
+    EndTangent,
+    ExhaustivePrune,
+    EXHAUSTIVE_PRUNE_DEFAULT,
+    RefineStage,
+    RefineStep,
+    FitStage,
+    FitSnapshot,
+    FitObserver,
+    FitParams,
     fit_poly_single,
-    fit_poly_list,
+    fit_poly_list_with_params,
 };
 
+pub use self::curve_fit_single::{
+    REPARAMETERIZE_DEFAULT,
+    SOLVERS_DEFAULT,
+    ErrorEval,
+    Reparameterize,
+    Solver,
+    cubic_calc_point,
+};
+// For implementations of `ErrorEval` (which may wrap the default).
+#[allow(unused_imports)]
+pub use self::curve_fit_single::{
+    ErrorEvalScalar,
+    WrapSlice,
+};
+pub use self::curve_fit_single::types::Cubic;
+
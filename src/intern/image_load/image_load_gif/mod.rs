@@ -0,0 +1,384 @@
+///
+/// Module for reading GIF images, see: https://www.w3.org/Graphics/GIF/spec-gif89a.txt
+///
+/// Images are drawn onto the logical screen (canvas) in order,
+/// each image is read as a frame (using the disposal & transparency of its
+/// graphic control extension), so animations are read as they're shown.
+/// Transparent pixels are white.
+///
+
+use ::std::io::{
+    Cursor,
+    Error,
+    ErrorKind,
+};
+
+use std::io::prelude::*;
+
+use super::PixelLayout;
+
+const BLOCK_EXTENSION: u8 = 0x21;
+const BLOCK_IMAGE: u8 = 0x2c;
+const BLOCK_TRAILER: u8 = 0x3b;
+
+const EXTENSION_GRAPHIC_CONTROL: u8 = 0xf9;
+
+const DISPOSE_BACKGROUND: u8 = 2;
+const DISPOSE_PREVIOUS: u8 = 3;
+
+// LZW codes never use more bits than this.
+const LZW_CODE_BITS_MAX: u32 = 12;
+
+// Stop reading images after this many frames.
+const FRAMES_MAX: usize = 4096;
+
+/// Returns (size, color_max, pixel_data) of the first image, or fail.
+#[allow(dead_code)]
+pub fn from_bytes(
+    data: &[u8],
+) -> Result<([usize; 2], usize, Vec<[u8; 3]>), Error> {
+    return from_reader(&mut Cursor::new(data));
+}
+
+/// Read the first image, any malformed input returns an error (never panics).
+pub fn from_reader<R: Read>(
+    f: &mut R,
+) -> Result<([usize; 2], usize, Vec<[u8; 3]>), Error> {
+    let mut frames = frames_read(f, true)?;
+    return Ok(frames.swap_remove(0));
+}
+
+/// Read all images (frames of an animation), see: `from_reader`.
+pub fn frames_from_reader<R: Read>(
+    f: &mut R,
+) -> Result<Vec<([usize; 2], usize, Vec<[u8; 3]>)>, Error> {
+    return frames_read(f, false);
+}
+
+/// Read the first image, passing each row (top to bottom) to `row_fn`
+/// along with the size and color range, returns (size, color_max).
+///
+/// The image is decoded first, since LZW data may be interlaced.
+pub fn rows_from_reader<R: Read, F>(
+    f: &mut R,
+    mut row_fn: F,
+) -> Result<([usize; 2], usize), Error> where F: FnMut(&[usize; 2], usize, &[[u8; 3]]) {
+    let (size, color_max, pixel_buffer) = from_reader(f)?;
+    for row in pixel_buffer.chunks(size[0]) {
+        row_fn(&size, color_max, row);
+    }
+    return Ok((size, color_max));
+}
+
+fn read_u8<R: Read>(
+    f: &mut R,
+) -> Result<u8, Error> {
+    let mut buf: [u8; 1] = [0];
+    f.read_exact(&mut buf)?;
+    return Ok(buf[0]);
+}
+
+fn read_u16<R: Read>(
+    f: &mut R,
+) -> Result<u16, Error> {
+    let mut buf: [u8; 2] = [0; 2];
+    f.read_exact(&mut buf)?;
+    return Ok(u16::from_le_bytes(buf));
+}
+
+/// Read a color table of `len` RGB entries.
+fn color_table_read<R: Read>(
+    f: &mut R,
+    len: usize,
+) -> Result<Vec<[u8; 3]>, Error> {
+    let mut data: Vec<u8> = vec![0; len * 3];
+    f.read_exact(&mut data)?;
+    return Ok(data.chunks(3).map(|c| [c[0], c[1], c[2]]).collect());
+}
+
+/// Read a sequence of data sub-blocks (ending with an empty block),
+/// appending their contents to `data` when set.
+fn sub_blocks_read<R: Read>(
+    f: &mut R,
+    mut data: Option<&mut Vec<u8>>,
+) -> Result<(), Error> {
+    let mut block: [u8; 255] = [0; 255];
+    loop {
+        let len = read_u8(f)? as usize;
+        if len == 0 {
+            return Ok(());
+        }
+        f.read_exact(&mut block[..len])?;
+        if let Some(ref mut data) = data {
+            data.extend_from_slice(&block[..len]);
+        }
+    }
+}
+
+/// Decode LZW data, filling `data_dst` with color indices,
+/// data beyond the end of `data_dst` is ignored.
+fn lzw_decode(
+    data_src: &[u8],
+    code_bits_min: u32,
+    data_dst: &mut [u8],
+) -> Result<(), Error> {
+    let code_clear = 1_usize << code_bits_min;
+    let code_end = code_clear + 1;
+    let codes_max = 1_usize << LZW_CODE_BITS_MAX;
+
+    // Each code is the string of its prefix code followed by its suffix.
+    let mut prefix: Vec<u16> = vec![0; codes_max];
+    let mut suffix: Vec<u8> = vec![0; codes_max];
+    let mut first: Vec<u8> = vec![0; codes_max];
+    let mut length: Vec<usize> = vec![0; codes_max];
+    for code in 0..code_clear {
+        suffix[code] = code as u8;
+        first[code] = code as u8;
+        length[code] = 1;
+    }
+
+    let mut code_bits = code_bits_min + 1;
+    let mut code_next = code_clear + 2;
+    let mut code_prev: Option<usize> = None;
+    let mut bit_buf: u32 = 0;
+    let mut bit_len: u32 = 0;
+    let mut data_src_iter = data_src.iter();
+    let mut i_dst = 0;
+    while i_dst < data_dst.len() {
+        while bit_len < code_bits {
+            match data_src_iter.next() {
+                Some(byte) => {
+                    bit_buf |= (*byte as u32) << bit_len;
+                    bit_len += 8;
+                }
+                None => {
+                    return Err(Error::new(ErrorKind::UnexpectedEof, "Image data is truncated"));
+                }
+            }
+        }
+        let code = (bit_buf & ((1 << code_bits) - 1)) as usize;
+        bit_buf >>= code_bits;
+        bit_len -= code_bits;
+
+        if code == code_clear {
+            code_bits = code_bits_min + 1;
+            code_next = code_clear + 2;
+            code_prev = None;
+            continue;
+        } else if code == code_end {
+            break;
+        } else if code > code_next || (code == code_next && code_prev.is_none()) {
+            return Err(Error::new(ErrorKind::Other, "Invalid LZW code"));
+        }
+
+        if let Some(code_prev) = code_prev {
+            if code_next < codes_max {
+                // When the code isn't yet known, it's the previous string
+                // followed by its own first value.
+                let value_first = if code == code_next { first[code_prev] } else { first[code] };
+                prefix[code_next] = code_prev as u16;
+                suffix[code_next] = value_first;
+                first[code_next] = first[code_prev];
+                length[code_next] = length[code_prev] + 1;
+                code_next += 1;
+                if code_next == (1 << code_bits) && code_bits < LZW_CODE_BITS_MAX {
+                    code_bits += 1;
+                }
+            }
+        }
+        code_prev = Some(code);
+
+        // Write the string backwards, from its last value.
+        let len = length[code];
+        let mut code_iter = code;
+        for i in (i_dst..(i_dst + len)).rev() {
+            if i < data_dst.len() {
+                data_dst[i] = suffix[code_iter];
+            }
+            code_iter = prefix[code_iter] as usize;
+        }
+        i_dst += len;
+    }
+    if i_dst < data_dst.len() {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "Image data is truncated"));
+    }
+    return Ok(());
+}
+
+/// The rows of an interlaced image in the order they're stored.
+fn interlaced_rows(
+    height: usize,
+) -> Vec<usize> {
+    let mut rows = Vec::with_capacity(height);
+    for &(y_start, y_step) in &[(0, 8), (4, 8), (2, 4), (1, 2)] {
+        rows.extend((y_start..height).step_by(y_step));
+    }
+    return rows;
+}
+
+/// Read images, drawing each onto the canvas & returning a copy as a frame
+/// (only the first image when `is_first_only`).
+fn frames_read<R: Read>(
+    f: &mut R,
+    is_first_only: bool,
+) -> Result<Vec<([usize; 2], usize, Vec<[u8; 3]>)>, Error> {
+    let truncated = |e: Error| {
+        if e.kind() == ErrorKind::UnexpectedEof {
+            Error::new(ErrorKind::UnexpectedEof, "Image data is truncated")
+        } else {
+            e
+        }
+    };
+
+    let mut header: [u8; 13] = [0; 13];
+    f.read_exact(&mut header).map_err(&truncated)?;
+    if !(&header[0..6] == b"GIF87a" || &header[0..6] == b"GIF89a") {
+        return Err(Error::new(ErrorKind::Other, "Invalid header"));
+    }
+    let size = [
+        u16::from_le_bytes([header[6], header[7]]) as usize,
+        u16::from_le_bytes([header[8], header[9]]) as usize,
+    ];
+    if !(size[0] > 0 && size[1] > 0) {
+        return Err(Error::new(ErrorKind::Other, "Invalid size"));
+    }
+    if size[0] * size[1] > super::IMAGE_PIXELS_MAX {
+        return Err(Error::new(ErrorKind::Other, "Image size too large"));
+    }
+    let color_table_global = if (header[10] & 0x80) != 0 {
+        Some(color_table_read(f, 2 << (header[10] & 0x07)).map_err(&truncated)?)
+    } else {
+        None
+    };
+
+    let layout = PixelLayout::RGBA8;
+    let bytes_per_pixel = layout.bytes_per_pixel();
+    // Start transparent (so white), the background color is ignored as most readers do.
+    let mut canvas: Vec<u8> = vec![0; size[0] * size[1] * bytes_per_pixel];
+    let mut canvas_prev: Vec<u8> = vec![];
+    let mut frames = vec![];
+    // From the graphic control extension, which applies to the next image.
+    let mut dispose = 0;
+    let mut transparent_index: Option<u8> = None;
+    loop {
+        match read_u8(f).map_err(&truncated)? {
+            BLOCK_TRAILER => {
+                break;
+            }
+            BLOCK_EXTENSION => {
+                let label = read_u8(f).map_err(&truncated)?;
+                if label == EXTENSION_GRAPHIC_CONTROL {
+                    let mut data: Vec<u8> = vec![];
+                    sub_blocks_read(f, Some(&mut data)).map_err(&truncated)?;
+                    if data.len() < 4 {
+                        return Err(Error::new(ErrorKind::Other, "Invalid graphic control"));
+                    }
+                    dispose = (data[0] >> 2) & 0x07;
+                    transparent_index = if (data[0] & 0x01) != 0 { Some(data[3]) } else { None };
+                } else {
+                    sub_blocks_read(f, None).map_err(&truncated)?;
+                }
+            }
+            BLOCK_IMAGE => {
+                if frames.len() == FRAMES_MAX {
+                    return Err(Error::new(ErrorKind::Other, "Too many images"));
+                }
+                let mut descriptor: [u8; 9] = [0; 9];
+                f.read_exact(&mut descriptor).map_err(&truncated)?;
+                let mut reader = &descriptor[..];
+                let x = read_u16(&mut reader)? as usize;
+                let y = read_u16(&mut reader)? as usize;
+                let width = read_u16(&mut reader)? as usize;
+                let height = read_u16(&mut reader)? as usize;
+                let flags = descriptor[8];
+                let color_table_local = if (flags & 0x80) != 0 {
+                    Some(color_table_read(f, 2 << (flags & 0x07)).map_err(&truncated)?)
+                } else {
+                    None
+                };
+                let is_interlaced = (flags & 0x40) != 0;
+
+                let code_bits_min = read_u8(f).map_err(&truncated)? as u32;
+                if !(code_bits_min >= 2 && code_bits_min < LZW_CODE_BITS_MAX) {
+                    return Err(Error::new(ErrorKind::Other, "Invalid LZW code size"));
+                }
+                let mut data: Vec<u8> = vec![];
+                sub_blocks_read(f, Some(&mut data)).map_err(&truncated)?;
+
+                let color_table = match color_table_local.as_ref().or(
+                    color_table_global.as_ref())
+                {
+                    Some(color_table) => color_table,
+                    None => {
+                        return Err(Error::new(ErrorKind::Other, "Missing color table"));
+                    }
+                };
+                if width * height > super::IMAGE_PIXELS_MAX {
+                    return Err(Error::new(ErrorKind::Other, "Image size too large"));
+                }
+                // Each code (of at least 3 bits) expands to at most `1 << LZW_CODE_BITS_MAX`
+                // values, check before allocating.
+                if width * height > ((data.len() * 8) / 3) << LZW_CODE_BITS_MAX {
+                    return Err(Error::new(ErrorKind::UnexpectedEof, "Image data is truncated"));
+                }
+                let mut indices: Vec<u8> = vec![0; width * height];
+                lzw_decode(&data, code_bits_min, &mut indices)?;
+
+                if dispose == DISPOSE_PREVIOUS {
+                    canvas_prev.clear();
+                    canvas_prev.extend_from_slice(&canvas);
+                }
+                let rows: Vec<usize> = if is_interlaced {
+                    interlaced_rows(height)
+                } else {
+                    (0..height).collect()
+                };
+                // Images may extend past the canvas, only the part within it is drawn.
+                for (row_indices, &row) in indices.chunks(::std::cmp::max(width, 1)).zip(&rows) {
+                    let y_canvas = y + row;
+                    if y_canvas >= size[1] {
+                        continue;
+                    }
+                    for (i, &index) in row_indices.iter().enumerate() {
+                        let x_canvas = x + i;
+                        if x_canvas >= size[0] || transparent_index == Some(index) {
+                            continue;
+                        }
+                        // Indices past the end of the color table are black.
+                        let rgb = color_table.get(index as usize).unwrap_or(&[0, 0, 0]);
+                        let offset = (y_canvas * size[0] + x_canvas) * bytes_per_pixel;
+                        canvas[offset..offset + 4].copy_from_slice(
+                            &[rgb[0], rgb[1], rgb[2], 255]);
+                    }
+                }
+
+                let mut pixel_buffer: Vec<[u8; 3]> = Vec::with_capacity(size[0] * size[1]);
+                layout.row_to_rgb(&canvas, &mut pixel_buffer);
+                frames.push((size, 255, pixel_buffer));
+                if is_first_only {
+                    break;
+                }
+
+                if dispose == DISPOSE_BACKGROUND {
+                    for y_canvas in y..::std::cmp::min(y + height, size[1]) {
+                        for x_canvas in x..::std::cmp::min(x + width, size[0]) {
+                            let offset = (y_canvas * size[0] + x_canvas) * bytes_per_pixel;
+                            canvas[offset..offset + 4].copy_from_slice(&[0, 0, 0, 0]);
+                        }
+                    }
+                } else if dispose == DISPOSE_PREVIOUS {
+                    ::std::mem::swap(&mut canvas, &mut canvas_prev);
+                }
+                dispose = 0;
+                transparent_index = None;
+            }
+            _ => {
+                return Err(Error::new(ErrorKind::Other, "Invalid block"));
+            }
+        }
+    }
+    if frames.is_empty() {
+        return Err(Error::new(ErrorKind::Other, "No images"));
+    }
+    return Ok(frames);
+}
@@ -0,0 +1,105 @@
+
+///
+/// Module for reading JPEG images, using the `jpeg-decoder` crate
+/// (only available when built with the `jpeg` feature).
+///
+/// The decoder can't decode a row at a time, so the whole image is decoded first.
+///
+
+use ::std::io::{
+    Cursor,
+    Error,
+    ErrorKind,
+};
+
+use std::io::prelude::*;
+
+/// Returns (size, color_max, pixel_data), or fail.
+#[allow(dead_code)]
+pub fn from_bytes(
+    data: &[u8],
+) -> Result<([usize; 2], usize, Vec<[u8; 3]>), Error> {
+    return from_reader(&mut Cursor::new(data));
+}
+
+/// Read the image, any malformed input returns an error (never panics).
+pub fn from_reader<R: Read>(
+    f: &mut R,
+) -> Result<([usize; 2], usize, Vec<[u8; 3]>), Error> {
+    let mut pixel_buffer: Vec<[u8; 3]> = vec![];
+    let (size, color_max) = rows_from_reader(f, |size, _color_max, row| {
+        if pixel_buffer.is_empty() {
+            pixel_buffer.reserve_exact(size[0] * size[1]);
+        }
+        pixel_buffer.extend_from_slice(row);
+    })?;
+    return Ok((size, color_max, pixel_buffer));
+}
+
+/// Read the image, passing each row (top to bottom) to `row_fn`
+/// along with the size and color range, returns (size, color_max).
+pub fn rows_from_reader<R: Read, F>(
+    f: &mut R,
+    mut row_fn: F,
+) -> Result<([usize; 2], usize), Error> where F: FnMut(&[usize; 2], usize, &[[u8; 3]]) {
+    let (size, layout, data) = decode(f)?;
+    let row_len = size[0] * layout.bytes_per_pixel();
+    let mut row: Vec<[u8; 3]> = Vec::with_capacity(size[0]);
+    for data_row in data.chunks(row_len) {
+        layout.row_to_rgb(data_row, &mut row);
+        row_fn(&size, 255, &row);
+    }
+    return Ok((size, 255));
+}
+
+/// Decode the image, returns (size, layout, data).
+#[cfg(feature = "jpeg")]
+fn decode<R: Read>(
+    f: &mut R,
+) -> Result<([usize; 2], super::PixelLayout, Vec<u8>), Error> {
+    use super::PixelLayout;
+    use jpeg_decoder::PixelFormat;
+
+    let to_error = |e: ::jpeg_decoder::Error| Error::new(ErrorKind::Other, e.to_string());
+    let mut decoder = ::jpeg_decoder::Decoder::new(f);
+    decoder.read_info().map_err(to_error)?;
+    let info = match decoder.info() {
+        Some(info) => info,
+        None => {
+            return Err(Error::new(ErrorKind::Other, "Invalid header"));
+        }
+    };
+    let size = [info.width as usize, info.height as usize];
+    if !(size[0] > 0 && size[1] > 0) {
+        return Err(Error::new(ErrorKind::Other, "Invalid size"));
+    }
+    if size[0] * size[1] > super::IMAGE_PIXELS_MAX {
+        return Err(Error::new(ErrorKind::Other, "Image size too large"));
+    }
+    decoder.set_max_decoding_buffer_size(super::IMAGE_PIXELS_MAX * 4);
+    let mut data = decoder.decode().map_err(to_error)?;
+    let layout = match info.pixel_format {
+        PixelFormat::L8 => PixelLayout::Gray8,
+        PixelFormat::L16 => {
+            // Decoded as native endian, Gray16 is most significant byte first.
+            for p in data.chunks_mut(2) {
+                let value = u16::from_ne_bytes([p[0], p[1]]);
+                p.copy_from_slice(&value.to_be_bytes());
+            }
+            PixelLayout::Gray16
+        }
+        PixelFormat::RGB24 => PixelLayout::RGB8,
+        PixelFormat::CMYK32 => PixelLayout::CMYK8,
+    };
+    if data.len() != size[0] * size[1] * layout.bytes_per_pixel() {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "Image data is truncated"));
+    }
+    return Ok((size, layout, data));
+}
+
+#[cfg(not(feature = "jpeg"))]
+fn decode<R: Read>(
+    _f: &mut R,
+) -> Result<([usize; 2], super::PixelLayout, Vec<u8>), Error> {
+    return Err(Error::new(ErrorKind::Other, "JPEG input requires the 'jpeg' feature"));
+}
@@ -0,0 +1,719 @@
+///
+/// Module for reading PNG & APNG images, see: https://www.w3.org/TR/png/
+///
+/// Supports all bit depths & color types (interlaced images aren't supported).
+/// The frames of animated images are drawn onto the canvas in order
+/// (using the disposal & blending of each frame), so animations are read as they're shown.
+/// Transparent pixels are white.
+///
+
+use ::std::io::{
+    Cursor,
+    Error,
+    ErrorKind,
+};
+
+use std::io::prelude::*;
+
+use super::PixelLayout;
+
+const COLOR_GRAY: u8 = 0;
+const COLOR_RGB: u8 = 2;
+const COLOR_PALETTE: u8 = 3;
+const COLOR_GRAY_ALPHA: u8 = 4;
+const COLOR_RGBA: u8 = 6;
+
+const DISPOSE_BACKGROUND: u8 = 1;
+const DISPOSE_PREVIOUS: u8 = 2;
+
+const BLEND_OVER: u8 = 1;
+
+// Chunks are never longer than this.
+const CHUNK_LEN_MAX: u32 = 0x7fff_ffff;
+
+// Stop reading frames after this many.
+const FRAMES_MAX: usize = 4096;
+
+/// Returns (size, color_max, pixel_data) of the (default) image, or fail.
+#[allow(dead_code)]
+pub fn from_bytes(
+    data: &[u8],
+) -> Result<([usize; 2], usize, Vec<[u8; 3]>), Error> {
+    return from_reader(&mut Cursor::new(data));
+}
+
+/// Read the image (not the animation), any malformed input returns an error (never panics).
+pub fn from_reader<R: Read>(
+    f: &mut R,
+) -> Result<([usize; 2], usize, Vec<[u8; 3]>), Error> {
+    let png = png_read(f)?;
+    let pixels = image_decode(&png.header, &png.header.size, &png.data)?;
+    let mut pixel_buffer: Vec<[u8; 3]> = Vec::with_capacity(pixels.len() / 4);
+    PixelLayout::RGBA8.row_to_rgb(&pixels, &mut pixel_buffer);
+    return Ok((png.header.size, 255, pixel_buffer));
+}
+
+/// Read all frames of an animation (a single frame for images which aren't animated),
+/// see: `from_reader`.
+pub fn frames_from_reader<R: Read>(
+    f: &mut R,
+) -> Result<Vec<([usize; 2], usize, Vec<[u8; 3]>)>, Error> {
+    let png = png_read(f)?;
+    let size = png.header.size;
+    if png.frames.is_empty() {
+        let pixels = image_decode(&png.header, &size, &png.data)?;
+        let mut pixel_buffer: Vec<[u8; 3]> = Vec::with_capacity(size[0] * size[1]);
+        PixelLayout::RGBA8.row_to_rgb(&pixels, &mut pixel_buffer);
+        return Ok(vec![(size, 255, pixel_buffer)]);
+    }
+
+    let mut canvas: Vec<u8> = vec![0; size[0] * size[1] * 4];
+    let mut canvas_prev: Vec<u8> = vec![];
+    let mut frames = vec![];
+    for (frame_index, frame) in png.frames.iter().enumerate() {
+        let pixels = image_decode(&png.header, &frame.size, &frame.data)?;
+        // Restoring the previous canvas before the first frame clears it.
+        let dispose = if frame_index == 0 && frame.dispose == DISPOSE_PREVIOUS {
+            DISPOSE_BACKGROUND
+        } else {
+            frame.dispose
+        };
+        if dispose == DISPOSE_PREVIOUS {
+            canvas_prev.clear();
+            canvas_prev.extend_from_slice(&canvas);
+        }
+        for (y, row) in pixels.chunks(frame.size[0] * 4).enumerate() {
+            let offset = ((frame.offset[1] + y) * size[0] + frame.offset[0]) * 4;
+            let row_dst = &mut canvas[offset..offset + row.len()];
+            if frame.blend == BLEND_OVER {
+                for (p_dst, p_src) in row_dst.chunks_mut(4).zip(row.chunks(4)) {
+                    let p = blend_over(p_dst, p_src);
+                    p_dst.copy_from_slice(&p);
+                }
+            } else {
+                row_dst.copy_from_slice(row);
+            }
+        }
+
+        let mut pixel_buffer: Vec<[u8; 3]> = Vec::with_capacity(size[0] * size[1]);
+        PixelLayout::RGBA8.row_to_rgb(&canvas, &mut pixel_buffer);
+        frames.push((size, 255, pixel_buffer));
+
+        if dispose == DISPOSE_BACKGROUND {
+            for y in 0..frame.size[1] {
+                let offset = ((frame.offset[1] + y) * size[0] + frame.offset[0]) * 4;
+                for v in &mut canvas[offset..offset + frame.size[0] * 4] {
+                    *v = 0;
+                }
+            }
+        } else if dispose == DISPOSE_PREVIOUS {
+            ::std::mem::swap(&mut canvas, &mut canvas_prev);
+        }
+    }
+    return Ok(frames);
+}
+
+/// Read the image, passing each row (top to bottom) to `row_fn`
+/// along with the size and color range, returns (size, color_max).
+///
+/// The image is decoded first, since the compressed data isn't split into rows.
+pub fn rows_from_reader<R: Read, F>(
+    f: &mut R,
+    mut row_fn: F,
+) -> Result<([usize; 2], usize), Error> where F: FnMut(&[usize; 2], usize, &[[u8; 3]]) {
+    let (size, color_max, pixel_buffer) = from_reader(f)?;
+    for row in pixel_buffer.chunks(size[0]) {
+        row_fn(&size, color_max, row);
+    }
+    return Ok((size, color_max));
+}
+
+struct Header {
+    size: [usize; 2],
+    bit_depth: u8,
+    color_type: u8,
+    /// RGBA for each palette index.
+    palette: Vec<[u8; 4]>,
+    /// Samples matching this value are transparent (gray & RGB images).
+    transparent: Option<[u16; 3]>,
+}
+
+/// A frame of an animation (from the `fcTL` chunk & its data).
+struct Frame {
+    size: [usize; 2],
+    offset: [usize; 2],
+    dispose: u8,
+    blend: u8,
+    /// Compressed (zlib) data.
+    data: Vec<u8>,
+}
+
+struct Png {
+    header: Header,
+    /// Compressed (zlib) data of the image.
+    data: Vec<u8>,
+    /// Frames of an animation, empty when the image isn't animated.
+    frames: Vec<Frame>,
+}
+
+fn u32_from_be(
+    data: &[u8],
+) -> u32 {
+    return u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+}
+
+/// Read all chunks, returns the header with compressed data of the image & each frame.
+fn png_read<R: Read>(
+    f: &mut R,
+) -> Result<Png, Error> {
+    let truncated = |e: Error| {
+        if e.kind() == ErrorKind::UnexpectedEof {
+            Error::new(ErrorKind::UnexpectedEof, "Image data is truncated")
+        } else {
+            e
+        }
+    };
+
+    let mut signature: [u8; 8] = [0; 8];
+    f.read_exact(&mut signature).map_err(&truncated)?;
+    if &signature != b"\x89PNG\r\n\x1a\n" {
+        return Err(Error::new(ErrorKind::Other, "Invalid header"));
+    }
+
+    let mut header: Option<Header> = None;
+    let mut data: Vec<u8> = vec![];
+    let mut frames: Vec<Frame> = vec![];
+    let mut is_animated = false;
+    loop {
+        let mut chunk_header: [u8; 8] = [0; 8];
+        f.read_exact(&mut chunk_header).map_err(&truncated)?;
+        let chunk_len = u32_from_be(&chunk_header[0..4]);
+        if chunk_len > CHUNK_LEN_MAX {
+            return Err(Error::new(ErrorKind::Other, "Invalid chunk length"));
+        }
+        // Read what's available instead of allocating the length (which may be invalid),
+        // CRC's aren't checked since invalid data is detected when decoding.
+        let mut chunk: Vec<u8> = vec![];
+        f.by_ref().take(chunk_len as u64 + 4).read_to_end(&mut chunk)?;
+        if chunk.len() != chunk_len as usize + 4 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "Image data is truncated"));
+        }
+        chunk.truncate(chunk_len as usize);
+
+        let chunk_type = &chunk_header[4..8];
+        if chunk_type == b"IEND" {
+            break;
+        } else if chunk_type == b"IHDR" {
+            header = Some(header_from_chunk(&chunk)?);
+            continue;
+        }
+        let header = match header {
+            Some(ref mut header) => header,
+            None => {
+                return Err(Error::new(ErrorKind::Other, "Missing header"));
+            }
+        };
+        if chunk_type == b"PLTE" {
+            header.palette = chunk.chunks(3).filter(|c| c.len() == 3).map(
+                |c| [c[0], c[1], c[2], 255]).collect();
+        } else if chunk_type == b"tRNS" {
+            if header.color_type == COLOR_PALETTE {
+                for (p, alpha) in header.palette.iter_mut().zip(&chunk) {
+                    p[3] = *alpha;
+                }
+            } else if chunk.len() >= 6 && header.color_type == COLOR_RGB {
+                let v = |i: usize| u16::from_be_bytes([chunk[i], chunk[i + 1]]);
+                header.transparent = Some([v(0), v(2), v(4)]);
+            } else if chunk.len() >= 2 && header.color_type == COLOR_GRAY {
+                let v = u16::from_be_bytes([chunk[0], chunk[1]]);
+                header.transparent = Some([v, v, v]);
+            }
+        } else if chunk_type == b"acTL" {
+            is_animated = true;
+        } else if chunk_type == b"fcTL" && is_animated {
+            if chunk.len() < 26 {
+                return Err(Error::new(ErrorKind::Other, "Invalid frame control"));
+            }
+            if frames.len() == FRAMES_MAX {
+                return Err(Error::new(ErrorKind::Other, "Too many frames"));
+            }
+            let value = |i: usize| u32_from_be(&chunk[i..i + 4]) as usize;
+            let frame_size = [value(4), value(8)];
+            let offset = [value(12), value(16)];
+            if !(frame_size[0] > 0 && frame_size[1] > 0 &&
+                 frame_size[0].checked_add(offset[0]).map_or(false, |x| x <= header.size[0]) &&
+                 frame_size[1].checked_add(offset[1]).map_or(false, |y| y <= header.size[1]))
+            {
+                return Err(Error::new(ErrorKind::Other, "Frame outside of the image"));
+            }
+            frames.push(Frame {
+                size: frame_size,
+                offset: offset,
+                dispose: chunk[24],
+                blend: chunk[25],
+                data: vec![],
+            });
+        } else if chunk_type == b"IDAT" {
+            data.extend_from_slice(&chunk);
+            // The image is the first frame when its frame control comes first.
+            if let Some(frame) = frames.last_mut() {
+                frame.data.extend_from_slice(&chunk);
+            }
+        } else if chunk_type == b"fdAT" {
+            // Skip the sequence number.
+            match frames.last_mut() {
+                Some(frame) if chunk.len() >= 4 => {
+                    frame.data.extend_from_slice(&chunk[4..]);
+                }
+                _ => {
+                    return Err(Error::new(ErrorKind::Other, "Frame data without frame control"));
+                }
+            }
+        }
+    }
+    let header = match header {
+        Some(header) => header,
+        None => {
+            return Err(Error::new(ErrorKind::Other, "Missing header"));
+        }
+    };
+    if data.is_empty() {
+        return Err(Error::new(ErrorKind::Other, "No images"));
+    }
+    return Ok(Png {
+        header: header,
+        data: data,
+        frames: frames,
+    });
+}
+
+fn header_from_chunk(
+    chunk: &[u8],
+) -> Result<Header, Error> {
+    if chunk.len() < 13 {
+        return Err(Error::new(ErrorKind::Other, "Invalid header"));
+    }
+    let size = [u32_from_be(&chunk[0..4]) as usize, u32_from_be(&chunk[4..8]) as usize];
+    if !(size[0] > 0 && size[1] > 0) {
+        return Err(Error::new(ErrorKind::Other, "Invalid size"));
+    }
+    match size[0].checked_mul(size[1]) {
+        Some(len) if len <= super::IMAGE_PIXELS_MAX => {}
+        _ => {
+            return Err(Error::new(ErrorKind::Other, "Image size too large"));
+        }
+    }
+    let (bit_depth, color_type) = (chunk[8], chunk[9]);
+    let is_valid = match color_type {
+        COLOR_GRAY => [1, 2, 4, 8, 16].contains(&bit_depth),
+        COLOR_PALETTE => [1, 2, 4, 8].contains(&bit_depth),
+        COLOR_RGB | COLOR_GRAY_ALPHA | COLOR_RGBA => [8, 16].contains(&bit_depth),
+        _ => false,
+    };
+    if !is_valid {
+        return Err(Error::new(ErrorKind::Other, "Unsupported bit depth or color type"));
+    }
+    if chunk[12] != 0 {
+        return Err(Error::new(ErrorKind::Other, "Interlaced images aren't supported"));
+    }
+    return Ok(Header {
+        size: size,
+        bit_depth: bit_depth,
+        color_type: color_type,
+        palette: vec![],
+        transparent: None,
+    });
+}
+
+/// Decompress & un-filter an image of `size`, returns RGBA pixels.
+fn image_decode(
+    header: &Header,
+    size: &[usize; 2],
+    data: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let channels = match header.color_type {
+        COLOR_GRAY | COLOR_PALETTE => 1,
+        COLOR_GRAY_ALPHA => 2,
+        COLOR_RGB => 3,
+        _ => 4,
+    };
+    let bits_per_pixel = channels * header.bit_depth as usize;
+    // The distance to the previous pixel when filtering (at least one byte).
+    let filter_bpp = ::std::cmp::max(1, bits_per_pixel / 8);
+    let row_len = (size[0] * bits_per_pixel + 7) / 8;
+    let data_len = (row_len + 1) * size[1];
+    let data = zlib_decode(data, data_len)?;
+
+    let mut pixels: Vec<u8> = Vec::with_capacity(size[0] * size[1] * 4);
+    let mut row_prev: Vec<u8> = vec![0; row_len];
+    let mut row: Vec<u8> = vec![0; row_len];
+    for row_src in data.chunks(row_len + 1) {
+        row.copy_from_slice(&row_src[1..]);
+        unfilter(row_src[0], filter_bpp, &mut row, &row_prev)?;
+        for x in 0..size[0] {
+            pixels.extend_from_slice(&pixel_to_rgba(header, &row, x));
+        }
+        ::std::mem::swap(&mut row, &mut row_prev);
+    }
+    return Ok(pixels);
+}
+
+/// Reverse the filter of a row, using the previous (un-filtered) row.
+fn unfilter(
+    filter: u8,
+    bpp: usize,
+    row: &mut [u8],
+    row_prev: &[u8],
+) -> Result<(), Error> {
+    match filter {
+        0 => {}
+        1 => {
+            for i in bpp..row.len() {
+                row[i] = row[i].wrapping_add(row[i - bpp]);
+            }
+        }
+        2 => {
+            for i in 0..row.len() {
+                row[i] = row[i].wrapping_add(row_prev[i]);
+            }
+        }
+        3 => {
+            for i in 0..row.len() {
+                let a = if i >= bpp { row[i - bpp] as u16 } else { 0 };
+                row[i] = row[i].wrapping_add(((a + row_prev[i] as u16) / 2) as u8);
+            }
+        }
+        4 => {
+            for i in 0..row.len() {
+                let (a, c) = if i >= bpp { (row[i - bpp], row_prev[i - bpp]) } else { (0, 0) };
+                let b = row_prev[i];
+                let p = a as i16 + b as i16 - c as i16;
+                let (pa, pb, pc) = (
+                    (p - a as i16).abs(),
+                    (p - b as i16).abs(),
+                    (p - c as i16).abs(),
+                );
+                let predict = if pa <= pb && pa <= pc { a } else if pb <= pc { b } else { c };
+                row[i] = row[i].wrapping_add(predict);
+            }
+        }
+        _ => {
+            return Err(Error::new(ErrorKind::Other, "Invalid filter"));
+        }
+    }
+    return Ok(());
+}
+
+/// The RGBA value of pixel `x` in an (un-filtered) row.
+fn pixel_to_rgba(
+    header: &Header,
+    row: &[u8],
+    x: usize,
+) -> [u8; 4] {
+    let bit_depth = header.bit_depth as usize;
+    // The sample of channel `c`, with its value reduced to 8 bits.
+    let sample = |channels: usize, c: usize| -> (u16, u8) {
+        if bit_depth == 16 {
+            let i = (x * channels + c) * 2;
+            return (u16::from_be_bytes([row[i], row[i + 1]]), row[i]);
+        } else if bit_depth == 8 {
+            let v = row[x * channels + c];
+            return (v as u16, v);
+        } else {
+            let bit = x * bit_depth;
+            let v = (row[bit / 8] >> (8 - bit_depth - (bit % 8))) & ((1 << bit_depth) - 1);
+            return (v as u16, ((v as usize * 255) / ((1 << bit_depth) - 1)) as u8);
+        }
+    };
+    match header.color_type {
+        COLOR_GRAY => {
+            let (v, v8) = sample(1, 0);
+            let alpha = if header.transparent == Some([v, v, v]) { 0 } else { 255 };
+            return [v8, v8, v8, alpha];
+        }
+        COLOR_PALETTE => {
+            // Indices past the end of the palette are black.
+            let (index, _) = sample(1, 0);
+            return *header.palette.get(index as usize).unwrap_or(&[0, 0, 0, 255]);
+        }
+        COLOR_GRAY_ALPHA => {
+            let ((_, v8), (_, a8)) = (sample(2, 0), sample(2, 1));
+            return [v8, v8, v8, a8];
+        }
+        COLOR_RGB => {
+            let (r, g, b) = (sample(3, 0), sample(3, 1), sample(3, 2));
+            let alpha = if header.transparent == Some([r.0, g.0, b.0]) { 0 } else { 255 };
+            return [r.1, g.1, b.1, alpha];
+        }
+        _ => {
+            return [sample(4, 0).1, sample(4, 1).1, sample(4, 2).1, sample(4, 3).1];
+        }
+    }
+}
+
+/// Blend `src` over `dst` (both RGBA).
+fn blend_over(
+    dst: &[u8],
+    src: &[u8],
+) -> [u8; 4] {
+    let (a_src, a_dst) = (src[3] as u32, dst[3] as u32);
+    let a_dst_visible = (a_dst * (255 - a_src) + 127) / 255;
+    let a = a_src + a_dst_visible;
+    if a == 0 {
+        return [0, 0, 0, 0];
+    }
+    let mix = |i: usize| ((src[i] as u32 * a_src + dst[i] as u32 * a_dst_visible) / a) as u8;
+    return [mix(0), mix(1), mix(2), a as u8];
+}
+
+/// Reads bits from a deflate stream (least significant bit first).
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit_buf: u32,
+    bit_len: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn bits(
+        &mut self,
+        len: u32,
+    ) -> Result<u32, Error> {
+        while self.bit_len < len {
+            match self.data.get(self.pos) {
+                Some(byte) => {
+                    self.bit_buf |= (*byte as u32) << self.bit_len;
+                    self.bit_len += 8;
+                    self.pos += 1;
+                }
+                None => {
+                    return Err(Error::new(ErrorKind::UnexpectedEof, "Image data is truncated"));
+                }
+            }
+        }
+        let value = self.bit_buf & ((1_u32 << len) - 1);
+        self.bit_buf >>= len;
+        self.bit_len -= len;
+        return Ok(value);
+    }
+}
+
+/// Canonical Huffman codes, the number of codes of each length & symbols ordered by code.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn from_lengths(
+        lengths: &[u8],
+    ) -> Result<Huffman, Error> {
+        let mut counts: [u16; 16] = [0; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+        // Reject codes which use more values than are available.
+        let mut left: i32 = 1;
+        for len in 1..16 {
+            left = (left << 1) - counts[len] as i32;
+            if left < 0 {
+                return Err(Error::new(ErrorKind::Other, "Invalid Huffman code"));
+            }
+        }
+        let mut offsets: [u16; 16] = [0; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols: Vec<u16> = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        return Ok(Huffman {
+            counts: counts,
+            symbols: symbols,
+        });
+    }
+
+    fn decode(
+        &self,
+        bits: &mut BitReader,
+    ) -> Result<u16, Error> {
+        let (mut code, mut first, mut index): (i32, i32, i32) = (0, 0, 0);
+        for len in 1..16 {
+            code |= bits.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + (code - first)) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        return Err(Error::new(ErrorKind::Other, "Invalid Huffman code"));
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7,
+    8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+
+/// The order code length codes are stored in (dynamic blocks).
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Decompress a zlib stream of exactly `data_len` bytes (any data after this is ignored),
+/// the length is never allocated up-front since it may not match the data.
+fn zlib_decode(
+    data_src: &[u8],
+    data_len: usize,
+) -> Result<Vec<u8>, Error> {
+    if data_src.len() < 2 ||
+        (data_src[0] & 0x0f) != 8 ||
+        (((data_src[0] as u16) << 8) | data_src[1] as u16) % 31 != 0 ||
+        (data_src[1] & 0x20) != 0
+    {
+        return Err(Error::new(ErrorKind::Other, "Invalid zlib header"));
+    }
+    let mut bits = BitReader { data: &data_src[2..], pos: 0, bit_buf: 0, bit_len: 0 };
+    let mut data: Vec<u8> = vec![];
+    let mut is_last = false;
+    while !is_last && data.len() < data_len {
+        is_last = bits.bits(1)? == 1;
+        match bits.bits(2)? {
+            0 => {
+                // Stored, aligned to the next byte.
+                bits.bit_buf = 0;
+                bits.bit_len = 0;
+                let len = bits.bits(16)? as usize;
+                let len_inv = bits.bits(16)? as usize;
+                if len != (!len_inv & 0xffff) {
+                    return Err(Error::new(ErrorKind::Other, "Invalid stored block"));
+                }
+                let block = match bits.data.get(bits.pos..bits.pos + len) {
+                    Some(block) => block,
+                    None => {
+                        return Err(Error::new(ErrorKind::UnexpectedEof, "Image data is truncated"));
+                    }
+                };
+                data.extend_from_slice(block);
+                bits.pos += len;
+            }
+            1 => {
+                let mut lengths: [u8; 288 + 30] = [0; 288 + 30];
+                for (i, len) in lengths.iter_mut().enumerate() {
+                    *len = match i {
+                        0..=143 => 8,
+                        144..=255 => 9,
+                        256..=279 => 7,
+                        280..=287 => 8,
+                        _ => 5,
+                    };
+                }
+                let lit = Huffman::from_lengths(&lengths[..288])?;
+                let dist = Huffman::from_lengths(&lengths[288..])?;
+                inflate_block(&mut bits, &lit, &dist, &mut data, data_len)?;
+            }
+            2 => {
+                let lit_len = bits.bits(5)? as usize + 257;
+                let dist_len = bits.bits(5)? as usize + 1;
+                let code_len = bits.bits(4)? as usize + 4;
+                if lit_len > 286 || dist_len > 30 {
+                    return Err(Error::new(ErrorKind::Other, "Invalid Huffman code"));
+                }
+                let mut code_lengths: [u8; 19] = [0; 19];
+                for &i in &CODE_LENGTH_ORDER[..code_len] {
+                    code_lengths[i] = bits.bits(3)? as u8;
+                }
+                let code = Huffman::from_lengths(&code_lengths)?;
+
+                let mut lengths: Vec<u8> = Vec::with_capacity(lit_len + dist_len);
+                while lengths.len() < lit_len + dist_len {
+                    let symbol = code.decode(&mut bits)?;
+                    let (value, repeat) = match symbol {
+                        0..=15 => (symbol as u8, 1),
+                        16 => match lengths.last() {
+                            Some(&len_prev) => (len_prev, 3 + bits.bits(2)?),
+                            None => {
+                                return Err(Error::new(ErrorKind::Other, "Invalid Huffman code"));
+                            }
+                        },
+                        17 => (0, 3 + bits.bits(3)?),
+                        _ => (0, 11 + bits.bits(7)?),
+                    };
+                    for _ in 0..repeat {
+                        lengths.push(value);
+                    }
+                }
+                if lengths.len() > lit_len + dist_len {
+                    return Err(Error::new(ErrorKind::Other, "Invalid Huffman code"));
+                }
+                let lit = Huffman::from_lengths(&lengths[..lit_len])?;
+                let dist = Huffman::from_lengths(&lengths[lit_len..])?;
+                inflate_block(&mut bits, &lit, &dist, &mut data, data_len)?;
+            }
+            _ => {
+                return Err(Error::new(ErrorKind::Other, "Invalid block type"));
+            }
+        }
+    }
+    if data.len() < data_len {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "Image data is truncated"));
+    }
+    data.truncate(data_len);
+    return Ok(data);
+}
+
+/// Decompress a Huffman coded block, stopping once `data_len` is reached.
+fn inflate_block(
+    bits: &mut BitReader,
+    lit: &Huffman,
+    dist: &Huffman,
+    data: &mut Vec<u8>,
+    data_len: usize,
+) -> Result<(), Error> {
+    while data.len() < data_len {
+        let symbol = lit.decode(bits)? as usize;
+        if symbol < 256 {
+            data.push(symbol as u8);
+        } else if symbol == 256 {
+            return Ok(());
+        } else {
+            let i = symbol - 257;
+            if i >= LENGTH_BASE.len() {
+                return Err(Error::new(ErrorKind::Other, "Invalid length"));
+            }
+            let len = LENGTH_BASE[i] as usize + bits.bits(LENGTH_EXTRA[i] as u32)? as usize;
+            let i = dist.decode(bits)? as usize;
+            if i >= DIST_BASE.len() {
+                return Err(Error::new(ErrorKind::Other, "Invalid distance"));
+            }
+            let distance = DIST_BASE[i] as usize + bits.bits(DIST_EXTRA[i] as u32)? as usize;
+            if distance > data.len() {
+                return Err(Error::new(ErrorKind::Other, "Invalid distance"));
+            }
+            let start = data.len() - distance;
+            for j in 0..len {
+                let v = data[start + j];
+                data.push(v);
+            }
+        }
+    }
+    return Ok(());
+}
@@ -17,9 +17,8 @@ macro_rules! elem {
     }
 }
 
-/// Returns (size, pixel_data), or fail.
-
 use ::std::io::{
+    Cursor,
     Error,
     ErrorKind,
     SeekFrom,
@@ -28,12 +27,75 @@ use ::std::io::{
 use std::io::prelude::*;
 use std::str::FromStr;
 
+// Header numbers longer than this are never valid.
+const NUMBER_LEN_MAX: usize = 20;
+
+/// Returns (size, color_max, pixel_data), or fail.
+#[allow(dead_code)]
 pub fn from_file(
-    mut f: &::std::fs::File,
+    f: &::std::fs::File,
+) -> Result<([usize; 2], usize, Vec<[u8; 3]>), Error> {
+    let mut f = f;
+    return from_reader(&mut f);
+}
+
+/// Read from memory, see: `from_file`.
+#[allow(dead_code)]
+pub fn from_bytes(
+    data: &[u8],
 ) -> Result<([usize; 2], usize, Vec<[u8; 3]>), Error> {
+    return from_reader(&mut Cursor::new(data));
+}
+
+/// Read all images from a multi-image stream (images written one after another),
+/// see: `from_reader`.
+pub fn frames_from_reader<R: Read + Seek>(
+    f: &mut R,
+) -> Result<Vec<([usize; 2], usize, Vec<[u8; 3]>)>, Error> {
+    let mut frames = vec![];
+    loop {
+        frames.push(from_reader(f)?);
+
+        // Stop at the end of the data, ignoring trailing white-space.
+        let mut buf: [u8; 1] = [0];
+        loop {
+            if f.read(&mut buf)? == 0 {
+                return Ok(frames);
+            }
+            if !elem!(buf[0], ' ' as u8, '\t' as u8, '\r' as u8, '\n' as u8) {
+                f.seek(SeekFrom::Current(-1))?;
+                break;
+            }
+        }
+    }
+}
 
-    fn read_until_newline(
-        mut f: &::std::fs::File,
+/// Read the image, any malformed input returns an error (never panics),
+/// the header is validated against the length of the data before allocating.
+pub fn from_reader<R: Read + Seek>(
+    f: &mut R,
+) -> Result<([usize; 2], usize, Vec<[u8; 3]>), Error> {
+    let mut pixel_buffer: Vec<[u8; 3]> = vec![];
+    let (size, color_max) = rows_from_reader(f, |size, _color_max, row| {
+        if pixel_buffer.is_empty() {
+            pixel_buffer.reserve_exact(size[0] * size[1]);
+        }
+        pixel_buffer.extend_from_slice(row);
+    })?;
+    return Ok((size, color_max, pixel_buffer));
+}
+
+/// Read the image a row at a time (top to bottom), passing each to `row_fn`
+/// along with the size and color range, returns (size, color_max).
+///
+/// Rows are read one at a time, so pixels can be converted without storing the whole image.
+pub fn rows_from_reader<R: Read + Seek, F>(
+    f: &mut R,
+    mut row_fn: F,
+) -> Result<([usize; 2], usize), Error> where F: FnMut(&[usize; 2], usize, &[[u8; 3]]) {
+
+    fn read_until_newline<R: Read>(
+        f: &mut R,
     ) -> Result<(), Error> {
         let mut buf: [u8; 1] = [0];
         loop {
@@ -45,8 +107,8 @@ pub fn from_file(
         Ok(())
     }
 
-    fn read_peek_byte(
-        mut f: &::std::fs::File,
+    fn read_peek_byte<R: Read + Seek>(
+        f: &mut R,
     ) -> Result<u8, Error> {
         let mut buf: [u8; 1] = [0];
         f.read_exact(&mut buf)?;
@@ -54,8 +116,8 @@ pub fn from_file(
         return Ok(buf[0]);
     }
 
-    fn read_as_usize_skip_ws(
-        mut f: &::std::fs::File,
+    fn read_as_usize_skip_ws<R: Read>(
+        f: &mut R,
     ) -> Result<usize, Error> {
         // note, we could attempt to evaluate this as bytes
         // (atio style). for now it seems Rust's std lib doesn't support this.
@@ -68,13 +130,11 @@ pub fn from_file(
                 if num_str.len() != 0 {
                     break;
                 }
+            } else if num_str.len() == NUMBER_LEN_MAX {
+                return Err(Error::new(ErrorKind::Other, "Number too long"));
             } else {
                 num_str.push(buf[0] as char);
             }
-
-            if num_str.len() == 0 {
-                return Err(Error::new(ErrorKind::Other, "No number found"));
-            }
         }
 
         return match usize::from_str(num_str.as_str()) {
@@ -125,14 +185,34 @@ pub fn from_file(
 
     // All header data is read.
 
-    // TODO, support allocation failure
-    let pixel_buffer_len = size[0] * size[1];
-    let mut pixel_buffer = Vec::<[u8; 3]>::with_capacity(pixel_buffer_len);
-    let mut pixel: [u8; 3] = [0; 3];
-    for _ in 0..pixel_buffer_len {
-        f.read_exact(&mut pixel)?;
-        pixel_buffer.push(pixel);
+    if color_max > 255 {
+        return Err(Error::new(ErrorKind::Other, "16 bit color depth is not supported"));
     }
-    return Ok((size, color_max, pixel_buffer));
-}
 
+    let pixel_buffer_len = match size[0].checked_mul(size[1]) {
+        Some(len) if len <= super::IMAGE_PIXELS_MAX => len,
+        _ => {
+            return Err(Error::new(ErrorKind::Other, "Image size too large"));
+        }
+    };
+
+    // Ensure the data exists before allocating.
+    {
+        let pos = f.seek(SeekFrom::Current(0))?;
+        let end = f.seek(SeekFrom::End(0))?;
+        f.seek(SeekFrom::Start(pos))?;
+        if ((end - pos) as usize) / 3 < pixel_buffer_len {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "Image data is truncated"));
+        }
+    }
+
+    let layout = super::PixelLayout::RGB8;
+    let mut data: Vec<u8> = vec![0; size[0] * layout.bytes_per_pixel()];
+    let mut row: Vec<[u8; 3]> = Vec::with_capacity(size[0]);
+    for _ in 0..size[1] {
+        f.read_exact(&mut data)?;
+        layout.row_to_rgb(&data, &mut row);
+        row_fn(&size, color_max, &row);
+    }
+    return Ok((size, color_max));
+}
@@ -0,0 +1,136 @@
+
+///
+/// Module for reading QOI images, see: https://qoiformat.org/qoi-specification.pdf
+///
+/// Pixels are decoded a row at a time, so the whole image is never stored.
+///
+
+use ::std::io::{
+    Cursor,
+    Error,
+    ErrorKind,
+};
+
+use std::io::prelude::*;
+
+use super::PixelLayout;
+
+const OP_RGB: u8 = 0xfe;
+const OP_RGBA: u8 = 0xff;
+const OP_INDEX: u8 = 0x00;
+const OP_DIFF: u8 = 0x40;
+const OP_LUMA: u8 = 0x80;
+const OP_RUN: u8 = 0xc0;
+const OP_MASK: u8 = 0xc0;
+
+/// Returns (size, color_max, pixel_data), or fail.
+#[allow(dead_code)]
+pub fn from_bytes(
+    data: &[u8],
+) -> Result<([usize; 2], usize, Vec<[u8; 3]>), Error> {
+    return from_reader(&mut Cursor::new(data));
+}
+
+/// Read the image, any malformed input returns an error (never panics).
+pub fn from_reader<R: Read>(
+    f: &mut R,
+) -> Result<([usize; 2], usize, Vec<[u8; 3]>), Error> {
+    let mut pixel_buffer: Vec<[u8; 3]> = vec![];
+    let (size, color_max) = rows_from_reader(f, |_size, _color_max, row| {
+        pixel_buffer.extend_from_slice(row);
+    })?;
+    return Ok((size, color_max, pixel_buffer));
+}
+
+/// Read the image a row at a time (top to bottom), passing each to `row_fn`
+/// along with the size and color range, returns (size, color_max).
+pub fn rows_from_reader<R: Read, F>(
+    f: &mut R,
+    mut row_fn: F,
+) -> Result<([usize; 2], usize), Error> where F: FnMut(&[usize; 2], usize, &[[u8; 3]]) {
+    let mut header: [u8; 14] = [0; 14];
+    f.read_exact(&mut header)?;
+    if &header[0..4] != b"qoif" {
+        return Err(Error::new(ErrorKind::Other, "Invalid header"));
+    }
+    let size = [
+        u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize,
+        u32::from_be_bytes([header[8], header[9], header[10], header[11]]) as usize,
+    ];
+    if !(size[0] > 0 && size[1] > 0) {
+        return Err(Error::new(ErrorKind::Other, "Invalid size"));
+    }
+    match size[0].checked_mul(size[1]) {
+        Some(len) if len <= super::IMAGE_PIXELS_MAX => {}
+        _ => {
+            return Err(Error::new(ErrorKind::Other, "Image size too large"));
+        }
+    }
+    // The channels don't change how pixels are stored, only what they contain.
+    if !(header[12] == 3 || header[12] == 4) {
+        return Err(Error::new(ErrorKind::Other, "Invalid channels"));
+    }
+
+    let truncated = |e: Error| {
+        if e.kind() == ErrorKind::UnexpectedEof {
+            Error::new(ErrorKind::UnexpectedEof, "Image data is truncated")
+        } else {
+            e
+        }
+    };
+    let mut read_byte = || -> Result<u8, Error> {
+        let mut buf: [u8; 1] = [0];
+        f.read_exact(&mut buf).map_err(&truncated)?;
+        return Ok(buf[0]);
+    };
+
+    let layout = PixelLayout::RGBA8;
+    let mut index: [[u8; 4]; 64] = [[0; 4]; 64];
+    let mut px: [u8; 4] = [0, 0, 0, 255];
+    // Runs may continue onto the next row.
+    let mut run = 0;
+    let mut data: Vec<u8> = Vec::with_capacity(size[0] * layout.bytes_per_pixel());
+    let mut row: Vec<[u8; 3]> = Vec::with_capacity(size[0]);
+    for _ in 0..size[1] {
+        data.clear();
+        for _ in 0..size[0] {
+            if run > 0 {
+                run -= 1;
+            } else {
+                let op = read_byte()?;
+                if op == OP_RGB {
+                    px[0] = read_byte()?;
+                    px[1] = read_byte()?;
+                    px[2] = read_byte()?;
+                } else if op == OP_RGBA {
+                    px[0] = read_byte()?;
+                    px[1] = read_byte()?;
+                    px[2] = read_byte()?;
+                    px[3] = read_byte()?;
+                } else if op & OP_MASK == OP_INDEX {
+                    px = index[op as usize];
+                } else if op & OP_MASK == OP_DIFF {
+                    px[0] = px[0].wrapping_add(((op >> 4) & 0x03).wrapping_sub(2));
+                    px[1] = px[1].wrapping_add(((op >> 2) & 0x03).wrapping_sub(2));
+                    px[2] = px[2].wrapping_add((op & 0x03).wrapping_sub(2));
+                } else if op & OP_MASK == OP_LUMA {
+                    let op_next = read_byte()?;
+                    let dg = (op & 0x3f).wrapping_sub(32);
+                    px[0] = px[0].wrapping_add(dg.wrapping_add((op_next >> 4).wrapping_sub(8)));
+                    px[1] = px[1].wrapping_add(dg);
+                    px[2] = px[2].wrapping_add(dg.wrapping_add((op_next & 0x0f).wrapping_sub(8)));
+                } else {
+                    debug_assert!(op & OP_MASK == OP_RUN);
+                    run = (op & 0x3f) as usize;
+                }
+                let hash = (px[0] as usize * 3 + px[1] as usize * 5 +
+                            px[2] as usize * 7 + px[3] as usize * 11) % 64;
+                index[hash] = px;
+            }
+            data.extend_from_slice(&px);
+        }
+        layout.row_to_rgb(&data, &mut row);
+        row_fn(&size, 255, &row);
+    }
+    return Ok((size, 255));
+}
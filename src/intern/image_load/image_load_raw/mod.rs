@@ -0,0 +1,108 @@
+
+///
+/// Module for reading headerless grayscale data,
+/// as written by ImageMagick's `gray:` format.
+///
+
+use ::std::io::{
+    Error,
+    ErrorKind,
+};
+
+use std::io::prelude::*;
+use std::str::FromStr;
+
+/// The layout of raw data, which can't be detected from the data itself.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct RawFormat {
+    pub size: [usize; 2],
+    /// Bits per pixel, 8 or 16 (most significant byte first).
+    pub depth: usize,
+}
+
+impl RawFormat {
+    /// Parse `WIDTHxHEIGHT[:8|16]`.
+    pub fn from_str(
+        text: &str,
+    ) -> Result<RawFormat, String> {
+        let (size_str, depth_str) = match text.find(':') {
+            Some(i) => (&text[..i], Some(&text[(i + 1)..])),
+            None => (text, None),
+        };
+        let size = {
+            let mut size_iter = size_str.splitn(2, 'x').map(|s| usize::from_str(s));
+            match (size_iter.next(), size_iter.next()) {
+                (Some(Ok(w)), Some(Ok(h))) if w > 0 && h > 0 => [w, h],
+                _ => {
+                    return Err(format!("Expected a size 'WIDTHxHEIGHT', not '{}'", size_str));
+                }
+            }
+        };
+        let depth = match depth_str {
+            None | Some("8") => 8,
+            Some("16") => 16,
+            Some(s) => {
+                return Err(format!("Expected a depth in [8, 16], not '{}'", s));
+            }
+        };
+        return Ok(RawFormat {
+            size: size,
+            depth: depth,
+        });
+    }
+
+    pub fn to_string(
+        &self,
+    ) -> String {
+        return format!("{}x{}:{}", self.size[0], self.size[1], self.depth);
+    }
+}
+
+/// Read the image, returns (size, color_max, pixel_data), or fail.
+pub fn from_reader<R: Read>(
+    f: &mut R,
+    raw: &RawFormat,
+) -> Result<([usize; 2], usize, Vec<[u8; 3]>), Error> {
+    let mut pixel_buffer: Vec<[u8; 3]> = vec![];
+    let (size, color_max) = rows_from_reader(f, raw, |_size, _color_max, row| {
+        pixel_buffer.extend_from_slice(row);
+    })?;
+    return Ok((size, color_max, pixel_buffer));
+}
+
+/// Read the image a row at a time (top to bottom), passing each to `row_fn`
+/// along with the size and color range, returns (size, color_max).
+pub fn rows_from_reader<R: Read, F>(
+    f: &mut R,
+    raw: &RawFormat,
+    mut row_fn: F,
+) -> Result<([usize; 2], usize), Error> where F: FnMut(&[usize; 2], usize, &[[u8; 3]]) {
+    let size = raw.size;
+    match size[0].checked_mul(size[1]) {
+        Some(len) if len <= super::IMAGE_PIXELS_MAX => {}
+        _ => {
+            return Err(Error::new(ErrorKind::Other, "Image size too large"));
+        }
+    }
+    let layout = if raw.depth == 16 {
+        super::PixelLayout::Gray16
+    } else {
+        super::PixelLayout::Gray8
+    };
+
+    // Only a single row is allocated, so truncated input fails before using memory for the image.
+    let mut data: Vec<u8> = vec![0; size[0] * layout.bytes_per_pixel()];
+    let mut row: Vec<[u8; 3]> = Vec::with_capacity(size[0]);
+    for _ in 0..size[1] {
+        if let Err(e) = f.read_exact(&mut data) {
+            return Err(if e.kind() == ErrorKind::UnexpectedEof {
+                Error::new(ErrorKind::UnexpectedEof, "Image data is truncated")
+            } else {
+                e
+            });
+        }
+        layout.row_to_rgb(&data, &mut row);
+        row_fn(&size, 255, &row);
+    }
+    return Ok((size, 255));
+}
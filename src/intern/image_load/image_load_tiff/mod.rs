@@ -0,0 +1,395 @@
+
+///
+/// Module for reading TIFF images, as commonly written by scanners.
+///
+/// Supports bilevel, grayscale (8 & 16 bit), RGB(A) & CMYK images,
+/// uncompressed or PackBits compressed, stored in strips (tiles aren't supported).
+/// Each image (page) is read as a frame.
+///
+
+use ::std::io::{
+    Cursor,
+    Error,
+    ErrorKind,
+    SeekFrom,
+};
+
+use std::io::prelude::*;
+
+use super::PixelLayout;
+
+const TAG_IMAGE_WIDTH: u16 = 256;
+const TAG_IMAGE_LENGTH: u16 = 257;
+const TAG_BITS_PER_SAMPLE: u16 = 258;
+const TAG_COMPRESSION: u16 = 259;
+const TAG_PHOTOMETRIC: u16 = 262;
+const TAG_STRIP_OFFSETS: u16 = 273;
+const TAG_SAMPLES_PER_PIXEL: u16 = 277;
+const TAG_ROWS_PER_STRIP: u16 = 278;
+const TAG_STRIP_BYTE_COUNTS: u16 = 279;
+const TAG_PLANAR_CONFIGURATION: u16 = 284;
+
+const COMPRESSION_NONE: u32 = 1;
+const COMPRESSION_PACKBITS: u32 = 32773;
+
+const PHOTOMETRIC_WHITE_IS_ZERO: u32 = 0;
+const PHOTOMETRIC_BLACK_IS_ZERO: u32 = 1;
+const PHOTOMETRIC_RGB: u32 = 2;
+const PHOTOMETRIC_SEPARATED: u32 = 5;
+
+// Directories with more entries than this are never valid.
+const IFD_ENTRIES_MAX: u16 = 4096;
+
+// Stop following the chain of directories (which may loop) after this many images.
+const FRAMES_MAX: usize = 4096;
+
+// PackBits data never expands by more than this.
+const PACKBITS_EXPAND_MAX: u64 = 128;
+
+/// Returns (size, color_max, pixel_data) of the first image, or fail.
+#[allow(dead_code)]
+pub fn from_bytes(
+    data: &[u8],
+) -> Result<([usize; 2], usize, Vec<[u8; 3]>), Error> {
+    return from_reader(&mut Cursor::new(data));
+}
+
+/// Read the first image, any malformed input returns an error (never panics),
+/// the header is validated against the length of the data before allocating.
+pub fn from_reader<R: Read + Seek>(
+    f: &mut R,
+) -> Result<([usize; 2], usize, Vec<[u8; 3]>), Error> {
+    let mut pixel_buffer: Vec<[u8; 3]> = vec![];
+    let (size, color_max) = rows_from_reader(f, |size, _color_max, row| {
+        if pixel_buffer.is_empty() {
+            pixel_buffer.reserve_exact(size[0] * size[1]);
+        }
+        pixel_buffer.extend_from_slice(row);
+    })?;
+    return Ok((size, color_max, pixel_buffer));
+}
+
+/// Read all images (pages), see: `from_reader`.
+pub fn frames_from_reader<R: Read + Seek>(
+    f: &mut R,
+) -> Result<Vec<([usize; 2], usize, Vec<[u8; 3]>)>, Error> {
+    let (is_big_endian, mut ifd_offset) = header_read(f)?;
+    let mut frames = vec![];
+    while ifd_offset != 0 {
+        if frames.len() == FRAMES_MAX {
+            return Err(Error::new(ErrorKind::Other, "Too many images"));
+        }
+        let (image, ifd_offset_next) = image_read(f, is_big_endian, ifd_offset)?;
+        let mut pixel_buffer: Vec<[u8; 3]> = Vec::with_capacity(image.size[0] * image.size[1]);
+        image_read_rows(f, &image, |_size, _color_max, row| {
+            pixel_buffer.extend_from_slice(row);
+        })?;
+        frames.push((image.size, 255, pixel_buffer));
+        ifd_offset = ifd_offset_next;
+    }
+    if frames.is_empty() {
+        return Err(Error::new(ErrorKind::Other, "No images"));
+    }
+    return Ok(frames);
+}
+
+/// Read the first image a row at a time (top to bottom), passing each to `row_fn`
+/// along with the size and color range, returns (size, color_max).
+pub fn rows_from_reader<R: Read + Seek, F>(
+    f: &mut R,
+    row_fn: F,
+) -> Result<([usize; 2], usize), Error> where F: FnMut(&[usize; 2], usize, &[[u8; 3]]) {
+    let (is_big_endian, ifd_offset) = header_read(f)?;
+    if ifd_offset == 0 {
+        return Err(Error::new(ErrorKind::Other, "No images"));
+    }
+    let (image, _) = image_read(f, is_big_endian, ifd_offset)?;
+    image_read_rows(f, &image, row_fn)?;
+    return Ok((image.size, 255));
+}
+
+/// An image from a directory, with everything needed to read its pixels.
+struct Image {
+    size: [usize; 2],
+    is_big_endian: bool,
+    /// Bits per sample (1, 8 or 16).
+    bits: usize,
+    samples_per_pixel: usize,
+    layout: PixelLayout,
+    is_white_zero: bool,
+    compression: u32,
+    rows_per_strip: usize,
+    strip_offsets: Vec<u64>,
+    strip_byte_counts: Vec<u64>,
+}
+
+fn read_u16<R: Read>(
+    f: &mut R,
+    is_big_endian: bool,
+) -> Result<u16, Error> {
+    let mut buf: [u8; 2] = [0; 2];
+    f.read_exact(&mut buf)?;
+    return Ok(if is_big_endian { u16::from_be_bytes(buf) } else { u16::from_le_bytes(buf) });
+}
+
+fn read_u32<R: Read>(
+    f: &mut R,
+    is_big_endian: bool,
+) -> Result<u32, Error> {
+    let mut buf: [u8; 4] = [0; 4];
+    f.read_exact(&mut buf)?;
+    return Ok(if is_big_endian { u32::from_be_bytes(buf) } else { u32::from_le_bytes(buf) });
+}
+
+/// Read the byte order & offset of the first directory.
+fn header_read<R: Read + Seek>(
+    f: &mut R,
+) -> Result<(bool, u64), Error> {
+    let mut header: [u8; 4] = [0; 4];
+    f.read_exact(&mut header)?;
+    let is_big_endian = match &header {
+        b"II*\0" => false,
+        b"MM\0*" => true,
+        _ => {
+            return Err(Error::new(ErrorKind::Other, "Invalid header"));
+        }
+    };
+    return Ok((is_big_endian, read_u32(f, is_big_endian)? as u64));
+}
+
+/// Read the directory at `ifd_offset`, returns the image & the offset of the next directory
+/// (zero for the last image).
+fn image_read<R: Read + Seek>(
+    f: &mut R,
+    is_big_endian: bool,
+    ifd_offset: u64,
+) -> Result<(Image, u64), Error> {
+    let data_len = f.seek(SeekFrom::End(0))?;
+    f.seek(SeekFrom::Start(ifd_offset))?;
+    let entries_len = read_u16(f, is_big_endian)?;
+    if entries_len > IFD_ENTRIES_MAX {
+        return Err(Error::new(ErrorKind::Other, "Invalid directory"));
+    }
+    // (tag, type, count, value or offset).
+    let mut entries: Vec<(u16, u16, u32, [u8; 4])> = Vec::with_capacity(entries_len as usize);
+    for _ in 0..entries_len {
+        let tag = read_u16(f, is_big_endian)?;
+        let value_type = read_u16(f, is_big_endian)?;
+        let count = read_u32(f, is_big_endian)?;
+        let mut value: [u8; 4] = [0; 4];
+        f.read_exact(&mut value)?;
+        entries.push((tag, value_type, count, value));
+    }
+    let ifd_offset_next = read_u32(f, is_big_endian)? as u64;
+
+    // Integer values of a tag, unsupported types are ignored.
+    let mut entry_values = |tag_find: u16| -> Result<Vec<u64>, Error> {
+        let &(_, value_type, count, value) = match entries.iter().find(|e| e.0 == tag_find) {
+            Some(entry) => entry,
+            None => {
+                return Ok(vec![]);
+            }
+        };
+        let value_size = match value_type {
+            1 => 1,
+            3 => 2,
+            4 => 4,
+            _ => {
+                return Ok(vec![]);
+            }
+        };
+        let values_size = count as u64 * value_size;
+        if values_size > data_len {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "Directory data is truncated"));
+        }
+        let mut data = vec![0; values_size as usize];
+        if values_size <= 4 {
+            data.copy_from_slice(&value[..values_size as usize]);
+        } else {
+            f.seek(SeekFrom::Start(read_u32(&mut &value[..], is_big_endian)? as u64))?;
+            f.read_exact(&mut data)?;
+        }
+        let mut data = &data[..];
+        let mut values = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            values.push(match value_size {
+                1 => { let v = data[0] as u64; data = &data[1..]; v }
+                2 => read_u16(&mut data, is_big_endian)? as u64,
+                _ => read_u32(&mut data, is_big_endian)? as u64,
+            });
+        }
+        return Ok(values);
+    };
+    let mut entry_value = |tag: u16, default: Option<u64>| -> Result<u64, Error> {
+        match (entry_values(tag)?.first(), default) {
+            (Some(&v), _) | (None, Some(v)) => Ok(v),
+            (None, None) => Err(Error::new(ErrorKind::Other, "Missing required tag")),
+        }
+    };
+
+    let size = [
+        entry_value(TAG_IMAGE_WIDTH, None)? as usize,
+        entry_value(TAG_IMAGE_LENGTH, None)? as usize,
+    ];
+    if !(size[0] > 0 && size[1] > 0) {
+        return Err(Error::new(ErrorKind::Other, "Invalid size"));
+    }
+    match size[0].checked_mul(size[1]) {
+        Some(len) if len <= super::IMAGE_PIXELS_MAX => {}
+        _ => {
+            return Err(Error::new(ErrorKind::Other, "Image size too large"));
+        }
+    }
+
+    let bits = entry_value(TAG_BITS_PER_SAMPLE, Some(1))? as usize;
+    let compression = entry_value(TAG_COMPRESSION, Some(COMPRESSION_NONE as u64))? as u32;
+    let photometric = entry_value(TAG_PHOTOMETRIC, None)? as u32;
+    let samples_per_pixel = entry_value(TAG_SAMPLES_PER_PIXEL, Some(1))? as usize;
+    let rows_per_strip = entry_value(TAG_ROWS_PER_STRIP, Some(size[1] as u64))? as usize;
+    if entry_value(TAG_PLANAR_CONFIGURATION, Some(1))? != 1 {
+        return Err(Error::new(ErrorKind::Other, "Planar TIFF images are not supported"));
+    }
+    if !(compression == COMPRESSION_NONE || compression == COMPRESSION_PACKBITS) {
+        return Err(Error::new(ErrorKind::Other, "Unsupported TIFF compression"));
+    }
+
+    let layout = match (photometric, samples_per_pixel, bits) {
+        (PHOTOMETRIC_WHITE_IS_ZERO, 1, 1) |
+        (PHOTOMETRIC_BLACK_IS_ZERO, 1, 1) |
+        (PHOTOMETRIC_WHITE_IS_ZERO, 1, 8) |
+        (PHOTOMETRIC_BLACK_IS_ZERO, 1, 8) => PixelLayout::Gray8,
+        (PHOTOMETRIC_WHITE_IS_ZERO, 1, 16) |
+        (PHOTOMETRIC_BLACK_IS_ZERO, 1, 16) => PixelLayout::Gray16,
+        (PHOTOMETRIC_RGB, 3, 8) => PixelLayout::RGB8,
+        (PHOTOMETRIC_RGB, 4, 8) => PixelLayout::RGBA8,
+        (PHOTOMETRIC_SEPARATED, 4, 8) => PixelLayout::CMYK8,
+        _ => {
+            return Err(Error::new(ErrorKind::Other, "Unsupported TIFF pixel format"));
+        }
+    };
+
+    let strip_offsets = entry_values(TAG_STRIP_OFFSETS)?;
+    let strip_byte_counts = entry_values(TAG_STRIP_BYTE_COUNTS)?;
+    if strip_offsets.is_empty() {
+        return Err(Error::new(ErrorKind::Other, "Tiled TIFF images are not supported"));
+    }
+    if rows_per_strip == 0 ||
+       strip_offsets.len() != strip_byte_counts.len() ||
+       strip_offsets.len() < (size[1] + rows_per_strip - 1) / rows_per_strip
+    {
+        return Err(Error::new(ErrorKind::Other, "Invalid strips"));
+    }
+    for (offset, byte_count) in strip_offsets.iter().zip(&strip_byte_counts) {
+        if offset + byte_count > data_len {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "Image data is truncated"));
+        }
+    }
+
+    return Ok((Image {
+        size: size,
+        is_big_endian: is_big_endian,
+        bits: bits,
+        samples_per_pixel: samples_per_pixel,
+        layout: layout,
+        is_white_zero: photometric == PHOTOMETRIC_WHITE_IS_ZERO,
+        compression: compression,
+        rows_per_strip: rows_per_strip,
+        strip_offsets: strip_offsets,
+        strip_byte_counts: strip_byte_counts,
+    }, ifd_offset_next));
+}
+
+/// Decode PackBits data, filling `data_dst`.
+fn packbits_decode(
+    mut data_src: &[u8],
+    data_dst: &mut Vec<u8>,
+    data_dst_len: usize,
+) -> Result<(), Error> {
+    let truncated = || Error::new(ErrorKind::UnexpectedEof, "Image data is truncated");
+    data_dst.clear();
+    while data_dst.len() < data_dst_len {
+        let n = *data_src.first().ok_or_else(truncated)? as i8;
+        data_src = &data_src[1..];
+        if n >= 0 {
+            let len = n as usize + 1;
+            if data_src.len() < len {
+                return Err(truncated());
+            }
+            data_dst.extend_from_slice(&data_src[..len]);
+            data_src = &data_src[len..];
+        } else if n != -128 {
+            let value = *data_src.first().ok_or_else(truncated)?;
+            data_src = &data_src[1..];
+            for _ in 0..(1 - n as isize) {
+                data_dst.push(value);
+            }
+        }
+    }
+    data_dst.truncate(data_dst_len);
+    return Ok(());
+}
+
+/// Read the pixels of the image, passing each row to `row_fn`.
+fn image_read_rows<R: Read + Seek, F>(
+    f: &mut R,
+    image: &Image,
+    mut row_fn: F,
+) -> Result<(), Error> where F: FnMut(&[usize; 2], usize, &[[u8; 3]]) {
+    let size = image.size;
+    let row_len = (size[0] * image.bits * image.samples_per_pixel + 7) / 8;
+    let mut data_src: Vec<u8> = vec![];
+    let mut data_strip: Vec<u8> = vec![];
+    let mut data_row: Vec<u8> = vec![];
+    let mut row: Vec<[u8; 3]> = Vec::with_capacity(size[0]);
+    for (i, (&offset, &byte_count)) in image.strip_offsets.iter().zip(
+        &image.strip_byte_counts).enumerate()
+    {
+        let y = i * image.rows_per_strip;
+        if y >= size[1] {
+            break;
+        }
+        let strip_len = ::std::cmp::min(image.rows_per_strip, size[1] - y) * row_len;
+        let expand_max = if image.compression == COMPRESSION_PACKBITS {
+            PACKBITS_EXPAND_MAX
+        } else {
+            1
+        };
+        if byte_count.saturating_mul(expand_max) < strip_len as u64 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "Image data is truncated"));
+        }
+        f.seek(SeekFrom::Start(offset))?;
+        data_src.resize(::std::cmp::min(byte_count as usize, strip_len * 2 + 2), 0);
+        f.read_exact(&mut data_src)?;
+        let data_strip = if image.compression == COMPRESSION_PACKBITS {
+            packbits_decode(&data_src, &mut data_strip, strip_len)?;
+            &data_strip[..]
+        } else {
+            &data_src[..strip_len]
+        };
+
+        for data_row_src in data_strip.chunks(row_len) {
+            data_row.clear();
+            if image.bits == 1 {
+                // Bits are most significant first.
+                data_row.extend((0..size[0]).map(|x| {
+                    if (data_row_src[x / 8] >> (7 - (x % 8))) & 1 != 0 { 255 } else { 0 }
+                }));
+            } else if image.bits == 16 && !image.is_big_endian {
+                // Gray16 is most significant byte first.
+                for p in data_row_src.chunks(2) {
+                    data_row.extend_from_slice(&[p[1], p[0]]);
+                }
+            } else {
+                data_row.extend_from_slice(data_row_src);
+            }
+            if image.is_white_zero {
+                for value in data_row.iter_mut() {
+                    *value = 255 - *value;
+                }
+            }
+            image.layout.row_to_rgb(&data_row, &mut row);
+            row_fn(&size, 255, &row);
+        }
+    }
+    return Ok(());
+}
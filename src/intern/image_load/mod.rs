@@ -3,49 +3,422 @@
 ///
 
 mod image_load_ppm;
+mod image_load_raw;
+mod image_load_tiff;
+mod image_load_jpeg;
+mod image_load_qoi;
+mod image_load_gif;
+mod image_load_png;
+
+pub use self::image_load_raw::RawFormat;
 
 use ::std::io::{
     Error,
     ErrorKind,
 };
 
+/// Images with more pixels than this are rejected,
+/// (also prevents over-allocation from malformed headers).
+pub const IMAGE_PIXELS_MAX: usize = 1 << 28;
+
+/// The number of intensity levels counted by `load_mask`.
+pub const HISTOGRAM_BINS: usize = 256;
+
+/// The layout of pixels in decoded data, converted to RGB by each loader
+/// so new formats don't each need to handle channels & alpha.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum PixelLayout {
+    Gray8,
+    /// Most significant byte first.
+    Gray16,
+    RGB8,
+    /// Composited over white, so transparent pixels are never filled.
+    RGBA8,
+    CMYK8,
+}
+
+impl PixelLayout {
+    pub fn bytes_per_pixel(
+        &self,
+    ) -> usize {
+        match *self {
+            PixelLayout::Gray8 => 1,
+            PixelLayout::Gray16 => 2,
+            PixelLayout::RGB8 => 3,
+            PixelLayout::RGBA8 => 4,
+            PixelLayout::CMYK8 => 4,
+        }
+    }
+
+    /// Convert a single pixel (`bytes_per_pixel` long) to RGB.
+    ///
+    /// 16 bit values are reduced to 8 bits, since only a threshold is needed.
+    pub fn to_rgb(
+        &self,
+        p: &[u8],
+    ) -> [u8; 3] {
+        let mul = |a: u8, b: u8| ((a as u32 * b as u32 + 127) / 255) as u8;
+        match *self {
+            PixelLayout::Gray8 | PixelLayout::Gray16 => [p[0], p[0], p[0]],
+            PixelLayout::RGB8 => [p[0], p[1], p[2]],
+            PixelLayout::RGBA8 => {
+                let a = p[3];
+                let over_white = |c: u8| mul(c, a) + (255 - a);
+                [over_white(p[0]), over_white(p[1]), over_white(p[2])]
+            }
+            PixelLayout::CMYK8 => {
+                let k_inv = 255 - p[3];
+                [mul(255 - p[0], k_inv), mul(255 - p[1], k_inv), mul(255 - p[2], k_inv)]
+            }
+        }
+    }
+
+    /// Convert a row of pixels, replacing the contents of `row`.
+    pub fn row_to_rgb(
+        &self,
+        data: &[u8],
+        row: &mut Vec<[u8; 3]>,
+    ) {
+        row.clear();
+        row.extend(data.chunks(self.bytes_per_pixel()).map(|p| self.to_rgb(p)));
+    }
+}
+
 #[derive(PartialEq, Debug, Copy, Clone)]
 pub enum ImageFormat {
     PPM,
-    // PNG,
+    TIFF,
+    /// Requires the `jpeg` feature.
+    JPEG,
+    QOI,
+    GIF,
+    PNG,
 }
 
-fn format_from_filepath(
-    filepath: &String,
+/// Detect the format from the first bytes of an image.
+pub fn format_from_magic(
+    data: &[u8],
 ) -> Option<ImageFormat> {
-    if filepath.ends_with(".ppm") {
+    if data.starts_with(b"P6") {
         return Some(ImageFormat::PPM);
-    // } else if filepath.ends_with(".png") {
-    //     return Some(ImageFormat::PNG);
+    } else if data.starts_with(b"II*\0") || data.starts_with(b"MM\0*") {
+        return Some(ImageFormat::TIFF);
+    } else if data.starts_with(b"\xff\xd8\xff") {
+        return Some(ImageFormat::JPEG);
+    } else if data.starts_with(b"qoif") {
+        return Some(ImageFormat::QOI);
+    } else if data.starts_with(b"GIF8") {
+        return Some(ImageFormat::GIF);
+    } else if data.starts_with(b"\x89PNG") {
+        return Some(ImageFormat::PNG);
     } else {
         return None;
     }
 }
 
+/// Detect the format from the file extension, otherwise the first bytes of the file.
+#[cfg(not(target_arch = "wasm32"))]
+fn format_from_filepath(
+    filepath: &String,
+) -> Result<ImageFormat, Error> {
+    let filepath_lower = filepath.to_lowercase();
+    if filepath_lower.ends_with(".ppm") {
+        return Ok(ImageFormat::PPM);
+    } else if filepath_lower.ends_with(".tif") || filepath_lower.ends_with(".tiff") {
+        return Ok(ImageFormat::TIFF);
+    } else if filepath_lower.ends_with(".jpg") || filepath_lower.ends_with(".jpeg") {
+        return Ok(ImageFormat::JPEG);
+    } else if filepath_lower.ends_with(".qoi") {
+        return Ok(ImageFormat::QOI);
+    } else if filepath_lower.ends_with(".gif") {
+        return Ok(ImageFormat::GIF);
+    } else if filepath_lower.ends_with(".png") || filepath_lower.ends_with(".apng") {
+        return Ok(ImageFormat::PNG);
+    }
+    let mut magic: Vec<u8> = vec![];
+    {
+        use std::io::Read;
+        ::std::fs::File::open(filepath)?.take(4).read_to_end(&mut magic)?;
+    }
+    return format_from_magic(&magic).ok_or_else(
+        || Error::new(ErrorKind::Other, "Unknown file format"));
+}
+
+#[allow(dead_code)]
+#[cfg(not(target_arch = "wasm32"))]
 pub fn from_filepath_format(
     filepath: &String,
     format: ImageFormat,
 ) -> Result<([usize; 2], usize, Vec<[u8; 3]>), Error> {
-    if format == ImageFormat::PPM {
-        let file = ::std::fs::File::open(filepath).expect("open failed");
-        return image_load_ppm::from_file(&file);
-    // } else if format == ImageFormat::PNG {
-    //     return image_load_png::from_filepath(filepath);
+    match format {
+        ImageFormat::PPM => {
+            let file = ::std::fs::File::open(filepath)?;
+            return image_load_ppm::from_file(&file);
+        }
+        ImageFormat::TIFF => {
+            let mut file = ::std::io::BufReader::new(::std::fs::File::open(filepath)?);
+            return image_load_tiff::from_reader(&mut file);
+        }
+        ImageFormat::JPEG => {
+            let mut file = ::std::io::BufReader::new(::std::fs::File::open(filepath)?);
+            return image_load_jpeg::from_reader(&mut file);
+        }
+        ImageFormat::QOI => {
+            let mut file = ::std::io::BufReader::new(::std::fs::File::open(filepath)?);
+            return image_load_qoi::from_reader(&mut file);
+        }
+        ImageFormat::GIF => {
+            let mut file = ::std::io::BufReader::new(::std::fs::File::open(filepath)?);
+            return image_load_gif::from_reader(&mut file);
+        }
+        ImageFormat::PNG => {
+            let mut file = ::std::io::BufReader::new(::std::fs::File::open(filepath)?);
+            return image_load_png::from_reader(&mut file);
+        }
+    }
+}
+
+/// Load an image from memory,
+/// malformed data returns an error instead of panicking.
+#[allow(dead_code)]
+pub fn from_bytes_format(
+    data: &[u8],
+    format: ImageFormat,
+) -> Result<([usize; 2], usize, Vec<[u8; 3]>), Error> {
+    match format {
+        ImageFormat::PPM => {
+            return image_load_ppm::from_bytes(data);
+        }
+        ImageFormat::TIFF => {
+            return image_load_tiff::from_bytes(data);
+        }
+        ImageFormat::JPEG => {
+            return image_load_jpeg::from_bytes(data);
+        }
+        ImageFormat::QOI => {
+            return image_load_qoi::from_bytes(data);
+        }
+        ImageFormat::GIF => {
+            return image_load_gif::from_bytes(data);
+        }
+        ImageFormat::PNG => {
+            return image_load_png::from_bytes(data);
+        }
     }
-    return Err(Error::new(ErrorKind::Other, "Unknown file format"));
 }
 
+#[allow(dead_code)]
+#[cfg(not(target_arch = "wasm32"))]
 pub fn from_filepath_any(
     filepath: &String,
 ) -> Result<([usize; 2], usize, Vec<[u8; 3]>), Error> {
-    if let Some(format) = format_from_filepath(filepath) {
-        return from_filepath_format(filepath, format);
+    return from_filepath_format(filepath, format_from_filepath(filepath)?);
+}
+
+/// Load an image from memory, detecting the format from the first bytes.
+#[allow(dead_code)]
+pub fn from_bytes_any(
+    data: &[u8],
+) -> Result<([usize; 2], usize, Vec<[u8; 3]>), Error> {
+    let format = format_from_magic(data).ok_or_else(
+        || Error::new(ErrorKind::Other, "Unknown file format"))?;
+    return from_bytes_format(data, format);
+}
+
+/// Load all frames of an image, formats without multiple frames return a single frame.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn from_filepath_any_frames(
+    filepath: &String,
+) -> Result<Vec<([usize; 2], usize, Vec<[u8; 3]>)>, Error> {
+    let mut file = ::std::io::BufReader::new(::std::fs::File::open(filepath)?);
+    match format_from_filepath(filepath)? {
+        ImageFormat::PPM => {
+            return image_load_ppm::frames_from_reader(&mut file);
+        }
+        ImageFormat::TIFF => {
+            return image_load_tiff::frames_from_reader(&mut file);
+        }
+        ImageFormat::JPEG => {
+            return Ok(vec![image_load_jpeg::from_reader(&mut file)?]);
+        }
+        ImageFormat::QOI => {
+            return Ok(vec![image_load_qoi::from_reader(&mut file)?]);
+        }
+        ImageFormat::GIF => {
+            return image_load_gif::frames_from_reader(&mut file);
+        }
+        ImageFormat::PNG => {
+            return image_load_png::frames_from_reader(&mut file);
+        }
     }
-    return Err(Error::new(ErrorKind::Other, "Unknown file format"));
 }
 
+/// Load all frames from memory, see: `from_filepath_any_frames`.
+#[allow(dead_code)]
+pub fn from_bytes_format_frames(
+    data: &[u8],
+    format: ImageFormat,
+) -> Result<Vec<([usize; 2], usize, Vec<[u8; 3]>)>, Error> {
+    match format {
+        ImageFormat::PPM => {
+            return image_load_ppm::frames_from_reader(&mut ::std::io::Cursor::new(data));
+        }
+        ImageFormat::TIFF => {
+            return image_load_tiff::frames_from_reader(&mut ::std::io::Cursor::new(data));
+        }
+        ImageFormat::JPEG => {
+            return Ok(vec![image_load_jpeg::from_bytes(data)?]);
+        }
+        ImageFormat::QOI => {
+            return Ok(vec![image_load_qoi::from_bytes(data)?]);
+        }
+        ImageFormat::GIF => {
+            return image_load_gif::frames_from_reader(&mut ::std::io::Cursor::new(data));
+        }
+        ImageFormat::PNG => {
+            return image_load_png::frames_from_reader(&mut ::std::io::Cursor::new(data));
+        }
+    }
+}
+
+
+/// Load headerless grayscale data, where `-` reads from the standard input.
+#[allow(dead_code)]
+#[cfg(not(target_arch = "wasm32"))]
+pub fn from_filepath_raw(
+    filepath: &String,
+    raw: &RawFormat,
+) -> Result<([usize; 2], usize, Vec<[u8; 3]>), Error> {
+    if filepath == "-" {
+        let stdin = ::std::io::stdin();
+        let mut f = stdin.lock();
+        return image_load_raw::from_reader(&mut f, raw);
+    }
+    let mut f = ::std::fs::File::open(filepath)?;
+    return image_load_raw::from_reader(&mut f, raw);
+}
+
+/// Load an image a row at a time (top to bottom), passing each to `row_fn`
+/// along with the size and color range, returns (size, color_max).
+///
+/// Use for large images, since the pixels of the whole image are never stored,
+/// headerless data is read when `raw` is set (where `-` reads from the standard input).
+#[cfg(not(target_arch = "wasm32"))]
+pub fn from_filepath_rows<F>(
+    filepath: &String,
+    raw: Option<&RawFormat>,
+    row_fn: F,
+) -> Result<([usize; 2], usize), Error> where F: FnMut(&[usize; 2], usize, &[[u8; 3]]) {
+    if let Some(raw) = raw {
+        if filepath == "-" {
+            let stdin = ::std::io::stdin();
+            let mut f = stdin.lock();
+            return image_load_raw::rows_from_reader(&mut f, raw, row_fn);
+        }
+        let mut f = ::std::io::BufReader::new(::std::fs::File::open(filepath)?);
+        return image_load_raw::rows_from_reader(&mut f, raw, row_fn);
+    }
+    let mut f = ::std::io::BufReader::new(::std::fs::File::open(filepath)?);
+    match format_from_filepath(filepath)? {
+        ImageFormat::PPM => {
+            return image_load_ppm::rows_from_reader(&mut f, row_fn);
+        }
+        ImageFormat::TIFF => {
+            return image_load_tiff::rows_from_reader(&mut f, row_fn);
+        }
+        ImageFormat::JPEG => {
+            return image_load_jpeg::rows_from_reader(&mut f, row_fn);
+        }
+        ImageFormat::QOI => {
+            return image_load_qoi::rows_from_reader(&mut f, row_fn);
+        }
+        ImageFormat::GIF => {
+            return image_load_gif::rows_from_reader(&mut f, row_fn);
+        }
+        ImageFormat::PNG => {
+            return image_load_png::rows_from_reader(&mut f, row_fn);
+        }
+    }
+}
+
+/// Load a binary image (mask), where pixels darker than `threshold` (from zero to one)
+/// are set, returns (mask, size, color_max).
+///
+/// Each row is converted as it's read, so the pixels of the image are never stored.
+/// When `histogram` is set, the number of pixels at each intensity
+/// (the mean of the color channels, as used for the threshold) is added to it.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_mask(
+    filepath: &String,
+    raw: Option<&RawFormat>,
+    threshold: f64,
+    mut histogram: Option<&mut [usize; HISTOGRAM_BINS]>,
+) -> Result<(Vec<bool>, [usize; 2], usize), Error> {
+    let mut mask: Vec<bool> = vec![];
+    let (size, color_max) = from_filepath_rows(filepath, raw, |size, color_max, row| {
+        if mask.is_empty() {
+            mask.reserve_exact(size[0] * size[1]);
+        }
+        let color_threshold = color_threshold_from_fraction(color_max, threshold);
+        mask.extend(row.iter().map(|p| pixel_sum(p) < color_threshold));
+        if let Some(ref mut histogram) = histogram {
+            let sum_max = color_max * 3;
+            for p in row {
+                let bin = (pixel_sum(p) as usize * (HISTOGRAM_BINS - 1)) / sum_max;
+                histogram[::std::cmp::min(bin, HISTOGRAM_BINS - 1)] += 1;
+            }
+        }
+    })?;
+    return Ok((mask, size, color_max));
+}
+
+/// Load the coverage of each pixel (one for black, zero for white),
+/// the inverse of the intensity compared with the threshold, see: `load_mask`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_coverage(
+    filepath: &String,
+    raw: Option<&RawFormat>,
+) -> Result<(Vec<f32>, [usize; 2]), Error> {
+    let mut coverage: Vec<f32> = vec![];
+    let (size, _color_max) = from_filepath_rows(filepath, raw, |size, color_max, row| {
+        if coverage.is_empty() {
+            coverage.reserve_exact(size[0] * size[1]);
+        }
+        let sum_max = (color_max * 3) as f32;
+        coverage.extend(row.iter().map(|p| 1.0 - (pixel_sum(p) as f32 / sum_max)));
+    })?;
+    return Ok((coverage, size));
+}
+
+/// Convert loaded pixels into a mask, see: `load_mask`.
+pub fn mask_from_pixels(
+    color_max: usize,
+    pixel_buffer: &[[u8; 3]],
+    threshold: f64,
+) -> Vec<bool> {
+    let color_threshold = color_threshold_from_fraction(color_max, threshold);
+    return pixel_buffer.iter().map(|p| pixel_sum(p) < color_threshold).collect();
+}
+
+/// The threshold to compare with `pixel_sum`.
+fn color_threshold_from_fraction(
+    color_max: usize,
+    threshold: f64,
+) -> u32 {
+    return ((color_max as f64 * threshold) as u32) * 3;
+}
+
+fn pixel_sum(
+    p: &[u8; 3],
+) -> u32 {
+    return (p[0] as u32) + (p[1] as u32) + (p[2] as u32);
+}
+
+/// Load headerless grayscale data from memory, see: `from_filepath_raw`.
+#[allow(dead_code)]
+pub fn from_bytes_raw(
+    mut data: &[u8],
+    raw: &RawFormat,
+) -> Result<([usize; 2], usize, Vec<[u8; 3]>), Error> {
+    return image_load_raw::from_reader(&mut data, raw);
+}
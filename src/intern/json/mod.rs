@@ -0,0 +1,235 @@
+
+///
+/// Mini module for reading JSON, see: https://www.json.org
+///
+/// Only what's needed to read small documents (commands & settings),
+/// object members keep their order, numbers are read as `f64`.
+///
+
+use std::str::FromStr;
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+/// Nesting deeper than this is an error (instead of overflowing the stack).
+const DEPTH_MAX: usize = 64;
+
+struct Reader<'a> {
+    text: &'a str,
+    /// Byte offset into `text`.
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn error(&self, message: &str) -> String {
+        return format!("{} at offset {}", message, self.offset);
+    }
+
+    fn peek(&self) -> Option<char> {
+        return self.text[self.offset..].chars().next();
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.offset += c.len_utf8();
+        return Some(c);
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if !(c == ' ' || c == '\t' || c == '\n' || c == '\r') {
+                break;
+            }
+            self.offset += 1;
+        }
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), String> {
+        if !self.text[self.offset..].starts_with(text) {
+            return Err(self.error(&format!("Expected '{}'", text)));
+        }
+        self.offset += text.len();
+        return Ok(());
+    }
+
+    fn read_hex4(&mut self) -> Result<u32, String> {
+        let digits = self.text.get(self.offset..self.offset + 4).ok_or_else(
+            || self.error("Expected 4 hex digits"))?;
+        let v = u32::from_str_radix(digits, 16).map_err(|_| self.error("Expected 4 hex digits"))?;
+        self.offset += 4;
+        return Ok(v);
+    }
+
+    fn read_string(&mut self) -> Result<String, String> {
+        self.expect("\"")?;
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                None => {
+                    return Err(self.error("Unterminated string"));
+                }
+                Some('"') => {
+                    return Ok(text);
+                }
+                Some('\\') => {
+                    let c = match self.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{08}',
+                        Some('f') => '\u{0c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            let mut code = self.read_hex4()?;
+                            // Surrogate pairs.
+                            if code >= 0xd800 && code < 0xdc00 {
+                                self.expect("\\u")?;
+                                let code_low = self.read_hex4()?;
+                                if !(code_low >= 0xdc00 && code_low < 0xe000) {
+                                    return Err(self.error("Invalid surrogate pair"));
+                                }
+                                code = 0x10000 + ((code - 0xd800) << 10) + (code_low - 0xdc00);
+                            }
+                            ::std::char::from_u32(code).ok_or_else(
+                                || self.error("Invalid unicode escape"))?
+                        }
+                        _ => {
+                            return Err(self.error("Invalid escape"));
+                        }
+                    };
+                    text.push(c);
+                }
+                Some(c) if (c as u32) < 0x20 => {
+                    return Err(self.error("Control character in string"));
+                }
+                Some(c) => {
+                    text.push(c);
+                }
+            }
+        }
+    }
+
+    fn read_number(&mut self) -> Result<f64, String> {
+        let offset_start = self.offset;
+        while let Some(c) = self.peek() {
+            if !(c.is_ascii_digit() || c == '-' || c == '+' || c == '.' || c == 'e' || c == 'E') {
+                break;
+            }
+            self.offset += 1;
+        }
+        let text = &self.text[offset_start..self.offset];
+        // Rust accepts some numbers JSON doesn't ("inf", "1.", ".5"), reject them here.
+        let is_valid = {
+            let digits = text.trim_start_matches('-');
+            digits.starts_with(|c: char| c.is_ascii_digit()) &&
+            !digits.contains(".e") && !digits.contains(".E") && !digits.ends_with('.')
+        };
+        match f64::from_str(text) {
+            Ok(v) if is_valid => {
+                return Ok(v);
+            }
+            _ => {
+                self.offset = offset_start;
+                return Err(self.error("Invalid number"));
+            }
+        }
+    }
+
+    fn read_value(&mut self, depth: usize) -> Result<Value, String> {
+        if depth > DEPTH_MAX {
+            return Err(self.error("Nesting too deep"));
+        }
+        self.skip_whitespace();
+        let value = match self.peek() {
+            Some('{') => {
+                self.offset += 1;
+                let mut members: Vec<(String, Value)> = vec![];
+                self.skip_whitespace();
+                if self.peek() == Some('}') {
+                    self.offset += 1;
+                } else {
+                    loop {
+                        self.skip_whitespace();
+                        let key = self.read_string()?;
+                        self.skip_whitespace();
+                        self.expect(":")?;
+                        members.push((key, self.read_value(depth + 1)?));
+                        self.skip_whitespace();
+                        match self.next() {
+                            Some(',') => {}
+                            Some('}') => break,
+                            _ => {
+                                return Err(self.error("Expected ',' or '}'"));
+                            }
+                        }
+                    }
+                }
+                Value::Object(members)
+            }
+            Some('[') => {
+                self.offset += 1;
+                let mut items: Vec<Value> = vec![];
+                self.skip_whitespace();
+                if self.peek() == Some(']') {
+                    self.offset += 1;
+                } else {
+                    loop {
+                        items.push(self.read_value(depth + 1)?);
+                        self.skip_whitespace();
+                        match self.next() {
+                            Some(',') => {}
+                            Some(']') => break,
+                            _ => {
+                                return Err(self.error("Expected ',' or ']'"));
+                            }
+                        }
+                    }
+                }
+                Value::Array(items)
+            }
+            Some('"') => Value::String(self.read_string()?),
+            Some('t') => {
+                self.expect("true")?;
+                Value::Bool(true)
+            }
+            Some('f') => {
+                self.expect("false")?;
+                Value::Bool(false)
+            }
+            Some('n') => {
+                self.expect("null")?;
+                Value::Null
+            }
+            Some(_) => Value::Number(self.read_number()?),
+            None => {
+                return Err(self.error("Unexpected end"));
+            }
+        };
+        return Ok(value);
+    }
+}
+
+/// Read a JSON document, any malformed input returns an error (never panics).
+pub fn from_str(
+    text: &str,
+) -> Result<Value, String> {
+    let mut reader = Reader {
+        text: text,
+        offset: 0,
+    };
+    let value = reader.read_value(0)?;
+    reader.skip_whitespace();
+    if reader.offset != text.len() {
+        return Err(reader.error("Unexpected data"));
+    }
+    return Ok(value);
+}
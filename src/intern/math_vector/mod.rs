@@ -49,6 +49,7 @@ pub fn is_finite_vn(
     return true;
 }
 
+#[allow(dead_code)]
 pub fn zero_vn(
     v0: &mut [f64; DIMS],
 ) {
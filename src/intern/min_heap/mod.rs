@@ -7,6 +7,8 @@
 ///   for ordering in the heap.
 /// - Supported duplicate entries,
 ///   (Note that the order, while not *undefined* is determined by the binary tree structure).
+/// - User data doesn't need to be `Copy`,
+///   it's moved into the heap on insertion and moved out again when popped.
 ///
 /// Overview:
 ///
@@ -18,11 +20,19 @@
 /// - heap.insert(sort_value, user_data) -> handle
 /// - heap.insert_or_update(handle, sort_value, user_data)
 /// - heap.node_value_update(handle, sort_value) -> handle
-/// - heap.node_value_update_with_data(handle, sort_value, user_data) -> handle
+/// - heap.node_value_update_with_data(handle, sort_value, user_data) -> user_data (previous)
 
-/// - heap.remove(handle)
+/// - heap.remove(handle) -> user_data
+/// - heap.compact(&mut handles)
 /// - heap.pop_min() -> Option(user_data)
+/// - heap.node_data(handle) -> &user_data
+/// - heap.node_data_mut(handle) -> &mut user_data
 ///
+/// Deterministic Order:
+///
+/// Since the order of equal values depends on the order items were added & removed,
+/// use `value_with_index` to order equal values by an index known to the caller,
+/// so the result only depends on the values & indices.
 
 /// Invalid index.
 const INVALID: usize = ::std::usize::MAX;
@@ -40,18 +50,33 @@ impl NodeHandle {
     pub const INVALID: NodeHandle = NodeHandle(INVALID);
 }
 
+/// A value which orders equal values by an index, see: `value_with_index`.
+pub type ValueIndex = (f64, usize);
+
+/// Return a value ordered by `value` then by `index` (when `use_index` is set),
+/// otherwise equal values are ordered by the structure of the heap.
+#[inline]
+pub fn value_with_index(
+    value: f64,
+    index: usize,
+    use_index: bool,
+) -> ValueIndex {
+    return (value, if use_index { index } else { 0 });
+}
+
 pub trait HeapValue: PartialOrd + Copy {}
 impl<TOrd> HeapValue for TOrd where TOrd: PartialOrd + Copy {}
 
-pub trait HeapData: Copy {}
-impl<TData> HeapData for TData where TData: Copy {}
+pub trait HeapData {}
+impl<TData> HeapData for TData {}
 
 pub struct Node<TOrd: HeapValue, TData: HeapData> {
     /// Value to order by.
     value: TOrd,
 
-    /// Data supplied by the API user.
-    user_data: TData,
+    /// Data supplied by the API user,
+    /// `None` once the node has been free'd (moved out to the caller).
+    user_data: Option<TData>,
 
     /// index into `MinHeap.tree_index`
     ///
@@ -207,7 +232,7 @@ impl<TOrd: HeapValue, TData: HeapData> MinHeap<TOrd, TData> {
         &mut self, free_node: usize,
     ) -> TData {
         let node = &mut self.node[free_node];
-        let user_data = node.user_data;
+        let user_data = node.user_data.take().unwrap();
         node.index = self.free;
         self.free = free_node;
         return user_data;
@@ -222,7 +247,7 @@ impl<TOrd: HeapValue, TData: HeapData> MinHeap<TOrd, TData> {
         let tree_index = self.tree_index.len();
 
         let nhandle = self.node_take(Node {
-            user_data: user_data,
+            user_data: Some(user_data),
             value: value,
             index: tree_index,
         });
@@ -299,7 +324,9 @@ impl<TOrd: HeapValue, TData: HeapData> MinHeap<TOrd, TData> {
         }
     }
 
-    pub fn remove(&mut self, nhandle: NodeHandle) {
+    pub fn remove(
+        &mut self, nhandle: NodeHandle,
+    ) -> TData {
         debug_assert!(self.tree_index.len() != 0);
         debug_assert!(nhandle.0 < self.node.len());
         let mut i = self.node[nhandle.0].index;
@@ -309,7 +336,7 @@ impl<TOrd: HeapValue, TData: HeapData> MinHeap<TOrd, TData> {
             self.heap_swap(p, i);
             i = p;
         }
-        self.pop_min();
+        return self.pop_min().unwrap();
     }
 
     #[allow(dead_code)]
@@ -328,20 +355,34 @@ impl<TOrd: HeapValue, TData: HeapData> MinHeap<TOrd, TData> {
         };
         if value < value_curr {
             self.node[nhandle.0].value = value;
-            self.heap_up(index);
+            // Fast path, skip re-ordering when the parent is still smaller.
+            if index != 0 && !self.heap_compare(bin_parent(index), index) {
+                self.heap_up(index);
+            }
         } else if value > value_curr {
             self.node[nhandle.0].value = value;
-            self.heap_down(index);
+            // Fast path, skip re-ordering when neither child is smaller.
+            let size = self.tree_index.len();
+            let l = bin_left(index);
+            let r = bin_right(index);
+            if (l < size && self.heap_compare(l, index)) ||
+               (r < size && self.heap_compare(r, index))
+            {
+                self.heap_down(index);
+            }
         }
     }
 
+    /// Update the value & data, returning the previous data.
     pub fn node_value_update_with_data(
         &mut self, nhandle: NodeHandle, value: TOrd, user_data: TData,
-    ) {
+    ) -> TData {
         debug_assert!(self.tree_index.len() != 0);
         debug_assert!(nhandle.0 < self.node.len());
-        self.node[nhandle.0].user_data = user_data;
+        let user_data_prev = ::std::mem::replace(
+            self.node[nhandle.0].user_data.as_mut().unwrap(), user_data);
         self.node_value_update(nhandle, value);
+        return user_data_prev;
     }
 
     #[allow(dead_code)]
@@ -353,8 +394,40 @@ impl<TOrd: HeapValue, TData: HeapData> MinHeap<TOrd, TData> {
     #[allow(dead_code)]
     pub fn node_data(
         &self, nhandle: NodeHandle,
-    ) -> TData {
-        return self.node[nhandle.0].user_data;
+    ) -> &TData {
+        return self.node[nhandle.0].user_data.as_ref().unwrap();
+    }
+    #[allow(dead_code)]
+    pub fn node_data_mut(
+        &mut self, nhandle: NodeHandle,
+    ) -> &mut TData {
+        return self.node[nhandle.0].user_data.as_mut().unwrap();
+    }
+
+    /// Free unused nodes, storing the remaining nodes in tree order.
+    ///
+    /// This invalidates node handles, `handles` are updated to reference the same nodes,
+    /// handles to nodes which are no longer in the heap are set to `NodeHandle::INVALID`.
+    #[allow(dead_code)]
+    pub fn compact(
+        &mut self, handles: &mut [NodeHandle],
+    ) {
+        let mut node_src: Vec<Option<Node<TOrd, TData>>> =
+            self.node.drain(..).map(|node| Some(node)).collect();
+        let mut remap = vec![INVALID; node_src.len()];
+        for node_index in &mut self.tree_index {
+            remap[*node_index] = self.node.len();
+            self.node.push(node_src[*node_index].take().unwrap());
+            *node_index = self.node.len() - 1;
+        }
+        self.node.shrink_to_fit();
+        self.free = INVALID;
+
+        for nhandle in handles {
+            if nhandle.0 != INVALID {
+                *nhandle = NodeHandle(remap[nhandle.0]);
+            }
+        }
     }
 
     #[allow(dead_code)]
@@ -2,5 +2,7 @@
 pub mod argparse;
 pub mod curve_fit_nd;
 pub mod image_load;
+pub mod json;
 pub mod math_vector;
 pub mod min_heap;
+pub mod rng;
@@ -0,0 +1,133 @@
+/// A small seedable pseudo-random number generator (xoshiro256**),
+/// so stochastic options give the same result for the same seed.
+///
+/// Overview:
+///
+/// Module:
+/// - Rng::new(seed) -> Rng
+/// - Rng::with_stream(seed, stream) -> Rng
+///
+/// Methods:
+/// - rng.next_u64() -> u64
+/// - rng.next_u32() -> u32
+/// - rng.next_f64() -> f64 (in `[0, 1)`)
+/// - rng.next_bool() -> bool
+/// - rng.range(len) -> usize (in `[0, len)`)
+/// - rng.shuffle(&mut slice)
+///
+/// Threads:
+///
+/// Each thread should use its own stream (typically the thread index),
+/// streams are separated by jumping ahead 2^128 steps,
+/// so they never overlap & the result doesn't depend on how work is scheduled.
+///
+/// This is *not* suitable for cryptography.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rng {
+    state: [u64; 4],
+}
+
+/// Used to expand the seed into the initial state (as recommended for xoshiro).
+#[inline]
+fn splitmix64(x: &mut u64) -> u64 {
+    *x = x.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = *x;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    return z ^ (z >> 31);
+}
+
+#[allow(dead_code)]
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        let mut x = seed;
+        let state = [
+            splitmix64(&mut x), splitmix64(&mut x), splitmix64(&mut x), splitmix64(&mut x),
+        ];
+        return Rng {
+            state: state,
+        };
+    }
+
+    /// Return the generator for `stream`, independent of other streams with the same seed.
+    pub fn with_stream(seed: u64, stream: usize) -> Rng {
+        let mut rng = Rng::new(seed);
+        for _ in 0..stream {
+            rng.jump();
+        }
+        return rng;
+    }
+
+    #[inline]
+    pub fn next_u64(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        return result;
+    }
+
+    #[inline]
+    pub fn next_u32(&mut self) -> u32 {
+        // The upper bits are the most random.
+        return (self.next_u64() >> 32) as u32;
+    }
+
+    /// Return a value in `[0, 1)`.
+    #[inline]
+    pub fn next_f64(&mut self) -> f64 {
+        return (self.next_u64() >> 11) as f64 * (1.0 / (1_u64 << 53) as f64);
+    }
+
+    #[inline]
+    pub fn next_bool(&mut self) -> bool {
+        return (self.next_u64() >> 63) != 0;
+    }
+
+    /// Return a value in `[0, len)`, without bias towards lower values.
+    pub fn range(&mut self, len: usize) -> usize {
+        debug_assert!(len != 0);
+        let len = len as u64;
+        // Reject values from the last partial multiple of `len`.
+        let limit = ::std::u64::MAX - (::std::u64::MAX % len);
+        loop {
+            let value = self.next_u64();
+            if value < limit {
+                return (value % len) as usize;
+            }
+        }
+    }
+
+    /// Shuffle `slice` in place (Fisher-Yates).
+    pub fn shuffle<T>(&mut self, slice: &mut [T]) {
+        for i in (1..slice.len()).rev() {
+            let j = self.range(i + 1);
+            slice.swap(i, j);
+        }
+    }
+
+    /// Advance the state by 2^128 steps, see: `with_stream`.
+    pub fn jump(&mut self) {
+        const JUMP: [u64; 4] = [
+            0x180ec6d33cfd0aba, 0xd5a61266f0c9392c, 0xa9582618e03fc9aa, 0x39abdc4529b1661c,
+        ];
+        let mut s = [0_u64; 4];
+        for &jump in &JUMP {
+            for b in 0..64 {
+                if (jump & (1_u64 << b)) != 0 {
+                    for j in 0..4 {
+                        s[j] ^= self.state[j];
+                    }
+                }
+                self.next_u64();
+            }
+        }
+        self.state = s;
+    }
+}
@@ -1,3 +1,6 @@
+// Most of the command line & file-system functionality is unused on WASM, see: `trace_bytes`.
+#![cfg_attr(target_arch = "wasm32", allow(dead_code, unused_imports))]
+
 ///
 /// Raster Re-Trace, Main function.
 ///
@@ -5,6 +8,8 @@
 /// image loading and calling tracing functionality.
 ///
 
+#[cfg(feature = "jpeg")]
+extern crate jpeg_decoder;
 
 mod intern;
 
@@ -13,26 +18,88 @@ mod polys_from_raster_outline;
 mod polys_from_raster_centerline;
 
 mod polys_simplify_collapse;
+mod polys_boolean;
+mod polys_hull;
+mod polys_join_dashes;
+mod polys_centerline_subpixel;
+mod polys_multiscale;
 
 mod image_skeletonize;
+mod image_filter;
+mod image_hatching;
+mod image_symmetry;
+mod image_rasterize;
+mod image_runs;
 
 use std::collections::LinkedList;
+use std::sync::Arc;
+
+mod curve_types;
+mod curve_simplify;
+mod curve_symmetry;
+mod curve_snap;
+mod curve_inflection;
+mod curve_round;
+mod curve_shapes;
+mod curve_resample;
+mod curve_stroke_width;
 
 // IO
 mod curve_write;
+mod image_write;
+
+mod memory_usage;
+mod extract_cache;
+#[cfg(not(target_arch = "wasm32"))]
+mod serve;
 
 use ::intern::{
     curve_fit_nd,
+    rng,
 };
 
+use std::any::Any;
+
 
 const PRINT_STATISTICS: bool = true;
 
+/// Statistics are printed to the standard output,
+/// except while serving where results are written there (see: `serve`).
+fn print_statistics(
+    trace_params: &TraceParams,
+) -> bool
+{
+    return PRINT_STATISTICS && !trace_params.use_serve && trace_params.listen_filepath.is_empty();
+}
+
+/// Exit status when any input fails in batch mode (invalid arguments exit with 1).
+const EXIT_BATCH_FAILED: i32 = 2;
+
+/// Exit status with `--fail-empty` when the inputs have no filled pixels.
+const EXIT_EMPTY: i32 = 3;
+
+/// Inputs in batch mode at least this size (in bytes) are traced using all threads,
+/// smaller inputs are traced in parallel with each other.
+const BATCH_INPUT_LARGE_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Maximum distance from the curve when converting curves back into polygons.
+const FLATTEN_TOLERANCE: f64 = 0.01;
+
+/// Maximum segment length of polygons before fitting, used by `Tracer`.
+const TRACER_LENGTH_THRESHOLD: f64 = 0.75;
+
 /// Debug passes:
 /// useful when investigating changes to internal behavior.
 mod debug_pass {
     const DIMS: usize = ::intern::math_vector::DIMS;
     use std::collections::LinkedList;
+    use std::sync::Mutex;
+
+    use ::intern::curve_fit_nd::{
+        FitObserver,
+        FitSnapshot,
+        FitStage,
+    };
 
     pub mod kind {
         /// polygon as extracted from pixels
@@ -41,262 +108,4173 @@ mod debug_pass {
         pub const PRE_FIT: u32 = 1 << 1;
         /// bezier handles
         pub const TANGENT: u32 = 1 << 2;
+        /// animation of each stage, from pixels to curves
+        pub const ANIM: u32 = 1 << 3;
+        /// direction & start point of each path
+        pub const DIRECTION: u32 = 1 << 4;
+        /// knots remaining after removing knots (before corners are split)
+        pub const POST_REMOVE: u32 = 1 << 5;
+        /// knots remaining after splitting corners (corner knots are marked)
+        pub const POST_CORNER: u32 = 1 << 6;
+    }
+
+    /// The name of each pass, as used on the command line.
+    pub const NAMES: &'static [(u32, &'static str)] = &[
+        (kind::PIXEL, "PIXEL"),
+        (kind::PRE_FIT, "PRE_FIT"),
+        (kind::TANGENT, "TANGENT"),
+        (kind::ANIM, "ANIM"),
+        (kind::DIRECTION, "DIRECTION"),
+        (kind::POST_REMOVE, "POST_REMOVE"),
+        (kind::POST_CORNER, "POST_CORNER"),
+    ];
+
+    /// Parse a comma separated list of pass names, returning the flags.
+    pub fn kind_from_names(
+        text: &str,
+    ) -> Result<u32, String>
+    {
+        let mut passes = 0;
+        for name in text.split(",") {
+            match NAMES.iter().find(|&&(_, n)| n == name) {
+                Some(&(flag, _)) => {
+                    passes |= flag;
+                }
+                None => {
+                    return Err(format!(
+                        concat!("Expected [PIXEL, PRE_FIT, TANGENT, ANIM, DIRECTION, ",
+                                "POST_REMOVE, POST_CORNER], not '{}'"),
+                        text));
+                }
+            }
+        }
+        return Ok(passes);
     }
+
+    /// Seconds to show each stage of the `ANIM` pass.
+    pub const ANIM_STEP_DURATION: f64 = 1.0;
     // passes that write out debug info
+    #[derive(Clone)]
     pub struct Item {
+        /// Name of the pass (used for labels in the output).
+        pub name: &'static str,
         pub poly_list: LinkedList<(bool, Vec<[f64; DIMS]>)>,
+        /// Points marked on top of the polygons (corner knots for example).
+        pub corners: Vec<[f64; DIMS]>,
     }
 
     pub fn add_pass(
         pass_items: &mut LinkedList<Item>,
+        name: &'static str,
         poly_list: &LinkedList<(bool, Vec<[f64; DIMS]>)>,
     )
     {
         pass_items.push_back(
             Item {
+                name: name,
                 poly_list: poly_list.clone(),
+                corners: vec![],
             }
         );
     }
+
+    /// Collects the knots remaining after each refinement pass while fitting,
+    /// see: `kind::POST_REMOVE` & `kind::POST_CORNER`.
+    pub struct FitSnapshots {
+        snapshots: Mutex<Vec<(usize, bool, FitSnapshot)>>,
+    }
+
+    impl FitObserver for FitSnapshots {
+        fn snapshot(
+            &self,
+            index: usize,
+            is_cyclic: bool,
+            snapshot: &FitSnapshot,
+        ) {
+            self.snapshots.lock().unwrap().push((index, is_cyclic, snapshot.clone()));
+        }
+    }
+
+    impl FitSnapshots {
+        pub fn new() -> FitSnapshots {
+            return FitSnapshots {
+                snapshots: Mutex::new(vec![]),
+            };
+        }
+
+        /// Add an item for each pass in `debug_passes`,
+        /// with the knots of each polygon (in the order polygons were passed to the fitter).
+        pub fn add_passes(
+            self,
+            pass_items: &mut LinkedList<Item>,
+            debug_passes: u32,
+        )
+        {
+            let mut snapshots = self.snapshots.into_inner().unwrap();
+            // Threads may fit polygons in any order.
+            snapshots.sort_by_key(|&(index, _, _)| index);
+            for &(flag, name, stage) in &[
+                (kind::POST_REMOVE, "POST_REMOVE", FitStage::PostRemove),
+                (kind::POST_CORNER, "POST_CORNER", FitStage::PostCorner),
+            ] {
+                if (debug_passes & flag) == 0 {
+                    continue;
+                }
+                let mut item = Item {
+                    name: name,
+                    poly_list: LinkedList::new(),
+                    corners: vec![],
+                };
+                for &(_, is_cyclic, ref snapshot) in &snapshots {
+                    if snapshot.stage != stage {
+                        continue;
+                    }
+                    item.poly_list.push_back(
+                        (is_cyclic, snapshot.knots.iter().map(|k| k.co).collect()));
+                    item.corners.extend(
+                        snapshot.knots.iter().filter(|k| k.is_corner).map(|k| k.co));
+                }
+                pass_items.push_back(item);
+            }
+        }
+    }
 }
 
-pub fn trace_image(
-    output_filepath: &String,
-    output_scale: f64,
+/// The offset which moves the image to `TraceParams::origin` (after flipping).
+fn output_origin_offset(
+    trace_params: &TraceParams,
+    size: &[usize; 2],
+) -> [f64; 2]
+{
+    let size = [size[0] as f64, size[1] as f64];
+    let use_flip_y = trace_params.output_use_flip_y();
+    let origin = trace_params.origin.unwrap_or(
+        if use_flip_y { Origin::BottomLeft } else { Origin::TopLeft });
+    // The location of the origin in the image (after flipping).
+    let co = match origin {
+        Origin::TopLeft => [0.0, if use_flip_y { size[1] } else { 0.0 }],
+        Origin::BottomLeft => [0.0, if use_flip_y { 0.0 } else { size[1] }],
+        Origin::Center => [size[0] / 2.0, size[1] / 2.0],
+    };
+    // Subtract from zero, so the offset is never negative zero.
+    return [0.0 - co[0], 0.0 - co[1]];
+}
+
+/// Calculate the transform applied to all geometry before writing,
+/// so output conventions are handled in one place instead of by each writer.
+///
+/// This maps pixel coordinates to pixel coordinates,
+/// since the output scale is applied by the writers.
+fn output_transform_from_params(
+    trace_params: &TraceParams,
+    size: &[usize; 2],
+) -> curve_types::Transform
+{
+    use curve_types::Transform;
+
+    let output_scale = trace_params.output_scale;
+    let mut t = Transform::IDENTITY;
+
+    // Image rows are stored top to bottom (matching SVG),
+    // flip for formats & applications which use Y-up.
+    if trace_params.output_use_flip_y() {
+        t = Transform::translate(0.0, size[1] as f64).multiply(
+            &Transform::scale(1.0, -1.0));
+    }
+
+    let origin_offset = output_origin_offset(trace_params, size);
+    if origin_offset != [0.0, 0.0] {
+        t = Transform::translate(origin_offset[0], origin_offset[1]).multiply(&t);
+    }
+
+    // The user transform is in output units.
+    if !trace_params.output_transform.is_identity() {
+        t = Transform::scale(1.0 / output_scale, 1.0 / output_scale).multiply(
+            &trace_params.output_transform.multiply(
+                &Transform::scale(output_scale, output_scale).multiply(&t)));
+    }
+
+    return t;
+}
+
+/// The name of a solver, as used by `--solvers`.
+fn solver_id(
+    solver: curve_fit_nd::Solver,
+) -> &'static str
+{
+    return match solver {
+        curve_fit_nd::Solver::Fallback => "FALLBACK",
+        curve_fit_nd::Solver::LeastSquare => "LS",
+        curve_fit_nd::Solver::Circle => "CIRCLE",
+        curve_fit_nd::Solver::Offset => "OFFSET",
+    };
+}
+
+/// The result of tracing an image, transformed & ready to write.
+pub struct TraceOutput {
+    pub drawing: curve_types::Drawing,
+    /// Centers of isolated pixels (see `DotMode::Circle`).
+    pub dots: Vec<[f64; 2]>,
+    pub dot_radius: f64,
+    pass_items: LinkedList<debug_pass::Item>,
+    /// Stages shown by the `ANIM` pass (before the curves).
+    anim_items: LinkedList<debug_pass::Item>,
+    /// Knot removals for each polygon (in pixels), when `refine_log_filepath` is set.
+    refine_log: Vec<Vec<curve_fit_nd::RefineStep>>,
+    /// Measurements of the extracted polygons (in pixels).
+    pub measurements: Vec<curve_types::RegionMeasurement>,
+    /// The time taken by each stage of tracing (in seconds).
+    pub timings: Vec<(&'static str, f64)>,
+    /// The distance from each point of `fit_error_points` to the fitted curves (in pixels),
+    /// when `report_filepath` or `error_map_filepath` is set.
+    pub fit_errors: Vec<f64>,
+    /// Points along the extracted polygons (subdivided, so they're at most half a pixel apart).
+    pub fit_error_points: Vec<[f64; 2]>,
+}
+
+/// Hooks to observe or modify the geometry between stages of tracing (in pixels),
+/// so applications can extend tracing (custom filters, snapping... etc)
+/// without changes to this crate.
+///
+/// All methods do nothing by default, `()` can be used when no hooks are needed.
+pub trait TraceHooks {
+    /// Polygons as extracted from the image, before they're simplified.
+    fn extract(
+        &self,
+        _poly_list: &mut LinkedList<(bool, Vec<[f64; 2]>)>,
+    ) {}
+
+    /// Polygons after simplification, before curves are fit.
+    ///
+    /// The number & order of polygons must not change,
+    /// since meta-data is attached to the curves fit to them.
+    fn simplify(
+        &self,
+        _poly_list: &mut LinkedList<(bool, Vec<[f64; 2]>)>,
+    ) {}
+
+    /// Curves after fitting & post-processing, before they're transformed for output.
+    fn fit(
+        &self,
+        _drawing: &mut curve_types::Drawing,
+    ) {}
+
+    /// Measures the error of each fit, see: `curve_fit_nd::ErrorEval`
+    /// (None to use the default).
+    fn error_eval(
+        &self,
+    ) -> Option<Arc<dyn curve_fit_nd::ErrorEval>> {
+        return None;
+    }
+}
+
+impl TraceHooks for () {}
+
+/// Optional hooks, doing nothing when unset.
+impl<H: TraceHooks> TraceHooks for Option<H> {
+    fn extract(
+        &self,
+        poly_list: &mut LinkedList<(bool, Vec<[f64; 2]>)>,
+    ) {
+        if let Some(ref hooks) = *self {
+            hooks.extract(poly_list);
+        }
+    }
+
+    fn simplify(
+        &self,
+        poly_list: &mut LinkedList<(bool, Vec<[f64; 2]>)>,
+    ) {
+        if let Some(ref hooks) = *self {
+            hooks.simplify(poly_list);
+        }
+    }
+
+    fn fit(
+        &self,
+        drawing: &mut curve_types::Drawing,
+    ) {
+        if let Some(ref hooks) = *self {
+            hooks.fit(drawing);
+        }
+    }
+
+    fn error_eval(
+        &self,
+    ) -> Option<Arc<dyn curve_fit_nd::ErrorEval>> {
+        return self.as_ref().and_then(|hooks| hooks.error_eval());
+    }
+}
+
+/// Both hooks, called in order.
+impl<A: TraceHooks, B: TraceHooks> TraceHooks for (A, B) {
+    fn extract(
+        &self,
+        poly_list: &mut LinkedList<(bool, Vec<[f64; 2]>)>,
+    ) {
+        self.0.extract(poly_list);
+        self.1.extract(poly_list);
+    }
+
+    fn simplify(
+        &self,
+        poly_list: &mut LinkedList<(bool, Vec<[f64; 2]>)>,
+    ) {
+        self.0.simplify(poly_list);
+        self.1.simplify(poly_list);
+    }
+
+    fn fit(
+        &self,
+        drawing: &mut curve_types::Drawing,
+    ) {
+        self.0.fit(drawing);
+        self.1.fit(drawing);
+    }
+
+    /// The first hooks which set the error evaluation.
+    fn error_eval(
+        &self,
+    ) -> Option<Arc<dyn curve_fit_nd::ErrorEval>> {
+        return self.0.error_eval().or_else(|| self.1.error_eval());
+    }
+}
+
+/// Polygons extracted from an image, ready for fitting,
+/// this doesn't depend on parameters which only affect fitting, see: `TraceParams::extract_eq`.
+struct TraceExtract {
+    /// The image used for tracing (without isolated pixels when they're removed).
+    image: Vec<bool>,
+    /// Centers of isolated pixels (see `DotMode::Circle`).
+    dots: Vec<[f64; 2]>,
+    meta_list: Vec<curve_types::PathMeta>,
+    measurements: Vec<curve_types::RegionMeasurement>,
+    poly_list_to_fit: LinkedList<(bool, Vec<[f64; 2]>)>,
+    pass_items: LinkedList<debug_pass::Item>,
+    anim_items: LinkedList<debug_pass::Item>,
+}
+
+pub fn trace_image_to_drawing(
+    trace_params: &TraceParams,
     image: &[bool],
     size: &[usize; 2],
-    error_threshold: f64,
-    simplify_threshold: f64,
-    corner_angle: f64,
-    use_optimize_exhaustive: bool,
     length_threshold: f64,
-    mode: curve_fit_nd::TraceMode,
-    // only for outline
-    turn_policy: polys_from_raster_outline::TurnPolicy,
-    debug_passes: u32,
-    debug_pass_scale: f64,
-) -> Result<(), ::std::io::Error>
+) -> TraceOutput
 {
+    return trace_image_to_drawing_with_hooks(trace_params, image, size, length_threshold, &());
+}
+
+/// Trace the image, calling `hooks` between each stage, see: `TraceHooks`.
+pub fn trace_image_to_drawing_with_hooks<H: TraceHooks>(
+    trace_params: &TraceParams,
+    image: &[bool],
+    size: &[usize; 2],
+    length_threshold: f64,
+    hooks: &H,
+) -> TraceOutput
+{
+    let time_extract = ::std::time::Instant::now();
+    let extract = trace_image_extract(trace_params, image, size, length_threshold, hooks);
+    let seconds_extract = time_extract.elapsed().as_secs_f64();
+    let mut output = trace_extract_to_drawing(
+        trace_params, &extract, size, length_threshold, hooks);
+    output.timings.insert(0, ("extract", seconds_extract));
+    return output;
+}
+
+/// Extract & simplify polygons from the image (everything before fitting).
+fn trace_image_extract<H: TraceHooks>(
+    trace_params: &TraceParams,
+    image: &[bool],
+    size: &[usize; 2],
+    length_threshold: f64,
+    hooks: &H,
+) -> TraceExtract
+{
+    let simplify_threshold = trace_params.simplify_threshold;
+    let simplify_size_classes = {
+        if trace_params.simplify_small.is_some() || trace_params.simplify_large.is_some() {
+            Some(polys_simplify_collapse::SimplifySizeClasses {
+                size_cutoff: trace_params.simplify_cutoff,
+                small: trace_params.simplify_small.unwrap_or(simplify_threshold),
+                large: trace_params.simplify_large.unwrap_or(simplify_threshold),
+            })
+        } else {
+            None
+        }
+    };
+    let mode = trace_params.mode;
+    let turn_policy = trace_params.turn_policy_seeded();
+    let debug_passes = trace_params.debug_passes;
+
     debug_assert!(size[0] * size[1] == image.len());
 
+    // Isolated pixels are written as circles or dropped,
+    // instead of fitting curves to degenerate polygons.
+    let mut image = image.to_vec();
+    let mut dots: Vec<[f64; 2]> = vec![];
+    if trace_params.dots != DotMode::Render {
+        let pixels = image_filter::remove_isolated_pixels(&mut image, size);
+        if trace_params.dots == DotMode::Circle {
+            dots.extend(pixels.iter().map(|p| [p[0] as f64 + 0.5, p[1] as f64 + 0.5]));
+        }
+    }
+
     // TODO, we could split these operations per-polygon
     // so they can be easily threaded.
 
     let mut pass_items: LinkedList<debug_pass::Item> = LinkedList::new();
+    let mut anim_items: LinkedList<debug_pass::Item> = LinkedList::new();
 
-    let poly_list_to_fit = {
+    let mut meta_list: Vec<curve_types::PathMeta> = vec![];
+    let mut measurements: Vec<curve_types::RegionMeasurement> = vec![];
+    let mut poly_list_to_fit = {
         let poly_list_int = match mode {
             intern::curve_fit_nd::TraceMode::Outline => {
-                polys_from_raster_outline::extract_outline(
-                    image, &size,
-                    turn_policy,
-                    true)
+                use polys_from_raster_outline::OutlineMethod;
+                match trace_params.outline_method {
+                    OutlineMethod::Steps => {
+                        polys_from_raster_outline::extract_outline(
+                            &image, &size,
+                            turn_policy,
+                            trace_params.edge_policy,
+                            true)
+                    }
+                    OutlineMethod::Edges => {
+                        polys_from_raster_outline::extract_outline_sparse(
+                            &image, &size,
+                            turn_policy,
+                            trace_params.edge_policy,
+                            true)
+                    }
+                    OutlineMethod::Runs => {
+                        use image_runs::ImageRuns;
+                        polys_from_raster_outline::extract_outline_runs(
+                            &ImageRuns::from_image(&image, &size),
+                            turn_policy,
+                            trace_params.edge_policy,
+                            true)
+                    }
+                }
             }
             curve_fit_nd::TraceMode::Centerline => {
                 use polys_from_raster_centerline;
 
                 polys_from_raster_centerline::extract_centerline(
-                    image, &size, true)
+                    &image, &size, true)
+            }
+        };
+
+        // Bounding shapes replace the outlines of each region.
+        let mut poly_list_dst = match trace_params.hull {
+            Some(hull) if mode == curve_fit_nd::TraceMode::Outline => {
+                polys_hull::poly_list_hull(&poly_list_int, hull)
+            }
+            _ => {
+                polys_utils::poly_list_f64_from_i32(&poly_list_int)
+            }
+        };
+
+        hooks.extract(&mut poly_list_dst);
+
+        // Dashed lines are joined before anything else,
+        // so they're handled as a single polygon from here on.
+        let (poly_list_dst, dash_list) = if trace_params.join_dashes != 0.0 &&
+                                            mode == curve_fit_nd::TraceMode::Centerline
+        {
+            polys_join_dashes::poly_list_join_dashes(poly_list_dst, trace_params.join_dashes)
+        } else {
+            let dash_list = vec![None; poly_list_dst.len()];
+            (poly_list_dst, dash_list)
+        };
+
+        // Polygons keep their order (one output for each input) until they're fit,
+        // so meta-data can be attached to the curves afterwards.
+        meta_list.extend(poly_list_dst.iter().enumerate().map(|(i, &(is_cyclic, ref poly))| {
+            let mut meta = match mode {
+                curve_fit_nd::TraceMode::Outline => {
+                    curve_types::PathMeta::from_poly(i, is_cyclic, poly)
+                }
+                // Center-lines don't bound regions.
+                curve_fit_nd::TraceMode::Centerline => {
+                    curve_types::PathMeta {
+                        region: i,
+                        dash: if trace_params.use_dash_array { dash_list[i] } else { None },
+                        .. Default::default()
+                    }
+                }
+            };
+            meta.hash = Some(polys_utils::poly_hash(is_cyclic, poly));
+            meta
+        }));
+
+        // Only keep outer outlines or holes (center-lines don't have holes).
+        let poly_list_dst = if trace_params.contours != ContourMode::All &&
+                               mode == curve_fit_nd::TraceMode::Outline
+        {
+            let use_holes = trace_params.contours == ContourMode::Holes;
+            let mut poly_list_keep = LinkedList::new();
+            let mut meta_list_keep = vec![];
+            for (poly, meta) in poly_list_dst.into_iter().zip(meta_list.drain(..)) {
+                if meta.is_hole == use_holes {
+                    poly_list_keep.push_back(poly);
+                    meta_list_keep.push(meta);
+                }
             }
+            meta_list = meta_list_keep;
+            poly_list_keep
+        } else {
+            poly_list_dst
         };
 
-        let poly_list_dst =
-            polys_utils::poly_list_f64_from_i32(&poly_list_int);
+        // Measure the polygons before they're simplified.
+        measurements.extend(poly_list_dst.iter().zip(&meta_list).map(
+            |(&(is_cyclic, ref poly), meta)| {
+                curve_types::RegionMeasurement::from_poly(meta.region, is_cyclic, poly)
+            }));
 
         if (debug_passes & debug_pass::kind::PIXEL) != 0 {
-            debug_pass::add_pass(&mut pass_items, &poly_list_dst);
+            debug_pass::add_pass(&mut pass_items, "PIXEL", &poly_list_dst);
+        }
+        if (debug_passes & debug_pass::kind::ANIM) != 0 {
+            debug_pass::add_pass(&mut anim_items, "Pixels", &poly_list_dst);
         }
 
-        // Ensure we always have at least one knot between 'corners'
-        // this means theres always a middle tangent, giving us more possible
-        // tangents when fitting the curve.
-        let poly_list_dst =
-            polys_utils::poly_list_subdivide(&poly_list_dst);
+        // Bounding shapes are written as-is, without being simplified or fit.
+        if trace_params.hull.is_some() && mode == curve_fit_nd::TraceMode::Outline {
+            poly_list_dst
+        } else {
+            // Ensure we always have at least one knot between 'corners'
+            // this means theres always a middle tangent, giving us more possible
+            // tangents when fitting the curve.
+            let poly_list_dst =
+                polys_utils::poly_list_subdivide(&poly_list_dst);
 
-        let poly_list_dst =
-            polys_simplify_collapse::poly_list_simplify(&poly_list_dst, simplify_threshold);
+            let poly_list_dst =
+                polys_simplify_collapse::poly_list_simplify(
+                    &poly_list_dst, simplify_threshold, simplify_size_classes,
+                    trace_params.use_deterministic_fit);
 
-        let poly_list_dst =
-            polys_utils::poly_list_subdivide(&poly_list_dst);
+            if (debug_passes & debug_pass::kind::ANIM) != 0 {
+                debug_pass::add_pass(&mut anim_items, "Simplified", &poly_list_dst);
+            }
+
+            let poly_list_dst =
+                polys_utils::poly_list_subdivide(&poly_list_dst);
 
-        // While a little excessive, setting the `length_threshold` around 1.0
-        // helps by ensure the density of the polygon is even
-        // (without this diagonals will have many more points).
-        let poly_list_dst = polys_utils::poly_list_subdivide_to_limit(
-            &poly_list_dst, length_threshold);
+            // While a little excessive, setting the `length_threshold` around 1.0
+            // helps by ensure the density of the polygon is even
+            // (without this diagonals will have many more points).
+            let poly_list_dst = if trace_params.use_resample_adaptive {
+                polys_utils::poly_list_subdivide_to_limit_adaptive(
+                    &poly_list_dst, length_threshold)
+            } else {
+                polys_utils::poly_list_subdivide_to_limit(
+                    &poly_list_dst, length_threshold)
+            };
 
-        poly_list_dst
+            poly_list_dst
+        }
     };
 
+    hooks.simplify(&mut poly_list_to_fit);
+    assert!(poly_list_to_fit.len() == meta_list.len(),
+            "TraceHooks::simplify must not change the number of polygons");
+
     if (debug_passes & debug_pass::kind::PRE_FIT) != 0 {
-        debug_pass::add_pass(&mut pass_items, &poly_list_to_fit);
+        debug_pass::add_pass(&mut pass_items, "PRE_FIT", &poly_list_to_fit);
     }
 
-    let curve_list =
-        curve_fit_nd::fit_poly_list(
-            poly_list_to_fit,
-            error_threshold,
-            corner_angle,
-            use_optimize_exhaustive,
-        );
+    return TraceExtract {
+        image: image,
+        dots: dots,
+        meta_list: meta_list,
+        measurements: measurements,
+        poly_list_to_fit: poly_list_to_fit,
+        pass_items: pass_items,
+        anim_items: anim_items,
+    };
+}
 
-    if PRINT_STATISTICS {
-        let mut total_points = 0;
-        for poly in &curve_list {
-            total_points += poly.1.len();
+/// Extract polygons (see: `trace_image_extract`),
+/// reading & writing `TraceParams::cache_dirpath` when set.
+#[cfg(not(target_arch = "wasm32"))]
+fn trace_image_extract_cached(
+    trace_params: &TraceParams,
+    image: &[bool],
+    size: &[usize; 2],
+    length_threshold: f64,
+) -> TraceExtract
+{
+    if trace_params.cache_dirpath.is_empty() {
+        return trace_image_extract(trace_params, image, size, length_threshold, &());
+    }
+    let key = extract_cache::Key::new(trace_params, image, size, length_threshold);
+    if let Some(extract) = extract_cache::read(&key, image.len()) {
+        if print_statistics(trace_params) {
+            println!("Extracted polygons read from cache: {}", key.filepath.display());
         }
-        println!("Total points: {}\n", total_points);
+        return extract;
     }
+    let extract = trace_image_extract(trace_params, image, size, length_threshold, &());
+    // Tracing doesn't depend on the cache, so only report the failure.
+    if let Err(e) = extract_cache::write(&key, &extract) {
+        use std::io::Write;
+        writeln!(&mut std::io::stderr(), "Error writing cache {:?}: {}", key.filepath, e).unwrap();
+    }
+    return extract;
+}
+
+/// WASM has no file-system to cache to.
+#[cfg(target_arch = "wasm32")]
+fn trace_image_extract_cached(
+    trace_params: &TraceParams,
+    image: &[bool],
+    size: &[usize; 2],
+    length_threshold: f64,
+) -> TraceExtract
+{
+    return trace_image_extract(trace_params, image, size, length_threshold, &());
+}
+
+/// Indices to split each polygon at for `TraceParams::multiscale`,
+/// the knots of curves fit to the downscaled image (snapped to the nearest points).
+fn trace_multiscale_splits(
+    trace_params: &TraceParams,
+    image: &[bool],
+    size: &[usize; 2],
+    length_threshold: f64,
+    poly_list: &LinkedList<(bool, Vec<[f64; 2]>)>,
+) -> Vec<Vec<usize>>
+{
+    let factor = trace_params.multiscale;
+    let scale = factor as f64;
+    let (image_coarse, size_coarse) = polys_multiscale::image_downscale(image, size, factor);
+    // Errors aren't scaled, since stair-steps in the downscaled image are also larger,
+    // knots which aren't needed at full resolution are removed when fitting each span.
+    let trace_params_coarse = TraceParams {
+        debug_passes: 0,
+        .. trace_params.clone()
+    };
+    let extract = trace_image_extract(
+        &trace_params_coarse, &image_coarse, &size_coarse, length_threshold, &());
+    let fit_params = curve_fit_nd::FitParams {
+        error_relative: None,
+        use_optimize_exhaustive: false,
+        use_refine_log: false,
+        .. trace_params_coarse.fit_params()
+    };
+    let curve_list = curve_fit_nd::fit_poly_list_with_params(
+        extract.poly_list_to_fit, &fit_params, None, None, None, None).0;
+    let knots: Vec<[f64; 2]> = curve_list.iter().flat_map(|&(_, ref curve)| {
+        curve.iter().map(|knot| [knot[1][0] * scale, knot[1][1] * scale])
+    }).collect();
+    // Outlines of the downscaled image are within a (downscaled) pixel of the full outline.
+    return polys_multiscale::poly_list_snap_knots(poly_list, &knots, scale * 2.0);
+}
+
+/// Fit curves to the extracted polygons, see: `trace_image_extract`.
+fn trace_extract_to_drawing<H: TraceHooks>(
+    trace_params: &TraceParams,
+    extract: &TraceExtract,
+    size: &[usize; 2],
+    length_threshold: f64,
+    hooks: &H,
+) -> TraceOutput
+{
+    let error_threshold = trace_params.error_threshold;
+    let corner_angle = trace_params.corner_threshold;
+    let fit_params = trace_params.fit_params();
+    let mode = trace_params.mode;
 
-    let f = ::std::fs::File::create(output_filepath).expect("Create output file");
+    // Weight corners so they aren't eroded by the least squares solution,
+    // include corners below the threshold since corners above it are already knots.
+    let weight_list: Option<Vec<Vec<f64>>> = if trace_params.corner_weight != 1.0 {
+        Some(extract.poly_list_to_fit.iter().map(|&(is_cyclic, ref poly)| {
+            polys_utils::poly_corner_weights(
+                is_cyclic, poly, corner_angle / 2.0, trace_params.corner_weight)
+        }).collect())
+    } else {
+        None
+    };
+
+    let anchor_list: Option<Vec<Vec<usize>>> = if !trace_params.anchors.is_empty() {
+        Some(polys_utils::poly_list_snap_anchors(
+            &extract.poly_list_to_fit, &trace_params.anchors))
+    } else {
+        None
+    };
+
+    let image = &extract.image[..];
+    let mut dots = extract.dots.clone();
+    let mut pass_items = extract.pass_items.clone();
+    let mut anim_items = extract.anim_items.clone();
+    let meta_list = extract.meta_list.clone();
+
+    // Only collect the knots of each refinement pass when they're written.
+    let fit_snapshots = if (trace_params.debug_passes &
+                            (debug_pass::kind::POST_REMOVE | debug_pass::kind::POST_CORNER)) != 0
     {
-        curve_write::svg::write_header(&f, &size, output_scale)?;
+        Some(debug_pass::FitSnapshots::new())
+    } else {
+        None
+    };
+    let fit_observer = fit_snapshots.as_ref().map(|o| o as &dyn curve_fit_nd::FitObserver);
 
-        match mode {
-            curve_fit_nd::TraceMode::Outline => {
-                curve_write::svg::write_curve_list_filled(
-                    &f, &size, output_scale, &curve_list)?;
-            },
-            curve_fit_nd::TraceMode::Centerline => {
-                curve_write::svg::write_curve_list_centerline(
-                    &f, &size, output_scale, &curve_list)?;
+    let time_fit = ::std::time::Instant::now();
+    let (curve_list, refine_log, fit_stats) = if trace_params.hull.is_some() &&
+                                                  mode == curve_fit_nd::TraceMode::Outline
+    {
+        // Bounding shapes use straight segments (handles on their knots).
+        let curve_list = extract.poly_list_to_fit.iter().map(|&(is_cyclic, ref poly)| {
+            (is_cyclic, poly.iter().map(|v| [*v, *v, *v]).collect())
+        }).collect();
+        (curve_list, vec![], Default::default())
+    } else if trace_params.multiscale > 1 {
+        // Anchors are always knots, so split at them too (each span keeps its end-points).
+        let mut split_list = trace_multiscale_splits(
+            trace_params, image, size, length_threshold, &extract.poly_list_to_fit);
+        if let Some(ref anchor_list) = anchor_list {
+            for (splits, anchors) in split_list.iter_mut().zip(anchor_list) {
+                splits.extend(anchors);
+                splits.sort();
+                splits.dedup();
+            }
+        }
+        // The number of spans each polygon is split into (None when it's not split).
+        let mut spans_len_list: Vec<Option<usize>> = vec![];
+        let mut poly_list_spans = LinkedList::new();
+        let mut weight_list_spans = vec![];
+        for (i, &(is_cyclic, ref poly)) in extract.poly_list_to_fit.iter().enumerate() {
+            let weights = weight_list.as_ref().map(|weight_list| &weight_list[i]);
+            match polys_multiscale::poly_split_spans(is_cyclic, poly, &split_list[i]) {
+                Some(spans) => {
+                    spans_len_list.push(Some(spans.len()));
+                    if let Some(weights) = weights {
+                        weight_list_spans.extend(polys_multiscale::poly_split_spans(
+                            is_cyclic, weights, &split_list[i]).unwrap());
+                    }
+                    poly_list_spans.extend(spans.into_iter().map(|span| (false, span)));
+                }
+                None => {
+                    spans_len_list.push(None);
+                    weight_list_spans.extend(weights.cloned());
+                    poly_list_spans.push_back((is_cyclic, poly.clone()));
+                }
             }
+        }
+        // Zero length handles where spans meet would be corners, zero the end-points after.
+        let end_tangent_zero = trace_params.end_tangent == curve_fit_nd::EndTangent::Zero;
+        let fit_params_spans = curve_fit_nd::FitParams {
+            error_relative: None,
+            end_tangent: if end_tangent_zero {
+                curve_fit_nd::EndTangent::Clamped
+            } else {
+                trace_params.end_tangent
+            },
+            use_refine_log: false,
+            .. fit_params.clone()
         };
-
-        // debug info, for developing mostly
-        {
-            for item in pass_items {
-                match mode {
-                    curve_fit_nd::TraceMode::Outline => {
-                        curve_write::svg::write_poly_list_filled(
-                            &f, &size, output_scale, &item.poly_list, debug_pass_scale)?;
-                    },
-                    curve_fit_nd::TraceMode::Centerline => {
-                        curve_write::svg::write_poly_list_centerline(
-                            &f, &size, output_scale, &item.poly_list, debug_pass_scale)?;
+        let (curve_list_spans, _, fit_stats) = curve_fit_nd::fit_poly_list_with_params(
+            poly_list_spans,
+            &fit_params_spans,
+            weight_list.as_ref().map(|_| &weight_list_spans[..]),
+            None,
+            hooks.error_eval(),
+            fit_observer,
+        );
+        let mut curve_iter = curve_list_spans.into_iter();
+        let curve_list = extract.poly_list_to_fit.iter().zip(spans_len_list).map(
+            |(&(is_cyclic, _), spans_len)| {
+                let mut curve = match spans_len {
+                    Some(spans_len) => {
+                        let spans = curve_iter.by_ref().take(spans_len).map(
+                            |(_, curve)| curve).collect();
+                        polys_multiscale::curve_join_spans(is_cyclic, spans, corner_angle)
                     }
+                    None => curve_iter.next().unwrap().1,
                 };
+                if end_tangent_zero && !is_cyclic && !curve.is_empty() {
+                    let i_last = curve.len() - 1;
+                    for &i in &[0, i_last] {
+                        curve[i][0] = curve[i][1];
+                        curve[i][2] = curve[i][1];
+                    }
+                }
+                (is_cyclic, curve)
+            }).collect();
+        (curve_list, vec![], fit_stats)
+    } else {
+        curve_fit_nd::fit_poly_list_with_params(
+            extract.poly_list_to_fit.clone(),
+            &fit_params,
+            weight_list.as_ref().map(|weight_list| &weight_list[..]),
+            anchor_list.as_ref().map(|anchor_list| &anchor_list[..]),
+            hooks.error_eval(),
+            fit_observer,
+        )
+    };
+    let mut drawing = curve_types::Drawing::from_curve_list_with_meta(curve_list, meta_list);
+    let mut timings = vec![("fit", time_fit.elapsed().as_secs_f64())];
 
-            }
-            if (debug_passes & debug_pass::kind::TANGENT) != 0 {
-                curve_write::svg::write_curve_list_with_tangent_info(
-                    &f, output_scale, &curve_list, debug_pass_scale)?;
+    if let Some(fit_snapshots) = fit_snapshots {
+        fit_snapshots.add_passes(&mut pass_items, trace_params.debug_passes);
+    }
+
+    // Measure before the curves are modified (merged, simplified, transformed... etc).
+    let (fit_errors, fit_error_points) = if !trace_params.report_filepath.is_empty() ||
+                                            !trace_params.error_map_filepath.is_empty()
+    {
+        let poly_list_measure = polys_utils::poly_list_subdivide_to_limit(
+            &extract.poly_list_to_fit, 0.5);
+        let fit_errors = polys_utils::poly_list_distances(
+            &poly_list_measure, &drawing.flatten(FLATTEN_TOLERANCE), error_threshold.max(1.0));
+        let fit_error_points = poly_list_measure.into_iter().flat_map(|(_, poly)| poly).collect();
+        (fit_errors, fit_error_points)
+    } else {
+        (vec![], vec![])
+    };
+    let time_post = ::std::time::Instant::now();
+
+    // Merge overlapping regions, flatten curves and refit only the paths that changed.
+    if (trace_params.use_merge_overlaps || trace_params.silhouette != 0) &&
+       mode == curve_fit_nd::TraceMode::Outline
+    {
+        // Subdivide to match the density of the polygons used for the initial fit.
+        let poly_list_flat = polys_utils::poly_list_subdivide_to_limit(
+            &drawing.flatten(FLATTEN_TOLERANCE), length_threshold);
+        let (poly_list_merged, poly_is_intact) =
+            polys_boolean::poly_list_union(&poly_list_flat);
+
+        // Merged regions are numbered after the extracted regions.
+        let region_offset = poly_list_flat.len();
+        let meta_list_merged = poly_list_merged.iter().enumerate().map(
+            |(i, &(is_cyclic, ref poly))| {
+                curve_types::PathMeta {
+                    hash: Some(polys_utils::poly_hash(is_cyclic, poly)),
+                    .. curve_types::PathMeta::from_poly(region_offset + i, is_cyclic, poly)
+                }
+            }).collect();
+        let mut drawing_merged = curve_types::Drawing::from_curve_list_with_meta(
+            curve_fit_nd::fit_poly_list_with_params(
+                poly_list_merged,
+                &curve_fit_nd::FitParams {
+                    use_refine_log: false,
+                    .. fit_params.clone()
+                },
+                None,
+                None,
+                hooks.error_eval(),
+                None,
+            ).0,
+            meta_list_merged);
+        for (path, is_intact) in drawing.paths.into_iter().zip(poly_is_intact) {
+            if is_intact {
+                drawing_merged.paths.push_back(path);
             }
         }
-
-        curve_write::svg::write_footer(&f)?;
+        drawing = drawing_merged;
     }
 
-    Ok(())
-}
+    // Pathological input may cause the fitter to create non-finite handles,
+    // never write these to the output.
+    let non_finite_len = drawing.validate_finite();
 
-#[derive(Clone)]
-pub struct TraceParams {
-    pub error_threshold: f64,
-    pub simplify_threshold: f64,
-    pub corner_threshold: f64,
-    pub use_optimize_exhaustive: bool,
-    pub input_filepath: String,
-    pub output_filepath: String,
-    pub output_scale: f64,
-    pub mode: curve_fit_nd::TraceMode,
-    pub turn_policy: polys_from_raster_outline::TurnPolicy,
+    let simplify_post_len = if trace_params.simplify_post != 0.0 {
+        curve_simplify::drawing_simplify_knots(&mut drawing, trace_params.simplify_post)
+    } else {
+        0
+    };
 
-    pub debug_passes: u32,
-    pub debug_pass_scale: f64,
+    if let Some(direction) = trace_params.direction {
+        drawing.orient_open_paths(direction, &[size[0] as f64 / 2.0, size[1] as f64 / 2.0]);
+    }
 
-    show_help: bool,
-}
+    // Detect the axis from the image, since curves may differ too much to match reliably.
+    let symmetry_axis = trace_params.symmetry.and_then(
+        |symmetry| image_symmetry::axis_detect(image, size, symmetry));
+    let symmetry_len = if let Some(ref axis) = symmetry_axis {
+        curve_symmetry::drawing_symmetrize(&mut drawing, axis, (2.0 * error_threshold).max(1.0))
+    } else {
+        0
+    };
 
-impl Default for TraceParams {
-    fn default(
-    ) -> TraceParams
-    {
-        TraceParams {
-            error_threshold: 1.0,
-            simplify_threshold: 2.5,
-            corner_threshold: 30.0_f64.to_radians(),
-            use_optimize_exhaustive: false,
-            input_filepath: String::new(),
-            output_filepath: String::new(),
-            output_scale: 1.0,
-            mode: curve_fit_nd::TraceMode::Outline,
-            turn_policy: polys_from_raster_outline::TurnPolicy::Majority,
-            debug_passes: 0,
-            debug_pass_scale: 1.0,
+    let round_corners_len = if trace_params.round_corners != 0.0 {
+        curve_round::drawing_round_corners(&mut drawing, trace_params.round_corners)
+    } else {
+        0
+    };
 
-            show_help: false,
-        }
-    }
-}
+    let shapes_len = if trace_params.use_detect_shapes {
+        curve_shapes::drawing_detect_shapes(&mut drawing, error_threshold)
+    } else {
+        0
+    };
 
-fn main()
-{
-    use intern::argparse;
-    let mut trace_params = TraceParams::default();
+    // Split last, since other changes to the curves may add inflections.
+    let split_inflections_len = if trace_params.use_split_inflections {
+        curve_inflection::drawing_split_inflections(&mut drawing)
+    } else {
+        0
+    };
 
-    // -----------------------------------------------------------------------
-    // Parse Args
-    {
-        use std::str::FromStr;
+    // Resample last, since the knots are replaced.
+    let resample_len = if trace_params.resample_output != 0 {
+        curve_resample::drawing_resample(
+            &mut drawing, trace_params.resample_output, trace_params.use_resample_output_curve)
+    } else {
+        0
+    };
 
-        let mut parser = argparse::new(
-            &mut trace_params,
-            "Bitmap image tracing utility",
-            );
+    hooks.fit(&mut drawing);
 
-        // File Options
+    if print_statistics(trace_params) {
+        if non_finite_len != 0 {
+            println!("Non-finite segments replaced: {}", non_finite_len);
+        }
+        if let Some(ref axis) = symmetry_axis {
+            println!("Symmetry axis: {} at {} (score {:.3}), knots matched: {}",
+                     ["X", "Y"][axis.axis], axis.position, axis.score, symmetry_len);
+        } else if trace_params.symmetry.is_some() {
+            println!("Symmetry axis: none found");
+        }
+        if simplify_post_len != 0 {
+            println!("Knots removed after fitting: {}", simplify_post_len);
+        }
+        if round_corners_len != 0 {
+            println!("Corners rounded: {}", round_corners_len);
+        }
+        if split_inflections_len != 0 {
+            println!("Knots added at inflections: {}", split_inflections_len);
+        }
+        if shapes_len != 0 {
+            println!("Shapes detected: {}", shapes_len);
+        }
+        if resample_len != 0 {
+            println!("Paths resampled: {}", resample_len);
+        }
+        if dots.len() != 0 {
+            println!("Total dots: {}", dots.len());
+        }
+        println!("Total points: {}\n", drawing.knots_len());
+    }
+
+    if trace_params.use_stats {
+        println!("Fitting threads: {}, scratch memory (peak): {} KiB",
+                 fit_stats.threads_len, fit_stats.arena_bytes_peak / 1024);
+        // Solvers which weren't used (as well as the fallback) are included, showing zero.
+        let mut solvers = trace_params.solvers.clone();
+        solvers.push(curve_fit_nd::Solver::Fallback);
+        println!("Segments by solver: {}", solvers.iter().map(|solver| {
+            format!("{} {}", solver_id(*solver), fit_stats.solver_segments_len[*solver as usize])
+        }).collect::<Vec<String>>().join(", "));
+        // Every fit, including fits which were discarded (exceeding the error threshold).
+        let solver_stats = &fit_stats.solver_stats;
+        println!("Fits by solver (all attempts): {}", solvers.iter().map(|solver| {
+            format!("{} {}", solver_id(*solver), solver_stats.wins_len[*solver as usize])
+        }).collect::<Vec<String>>().join(", "));
+        if solver_stats.least_square_len != 0 {
+            println!("Re-parameterization iterations (average): {:.2} for {} least squares fits",
+                     solver_stats.reparameterize_iterations_len as f64 /
+                     solver_stats.least_square_len as f64,
+                     solver_stats.least_square_len);
+        }
+        match memory_usage::peak_resident_bytes() {
+            Some(bytes) => println!("Resident memory (peak): {} KiB\n", bytes / 1024),
+            None => println!("Resident memory (peak): unknown\n"),
+        }
+    }
+
+    // Half a pixel, scaled by the transform (using the average of both axes).
+    let mut dot_radius = 0.5;
+    {
+        let t = output_transform_from_params(trace_params, size);
+        if !t.is_identity() {
+            drawing.transform_affine(&t);
+            for item in pass_items.iter_mut().chain(anim_items.iter_mut()) {
+                polys_utils::poly_list_transform(&mut item.poly_list, |v| t.apply(v));
+                for v in &mut item.corners {
+                    *v = t.apply(v);
+                }
+            }
+            for v in &mut dots {
+                *v = t.apply(v);
+            }
+            dot_radius *= t.determinant().abs().sqrt();
+        }
+
+        // Snap after transforming, so the grid is aligned to the output.
+        if trace_params.snap != 0.0 {
+            let (snapped_len, rejected_len) = curve_snap::drawing_snap(
+                &mut drawing,
+                trace_params.snap / trace_params.output_scale,
+                trace_params.use_snap_handles,
+                error_threshold * t.determinant().abs().sqrt(),
+            );
+            if print_statistics(trace_params) {
+                println!("Snapped points: {}, exceeding the error: {}\n",
+                         snapped_len, rejected_len);
+            }
+        }
+    }
+    timings.push(("post-process", time_post.elapsed().as_secs_f64()));
+
+    return TraceOutput {
+        drawing: drawing,
+        dots: dots,
+        dot_radius: dot_radius,
+        pass_items: pass_items,
+        anim_items: anim_items,
+        refine_log: refine_log,
+        measurements: extract.measurements.clone(),
+        timings: timings,
+        fit_errors: fit_errors,
+        fit_error_points: fit_error_points,
+    };
+}
+
+/// Write the traced elements (without the header & footer).
+fn write_svg_body<W: ::std::io::Write>(
+    mut f: W,
+    trace_params: &TraceParams,
+    size: &[usize; 2],
+    output: &TraceOutput,
+) -> Result<(), ::std::io::Error>
+{
+    let output_scale = trace_params.output_scale;
+    let debug_pass_scale = trace_params.debug_pass_scale * output_scale;
+    let use_svg_layers = trace_params.use_svg_layers;
+
+    // Optionally wrap each group of elements in a layer.
+    macro_rules! layer_begin {
+        ($label:expr) => {
+            if use_svg_layers {
+                curve_write::svg::write_layer_begin(&mut f, $label)?;
+            }
+        }
+    }
+    macro_rules! layer_end {
+        () => {
+            if use_svg_layers {
+                curve_write::svg::write_layer_end(&mut f)?;
+            }
+        }
+    }
+
+    layer_begin!("Curves");
+    match trace_params.mode {
+        curve_fit_nd::TraceMode::Outline => {
+            curve_write::svg::write_drawing_filled(
+                &mut f, size, output_scale, &output.drawing, trace_params.use_path_ids)?;
+        },
+        curve_fit_nd::TraceMode::Centerline => {
+            curve_write::svg::write_drawing_centerline(
+                &mut f, size, output_scale, &output.drawing, trace_params.use_path_ids)?;
+        }
+    };
+    layer_end!();
+
+    if output.dots.len() != 0 {
+        layer_begin!("Dots");
+        curve_write::svg::write_dots(&mut f, output_scale, &output.dots, output.dot_radius)?;
+        layer_end!();
+    }
+
+    // debug info, for developing mostly
+    {
+        // Items may be collected for other outputs, see: `TraceParams::outputs_extra`.
+        let pass_items = output.pass_items.iter().filter(|item| {
+            debug_pass::NAMES.iter().any(
+                |&(flag, name)| name == item.name && (trace_params.debug_passes & flag) != 0)
+        });
+        for item in pass_items {
+            layer_begin!(item.name);
+            match trace_params.mode {
+                curve_fit_nd::TraceMode::Outline => {
+                    curve_write::svg::write_poly_list_filled(
+                        &mut f, size, output_scale, &item.poly_list, debug_pass_scale)?;
+                },
+                curve_fit_nd::TraceMode::Centerline => {
+                    curve_write::svg::write_poly_list_centerline(
+                        &mut f, size, output_scale, &item.poly_list, debug_pass_scale)?;
+                }
+            };
+            if item.corners.len() != 0 {
+                curve_write::svg::write_corners(
+                    &mut f, output_scale, &item.corners, debug_pass_scale)?;
+            }
+            layer_end!();
+        }
+        if (trace_params.debug_passes & debug_pass::kind::TANGENT) != 0 {
+            layer_begin!("TANGENT");
+            curve_write::svg::write_drawing_with_tangent_info(
+                &mut f, output_scale, &output.drawing, debug_pass_scale)?;
+            layer_end!();
+        }
+        if (trace_params.debug_passes & debug_pass::kind::DIRECTION) != 0 {
+            layer_begin!("DIRECTION");
+            curve_write::svg::write_drawing_with_direction_info(
+                &mut f, output_scale, &output.drawing, debug_pass_scale)?;
+            layer_end!();
+        }
+        if (trace_params.debug_passes & debug_pass::kind::ANIM) != 0 {
+            let stages: Vec<_> = output.anim_items.iter().map(
+                |item| (item.name, &item.poly_list)).collect();
+            layer_begin!("ANIM");
+            curve_write::svg::write_anim_stages(
+                &mut f, output_scale, &stages, &output.drawing, debug_pass_scale,
+                debug_pass::ANIM_STEP_DURATION)?;
+            layer_end!();
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn trace_image(
+    trace_params: &TraceParams,
+    image: &[bool],
+    size: &[usize; 2],
+    length_threshold: f64,
+) -> Result<TraceOutput, ::std::io::Error>
+{
+    let trace_params_all;
+    let trace_params_trace = if trace_params.outputs_extra.is_empty() {
+        trace_params
+    } else {
+        trace_params_all = trace_params.with_debug_passes_all();
+        &trace_params_all
+    };
+    let time_extract = ::std::time::Instant::now();
+    let extract = trace_image_extract_cached(trace_params_trace, image, size, length_threshold);
+    let seconds_extract = time_extract.elapsed().as_secs_f64();
+    let mut output = trace_extract_to_drawing(
+        trace_params_trace, &extract, size, length_threshold, &());
+    output.timings.insert(0, ("extract", seconds_extract));
+
+    write_output_all(trace_params, image, size, &mut output)?;
+
+    Ok(output)
+}
+
+/// Trace & write the output, calling `hooks` between each stage, see: `TraceHooks`.
+///
+/// The extraction cache isn't used, since it doesn't account for the hooks.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn trace_image_with_hooks<H: TraceHooks>(
+    trace_params: &TraceParams,
+    image: &[bool],
+    size: &[usize; 2],
+    length_threshold: f64,
+    hooks: &H,
+) -> Result<TraceOutput, ::std::io::Error>
+{
+    let mut output = trace_image_to_drawing_with_hooks(
+        &trace_params.with_debug_passes_all(), image, size, length_threshold, hooks);
+
+    write_output_all(trace_params, image, size, &mut output)?;
+
+    Ok(output)
+}
+
+/// Write the output & extra files, then the report (which includes the time taken to write).
+#[cfg(not(target_arch = "wasm32"))]
+fn write_output_all(
+    trace_params: &TraceParams,
+    image: &[bool],
+    size: &[usize; 2],
+    output: &mut TraceOutput,
+) -> Result<(), ::std::io::Error>
+{
+    let time_write = ::std::time::Instant::now();
+    write_output_extras(trace_params, size, output)?;
+    write_outputs(trace_params, size, output)?;
+    output.timings.push(("write", time_write.elapsed().as_secs_f64()));
+
+    if !trace_params.report_filepath.is_empty() {
+        let f = ::std::fs::File::create(&trace_params.report_filepath)?;
+        write_report(f, trace_params, image, size, output)?;
+    }
+
+    Ok(())
+}
+
+/// Write the files requested besides the output (refine log, measurements & preview).
+#[cfg(not(target_arch = "wasm32"))]
+fn write_output_extras(
+    trace_params: &TraceParams,
+    size: &[usize; 2],
+    output: &TraceOutput,
+) -> Result<(), ::std::io::Error>
+{
+    if !trace_params.refine_log_filepath.is_empty() {
+        let f = ::std::fs::File::create(&trace_params.refine_log_filepath)?;
+        curve_write::json::write_refine_log(&f, &output.refine_log)?;
+    }
+    if !trace_params.measurements_filepath.is_empty() {
+        let f = ::std::fs::File::create(&trace_params.measurements_filepath)?;
+        curve_write::csv::write_measurements(
+            &f, &output.measurements, trace_params.output_scale)?;
+    }
+    if !trace_params.strokes_filepath.is_empty() {
+        let f = ::std::fs::File::create(&trace_params.strokes_filepath)?;
+        curve_write::json::write_strokes(
+            &f, size, trace_params.output_scale, &output.drawing,
+            &output.dots, output.dot_radius)?;
+    }
+    if !trace_params.preview_png_filepath.is_empty() {
+        let f = ::std::fs::File::create(&trace_params.preview_png_filepath)?;
+        write_preview_png(&f, trace_params, size, output)?;
+    }
+    if !trace_params.error_map_filepath.is_empty() {
+        let f = ::std::fs::File::create(&trace_params.error_map_filepath)?;
+        write_error_map(&f, trace_params, size, output)?;
+    }
+
+    Ok(())
+}
+
+/// Write `output` to `TraceParams::output_filepath` & each of `TraceParams::outputs_extra`.
+#[cfg(not(target_arch = "wasm32"))]
+fn write_outputs(
+    trace_params: &TraceParams,
+    size: &[usize; 2],
+    output: &TraceOutput,
+) -> Result<(), ::std::io::Error>
+{
+    write_output(trace_params, size, output)?;
+    for output_extra in &trace_params.outputs_extra {
+        let trace_params_extra = TraceParams {
+            output_filepath: output_extra.filepath.clone(),
+            output_format: None,
+            outputs_extra: vec![],
+            debug_passes: output_extra.debug_passes,
+            .. trace_params.clone()
+        };
+        write_output(&trace_params_extra, size, output)?;
+    }
+    Ok(())
+}
+
+/// Write `output` to `TraceParams::output_filepath`, using the output format.
+#[cfg(not(target_arch = "wasm32"))]
+fn write_output(
+    trace_params: &TraceParams,
+    size: &[usize; 2],
+    output: &TraceOutput,
+) -> Result<(), ::std::io::Error>
+{
+    let f = ::std::fs::File::create(&trace_params.output_filepath)?;
+    return write_output_to(f, trace_params, size, output);
+}
+
+/// Write `output` using the output format, see: `write_output`.
+fn write_output_to<W: ::std::io::Write>(
+    mut f: W,
+    trace_params: &TraceParams,
+    size: &[usize; 2],
+    output: &TraceOutput,
+) -> Result<(), ::std::io::Error>
+{
+    let output_filepath = &trace_params.output_filepath;
+    let output_format = trace_params.output_format_main();
+    if output_format == OutputFormat::Glif {
+        // No need to reverse, flipping to Y-up keeps the outer contours counter-clockwise
+        // as expected for PostScript outlines.
+        let name = ::std::path::Path::new(output_filepath)
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let metadata_items = trace_params.metadata_items();
+        // The baseline is in pixels, transform it like the curves.
+        let baseline = output_transform_from_params(trace_params, size).apply(
+            &[0.0, trace_params.glif_baseline.unwrap_or(size[1] as f64)])[1];
+        curve_write::glif::write_glyph(
+            &mut f, &name, &size, &output.drawing,
+            trace_params.glif_upm,
+            baseline,
+            if trace_params.use_metadata { Some(&metadata_items) } else { None },
+        )?;
+        return Ok(());
+    }
+    if output_format == OutputFormat::Pdf {
+        let metadata_items = trace_params.metadata_items();
+        curve_write::pdf::write_document(
+            &mut f, &[pdf_page(trace_params, size, output)?],
+            if trace_params.use_metadata { Some(&metadata_items) } else { None },
+        )?;
+        return Ok(());
+    }
+    if output_format == OutputFormat::Json {
+        curve_write::json::write_drawing(
+            &mut f, size, trace_params.output_scale, &output.drawing,
+            &output.dots, output.dot_radius)?;
+        return Ok(());
+    }
+
+    if !trace_params.template_filepath.is_empty() {
+        write_svg_template(&mut f, trace_params, size, output)?;
+        return Ok(());
+    }
+
+    write_svg(&mut f, trace_params, size, output)?;
+
+    Ok(())
+}
+
+/// Fit the polygons extracted from the image at each error threshold in `TraceParams::lod`,
+/// sharing extraction & simplification between the levels of detail.
+///
+/// With `TraceParams::use_svg_layers` each level is written as a layer of a single SVG,
+/// otherwise each level is written to its own file, see: `lod_filepath`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn trace_image_lod(
+    trace_params: &TraceParams,
+    image: &[bool],
+    size: &[usize; 2],
+    length_threshold: f64,
+) -> Result<(), ::std::io::Error>
+{
+    let extract = trace_image_extract_cached(trace_params, image, size, length_threshold);
+    let levels = trace_params.lod.iter().enumerate().map(|(i, &error_threshold)| {
+        let trace_params_level = TraceParams {
+            error_threshold: error_threshold,
+            output_filepath: lod_filepath(&trace_params.output_filepath, i),
+            .. trace_params.clone()
+        };
+        let output = trace_extract_to_drawing(
+            &trace_params_level, &extract, size, length_threshold, &());
+        (trace_params_level, output)
+    });
+
+    if !trace_params.use_svg_layers {
+        for (trace_params_level, output) in levels {
+            write_output(&trace_params_level, size, &output)?;
+        }
+        return Ok(());
+    }
+
+    let f = ::std::fs::File::create(&trace_params.output_filepath)?;
+    curve_write::svg::write_header(
+        &f, &size, &output_origin_offset(trace_params, size), trace_params.output_scale, true)?;
+    if trace_params.use_metadata {
+        curve_write::svg::write_metadata(&f, &trace_params.metadata_items())?;
+    }
+    for (i, (trace_params_level, output)) in levels.enumerate() {
+        // Each level is a single layer, without layers for the elements within it.
+        let trace_params_level = TraceParams {
+            use_svg_layers: false,
+            .. trace_params_level
+        };
+        curve_write::svg::write_layer_begin(&f, &format!("LOD-{}", i))?;
+        write_svg_body(&f, &trace_params_level, size, &output)?;
+        curve_write::svg::write_layer_end(&f)?;
+    }
+    curve_write::svg::write_footer(&f)?;
+
+    Ok(())
+}
+
+/// Write an anti-aliased raster of the output (matching the SVG view),
+/// see: `TraceParams::preview_png_filepath`.
+fn write_preview_png<W: ::std::io::Write>(
+    f: W,
+    trace_params: &TraceParams,
+    size: &[usize; 2],
+    output: &TraceOutput,
+) -> Result<(), ::std::io::Error>
+{
+    let scale = trace_params.preview_scale;
+    let size_preview = [
+        ::std::cmp::max(1, (size[0] as f64 * scale).round() as usize),
+        ::std::cmp::max(1, (size[1] as f64 * scale).round() as usize),
+    ];
+    let view_min = output_origin_offset(trace_params, size);
+    // Show the output as it's displayed, where formats which use Y-up have the Y axis flipped.
+    let use_flip_y = trace_params.output_format_main().is_y_up();
+    let to_preview = |v: &[f64; 2]| {
+        let y = v[1] - view_min[1];
+        [(v[0] - view_min[0]) * scale, (if use_flip_y { size[1] as f64 - y } else { y }) * scale]
+    };
+    let mut poly_list = output.drawing.flatten(FLATTEN_TOLERANCE);
+    polys_utils::poly_list_transform(&mut poly_list, &to_preview);
+
+    // Strokes are one unit wide in the output, like the SVG.
+    let stroke_width = scale / trace_params.output_scale;
+    let mut raster_fill = image_rasterize::Raster::new(&size_preview);
+    let mut raster_stroke = image_rasterize::Raster::new(&size_preview);
+    let poly_list_stroke: LinkedList<(bool, Vec<[f64; 2]>)> = match trace_params.mode {
+        curve_fit_nd::TraceMode::Outline => {
+            for &(is_cyclic, ref poly) in &poly_list {
+                if is_cyclic {
+                    raster_fill.add_poly(poly);
+                }
+            }
+            poly_list.into_iter().filter(|&(is_cyclic, _)| !is_cyclic).collect()
+        },
+        curve_fit_nd::TraceMode::Centerline => poly_list,
+    };
+    raster_stroke.add_poly_list_stroke(&poly_list_stroke, stroke_width);
+    let dots: Vec<[f64; 2]> = output.dots.iter().map(&to_preview).collect();
+    raster_stroke.add_circles(&dots, output.dot_radius * scale);
+
+    let pixels: Vec<[u8; 4]> = raster_fill.coverage().iter().zip(
+        raster_stroke.coverage()).map(|(fill, stroke)| {
+        [0, 0, 0, (fill.max(stroke) * 255.0).round() as u8]
+    }).collect();
+    image_write::png::write_rgba(f, &size_preview, &pixels)?;
+
+    Ok(())
+}
+
+/// Write a grayscale image of the fit error along the boundary,
+/// see: `TraceParams::error_map_filepath`.
+///
+/// Pixels the polygons pass through are `1` (no error) to `255` (the error threshold or more),
+/// the largest error is used when multiple points are in a pixel, other pixels are zero.
+fn write_error_map<W: ::std::io::Write>(
+    f: W,
+    trace_params: &TraceParams,
+    size: &[usize; 2],
+    output: &TraceOutput,
+) -> Result<(), ::std::io::Error>
+{
+    let mut pixels = vec![0_u8; size[0] * size[1]];
+    if size[0] != 0 && size[1] != 0 {
+        for (v, &error) in output.fit_error_points.iter().zip(&output.fit_errors) {
+            // Outlines are on pixel corners, center-lines on pixel centers.
+            let (x, y) = match trace_params.mode {
+                curve_fit_nd::TraceMode::Outline => (v[0].floor(), v[1].floor()),
+                curve_fit_nd::TraceMode::Centerline => (v[0].round(), v[1].round()),
+            };
+            let x = (x.max(0.0) as usize).min(size[0] - 1);
+            let y = (y.max(0.0) as usize).min(size[1] - 1);
+            let value = 1.0 + ((error / trace_params.error_threshold).min(1.0) * 254.0);
+            let pixel = &mut pixels[x + (y * size[0])];
+            *pixel = (*pixel).max(value.round() as u8);
+        }
+    }
+    image_write::pgm::write_gray(f, size, &pixels)?;
+
+    Ok(())
+}
+
+/// Write an HTML page with the input, output, parameters, timings & fit error,
+/// see: `TraceParams::report_filepath`.
+fn write_report<W: ::std::io::Write>(
+    f: W,
+    trace_params: &TraceParams,
+    image: &[bool],
+    size: &[usize; 2],
+    output: &TraceOutput,
+) -> Result<(), ::std::io::Error>
+{
+    let pixels: Vec<[u8; 4]> = image.iter().map(|&is_fill| {
+        if is_fill { [0, 0, 0, 255] } else { [255, 255, 255, 255] }
+    }).collect();
+    let mut image_png: Vec<u8> = vec![];
+    image_write::png::write_rgba(&mut image_png, size, &pixels)?;
+    let mut svg: Vec<u8> = vec![];
+    write_svg(&mut svg, trace_params, size, output)?;
+
+    let title = trace_params.input_filepaths.iter().map(|filepath| {
+        ::std::path::Path::new(filepath).file_name()
+            .map_or_else(|| filepath.clone(), |name| name.to_string_lossy().into_owned())
+    }).collect::<Vec<String>>().join(", ");
+    curve_write::html::write_report(
+        f, &title, &image_png, &svg, &trace_params.metadata_items(),
+        &output.timings, &output.fit_errors, trace_params.error_threshold)?;
+
+    Ok(())
+}
+
+/// Write the traced elements into the SVG template, see: `TraceParams::template_filepath`.
+#[cfg(not(target_arch = "wasm32"))]
+fn write_svg_template<W: ::std::io::Write>(
+    mut f: W,
+    trace_params: &TraceParams,
+    size: &[usize; 2],
+    output: &TraceOutput,
+) -> Result<(), ::std::io::Error>
+{
+    use std::io::{Error, ErrorKind};
+    let template = ::std::fs::read_to_string(&trace_params.template_filepath)?;
+
+    // Without the SVG header, the origin is applied by offsetting the group.
+    let view_min = output_origin_offset(trace_params, size);
+    let mut content: Vec<u8> = vec![];
+    curve_write::svg::write_group_begin(
+        &mut content, env!("CARGO_PKG_NAME"), &[
+            0.0 - view_min[0] * trace_params.output_scale,
+            0.0 - view_min[1] * trace_params.output_scale,
+        ])?;
+    write_svg_body(&mut content, trace_params, size, output)?;
+    curve_write::svg::write_group_end(&mut content)?;
+
+    let text = curve_write::svg::template_splice(
+        &template, &trace_params.template_selector, &String::from_utf8_lossy(&content),
+    ).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    f.write_all(text.as_bytes())?;
+
+    Ok(())
+}
+
+/// Templates are read from a file, which isn't supported on WASM.
+#[cfg(target_arch = "wasm32")]
+fn write_svg_template<W: ::std::io::Write>(
+    _f: W,
+    _trace_params: &TraceParams,
+    _size: &[usize; 2],
+    _output: &TraceOutput,
+) -> Result<(), ::std::io::Error>
+{
+    use std::io::{Error, ErrorKind};
+    return Err(Error::new(ErrorKind::Unsupported, "SVG templates require file-system access"));
+}
+
+/// Write a complete SVG document for a single traced image.
+fn write_svg<W: ::std::io::Write>(
+    mut f: W,
+    trace_params: &TraceParams,
+    size: &[usize; 2],
+    output: &TraceOutput,
+) -> Result<(), ::std::io::Error>
+{
+    curve_write::svg::write_header(
+        &mut f, &size, &output_origin_offset(trace_params, size),
+        trace_params.output_scale, trace_params.use_svg_layers || trace_params.use_stroke_widths)?;
+    if trace_params.use_metadata {
+        curve_write::svg::write_metadata(&mut f, &trace_params.metadata_items())?;
+    }
+    write_svg_body(&mut f, trace_params, size, output)?;
+    curve_write::svg::write_footer(&mut f)?;
+
+    Ok(())
+}
+
+/// Write the traced curves & dots as a PDF page (matching the SVG view).
+fn pdf_page(
+    trace_params: &TraceParams,
+    size: &[usize; 2],
+    output: &TraceOutput,
+) -> Result<curve_write::pdf::Page, ::std::io::Error>
+{
+    let scale = trace_params.output_scale;
+    let size_page = [size[0] as f64 * scale, size[1] as f64 * scale];
+    let mut content: Vec<u8> = vec![];
+    curve_write::pdf::write_page_content(
+        &mut content, &output_origin_offset(trace_params, size), scale,
+        &output.drawing, trace_params.mode == curve_fit_nd::TraceMode::Outline,
+        &output.dots, output.dot_radius,
+    )?;
+    return Ok(curve_write::pdf::Page {
+        size: size_page,
+        content: content,
+    });
+}
+
+/// Trace RGBA pixels (4 bytes per pixel, rows from the top, as used by HTML canvas `ImageData`),
+/// returning the SVG document, without file-system access (so it can run in WASM).
+///
+/// Pixels darker than half intensity are filled, transparent pixels are treated as white.
+#[allow(dead_code)]
+pub fn trace_bytes(
+    image_data: &[u8],
+    width: usize,
+    height: usize,
+    trace_params: &TraceParams,
+) -> Result<String, ::std::io::Error>
+{
+    use std::io::{Error, ErrorKind};
+    // Checked, since `usize` is 32 bits on WASM.
+    if width.checked_mul(height).and_then(|len| len.checked_mul(4)) != Some(image_data.len()) {
+        return Err(Error::new(ErrorKind::InvalidInput, format!(
+            "Expected 4 bytes per pixel for a {}x{} image, not {} bytes",
+            width, height, image_data.len(),
+        )));
+    }
+    let size = [width, height];
+    let mut image: Vec<bool> = image_data.chunks(4).map(|p| {
+        let t = p[0] as u32 + p[1] as u32 + p[2] as u32;
+        // Composite over white.
+        let t = (t * p[3] as u32 + 765 * (255 - p[3] as u32)) / 255;
+        t < (127 * 3)
+    }).collect();
+    image_prepare_for_trace(trace_params, &mut image, &size);
+
+    let output = trace_image_to_drawing(trace_params, &image, &size, 0.75);
+    let mut data: Vec<u8> = vec![];
+    write_svg(&mut data, trace_params, &size, &output)?;
+    return String::from_utf8(data).map_err(|e| Error::new(ErrorKind::InvalidData, e));
+}
+
+/// Trace an image for interactive editors, keeping the extracted polygons
+/// so changing parameters which only affect fitting (error, corner angle... etc)
+/// only needs to fit the curves again.
+#[allow(dead_code)]
+pub struct Tracer<H: TraceHooks = ()> {
+    trace_params: TraceParams,
+    /// The image before filtering.
+    image: Vec<bool>,
+    size: [usize; 2],
+    extract: TraceExtract,
+    hooks: H,
+}
+
+#[allow(dead_code)]
+impl Tracer {
+    pub fn new(
+        trace_params: &TraceParams,
+        image: Vec<bool>,
+        size: &[usize; 2],
+    ) -> Tracer
+    {
+        return Tracer::with_hooks(trace_params, image, size, ());
+    }
+}
+
+#[allow(dead_code)]
+impl<H: TraceHooks> Tracer<H> {
+    /// Trace using `hooks`, see: `TraceHooks`.
+    pub fn with_hooks(
+        trace_params: &TraceParams,
+        image: Vec<bool>,
+        size: &[usize; 2],
+        hooks: H,
+    ) -> Tracer<H>
+    {
+        let extract = Tracer::extract(trace_params, &image, size, &hooks);
+        return Tracer {
+            trace_params: trace_params.clone(),
+            image: image,
+            size: *size,
+            extract: extract,
+            hooks: hooks,
+        };
+    }
+
+    fn extract(
+        trace_params: &TraceParams,
+        image: &[bool],
+        size: &[usize; 2],
+        hooks: &H,
+    ) -> TraceExtract
+    {
+        let mut image = image.to_vec();
+        image_prepare_for_trace(trace_params, &mut image, size);
+        return trace_image_extract(
+            trace_params, &image, size, TRACER_LENGTH_THRESHOLD, hooks);
+    }
+
+    pub fn trace_params(&self) -> &TraceParams {
+        return &self.trace_params;
+    }
+
+    /// Use new parameters, polygons are only extracted again when needed,
+    /// returns true when they were.
+    pub fn set_trace_params(
+        &mut self,
+        trace_params: &TraceParams,
+    ) -> bool
+    {
+        let use_extract = !self.trace_params.extract_eq(trace_params);
+        if use_extract {
+            self.extract = Tracer::extract(trace_params, &self.image, &self.size, &self.hooks);
+        }
+        self.trace_params = trace_params.clone();
+        return use_extract;
+    }
+
+    /// Fit curves using the current parameters.
+    pub fn trace(&self) -> TraceOutput {
+        return trace_extract_to_drawing(
+            &self.trace_params, &self.extract, &self.size, TRACER_LENGTH_THRESHOLD, &self.hooks);
+    }
+
+    /// Fit curves using the current parameters, returning the SVG document.
+    pub fn trace_to_svg(&self) -> Result<String, ::std::io::Error> {
+        use std::io::{Error, ErrorKind};
+        let mut data: Vec<u8> = vec![];
+        write_svg(&mut data, &self.trace_params, &self.size, &self.trace())?;
+        return String::from_utf8(data).map_err(|e| Error::new(ErrorKind::InvalidData, e));
+    }
+}
+
+/// Trace multiple images into a single SVG, laid out in a grid with `columns`,
+/// each image is written into its own group.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn trace_images_montage(
+    trace_params: &TraceParams,
+    images: &[(Vec<bool>, [usize; 2])],
+    columns: usize,
+    length_threshold: f64,
+) -> Result<(), ::std::io::Error>
+{
+    let output_scale = trace_params.output_scale;
+
+    // All cells use the size of the largest image.
+    let mut cell_size = [0, 0];
+    for &(_, size) in images {
+        cell_size[0] = ::std::cmp::max(cell_size[0], size[0]);
+        cell_size[1] = ::std::cmp::max(cell_size[1], size[1]);
+    }
+    let columns = ::std::cmp::max(1, ::std::cmp::min(columns, images.len()));
+    let rows = (images.len() + columns - 1) / columns;
+    let montage_size = [cell_size[0] * columns, cell_size[1] * rows];
+
+    let f = ::std::fs::File::create(&trace_params.output_filepath).expect("Create output file");
+    curve_write::svg::write_header(
+        &f, &montage_size, &[0.0, 0.0], output_scale, trace_params.use_svg_layers)?;
+    if trace_params.use_metadata {
+        curve_write::svg::write_metadata(&f, &trace_params.metadata_items())?;
+    }
+    for (i, &(ref image, ref size)) in images.iter().enumerate() {
+        let output = trace_image_to_drawing(trace_params, image, size, length_threshold);
+        curve_write::svg::write_group_begin(
+            &f,
+            &format!("input-{}", i),
+            &[
+                ((i % columns) * cell_size[0]) as f64 * output_scale,
+                ((i / columns) * cell_size[1]) as f64 * output_scale,
+            ],
+        )?;
+        write_svg_body(&f, trace_params, size, &output)?;
+        curve_write::svg::write_group_end(&f)?;
+    }
+    curve_write::svg::write_footer(&f)?;
+
+    Ok(())
+}
+
+/// Trace each frame of a multi-frame input into the same SVG,
+/// where only the first frame is visible.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn trace_image_frames_layered(
+    trace_params: &TraceParams,
+    frames: &[(Vec<bool>, [usize; 2])],
+    length_threshold: f64,
+) -> Result<(), ::std::io::Error>
+{
+    let mut frame_size = [0, 0];
+    for &(_, size) in frames {
+        frame_size[0] = ::std::cmp::max(frame_size[0], size[0]);
+        frame_size[1] = ::std::cmp::max(frame_size[1], size[1]);
+    }
+
+    let f = ::std::fs::File::create(&trace_params.output_filepath).expect("Create output file");
+    curve_write::svg::write_header(
+        &f, &frame_size, &output_origin_offset(trace_params, &frame_size),
+        trace_params.output_scale, trace_params.use_svg_layers)?;
+    if trace_params.use_metadata {
+        curve_write::svg::write_metadata(&f, &trace_params.metadata_items())?;
+    }
+    for (i, &(ref image, ref size)) in frames.iter().enumerate() {
+        let output = trace_image_to_drawing(trace_params, image, size, length_threshold);
+        curve_write::svg::write_frame_begin(&f, &format!("frame-{:04}", i + 1), i == 0)?;
+        write_svg_body(&f, trace_params, size, &output)?;
+        curve_write::svg::write_group_end(&f)?;
+    }
+    curve_write::svg::write_footer(&f)?;
+
+    Ok(())
+}
+
+/// Trace the difference between two images (the same size) into a single SVG,
+/// areas only filled in `image_other` are written to the "Added" layer,
+/// areas only filled in `image` to the "Removed" layer.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn trace_image_diff(
+    trace_params: &TraceParams,
+    image: &[bool],
+    image_other: &[bool],
+    size: &[usize; 2],
+    length_threshold: f64,
+) -> Result<(), ::std::io::Error>
+{
+    let mut image_added = image_other.to_vec();
+    image_filter::subtract(&mut image_added, image);
+    let mut image_removed = image.to_vec();
+    image_filter::subtract(&mut image_removed, image_other);
+
+    let f = ::std::fs::File::create(&trace_params.output_filepath).expect("Create output file");
+    curve_write::svg::write_header(
+        &f, size, &output_origin_offset(trace_params, size),
+        trace_params.output_scale, true)?;
+    if trace_params.use_metadata {
+        curve_write::svg::write_metadata(&f, &trace_params.metadata_items())?;
+    }
+    for &(label, ref image_diff) in &[("Added", &image_added), ("Removed", &image_removed)] {
+        let output = trace_image_to_drawing(trace_params, image_diff, size, length_threshold);
+        curve_write::svg::write_layer_begin(&f, label)?;
+        write_svg_body(&f, trace_params, size, &output)?;
+        curve_write::svg::write_layer_end(&f)?;
+    }
+    curve_write::svg::write_footer(&f)?;
+
+    Ok(())
+}
+
+/// Number the file path for a frame (starting at 1), `out.svg` -> `out_0001.svg`.
+pub fn frame_filepath(
+    filepath: &str,
+    frame: usize,
+) -> String {
+    return filepath_with_suffix(filepath, &format!("_{:04}", frame));
+}
+
+/// The file path for each level of detail, see: `TraceParams::lod`.
+pub fn lod_filepath(
+    filepath: &str,
+    level: usize,
+) -> String {
+    return filepath_with_suffix(filepath, &format!("_lod{}", level));
+}
+
+/// Add `suffix` to the file name (before the extension).
+fn filepath_with_suffix(
+    filepath: &str,
+    suffix: &str,
+) -> String {
+    let path = ::std::path::Path::new(filepath);
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut name = format!("{}{}", stem, suffix);
+    if let Some(ext) = path.extension() {
+        name.push('.');
+        name.push_str(&ext.to_string_lossy());
+    }
+    return path.with_file_name(name).to_string_lossy().into_owned();
+}
+
+/// Pixels for each inch (matching SVG & CSS).
+const PIXELS_PER_INCH: f64 = 96.0;
+
+/// Parse a size `WxH` with an optional unit suffix in `[mm, in, px]`, returning pixels.
+pub fn fit_size_from_str(
+    text: &str,
+) -> Result<[f64; 2], String> {
+    let (text_size, unit_scale) = {
+        if text.ends_with("mm") {
+            (&text[..text.len() - 2], PIXELS_PER_INCH / 25.4)
+        } else if text.ends_with("in") {
+            (&text[..text.len() - 2], PIXELS_PER_INCH)
+        } else if text.ends_with("px") {
+            (&text[..text.len() - 2], 1.0)
+        } else {
+            (text, 1.0)
+        }
+    };
+    let values: Vec<&str> = text_size.split('x').collect();
+    if values.len() != 2 {
+        return Err(format!("Expected WxH[mm|in|px], not '{}'", text));
+    }
+    let mut size = [0.0; 2];
+    for (i, value) in values.iter().enumerate() {
+        match value.parse::<f64>() {
+            Ok(v) if v > 0.0 && v.is_finite() => {
+                size[i] = v * unit_scale;
+            }
+            _ => {
+                return Err(format!("Expected a positive number, not '{}'", value));
+            }
+        }
+    }
+    return Ok(size);
+}
+
+/// The scale which fits an image of `size` within `fit_size` (preserving the aspect).
+pub fn output_scale_to_fit(
+    fit_size: &[f64; 2],
+    size: &[usize; 2],
+) -> f64 {
+    return (fit_size[0] / size[0] as f64).min(fit_size[1] / size[1] as f64);
+}
+
+/// Parse anchor points, an `X Y` pixel coordinate on each line
+/// (also separated by a comma), blank lines and lines starting with `#` are ignored.
+pub fn anchors_from_str(
+    text: &str,
+) -> Result<Vec<[f64; 2]>, String> {
+    let mut anchors: Vec<[f64; 2]> = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let values: Vec<&str> = line.split(|c: char| c == ',' || c.is_whitespace()).filter(
+            |value| !value.is_empty()).collect();
+        let co: Vec<f64> = values.iter().filter_map(
+            |value| value.parse::<f64>().ok().filter(|v| v.is_finite())).collect();
+        if values.len() != 2 || co.len() != 2 {
+            return Err(format!("Expected 'X Y' on line {}, not '{}'", i + 1, line));
+        }
+        anchors.push([co[0], co[1]]);
+    }
+    return Ok(anchors);
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum OutputFormat {
+    Svg,
+    /// UFO glyph.
+    Glif,
+    Pdf,
+    /// Curves for applications which use them directly, see: `curve_write::json::write_drawing`.
+    Json,
+}
+
+impl OutputFormat {
+    /// Detect the format from the file extension, falling back to SVG.
+    pub fn from_filepath(
+        filepath: &str,
+    ) -> OutputFormat {
+        if filepath.to_lowercase().ends_with(".glif") {
+            return OutputFormat::Glif;
+        }
+        if filepath.to_lowercase().ends_with(".pdf") {
+            return OutputFormat::Pdf;
+        }
+        if filepath.to_lowercase().ends_with(".json") {
+            return OutputFormat::Json;
+        }
+        return OutputFormat::Svg;
+    }
+
+    /// Formats where the Y axis points up, so the output is flipped by default,
+    /// see: `TraceParams::output_use_flip_y`.
+    pub fn is_y_up(
+        &self,
+    ) -> bool {
+        return match *self {
+            OutputFormat::Glif | OutputFormat::Pdf => true,
+            OutputFormat::Svg | OutputFormat::Json => false,
+        };
+    }
+}
+
+/// An output besides `TraceParams::output_filepath`, written from the same trace.
+#[derive(Clone, PartialEq, Debug)]
+pub struct OutputExtra {
+    /// The format is detected from the file extension, see: `OutputFormat::from_filepath`.
+    pub filepath: String,
+    /// Debug passes for this output (`TraceParams::debug_passes` is for the first output).
+    pub debug_passes: u32,
+}
+
+impl OutputExtra {
+    /// Parse `FILEPATH[:PASSES]`, a suffix which isn't a list of passes is part of the path.
+    pub fn from_arg(
+        arg: &str,
+    ) -> OutputExtra
+    {
+        if let Some(i) = arg.rfind(':') {
+            if let Ok(debug_passes) = debug_pass::kind_from_names(&arg[(i + 1)..]) {
+                return OutputExtra {
+                    filepath: arg[..i].to_string(),
+                    debug_passes: debug_passes,
+                };
+            }
+        }
+        return OutputExtra {
+            filepath: arg.to_string(),
+            debug_passes: 0,
+        };
+    }
+}
+
+/// How to write the frames of multi-frame inputs.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum FrameMode {
+    /// Write each frame to its own file, numbered from 1.
+    Files,
+    /// Write each frame into a group of a single SVG.
+    Layers,
+}
+
+/// Which outlines to trace.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ContourMode {
+    /// Outer outlines & holes.
+    All,
+    /// Outer outlines (silhouettes), ignoring holes.
+    Outer,
+    /// Holes only.
+    Holes,
+}
+
+/// Where the origin of the output coordinates is placed on the image.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Origin {
+    TopLeft,
+    BottomLeft,
+    Center,
+}
+
+/// How to handle isolated (single) pixels.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum DotMode {
+    /// Trace as any other region.
+    Render,
+    /// Write a circle at the pixel center.
+    Circle,
+    /// Remove from the output.
+    Drop,
+}
+
+#[derive(Clone)]
+pub struct TraceParams {
+    pub error_threshold: f64,
+    pub error_relative: f64,
+    pub error_min: f64,
+    /// When set, fit at each of these error thresholds (levels of detail),
+    /// instead of `error_threshold`, see: `trace_image_lod`.
+    pub lod: Vec<f64>,
+    pub simplify_threshold: f64,
+    /// Override `simplify_threshold` for polygons smaller than `simplify_cutoff`.
+    pub simplify_small: Option<f64>,
+    /// Override `simplify_threshold` for polygons at least `simplify_cutoff` in size.
+    pub simplify_large: Option<f64>,
+    pub simplify_cutoff: f64,
+    /// Remove fitted knots when the curve deviates less than this (zero to disable).
+    pub simplify_post: f64,
+    pub corner_threshold: f64,
+    pub use_optimize_exhaustive: bool,
+    /// Stop exhaustive refitting once `exhaustive_steps` consecutive candidates
+    /// improve the error by less than `exhaustive_epsilon`, see: `curve_fit_nd::ExhaustivePrune`.
+    pub use_exhaustive_prune: bool,
+    pub exhaustive_epsilon: f64,
+    pub exhaustive_steps: usize,
+    /// When above 1, fit curves to the image downscaled by this factor first,
+    /// splitting polygons at the knots of the coarse curves, see: `polys_multiscale`.
+    pub multiscale: usize,
+    /// Order equally suitable knots & edges by index while simplifying & fitting,
+    /// so the output only depends on the input & parameters.
+    pub use_deterministic_fit: bool,
+    /// Store point lengths & tangents as `f32` while fitting,
+    /// curves exceeding the error threshold are fit again using `f64`.
+    pub use_precision_f32: bool,
+    pub use_resample_adaptive: bool,
+    pub end_tangent: curve_fit_nd::EndTangent,
+    /// Limit handle lengths to this factor of the segment length (zero to disable).
+    pub max_handle_ratio: f64,
+    /// Average tangents along paths within this length before fitting (zero to disable).
+    pub tangent_smooth: f64,
+    /// Methods used to fit each segment (in order), see: `curve_fit_nd::Solver`.
+    pub solvers: Vec<curve_fit_nd::Solver>,
+    /// Maximum re-parameterization iterations refining the least squares solution.
+    pub refine_iterations: usize,
+    /// Stop re-parameterizing once the error improves by less than this (zero to disable).
+    pub refine_epsilon: f64,
+    /// Weight of points at corners when fitting,
+    /// higher values reduce rounding of corners (one to disable).
+    pub corner_weight: f64,
+    /// Points (in pixels) which are always kept as knots, snapped to the nearest polygon point.
+    pub anchors: Vec<[f64; 2]>,
+    /// Replace corners with arcs of this radius (zero to disable).
+    pub round_corners: f64,
+    /// Split segments where their curvature changes direction.
+    pub use_split_inflections: bool,
+    /// Replace paths matching circles, ellipses, rectangles & lines with these shapes.
+    pub use_detect_shapes: bool,
+    /// Resample each path to this many points, spaced uniformly by arc length (zero to disable).
+    pub resample_output: usize,
+    /// Join resampled points with a smooth curve (instead of lines).
+    pub use_resample_output_curve: bool,
+    pub use_merge_overlaps: bool,
+    /// Orient open paths (center-lines) to follow this direction.
+    pub direction: Option<curve_types::StrokeDirection>,
+    /// Make curves symmetrical along a mirror axis detected from the image.
+    pub symmetry: Option<image_symmetry::Symmetry>,
+    /// Multiple inputs are only supported with `montage_columns`.
+    pub input_filepaths: Vec<String>,
+    /// Read inputs as headerless grayscale data with this layout.
+    pub input_raw: Option<::intern::image_load::RawFormat>,
+    /// Pixels darker than this (from zero to one) are filled.
+    pub threshold: f64,
+    /// Fail when there are no filled pixels to trace (the empty output is still written).
+    pub use_fail_empty: bool,
+    /// When set, write the intensity histogram of the input (as SVG, `-` prints it).
+    pub histogram_filepath: String,
+    /// When set, only trace pixels which are filled in this image.
+    pub mask_filepath: String,
+    /// When set, trace the difference between the input and this image.
+    pub diff_filepath: String,
+    pub output_filepath: String,
+    /// When unset, detect from `output_filepath`.
+    pub output_format: Option<OutputFormat>,
+    /// Outputs from additional `--output` arguments.
+    pub outputs_extra: Vec<OutputExtra>,
+    /// When set, write measurements of each region (as CSV).
+    pub measurements_filepath: String,
+    /// When set, write center-lines with the stroke width at each knot (as JSON).
+    pub strokes_filepath: String,
+    /// When set, write a raster preview of the output (as PNG).
+    pub preview_png_filepath: String,
+    /// The size of the preview relative to the input image.
+    pub preview_scale: f64,
+    /// When set, write the fit error along the boundary of each region (as PGM),
+    /// see: `write_error_map`.
+    pub error_map_filepath: String,
+    /// When set, write a report with the input, output, parameters, timings & fit error
+    /// (as HTML).
+    pub report_filepath: String,
+    pub output_scale: f64,
+    /// When set, calculate `output_scale` to fit within this size (in pixels).
+    pub fit_size: Option<[f64; 2]>,
+    pub output_transform: curve_types::Transform,
+    /// Flip the output so the Y axis points up
+    /// (always done for formats which use Y-up), see: `output_use_flip_y`.
+    pub use_flip_y: bool,
+    /// When unset, use the top-left (or the bottom-left when `use_flip_y` is set).
+    pub origin: Option<Origin>,
+    /// Snap knots to a grid of this size in output units (zero to disable).
+    pub snap: f64,
+    pub use_snap_handles: bool,
+    /// When non-zero, lay out all inputs in a grid with this many columns.
+    pub montage_columns: usize,
+    /// When set, trace all frames of the input (otherwise only the first).
+    pub frames: Option<FrameMode>,
+    /// Trace each input into its own file, using `output_filepath` as the directory.
+    pub use_batch: bool,
+    /// When set, write the result of tracing each input in batch mode (as JSON).
+    pub batch_report_filepath: String,
+    /// Write the inputs in batch mode as pages of a single PDF (`output_filepath`),
+    /// each page keeps its own size & scale.
+    pub use_pdf_multipage: bool,
+    pub mode: curve_fit_nd::TraceMode,
+    pub turn_policy: polys_from_raster_outline::TurnPolicy,
+    /// The seed passed to the `RANDOM` turn policy (`RANDOM:SEED`),
+    /// otherwise it's seeded from `seed`, see: `turn_policy_seeded`.
+    pub turn_policy_seed: Option<u32>,
+    /// Handling of outlines touching the image border.
+    pub edge_policy: polys_from_raster_outline::EdgePolicy,
+    pub contours: ContourMode,
+    /// When set, write a bounding shape for each region instead of its outline.
+    pub hull: Option<polys_hull::HullMode>,
+    /// Join dashed center-lines with gaps up to this length (zero to disable).
+    pub join_dashes: f64,
+    /// Move center-lines to the middle of strokes in the grayscale image,
+    /// measuring cross-sections up to this distance (zero to disable),
+    /// see: `polys_centerline_subpixel`.
+    pub centerline_subpixel: f64,
+    /// Write the dash pattern of joined dashes.
+    pub use_dash_array: bool,
+    /// Write the stroke width at each knot of center-lines as Inkscape power-strokes.
+    pub use_stroke_widths: bool,
+    pub dots: DotMode,
+    pub min_feature: usize,
+    /// Remove hatching made of strokes up to this width (zero to disable).
+    pub remove_hatching: usize,
+    pub fill_holes: usize,
+    /// When non-zero, join regions closer than this & fill all holes,
+    /// tracing a single silhouette.
+    pub silhouette: usize,
+
+    pub use_svg_layers: bool,
+    /// Write an `id` for each path from the hash of its source polygon, see: `PathMeta::hash`.
+    pub use_path_ids: bool,
+    /// When set, insert the output into this SVG.
+    pub template_filepath: String,
+    /// Where to insert the output into the template, see: `curve_write::svg::template_splice`.
+    pub template_selector: String,
+    pub use_metadata: bool,
+    pub glif_upm: f64,
+    /// When unset, use the bottom of the image.
+    pub glif_baseline: Option<f64>,
+
+    pub debug_passes: u32,
+    pub debug_pass_scale: f64,
+    /// When set, write the order knots are removed while fitting (as JSON).
+    pub refine_log_filepath: String,
+    /// Print memory usage after tracing.
+    pub use_stats: bool,
+    /// The maximum number of threads (zero to use all available threads).
+    pub threads_len: usize,
+    /// Seed for stochastic options, see: `rng_stream`.
+    pub seed: u64,
+    /// How outlines are followed, this only changes the memory used & performance.
+    pub outline_method: polys_from_raster_outline::OutlineMethod,
+    /// When set, store extracted polygons in this directory,
+    /// reusing them when only fitting parameters change, see: `extract_cache`.
+    pub cache_dirpath: String,
+    /// Keep running, tracing images for commands read from the standard input, see: `serve`.
+    pub use_serve: bool,
+    /// When set, keep running, tracing images for commands received on a socket at this path.
+    pub listen_filepath: String,
+    /// Don't print warnings (for arguments which have no effect for example).
+    pub use_quiet: bool,
+
+    show_help: bool,
+    /// Only print help for this group, see: `ArgumentParser::print_help_group`.
+    show_help_group: String,
+    /// Arguments which have no effect, see: `ArgumentParser::warnings`.
+    warnings: Vec<String>,
+}
+
+impl TraceParams {
+    /// The format of `output_filepath`.
+    pub fn output_format_main(
+        &self,
+    ) -> OutputFormat
+    {
+        return self.output_format.unwrap_or_else(
+            || OutputFormat::from_filepath(&self.output_filepath));
+    }
+
+    /// Flip the output so the Y axis points up,
+    /// when requested or when the output format uses Y-up, see: `OutputFormat::is_y_up`.
+    pub fn output_use_flip_y(
+        &self,
+    ) -> bool
+    {
+        return self.use_flip_y || self.output_format_main().is_y_up();
+    }
+
+    /// Return true when the output (or any of the extra outputs) uses `output_format`.
+    pub fn output_format_any(
+        &self,
+        output_format: OutputFormat,
+    ) -> bool
+    {
+        return self.output_format_main() == output_format ||
+            self.outputs_extra.iter().any(
+                |output| OutputFormat::from_filepath(&output.filepath) == output_format);
+    }
+
+    /// The random number generator for `stream` (typically the thread index),
+    /// so stochastic options are reproducible for the same `seed`.
+    pub fn rng_stream(
+        &self,
+        stream: usize,
+    ) -> rng::Rng
+    {
+        return rng::Rng::with_stream(self.seed, stream);
+    }
+
+    /// The turn policy, where `RANDOM` without a seed of its own is seeded from `seed`.
+    pub fn turn_policy_seeded(
+        &self,
+    ) -> polys_from_raster_outline::TurnPolicy
+    {
+        use polys_from_raster_outline::TurnPolicy;
+        return match self.turn_policy {
+            TurnPolicy::Random(_) => TurnPolicy::Random(self.turn_policy_seed.unwrap_or_else(
+                || self.rng_stream(0).next_u32())),
+            turn_policy => turn_policy,
+        };
+    }
+
+    /// Early termination for exhaustive refitting (steps of zero when disabled).
+    pub fn exhaustive_prune(&self) -> curve_fit_nd::ExhaustivePrune {
+        return curve_fit_nd::ExhaustivePrune {
+            epsilon: self.exhaustive_epsilon,
+            steps: if self.use_exhaustive_prune { self.exhaustive_steps } else { 0 },
+        };
+    }
+
+    /// Settings for fitting curves, see: `curve_fit_nd::FitParams`.
+    pub fn fit_params(&self) -> curve_fit_nd::FitParams {
+        let error_relative = if self.error_relative != 0.0 {
+            Some(curve_fit_nd::ErrorRelative {
+                factor: self.error_relative,
+                min: self.error_min.min(self.error_threshold),
+                max: self.error_threshold,
+            })
+        } else {
+            None
+        };
+        return curve_fit_nd::FitParams {
+            error_threshold: self.error_threshold,
+            error_relative: error_relative,
+            corner_angle: self.corner_threshold,
+            use_optimize_exhaustive: self.use_optimize_exhaustive,
+            end_tangent: self.end_tangent,
+            max_handle_ratio: self.max_handle_ratio,
+            tangent_smooth: self.tangent_smooth,
+            solvers: self.solvers.clone(),
+            reparameterize: curve_fit_nd::Reparameterize {
+                iterations: self.refine_iterations,
+                epsilon: self.refine_epsilon,
+            },
+            exhaustive_prune: self.exhaustive_prune(),
+            use_deterministic: self.use_deterministic_fit,
+            use_precision_f32: self.use_precision_f32,
+            use_refine_log: !self.refine_log_filepath.is_empty(),
+            threads_len_max: threads_len_from_params(self),
+        };
+    }
+
+    /// Measure the width of center-lines at each knot, see: `curve_stroke_width`.
+    pub fn use_stroke_widths_any(
+        &self,
+    ) -> bool
+    {
+        return self.use_stroke_widths || !self.strokes_filepath.is_empty();
+    }
+
+    /// Return true when both parameters extract the same polygons from an image,
+    /// so only fitting needs to be performed again.
+    pub fn extract_eq(
+        &self,
+        other: &TraceParams,
+    ) -> bool
+    {
+        return
+            self.mode == other.mode &&
+            self.turn_policy_seeded() == other.turn_policy_seeded() &&
+            self.edge_policy == other.edge_policy &&
+            self.contours == other.contours &&
+            self.hull == other.hull &&
+            self.join_dashes == other.join_dashes &&
+            self.centerline_subpixel == other.centerline_subpixel &&
+            self.use_dash_array == other.use_dash_array &&
+            self.dots == other.dots &&
+            self.min_feature == other.min_feature &&
+            self.remove_hatching == other.remove_hatching &&
+            self.fill_holes == other.fill_holes &&
+            self.silhouette == other.silhouette &&
+            self.simplify_threshold == other.simplify_threshold &&
+            self.simplify_small == other.simplify_small &&
+            self.simplify_large == other.simplify_large &&
+            self.simplify_cutoff == other.simplify_cutoff &&
+            self.use_deterministic_fit == other.use_deterministic_fit &&
+            self.use_resample_adaptive == other.use_resample_adaptive &&
+            self.debug_passes == other.debug_passes;
+    }
+
+    /// A copy of the parameters which collects the debug passes of every output.
+    pub fn with_debug_passes_all(
+        &self,
+    ) -> TraceParams
+    {
+        return TraceParams {
+            debug_passes: self.outputs_extra.iter().fold(
+                self.debug_passes, |passes, output| passes | output.debug_passes),
+            .. self.clone()
+        };
+    }
+
+    /// Parameters as `(name, value)` pairs using the command line argument names & values,
+    /// written into the output so results can be reproduced.
+    pub fn metadata_items(
+        &self,
+    ) -> Vec<(&'static str, String)>
+    {
+        use polys_from_raster_outline::TurnPolicy;
+
+        let mut passes: Vec<&str> = vec![];
+        for &(flag, name) in debug_pass::NAMES {
+            if (self.debug_passes & flag) != 0 {
+                passes.push(name);
+            }
+        }
+
+        let mut items: Vec<(&'static str, String)> = self.input_filepaths.iter().map(
+            |filepath| ("input", filepath.clone())).collect();
+        items.extend(vec![
+            ("input-raw", self.input_raw.map(|r| r.to_string()).unwrap_or_default()),
+            ("threshold", self.threshold.to_string()),
+            ("histogram", self.histogram_filepath.clone()),
+            ("mask", self.mask_filepath.clone()),
+            ("diff", self.diff_filepath.clone()),
+            ("mode", match self.mode {
+                curve_fit_nd::TraceMode::Outline => "OUTLINE",
+                curve_fit_nd::TraceMode::Centerline => "CENTER",
+            }.to_string()),
+            ("turnpolicy", match self.turn_policy_seeded() {
+                TurnPolicy::Black => "BLACK".to_string(),
+                TurnPolicy::White => "WHITE".to_string(),
+                TurnPolicy::Majority => "MAJORITY".to_string(),
+                TurnPolicy::Minority => "MINORITY".to_string(),
+                TurnPolicy::Random(seed) => format!("RANDOM:{}", seed),
+            }),
+            ("edge-policy", format!("{:?}", self.edge_policy).to_uppercase()),
+            ("contours", format!("{:?}", self.contours).to_uppercase()),
+            ("hull", match self.hull {
+                Some(polys_hull::HullMode::Convex) => "CONVEX",
+                Some(polys_hull::HullMode::OrientedBox) => "OBB",
+                Some(polys_hull::HullMode::AlignedBox) => "AABB",
+                None => "",
+            }.to_string()),
+            ("join-dashes", self.join_dashes.to_string()),
+            ("centerline-subpixel", self.centerline_subpixel.to_string()),
+            ("dash-array", self.use_dash_array.to_string()),
+            ("stroke-widths", self.use_stroke_widths.to_string()),
+            ("dots", format!("{:?}", self.dots).to_uppercase()),
+            ("merge-overlaps", self.use_merge_overlaps.to_string()),
+            ("direction", match self.direction {
+                Some(curve_types::StrokeDirection::LeftToRight) => "LTR",
+                Some(curve_types::StrokeDirection::TopToBottom) => "TTB",
+                Some(curve_types::StrokeDirection::Outward) => "OUTWARD",
+                None => "",
+            }.to_string()),
+            ("symmetry", self.symmetry.map(
+                |v| format!("{:?}", v).to_uppercase()).unwrap_or_default()),
+            ("min-feature", self.min_feature.to_string()),
+            ("remove-hatching", self.remove_hatching.to_string()),
+            ("fill-holes", self.fill_holes.to_string()),
+            ("silhouette", self.silhouette.to_string()),
+            ("error", self.error_threshold.to_string()),
+            ("error-relative", self.error_relative.to_string()),
+            ("error-min", self.error_min.to_string()),
+            ("lod", self.lod.iter().map(|v| v.to_string()).collect::<Vec<String>>().join(",")),
+            ("simplify", self.simplify_threshold.to_string()),
+            ("simplify-small", self.simplify_small.map(|v| v.to_string()).unwrap_or_default()),
+            ("simplify-large", self.simplify_large.map(|v| v.to_string()).unwrap_or_default()),
+            ("simplify-cutoff", self.simplify_cutoff.to_string()),
+            ("simplify-post", self.simplify_post.to_string()),
+            // Stored in radians, round to avoid precision loss showing in the degree value.
+            ("corner", ((self.corner_threshold.to_degrees() * 1e9).round() / 1e9).to_string()),
+            ("optimize-exhaustive", self.use_optimize_exhaustive.to_string()),
+            ("exhaustive-prune", self.use_exhaustive_prune.to_string()),
+            ("exhaustive-epsilon", self.exhaustive_epsilon.to_string()),
+            ("exhaustive-steps", self.exhaustive_steps.to_string()),
+            ("multiscale", self.multiscale.to_string()),
+            ("seed", self.seed.to_string()),
+            ("deterministic-fit", self.use_deterministic_fit.to_string()),
+            ("precision-f32", self.use_precision_f32.to_string()),
+            ("end-tangent", format!("{:?}", self.end_tangent).to_uppercase()),
+            ("max-handle-ratio", self.max_handle_ratio.to_string()),
+            ("tangent-smooth", self.tangent_smooth.to_string()),
+            ("solvers", self.solvers.iter().map(
+                |solver| solver_id(*solver)).collect::<Vec<&str>>().join(",")),
+            ("refine-iterations", self.refine_iterations.to_string()),
+            ("refine-epsilon", self.refine_epsilon.to_string()),
+            ("corner-weight", self.corner_weight.to_string()),
+            ("anchors", self.anchors.iter().map(
+                |v| format!("{},{}", v[0], v[1])).collect::<Vec<String>>().join(" ")),
+            ("round-corners", self.round_corners.to_string()),
+            ("split-inflections", self.use_split_inflections.to_string()),
+            ("detect-shapes", self.use_detect_shapes.to_string()),
+            ("resample-adaptive", self.use_resample_adaptive.to_string()),
+            ("resample-output", self.resample_output.to_string()),
+            ("resample-output-curve", self.use_resample_output_curve.to_string()),
+            ("export-measurements", self.measurements_filepath.clone()),
+            ("export-strokes", self.strokes_filepath.clone()),
+            ("preview-png", self.preview_png_filepath.clone()),
+            ("preview-scale", self.preview_scale.to_string()),
+            ("error-map", self.error_map_filepath.clone()),
+            ("report", self.report_filepath.clone()),
+            ("scale", self.output_scale.to_string()),
+            ("fit-size", self.fit_size.map(
+                |v| format!("{}x{}", v[0], v[1])).unwrap_or_default()),
+            ("transform", self.output_transform.to_svg_string()),
+            ("flip-y", self.use_flip_y.to_string()),
+            ("origin", match self.origin {
+                Some(Origin::TopLeft) => "TOPLEFT",
+                Some(Origin::BottomLeft) => "BOTTOMLEFT",
+                Some(Origin::Center) => "CENTER",
+                None => "",
+            }.to_string()),
+            ("snap", self.snap.to_string()),
+            ("snap-handles", self.use_snap_handles.to_string()),
+            ("format", match self.output_format {
+                Some(OutputFormat::Svg) => "SVG",
+                Some(OutputFormat::Glif) => "GLIF",
+                Some(OutputFormat::Pdf) => "PDF",
+                Some(OutputFormat::Json) => "JSON",
+                None => "",
+            }.to_string()),
+            ("upm", self.glif_upm.to_string()),
+            ("baseline", self.glif_baseline.map(|v| v.to_string()).unwrap_or_default()),
+            ("svg-layers", self.use_svg_layers.to_string()),
+            ("path-ids", self.use_path_ids.to_string()),
+            ("template", self.template_filepath.clone()),
+            ("insert-selector", self.template_selector.clone()),
+            ("passes", passes.join(",")),
+            ("pass-scale", self.debug_pass_scale.to_string()),
+            ("refine-log", self.refine_log_filepath.clone()),
+            ("montage", self.montage_columns.to_string()),
+            ("frames", self.frames.map(
+                |m| format!("{:?}", m).to_uppercase()).unwrap_or_default()),
+            ("batch", self.use_batch.to_string()),
+            ("batch-report", self.batch_report_filepath.clone()),
+            ("pdf-multipage", self.use_pdf_multipage.to_string()),
+        ]);
+        return items;
+    }
+}
+
+impl Default for TraceParams {
+    fn default(
+    ) -> TraceParams
+    {
+        TraceParams {
+            error_threshold: 1.0,
+            error_relative: 0.0,
+            error_min: 0.1,
+            lod: vec![],
+            simplify_threshold: 2.5,
+            simplify_small: None,
+            simplify_large: None,
+            simplify_cutoff: 32.0,
+            simplify_post: 0.0,
+            corner_threshold: 30.0_f64.to_radians(),
+            use_optimize_exhaustive: false,
+            use_exhaustive_prune: true,
+            exhaustive_epsilon: curve_fit_nd::EXHAUSTIVE_PRUNE_DEFAULT.epsilon,
+            exhaustive_steps: curve_fit_nd::EXHAUSTIVE_PRUNE_DEFAULT.steps,
+            multiscale: 1,
+            use_deterministic_fit: false,
+            use_precision_f32: false,
+            use_resample_adaptive: false,
+            end_tangent: curve_fit_nd::EndTangent::Natural,
+            max_handle_ratio: 0.0,
+            tangent_smooth: 0.0,
+            solvers: curve_fit_nd::SOLVERS_DEFAULT.to_vec(),
+            refine_iterations: curve_fit_nd::REPARAMETERIZE_DEFAULT.iterations,
+            refine_epsilon: curve_fit_nd::REPARAMETERIZE_DEFAULT.epsilon,
+            corner_weight: 1.0,
+            anchors: vec![],
+            round_corners: 0.0,
+            use_split_inflections: false,
+            use_detect_shapes: false,
+            resample_output: 0,
+            use_resample_output_curve: false,
+            use_merge_overlaps: false,
+            direction: None,
+            symmetry: None,
+            input_filepaths: vec![],
+            input_raw: None,
+            threshold: 0.5,
+            use_fail_empty: false,
+            histogram_filepath: String::new(),
+            mask_filepath: String::new(),
+            diff_filepath: String::new(),
+            output_filepath: String::new(),
+            output_format: None,
+            outputs_extra: vec![],
+            measurements_filepath: "".to_string(),
+            strokes_filepath: "".to_string(),
+            preview_png_filepath: String::new(),
+            preview_scale: 1.0,
+            error_map_filepath: String::new(),
+            report_filepath: String::new(),
+            output_scale: 1.0,
+            fit_size: None,
+            output_transform: curve_types::Transform::IDENTITY,
+            use_flip_y: false,
+            origin: None,
+            snap: 0.0,
+            use_snap_handles: false,
+            montage_columns: 0,
+            frames: None,
+            use_batch: false,
+            batch_report_filepath: String::new(),
+            use_pdf_multipage: false,
+            mode: curve_fit_nd::TraceMode::Outline,
+            turn_policy: polys_from_raster_outline::TurnPolicy::Majority,
+            turn_policy_seed: None,
+            edge_policy: polys_from_raster_outline::EdgePolicy::Close,
+            contours: ContourMode::All,
+            hull: None,
+            join_dashes: 0.0,
+            centerline_subpixel: 0.0,
+            use_dash_array: false,
+            use_stroke_widths: false,
+            dots: DotMode::Render,
+            min_feature: 0,
+            remove_hatching: 0,
+            fill_holes: 0,
+            silhouette: 0,
+            use_svg_layers: false,
+            use_path_ids: false,
+            template_filepath: String::new(),
+            template_selector: String::new(),
+            use_metadata: true,
+            glif_upm: 1000.0,
+            glif_baseline: None,
+            debug_passes: 0,
+            debug_pass_scale: 1.0,
+            refine_log_filepath: "".to_string(),
+            use_stats: false,
+            threads_len: 0,
+            seed: 0,
+            outline_method: polys_from_raster_outline::OutlineMethod::Steps,
+            cache_dirpath: String::new(),
+            use_serve: false,
+            listen_filepath: String::new(),
+
+            use_quiet: false,
+            show_help: false,
+            show_help_group: String::new(),
+            warnings: vec![],
+        }
+    }
+}
+
+/// Return an error for parameters which can't be used together.
+fn trace_params_error(
+    trace_params: &TraceParams,
+) -> Option<&'static str>
+{
+    let is_output_svg = trace_params.output_format.unwrap_or_else(
+        || OutputFormat::from_filepath(&trace_params.output_filepath)) == OutputFormat::Svg;
+    let use_montage = trace_params.montage_columns != 0;
+    let use_diff = !trace_params.diff_filepath.is_empty();
+    let use_template = !trace_params.template_filepath.is_empty();
+    let use_batch = trace_params.use_batch;
+    return if !use_montage && !use_batch && trace_params.input_filepaths.len() != 1 {
+        Some("Multiple inputs require --montage or --batch")
+    } else if use_batch &&
+              (use_montage || trace_params.frames.is_some() || use_diff ||
+               !trace_params.refine_log_filepath.is_empty() ||
+               !trace_params.measurements_filepath.is_empty() ||
+               !trace_params.preview_png_filepath.is_empty())
+    {
+        Some(concat!("--batch can't be used with --montage, --frames, --diff, ",
+                     "--refine-log, --export-measurements or --preview-png"))
+    } else if !use_batch && !trace_params.batch_report_filepath.is_empty() {
+        Some("--batch-report requires --batch")
+    } else if trace_params.use_pdf_multipage && !use_batch {
+        Some("--pdf-multipage requires --batch")
+    } else if trace_params.use_pdf_multipage &&
+              trace_params.output_format_main() != OutputFormat::Pdf
+    {
+        Some("--pdf-multipage requires PDF output")
+    } else if use_montage && !is_output_svg {
+        Some("--montage requires SVG output")
+    } else if use_montage && trace_params.origin.is_some() {
+        Some("--origin can't be used with --montage")
+    } else if use_montage && trace_params.frames.is_some() {
+        Some("--frames can't be used with --montage")
+    } else if trace_params.frames.is_some() && trace_params.input_raw.is_some() {
+        Some("--frames can't be used with --input-raw")
+    } else if trace_params.frames == Some(FrameMode::Layers) && !is_output_svg {
+        Some("--frames LAYERS requires SVG output")
+    } else if trace_params.hull.is_some() &&
+              trace_params.mode != curve_fit_nd::TraceMode::Outline
+    {
+        Some("--hull requires --mode OUTLINE")
+    } else if trace_params.hull.is_some() &&
+              trace_params.edge_policy == polys_from_raster_outline::EdgePolicy::Open
+    {
+        Some("--hull can't be used with --edge-policy OPEN")
+    } else if trace_params.centerline_subpixel != 0.0 &&
+              trace_params.mode != curve_fit_nd::TraceMode::Centerline
+    {
+        Some("--centerline-subpixel requires --mode CENTER")
+    } else if trace_params.centerline_subpixel != 0.0 &&
+              (use_montage || trace_params.frames.is_some() || use_diff || use_batch ||
+               !trace_params.lod.is_empty() ||
+               trace_params.input_filepaths.iter().any(|filepath| filepath == "-"))
+    {
+        Some(concat!("--centerline-subpixel can't be used with --montage, --frames, --diff, ",
+                     "--batch, --lod or input from the standard input"))
+    } else if trace_params.use_stroke_widths &&
+              (use_montage || trace_params.frames.is_some() || use_diff || use_template ||
+               use_batch || !trace_params.lod.is_empty() || !is_output_svg)
+    {
+        // Path effects are referenced by ID, which must be unique within the document.
+        Some(concat!("--stroke-widths can't be used with --montage, --frames, --diff, ",
+                     "--template, --batch, --lod or non-SVG output"))
+    } else if use_diff && (use_montage || trace_params.frames.is_some()) {
+        Some("--diff can't be used with --montage or --frames")
+    } else if use_diff && !is_output_svg {
+        Some("--diff requires SVG output")
+    } else if use_template &&
+              (use_montage || trace_params.frames.is_some() || use_diff || !is_output_svg)
+    {
+        Some("--template can't be used with --montage, --frames, --diff or non-SVG output")
+    } else if trace_params.use_resample_output_curve && trace_params.resample_output == 0 {
+        Some("--resample-output-curve requires --resample-output")
+    } else if !trace_params.lod.is_empty() &&
+              (use_montage || trace_params.frames.is_some() || use_diff || use_batch ||
+               !trace_params.refine_log_filepath.is_empty() ||
+               !trace_params.measurements_filepath.is_empty() ||
+               !trace_params.preview_png_filepath.is_empty())
+    {
+        Some(concat!("--lod can't be used with --montage, --frames, --diff, --batch, ",
+                     "--refine-log, --export-measurements or --preview-png"))
+    } else if !trace_params.lod.is_empty() && trace_params.use_svg_layers && !is_output_svg {
+        Some("--lod with --svg-layers requires SVG output")
+    } else if !trace_params.outputs_extra.is_empty() &&
+              (use_montage || trace_params.frames.is_some() || use_diff || use_batch ||
+               !trace_params.lod.is_empty())
+    {
+        Some("Multiple --output can't be used with --montage, --frames, --diff, --batch or --lod")
+    } else if !trace_params.use_flip_y &&
+              trace_params.outputs_extra.iter().any(|output| {
+                  OutputFormat::from_filepath(&output.filepath).is_y_up() !=
+                      trace_params.output_format_main().is_y_up()
+              })
+    {
+        // All outputs are written from the same curves.
+        Some("Multiple --output using both Y-up (GLIF, PDF) & Y-down formats require --flip-y")
+    } else if trace_params.multiscale > 1 &&
+              (trace_params.hull.is_some() || trace_params.error_relative != 0.0 ||
+               !trace_params.refine_log_filepath.is_empty())
+    {
+        // Spans would use their own size for relative errors.
+        Some("--multiscale can't be used with --hull, --error-relative or --refine-log")
+    } else if !trace_params.cache_dirpath.is_empty() &&
+              (trace_params.debug_passes != 0 ||
+               trace_params.outputs_extra.iter().any(|output| output.debug_passes != 0))
+    {
+        Some("--cache can't be used with --passes")
+    } else if trace_params.use_path_ids &&
+              (use_montage || trace_params.frames == Some(FrameMode::Layers) ||
+               (!trace_params.lod.is_empty() && trace_params.use_svg_layers) || !is_output_svg)
+    {
+        // The same shape in each input, frame or level would write duplicate IDs.
+        Some(concat!("--path-ids can't be used with --montage, --frames LAYERS, ",
+                     "--lod with --svg-layers or non-SVG output"))
+    } else if use_template && trace_params.use_svg_layers {
+        Some("--template can't be used with --svg-layers")
+    } else if !use_template && !trace_params.template_selector.is_empty() {
+        Some("--insert-selector requires --template")
+    } else if !trace_params.refine_log_filepath.is_empty() &&
+              (use_montage || trace_params.frames.is_some() || use_diff)
+    {
+        Some("--refine-log can't be used with --montage, --frames or --diff")
+    } else if !trace_params.measurements_filepath.is_empty() &&
+              (use_montage || trace_params.frames.is_some() || use_diff)
+    {
+        Some("--export-measurements can't be used with --montage, --frames or --diff")
+    } else if !trace_params.strokes_filepath.is_empty() &&
+              trace_params.mode != curve_fit_nd::TraceMode::Centerline
+    {
+        Some("--export-strokes requires --mode CENTER")
+    } else if !trace_params.strokes_filepath.is_empty() &&
+              (use_montage || trace_params.frames.is_some() || use_diff || use_batch ||
+               !trace_params.lod.is_empty())
+    {
+        Some("--export-strokes can't be used with --montage, --frames, --diff, --batch or --lod")
+    } else if trace_params.use_stroke_widths_any() &&
+              trace_params.input_filepaths.iter().any(|filepath| filepath == "-")
+    {
+        // The image is read again (without skeletonizing).
+        Some("--stroke-widths & --export-strokes can't be used with input from the standard input")
+    } else if !trace_params.preview_png_filepath.is_empty() &&
+              (use_montage || trace_params.frames.is_some() || use_diff)
+    {
+        Some("--preview-png can't be used with --montage, --frames or --diff")
+    } else if !trace_params.histogram_filepath.is_empty() &&
+              (use_montage || trace_params.frames.is_some() || use_diff || use_batch)
+    {
+        Some("--histogram can't be used with --montage, --frames, --diff or --batch")
+    } else if !trace_params.report_filepath.is_empty() &&
+              (use_montage || trace_params.frames.is_some() || use_diff || use_batch ||
+               !trace_params.lod.is_empty())
+    {
+        Some("--report can't be used with --montage, --frames, --diff, --batch or --lod")
+    } else if !trace_params.error_map_filepath.is_empty() &&
+              (use_montage || trace_params.frames.is_some() || use_diff || use_batch ||
+               !trace_params.lod.is_empty())
+    {
+        Some("--error-map can't be used with --montage, --frames, --diff, --batch or --lod")
+    } else {
+        None
+    };
+}
+
+/// WASM has no command line, see: `trace_bytes`.
+#[cfg(target_arch = "wasm32")]
+fn main() {}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main()
+{
+    use intern::argparse;
+    let mut trace_params = TraceParams::default();
+
+    // -----------------------------------------------------------------------
+    // Parse Args
+    //
+    // A closure since commands read with `--serve` are parsed the same way.
+    let args_parse = |trace_params: &mut TraceParams, args: &[String]| -> Result<(), String> {
+        use std::str::FromStr;
+
+        let mut parser = argparse::new(
+            trace_params,
+            "Bitmap image tracing utility",
+            );
+
+        // File Options
+        {
+            let parser_group = Some(parser.add_argument_group(
+                "File Options",
+                ""
+            ));
+            parser.add_argument(
+                "-i", "--input",
+                concat!("The file path to use for input ",
+                        "(may be passed multiple times with --montage, '-' reads from stdin with ",
+                        "--input-raw)"),
+                "FILEPATH",
+                Box::new(|dest_data, my_args| {
+                    dest_data.input_filepaths.push(my_args[0].clone());
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT | argparse::ARGDEF_REQUIRED,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--input-raw",
+                concat!("Read input as headerless grayscale data (as written by ImageMagick's ",
+                        "'gray:' format), with the size & optional bit depth (8 or 16), ",
+                        "(defaults to 8)."),
+                "WIDTHxHEIGHT[:DEPTH]",
+                Box::new(|dest_data, my_args| {
+                    dest_data.input_raw = Some(
+                        ::intern::image_load::RawFormat::from_str(&my_args[0])?);
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--threshold",
+                concat!("Pixels darker than this are filled, ",
+                        "from black to white [0.0 - 1.0] (defaults to 0.5)."),
+                "THRESHOLD",
+                Box::new(|dest_data, my_args| {
+                    match f64::from_str(&my_args[0]) {
+                        Ok(v) if v >= 0.0 && v <= 1.0 => {
+                            dest_data.threshold = v;
+                            return Ok(1);
+                        },
+                        _ => {
+                            return Err(format!(
+                                "Expected a number in [0.0 - 1.0], not '{}'", my_args[0]));
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--fail-empty",
+                concat!("When passed, exit with status 3 when the inputs have no filled pixels ",
+                        "(after the threshold & filters), the empty output is still written, ",
+                        "with --batch such inputs fail."),
+                "",
+                Box::new(|dest_data, _my_args| {
+                    dest_data.use_fail_empty = true;
+                    return Ok(0);
+                }),
+                0, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--histogram",
+                concat!("Write the intensity histogram of the input as an SVG bar chart ",
+                        "with --threshold marked, to help choose a threshold, ",
+                        "'-' prints it instead (single input only)."),
+                "FILEPATH",
+                Box::new(|dest_data, my_args| {
+                    dest_data.histogram_filepath = my_args[0].clone();
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--mask",
+                concat!("Only trace pixels which are filled (dark) in this image, ",
+                        "which must be the same size as the input."),
+                "FILEPATH",
+                Box::new(|dest_data, my_args| {
+                    dest_data.mask_filepath = my_args[0].clone();
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--diff",
+                concat!("Trace the areas which differ from this image ",
+                        "(the same size as the input), ",
+                        "areas only filled in this image are written to the 'Added' layer, ",
+                        "areas only filled in the input to the 'Removed' layer."),
+                "FILEPATH",
+                Box::new(|dest_data, my_args| {
+                    dest_data.diff_filepath = my_args[0].clone();
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "-o", "--output",
+                concat!("The file path to use for writing, ",
+                        "may be passed multiple times to write several outputs from one trace, ",
+                        "an optional ':' suffix selects debug passes for this output ",
+                        "(see --passes, which only applies to the first output), ",
+                        "e.g. 'debug.svg:PIXEL,TANGENT'. ",
+                        "Additional outputs detect their format from the file extension."),
+                "FILEPATH[:PASSES]",
+                Box::new(|dest_data, my_args| {
+                    let output = OutputExtra::from_arg(&my_args[0]);
+                    if dest_data.output_filepath.is_empty() {
+                        dest_data.output_filepath = output.filepath;
+                        dest_data.debug_passes |= output.debug_passes;
+                    } else {
+                        dest_data.outputs_extra.push(output);
+                    }
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT | argparse::ARGDEF_REQUIRED,
+                parser_group,
+            );
+            parser.add_argument(
+                "-f", "--format",
+                concat!("The output file format [SVG, GLIF, PDF, JSON], ",
+                        "(defaults to the output file extension, falling back to SVG)."),
+                "FORMAT",
+                Box::new(|dest_data, my_args| {
+                    match my_args[0].as_ref() {
+                        "SVG" => {
+                            dest_data.output_format = Some(OutputFormat::Svg);
+                        }
+                        "GLIF" => {
+                            dest_data.output_format = Some(OutputFormat::Glif);
+                        }
+                        "PDF" => {
+                            dest_data.output_format = Some(OutputFormat::Pdf);
+                        }
+                        "JSON" => {
+                            dest_data.output_format = Some(OutputFormat::Json);
+                        }
+                        _ => {
+                            return Err(format!(
+                                "Expected [SVG, GLIF, PDF, JSON], not '{}'",
+                                my_args[0],
+                            ));
+                        }
+                    }
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--export-measurements",
+                concat!("Write the area, perimeter, centroid & bounds of each region as CSV, ",
+                        "areas are written in pixels as well as scaled units ",
+                        "(single input only)."),
+                "FILEPATH",
+                Box::new(|dest_data, my_args| {
+                    dest_data.measurements_filepath = my_args[0].clone();
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--export-strokes",
+                concat!("Write center-lines with the stroke width at each knot as JSON, ",
+                        "for drawing applications (CENTER mode, single input only)."),
+                "FILEPATH",
+                Box::new(|dest_data, my_args| {
+                    dest_data.strokes_filepath = my_args[0].clone();
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--preview-png",
+                concat!("Write an anti-aliased raster of the output as a PNG ",
+                        "(black on a transparent background), ",
+                        "for checking results without an SVG renderer (single input only)."),
+                "FILEPATH",
+                Box::new(|dest_data, my_args| {
+                    dest_data.preview_png_filepath = my_args[0].clone();
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--preview-scale",
+                "The size of '--preview-png' relative to the input image (defaults to 1.0).",
+                "SCALE",
+                Box::new(|dest_data, my_args| {
+                    match f64::from_str(&my_args[0]) {
+                        Ok(v) if v > 0.0 => {
+                            dest_data.preview_scale = v;
+                            return Ok(1);
+                        },
+                        Ok(v) => {
+                            return Err(format!("Expected a positive number, not '{}'", v));
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--error-map",
+                concat!("Write a grayscale PGM image of the fit error along the boundary, ",
+                        "from black (no error) to white (the error threshold or more), ",
+                        "showing where the error is spent (single input only)."),
+                "FILEPATH",
+                Box::new(|dest_data, my_args| {
+                    dest_data.error_map_filepath = my_args[0].clone();
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--report",
+                concat!("Write a single HTML file with the input, the output, parameters, ",
+                        "the time taken by each stage & a histogram of the fit error, ",
+                        "for sharing & checking results (single input only)."),
+                "FILEPATH",
+                Box::new(|dest_data, my_args| {
+                    dest_data.report_filepath = my_args[0].clone();
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+        }
+
+        // Tracing Methods
+        {
+            let parser_group = Some(parser.add_argument_group(
+                "Tracing Behavior",
+                ""
+            ));
+            parser.add_argument(
+                "-m", "--mode",
+                concat!("The method used for tracing the image in [OUTLINE, CENTER], ",
+                        "(defaults to OUTLINE)."),
+                "MODE",
+                Box::new(|dest_data, my_args| {
+                    match my_args[0].as_ref() {
+                        "OUTLINE" => {
+                            dest_data.mode = curve_fit_nd::TraceMode::Outline;
+                        },
+                        "CENTER" => {
+                            dest_data.mode = curve_fit_nd::TraceMode::Centerline;
+                        },
+                        _ => {
+                            return Err(format!(
+                                "Expected [OUTLINE, CENTER], not '{}'",
+                                my_args[0],
+                            ));
+                        }
+                    }
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "-z", "--turnpolicy",
+                concat!("Method for extracting outlines ",
+                        "[BLACK, WHITE, MAJORITY, MINORITY, RANDOM], ",
+                        "RANDOM is seeded from --seed unless passed a seed (RANDOM:SEED), ",
+                        "(defaults to MAJORITY)."),
+                "POLICY",
+                Box::new(|dest_data, my_args| {
+                    match my_args[0].as_ref() {
+                        "BLACK" => {
+                            dest_data.turn_policy =
+                                polys_from_raster_outline::TurnPolicy::Black;
+                        }
+                        "WHITE" => {
+                            dest_data.turn_policy =
+                                polys_from_raster_outline::TurnPolicy::White;
+                        }
+                        "MAJORITY" => {
+                            dest_data.turn_policy =
+                                polys_from_raster_outline::TurnPolicy::Majority;
+                        }
+                        "MINORITY" => {
+                            dest_data.turn_policy =
+                                polys_from_raster_outline::TurnPolicy::Minority;
+                        }
+                        "RANDOM" => {
+                            dest_data.turn_policy =
+                                polys_from_raster_outline::TurnPolicy::Random(0);
+                            dest_data.turn_policy_seed = None;
+                        }
+                        arg if arg.starts_with("RANDOM:") => {
+                            let seed = &arg["RANDOM:".len()..];
+                            match seed.parse::<u32>() {
+                                Ok(seed) => {
+                                    dest_data.turn_policy =
+                                        polys_from_raster_outline::TurnPolicy::Random(seed);
+                                    dest_data.turn_policy_seed = Some(seed);
+                                }
+                                Err(_) => {
+                                    return Err(format!(
+                                        "Expected an unsigned integer seed, not '{}'",
+                                        seed,
+                                    ));
+                                }
+                            }
+                        }
+                        _ => {
+                            return Err(format!(
+                                "Expected [BLACK, WHITE, MAJORITY, MINORITY, RANDOM], not '{}'",
+                                my_args[0],
+                            ));
+                        }
+                    }
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--edge-policy",
+                concat!("Method for handling outlines touching the image border ",
+                        "[CLOSE, OPEN, EXTEND], OPEN removes segments along the border ",
+                        "(writing open paths), EXTEND moves them outside the image ",
+                        "so corners aren't rounded, (defaults to CLOSE)."),
+                "POLICY",
+                Box::new(|dest_data, my_args| {
+                    use polys_from_raster_outline::EdgePolicy;
+                    match my_args[0].as_ref() {
+                        "CLOSE" => {
+                            dest_data.edge_policy = EdgePolicy::Close;
+                        }
+                        "OPEN" => {
+                            dest_data.edge_policy = EdgePolicy::Open;
+                        }
+                        "EXTEND" => {
+                            dest_data.edge_policy = EdgePolicy::Extend;
+                        }
+                        _ => {
+                            return Err(format!(
+                                "Expected [CLOSE, OPEN, EXTEND], not '{}'",
+                                my_args[0],
+                            ));
+                        }
+                    }
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--contours",
+                concat!("Outlines to trace [ALL, OUTER, HOLES], OUTER ignores holes (tracing ",
+                        "silhouettes), HOLES only traces holes, (defaults to ALL)."),
+                "MODE",
+                Box::new(|dest_data, my_args| {
+                    match my_args[0].as_ref() {
+                        "ALL" => {
+                            dest_data.contours = ContourMode::All;
+                        }
+                        "OUTER" => {
+                            dest_data.contours = ContourMode::Outer;
+                        }
+                        "HOLES" => {
+                            dest_data.contours = ContourMode::Holes;
+                        }
+                        _ => {
+                            return Err(format!(
+                                "Expected [ALL, OUTER, HOLES], not '{}'",
+                                my_args[0],
+                            ));
+                        }
+                    }
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--hull",
+                concat!("Write a bounding shape for each region instead of its outline ",
+                        "[CONVEX, OBB, AABB], CONVEX writes the convex hull, ",
+                        "OBB the smallest (rotated) bounding rectangle, ",
+                        "AABB the bounding rectangle aligned to the image, ",
+                        "holes are ignored (OUTLINE mode only)."),
+                "MODE",
+                Box::new(|dest_data, my_args| {
+                    use polys_hull::HullMode;
+                    match my_args[0].as_ref() {
+                        "CONVEX" => {
+                            dest_data.hull = Some(HullMode::Convex);
+                        }
+                        "OBB" => {
+                            dest_data.hull = Some(HullMode::OrientedBox);
+                        }
+                        "AABB" => {
+                            dest_data.hull = Some(HullMode::AlignedBox);
+                        }
+                        _ => {
+                            return Err(format!(
+                                "Expected [CONVEX, OBB, AABB], not '{}'",
+                                my_args[0],
+                            ));
+                        }
+                    }
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--join-dashes",
+                concat!("Join straight center-lines which continue in the same direction, ",
+                        "separated by gaps up to this length, so dashed lines are traced ",
+                        "as a single path (CENTER mode only), (defaults to 0, disabled)."),
+                "GAP",
+                Box::new(|dest_data, my_args| {
+                    match f64::from_str(&my_args[0]) {
+                        Ok(v) if v >= 0.0 => {
+                            dest_data.join_dashes = v;
+                            return Ok(1);
+                        },
+                        Ok(v) => {
+                            return Err(format!("Expected a positive number, not '{}'", v));
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--centerline-subpixel",
+                concat!("Move center-lines to the middle of strokes in the grayscale image ",
+                        "(before the threshold), for smoother center-lines from anti-aliased ",
+                        "images, measuring cross-sections of strokes up to this distance ",
+                        "(CENTER mode only), (defaults to 0, disabled)."),
+                "RADIUS",
+                Box::new(|dest_data, my_args| {
+                    match f64::from_str(&my_args[0]) {
+                        Ok(v) if v >= 0.0 => {
+                            dest_data.centerline_subpixel = v;
+                            return Ok(1);
+                        },
+                        Ok(v) => {
+                            return Err(format!("Expected a positive number, not '{}'", v));
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--dash-array",
+                concat!("When passed, write the average dash & gap length of joined dashes ",
+                        "as the stroke-dasharray of the path (requires --join-dashes)."),
+                "",
+                Box::new(|dest_data, _my_args| {
+                    dest_data.use_dash_array = true;
+                    return Ok(0);
+                }),
+                0, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--stroke-widths",
+                concat!("When passed, measure the width of strokes at each knot, ",
+                        "written as Inkscape power-stroke path effects so variable widths ",
+                        "are kept, other applications show the filled outline ",
+                        "(CENTER mode & SVG output only)."),
+                "",
+                Box::new(|dest_data, _my_args| {
+                    dest_data.use_stroke_widths = true;
+                    return Ok(0);
+                }),
+                0, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--dots",
+                concat!("Method for handling isolated pixels [RENDER, CIRCLE, DROP], ",
+                        "CIRCLE writes circles at pixel centers, ",
+                        "(defaults to RENDER)."),
+                "MODE",
+                Box::new(|dest_data, my_args| {
+                    match my_args[0].as_ref() {
+                        "RENDER" => {
+                            dest_data.dots = DotMode::Render;
+                        }
+                        "CIRCLE" => {
+                            dest_data.dots = DotMode::Circle;
+                        }
+                        "DROP" => {
+                            dest_data.dots = DotMode::Drop;
+                        }
+                        _ => {
+                            return Err(format!(
+                                "Expected [RENDER, CIRCLE, DROP], not '{}'",
+                                my_args[0],
+                            ));
+                        }
+                    }
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--merge-overlaps",
+                concat!("When passed, merge overlapping regions into a single outline ",
+                        "(OUTLINE mode only)."),
+                "",
+                Box::new(|dest_data, _my_args| {
+                    dest_data.use_merge_overlaps = true;
+                    return Ok(0);
+                }),
+                0, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--direction",
+                concat!("Orient open strokes (CENTER mode) in [LTR, TTB, OUTWARD], ",
+                        "left-to-right, top-to-bottom, or starting nearest the image center, ",
+                        "(defaults to the direction strokes are traced)."),
+                "DIRECTION",
+                Box::new(|dest_data, my_args| {
+                    use curve_types::StrokeDirection;
+                    match my_args[0].as_ref() {
+                        "LTR" => {
+                            dest_data.direction = Some(StrokeDirection::LeftToRight);
+                        }
+                        "TTB" => {
+                            dest_data.direction = Some(StrokeDirection::TopToBottom);
+                        }
+                        "OUTWARD" => {
+                            dest_data.direction = Some(StrokeDirection::Outward);
+                        }
+                        _ => {
+                            return Err(format!(
+                                "Expected [LTR, TTB, OUTWARD], not '{}'",
+                                my_args[0],
+                            ));
+                        }
+                    }
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--symmetry",
+                concat!("Make the result symmetrical along a mirror axis detected from the ",
+                        "image in [X, Y, AUTO], where X matches the left & right halves, ",
+                        "AUTO uses the best matching axis ",
+                        "(when the image is close to symmetrical)."),
+                "AXIS",
+                Box::new(|dest_data, my_args| {
+                    match my_args[0].as_ref() {
+                        "X" => {
+                            dest_data.symmetry = Some(image_symmetry::Symmetry::X);
+                        }
+                        "Y" => {
+                            dest_data.symmetry = Some(image_symmetry::Symmetry::Y);
+                        }
+                        "AUTO" => {
+                            dest_data.symmetry = Some(image_symmetry::Symmetry::Auto);
+                        }
+                        _ => {
+                            return Err(format!(
+                                "Expected [X, Y, AUTO], not '{}'",
+                                my_args[0],
+                            ));
+                        }
+                    }
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+        }
+
+        // Image Filters
+        {
+            let parser_group = Some(parser.add_argument_group(
+                "Image Filters",
+                "Filters applied to the image before tracing."
+            ));
+            parser.add_argument(
+                "", "--min-feature",
+                "Remove filled features narrower than this size (defaults to 0, disabled).",
+                "PIXELS",
+                Box::new(|dest_data, my_args| {
+                    match usize::from_str(&my_args[0]) {
+                        Ok(v) => {
+                            dest_data.min_feature = v;
+                            return Ok(1);
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--remove-hatching",
+                concat!("Remove hatching (dense parallel strokes up to this width) ",
+                        "so hatched regions are traced by their outlines ",
+                        "(defaults to 0, disabled)."),
+                "PIXELS",
+                Box::new(|dest_data, my_args| {
+                    match usize::from_str(&my_args[0]) {
+                        Ok(v) => {
+                            dest_data.remove_hatching = v;
+                            return Ok(1);
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--fill-holes",
+                "Fill empty features (holes) narrower than this size (defaults to 0, disabled).",
+                "PIXELS",
+                Box::new(|dest_data, my_args| {
+                    match usize::from_str(&my_args[0]) {
+                        Ok(v) => {
+                            dest_data.fill_holes = v;
+                            return Ok(1);
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--silhouette",
+                concat!("Trace a silhouette around all regions (for cut-lines & stickers), ",
+                        "joining regions closer than this size, filling all holes ",
+                        "& merging overlapping outlines (defaults to 0, disabled)."),
+                "PIXELS",
+                Box::new(|dest_data, my_args| {
+                    match usize::from_str(&my_args[0]) {
+                        Ok(v) => {
+                            dest_data.silhouette = v;
+                            return Ok(1);
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+        }
+
+        // Curve Evaluation
+        {
+            let parser_group = Some(parser.add_argument_group(
+                "Curve Evaluation Options",
+                "Parameters controlling curve evaluation behavior."
+            ));
+            parser.add_argument(
+                "-e", "--error",
+                "The error threshold (defaults to 1.0)",
+                "PIXELS",
+                Box::new(|dest_data, my_args| {
+                    match f64::from_str(&my_args[0]) {
+                        Ok(v) => {
+                            dest_data.error_threshold = v;
+                            return Ok(1);
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--error-relative",
+                concat!("Scale the error threshold by the size of each region ",
+                        "(multiplied by the bounding-box diagonal), ",
+                        "using --error as the maximum (defaults to 0, disabled)."),
+                "FACTOR",
+                Box::new(|dest_data, my_args| {
+                    match f64::from_str(&my_args[0]) {
+                        Ok(v) => {
+                            dest_data.error_relative = v;
+                            return Ok(1);
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--error-min",
+                "The minimum error threshold when using --error-relative (defaults to 0.1).",
+                "PIXELS",
+                Box::new(|dest_data, my_args| {
+                    match f64::from_str(&my_args[0]) {
+                        Ok(v) => {
+                            dest_data.error_min = v;
+                            return Ok(1);
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--lod",
+                concat!("Fit at each of these comma separated error thresholds ",
+                        "(levels of detail), sharing extraction & simplification, ",
+                        "each level is written as a layer with --svg-layers, ",
+                        "otherwise to its own file (with a '_lod#' suffix), e.g. '0.5,2,8'."),
+                "ERRORS",
+                Box::new(|dest_data, my_args| {
+                    let mut lod = vec![];
+                    for text in my_args[0].split(",") {
+                        match f64::from_str(text) {
+                            Ok(v) if v > 0.0 => {
+                                lod.push(v);
+                            },
+                            Ok(v) => {
+                                return Err(format!("Expected a positive number, not '{}'", v));
+                            },
+                            Err(e) => {
+                                return Err(format!("{} in '{}'", e, text));
+                            },
+                        }
+                    }
+                    dest_data.lod = lod;
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "-t", "--simplify",
+                "Simplify polygon before fitting (defaults to 2.0)",
+                "PIXELS",
+                Box::new(|dest_data, my_args| {
+                    match f64::from_str(&my_args[0]) {
+                        Ok(v) => {
+                            dest_data.simplify_threshold = v;
+                            return Ok(1);
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--simplify-small",
+                concat!("Simplify threshold for regions smaller than --simplify-cutoff, ",
+                        "(defaults to --simplify)."),
+                "PIXELS",
+                Box::new(|dest_data, my_args| {
+                    match f64::from_str(&my_args[0]) {
+                        Ok(v) => {
+                            dest_data.simplify_small = Some(v);
+                            return Ok(1);
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--simplify-large",
+                concat!("Simplify threshold for regions no smaller than --simplify-cutoff, ",
+                        "(defaults to --simplify)."),
+                "PIXELS",
+                Box::new(|dest_data, my_args| {
+                    match f64::from_str(&my_args[0]) {
+                        Ok(v) => {
+                            dest_data.simplify_large = Some(v);
+                            return Ok(1);
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--simplify-cutoff",
+                concat!("The bounding-box diagonal separating small from large regions, ",
+                        "(defaults to 32)."),
+                "PIXELS",
+                Box::new(|dest_data, my_args| {
+                    match f64::from_str(&my_args[0]) {
+                        Ok(v) => {
+                            dest_data.simplify_cutoff = v;
+                            return Ok(1);
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--simplify-post",
+                concat!("Remove knots after fitting when the curve deviates less than this, ",
+                        "useful for long gentle curves (defaults to 0, disabled)."),
+                "PIXELS",
+                Box::new(|dest_data, my_args| {
+                    match f64::from_str(&my_args[0]) {
+                        Ok(v) => {
+                            dest_data.simplify_post = v;
+                            return Ok(1);
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+
+
+            parser.add_argument(
+                "-c", "--corner",
+                "The corner threshold (`pi` or greater to disable, defaults to 30.0)",
+                "DEGREES",
+                Box::new(|dest_data, my_args| {
+                    match f64::from_str(&my_args[0]) {
+                        Ok(v) => {
+                            dest_data.corner_threshold = v.to_radians();
+                            return Ok(1);
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--optimize-exhaustive",
+                "When passed, perform exhaustive curve fitting (can be slow!)",
+                "",
+                Box::new(|dest_data, _my_args| {
+                    dest_data.use_optimize_exhaustive = true;
+                    return Ok(0);
+                }),
+                0, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--exhaustive-epsilon",
+                concat!("Stop exhaustive fitting for each knot once candidates improve the error ",
+                        "by less than this, testing candidates nearest the greatest error first ",
+                        "(defaults to 0.01)."),
+                "EPSILON",
+                Box::new(|dest_data, my_args| {
+                    match f64::from_str(&my_args[0]) {
+                        Ok(v) if v >= 0.0 => {
+                            dest_data.exhaustive_epsilon = v;
+                            return Ok(1);
+                        },
+                        Ok(v) => {
+                            return Err(format!("Expected a positive number, not '{}'", v));
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--exhaustive-steps",
+                concat!("The number of consecutive candidates below --exhaustive-epsilon ",
+                        "before exhaustive fitting stops (defaults to 16)."),
+                "NUMBER",
+                Box::new(|dest_data, my_args| {
+                    match usize::from_str(&my_args[0]) {
+                        Ok(v) => {
+                            if v == 0 {
+                                return Err("Expected a number of 1 or more".to_string());
+                            }
+                            dest_data.exhaustive_steps = v;
+                            return Ok(1);
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--no-exhaustive-prune",
+                "When passed, exhaustive fitting tests every candidate (slowest).",
+                "",
+                Box::new(|dest_data, _my_args| {
+                    dest_data.use_exhaustive_prune = false;
+                    return Ok(0);
+                }),
+                0, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--multiscale",
+                concat!("Fit curves to the image downscaled by this factor first, ",
+                        "then fit the outlines in spans between the knots of the coarse curves, ",
+                        "faster for large images with smooth regions (defaults to 1, disabled)."),
+                "FACTOR",
+                Box::new(|dest_data, my_args| {
+                    match usize::from_str(&my_args[0]) {
+                        Ok(v) => {
+                            if v == 0 {
+                                return Err("Expected a factor of 1 or more".to_string());
+                            }
+                            dest_data.multiscale = v;
+                            return Ok(1);
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--deterministic-fit",
+                concat!("When passed, knots & edges which are equally suitable for removal ",
+                        "are removed in order of their index, so the output doesn't depend on ",
+                        "the order they're processed (for reproducible builds)."),
+                "",
+                Box::new(|dest_data, _my_args| {
+                    dest_data.use_deterministic_fit = true;
+                    return Ok(0);
+                }),
+                0, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--precision-f32",
+                concat!("When passed, point lengths & tangents are stored with single precision ",
+                        "while fitting, using less memory for very large traces, ",
+                        "curves exceeding the error threshold are fit again ",
+                        "with double precision."),
+                "",
+                Box::new(|dest_data, _my_args| {
+                    dest_data.use_precision_f32 = true;
+                    return Ok(0);
+                }),
+                0, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--end-tangent",
+                concat!("Method for calculating tangents at the ends of open paths ",
+                        "[NATURAL, CLAMPED, ZERO], ",
+                        "CLAMPED uses multiple points to prevent ends from flaring, ",
+                        "ZERO uses zero length end handles, ",
+                        "(defaults to NATURAL)."),
+                "METHOD",
+                Box::new(|dest_data, my_args| {
+                    match my_args[0].as_ref() {
+                        "NATURAL" => {
+                            dest_data.end_tangent = curve_fit_nd::EndTangent::Natural;
+                        }
+                        "CLAMPED" => {
+                            dest_data.end_tangent = curve_fit_nd::EndTangent::Clamped;
+                        }
+                        "ZERO" => {
+                            dest_data.end_tangent = curve_fit_nd::EndTangent::Zero;
+                        }
+                        _ => {
+                            return Err(format!(
+                                "Expected [NATURAL, CLAMPED, ZERO], not '{}'",
+                                my_args[0],
+                            ));
+                        }
+                    }
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--max-handle-ratio",
+                concat!("Limit the length of each handle to this factor of the distance ",
+                        "between its knots while fitting, preventing spikes on noisy input ",
+                        "(defaults to 0, disabled)."),
+                "RATIO",
+                Box::new(|dest_data, my_args| {
+                    match f64::from_str(&my_args[0]) {
+                        Ok(v) if v >= 0.0 => {
+                            dest_data.max_handle_ratio = v;
+                            return Ok(1);
+                        },
+                        Ok(v) => {
+                            return Err(format!("Expected a positive number, not '{}'", v));
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--tangent-smooth",
+                concat!("Average the tangents of points along each path within this length ",
+                        "before fitting (stopping at corners), reducing wobble from ",
+                        "stair-steps & noise, useful for center-lines ",
+                        "(defaults to 0, disabled)."),
+                "LENGTH",
+                Box::new(|dest_data, my_args| {
+                    match f64::from_str(&my_args[0]) {
+                        Ok(v) if v >= 0.0 => {
+                            dest_data.tangent_smooth = v;
+                            return Ok(1);
+                        },
+                        Ok(v) => {
+                            return Err(format!("Expected a positive number, not '{}'", v));
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--solvers",
+                concat!("Methods used to calculate handles when fitting, comma separated list ",
+                        "of [LS, CIRCLE, OFFSET] tried in order, the result with the lowest ",
+                        "error is used (a fallback is always calculated), ",
+                        "(defaults to CIRCLE,OFFSET,LS)."),
+                "SOLVERS",
+                Box::new(|dest_data, my_args| {
+                    use curve_fit_nd::Solver;
+                    dest_data.solvers.clear();
+                    for solver_string in my_args[0].split(",") {
+                        let solver = match solver_string.as_ref() {
+                            "LS" => Solver::LeastSquare,
+                            "CIRCLE" => Solver::Circle,
+                            "OFFSET" => Solver::Offset,
+                            _ => {
+                                return Err(format!(
+                                    "Expected [LS, CIRCLE, OFFSET], not '{}'", solver_string));
+                            }
+                        };
+                        if dest_data.solvers.contains(&solver) {
+                            return Err(format!(
+                                "Expected each solver once, not '{}'", my_args[0]));
+                        }
+                        dest_data.solvers.push(solver);
+                    }
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--refine-iterations",
+                concat!("The maximum number of iterations refining the least squares solution, ",
+                        "more iterations may fit closer at the cost of speed, ",
+                        "(defaults to 4)."),
+                "NUMBER",
+                Box::new(|dest_data, my_args| {
+                    match usize::from_str(&my_args[0]) {
+                        Ok(v) => {
+                            dest_data.refine_iterations = v;
+                            return Ok(1);
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--refine-epsilon",
+                concat!("Stop refining the least squares solution once the error improves ",
+                        "by less than this (or gets worse), ",
+                        "(defaults to 0, always use all iterations)."),
+                "EPSILON",
+                Box::new(|dest_data, my_args| {
+                    match f64::from_str(&my_args[0]) {
+                        Ok(v) if v >= 0.0 => {
+                            dest_data.refine_epsilon = v;
+                            return Ok(1);
+                        },
+                        Ok(v) => {
+                            return Err(format!("Expected a positive number, not '{}'", v));
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--corner-weight",
+                concat!("Weight of points at corners (turning by over half of '--corner') ",
+                        "when fitting, ",
+                        "higher values reduce rounding of corners ",
+                        "without lowering the '--error' for the whole curve ",
+                        "(defaults to 1, disabled)."),
+                "WEIGHT",
+                Box::new(|dest_data, my_args| {
+                    match f64::from_str(&my_args[0]) {
+                        Ok(v) if v > 0.0 => {
+                            dest_data.corner_weight = v;
+                            return Ok(1);
+                        },
+                        Ok(v) => {
+                            return Err(format!("Expected a positive number, not '{}'", v));
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--anchors",
+                concat!("A file of pixel coordinates which are always kept as knots ",
+                        "(snapped to the nearest point of the traced polygons), ",
+                        "an 'X Y' pair on each line, ",
+                        "for registration marks or connection points."),
+                "FILE",
+                Box::new(|dest_data, my_args| {
+                    let text = ::std::fs::read_to_string(&my_args[0]).map_err(
+                        |e| format!("Error reading '{}': {}", my_args[0], e))?;
+                    dest_data.anchors = anchors_from_str(&text)?;
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--round-corners",
+                concat!("Replace corners with arcs of this radius after fitting ",
+                        "(reduced where segments are too short), ",
+                        "(defaults to 0, disabled)."),
+                "PIXELS",
+                Box::new(|dest_data, my_args| {
+                    match f64::from_str(&my_args[0]) {
+                        Ok(v) if v >= 0.0 => {
+                            dest_data.round_corners = v;
+                            return Ok(1);
+                        },
+                        Ok(v) => {
+                            return Err(format!("Expected a positive number, not '{}'", v));
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--split-inflections",
+                concat!("When passed, split segments at inflection points after fitting, ",
+                        "so the curvature of each segment bends in one direction."),
+                "",
+                Box::new(|dest_data, _my_args| {
+                    dest_data.use_split_inflections = true;
+                    return Ok(0);
+                }),
+                0, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--detect-shapes",
+                concat!("When passed, paths matching a circle, ellipse, rectangle or line ",
+                        "(within the error threshold) are written as SVG elements of that shape."),
+                "",
+                Box::new(|dest_data, _my_args| {
+                    dest_data.use_detect_shapes = true;
+                    return Ok(0);
+                }),
+                0, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--resample-adaptive",
+                concat!("When passed, use more points around sharp turns and fewer on ",
+                        "straight runs when resampling before fitting."),
+                "",
+                Box::new(|dest_data, _my_args| {
+                    dest_data.use_resample_adaptive = true;
+                    return Ok(0);
+                }),
+                0, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--resample-output",
+                concat!("Resample each path after fitting to this many points, ",
+                        "spaced uniformly by arc length, joined by lines ",
+                        "(for animation paths or laser galvanometers), ",
+                        "(defaults to 0, disabled)."),
+                "NUMBER",
+                Box::new(|dest_data, my_args| {
+                    match usize::from_str(&my_args[0]) {
+                        Ok(v) if v >= 2 => {
+                            dest_data.resample_output = v;
+                            return Ok(1);
+                        },
+                        Ok(v) => {
+                            return Err(format!("Expected at least 2, not '{}'", v));
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--resample-output-curve",
+                concat!("When passed, join the points of '--resample-output' with a smooth curve ",
+                        "passing through them, instead of lines."),
+                "",
+                Box::new(|dest_data, _my_args| {
+                    dest_data.use_resample_output_curve = true;
+                    return Ok(0);
+                }),
+                0, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+        }
+
+        // Output Options
         {
             let parser_group = Some(parser.add_argument_group(
-                "File Options",
-                ""
+                "Output Options",
+                "Generic options for output (format agnostic)."
             ));
             parser.add_argument(
-                "-i", "--input",
-                "The file path to use for input",
-                "FILEPATH",
+                "-s", "--scale",
+                "Scale for output, (defaults to 1).",
+                "SCALE",
+                Box::new(|dest_data, my_args| {
+                    match f64::from_str(&my_args[0]) {
+                        Ok(v) => {
+                            dest_data.output_scale = v;
+                            return Ok(1);
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--fit-size",
+                concat!("Calculate the scale so the output fits within this size, ",
+                        "preserving the aspect ratio (overrides --scale), ",
+                        "units in [mm, in, px] at 96 pixels per inch, ",
+                        "(defaults to px), e.g. 210x297mm."),
+                "WxH",
                 Box::new(|dest_data, my_args| {
-                    dest_data.input_filepath = my_args[0].clone();
+                    dest_data.fit_size = Some(fit_size_from_str(&my_args[0])?);
                     return Ok(1);
                 }),
-                1, argparse::ARGDEF_DEFAULT | argparse::ARGDEF_REQUIRED,
+                1, argparse::ARGDEF_DEFAULT,
                 parser_group,
             );
             parser.add_argument(
-                "-o", "--output",
-                "The file path to use for writing",
-                "FILEPATH",
+                "", "--transform",
+                concat!("Transform applied to the output, using SVG syntax, ",
+                        "e.g. 'translate(x,y) scale(sx,sy) rotate(degrees)'."),
+                "TRANSFORM",
                 Box::new(|dest_data, my_args| {
-                    dest_data.output_filepath = my_args[0].clone();
-                    return Ok(1);
+                    match curve_types::Transform::from_svg_str(&my_args[0]) {
+                        Ok(t) => {
+                            dest_data.output_transform = t;
+                            return Ok(1);
+                        },
+                        Err(e) => {
+                            return Err(e);
+                        },
+                    }
                 }),
-                1, argparse::ARGDEF_DEFAULT | argparse::ARGDEF_REQUIRED,
+                1, argparse::ARGDEF_DEFAULT,
                 parser_group,
             );
-        }
-
-        // Tracing Methods
-        {
-            let parser_group = Some(parser.add_argument_group(
-                "Tracing Behavior",
-                ""
-            ));
             parser.add_argument(
-                "-m", "--mode",
-                concat!("The method used for tracing the image in [OUTLINE, CENTER], ",
-                        "(defaults to OUTLINE)."),
-                "MODE",
+                "", "--flip-y",
+                concat!("When passed, flip the output vertically so the Y axis points up ",
+                        "(GLIF & PDF output always use Y-up)."),
+                "",
+                Box::new(|dest_data, _my_args| {
+                    dest_data.use_flip_y = true;
+                    return Ok(0);
+                }),
+                0, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--origin",
+                concat!("Where the origin (0, 0) of the output is placed on the image ",
+                        "[TOPLEFT, BOTTOMLEFT, CENTER], ",
+                        "(defaults to TOPLEFT, or BOTTOMLEFT when the Y axis points up)."),
+                "ORIGIN",
                 Box::new(|dest_data, my_args| {
                     match my_args[0].as_ref() {
-                        "OUTLINE" => {
-                            dest_data.mode = curve_fit_nd::TraceMode::Outline;
-                        },
+                        "TOPLEFT" => {
+                            dest_data.origin = Some(Origin::TopLeft);
+                        }
+                        "BOTTOMLEFT" => {
+                            dest_data.origin = Some(Origin::BottomLeft);
+                        }
                         "CENTER" => {
-                            dest_data.mode = curve_fit_nd::TraceMode::Centerline;
-                        },
+                            dest_data.origin = Some(Origin::Center);
+                        }
                         _ => {
                             return Err(format!(
-                                "Expected [OUTLINE, CENTER], not '{}'",
+                                "Expected [TOPLEFT, BOTTOMLEFT, CENTER], not '{}'",
                                 my_args[0],
                             ));
                         }
@@ -307,31 +4285,236 @@ fn main()
                 parser_group,
             );
             parser.add_argument(
-                "-z", "--turnpolicy",
-                concat!("Method for extracting outlines [BLACK, WHITE, MAJORITY, MINORITY], ",
-                        "(defaults to MAJORITY)."),
-                "POLICY",
+                "", "--snap",
+                concat!("Snap points to a grid of this size in output units, ",
+                        "points are left as-is when snapping exceeds the error threshold, ",
+                        "(defaults to 0, disabled)."),
+                "GRID",
+                Box::new(|dest_data, my_args| {
+                    match f64::from_str(&my_args[0]) {
+                        Ok(v) => {
+                            if !(v >= 0.0) {
+                                return Err(format!("Expected a positive value, not '{}'", v));
+                            }
+                            dest_data.snap = v;
+                            return Ok(1);
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--snap-handles",
+                "When passed, snap handles as well as points when using --snap.",
+                "",
+                Box::new(|dest_data, _my_args| {
+                    dest_data.use_snap_handles = true;
+                    return Ok(0);
+                }),
+                0, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--svg-layers",
+                concat!("When passed, write the curves and each debug pass into their own ",
+                        "Inkscape compatible layer."),
+                "",
+                Box::new(|dest_data, _my_args| {
+                    dest_data.use_svg_layers = true;
+                    return Ok(0);
+                }),
+                0, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--path-ids",
+                concat!("When passed, write each path as its own element with an 'id' & ",
+                        "'data-hash' from its source contour, stable between traces ",
+                        "so changed shapes can be found by comparing outputs ",
+                        "(holes are written with the path which contains them)."),
+                "",
+                Box::new(|dest_data, _my_args| {
+                    dest_data.use_path_ids = true;
+                    return Ok(0);
+                }),
+                0, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--template",
+                concat!("Insert the traced paths into this SVG, ",
+                        "at the end of the document unless '--insert-selector' is passed."),
+                "FILEPATH",
+                Box::new(|dest_data, my_args| {
+                    dest_data.template_filepath = my_args[0].clone();
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--insert-selector",
+                concat!("Insert the traced paths at the start of the template element ",
+                        "with this id, e.g. '#art'."),
+                "SELECTOR",
+                Box::new(|dest_data, my_args| {
+                    if !my_args[0].starts_with('#') || my_args[0].len() == 1 {
+                        return Err(format!("Expected a selector '#id', not '{}'", my_args[0]));
+                    }
+                    dest_data.template_selector = my_args[0].clone();
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--no-metadata",
+                "When passed, don't write the parameters used for tracing into the output.",
+                "",
+                Box::new(|dest_data, _my_args| {
+                    dest_data.use_metadata = false;
+                    return Ok(0);
+                }),
+                0, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "-p", "--passes",
+                concat!("Write extra debug graphics, comma separated list of passes including ",
+                        "[PIXEL, PRE_FIT, TANGENT, ANIM, DIRECTION, POST_REMOVE, POST_CORNER], ",
+                        "(defaults to [])."),
+                "PASSES",
+                Box::new(|dest_data, my_args| {
+                    dest_data.debug_passes |= debug_pass::kind_from_names(&my_args[0])?;
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--pass-scale",
+                "Scale graphic details used in some debug passes, (defaults to 1).",
+                "SCALE",
+                Box::new(|dest_data, my_args| {
+                    match f64::from_str(&my_args[0]) {
+                        Ok(v) => {
+                            dest_data.debug_pass_scale = v;
+                            return Ok(1);
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--refine-log",
+                concat!("Write the knots removed while fitting as JSON, in the order they're ",
+                        "removed with the resulting error, to see why details are smoothed away ",
+                        "(in pixels, single input only)."),
+                "FILEPATH",
+                Box::new(|dest_data, my_args| {
+                    dest_data.refine_log_filepath = my_args[0].clone();
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--stats",
+                concat!("Print the number of threads & memory used when fitting, ",
+                        "how often each solver was used & the average re-parameterization ",
+                        "iterations, as well as the peak memory used by the process ",
+                        "(where supported)."),
+                "",
+                Box::new(|dest_data, _my_args| {
+                    dest_data.use_stats = true;
+                    return Ok(0);
+                }),
+                0, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "-q", "--quiet",
+                concat!("When passed, don't print warnings ",
+                        "(for arguments which have no effect or images without filled pixels)."),
+                "",
+                Box::new(|dest_data, _my_args| {
+                    dest_data.use_quiet = true;
+                    return Ok(0);
+                }),
+                0, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--threads",
+                concat!("The maximum number of threads used for tracing, ",
+                        "in batch mode threads are shared between inputs, ",
+                        "(defaults to 0, all available threads)."),
+                "NUMBER",
+                Box::new(|dest_data, my_args| {
+                    match usize::from_str(&my_args[0]) {
+                        Ok(v) => {
+                            dest_data.threads_len = v;
+                            return Ok(1);
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--seed",
+                concat!("Seed for options which make random choices, ",
+                        "each thread uses its own sequence so the output is reproducible ",
+                        "(defaults to 0)."),
+                "SEED",
+                Box::new(|dest_data, my_args| {
+                    match u64::from_str(&my_args[0]) {
+                        Ok(v) => {
+                            dest_data.seed = v;
+                            return Ok(1);
+                        },
+                        Err(e) => {
+                            return Err(e.to_string());
+                        },
+                    }
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--outline-method",
+                concat!("Method for following outlines [STEPS, EDGES, RUNS], ",
+                        "EDGES follows them in the image directly (using less memory), ",
+                        "RUNS follows them along the filled runs of each row ",
+                        "(skipping empty space), ",
+                        "the output is unchanged, (defaults to STEPS)."),
+                "METHOD",
                 Box::new(|dest_data, my_args| {
+                    use polys_from_raster_outline::OutlineMethod;
                     match my_args[0].as_ref() {
-                        "BLACK" => {
-                            dest_data.turn_policy =
-                                polys_from_raster_outline::TurnPolicy::Black;
-                        }
-                        "WHITE" => {
-                            dest_data.turn_policy =
-                                polys_from_raster_outline::TurnPolicy::White;
+                        "STEPS" => {
+                            dest_data.outline_method = OutlineMethod::Steps;
                         }
-                        "MAJORITY" => {
-                            dest_data.turn_policy =
-                                polys_from_raster_outline::TurnPolicy::Majority;
+                        "EDGES" => {
+                            dest_data.outline_method = OutlineMethod::Edges;
                         }
-                        "MINORITY" => {
-                            dest_data.turn_policy =
-                                polys_from_raster_outline::TurnPolicy::Minority;
+                        "RUNS" => {
+                            dest_data.outline_method = OutlineMethod::Runs;
                         }
                         _ => {
                             return Err(format!(
-                                "Expected [BLACK, WHITE, MAJORITY, MINORITY], not '{}'",
+                                "Expected [STEPS, EDGES, RUNS], not '{}'",
                                 my_args[0],
                             ));
                         }
@@ -341,22 +4524,36 @@ fn main()
                 1, argparse::ARGDEF_DEFAULT,
                 parser_group,
             );
+            parser.add_argument(
+                "", "--cache",
+                concat!("Store the polygons extracted from each image in this directory, ",
+                        "running again with only fitting parameters changed ",
+                        "(--error, --corner... etc) reads them instead of extracting again."),
+                "DIR",
+                Box::new(|dest_data, my_args| {
+                    dest_data.cache_dirpath = my_args[0].clone();
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
         }
 
-        // Curve Evaluation
+        // Montage Options
         {
             let parser_group = Some(parser.add_argument_group(
-                "Curve Evaluation Options",
-                "Parameters controlling curve evaluation behavior."
+                "Montage Options",
+                "Trace multiple inputs into a single SVG, useful for sprite sheets & comparisons."
             ));
             parser.add_argument(
-                "-e", "--error",
-                "The error threshold (defaults to 1.0)",
-                "PIXELS",
+                "", "--montage",
+                concat!("Lay out the traced inputs in a grid with this many columns, ",
+                        "each input is written into its own group, (defaults to 0, disabled)."),
+                "COLS",
                 Box::new(|dest_data, my_args| {
-                    match f64::from_str(&my_args[0]) {
+                    match usize::from_str(&my_args[0]) {
                         Ok(v) => {
-                            dest_data.error_threshold = v;
+                            dest_data.montage_columns = v;
                             return Ok(1);
                         },
                         Err(e) => {
@@ -367,50 +4564,85 @@ fn main()
                 1, argparse::ARGDEF_DEFAULT,
                 parser_group,
             );
+        }
+
+        // Frame Options
+        {
+            let parser_group = Some(parser.add_argument_group(
+                "Frame Options",
+                concat!("Trace multi-frame inputs (animated GIF & APNG files, ",
+                        "PPM files containing multiple images or multi-page TIFF files), ",
+                        "useful for animation.")
+            ));
             parser.add_argument(
-                "-t", "--simplify",
-                "Simplify polygon before fitting (defaults to 2.0)",
-                "PIXELS",
+                "", "--frames",
+                concat!("Trace all frames of the input [FILES, LAYERS], ",
+                        "FILES numbers the output for each frame (out_0001.svg, ...), ",
+                        "LAYERS writes each frame into a group of a single SVG, ",
+                        "hiding all except the first, (defaults to tracing the first frame)."),
+                "MODE",
                 Box::new(|dest_data, my_args| {
-                    match f64::from_str(&my_args[0]) {
-                        Ok(v) => {
-                            dest_data.simplify_threshold = v;
-                            return Ok(1);
-                        },
-                        Err(e) => {
-                            return Err(e.to_string());
-                        },
+                    match my_args[0].as_ref() {
+                        "FILES" => {
+                            dest_data.frames = Some(FrameMode::Files);
+                        }
+                        "LAYERS" => {
+                            dest_data.frames = Some(FrameMode::Layers);
+                        }
+                        _ => {
+                            return Err(format!(
+                                "Expected [FILES, LAYERS], not '{}'",
+                                my_args[0],
+                            ));
+                        }
                     }
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_DEFAULT,
+                parser_group,
+            );
+        }
+
+        // Batch Options
+        {
+            let parser_group = Some(parser.add_argument_group(
+                "Batch Options",
+                concat!("Trace many inputs, each into its own file, ",
+                        "useful for large automated conversions.")
+            ));
+            parser.add_argument(
+                "", "--batch",
+                concat!("When passed, trace each input into its own file in the --output ",
+                        "directory (named after the input), continuing past inputs which fail, ",
+                        "exits with status 2 when any input fails."),
+                "",
+                Box::new(|dest_data, _my_args| {
+                    dest_data.use_batch = true;
+                    return Ok(0);
                 }),
-                1, argparse::ARGDEF_DEFAULT,
+                0, argparse::ARGDEF_DEFAULT,
                 parser_group,
             );
-
-
             parser.add_argument(
-                "-c", "--corner",
-                "The corner threshold (`pi` or greater to disable, defaults to 30.0)",
-                "DEGREES",
+                "", "--batch-report",
+                concat!("Write the status, error & statistics of each input in batch mode ",
+                        "(as JSON)."),
+                "FILEPATH",
                 Box::new(|dest_data, my_args| {
-                    match f64::from_str(&my_args[0]) {
-                        Ok(v) => {
-                            dest_data.corner_threshold = v.to_radians();
-                            return Ok(1);
-                        },
-                        Err(e) => {
-                            return Err(e.to_string());
-                        },
-                    }
+                    dest_data.batch_report_filepath = my_args[0].clone();
+                    return Ok(1);
                 }),
                 1, argparse::ARGDEF_DEFAULT,
                 parser_group,
             );
             parser.add_argument(
-                "", "--optimize-exhaustive",
-                "When passed, perform exhaustive curve fitting (can be slow!)",
+                "", "--pdf-multipage",
+                concat!("When passed, write the inputs as pages of a single PDF document ",
+                        "(the --output file) instead of a file for each input, ",
+                        "each page keeps the size of its input."),
                 "",
                 Box::new(|dest_data, _my_args| {
-                    dest_data.use_optimize_exhaustive = true;
+                    dest_data.use_pdf_multipage = true;
                     return Ok(0);
                 }),
                 0, argparse::ARGDEF_DEFAULT,
@@ -418,20 +4650,59 @@ fn main()
             );
         }
 
-        // Output Options
+        // Server Options
         {
             let parser_group = Some(parser.add_argument_group(
-                "Output Options",
-                "Generic options for output (format agnostic)."
+                "Server Options",
+                concat!("Keep running, tracing images for each command received, ",
+                        "for applications which run the tracer as a sub-process.")
             ));
             parser.add_argument(
-                "-s", "--scale",
-                "Scale for output, (defaults to 1).",
-                "SCALE",
+                "", "--serve",
+                concat!("When passed, read commands from the standard input, a JSON object ",
+                        "on each line, e.g. {\"input\": \"in.png\", \"output\": \"out.svg\", ",
+                        "\"error\": 0.8}, where other members are options (without dashes) ",
+                        "which replace those passed, a line of JSON is written to the standard ",
+                        "output for each command (with the document when there's no output)."),
+                "",
+                Box::new(|dest_data, _my_args| {
+                    dest_data.use_serve = true;
+                    return Ok(0);
+                }),
+                0, argparse::ARGDEF_SKIP_REQUIRED,
+                parser_group,
+            );
+            parser.add_argument(
+                "", "--listen",
+                concat!("Accept connections on a Unix domain socket at this path, ",
+                        "running commands as --serve does, where each command & result ",
+                        "is the JSON text prefixed by its length ",
+                        "(4 bytes, unsigned, little endian), ",
+                        "commands with \"curves\": true receive the curves instead of a document."),
+                "FILEPATH",
+                Box::new(|dest_data, my_args| {
+                    dest_data.listen_filepath = my_args[0].clone();
+                    return Ok(1);
+                }),
+                1, argparse::ARGDEF_SKIP_REQUIRED,
+                parser_group,
+            );
+        }
+
+        // GLIF Output Options
+        {
+            let parser_group = Some(parser.add_argument_group(
+                "GLIF Output Options",
+                "Options for UFO glyph output, for type design."
+            ));
+            parser.add_argument(
+                "", "--upm",
+                "Units per em, the height of the image is scaled to this size (defaults to 1000).",
+                "UNITS",
                 Box::new(|dest_data, my_args| {
                     match f64::from_str(&my_args[0]) {
                         Ok(v) => {
-                            dest_data.output_scale = v;
+                            dest_data.glif_upm = v;
                             return Ok(1);
                         },
                         Err(e) => {
@@ -443,44 +4714,14 @@ fn main()
                 parser_group,
             );
             parser.add_argument(
-                "-p", "--passes",
-                concat!("Write extra debug graphics, comma separated list of passes including ",
-                        "[PIXEL, PRE_FIT, TANGENT], ",
-                        "(defaults to [])."),
-                "PASSES",
-                Box::new(|dest_data, my_args| {
-                    for pass_string in my_args[0].split(",") {
-                        match pass_string.as_ref() {
-                            "PIXEL" => {
-                                dest_data.debug_passes |= debug_pass::kind::PIXEL;
-                            }
-                            "PRE_FIT" => {
-                                dest_data.debug_passes |= debug_pass::kind::PRE_FIT;
-                            }
-                            "TANGENT" => {
-                                dest_data.debug_passes |= debug_pass::kind::TANGENT;
-                            }
-                            _ => {
-                                return Err(format!(
-                                    "Expected [PIXEL, PRE_FIT, TANGENT], not '{}'",
-                                    my_args[0],
-                                ));
-                            }
-                        }
-                    }
-                    return Ok(1);
-                }),
-                1, argparse::ARGDEF_DEFAULT,
-                parser_group,
-            );
-            parser.add_argument(
-                "", "--pass-scale",
-                "Scale graphic details used in some debug passes, (defaults to 1).",
-                "SCALE",
+                "", "--baseline",
+                concat!("The baseline in pixels from the top of the image, ",
+                        "(defaults to the bottom of the image)."),
+                "Y",
                 Box::new(|dest_data, my_args| {
                     match f64::from_str(&my_args[0]) {
                         Ok(v) => {
-                            dest_data.debug_pass_scale = v;
+                            dest_data.glif_baseline = Some(v);
                             return Ok(1);
                         },
                         Err(e) => {
@@ -495,21 +4736,75 @@ fn main()
 
         parser.add_argument(
             "-h", "--help",
-            "Print help text",
-            "",
-            Box::new(|dest_data, _my_args| {
+            concat!("Print help text, when a GROUP is passed only print options in groups ",
+                    "starting with this name (case insensitive), e.g. '--help output'."),
+            "[GROUP]",
+            Box::new(|dest_data, my_args| {
                 dest_data.show_help = true;
+                if my_args.len() != 0 && !my_args[0].starts_with("-") {
+                    dest_data.show_help_group = my_args[0].clone();
+                    return Ok(1);
+                }
                 return Ok(0);
             }),
-            0, argparse::ARGDEF_DEFAULT,
+            0, argparse::ARGDEF_VARARGS,
             None,
         );
 
-        let args: Vec<String> = ::std::env::args().collect();
-        let result = parser.parse(&args[1..]);
+        // Report options which would otherwise be ignored.
+        for id in &[
+            "--turnpolicy", "--edge-policy", "--contours", "--outline-method",
+            "--merge-overlaps", "--silhouette", "--multiscale",
+        ] {
+            parser.add_conflicts(id, &["--mode"], Some("CENTER"));
+        }
+        for id in &["--join-dashes", "--stroke-widths"] {
+            parser.add_requires(id, &["--mode"], Some("CENTER"));
+        }
+        parser.add_requires("--dash-array", &["--join-dashes"], None);
+        // Report options which have no effect (as warnings, since the output is still valid).
+        parser.add_applies("--error-min", "without --error-relative", |p| p.error_relative != 0.0);
+        parser.add_applies("--simplify-cutoff", "without --simplify-small or --simplify-large",
+                           |p| p.simplify_small.is_some() || p.simplify_large.is_some());
+        parser.add_applies("--refine-epsilon", "with --refine-iterations 0",
+                           |p| p.refine_iterations != 0);
+        for id in &["--exhaustive-epsilon", "--exhaustive-steps", "--no-exhaustive-prune"] {
+            parser.add_applies(id, "without --optimize-exhaustive",
+                               |p| p.use_optimize_exhaustive);
+        }
+        for id in &["--exhaustive-epsilon", "--exhaustive-steps"] {
+            parser.add_applies(id, "with --no-exhaustive-prune", |p| p.use_exhaustive_prune);
+        }
+        parser.add_applies("--snap-handles", "without --snap", |p| p.snap != 0.0);
+        // Passes may also be selected by each output, so this is checked after parsing.
+        parser.add_applies("--pass-scale", "without --passes (or an --output with passes)",
+                           |p| p.debug_passes != 0 ||
+                               p.outputs_extra.iter().any(|output| output.debug_passes != 0));
+        parser.add_applies("--preview-scale", "without --preview-png",
+                           |p| !p.preview_png_filepath.is_empty());
+        for id in &["--upm", "--baseline"] {
+            parser.add_applies(id, "without GLIF output",
+                               |p| p.output_format_any(OutputFormat::Glif));
+        }
 
+        let result = parser.parse(args);
+        parser.dest_data.warnings = parser.warnings().to_vec();
         if parser.dest_data.show_help {
-            parser.print_help();
+            if parser.dest_data.show_help_group.is_empty() {
+                parser.print_help();
+            } else if let Err(e) = parser.print_help_group(&parser.dest_data.show_help_group) {
+                // Report the error instead of exiting quietly.
+                parser.dest_data.show_help = false;
+                return Err(e);
+            }
+        }
+        return result;
+    };
+    {
+        let args: Vec<String> = ::std::env::args().collect();
+        let result = args_parse(&mut trace_params, &args[1..]);
+
+        if trace_params.show_help {
             return;
         }
 
@@ -523,50 +4818,679 @@ fn main()
         }
     }
 
-    match ::intern::image_load::from_filepath_any(&trace_params.input_filepath) {
-        Ok((size, color_max, pixel_buffer)) => {
-            println!("{:?} {}", size, color_max);
-            let mut image: Vec<bool> = vec![false; pixel_buffer.len()];
-            let color_mid = ((color_max / 2) as u32) * 3;
-            for (p_src, p_dst) in pixel_buffer.iter().zip(&mut image) {
-                let t = (p_src[0] as u32) +
-                        (p_src[1] as u32) +
-                        (p_src[2] as u32);
-                if t < color_mid {
-                    *p_dst = true;
-                }
+    {
+        use std::io::Write;
+        let error = if trace_params.use_serve || !trace_params.listen_filepath.is_empty() {
+            if !trace_params.input_filepaths.is_empty() ||
+               !trace_params.output_filepath.is_empty()
+            {
+                Some(concat!("--serve & --listen can't be used with --input or --output ",
+                             "(commands pass them)"))
+            } else if trace_params.use_serve && !trace_params.listen_filepath.is_empty() {
+                Some("--serve can't be used with --listen")
+            } else {
+                serve::trace_params_error(&trace_params)
+            }
+        } else {
+            trace_params_error(&trace_params)
+        };
+        if let Some(error) = error {
+            writeln!(&mut std::io::stderr(), "{}, aborting!", error).unwrap();
+            std::process::exit(1);
+        }
+    }
+
+    // Commands may pass any arguments while serving, so these only apply to each command.
+    if !trace_params.use_quiet &&
+       !trace_params.use_serve &&
+       trace_params.listen_filepath.is_empty()
+    {
+        use std::io::Write;
+        // Not the standard output, which may be used for output (see `--histogram`).
+        for warning in &trace_params.warnings {
+            writeln!(&mut std::io::stderr(), "{}", warning).unwrap();
+        }
+    }
+
+    if trace_params.use_serve {
+        let stdin = ::std::io::stdin();
+        let mut server = serve::Server::new(&trace_params, args_parse);
+        if let Err(e) = serve::serve(&mut server, stdin.lock(), ::std::io::stdout()) {
+            println!("Error serving {:?}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if !trace_params.listen_filepath.is_empty() {
+        let server = serve::Server::new(&trace_params, args_parse);
+        if let Err(e) = serve::listen(server, &trace_params.listen_filepath) {
+            println!("Error listening on {:?}: {}", trace_params.listen_filepath, e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let mask = if !trace_params.mask_filepath.is_empty() {
+        match ::intern::image_load::load_mask(&trace_params.mask_filepath, None, 0.5, None) {
+            Ok((image, size, _color_max)) => {
+                Some((image, size))
+            }
+            Err(e) => {
+                println!("Error reading mask {:?}", e);
+                return;
             }
+        }
+    } else {
+        None
+    };
 
-            if trace_params.mode == curve_fit_nd::TraceMode::Centerline {
-                use image_skeletonize;
-                image_skeletonize::calculate(&mut image, &[size[0], size[1]]);
-            }
-
-            match trace_image(
-                &trace_params.output_filepath,
-                trace_params.output_scale,
-                &image.as_slice(),
-                &size,
-                trace_params.error_threshold,
-                trace_params.simplify_threshold,
-                trace_params.corner_threshold,
-                trace_params.use_optimize_exhaustive,
-                0.75,
-                trace_params.mode,
-                trace_params.turn_policy,
-                trace_params.debug_passes,
-                trace_params.debug_pass_scale * trace_params.output_scale,
-                )
-            {
-                Ok(()) => {}
+    if trace_params.use_batch {
+        let batch_items = trace_batch(&trace_params, mask.as_ref());
+        let failed_len = batch_items.iter().filter(|item| item.error.is_some()).count();
+        println!("Batch traced: {}, failed: {}", batch_items.len() - failed_len, failed_len);
+        if !trace_params.batch_report_filepath.is_empty() {
+            let result = ::std::fs::File::create(&trace_params.batch_report_filepath).and_then(
+                |f| write_batch_report(&f, &batch_items));
+            if let Err(e) = result {
+                println!("Error writing batch report {:?}", e);
+                std::process::exit(EXIT_BATCH_FAILED);
+            }
+        }
+        if failed_len != 0 {
+            std::process::exit(EXIT_BATCH_FAILED);
+        }
+        return;
+    }
+
+    let mut images: Vec<(Vec<bool>, [usize; 2])> = vec![];
+    for filepath in &trace_params.input_filepaths {
+        let images_result = if trace_params.frames.is_some() {
+            image_load_frames_for_trace(&trace_params, filepath, mask.as_ref())
+        } else {
+            image_load_for_trace(&trace_params, filepath, mask.as_ref()).map(|image_and_size| {
+                vec![image_and_size]
+            })
+        };
+        match images_result {
+            Ok(images_file) => {
+                images.extend(images_file);
+            }
+            Err(e) => {
+                println!("Error reading image {:?}", e);
+                return;
+            }
+        }
+    }
+
+    let is_empty = images.iter().all(|&(ref image, _)| !image.contains(&true));
+
+    if let Some(fit_size) = trace_params.fit_size {
+        // All frames & montage cells use the size of the largest image.
+        let mut size = [0, 0];
+        for &(_, size_image) in &images {
+            size[0] = ::std::cmp::max(size[0], size_image[0]);
+            size[1] = ::std::cmp::max(size[1], size_image[1]);
+        }
+        if trace_params.montage_columns != 0 {
+            let columns = ::std::cmp::min(trace_params.montage_columns, images.len());
+            size[0] *= columns;
+            size[1] *= (images.len() + columns - 1) / columns;
+        }
+        if size[0] != 0 && size[1] != 0 {
+            trace_params.output_scale = output_scale_to_fit(&fit_size, &size);
+        }
+    }
+
+    let result = {
+        if let Some(frames) = trace_params.frames {
+            match frames {
+                FrameMode::Files => {
+                    let mut result = Ok(());
+                    for (i, &(ref image, ref size)) in images.iter().enumerate() {
+                        let trace_params_frame = TraceParams {
+                            output_filepath: frame_filepath(&trace_params.output_filepath, i + 1),
+                            .. trace_params.clone()
+                        };
+                        result = trace_image(
+                            &trace_params_frame, &image.as_slice(), size, 0.75).map(|_| ());
+                        if result.is_err() {
+                            break;
+                        }
+                    }
+                    result
+                },
+                FrameMode::Layers => {
+                    trace_image_frames_layered(&trace_params, &images, 0.75)
+                },
+            }
+        } else if !trace_params.diff_filepath.is_empty() {
+            let (ref image, ref size) = images[0];
+            match image_load_for_trace(&trace_params, &trace_params.diff_filepath, mask.as_ref()) {
+                Ok((ref image_other, ref size_other)) if size_other == size => {
+                    trace_image_diff(&trace_params, image, image_other, size, 0.75)
+                }
+                Ok((_, size_other)) => {
+                    println!("Diff image size {:?} doesn't match the image size {:?}",
+                             size_other, size);
+                    return;
+                }
+                Err(e) => {
+                    println!("Error reading diff image {:?}", e);
+                    return;
+                }
+            }
+        } else if !trace_params.lod.is_empty() {
+            let (ref image, ref size) = images[0];
+            trace_image_lod(&trace_params, image, size, 0.75)
+        } else if trace_params.centerline_subpixel != 0.0 || trace_params.use_stroke_widths_any() {
+            let (ref image, ref size) = images[0];
+            let hooks = (|| {
+                let hooks_subpixel = if trace_params.centerline_subpixel != 0.0 {
+                    Some(image_load_centerline_subpixel_hooks(&trace_params, size, mask.as_ref())?)
+                } else {
+                    None
+                };
+                let hooks_stroke_width = if trace_params.use_stroke_widths_any() {
+                    Some(image_load_stroke_width_hooks(&trace_params, size, mask.as_ref())?)
+                } else {
+                    None
+                };
+                Ok((hooks_subpixel, hooks_stroke_width))
+            })();
+            match hooks {
+                Ok(hooks) => {
+                    trace_image_with_hooks(&trace_params, &image.as_slice(), size, 0.75, &hooks)
+                        .map(|_| ())
+                }
                 Err(e) => {
-                    println!("Error writing output {:?}", e);
+                    let e: ::std::io::Error = e;
+                    println!("Error reading image {:?}", e);
+                    return;
                 }
             }
+        } else if trace_params.montage_columns == 0 {
+            let (ref image, ref size) = images[0];
+            trace_image(&trace_params, &image.as_slice(), size, 0.75).map(|_| ())
+        } else {
+            trace_images_montage(&trace_params, &images, trace_params.montage_columns, 0.75)
         }
+    };
+    match result {
+        Ok(()) => {}
         Err(e) => {
-            println!("Error reading PPM {:?}", e);
+            println!("Error writing output {:?}", e);
+        }
+    }
+    if is_empty && trace_params.use_fail_empty {
+        std::process::exit(EXIT_EMPTY);
+    }
+}
+
+/// The result of tracing a single input in batch mode.
+pub struct BatchItem {
+    pub input_filepath: String,
+    pub output_filepath: String,
+    /// When set, tracing failed with this error.
+    pub error: Option<String>,
+    pub paths_len: usize,
+    pub knots_len: usize,
+}
+
+/// The output file path for an input in batch mode, see: `TraceParams::use_batch`.
+pub fn batch_output_filepath(
+    output_dirpath: &str,
+    input_filepath: &str,
+    output_format: Option<OutputFormat>,
+) -> String {
+    let path = ::std::path::Path::new(input_filepath);
+    let name = path.file_stem().unwrap_or(path.as_os_str()).to_string_lossy();
+    let ext = match output_format {
+        Some(OutputFormat::Glif) => "glif",
+        Some(OutputFormat::Pdf) => "pdf",
+        Some(OutputFormat::Json) => "json",
+        Some(OutputFormat::Svg) | None => "svg",
+    };
+    return ::std::path::Path::new(output_dirpath).join(
+        format!("{}.{}", name, ext)).to_string_lossy().into_owned();
+}
+
+/// Trace each input into its own file, continuing past inputs which fail
+/// (including panics, so one pathological input doesn't stop the batch).
+///
+/// Threads are shared between inputs (see `TraceParams::threads_len`),
+/// large inputs are traced one at a time using all threads,
+/// then the remaining inputs are traced in parallel using a single thread each,
+/// so the number of threads doesn't multiply with the number of inputs.
+///
+/// With `TraceParams::use_pdf_multipage` the traced inputs are written as pages
+/// of a single document once all inputs have been traced (in input order).
+#[cfg(not(target_arch = "wasm32"))]
+pub fn trace_batch(
+    trace_params: &TraceParams,
+    mask: Option<&(Vec<bool>, [usize; 2])>,
+) -> Vec<BatchItem>
+{
+    use std::io::{Error, ErrorKind};
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::sync::Mutex;
+
+    let threads_len = threads_len_from_params(trace_params);
+    let use_pdf_multipage = trace_params.use_pdf_multipage;
+    let dir_result = if use_pdf_multipage {
+        Ok(())
+    } else {
+        ::std::fs::create_dir_all(&trace_params.output_filepath)
+    };
+    let input_filepaths = &trace_params.input_filepaths;
+    let pages: Mutex<Vec<Option<curve_write::pdf::Page>>> =
+        Mutex::new(input_filepaths.iter().map(|_| None).collect());
+    let trace_file = |i: usize, threads_len: usize| {
+        let input_filepath = &input_filepaths[i];
+        let output_filepath = if use_pdf_multipage {
+            trace_params.output_filepath.clone()
+        } else {
+            batch_output_filepath(
+                &trace_params.output_filepath, input_filepath, trace_params.output_format)
+        };
+        println!("Batch: {} -> {}", input_filepath, output_filepath);
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            if let Err(ref e) = dir_result {
+                return Err(Error::new(e.kind(), e.to_string()));
+            }
+            let (image, size) = image_load_for_trace(trace_params, input_filepath, mask)?;
+            let is_empty = !image.contains(&true);
+            let mut trace_params_file = TraceParams {
+                output_filepath: output_filepath.clone(),
+                threads_len: threads_len,
+                .. trace_params.clone()
+            };
+            if let Some(fit_size) = trace_params.fit_size {
+                trace_params_file.output_scale = output_scale_to_fit(&fit_size, &size);
+            }
+            let output = if use_pdf_multipage {
+                let output = trace_image_to_drawing(&trace_params_file, &image, &size, 0.75);
+                let page = pdf_page(&trace_params_file, &size, &output)?;
+                pages.lock().unwrap()[i] = Some(page);
+                output
+            } else {
+                trace_image(&trace_params_file, &image, &size, 0.75)?
+            };
+            if is_empty && trace_params.use_fail_empty {
+                return Err(Error::new(ErrorKind::InvalidData, "No filled pixels"));
+            }
+            Ok(output)
+        })).unwrap_or_else(|panic| {
+            Err(Error::new(ErrorKind::Other, format!("Panic: {}", panic_message(&*panic))))
+        });
+        let mut item = BatchItem {
+            input_filepath: input_filepath.clone(),
+            output_filepath: output_filepath,
+            error: None,
+            paths_len: 0,
+            knots_len: 0,
+        };
+        match result {
+            Ok(output) => {
+                item.paths_len = output.drawing.paths.len();
+                item.knots_len = output.drawing.knots_len();
+            }
+            Err(e) => {
+                println!("Error tracing {:?}: {}", input_filepath, e);
+                item.error = Some(e.to_string());
+            }
+        }
+        item
+    };
+
+    let mut batch_items: Vec<Option<BatchItem>> = input_filepaths.iter().map(|_| None).collect();
+    let mut queue: Vec<usize> = vec![];
+    for (i, input_filepath) in input_filepaths.iter().enumerate() {
+        let is_large = ::std::fs::metadata(input_filepath).map(
+            |metadata| metadata.len() >= BATCH_INPUT_LARGE_BYTES).unwrap_or(false);
+        if is_large || threads_len == 1 {
+            batch_items[i] = Some(trace_file(i, threads_len));
+        } else {
+            queue.push(i);
+        }
+    }
+
+    // Each thread traces inputs until none remain (in order, since they're popped).
+    queue.reverse();
+    let workers_len = ::std::cmp::min(threads_len, queue.len());
+    let queue = Mutex::new(queue);
+    let trace_file = &trace_file;
+    let results: Vec<Vec<(usize, BatchItem)>> = ::std::thread::scope(|scope| {
+        let join_handles: Vec<_> = (0..workers_len).map(|_| {
+            let queue = &queue;
+            scope.spawn(move || {
+                let mut result = vec![];
+                loop {
+                    let item = queue.lock().unwrap().pop();
+                    if let Some(i) = item {
+                        result.push((i, trace_file(i, 1)));
+                    } else {
+                        break;
+                    }
+                }
+                result
+            })
+        }).collect();
+        join_handles.into_iter().map(|child| child.join().unwrap()).collect()
+    });
+    for (i, item) in results.into_iter().flat_map(|result| result) {
+        batch_items[i] = Some(item);
+    }
+    let mut batch_items: Vec<BatchItem> = batch_items.into_iter().map(
+        |item| item.unwrap()).collect();
+
+    if use_pdf_multipage {
+        // Inputs which failed have no page.
+        let pages: Vec<curve_write::pdf::Page> = pages.into_inner().unwrap().into_iter().filter_map(
+            |page| page).collect();
+        let metadata_items = trace_params.metadata_items();
+        let result = ::std::fs::File::create(&trace_params.output_filepath).and_then(|f| {
+            curve_write::pdf::write_document(
+                &f, &pages,
+                if trace_params.use_metadata { Some(&metadata_items) } else { None },
+            )
+        });
+        if let Err(e) = result {
+            println!("Error writing {:?}: {}", trace_params.output_filepath, e);
+            for item in batch_items.iter_mut().filter(|item| item.error.is_none()) {
+                item.error = Some(e.to_string());
+            }
+        }
+    }
+    return batch_items;
+}
+
+/// The message passed to `panic!`, (empty when it's not a string).
+fn panic_message(
+    panic: &(dyn Any + Send),
+) -> String
+{
+    return match panic.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => panic.downcast_ref::<String>().cloned().unwrap_or_default(),
+    };
+}
+
+/// The number of threads to use, see: `TraceParams::threads_len`.
+fn threads_len_from_params(
+    trace_params: &TraceParams,
+) -> usize
+{
+    // WASM has no threads.
+    if cfg!(target_arch = "wasm32") {
+        return 1;
+    }
+    if trace_params.threads_len != 0 {
+        return trace_params.threads_len;
+    }
+    return ::std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+}
+
+/// Write the result of each input in batch mode (as JSON).
+fn write_batch_report<W: ::std::io::Write>(
+    mut f: W,
+    batch_items: &[BatchItem],
+) -> Result<(), ::std::io::Error>
+{
+    use curve_write::json::string_escape;
+    writeln!(f, "{{")?;
+    writeln!(f, "  \"files\": [")?;
+    for (i, item) in batch_items.iter().enumerate() {
+        write!(f, "    {{\"input\": \"{}\", \"output\": \"{}\", ",
+               string_escape(&item.input_filepath), string_escape(&item.output_filepath))?;
+        match item.error {
+            Some(ref error) => {
+                write!(f, "\"status\": \"error\", \"error\": \"{}\"", string_escape(error))?;
+            }
+            None => {
+                write!(f, "\"status\": \"ok\", \"paths\": {}, \"points\": {}",
+                       item.paths_len, item.knots_len)?;
+            }
+        }
+        writeln!(f, "}}{}", if i + 1 != batch_items.len() { "," } else { "" })?;
+    }
+    writeln!(f, "  ],")?;
+    writeln!(f, "  \"failed\": {}",
+             batch_items.iter().filter(|item| item.error.is_some()).count())?;
+    writeln!(f, "}}")?;
+    Ok(())
+}
+
+/// Load an image, converting it into a binary image ready for tracing.
+///
+/// * `mask` - when set, only pixels filled in the mask are kept.
+#[cfg(not(target_arch = "wasm32"))]
+fn image_load_for_trace(
+    trace_params: &TraceParams,
+    filepath: &String,
+    mask: Option<&(Vec<bool>, [usize; 2])>,
+) -> Result<(Vec<bool>, [usize; 2]), ::std::io::Error>
+{
+    use intern::image_load::HISTOGRAM_BINS;
+    let mut histogram = [0; HISTOGRAM_BINS];
+    let use_histogram = !trace_params.histogram_filepath.is_empty();
+    let (mut image, size, color_max) = ::intern::image_load::load_mask(
+        filepath, trace_params.input_raw.as_ref(), trace_params.threshold,
+        if use_histogram { Some(&mut histogram) } else { None },
+    )?;
+    println!("{:?} {}", size, color_max);
+    if use_histogram {
+        if trace_params.histogram_filepath == "-" {
+            let stdout = ::std::io::stdout();
+            write_histogram_text(stdout.lock(), &histogram, trace_params.threshold)?;
+        } else {
+            let f = ::std::fs::File::create(&trace_params.histogram_filepath)?;
+            write_histogram_svg(f, &histogram, trace_params.threshold)?;
+        }
+    }
+    if let Some(mask) = mask {
+        image_apply_mask(&mut image, &size, mask)?;
+    }
+    image_prepare_for_trace(trace_params, &mut image, &size);
+    if !trace_params.use_quiet && !image.contains(&true) {
+        use std::io::Write;
+        // The histogram may be written to the standard output.
+        writeln!(&mut std::io::stderr(),
+                 "Warning: {:?} has no filled pixels (using --threshold {}), the output is empty",
+                 filepath, trace_params.threshold).unwrap();
+    }
+
+    return Ok((image, size));
+}
+
+/// Load the grayscale input, for moving center-lines to the middle of strokes,
+/// see: `TraceParams::centerline_subpixel`.
+#[cfg(not(target_arch = "wasm32"))]
+fn image_load_centerline_subpixel_hooks(
+    trace_params: &TraceParams,
+    size: &[usize; 2],
+    mask: Option<&(Vec<bool>, [usize; 2])>,
+) -> Result<polys_centerline_subpixel::CenterlineSubpixelHooks, ::std::io::Error>
+{
+    use std::io::{Error, ErrorKind};
+    let (mut coverage, size_coverage) = ::intern::image_load::load_coverage(
+        &trace_params.input_filepaths[0], trace_params.input_raw.as_ref())?;
+    if size_coverage != *size {
+        return Err(Error::new(ErrorKind::InvalidData, "Image size changed while reading"));
+    }
+    // Masked pixels are cleared, as they are for tracing.
+    if let Some(&(ref mask_image, _)) = mask {
+        for (c, &is_fill) in coverage.iter_mut().zip(mask_image) {
+            if !is_fill {
+                *c = 0.0;
+            }
+        }
+    }
+    return Ok(polys_centerline_subpixel::CenterlineSubpixelHooks {
+        coverage: polys_centerline_subpixel::Coverage {
+            data: coverage,
+            size: size_coverage,
+        },
+        radius: trace_params.centerline_subpixel,
+    });
+}
+
+/// Load the binary image (without skeletonizing), for measuring the width of center-lines,
+/// see: `TraceParams::use_stroke_widths_any`.
+#[cfg(not(target_arch = "wasm32"))]
+fn image_load_stroke_width_hooks(
+    trace_params: &TraceParams,
+    size: &[usize; 2],
+    mask: Option<&(Vec<bool>, [usize; 2])>,
+) -> Result<curve_stroke_width::StrokeWidthHooks, ::std::io::Error>
+{
+    use std::io::{Error, ErrorKind};
+    let (mut image, size_image, _) = ::intern::image_load::load_mask(
+        &trace_params.input_filepaths[0], trace_params.input_raw.as_ref(),
+        trace_params.threshold, None)?;
+    if size_image != *size {
+        return Err(Error::new(ErrorKind::InvalidData, "Image size changed while reading"));
+    }
+    if let Some(mask) = mask {
+        image_apply_mask(&mut image, &size_image, mask)?;
+    }
+    image_filter_for_trace(trace_params, &mut image, &size_image);
+    return Ok(curve_stroke_width::StrokeWidthHooks {
+        image: image,
+        size: size_image,
+    });
+}
+
+/// Print the histogram as rows of bars (each row combining multiple intensity levels),
+/// marking the row containing the threshold.
+fn write_histogram_text<W: ::std::io::Write>(
+    mut f: W,
+    histogram: &[usize],
+    threshold: f64,
+) -> Result<(), ::std::io::Error>
+{
+    const ROWS: usize = 32;
+    const BAR_WIDTH: usize = 60;
+    let bins_per_row = histogram.len() / ROWS;
+    let rows: Vec<usize> = histogram.chunks(bins_per_row).map(|bins| bins.iter().sum()).collect();
+    let row_max = ::std::cmp::max(1, rows.iter().cloned().max().unwrap_or(0));
+    let threshold_bin = (threshold * (histogram.len() - 1) as f64).round() as usize;
+    for (i, count) in rows.iter().enumerate() {
+        let bar_len = (count * BAR_WIDTH + row_max - 1) / row_max;
+        writeln!(f, "{:3}-{:3} {:<width$} {}{}",
+                 i * bins_per_row, (i + 1) * bins_per_row - 1,
+                 "#".repeat(bar_len), count,
+                 if threshold_bin / bins_per_row == i { "  <- threshold" } else { "" },
+                 width = BAR_WIDTH)?;
+    }
+    Ok(())
+}
+
+/// Write the histogram as an SVG bar chart with the threshold marked (as a red line),
+/// bar heights use a square root scale so small counts remain visible.
+fn write_histogram_svg<W: ::std::io::Write>(
+    mut f: W,
+    histogram: &[usize],
+    threshold: f64,
+) -> Result<(), ::std::io::Error>
+{
+    use curve_write::Num;
+
+    const BAR_WIDTH: usize = 2;
+    const HEIGHT: f64 = 200.0;
+    let width = histogram.len() * BAR_WIDTH;
+    let count_max = ::std::cmp::max(1, histogram.iter().cloned().max().unwrap_or(0));
+    writeln!(f, "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>")?;
+    writeln!(f, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">",
+             width, HEIGHT)?;
+    writeln!(f, "  <rect width=\"{}\" height=\"{}\" fill=\"white\"/>", width, HEIGHT)?;
+    for (i, &count) in histogram.iter().enumerate() {
+        if count == 0 {
+            continue;
+        }
+        let height = HEIGHT * (count as f64 / count_max as f64).sqrt();
+        writeln!(f, "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"black\"/>",
+                 i * BAR_WIDTH, Num::exact(HEIGHT - height), BAR_WIDTH, Num::exact(height))?;
+    }
+    let x = Num::exact(threshold * width as f64);
+    writeln!(f, "  <line x1=\"{}\" y1=\"0\" x2=\"{}\" y2=\"{}\" stroke=\"red\"/>",
+             x, x, HEIGHT)?;
+    writeln!(f, "</svg>")?;
+    Ok(())
+}
+
+/// Load all frames of an image, see: `image_load_for_trace`.
+#[cfg(not(target_arch = "wasm32"))]
+fn image_load_frames_for_trace(
+    trace_params: &TraceParams,
+    filepath: &String,
+    mask: Option<&(Vec<bool>, [usize; 2])>,
+) -> Result<Vec<(Vec<bool>, [usize; 2])>, ::std::io::Error>
+{
+    let frames = ::intern::image_load::from_filepath_any_frames(filepath)?;
+    println!("Frames: {}", frames.len());
+    let mut images = Vec::with_capacity(frames.len());
+    for (size, color_max, pixel_buffer) in frames {
+        println!("{:?} {}", size, color_max);
+        let mut image = ::intern::image_load::mask_from_pixels(
+            color_max, &pixel_buffer, trace_params.threshold);
+        if let Some(mask) = mask {
+            image_apply_mask(&mut image, &size, mask)?;
         }
+        image_prepare_for_trace(trace_params, &mut image, &size);
+        images.push((image, size));
+    }
+    return Ok(images);
+}
+
+/// Clear pixels outside the mask, the mask must be the same size as the image.
+fn image_apply_mask(
+    image: &mut Vec<bool>,
+    size: &[usize; 2],
+    mask: &(Vec<bool>, [usize; 2]),
+) -> Result<(), ::std::io::Error>
+{
+    use std::io::{Error, ErrorKind};
+    let (ref mask_image, ref mask_size) = *mask;
+    if mask_size != size {
+        return Err(Error::new(ErrorKind::InvalidInput, format!(
+            "Mask size {:?} doesn't match the image size {:?}", mask_size, size,
+        )));
+    }
+    image_filter::mask(image, mask_image);
+    return Ok(());
+}
+
+/// Filter the binary image, skeletonizing for center-line tracing.
+fn image_prepare_for_trace(
+    trace_params: &TraceParams,
+    image: &mut Vec<bool>,
+    size: &[usize; 2],
+) {
+    image_filter_for_trace(trace_params, image, size);
+
+    if trace_params.mode == curve_fit_nd::TraceMode::Centerline {
+        use image_skeletonize;
+        image_skeletonize::calculate_with_threads(
+            image, size, threads_len_from_params(trace_params));
+    }
+}
+
+/// Apply the filters from `TraceParams` to the binary image (without skeletonizing).
+fn image_filter_for_trace(
+    trace_params: &TraceParams,
+    image: &mut Vec<bool>,
+    size: &[usize; 2],
+) {
+    // Hatching is removed first, since other filters would join or break up its strokes.
+    image_hatching::remove_hatching(image, size, trace_params.remove_hatching);
+
+    // Holes are filled first, so specks inside them aren't removed separately.
+    image_filter::close(image, size, trace_params.fill_holes);
+    image_filter::open(image, size, trace_params.min_feature);
+
+    if trace_params.silhouette != 0 {
+        image_filter::close(image, size, trace_params.silhouette);
+        image_filter::fill_enclosed(image, size);
     }
 }
 
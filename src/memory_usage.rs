@@ -0,0 +1,27 @@
+///
+/// Report memory used by the process.
+///
+
+/// Return the peak resident memory of this process (in bytes),
+/// `None` when unsupported on this platform.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn peak_resident_bytes() -> Option<usize> {
+    // Only supported on Linux, where this is the "high water mark" (`VmHWM`) in kilobytes.
+    let status = match ::std::fs::read_to_string("/proc/self/status") {
+        Ok(s) => s,
+        Err(_) => return None,
+    };
+    for line in status.lines() {
+        if line.starts_with("VmHWM:") {
+            let kb = line["VmHWM:".len()..].trim().trim_end_matches("kB").trim();
+            return kb.parse::<usize>().ok().map(|kb| kb * 1024);
+        }
+    }
+    return None;
+}
+
+/// WASM has no process status to read.
+#[cfg(target_arch = "wasm32")]
+pub fn peak_resident_bytes() -> Option<usize> {
+    return None;
+}
@@ -0,0 +1,293 @@
+///
+/// Boolean operations on polygons.
+///
+/// Currently only union is supported, where the filled regions of all polygons
+/// are merged using the non-zero winding rule (matching SVG's default fill-rule).
+///
+
+use std::collections::LinkedList;
+
+// Offset from edges used to sample the winding on either side.
+const SIDE_EPS: f64 = 1e-6;
+// Ignore intersections this close to an edges end-points (in edge factor).
+const SPLIT_EPS: f64 = 1e-9;
+
+struct Edge {
+    v1: [f64; 2],
+    v2: [f64; 2],
+    /// Index of the polygon this edge belongs to.
+    poly_index: usize,
+    /// Intersections along this edge `(factor, co)`.
+    splits: Vec<(f64, [f64; 2])>,
+}
+
+/// Buckets edges into horizontal bands, so winding queries
+/// only need to check edges overlapping the same Y range.
+struct EdgeBands {
+    y_min: f64,
+    band_size: f64,
+    bands: Vec<Vec<usize>>,
+}
+
+impl EdgeBands {
+    fn new(edges: &Vec<Edge>) -> EdgeBands {
+        let mut y_min = ::std::f64::MAX;
+        let mut y_max = -::std::f64::MAX;
+        for e in edges {
+            y_min = y_min.min(e.v1[1].min(e.v2[1]));
+            y_max = y_max.max(e.v1[1].max(e.v2[1]));
+        }
+        let bands_len = ((edges.len() as f64).sqrt() as usize).max(1);
+        let band_size = ((y_max - y_min) / bands_len as f64).max(::std::f64::EPSILON);
+        let mut bands: Vec<Vec<usize>> = vec![vec![]; bands_len];
+        let mut ret = EdgeBands {
+            y_min: y_min,
+            band_size: band_size,
+            bands: vec![],
+        };
+        for (i, e) in edges.iter().enumerate() {
+            let b_min = ret.band_index(e.v1[1].min(e.v2[1]), bands_len);
+            let b_max = ret.band_index(e.v1[1].max(e.v2[1]), bands_len);
+            for b in b_min..(b_max + 1) {
+                bands[b].push(i);
+            }
+        }
+        ret.bands = bands;
+        return ret;
+    }
+
+    fn band_index(&self, y: f64, bands_len: usize) -> usize {
+        let b = ((y - self.y_min) / self.band_size).floor();
+        if b <= 0.0 {
+            return 0;
+        }
+        return (b as usize).min(bands_len - 1);
+    }
+
+    /// Non-zero winding number of `p`, using a ray cast along the positive X axis.
+    fn winding(&self, edges: &Vec<Edge>, p: &[f64; 2]) -> i32 {
+        let mut w = 0;
+        if self.bands.len() == 0 {
+            return w;
+        }
+        let b = self.band_index(p[1], self.bands.len());
+        for i in &self.bands[b] {
+            let e = &edges[*i];
+            if (e.v1[1] <= p[1]) != (e.v2[1] <= p[1]) {
+                let x = e.v1[0] +
+                    ((p[1] - e.v1[1]) / (e.v2[1] - e.v1[1])) * (e.v2[0] - e.v1[0]);
+                if x > p[0] {
+                    w += if e.v2[1] > e.v1[1] { 1 } else { -1 };
+                }
+            }
+        }
+        return w;
+    }
+}
+
+/// Return the factors along both edges when they intersect.
+fn isect_seg_seg(
+    a1: &[f64; 2], a2: &[f64; 2],
+    b1: &[f64; 2], b2: &[f64; 2],
+) -> Option<(f64, f64)> {
+    let da = [a2[0] - a1[0], a2[1] - a1[1]];
+    let db = [b2[0] - b1[0], b2[1] - b1[1]];
+    let div = da[0] * db[1] - da[1] * db[0];
+    if div.abs() < ::std::f64::EPSILON {
+        // parallel (overlapping collinear edges aren't handled).
+        return None;
+    }
+    let d = [b1[0] - a1[0], b1[1] - a1[1]];
+    let t_a = (d[0] * db[1] - d[1] * db[0]) / div;
+    let t_b = (d[0] * da[1] - d[1] * da[0]) / div;
+    if t_a >= -SPLIT_EPS && t_a <= 1.0 + SPLIT_EPS &&
+       t_b >= -SPLIT_EPS && t_b <= 1.0 + SPLIT_EPS
+    {
+        return Some((t_a, t_b));
+    }
+    return None;
+}
+
+fn edges_calc_splits(
+    edges: &mut Vec<Edge>,
+) {
+    // Sweep along the X axis, only testing edges which overlap on X.
+    let mut order: Vec<usize> = (0..edges.len()).collect();
+    order.sort_by(|a, b| {
+        let a = edges[*a].v1[0].min(edges[*a].v2[0]);
+        let b = edges[*b].v1[0].min(edges[*b].v2[0]);
+        a.partial_cmp(&b).unwrap()
+    });
+
+    for i_order in 0..order.len() {
+        let i = order[i_order];
+        let (a1, a2) = (edges[i].v1, edges[i].v2);
+        let a_x_max = a1[0].max(a2[0]);
+        let a_y_min = a1[1].min(a2[1]);
+        let a_y_max = a1[1].max(a2[1]);
+        for j in &order[(i_order + 1)..] {
+            let j = *j;
+            let (b1, b2) = (edges[j].v1, edges[j].v2);
+            if b1[0].min(b2[0]) > a_x_max {
+                break;
+            }
+            if b1[1].max(b2[1]) < a_y_min ||
+               b1[1].min(b2[1]) > a_y_max
+            {
+                continue;
+            }
+            if let Some((t_a, t_b)) = isect_seg_seg(&a1, &a2, &b1, &b2) {
+                let a_is_inner = t_a > SPLIT_EPS && t_a < 1.0 - SPLIT_EPS;
+                let b_is_inner = t_b > SPLIT_EPS && t_b < 1.0 - SPLIT_EPS;
+                // Use existing vertices where possible so split edges connect exactly.
+                let co = {
+                    if !a_is_inner {
+                        if t_a < 0.5 { a1 } else { a2 }
+                    } else if !b_is_inner {
+                        if t_b < 0.5 { b1 } else { b2 }
+                    } else {
+                        [a1[0] + (a2[0] - a1[0]) * t_a,
+                         a1[1] + (a2[1] - a1[1]) * t_a]
+                    }
+                };
+                if a_is_inner {
+                    edges[i].splits.push((t_a, co));
+                }
+                if b_is_inner {
+                    edges[j].splits.push((t_b, co));
+                }
+            }
+        }
+    }
+}
+
+/// Merge all cyclic polygons using the non-zero winding rule.
+///
+/// Returns the newly created polygons,
+/// as well as a flag for each input polygon which is set
+/// when it was left unchanged (so callers may keep their own copy).
+/// Unchanged polygons are not included in the returned list,
+/// non-cyclic polygons are always considered unchanged.
+pub fn poly_list_union(
+    poly_list_src: &LinkedList<(bool, Vec<[f64; 2]>)>,
+) -> (LinkedList<(bool, Vec<[f64; 2]>)>, Vec<bool>) {
+    let mut edges: Vec<Edge> = vec![];
+    for (poly_index, &(is_cyclic, ref poly)) in poly_list_src.iter().enumerate() {
+        if !is_cyclic || poly.len() < 3 {
+            continue;
+        }
+        let mut v_prev = &poly[poly.len() - 1];
+        for v_curr in poly {
+            if v_prev != v_curr {
+                edges.push(Edge {
+                    v1: *v_prev,
+                    v2: *v_curr,
+                    poly_index: poly_index,
+                    splits: vec![],
+                });
+            }
+            v_prev = v_curr;
+        }
+    }
+
+    edges_calc_splits(&mut edges);
+
+    let bands = EdgeBands::new(&edges);
+
+    // Split edges and keep only the ones which separate filled from empty space.
+    let mut poly_is_intact: Vec<bool> = vec![true; poly_list_src.len()];
+    for e in &mut edges {
+        if e.splits.len() != 0 {
+            poly_is_intact[e.poly_index] = false;
+            e.splits.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        }
+    }
+
+    let mut edges_keep: Vec<([f64; 2], [f64; 2], usize)> = vec![];
+    for e in &edges {
+        let mut co_prev = e.v1;
+        for co_curr in e.splits.iter().map(|s| s.1).chain(Some(e.v2)) {
+            if co_prev == co_curr {
+                continue;
+            }
+            let mid = [(co_prev[0] + co_curr[0]) * 0.5, (co_prev[1] + co_curr[1]) * 0.5];
+            let no = {
+                let d = [co_curr[0] - co_prev[0], co_curr[1] - co_prev[1]];
+                let d_len = (d[0] * d[0] + d[1] * d[1]).sqrt();
+                [(-d[1] / d_len) * SIDE_EPS, (d[0] / d_len) * SIDE_EPS]
+            };
+            let w_l = bands.winding(&edges, &[mid[0] + no[0], mid[1] + no[1]]);
+            let w_r = bands.winding(&edges, &[mid[0] - no[0], mid[1] - no[1]]);
+            if (w_l != 0) != (w_r != 0) {
+                // Keep the original direction, the filled side is unchanged.
+                edges_keep.push((co_prev, co_curr, e.poly_index));
+            } else {
+                poly_is_intact[e.poly_index] = false;
+            }
+            co_prev = co_curr;
+        }
+    }
+
+    // Intact polygons are returned to the caller as-is.
+    edges_keep.retain(|e| !poly_is_intact[e.2]);
+
+    let poly_list_dst = edges_chain_into_polys(&edges_keep);
+
+    return (poly_list_dst, poly_is_intact);
+}
+
+/// Join edges into cyclic polygons,
+/// (edges must connect using exactly matching coordinates).
+fn edges_chain_into_polys(
+    edges: &Vec<([f64; 2], [f64; 2], usize)>,
+) -> LinkedList<(bool, Vec<[f64; 2]>)> {
+    use std::collections::HashMap;
+
+    fn co_key(co: &[f64; 2]) -> (u64, u64) {
+        (co[0].to_bits(), co[1].to_bits())
+    }
+
+    let mut edges_from_co: HashMap<(u64, u64), Vec<usize>> = HashMap::new();
+    for (i, e) in edges.iter().enumerate() {
+        edges_from_co.entry(co_key(&e.0)).or_insert_with(|| vec![]).push(i);
+    }
+
+    let mut edges_used = vec![false; edges.len()];
+    let mut poly_list_dst: LinkedList<(bool, Vec<[f64; 2]>)> = LinkedList::new();
+    for i_init in 0..edges.len() {
+        if edges_used[i_init] {
+            continue;
+        }
+        let co_init = co_key(&edges[i_init].0);
+        let mut poly: Vec<[f64; 2]> = vec![];
+        let mut i = i_init;
+        let mut is_closed = false;
+        loop {
+            edges_used[i] = true;
+            poly.push(edges[i].0);
+            let key_next = co_key(&edges[i].1);
+            if key_next == co_init {
+                is_closed = true;
+                break;
+            }
+            // Any unused edge will do, touching loops are still filled correctly.
+            let i_next = match edges_from_co.get(&key_next) {
+                Some(edge_indices) => {
+                    edge_indices.iter().cloned().find(|i_test| !edges_used[*i_test])
+                }
+                None => None,
+            };
+            if let Some(i_next) = i_next {
+                i = i_next;
+            } else {
+                break;
+            }
+        }
+
+        // Dangling chains may occur from precision issues, skip them.
+        if is_closed && poly.len() >= 3 {
+            poly_list_dst.push_back((true, poly));
+        }
+    }
+    return poly_list_dst;
+}
@@ -0,0 +1,162 @@
+///
+/// Move center-lines to the middle of strokes in the grayscale image (sub-pixel precision).
+///
+/// Center-lines follow the skeleton of the binary image which is on the pixel grid,
+/// for anti-aliased strokes this may be up to a pixel from the middle of the stroke.
+/// Each point is moved along the normal of the center-line to the centroid
+/// of the stroke's cross-section, where samples are weighted by their coverage.
+///
+
+const DIMS: usize = ::intern::math_vector::DIMS;
+
+use std::collections::HashMap;
+use std::collections::LinkedList;
+
+use ::intern::math_vector::{
+    len_squared_vnvn,
+    madd_vnvn_fl,
+    normalized_vnvn,
+};
+
+/// Distance between samples of each cross-section (in pixels).
+const SAMPLE_STEP: f64 = 0.25;
+
+/// Cross-sections are measured again from the centroid,
+/// since the cross-section is truncated when the center-line is far from the middle.
+const ITERATIONS: usize = 3;
+
+/// Cross-sections end at the first sample (on each side) with less coverage than this,
+/// so strokes separated by a gap aren't included.
+const COVERAGE_MIN: f64 = 1.0 / 64.0;
+
+/// The grayscale image used to measure cross-sections, see: `image_load::load_coverage`.
+pub struct Coverage {
+    /// The coverage of each pixel (one for black, zero for white).
+    pub data: Vec<f32>,
+    pub size: [usize; 2],
+}
+
+impl Coverage {
+    /// Bilinear sample, pixel centers are at integer coordinates (matching center-lines),
+    /// zero outside the image.
+    fn sample(
+        &self,
+        p: &[f64; DIMS],
+    ) -> f64 {
+        let x = p[0].floor();
+        let y = p[1].floor();
+        let (fx, fy) = (p[0] - x, p[1] - y);
+        let (x, y) = (x as isize, y as isize);
+        let pixel = |x: isize, y: isize| {
+            if x < 0 || y < 0 || x as usize >= self.size[0] || y as usize >= self.size[1] {
+                0.0
+            } else {
+                self.data[x as usize + (y as usize * self.size[0])] as f64
+            }
+        };
+        return
+            ((pixel(x, y) * (1.0 - fx)) + (pixel(x + 1, y) * fx)) * (1.0 - fy) +
+            ((pixel(x, y + 1) * (1.0 - fx)) + (pixel(x + 1, y + 1) * fx)) * fy;
+    }
+
+    /// The offset along `no` (unit length) from `p` to the centroid of the cross-section,
+    /// sampled up to `radius` on either side.
+    fn cross_section_offset(
+        &self,
+        p: &[f64; DIMS],
+        no: &[f64; DIMS],
+        radius: f64,
+    ) -> f64 {
+        let mut weight_sum = self.sample(p);
+        let mut offset_sum = 0.0;
+        for &side in &[-1.0, 1.0] {
+            let mut t = SAMPLE_STEP;
+            while t <= radius {
+                let weight = self.sample(&madd_vnvn_fl(p, no, t * side));
+                if weight < COVERAGE_MIN {
+                    break;
+                }
+                weight_sum += weight;
+                offset_sum += weight * t * side;
+                t += SAMPLE_STEP;
+            }
+        }
+        if weight_sum < COVERAGE_MIN {
+            return 0.0;
+        }
+        return offset_sum / weight_sum;
+    }
+}
+
+/// Move the points of each center-line to the middle of the stroke,
+/// measuring cross-sections up to `radius` from each point.
+///
+/// Polygons are subdivided so points are at most a pixel apart, end-points shared by
+/// multiple polygons (junctions) aren't moved, so the polygons remain connected.
+pub fn poly_list_centerline_subpixel(
+    poly_list: &mut LinkedList<(bool, Vec<[f64; DIMS]>)>,
+    coverage: &Coverage,
+    radius: f64,
+) {
+    use polys_utils;
+
+    let key = |v: &[f64; DIMS]| (v[0].to_bits(), v[1].to_bits());
+    let mut endpoint_users: HashMap<(u64, u64), usize> = HashMap::new();
+    for &(is_cyclic, ref poly) in poly_list.iter() {
+        if !is_cyclic && poly.len() != 0 {
+            *endpoint_users.entry(key(&poly[0])).or_insert(0) += 1;
+            *endpoint_users.entry(key(&poly[poly.len() - 1])).or_insert(0) += 1;
+        }
+    }
+
+    for &mut (is_cyclic, ref mut poly) in poly_list.iter_mut() {
+        if poly.len() < 2 {
+            continue;
+        }
+        let poly_src = polys_utils::poly_subdivide_to_limit(is_cyclic, poly, 1.0);
+        let points_len = poly_src.len();
+        let mut poly_dst = Vec::with_capacity(points_len);
+        for i in 0..points_len {
+            let v = &poly_src[i];
+            let (i_prev, i_next) = if is_cyclic {
+                ((i + points_len - 1) % points_len, (i + 1) % points_len)
+            } else {
+                if (i == 0 || i + 1 == points_len) && endpoint_users[&key(v)] > 1 {
+                    poly_dst.push(*v);
+                    continue;
+                }
+                (if i != 0 { i - 1 } else { 0 }, ::std::cmp::min(i + 1, points_len - 1))
+            };
+            if len_squared_vnvn(&poly_src[i_prev], &poly_src[i_next]) == 0.0 {
+                poly_dst.push(*v);
+                continue;
+            }
+            let tangent = normalized_vnvn(&poly_src[i_next], &poly_src[i_prev]);
+            let no = [-tangent[1], tangent[0]];
+            let mut offset = 0.0;
+            for _ in 0..ITERATIONS {
+                let offset_step = coverage.cross_section_offset(
+                    &madd_vnvn_fl(v, &no, offset), &no, radius);
+                // Never move further than `radius` from the center-line.
+                offset = (offset + offset_step).max(-radius).min(radius);
+            }
+            poly_dst.push(madd_vnvn_fl(v, &no, offset));
+        }
+        *poly = poly_dst;
+    }
+}
+
+/// Refine center-lines as they're extracted, see: `poly_list_centerline_subpixel`.
+pub struct CenterlineSubpixelHooks {
+    pub coverage: Coverage,
+    pub radius: f64,
+}
+
+impl ::TraceHooks for CenterlineSubpixelHooks {
+    fn extract(
+        &self,
+        poly_list: &mut LinkedList<(bool, Vec<[f64; DIMS]>)>,
+    ) {
+        poly_list_centerline_subpixel(poly_list, &self.coverage, self.radius);
+    }
+}
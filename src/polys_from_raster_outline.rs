@@ -21,12 +21,492 @@ macro_rules! elem {
 
 use std::collections::LinkedList;
 
-#[derive(Copy, Clone)]
+use image_runs::ImageRuns;
+
+/// How to resolve ambiguous vertices, where two filled pixels touch diagonally.
+///
+/// `Majority` & `Minority` count pixels in square windows of increasing size around the vertex,
+/// when all windows have equal counts the tie is resolved as `White` for `Majority`
+/// (and `Black` for `Minority`).
+/// This depends only on the surrounding pixels, so equivalent inputs give identical outlines.
+#[derive(Copy, Clone, PartialEq)]
 pub enum TurnPolicy {
     Black,
     White,
     Majority,
     Minority,
+    /// Pseudo-random choice from the vertex position and the seed,
+    /// stable for the same image and seed.
+    Random(u32),
+}
+
+/// How to store the steps along outlines which haven't been walked yet,
+/// all methods give identical outlines.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum OutlineMethod {
+    /// Store the steps from each vertex, see: `extract_outline`.
+    Steps,
+    /// Store a bit for each walked pixel edge, see: `extract_outline_sparse`.
+    Edges,
+    /// Use the filled runs of each row, see: `extract_outline_runs`.
+    Runs,
+}
+
+/// How to handle outlines of regions touching the image border.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum EdgePolicy {
+    /// Close outlines along the border.
+    Close,
+    /// Remove segments along the border, leaving open paths.
+    Open,
+    /// Move points on the border outside the image (by `EDGE_EXTEND` pixels),
+    /// so corners rounded when fitting are outside the image.
+    Extend,
+}
+
+/// Distance to move points outside the image for `EdgePolicy::Extend`.
+const EDGE_EXTEND: i32 = 2;
+
+/// Borders of the image a vertex lies on.
+mod edge {
+    pub const X_MIN: u8 = 1 << 0;
+    pub const X_MAX: u8 = 1 << 1;
+    pub const Y_MIN: u8 = 1 << 2;
+    pub const Y_MAX: u8 = 1 << 3;
+}
+
+fn vertex_edge_flags(
+    v: &[i32; DIMS],
+    size: &[usize; 2],
+) -> u8 {
+    let mut flag = 0;
+    if v[0] == 0 { flag |= edge::X_MIN; }
+    if v[0] == size[0] as i32 { flag |= edge::X_MAX; }
+    if v[1] == 0 { flag |= edge::Y_MIN; }
+    if v[1] == size[1] as i32 { flag |= edge::Y_MAX; }
+    return flag;
+}
+
+/// Add an outline to `poly_list`, handling points on the image border using `edge_policy`.
+fn poly_list_push_with_edge_policy(
+    poly_list: &mut LinkedList<(bool, Vec<[i32; DIMS]>)>,
+    mut poly: Vec<[i32; DIMS]>,
+    size: &[usize; 2],
+    edge_policy: EdgePolicy,
+) {
+    match edge_policy {
+        EdgePolicy::Close => {
+            poly_list.push_back((true, poly));
+        },
+        EdgePolicy::Open => {
+            let poly_len = poly.len();
+            let flags: Vec<u8> = poly.iter().map(|v| vertex_edge_flags(v, size)).collect();
+            // Segments from `i` to the next point, which lie on the same border.
+            let is_border: Vec<bool> = (0..poly_len).map(
+                |i| (flags[i] & flags[(i + 1) % poly_len]) != 0).collect();
+            let i_start = match is_border.iter().position(|b| *b) {
+                Some(i) => i + 1,
+                None => {
+                    poly_list.push_back((true, poly));
+                    return;
+                }
+            };
+            // Start after a border segment, so the last segment ends the last open path.
+            let mut poly_open: Vec<[i32; DIMS]> = vec![];
+            for j in 0..poly_len {
+                let i = (i_start + j) % poly_len;
+                if is_border[i] {
+                    if poly_open.len() != 0 {
+                        poly_list.push_back((false, ::std::mem::replace(&mut poly_open, vec![])));
+                    }
+                } else {
+                    if poly_open.len() == 0 {
+                        poly_open.push(poly[i]);
+                    }
+                    poly_open.push(poly[(i + 1) % poly_len]);
+                }
+            }
+            debug_assert!(poly_open.len() == 0);
+        },
+        EdgePolicy::Extend => {
+            for v in &mut poly {
+                let flag = vertex_edge_flags(v, size);
+                if (flag & edge::X_MIN) != 0 { v[0] -= EDGE_EXTEND; }
+                if (flag & edge::X_MAX) != 0 { v[0] += EDGE_EXTEND; }
+                if (flag & edge::Y_MIN) != 0 { v[1] -= EDGE_EXTEND; }
+                if (flag & edge::Y_MAX) != 0 { v[1] += EDGE_EXTEND; }
+            }
+            poly_list.push_back((true, poly));
+        },
+    }
+}
+
+/// Hash a vertex position with a seed (a 32 bit finalizer from MurmurHash3).
+fn hash_vertex(
+    x: i32,
+    y: i32,
+    seed: u32,
+) -> u32 {
+    let mut h: u32 = seed;
+    for v in &[x as u32, y as u32] {
+        h ^= v.wrapping_mul(0xcc9e2d51).rotate_left(15).wrapping_mul(0x1b873593);
+        h = h.rotate_left(13).wrapping_mul(5).wrapping_add(0xe6546b64);
+    }
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85ebca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2ae35);
+    h ^= h >> 16;
+    return h;
+}
+
+/// Directions of each step along an outline (from one vertex to the next).
+mod dir {
+    pub const L: u8 = (1 << 0);
+    pub const R: u8 = (1 << 1);
+    pub const D: u8 = (1 << 2);
+    pub const U: u8 = (1 << 3);
+}
+
+// assumed in-range
+macro_rules! xy {
+    ($x:expr, $y:expr, $x_span:expr) => {
+        $x + ($y * $x_span)
+    }
+}
+
+/// Steps along outlines from each vertex (pixel corner),
+/// which haven't been walked yet.
+trait StepMask {
+    /// Directions which can be walked from the vertex at `x`, `y`.
+    fn steps(&self, x: i32, y: i32) -> u8;
+    /// Mark the step `d` from the vertex at `x`, `y` as walked.
+    fn step_clear(&mut self, x: i32, y: i32, d: u8);
+    /// Get pixel (false outside the image), used by turn policies.
+    fn pixel_get(&self, x: i32, y: i32) -> bool;
+}
+
+/// Get pixel from an image (false outside the image).
+fn image_pixel_get(image: &[bool], size: &[i32; 2], x: i32, y: i32) -> bool {
+    if x >= 0 && x < size[0] &&
+       y >= 0 && y < size[1]
+    {
+        return image[xy!(x, y, size[0]) as usize];
+    } else {
+        return false;
+    }
+}
+
+/// Steps stored for each vertex (using a byte for each).
+struct StepImage<'a> {
+    image: &'a [bool],
+    size: [i32; 2],
+    data: Vec<u8>,
+    x_span: i32,
+}
+
+impl<'a> StepMask for StepImage<'a> {
+    fn pixel_get(&self, x: i32, y: i32) -> bool {
+        return image_pixel_get(self.image, &self.size, x, y);
+    }
+    fn steps(&self, x: i32, y: i32) -> u8 {
+        return self.data[xy!(x, y, self.x_span) as usize];
+    }
+    fn step_clear(&mut self, x: i32, y: i32, d: u8) {
+        self.data[xy!(x, y, self.x_span) as usize] &= !d;
+    }
+}
+
+/// Steps calculated from the image, only storing a bit for each walked pixel edge.
+struct StepEdges<'a> {
+    image: &'a [bool],
+    size: [i32; 2],
+    /// Walked edges, vertical edges first (from each vertex upwards),
+    /// followed by horizontal edges (from each vertex to the right).
+    walked: Vec<u32>,
+}
+
+impl<'a> StepEdges<'a> {
+    fn new(image: &'a [bool], size: &[usize; 2]) -> StepEdges<'a> {
+        let edges_len = ((size[0] + 1) * size[1]) + (size[0] * (size[1] + 1));
+        return StepEdges {
+            image: image,
+            size: [size[0] as i32, size[1] as i32],
+            walked: vec![0; (edges_len + 31) / 32],
+        };
+    }
+
+    /// The edge walked by the step `d` from the vertex at `x`, `y`.
+    fn edge_index(&self, x: i32, y: i32, d: u8) -> usize {
+        let (x_span_v, x_span_h) = (self.size[0] + 1, self.size[0]);
+        let edges_v_len = x_span_v * self.size[1];
+        return match d {
+            dir::U => xy!(x, y, x_span_v),
+            dir::D => xy!(x, y - 1, x_span_v),
+            dir::R => edges_v_len + xy!(x, y, x_span_h),
+            dir::L => edges_v_len + xy!(x - 1, y, x_span_h),
+            _ => unreachable!(),
+        } as usize;
+    }
+
+    fn is_walked(&self, x: i32, y: i32, d: u8) -> bool {
+        let i = self.edge_index(x, y, d);
+        return (self.walked[i / 32] & (1 << (i % 32))) != 0;
+    }
+}
+
+impl<'a> StepMask for StepEdges<'a> {
+    fn pixel_get(&self, x: i32, y: i32) -> bool {
+        return image_pixel_get(self.image, &self.size, x, y);
+    }
+    fn steps(&self, x: i32, y: i32) -> u8 {
+        // The pixels around the vertex, matching the steps `extract_outline` adds for each pixel.
+        let ld = self.pixel_get(x - 1, y - 1);
+        let rd = self.pixel_get(x, y - 1);
+        let lu = self.pixel_get(x - 1, y);
+        let ru = self.pixel_get(x, y);
+        let mut d: u8 = 0;
+        if ru && !lu && !self.is_walked(x, y, dir::U) {
+            d |= dir::U;
+        }
+        if ld && !rd && !self.is_walked(x, y, dir::D) {
+            d |= dir::D;
+        }
+        if lu && !ld && !self.is_walked(x, y, dir::L) {
+            d |= dir::L;
+        }
+        if rd && !ru && !self.is_walked(x, y, dir::R) {
+            d |= dir::R;
+        }
+        return d;
+    }
+    fn step_clear(&mut self, x: i32, y: i32, d: u8) {
+        let i = self.edge_index(x, y, d);
+        self.walked[i / 32] |= 1 << (i % 32);
+    }
+}
+
+/// Steps calculated from the filled runs of each row.
+///
+/// Only vertical steps (the ends of runs) store if they have been walked.
+/// Horizontal steps can only be walked again at vertices where two filled pixels touch diagonally,
+/// where each incoming direction leads to a different step,
+/// so they never need to be stored (unlike walks starting at these vertices, which go up or down).
+struct StepRuns<'a> {
+    runs: &'a ImageRuns,
+    /// For each run, `dir::U` when the step up its start has been walked,
+    /// `dir::D` when the step down its end has been walked.
+    walked: Vec<u8>,
+}
+
+impl<'a> StepRuns<'a> {
+    fn new(runs: &'a ImageRuns) -> StepRuns<'a> {
+        return StepRuns {
+            runs: runs,
+            walked: vec![0; runs.runs_len()],
+        };
+    }
+}
+
+impl<'a> StepMask for StepRuns<'a> {
+    fn pixel_get(&self, x: i32, y: i32) -> bool {
+        return self.runs.pixel_get(x, y);
+    }
+    fn steps(&self, x: i32, y: i32) -> u8 {
+        // The runs containing the pixels around the vertex.
+        let ld = self.runs.run_index(x - 1, y - 1);
+        let rd = self.runs.run_index(x, y - 1);
+        let lu = self.runs.run_index(x - 1, y);
+        let ru = self.runs.run_index(x, y);
+        let mut d: u8 = 0;
+        if let (Some(i), None) = (ru, lu) {
+            if (self.walked[i] & dir::U) == 0 {
+                d |= dir::U;
+            }
+        }
+        if let (Some(i), None) = (ld, rd) {
+            if (self.walked[i] & dir::D) == 0 {
+                d |= dir::D;
+            }
+        }
+        if lu.is_some() && ld.is_none() {
+            d |= dir::L;
+        }
+        if rd.is_some() && ru.is_none() {
+            d |= dir::R;
+        }
+        return d;
+    }
+    fn step_clear(&mut self, x: i32, y: i32, d: u8) {
+        match d {
+            dir::U => {
+                let i = self.runs.run_index(x, y).unwrap();
+                self.walked[i] |= dir::U;
+            }
+            dir::D => {
+                let i = self.runs.run_index(x - 1, y - 1).unwrap();
+                self.walked[i] |= dir::D;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Walk the outline starting at `x_init`, `y_init`,
+/// returning the polygon & the number of steps taken.
+fn poly_from_direction_mask<S: StepMask>(
+    steps: &mut S,
+    x_init: i32,
+    y_init: i32,
+    turn_policy: TurnPolicy,
+    use_simplify: bool,
+    direction_init_prev: u8,
+) -> (Vec<[i32; DIMS]>, usize) {
+    let mut poly: Vec<[i32; DIMS]> = vec![];
+    let mut x = x_init;
+    let mut y = y_init;
+    let mut d_prev: u8 = direction_init_prev;
+    let mut handled: usize = 0;
+    loop {
+        if use_simplify &&
+           (poly.len() > 1) && {
+                let xy_a = &poly[poly.len() - 2];
+                let xy_b = &poly[poly.len() - 1];
+                {
+                    ((x == xy_a[0] && x == xy_b[0]) ||
+                     (y == xy_a[1] && y == xy_b[1]))
+                }
+           }
+        {
+            let xy = poly.last_mut().unwrap();
+            xy[0] = x;
+            xy[1] = y;
+        } else {
+            poly.push({
+                let mut xy: [i32; DIMS] = [0; DIMS];
+                xy[0] = x;
+                xy[1] = y;
+                xy
+            });
+        }
+
+        if handled != 0 {
+            if x == x_init && y == y_init {
+                poly.pop(); // weak
+                break;
+            }
+        }
+
+        let (x_step, y_step) = (x, y);
+        let d = steps.steps(x, y);
+
+        macro_rules! step_move {
+            ($dir:expr) => {
+                match $dir {
+                    dir::L => { x -= 1; }
+                    dir::R => { x += 1; }
+                    dir::D => { y -= 1; }
+                    dir::U => { y += 1; }
+                    _ => { unreachable!(); }
+
+                }
+            }
+        }
+
+        // step along the first match
+        macro_rules! step_first_match {
+            // ensure we're constant so the following expression is a constant
+            ($a:expr, $b:expr, $c:expr) => {
+                {
+                    ensure_const_expr!($a, u8);
+                    ensure_const_expr!($b, u8);
+                    ensure_const_expr!($c, u8);
+
+                    if      (d & $a) != 0 { step_move!($a); $a }
+                    else if (d & $b) != 0 { step_move!($b); $b }
+                    else if (d & $c) != 0 { step_move!($c); $c }
+                    else { unreachable!(); }
+                }
+            }
+        }
+
+        // Returns None when the pixel counts are equal for all window sizes.
+        fn is_majority<S: StepMask>(
+            x: i32,
+            y: i32,
+            steps: &S,
+        ) -> Option<bool> {
+            for i in 2..5 {
+                let mut ct: i32 = 0;
+                for a in (-i + 1)..i {
+                    ct += if steps.pixel_get(x + a,     y + i - 1) { 1 } else { -1 };
+                    ct += if steps.pixel_get(x + i - 1, y + a - 1) { 1 } else { -1 };
+                    ct += if steps.pixel_get(x + a - 1, y - i)     { 1 } else { -1 };
+                    ct += if steps.pixel_get(x - i,     y + a)     { 1 } else { -1 };
+                }
+                if ct > 0 {
+                    return Some(true);
+                } else if ct < 0 {
+                    return Some(false);
+                }
+            }
+            return None;
+        }
+
+        // From the previous direction,
+        // take the nearest next step in a counter-clockwise order.
+
+        let d_next: u8 = {
+            if elem!(d, dir::L, dir::R, dir::D, dir::U) {
+                // non-ambiguous case
+                step_move!(d);
+                d
+            } else {
+                // ambiguous case
+                let turn_ccw: bool = {
+                    match turn_policy {
+                        TurnPolicy::Black => { true },
+                        TurnPolicy::White => { false },
+                        TurnPolicy::Majority => {
+                            is_majority(x, y, steps).unwrap_or(false)
+                        },
+                        TurnPolicy::Minority => {
+                            !is_majority(x, y, steps).unwrap_or(false)
+                        },
+                        TurnPolicy::Random(seed) => {
+                            (hash_vertex(x, y, seed) & 1) != 0
+                        },
+                    }
+                };
+
+                if turn_ccw == false {
+                    match d_prev {
+                        dir::L => { step_first_match!(dir::D, dir::L, dir::U) },
+                        dir::U => { step_first_match!(dir::L, dir::U, dir::R) },
+                        dir::R => { step_first_match!(dir::U, dir::R, dir::D) },
+                        dir::D => { step_first_match!(dir::R, dir::D, dir::L) },
+                        _ => { unreachable!(); }
+                    }
+                } else {
+                    match d_prev {
+                        dir::L => { step_first_match!(dir::U, dir::L, dir::D) },
+                        dir::U => { step_first_match!(dir::R, dir::U, dir::L) },
+                        dir::R => { step_first_match!(dir::D, dir::R, dir::U) },
+                        dir::D => { step_first_match!(dir::L, dir::D, dir::R) },
+                        _ => { unreachable!(); }
+                    }
+                }
+            }
+        };
+
+        // never walk this direction again
+        steps.step_clear(x_step, y_step, d_next);
+        d_prev = d_next;
+
+        handled += 1;
+    }
+
+    return (poly, handled);
 }
 
 // TODO, split into own file?
@@ -34,31 +514,18 @@ pub enum TurnPolicy {
 ///
 /// Perform the image to bitmap outline generation.
 ///
+/// * `edge_policy` - how to handle outlines touching the image border.
 /// * `use_simplify` - don't write intermediate points (one per pixel) between corners.
 pub fn extract_outline(
     image: &[bool],
     size: &[usize; 2],
     turn_policy: TurnPolicy,
+    edge_policy: EdgePolicy,
     use_simplify: bool,
 ) -> LinkedList<(bool, Vec<[i32; DIMS]>)> {
-    mod dir {
-        pub const L: u8 = (1 << 0);
-        pub const R: u8 = (1 << 1);
-        pub const D: u8 = (1 << 2);
-        pub const U: u8 = (1 << 3);
-    }
-
     let psize: [usize; 2] = [size[0] + 1, size[1] + 1];
     let mut pimage: Vec<u8> = vec![0; psize[0] * psize[1]];
 
-    // assumed in-range
-    macro_rules! xy {
-        ($x:expr, $y:expr, $x_span:expr) => {
-            $x + ($y * $x_span)
-        }
-    }
-
-
     macro_rules! xy_is_filled_l {
         ($x:expr, $y:expr) => {
             if $x != 0 {
@@ -122,191 +589,30 @@ pub fn extract_outline(
         }
     }
 
+    let mut steps = StepImage {
+        image: image,
+        size: [size[0] as i32, size[1] as i32],
+        data: pimage,
+        x_span: psize[0] as i32,
+    };
+
     let mut poly_list = LinkedList::new();
     {
-        fn poly_from_direction_mask(
-            pimage: &mut Vec<u8>,
-            x_init: i32,
-            y_init: i32,
-            x_span: i32,
-            // only needed for checking majority turning
-            image_data: &(&[bool], [i32; 2]),
-            turn_policy: TurnPolicy,
-            use_simplify: bool,
-            direction_init_prev: u8,
-        ) -> (Vec<[i32; DIMS]>, usize) {
-            let mut poly: Vec<[i32; DIMS]> = vec![];
-            let mut x = x_init;
-            let mut y = y_init;
-            let mut d_prev: u8 = direction_init_prev;
-            let mut handled: usize = 0;
-            loop {
-                if use_simplify &&
-                   (poly.len() > 1) && {
-                        let xy_a = &poly[poly.len() - 2];
-                        let xy_b = &poly[poly.len() - 1];
-                        {
-                            ((x == xy_a[0] && x == xy_b[0]) ||
-                             (y == xy_a[1] && y == xy_b[1]))
-                        }
-                   }
-                {
-                    let xy = poly.last_mut().unwrap();
-                    xy[0] = x;
-                    xy[1] = y;
-                } else {
-                    poly.push({
-                        let mut xy: [i32; DIMS] = [0; DIMS];
-                        xy[0] = x;
-                        xy[1] = y;
-                        xy
-                    });
-                }
-
-                if handled != 0 {
-                    if x == x_init && y == y_init {
-                        poly.pop(); // weak
-                        break;
-                    }
-                }
-
-                let index: usize = xy!(x, y, x_span) as usize;
-                let d = pimage[index];
-
-                macro_rules! step_move {
-                    ($dir:expr) => {
-                        match $dir {
-                            dir::L => { x -= 1; }
-                            dir::R => { x += 1; }
-                            dir::D => { y -= 1; }
-                            dir::U => { y += 1; }
-                            _ => { unreachable!(); }
-
-                        }
-                    }
-                }
-
-                // step along the first match
-                macro_rules! step_first_match {
-                    // ensure we're constant so the following expression is a constant
-                    ($a:expr, $b:expr, $c:expr) => {
-                        {
-                            ensure_const_expr!($a, u8);
-                            ensure_const_expr!($b, u8);
-                            ensure_const_expr!($c, u8);
-
-                            if      (d & $a) != 0 { step_move!($a); $a }
-                            else if (d & $b) != 0 { step_move!($b); $b }
-                            else if (d & $c) != 0 { step_move!($c); $c }
-                            else { unreachable!(); }
-                        }
-                    }
-                }
-
-                fn is_majority(
-                    x: i32,
-                    y: i32,
-                    image_data: &(&[bool], [i32; 2]),
-                ) -> bool {
-
-                    macro_rules! xy_or {
-                        ($x:expr, $y:expr, $default:expr) => {
-                            if ($x >= 0 && $x < image_data.1[0]) &&
-                               ($y >= 0 && $y < image_data.1[1])
-                            {
-                                image_data.0[xy!($x, $y, image_data.1[0]) as usize]
-                            } else {
-                                $default
-                            }
-                        }
-                    }
-
-                    for i in 2..5 {
-                        let mut ct: i32 = 0;
-                        for a in (-i + 1)..i {
-                            ct += if xy_or!(x + a,     y + i - 1, false) { 1 } else { -1 };
-                            ct += if xy_or!(x + i - 1, y + a - 1, false) { 1 } else { -1 };
-                            ct += if xy_or!(x + a - 1, y - i,     false) { 1 } else { -1 };
-                            ct += if xy_or!(x - i,     y + a,     false) { 1 } else { -1 };
-                        }
-                        if ct > 0 {
-                            return true;
-                        } else if ct < 0 {
-                            return false;
-                        }
-                    }
-                    return false;
-                }
-
-                // From the previous direction,
-                // take the nearest next step in a counter-clockwise order.
-
-                let d_next: u8 = {
-                    if elem!(d, dir::L, dir::R, dir::D, dir::U) {
-                        // non-ambiguous case
-                        step_move!(d);
-                        d
-                    } else {
-                        // ambiguous case
-                        let turn_ccw: bool = {
-                            match turn_policy {
-                                TurnPolicy::Black => { true },
-                                TurnPolicy::White => { false },
-                                TurnPolicy::Majority => {  is_majority(x, y, image_data) },
-                                TurnPolicy::Minority => { !is_majority(x, y, image_data) },
-                            }
-                        };
-
-                        if turn_ccw == false {
-                            match d_prev {
-                                dir::L => { step_first_match!(dir::D, dir::L, dir::U) },
-                                dir::U => { step_first_match!(dir::L, dir::U, dir::R) },
-                                dir::R => { step_first_match!(dir::U, dir::R, dir::D) },
-                                dir::D => { step_first_match!(dir::R, dir::D, dir::L) },
-                                _ => { unreachable!(); }
-                            }
-                        } else {
-                            match d_prev {
-                                dir::L => { step_first_match!(dir::U, dir::L, dir::D) },
-                                dir::U => { step_first_match!(dir::R, dir::U, dir::L) },
-                                dir::R => { step_first_match!(dir::D, dir::R, dir::U) },
-                                dir::D => { step_first_match!(dir::L, dir::D, dir::R) },
-                                _ => { unreachable!(); }
-                            }
-                        }
-                    }
-                };
-
-                // never walk this direction again
-                pimage[index] &= !d_next;
-                d_prev = d_next;
-
-                handled += 1;
-            }
-
-            return (poly, handled);
-        }
-
         let mut steps_handled: usize = 0;
 
-        let image_data = (image, [size[0] as i32, size[1] as i32]);
-
         'outer:
         for y in 0..psize[1] {
             for x in 0..psize[0] {
-                let index = xy!(x, y, psize[0]);
-                let d = pimage[index];
+                let d = steps.steps(x as i32, y as i32);
                 // always start searching for up, since we do clockwise search
                 if (d & dir::U) != 0 {
                     let (poly, handled) = poly_from_direction_mask(
-                        &mut pimage,
+                        &mut steps,
                         x as i32,
                         y as i32,
-                        psize[0] as i32,
-                        &image_data,
                         turn_policy,
                         use_simplify, dir::L);
-                    poly_list.push_back((true, poly));
+                    poly_list_push_with_edge_policy(&mut poly_list, poly, size, edge_policy);
                     steps_handled += handled;
 
                     if steps_total == steps_handled {
@@ -318,3 +624,67 @@ pub fn extract_outline(
     }
     return poly_list;
 }
+
+/// Perform the image to bitmap outline generation, following outlines in the image directly
+/// instead of storing the steps from each vertex.
+/// This only stores a bit for each walked pixel edge,
+/// using less memory for large images (especially when they're sparse).
+///
+/// The result is identical to `extract_outline`.
+pub fn extract_outline_sparse(
+    image: &[bool],
+    size: &[usize; 2],
+    turn_policy: TurnPolicy,
+    edge_policy: EdgePolicy,
+    use_simplify: bool,
+) -> LinkedList<(bool, Vec<[i32; DIMS]>)> {
+    let mut steps = StepEdges::new(image, size);
+
+    let mut poly_list = LinkedList::new();
+    for y in 0..(size[1] + 1) {
+        for x in 0..(size[0] + 1) {
+            // always start searching for up, since we do clockwise search
+            if (steps.steps(x as i32, y as i32) & dir::U) != 0 {
+                let (poly, _handled) = poly_from_direction_mask(
+                    &mut steps,
+                    x as i32,
+                    y as i32,
+                    turn_policy,
+                    use_simplify, dir::L);
+                poly_list_push_with_edge_policy(&mut poly_list, poly, size, edge_policy);
+            }
+        }
+    }
+    return poly_list;
+}
+
+/// Perform the image to bitmap outline generation, following outlines in the runs of each row.
+/// Outlines start at the beginning of runs, so empty space is skipped entirely.
+///
+/// The result is identical to `extract_outline`.
+pub fn extract_outline_runs(
+    runs: &ImageRuns,
+    turn_policy: TurnPolicy,
+    edge_policy: EdgePolicy,
+    use_simplify: bool,
+) -> LinkedList<(bool, Vec<[i32; DIMS]>)> {
+    let mut steps = StepRuns::new(runs);
+
+    let mut poly_list = LinkedList::new();
+    for y in 0..runs.size[1] {
+        for run in runs.row(y) {
+            let x = run[0];
+            // always start searching for up, since we do clockwise search
+            if (steps.steps(x as i32, y as i32) & dir::U) != 0 {
+                let (poly, _handled) = poly_from_direction_mask(
+                    &mut steps,
+                    x as i32,
+                    y as i32,
+                    turn_policy,
+                    use_simplify, dir::L);
+                poly_list_push_with_edge_policy(&mut poly_list, poly, &runs.size, edge_policy);
+            }
+        }
+    }
+    return poly_list;
+}
@@ -0,0 +1,163 @@
+///
+/// Bounding shapes (convex hulls & bounding boxes) of extracted polygons.
+///
+/// Shapes wind in the same direction as outer outlines,
+/// so they can be written in place of the outlines.
+///
+
+const DIMS: usize = ::intern::math_vector::DIMS;
+
+use std::collections::LinkedList;
+
+/// The bounding shape to calculate for each region.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum HullMode {
+    /// The convex hull.
+    Convex,
+    /// The bounding rectangle with the smallest area (oriented to an edge of the convex hull).
+    OrientedBox,
+    /// The bounding rectangle aligned to the image axes.
+    AlignedBox,
+}
+
+/// Twice the signed area, negative for outer outlines (with Y pointing down).
+fn poly_area_x2(
+    poly: &[[f64; DIMS]],
+) -> f64 {
+    let mut area_x2 = 0.0;
+    if let Some(v_last) = poly.last() {
+        let mut v_prev = v_last;
+        for v_curr in poly {
+            area_x2 += v_prev[0] * v_curr[1] - v_curr[0] * v_prev[1];
+            v_prev = v_curr;
+        }
+    }
+    return area_x2;
+}
+
+fn cross(
+    o: &[i32; DIMS],
+    a: &[i32; DIMS],
+    b: &[i32; DIMS],
+) -> i64 {
+    return
+        (a[0] - o[0]) as i64 * (b[1] - o[1]) as i64 -
+        (a[1] - o[1]) as i64 * (b[0] - o[0]) as i64;
+}
+
+/// Convex hull (Andrew's monotone chain), collinear points are removed.
+pub fn convex_hull(
+    points: &[[i32; DIMS]],
+) -> Vec<[i32; DIMS]> {
+    let mut points = points.to_vec();
+    points.sort();
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+
+    let mut hull: Vec<[i32; DIMS]> = Vec::with_capacity(points.len() * 2);
+    // Lower, then upper hull.
+    for pass in 0..2 {
+        let hull_len_min = hull.len() + 1;
+        for i in 0..points.len() {
+            let p = if pass == 0 { &points[i] } else { &points[points.len() - 1 - i] };
+            while hull.len() > hull_len_min &&
+                  cross(&hull[hull.len() - 2], &hull[hull.len() - 1], p) <= 0
+            {
+                hull.pop();
+            }
+            hull.push(*p);
+        }
+        // The last point is the first point of the next pass.
+        hull.pop();
+    }
+    return hull;
+}
+
+/// Return the bounding shape of `poly`.
+pub fn poly_hull(
+    poly: &[[i32; DIMS]],
+    mode: HullMode,
+) -> Vec<[f64; DIMS]> {
+    let hull: Vec<[f64; DIMS]> = convex_hull(poly).iter().map(
+        |v| [v[0] as f64, v[1] as f64]).collect();
+
+    let mut poly_dst = match mode {
+        HullMode::Convex => {
+            hull
+        },
+        HullMode::AlignedBox => {
+            let mut min = [::std::f64::MAX; DIMS];
+            let mut max = [-::std::f64::MAX; DIMS];
+            for v in &hull {
+                for j in 0..DIMS {
+                    min[j] = min[j].min(v[j]);
+                    max[j] = max[j].max(v[j]);
+                }
+            }
+            vec![[min[0], min[1]], [max[0], min[1]], [max[0], max[1]], [min[0], max[1]]]
+        },
+        HullMode::OrientedBox => {
+            if hull.len() < 3 {
+                hull
+            } else {
+                // The smallest bounding rectangle has a side on an edge of the hull,
+                // test each edge direction.
+                let mut best: Option<(f64, [f64; DIMS], [f64; DIMS], [f64; 4])> = None;
+                let mut v_prev = &hull[hull.len() - 1];
+                for v_curr in &hull {
+                    let d = [v_curr[0] - v_prev[0], v_curr[1] - v_prev[1]];
+                    let d_len = (d[0] * d[0] + d[1] * d[1]).sqrt();
+                    let u = [d[0] / d_len, d[1] / d_len];
+                    let n = [-u[1], u[0]];
+                    let mut range = [::std::f64::MAX, -::std::f64::MAX,
+                                     ::std::f64::MAX, -::std::f64::MAX];
+                    for v in &hull {
+                        let a = v[0] * u[0] + v[1] * u[1];
+                        let b = v[0] * n[0] + v[1] * n[1];
+                        range = [range[0].min(a), range[1].max(a),
+                                 range[2].min(b), range[3].max(b)];
+                    }
+                    let area = (range[1] - range[0]) * (range[3] - range[2]);
+                    if best.map_or(true, |b| area < b.0) {
+                        best = Some((area, u, n, range));
+                    }
+                    v_prev = v_curr;
+                }
+                let (_, u, n, range) = best.unwrap();
+                let corner = |a: f64, b: f64| [u[0] * a + n[0] * b, u[1] * a + n[1] * b];
+                vec![
+                    corner(range[0], range[2]),
+                    corner(range[1], range[2]),
+                    corner(range[1], range[3]),
+                    corner(range[0], range[3]),
+                ]
+            }
+        },
+    };
+
+    if poly_area_x2(&poly_dst) > 0.0 {
+        poly_dst.reverse();
+    }
+    return poly_dst;
+}
+
+/// Return the bounding shape for each region, holes are skipped.
+pub fn poly_list_hull(
+    poly_list: &LinkedList<(bool, Vec<[i32; DIMS]>)>,
+    mode: HullMode,
+) -> LinkedList<(bool, Vec<[f64; DIMS]>)> {
+    let mut poly_list_dst = LinkedList::new();
+    for &(is_cyclic, ref poly) in poly_list {
+        if is_cyclic {
+            let poly_f64: Vec<[f64; DIMS]> = poly.iter().map(
+                |v| [v[0] as f64, v[1] as f64]).collect();
+            if poly_area_x2(&poly_f64) > 0.0 {
+                continue;
+            }
+        }
+        poly_list_dst.push_back((true, poly_hull(poly, mode)));
+    }
+    return poly_list_dst;
+}
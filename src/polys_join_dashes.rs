@@ -0,0 +1,179 @@
+///
+/// Join dashed lines (extracted as center-lines) into single polygons.
+///
+/// Straight open polygons which continue in the same direction,
+/// separated by gaps below a threshold, are joined end to end.
+/// The dash pattern is returned so it can be written with the path.
+///
+
+const DIMS: usize = ::intern::math_vector::DIMS;
+
+use std::collections::LinkedList;
+
+use ::intern::math_vector::{
+    len_vnvn,
+    normalized_vn,
+    sub_vnvn,
+};
+
+/// Maximum distance (in pixels) of a dash from a straight line.
+const LINE_TOLERANCE: f64 = 1.5;
+
+/// Maximum angle (in radians) between dashes which are joined.
+const ANGLE_TOLERANCE: f64 = 10.0 * ::std::f64::consts::PI / 180.0;
+
+fn cross(a: &[f64; DIMS], b: &[f64; DIMS]) -> f64 {
+    a[0] * b[1] - a[1] * b[0]
+}
+
+fn dot(a: &[f64; DIMS], b: &[f64; DIMS]) -> f64 {
+    a[0] * b[0] + a[1] * b[1]
+}
+
+/// Distance of `p` from the (infinite) line through `l0` in direction `dir` (unit length).
+fn line_distance(
+    p: &[f64; DIMS],
+    l0: &[f64; DIMS],
+    dir: &[f64; DIMS],
+) -> f64 {
+    return cross(&sub_vnvn(p, l0), dir).abs();
+}
+
+/// When the polygon is open & straight, return its end-points.
+fn poly_as_dash(
+    is_cyclic: bool,
+    poly: &[[f64; DIMS]],
+) -> Option<([f64; DIMS], [f64; DIMS])> {
+    if is_cyclic || poly.len() < 2 {
+        return None;
+    }
+    let (p0, p1) = (poly[0], poly[poly.len() - 1]);
+    // Too short to have a reliable direction.
+    if len_vnvn(&p0, &p1) < LINE_TOLERANCE * 2.0 {
+        return None;
+    }
+    let dir = normalized_vn(&sub_vnvn(&p1, &p0));
+    if poly.iter().any(|v| line_distance(v, &p0, &dir) > LINE_TOLERANCE) {
+        return None;
+    }
+    return Some((p0, p1));
+}
+
+/// Find the dash which continues the chain from its last point,
+/// returns the index & if the dash needs to be reversed.
+fn chain_find_next(
+    dashes: &[Option<([f64; DIMS], [f64; DIMS])>],
+    used: &[bool],
+    chain_start: &[f64; DIMS],
+    chain_end: &[f64; DIMS],
+    gap: f64,
+) -> Option<(usize, bool)> {
+    let dir = normalized_vn(&sub_vnvn(chain_end, chain_start));
+    let mut best: Option<(f64, usize, bool)> = None;
+    for (i, dash) in dashes.iter().enumerate() {
+        if used[i] {
+            continue;
+        }
+        if let Some((p0, p1)) = *dash {
+            for &(a, b, is_reversed) in &[(p0, p1, false), (p1, p0, true)] {
+                let gap_len = len_vnvn(chain_end, &a);
+                if gap_len > gap || best.map_or(false, |best| gap_len >= best.0) {
+                    continue;
+                }
+                // The dash must continue past the end of the chain, along the same line.
+                let dash_dir = normalized_vn(&sub_vnvn(&b, &a));
+                if dot(&dir, &dash_dir) < ANGLE_TOLERANCE.cos() ||
+                   dot(&dir, &sub_vnvn(&a, chain_end)) < -LINE_TOLERANCE ||
+                   line_distance(&a, chain_start, &dir) > LINE_TOLERANCE ||
+                   line_distance(&b, chain_start, &dir) > LINE_TOLERANCE
+                {
+                    continue;
+                }
+                best = Some((gap_len, i, is_reversed));
+            }
+        }
+    }
+    return best.map(|best| (best.1, best.2));
+}
+
+/// Join dashes separated by gaps no larger than `gap`.
+///
+/// Returns the polygons (joined polygons take the place of their first dash)
+/// and the dash pattern `[dash, gap]` (average lengths) for each joined polygon.
+pub fn poly_list_join_dashes(
+    poly_list: LinkedList<(bool, Vec<[f64; DIMS]>)>,
+    gap: f64,
+) -> (LinkedList<(bool, Vec<[f64; DIMS]>)>, Vec<Option<[f64; 2]>>) {
+    let poly_list: Vec<(bool, Vec<[f64; DIMS]>)> = poly_list.into_iter().collect();
+    let dashes: Vec<Option<([f64; DIMS], [f64; DIMS])>> = poly_list.iter().map(
+        |&(is_cyclic, ref poly)| poly_as_dash(is_cyclic, poly)).collect();
+
+    let mut used = vec![false; poly_list.len()];
+    // Dashes (and if they're reversed) for each chain, by the index of their first dash.
+    let mut chains: Vec<Option<Vec<(usize, bool)>>> = vec![None; poly_list.len()];
+    for i in 0..poly_list.len() {
+        let (p0, p1) = match dashes[i] {
+            Some(dash) if !used[i] => dash,
+            _ => continue,
+        };
+        used[i] = true;
+        let mut chain: Vec<(usize, bool)> = vec![(i, false)];
+        let mut chain_ends = [p0, p1];
+        // Extend the chain forwards, then backwards
+        // (reversing it twice keeps the direction of the first dash).
+        for _ in 0..2 {
+            while let Some((j, is_reversed)) = chain_find_next(
+                &dashes, &used, &chain_ends[0], &chain_ends[1], gap)
+            {
+                used[j] = true;
+                chain.push((j, is_reversed));
+                let dash = dashes[j].unwrap();
+                chain_ends[1] = if is_reversed { dash.0 } else { dash.1 };
+            }
+            chain.reverse();
+            for item in &mut chain {
+                item.1 = !item.1;
+            }
+            chain_ends.swap(0, 1);
+        }
+        if chain.len() > 1 {
+            let i_first = chain.iter().map(|item| item.0).min().unwrap();
+            chains[i_first] = Some(chain);
+        } else {
+            used[i] = false;
+        }
+    }
+
+    let mut poly_list_dst: LinkedList<(bool, Vec<[f64; DIMS]>)> = LinkedList::new();
+    let mut dash_list: Vec<Option<[f64; 2]>> = vec![];
+    for i in 0..poly_list.len() {
+        if let Some(ref chain) = chains[i] {
+            let mut poly_dst: Vec<[f64; DIMS]> = vec![];
+            let mut dash_len_sum = 0.0;
+            let mut gap_len_sum = 0.0;
+            for &(j, is_reversed) in chain {
+                let poly = &poly_list[j].1;
+                if let Some(v_prev) = poly_dst.last() {
+                    let v_next = if is_reversed { &poly[poly.len() - 1] } else { &poly[0] };
+                    gap_len_sum += len_vnvn(v_prev, v_next);
+                }
+                let dash = dashes[j].unwrap();
+                dash_len_sum += len_vnvn(&dash.0, &dash.1);
+                if is_reversed {
+                    poly_dst.extend(poly.iter().rev());
+                } else {
+                    poly_dst.extend(poly.iter());
+                }
+            }
+            poly_list_dst.push_back((false, poly_dst));
+            dash_list.push(Some([
+                dash_len_sum / chain.len() as f64,
+                gap_len_sum / (chain.len() - 1) as f64,
+            ]));
+        } else if !used[i] {
+            poly_list_dst.push_back(poly_list[i].clone());
+            dash_list.push(None);
+        }
+    }
+    return (poly_list_dst, dash_list);
+}
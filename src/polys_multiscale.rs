@@ -0,0 +1,184 @@
+///
+/// Multi-scale tracing, curves are fit to a downscaled image first,
+/// then the full resolution polygons are split at the knots of the coarse curves,
+/// so each span is fit separately.
+///
+/// Fitting is slower than linear in the number of points of each polygon,
+/// so fitting many short spans is faster than fitting the whole polygon.
+/// For large smooth regions the coarse knots are close to where knots would be
+/// placed at full resolution, so the result is similar.
+///
+
+const DIMS: usize = ::intern::math_vector::DIMS;
+
+use std::collections::HashMap;
+use std::collections::LinkedList;
+
+use ::intern::math_vector::{
+    add_vnvn,
+    dot_vnvn,
+    len_squared_vnvn,
+    madd_vnvn_fl,
+    normalize_vn,
+    sub_vnvn,
+};
+
+/// Downscale the image by `factor`, where each pixel is filled when at least half
+/// of the pixels it covers are (blocks are clipped at the image bounds).
+pub fn image_downscale(
+    image: &[bool],
+    size: &[usize; 2],
+    factor: usize,
+) -> (Vec<bool>, [usize; 2]) {
+    debug_assert!(factor != 0);
+    let size_dst = [(size[0] + factor - 1) / factor, (size[1] + factor - 1) / factor];
+    let mut image_dst = vec![false; size_dst[0] * size_dst[1]];
+    for y_dst in 0..size_dst[1] {
+        let y_range = (y_dst * factor)..::std::cmp::min((y_dst + 1) * factor, size[1]);
+        for x_dst in 0..size_dst[0] {
+            let x_range = (x_dst * factor)..::std::cmp::min((x_dst + 1) * factor, size[0]);
+            let mut filled = 0;
+            for y in y_range.clone() {
+                let row = &image[(y * size[0])..((y + 1) * size[0])];
+                filled += row[x_range.clone()].iter().filter(|&&is_fill| is_fill).count();
+            }
+            image_dst[x_dst + (y_dst * size_dst[0])] =
+                filled * 2 >= x_range.len() * y_range.len();
+        }
+    }
+    return (image_dst, size_dst);
+}
+
+/// Indices of the points nearest to each knot (when within `radius`),
+/// for each polygon (sorted, without duplicates).
+///
+/// Unlike `polys_utils::poly_list_snap_anchors`, points are looked up in a grid,
+/// since there may be many knots.
+pub fn poly_list_snap_knots(
+    poly_list: &LinkedList<(bool, Vec<[f64; DIMS]>)>,
+    knots: &[[f64; DIMS]],
+    radius: f64,
+) -> Vec<Vec<usize>>
+{
+    let cell = |v: &[f64; DIMS]| ((v[0] / radius).floor() as i64, (v[1] / radius).floor() as i64);
+    let mut grid: HashMap<(i64, i64), Vec<(usize, usize)>> = HashMap::new();
+    for (i_poly, &(_is_cyclic, ref poly)) in poly_list.iter().enumerate() {
+        for (i, v) in poly.iter().enumerate() {
+            grid.entry(cell(v)).or_insert_with(Vec::new).push((i_poly, i));
+        }
+    }
+    let poly_vec: Vec<&Vec<[f64; DIMS]>> = poly_list.iter().map(|&(_, ref poly)| poly).collect();
+
+    let mut split_list: Vec<Vec<usize>> = vec![vec![]; poly_list.len()];
+    for knot in knots {
+        let (x, y) = cell(knot);
+        let mut best: Option<(f64, usize, usize)> = None;
+        for y_cell in (y - 1)..(y + 2) {
+            for x_cell in (x - 1)..(x + 2) {
+                for &(i_poly, i) in grid.get(&(x_cell, y_cell)).map_or(&[][..], |v| &v[..]) {
+                    let len_sq = len_squared_vnvn(knot, &poly_vec[i_poly][i]);
+                    if len_sq <= radius * radius && best.map_or(true, |best| len_sq < best.0) {
+                        best = Some((len_sq, i_poly, i));
+                    }
+                }
+            }
+        }
+        if let Some((_, i_poly, i)) = best {
+            split_list[i_poly].push(i);
+        }
+    }
+    for splits in &mut split_list {
+        splits.sort();
+        splits.dedup();
+    }
+    return split_list;
+}
+
+/// Split a polygon (or values for each of its points) into open spans,
+/// which start & end at `splits` (sorted indices).
+///
+/// Returns None when there aren't enough splits,
+/// two for cyclic polygons, one (besides the end-points) for open polygons.
+pub fn poly_split_spans<T: Copy>(
+    is_cyclic: bool,
+    poly: &[T],
+    splits: &[usize],
+) -> Option<Vec<Vec<T>>>
+{
+    let points_len = poly.len();
+    if is_cyclic {
+        if splits.len() < 2 {
+            return None;
+        }
+        let mut spans: Vec<Vec<T>> = splits.windows(2).map(|pair| {
+            poly[pair[0]..(pair[1] + 1)].to_vec()
+        }).collect();
+        // The last span wraps around to the first split.
+        let mut span_wrap = poly[splits[splits.len() - 1]..].to_vec();
+        span_wrap.extend_from_slice(&poly[..(splits[0] + 1)]);
+        spans.push(span_wrap);
+        return Some(spans);
+    } else {
+        let mut bounds: Vec<usize> = vec![0];
+        bounds.extend(splits.iter().filter(|&&i| i != 0 && i + 1 < points_len));
+        if bounds.len() == 1 {
+            return None;
+        }
+        bounds.push(points_len - 1);
+        return Some(bounds.windows(2).map(|pair| poly[pair[0]..(pair[1] + 1)].to_vec()).collect());
+    }
+}
+
+/// Join the curves fit to each span of a polygon (see: `poly_split_spans`),
+/// aligning the handles where spans meet unless they form a corner
+/// (turning by more than `corner_angle`).
+pub fn curve_join_spans(
+    is_cyclic: bool,
+    spans: Vec<Vec<[[f64; DIMS]; 3]>>,
+    corner_angle: f64,
+) -> Vec<[[f64; DIMS]; 3]>
+{
+    let corner_dot = corner_angle.cos();
+    let mut curve: Vec<[[f64; DIMS]; 3]> = vec![];
+    // Indices of knots where spans meet.
+    let mut joins: Vec<usize> = vec![];
+    for span in spans {
+        if span.is_empty() {
+            continue;
+        }
+        match curve.pop() {
+            Some(knot_prev) => {
+                joins.push(curve.len());
+                curve.push([knot_prev[0], knot_prev[1], span[0][2]]);
+            }
+            None => {
+                curve.push(span[0]);
+            }
+        }
+        curve.extend_from_slice(&span[1..]);
+    }
+    if is_cyclic && curve.len() > 1 {
+        // The last knot is the same point as the first.
+        let knot_last = curve.pop().unwrap();
+        curve[0][0] = knot_last[0];
+        joins.push(0);
+    }
+
+    for i in joins {
+        let knot = &mut curve[i];
+        let mut dir_prev = sub_vnvn(&knot[1], &knot[0]);
+        let mut dir_next = sub_vnvn(&knot[2], &knot[1]);
+        let len_prev = normalize_vn(&mut dir_prev);
+        let len_next = normalize_vn(&mut dir_next);
+        if len_prev == 0.0 || len_next == 0.0 || dot_vnvn(&dir_prev, &dir_next) < corner_dot {
+            continue;
+        }
+        let mut dir = add_vnvn(&dir_prev, &dir_next);
+        if normalize_vn(&mut dir) == 0.0 {
+            continue;
+        }
+        knot[0] = madd_vnvn_fl(&knot[1], &dir, -len_prev);
+        knot[2] = madd_vnvn_fl(&knot[1], &dir, len_next);
+    }
+    return curve;
+}
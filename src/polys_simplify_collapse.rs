@@ -1,3 +1,9 @@
+///
+/// Polyline decimation using quadric error metrics.
+///
+/// Edges are collapsed cheapest first, moving the remaining point
+/// to the location which best fits the edges it replaces.
+///
 
 use intern::min_heap;
 
@@ -102,7 +108,7 @@ mod quadric {
             // 3x3 matrix multiply & negate
             // (ac, bc) == (x, y).
             return Some([
-                -(m[0] * q.ac),
+                -(m[0] * q.ac + m[1] * q.bc),
                 -(m[1] * q.ac + m[2] * q.bc),
             ]);
         } else {
@@ -156,11 +162,12 @@ struct EdgeRemove {
 fn edge_heap_insert(
     poly_edit: &Vec<[f64; 2]>,
     quadrics: &Vec<quadric::Quadric>,
-    heap: &mut min_heap::MinHeap<f64, EdgeRemove>,
+    heap: &mut min_heap::MinHeap<min_heap::ValueIndex, EdgeRemove>,
     e: &Edge,
     e_handle: &mut min_heap::NodeHandle,
     i: usize,
     simplify_threshold_sq: f64,
+    use_deterministic: bool,
 ) {
     use std::f64;
 
@@ -186,7 +193,7 @@ fn edge_heap_insert(
     *e_handle = {
         if cost < simplify_threshold_sq {
             heap.insert(
-                cost,
+                min_heap::value_with_index(cost, i, use_deterministic),
                 EdgeRemove {
                     edge_index: i,
                     collapse_co: optimize_co,
@@ -201,11 +208,12 @@ fn edge_heap_insert(
 fn edge_heap_update(
     poly_edit: &Vec<[f64; 2]>,
     quadrics: &Vec<quadric::Quadric>,
-    heap: &mut min_heap::MinHeap<f64, EdgeRemove>,
+    heap: &mut min_heap::MinHeap<min_heap::ValueIndex, EdgeRemove>,
     e: &Edge,
     e_handle: &mut min_heap::NodeHandle,
     i: usize,
     simplify_threshold_sq: f64,
+    use_deterministic: bool,
 ) {
     if *e_handle != min_heap::NodeHandle::INVALID {
         heap.remove(*e_handle);
@@ -216,6 +224,7 @@ fn edge_heap_update(
         heap,
         e, e_handle, i,
         simplify_threshold_sq,
+        use_deterministic,
     );
 }
 
@@ -223,13 +232,16 @@ const INVALID_CO: [f64; 2] = [::std::f64::MAX, ::std::f64::MAX];
 
 fn edge_heap_collapse(
     poly_edit: &mut Vec<[f64; 2]>,
+    costs: &mut Option<Vec<f64>>,
     quadrics: &mut Vec<quadric::Quadric>,
-    heap: &mut min_heap::MinHeap<f64, EdgeRemove>,
+    heap: &mut min_heap::MinHeap<min_heap::ValueIndex, EdgeRemove>,
     edges: &mut Vec<Edge>,
     edges_handle: &mut Vec<min_heap::NodeHandle>,
     i: usize,
     collapse_co: &[f64; 2],
+    collapse_cost_sq: f64,
     simplify_threshold_sq: f64,
+    use_deterministic: bool,
 ) {
     let (i_prev, i_next) = {
         let e = &mut edges[i];
@@ -264,6 +276,9 @@ fn edge_heap_collapse(
 
     poly_edit[i_vert_keep] = *collapse_co;
     poly_edit[i_vert_drop] = INVALID_CO;
+    if let Some(ref mut costs) = *costs {
+        costs[i_vert_drop] = collapse_cost_sq.sqrt();
+    }
 
     // let q = quadrics[i_vert_drop];
     quadrics[i_vert_keep] = quadric::add(&quadrics[i_vert_keep], &quadrics[i_vert_drop]);
@@ -282,17 +297,60 @@ fn edge_heap_collapse(
                     heap,
                     e, &mut edges_handle[*i_other], *i_other,
                     simplify_threshold_sq,
+                    use_deterministic,
                 );
             }
         }
     }
 }
 
-pub fn poly_simplify(
+/// Options for `poly_simplify_with_options`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PolySimplify {
+    /// Only collapse edges when the resulting error is below this distance,
+    /// use `f64::INFINITY` to simplify down to `target_len`.
+    pub threshold: f64,
+    /// Stop once the polygon has this many points (zero to only use `threshold`).
+    pub target_len: usize,
+    /// Calculate the cost of removing each point, see `PolySimplifyResult::costs`.
+    pub use_costs: bool,
+    /// Collapse edges with equal cost in order of their index,
+    /// so the result doesn't depend on the order edges are added to the heap.
+    pub use_deterministic: bool,
+}
+
+pub struct PolySimplifyResult {
+    pub poly: Vec<[f64; 2]>,
+    /// The error (as a distance) at which each point in the input polygon was removed,
+    /// `f64::INFINITY` for points which remain.
+    ///
+    /// Since edges are collapsed cheapest first,
+    /// this can be used to simplify the polygon again with a stricter threshold
+    /// by keeping only points with a cost above it.
+    pub costs: Option<Vec<f64>>,
+}
+
+/// Simplify a polygon, collapsing edges until no edge can be removed
+/// without exceeding the threshold, or the polygon has `target_len` points.
+///
+/// Cyclic polygons keep at least 4 points, other polygons keep both end-points.
+pub fn poly_simplify_with_options(
     is_cyclic: bool,
     poly: &Vec<[f64; 2]>,
-    simplify_threshold: f64,
-) -> Vec<[f64; 2]> {
+    options: &PolySimplify,
+) -> PolySimplifyResult {
+    let mut costs = if options.use_costs {
+        Some(vec![::std::f64::INFINITY; poly.len()])
+    } else {
+        None
+    };
+    if poly.len() < if is_cyclic { 3 } else { 2 } {
+        return PolySimplifyResult {
+            poly: poly.clone(),
+            costs: costs,
+        };
+    }
+
     // points we're allowed to adjust
     let mut poly_edit = poly.clone();
     let mut edges: Vec<Edge> = Vec::with_capacity(poly.len()  /* is_cyclic TODO */ );
@@ -340,8 +398,9 @@ pub fn poly_simplify(
     }
 
     // Edges are setup, now collapse
-    let simplify_threshold_sq = simplify_threshold * simplify_threshold;
-    let mut heap = min_heap::MinHeap::<f64, EdgeRemove>::with_capacity(edges.len());
+    let simplify_threshold_sq = options.threshold * options.threshold;
+    let mut heap =
+        min_heap::MinHeap::<min_heap::ValueIndex, EdgeRemove>::with_capacity(edges.len());
     let mut edges_handle = vec![min_heap::NodeHandle::INVALID; edges.len()];
     for i in {
         if is_cyclic {
@@ -356,13 +415,14 @@ pub fn poly_simplify(
             &mut heap,
             &edges[i], &mut edges_handle[i], i,
             simplify_threshold_sq,
+            options.use_deterministic,
         );
     }
 
-    let poly_minimum_len = if is_cyclic { 4 } else { 2 };
+    let poly_minimum_len = ::std::cmp::max(if is_cyclic { 4 } else { 2 }, options.target_len);
     let mut poly_remaining_len = poly.len();
 
-    while let Some(r) = heap.pop_min() {
+    while let Some(((cost_sq, _), r)) = heap.pop_min_with_value() {
         // will never use again, set invalid for hygiene
         edges_handle[r.edge_index] = min_heap::NodeHandle::INVALID;
         if poly_remaining_len <= poly_minimum_len {
@@ -372,13 +432,16 @@ pub fn poly_simplify(
 
         edge_heap_collapse(
             &mut poly_edit,
+            &mut costs,
             &mut quadrics,
             &mut heap,
             &mut edges,
             &mut edges_handle,
             r.edge_index,
             &r.collapse_co,
+            cost_sq,
             simplify_threshold_sq,
+            options.use_deterministic,
         );
     }
 
@@ -395,20 +458,92 @@ pub fn poly_simplify(
     poly_edit.truncate(i_dst);
     poly_edit.shrink_to_fit();
 
-    return poly_edit;
+    return PolySimplifyResult {
+        poly: poly_edit,
+        costs: costs,
+    };
+}
+
+pub fn poly_simplify(
+    is_cyclic: bool,
+    poly: &Vec<[f64; 2]>,
+    simplify_threshold: f64,
+    use_deterministic: bool,
+) -> Vec<[f64; 2]> {
+    return poly_simplify_with_options(
+        is_cyclic, poly,
+        &PolySimplify {
+            threshold: simplify_threshold,
+            target_len: 0,
+            use_costs: false,
+            use_deterministic: use_deterministic,
+        },
+    ).poly;
+}
+
+/// Simplify a path, flattening curves (within `flatten_tolerance`) before simplifying.
+///
+/// The resulting path has straight segments, meta-data is kept.
+#[allow(dead_code)]
+pub fn path_simplify(
+    path: &::curve_types::Path,
+    flatten_tolerance: f64,
+    options: &PolySimplify,
+) -> (::curve_types::Path, Option<Vec<f64>>) {
+    let result = poly_simplify_with_options(
+        path.cyclic, &path.flatten(flatten_tolerance), options);
+    let mut path_dst = ::curve_types::Path::from_poly(path.cyclic, &result.poly);
+    path_dst.meta = path.meta.clone();
+    return (path_dst, result.costs);
 }
 
 
 use std::collections::LinkedList;
 
+/// Use a different simplify threshold for small & large polygons,
+/// so small details (punctuation in scanned text for example)
+/// aren't simplified as aggressively as large shapes.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SimplifySizeClasses {
+    /// Polygons with a bounding-box diagonal below this size are considered small.
+    pub size_cutoff: f64,
+    pub small: f64,
+    pub large: f64,
+}
+
+impl SimplifySizeClasses {
+    /// Return the simplify threshold to use for `poly`.
+    pub fn simplify_threshold_for_poly(
+        &self,
+        poly: &Vec<[f64; 2]>,
+    ) -> f64 {
+        let mut min = [::std::f64::MAX; 2];
+        let mut max = [-::std::f64::MAX; 2];
+        for v in poly {
+            for j in 0..2 {
+                min[j] = min[j].min(v[j]);
+                max[j] = max[j].max(v[j]);
+            }
+        }
+        let diagonal = if poly.len() != 0 { len(&[max[0] - min[0], max[1] - min[1]]) } else { 0.0 };
+        return if diagonal < self.size_cutoff { self.small } else { self.large };
+    }
+}
+
 pub fn poly_list_simplify(
     poly_list_src: &LinkedList<(bool, Vec<[f64; 2]>)>,
     simplify_threshold: f64,
+    size_classes: Option<SimplifySizeClasses>,
+    use_deterministic: bool,
 ) -> LinkedList<(bool, Vec<[f64; 2]>)> {
     let mut poly_list_dst: LinkedList<(bool, Vec<[f64; 2]>)> = LinkedList::new();
     for &(is_cyclic, ref poly_src) in poly_list_src {
+        let simplify_threshold = match size_classes {
+            Some(s) => s.simplify_threshold_for_poly(poly_src),
+            None => simplify_threshold,
+        };
         poly_list_dst.push_back(
-            (is_cyclic, poly_simplify(is_cyclic, poly_src, simplify_threshold)));
+            (is_cyclic, poly_simplify(is_cyclic, poly_src, simplify_threshold, use_deterministic)));
     }
     return poly_list_dst;
 }
@@ -13,6 +13,8 @@ use ::intern::math_vector::{
     mid_vnvn,
     len_squared_vnvn,
     interp_vnvn,
+    dot_vnvn,
+    normalized_vnvn,
 };
 
 // Add cyclic option (cases where all polys state is known)
@@ -150,3 +152,248 @@ pub fn poly_list_subdivide_to_limit(
     }
     return poly_list_dst;
 }
+
+// Scale the limit between these values based on how sharply the polygon turns,
+// straight runs use the largest limit, turns of 90 degrees or more the smallest.
+const ADAPTIVE_LIMIT_FAC_MIN: f64 = 0.5;
+const ADAPTIVE_LIMIT_FAC_MAX: f64 = 2.0;
+
+// Subdivide until segments are smaller then the limit,
+// scaled by the turn at either end of each segment,
+// so more points are used around sharp turns and fewer on straight runs.
+pub fn poly_subdivide_to_limit_adaptive(
+    is_cyclic: bool,
+    poly_src: &Vec<[f64; DIMS]>,
+    limit: f64,
+) -> Vec<[f64; DIMS]>
+{
+    let len = poly_src.len();
+    if len < 3 {
+        return poly_subdivide_to_limit(is_cyclic, poly_src, limit);
+    }
+
+    // Turn at each point in [0..1] (where 1 is 90 degrees or more).
+    let turn: Vec<f64> = (0..len).map(|i| {
+        if !is_cyclic && (i == 0 || i == len - 1) {
+            return 0.0;
+        }
+        let v_prev = &poly_src[(i + len - 1) % len];
+        let v_curr = &poly_src[i];
+        let v_next = &poly_src[(i + 1) % len];
+        let d = dot_vnvn(&normalized_vnvn(v_curr, v_prev), &normalized_vnvn(v_next, v_curr));
+        // Coincident points normalize to zero, also treated as straight.
+        if !d.is_finite() {
+            return 0.0;
+        }
+        return (1.0 - d.max(0.0)).min(1.0);
+    }).collect();
+
+    let mut poly_dst: Vec<[f64; DIMS]> = Vec::with_capacity(len);
+
+    let mut i_prev = if is_cyclic { len - 1 } else { 0 };
+    if !is_cyclic {
+        poly_dst.push(poly_src[i_prev]);
+    }
+
+    for i_curr in (if is_cyclic { 0 } else { 1 })..len {
+        let v_orig_prev = &poly_src[i_prev];
+        let v_orig_curr = &poly_src[i_curr];
+        let t = turn[i_prev].max(turn[i_curr]);
+        let limit_local = limit * (
+            ADAPTIVE_LIMIT_FAC_MAX * (1.0 - t) + ADAPTIVE_LIMIT_FAC_MIN * t);
+
+        // subdivided point(s)
+        let len_sq = len_squared_vnvn(v_orig_prev, v_orig_curr);
+        if len_sq > sq(limit_local) {
+            let sub = (len_sq.sqrt() / limit_local).floor();
+            let inc = 1.0 / sub;
+            let mut step = inc;
+            for _ in 0..((sub as usize) - 1) {
+                poly_dst.push(interp_vnvn(v_orig_prev, v_orig_curr, step));
+                step += inc;
+            }
+        }
+        // regular point
+        poly_dst.push(*v_orig_curr);
+        i_prev = i_curr;
+    }
+
+    return poly_dst;
+}
+
+pub fn poly_list_subdivide_to_limit_adaptive(
+    poly_list_src: &LinkedList<(bool, Vec<[f64; DIMS]>)>, limit: f64,
+) -> LinkedList<(bool, Vec<[f64; DIMS]>)>
+{
+    let mut poly_list_dst: LinkedList<(bool, Vec<[f64; DIMS]>)> = LinkedList::new();
+    for &(is_cyclic, ref poly_src) in poly_list_src {
+        poly_list_dst.push_back(
+            (is_cyclic, poly_subdivide_to_limit_adaptive(is_cyclic, poly_src, limit)));
+    }
+    return poly_list_dst;
+}
+
+// Transform points in-place
+pub fn poly_list_transform<F>(
+    poly_list: &mut LinkedList<(bool, Vec<[f64; DIMS]>)>, f: F,
+) where F: Fn(&[f64; DIMS]) -> [f64; DIMS]
+{
+    for &mut (_is_cyclic, ref mut poly) in poly_list {
+        for v in poly {
+            *v = f(v);
+        }
+    }
+}
+
+// Weights for each point, `weight` where the polygon turns by more than `angle` (in radians),
+// otherwise one.
+pub fn poly_corner_weights(
+    is_cyclic: bool, poly: &Vec<[f64; DIMS]>, angle: f64, weight: f64,
+) -> Vec<f64>
+{
+    let angle_cos = angle.cos();
+    let len = poly.len();
+    return (0..len).map(|i| {
+        if !is_cyclic && (i == 0 || i + 1 == len) {
+            return 1.0;
+        }
+        let v_prev = &poly[(i + len - 1) % len];
+        let v_next = &poly[(i + 1) % len];
+        // Zero length edges give NaN, which isn't a corner.
+        let dot = dot_vnvn(&normalized_vnvn(&poly[i], v_prev), &normalized_vnvn(v_next, &poly[i]));
+        if dot < angle_cos { weight } else { 1.0 }
+    }).collect();
+}
+
+// Indices of the points nearest to each anchor, for each polygon (sorted, without duplicates).
+pub fn poly_list_snap_anchors(
+    poly_list: &LinkedList<(bool, Vec<[f64; DIMS]>)>, anchors: &[[f64; DIMS]],
+) -> Vec<Vec<usize>>
+{
+    let mut anchor_list: Vec<Vec<usize>> = vec![vec![]; poly_list.len()];
+    for anchor in anchors {
+        let mut best: Option<(f64, usize, usize)> = None;
+        for (i_poly, &(_is_cyclic, ref poly)) in poly_list.iter().enumerate() {
+            for (i, v) in poly.iter().enumerate() {
+                let len_sq = len_squared_vnvn(anchor, v);
+                if best.map_or(true, |best| len_sq < best.0) {
+                    best = Some((len_sq, i_poly, i));
+                }
+            }
+        }
+        if let Some((_, i_poly, i)) = best {
+            anchor_list[i_poly].push(i);
+        }
+    }
+    for anchors_poly in &mut anchor_list {
+        anchors_poly.sort();
+        anchors_poly.dedup();
+    }
+    return anchor_list;
+}
+
+/// A hash of the polygon which is stable between runs & platforms,
+/// cyclic polygons are hashed from their lowest point (then left-most),
+/// so the hash doesn't depend on where the polygon starts.
+pub fn poly_hash(
+    is_cyclic: bool,
+    poly: &[[f64; DIMS]],
+) -> u64
+{
+    use extract_cache::{
+        hash_fnv1a,
+        FNV_OFFSET,
+    };
+
+    let start = if is_cyclic {
+        (0..poly.len()).fold(0, |i_best, i| {
+            let (a, b) = (&poly[i], &poly[i_best]);
+            if (a[1], a[0]) < (b[1], b[0]) { i } else { i_best }
+        })
+    } else {
+        0
+    };
+    let mut hash = hash_fnv1a(FNV_OFFSET, &[is_cyclic as u8]);
+    for v in poly[start..].iter().chain(&poly[..start]) {
+        hash = hash_fnv1a(hash, &v[0].to_bits().to_le_bytes());
+        hash = hash_fnv1a(hash, &v[1].to_bits().to_le_bytes());
+    }
+    return hash;
+}
+
+/// The distance from each point of `poly_list_src` to the matching polygon in `poly_list_dst`
+/// (its nearest edge), for measuring how closely curves follow the polygons they're fit to.
+///
+/// Edges are looked up in a grid with cells of `radius`,
+/// points further than `radius` from every edge are measured against all edges.
+pub fn poly_list_distances(
+    poly_list_src: &LinkedList<(bool, Vec<[f64; DIMS]>)>,
+    poly_list_dst: &LinkedList<(bool, Vec<[f64; DIMS]>)>,
+    radius: f64,
+) -> Vec<f64>
+{
+    use std::collections::HashMap;
+    use ::intern::math_vector::sub_vnvn;
+
+    fn dist_squared_to_segment(
+        p: &[f64; DIMS], l1: &[f64; DIMS], l2: &[f64; DIMS],
+    ) -> f64 {
+        let u = sub_vnvn(l2, l1);
+        let len_sq = dot_vnvn(&u, &u);
+        let lambda = if len_sq != 0.0 {
+            (dot_vnvn(&u, &sub_vnvn(p, l1)) / len_sq).max(0.0).min(1.0)
+        } else {
+            0.0
+        };
+        return len_squared_vnvn(p, &interp_vnvn(l1, l2, lambda));
+    }
+
+    let cell = |v: &[f64; DIMS]| ((v[0] / radius).floor() as i64, (v[1] / radius).floor() as i64);
+    let mut distances: Vec<f64> = vec![];
+    for (&(_, ref poly_src), &(is_cyclic, ref poly_dst)) in
+        poly_list_src.iter().zip(poly_list_dst)
+    {
+        if poly_dst.is_empty() {
+            continue;
+        }
+        let poly_dst = poly_subdivide_to_limit(is_cyclic, poly_dst, radius);
+        let edges: Vec<(usize, usize)> = if poly_dst.len() == 1 {
+            vec![(0, 0)]
+        } else if is_cyclic {
+            (0..poly_dst.len()).map(|i| (i, (i + 1) % poly_dst.len())).collect()
+        } else {
+            (1..poly_dst.len()).map(|i| (i - 1, i)).collect()
+        };
+        let mut grid: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+        for (i_edge, &(i1, i2)) in edges.iter().enumerate() {
+            let (c1, c2) = (cell(&poly_dst[i1]), cell(&poly_dst[i2]));
+            for y in c1.1.min(c2.1)..(c1.1.max(c2.1) + 1) {
+                for x in c1.0.min(c2.0)..(c1.0.max(c2.0) + 1) {
+                    grid.entry((x, y)).or_insert_with(Vec::new).push(i_edge);
+                }
+            }
+        }
+        let edge_dist_sq = |v: &[f64; DIMS], i_edge: usize| {
+            let (i1, i2) = edges[i_edge];
+            dist_squared_to_segment(v, &poly_dst[i1], &poly_dst[i2])
+        };
+        for v in poly_src {
+            let (x, y) = cell(v);
+            let mut best_sq = ::std::f64::MAX;
+            for y_cell in (y - 1)..(y + 2) {
+                for x_cell in (x - 1)..(x + 2) {
+                    for &i_edge in grid.get(&(x_cell, y_cell)).map_or(&[][..], |v| &v[..]) {
+                        best_sq = best_sq.min(edge_dist_sq(v, i_edge));
+                    }
+                }
+            }
+            if best_sq > sq(radius) {
+                best_sq = (0..edges.len()).fold(best_sq, |best_sq, i_edge| {
+                    best_sq.min(edge_dist_sq(v, i_edge))
+                });
+            }
+            distances.push(best_sq.sqrt());
+        }
+    }
+    return distances;
+}
@@ -0,0 +1,412 @@
+///
+/// Trace images for commands read as lines of JSON (see: `TraceParams::use_serve`)
+/// or messages received on a socket (see: `TraceParams::listen_filepath`).
+///
+/// Each command is an object, where "input" is the image to trace & "output" the file to write,
+/// other members are command line options (without the leading dashes, besides "help")
+/// which replace the options the server was started with, for example:
+///
+///   {"input": "in.png", "output": "out.svg", "error": 0.8, "mode": "CENTER"}
+///
+/// Strings & numbers are passed as the option value, true passes an option without a value,
+/// arrays pass multiple values, false & null are ignored.
+///
+/// The result of each command is written as a line of JSON (in order), either
+/// `{"status": "ok", ...}` or `{"status": "error", "error": "..."}`,
+/// when the output is omitted (or "-") the result contains the document instead,
+/// or the curves when the command has `"curves": true`, see: `curve_write::json::write_drawing`.
+/// Statistics aren't printed while serving, so the output only contains results.
+///
+/// Socket messages (commands & results) are the JSON text,
+/// prefixed by its length in bytes (4 bytes, unsigned, little endian).
+///
+/// The last image & the polygons extracted from it are kept, so commands for the same image
+/// which only change fitting parameters (error, corner angle... etc) only fit the curves again.
+///
+
+use std::io::prelude::*;
+use std::io::{
+    Error,
+    ErrorKind,
+};
+use std::time::SystemTime;
+
+use curve_write::json::string_escape;
+use intern::image_load::RawFormat;
+use intern::json;
+use {
+    TraceOutput,
+    TraceParams,
+    Tracer,
+};
+
+/// Socket messages larger than this are an error (so a bad length isn't allocated).
+const MESSAGE_BYTES_MAX: usize = 64 * 1024 * 1024;
+
+/// Identifies the image loaded for a command, so it can be reused by the next command.
+#[derive(PartialEq)]
+struct ImageKey {
+    input_filepath: String,
+    /// So changes to the file on disk are loaded.
+    input_modified: Option<SystemTime>,
+    input_raw: Option<RawFormat>,
+    threshold: f64,
+    mask_filepath: String,
+    mask_modified: Option<SystemTime>,
+}
+
+impl ImageKey {
+    fn new(
+        trace_params: &TraceParams,
+    ) -> ImageKey {
+        let modified = |filepath: &str| {
+            ::std::fs::metadata(filepath).and_then(|metadata| metadata.modified()).ok()
+        };
+        let input_filepath = &trace_params.input_filepaths[0];
+        return ImageKey {
+            input_filepath: input_filepath.clone(),
+            input_modified: modified(input_filepath),
+            input_raw: trace_params.input_raw,
+            threshold: trace_params.threshold,
+            mask_filepath: trace_params.mask_filepath.clone(),
+            mask_modified: if trace_params.mask_filepath.is_empty() {
+                None
+            } else {
+                modified(&trace_params.mask_filepath)
+            },
+        };
+    }
+}
+
+/// Return an error for parameters which can't be used by commands.
+pub fn trace_params_error(
+    trace_params: &TraceParams,
+) -> Option<&'static str> {
+    if trace_params.use_batch || trace_params.montage_columns != 0 ||
+       trace_params.frames.is_some() || !trace_params.diff_filepath.is_empty() ||
+       !trace_params.lod.is_empty() || !trace_params.histogram_filepath.is_empty()
+    {
+        return Some(concat!("--serve can't be used with --batch, --montage, --frames, --diff, ",
+                            "--lod or --histogram"));
+    }
+    // The tracer only collects the debug passes of a single output.
+    if !trace_params.outputs_extra.is_empty() {
+        return Some("Multiple --output can't be used with --serve");
+    }
+    // The standard input is used for commands.
+    if trace_params.input_filepaths.iter().any(|filepath| filepath == "-") {
+        return Some("--serve can't read input from the standard input");
+    }
+    return None;
+}
+
+/// Convert a command into command line arguments (an omitted output is passed as "-"),
+/// returns the arguments & true when the result contains the curves.
+fn command_args(
+    text: &str,
+) -> Result<(Vec<String>, bool), String> {
+    // Otherwise the error is for the first value which doesn't match (`null` for example).
+    if !text.trim_start().starts_with('{') {
+        return Err("Expected a JSON object".to_string());
+    }
+    let members = match json::from_str(text)? {
+        json::Value::Object(members) => members,
+        _ => {
+            return Err("Expected a JSON object".to_string());
+        }
+    };
+    let mut args: Vec<String> = vec![];
+    let mut use_output = false;
+    let mut use_curves = false;
+    for (key, value) in members {
+        // Help is printed to the standard output, which would corrupt the results.
+        if key == "help" {
+            return Err("'help' can't be used in commands".to_string());
+        }
+        // Not a command line option.
+        if key == "curves" {
+            use_curves = value == json::Value::Bool(true);
+            continue;
+        }
+        let values = match value {
+            json::Value::Null | json::Value::Bool(false) => {
+                continue;
+            }
+            json::Value::Bool(true) => vec![],
+            json::Value::Array(items) => items,
+            value => vec![value],
+        };
+        use_output |= key == "output";
+        args.push(format!("--{}", key));
+        for value in values {
+            args.push(match value {
+                json::Value::String(text) => text,
+                json::Value::Number(v) => v.to_string(),
+                _ => {
+                    return Err(format!("Expected strings or numbers for '{}'", key));
+                }
+            });
+        }
+    }
+    if !use_output {
+        args.push("--output".to_string());
+        args.push("-".to_string());
+    }
+    return Ok((args, use_curves));
+}
+
+/// Runs commands, keeping the last image loaded, see: module docs.
+pub struct Server<F> {
+    /// The parameters commands start with.
+    trace_params: TraceParams,
+    /// Parses command line arguments into parameters.
+    args_parse: F,
+    tracer: Option<(ImageKey, Tracer)>,
+}
+
+impl<F> Server<F> where F: Fn(&mut TraceParams, &[String]) -> Result<(), String> {
+    pub fn new(
+        trace_params: &TraceParams,
+        args_parse: F,
+    ) -> Server<F> {
+        return Server {
+            trace_params: trace_params.clone(),
+            args_parse: args_parse,
+            tracer: None,
+        };
+    }
+
+    /// Run a command, returning the result (a JSON object on a single line).
+    pub fn command(
+        &mut self,
+        text: &str,
+    ) -> String {
+        match self.command_or_error(text) {
+            Ok(result) => result,
+            Err(e) => format!("{{\"status\": \"error\", \"error\": \"{}\"}}", string_escape(&e)),
+        }
+    }
+
+    fn command_or_error(
+        &mut self,
+        text: &str,
+    ) -> Result<String, String> {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let (args, use_curves) = command_args(text)?;
+        // Parsing arguments shouldn't stop the server either, see below.
+        let trace_params = catch_unwind(AssertUnwindSafe(|| {
+            let mut trace_params = self.trace_params.clone();
+            (self.args_parse)(&mut trace_params, &args).map(|_| trace_params)
+        })).unwrap_or_else(|panic| {
+            Err(format!("Panic: {}", ::panic_message(&*panic)))
+        })?;
+        if let Some(error) = trace_params_error(&trace_params).or_else(
+            || ::trace_params_error(&trace_params))
+        {
+            return Err(error.to_string());
+        }
+
+        // A pathological input shouldn't stop the server.
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            self.trace(&trace_params)
+        })).unwrap_or_else(|panic| {
+            Err(format!("Panic: {}", ::panic_message(&*panic)))
+        });
+        if result.is_err() {
+            self.tracer = None;
+        }
+        let (trace_params, size, output) = result?;
+
+        let mut result = format!(
+            "{{\"status\": \"ok\", \"paths\": {}, \"points\": {}",
+            output.drawing.paths.len(), output.drawing.knots_len()).into_bytes();
+        if trace_params.output_filepath != "-" {
+            write!(result, ", \"output\": \"{}\"", string_escape(&trace_params.output_filepath))
+        } else if use_curves {
+            write!(result, ", \"curves\": ").and_then(|_| {
+                ::curve_write::json::write_drawing(
+                    &mut result, &size, trace_params.output_scale,
+                    &output.drawing, &output.dots, output.dot_radius)
+            })
+        } else {
+            let mut data: Vec<u8> = vec![];
+            ::write_output_to(&mut data, &trace_params, &size, &output).map_err(
+                |e| e.to_string())?;
+            let document = String::from_utf8(data).map_err(
+                |_| "Binary output requires an output file".to_string())?;
+            write!(result, ", \"document\": \"{}\"", string_escape(&document))
+        }.map_err(|e| e.to_string())?;
+        result.push(b'}');
+        return Ok(String::from_utf8(result).unwrap());
+    }
+
+    /// Trace & write the output (unless it's "-"),
+    /// returning the parameters used (with the scale to fit), the image size & the output.
+    fn trace(
+        &mut self,
+        trace_params: &TraceParams,
+    ) -> Result<(TraceParams, [usize; 2], TraceOutput), String> {
+        let image_key = ImageKey::new(trace_params);
+        let is_loaded = match self.tracer {
+            Some((ref image_key_loaded, _)) => *image_key_loaded == image_key,
+            None => false,
+        };
+        if !is_loaded {
+            // Free the previous image first.
+            self.tracer = None;
+            let (image, size) = image_load(trace_params).map_err(|e| e.to_string())?;
+            self.tracer = Some((image_key, Tracer::new(trace_params, image, &size)));
+        }
+        let tracer = &mut self.tracer.as_mut().unwrap().1;
+        let size = tracer.size;
+
+        let mut trace_params = trace_params.clone();
+        if let Some(fit_size) = trace_params.fit_size {
+            trace_params.output_scale = ::output_scale_to_fit(&fit_size, &size);
+        }
+        tracer.set_trace_params(&trace_params);
+        let output = tracer.trace();
+
+        ::write_output_extras(&trace_params, &size, &output).map_err(|e| e.to_string())?;
+        if trace_params.output_filepath != "-" {
+            ::write_output(&trace_params, &size, &output).map_err(|e| e.to_string())?;
+        }
+        // Isolated pixels are removed from the image when they're written as dots.
+        if trace_params.use_fail_empty &&
+           !tracer.extract.image.contains(&true) && tracer.extract.dots.is_empty()
+        {
+            return Err("No filled pixels".to_string());
+        }
+        return Ok((trace_params, size, output));
+    }
+}
+
+/// Load the image (before filtering, see: `Tracer`), applying the mask.
+fn image_load(
+    trace_params: &TraceParams,
+) -> Result<(Vec<bool>, [usize; 2]), Error> {
+    let (mut image, size, _color_max) = ::intern::image_load::load_mask(
+        &trace_params.input_filepaths[0], trace_params.input_raw.as_ref(),
+        trace_params.threshold, None,
+    )?;
+    if !trace_params.mask_filepath.is_empty() {
+        let mask = ::intern::image_load::load_mask(&trace_params.mask_filepath, None, 0.5, None)?;
+        ::image_apply_mask(&mut image, &size, &(mask.0, mask.1))?;
+    }
+    return Ok((image, size));
+}
+
+/// Run commands read from `input` (a line each) until the input ends,
+/// writing the result of each to `output`, see: `Server::command`.
+pub fn serve<F, R: BufRead, W: Write>(
+    server: &mut Server<F>,
+    input: R,
+    mut output: W,
+) -> Result<(), Error> where F: Fn(&mut TraceParams, &[String]) -> Result<(), String> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        writeln!(output, "{}", server.command(&line))?;
+        // Results are read as they're written.
+        output.flush()?;
+    }
+    Ok(())
+}
+
+/// Read a socket message, returns None at the end of input (between messages).
+fn message_read<R: Read>(
+    mut f: R,
+) -> Result<Option<String>, Error> {
+    let mut len_bytes: [u8; 4] = [0; 4];
+    match f.read_exact(&mut len_bytes) {
+        Ok(()) => {}
+        Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => {
+            return Ok(None);
+        }
+        Err(e) => {
+            return Err(e);
+        }
+    }
+    let len = u32::from_le_bytes(len_bytes) as usize;
+    if len > MESSAGE_BYTES_MAX {
+        return Err(Error::new(ErrorKind::InvalidData, "Message too large"));
+    }
+    let mut data: Vec<u8> = vec![0; len];
+    f.read_exact(&mut data)?;
+    return String::from_utf8(data).map(Some).map_err(
+        |e| Error::new(ErrorKind::InvalidData, e));
+}
+
+fn message_write<W: Write>(
+    mut f: W,
+    text: &str,
+) -> Result<(), Error> {
+    f.write_all(&(text.len() as u32).to_le_bytes())?;
+    f.write_all(text.as_bytes())?;
+    return f.flush();
+}
+
+/// Run commands read from `f` (as socket messages) until the input ends,
+/// writing the result of each, see: `Server::command`.
+pub fn serve_messages<F, S: Read + Write>(
+    server: &::std::sync::Mutex<Server<F>>,
+    mut f: S,
+) -> Result<(), Error> where F: Fn(&mut TraceParams, &[String]) -> Result<(), String> {
+    while let Some(text) = message_read(&mut f)? {
+        // Commands don't leave the server in an invalid state when they panic
+        // (see: `Server::command`), so keep serving other connections.
+        let result = server.lock().unwrap_or_else(|e| e.into_inner()).command(&text);
+        message_write(&mut f, &result)?;
+    }
+    Ok(())
+}
+
+/// Accept connections on a Unix domain socket at `filepath`, running the commands of each
+/// (connections are handled in parallel, commands one at a time), never returns on success.
+#[cfg(unix)]
+pub fn listen<F>(
+    server: Server<F>,
+    filepath: &str,
+) -> Result<(), Error>
+    where F: 'static + Send + Fn(&mut TraceParams, &[String]) -> Result<(), String>
+{
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixListener;
+    use std::sync::{
+        Arc,
+        Mutex,
+    };
+
+    // Remove the socket left by a previous run (never other kinds of files).
+    if let Ok(metadata) = ::std::fs::symlink_metadata(filepath) {
+        if metadata.file_type().is_socket() {
+            ::std::fs::remove_file(filepath)?;
+        }
+    }
+    let listener = UnixListener::bind(filepath)?;
+    let server = Arc::new(Mutex::new(server));
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let server = server.clone();
+        ::std::thread::spawn(move || {
+            // Errors only end this connection.
+            if let Err(e) = serve_messages(&server, stream) {
+                println!("Error reading commands {:?}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn listen<F>(
+    _server: Server<F>,
+    _filepath: &str,
+) -> Result<(), Error>
+    where F: 'static + Send + Fn(&mut TraceParams, &[String]) -> Result<(), String>
+{
+    return Err(Error::new(ErrorKind::Other, "Unix domain sockets aren't supported"));
+}
@@ -9,14 +9,16 @@ macro_rules! test_image {
             static IMAGE: &'static [bool] = $image;
             let size = $size;
             debug_assert!(IMAGE.len() == (size[0] * size[1]));
-            match ::trace_image(
-                &String::from(concat!(stringify!($id), ".svg")),
-                1.0, IMAGE, &size, $error, $length, $corner_angle, false,
-                0.75,
-                curve_fit_nd::TraceMode::Outline,
-                polys_from_raster_outline::TurnPolicy::Majority,
-                0, 1.0,
-            ) {
+            let trace_params = ::TraceParams {
+                output_filepath: String::from(concat!(stringify!($id), ".svg")),
+                error_threshold: $error,
+                simplify_threshold: $length,
+                corner_threshold: $corner_angle,
+                mode: curve_fit_nd::TraceMode::Outline,
+                turn_policy: polys_from_raster_outline::TurnPolicy::Majority,
+                .. ::TraceParams::default()
+            };
+            match ::trace_image(&trace_params, IMAGE, &size, 0.75) {
                 Ok(_) => (),
                 Err(e) => println!("Error {:?}", e),
             }
@@ -39,3 +41,3689 @@ test_image!(
     false, false, true,  true,  false, false, false, true,  true,  false,
     ]);
 
+
+#[test]
+fn test_poly_list_union() {
+    use std::collections::LinkedList;
+    use polys_boolean;
+
+    // Two overlapping squares and one separate square.
+    let mut poly_list: LinkedList<(bool, Vec<[f64; 2]>)> = LinkedList::new();
+    poly_list.push_back((true, vec![[0.0, 0.0], [2.0, 0.0], [2.0, 2.0], [0.0, 2.0]]));
+    poly_list.push_back((true, vec![[1.0, 1.0], [3.0, 1.0], [3.0, 3.0], [1.0, 3.0]]));
+    poly_list.push_back((true, vec![[5.0, 5.0], [6.0, 5.0], [6.0, 6.0], [5.0, 6.0]]));
+
+    let (poly_list_merged, poly_is_intact) = polys_boolean::poly_list_union(&poly_list);
+    assert!(poly_is_intact == vec![false, false, true]);
+    assert!(poly_list_merged.len() == 1);
+    let poly = &poly_list_merged.front().unwrap().1;
+    assert!(poly.len() == 8);
+    assert!(!poly.contains(&[1.0, 1.0]));
+    assert!(!poly.contains(&[2.0, 2.0]));
+}
+
+#[test]
+fn test_path_geometry() {
+    use curve_types::{
+        CubicSegment,
+        Path,
+    };
+
+    // Square with straight handles (a third along each side).
+    let co = [[0.0, 0.0], [3.0, 0.0], [3.0, 3.0], [0.0, 3.0]];
+    let mut cubic_array = vec![];
+    for i in 0..co.len() {
+        let v_prev = co[(i + co.len() - 1) % co.len()];
+        let v_curr = co[i];
+        let v_next = co[(i + 1) % co.len()];
+        cubic_array.push([
+            [v_curr[0] + (v_prev[0] - v_curr[0]) / 3.0, v_curr[1] + (v_prev[1] - v_curr[1]) / 3.0],
+            v_curr,
+            [v_curr[0] + (v_next[0] - v_curr[0]) / 3.0, v_curr[1] + (v_next[1] - v_curr[1]) / 3.0],
+        ]);
+    }
+    let mut path = Path::from_cubic_array(true, &cubic_array);
+    assert!(path.segments_len() == 4);
+    assert!((path.length() - 12.0).abs() < 1e-9);
+    assert!(path.bounds() == ([0.0, 0.0], [3.0, 3.0]));
+    assert!(path.flatten(0.01).len() == 4);
+
+    // Evaluating by arc length, the handles are a third along each side so speed is constant.
+    let near = |a: [f64; 2], b: [f64; 2]| (a[0] - b[0]).abs() < 1e-6 && (a[1] - b[1]).abs() < 1e-6;
+    assert!(near(path.eval(0.0), [0.0, 0.0]));
+    assert!(near(path.eval(0.125), [1.5, 0.0]));
+    assert!(near(path.eval_at_length(7.5), [1.5, 3.0]));
+    assert!(near(path.eval(1.0), [0.0, 0.0]));
+    assert!(near(path.eval_at_length(100.0), [0.0, 0.0]));
+
+    // A curve with uneven speed, the parameter is the inverse of the length.
+    let seg = CubicSegment { p0: [0.0, 0.0], p1: [0.1, 0.0], p2: [1.0, 4.0], p3: [10.0, 0.0] };
+    for &length in &[0.5, 3.0, seg.length() * 0.9] {
+        let t = seg.param_at_length(length);
+        assert!((seg.length_to(t) - length).abs() < 1e-6);
+    }
+    assert!(seg.param_at_length(-1.0) == 0.0 && seg.param_at_length(1e6) == 1.0);
+
+    path.reverse();
+    assert!(path.knots[0].co == [0.0, 3.0]);
+    assert!(path.knots[0].handle_next == [1.0, 3.0]);
+    assert!((path.length() - 12.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_curve_resample() {
+    use curve_resample;
+    use curve_types::{
+        Path,
+    };
+
+    // An open curve (a third along for handles gives constant speed) & a cyclic square.
+    let mut path_open = Path::from_cubic_array(false, &[
+        [[0.0, 0.0], [0.0, 0.0], [3.0, 0.0]],
+        [[6.0, 0.0], [9.0, 0.0], [9.0, 0.0]],
+    ]);
+    assert!(curve_resample::path_resample(&mut path_open, 4, false));
+    let co: Vec<[f64; 2]> = path_open.knots.iter().map(|k| k.co).collect();
+    let near = |a: [f64; 2], b: [f64; 2]| (a[0] - b[0]).abs() < 1e-6 && (a[1] - b[1]).abs() < 1e-6;
+    assert!(near(co[0], [0.0, 0.0]) && near(co[1], [3.0, 0.0]) &&
+            near(co[2], [6.0, 0.0]) && near(co[3], [9.0, 0.0]));
+    assert!(path_open.knots.iter().all(|k| k.handle_prev == k.co && k.handle_next == k.co));
+
+    let mut path_cyclic = Path::from_cubic_array(true, &[
+        [[0.0, 0.0], [0.0, 0.0], [0.0, 0.0]],
+        [[4.0, 0.0], [4.0, 0.0], [4.0, 0.0]],
+        [[4.0, 4.0], [4.0, 4.0], [4.0, 4.0]],
+        [[0.0, 4.0], [0.0, 4.0], [0.0, 4.0]],
+    ]);
+    assert!(curve_resample::path_resample(&mut path_cyclic, 8, true));
+    assert!(path_cyclic.knots.len() == 8);
+    // The first point isn't repeated, each point is half way along a side or at a corner.
+    assert!(near(path_cyclic.knots[1].co, [2.0, 0.0]));
+    assert!(near(path_cyclic.knots[7].co, [0.0, 2.0]));
+    // Handles are aligned, so the curve is smooth through each point.
+    assert!(path_cyclic.knots.iter().all(|k| k.is_smooth()));
+
+    // Paths without length are left as-is.
+    let mut path_point = Path::from_cubic_array(false, &[[[1.0, 1.0], [1.0, 1.0], [1.0, 1.0]]]);
+    assert!(!curve_resample::path_resample(&mut path_point, 4, false));
+    assert!(path_point.knots.len() == 1);
+}
+
+#[test]
+fn test_transform_from_svg_str() {
+    use curve_types::Transform;
+
+    let t = Transform::from_svg_str("translate(10, 20) scale(2) rotate(90)").unwrap();
+    let v = t.apply(&[1.0, 0.0]);
+    assert!((v[0] - 10.0).abs() < 1e-9 && (v[1] - 22.0).abs() < 1e-9);
+
+    assert!(Transform::from_svg_str("").unwrap().is_identity());
+    assert!(Transform::from_svg_str("scale(1, 2").is_err());
+    assert!(Transform::from_svg_str("spin(4)").is_err());
+}
+
+#[test]
+fn test_fit_size_from_str() {
+    assert!(::fit_size_from_str("800x600").unwrap() == [800.0, 600.0]);
+    assert!(::fit_size_from_str("800x600px").unwrap() == [800.0, 600.0]);
+    assert!(::fit_size_from_str("2x0.5in").unwrap() == [192.0, 48.0]);
+    let size = ::fit_size_from_str("254x25.4mm").unwrap();
+    assert!((size[0] - 960.0).abs() < 1e-9 && (size[1] - 96.0).abs() < 1e-9);
+
+    assert!(::fit_size_from_str("800").is_err());
+    assert!(::fit_size_from_str("800x600x2").is_err());
+    assert!(::fit_size_from_str("800x0").is_err());
+    assert!(::fit_size_from_str("800x600cm").is_err());
+
+    // The aspect is preserved, so the smallest scale is used.
+    assert!(::output_scale_to_fit(&[800.0, 600.0], &[400, 100]) == 2.0);
+    assert!(::output_scale_to_fit(&[800.0, 600.0], &[100, 1200]) == 0.5);
+}
+
+#[test]
+fn test_split_inflections() {
+    use curve_inflection;
+    use curve_types::{
+        CubicSegment,
+        Path,
+    };
+
+    // An S curve has one inflection (at the middle), an arc has none.
+    let seg = CubicSegment { p0: [0.0, 0.0], p1: [1.0, 1.0], p2: [2.0, -1.0], p3: [3.0, 0.0] };
+    let factors = curve_inflection::segment_inflections(&seg);
+    assert!(factors.len() == 1 && (factors[0] - 0.5).abs() < 1e-9);
+    let seg_arc = CubicSegment { p0: [0.0, 0.0], p1: [0.0, 1.0], p2: [1.0, 2.0], p3: [2.0, 2.0] };
+    assert!(curve_inflection::segment_inflections(&seg_arc).is_empty());
+
+    // Splitting keeps the shape, each segment bends in one direction.
+    for &is_cyclic in &[false, true] {
+        let path_orig = Path::from_cubic_array(is_cyclic, &[
+            [[-1.0, 0.0], [0.0, 0.0], [1.0, 1.0]],
+            [[2.0, -1.0], [3.0, 0.0], [4.0, 1.0]],
+            [[4.0, 3.0], [3.0, 4.0], [0.0, 4.0]],
+        ]);
+        let mut path = path_orig.clone();
+        let added_len = curve_inflection::path_split_inflections(&mut path);
+        assert!(added_len >= 1);
+        assert!(path.knots.len() == path_orig.knots.len() + added_len);
+        assert!(path.segments().all(|seg| curve_inflection::segment_inflections(&seg).is_empty()));
+        let poly_a = path_orig.flatten(1e-4);
+        let poly_b = path.flatten(1e-4);
+        for &(poly_src, poly_dst) in &[(&poly_a, &poly_b), (&poly_b, &poly_a)] {
+            assert!(poly_src.iter().all(|v| poly_dst.iter().any(|v_other| {
+                (v[0] - v_other[0]).abs() < 1e-2 && (v[1] - v_other[1]).abs() < 1e-2
+            })));
+        }
+    }
+}
+
+#[test]
+fn test_round_corners() {
+    use curve_round;
+    use curve_types::Path;
+
+    // A square, each corner is replaced by a quarter circle.
+    let mut path = Path::from_poly(true, &[[0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 10.0]]);
+    assert!(curve_round::path_round_corners(&mut path, 1.0) == 4);
+    assert!(path.knots.len() == 8);
+    let is_edge = |v: f64| v == 0.0 || v == 10.0;
+    let mut arcs_len = 0;
+    for seg in path.segments() {
+        if seg.p0[0] == seg.p3[0] || seg.p0[1] == seg.p3[1] {
+            continue;
+        }
+        // The center is one unit inside the corner.
+        let corner = [
+            if is_edge(seg.p0[0]) { seg.p0[0] } else { seg.p3[0] },
+            if is_edge(seg.p0[1]) { seg.p0[1] } else { seg.p3[1] },
+        ];
+        let center = [seg.p0[0] + seg.p3[0] - corner[0], seg.p0[1] + seg.p3[1] - corner[1]];
+        for i in 0..5 {
+            let v = seg.eval(i as f64 / 4.0);
+            let d = ((v[0] - center[0]).powi(2) + (v[1] - center[1]).powi(2)).sqrt();
+            assert!((d - 1.0).abs() < 1e-3);
+        }
+        arcs_len += 1;
+    }
+    assert!(arcs_len == 4);
+
+    // The radius is reduced for short segments, the ends of open paths aren't changed.
+    let mut path = Path::from_poly(false, &[[0.0, 0.0], [1.0, 0.0], [1.0, 1.0]]);
+    assert!(curve_round::path_round_corners(&mut path, 4.0) == 1);
+    assert!(path.knots.len() == 4);
+    assert!(path.knots[0].co == [0.0, 0.0] && path.knots[3].co == [1.0, 1.0]);
+    assert!((path.knots[1].co[0] - 0.5).abs() < 1e-6 && (path.knots[2].co[1] - 0.5).abs() < 1e-6);
+}
+
+#[test]
+fn test_detect_shapes() {
+    use curve_types::Shape;
+    use curve_write;
+
+    // A rectangle & a disc.
+    let size = [64, 32];
+    let image: Vec<bool> = (0..(size[0] * size[1])).map(|i| {
+        let (x, y) = (i % size[0], i / size[0]);
+        let (dx, dy) = (x as f64 + 0.5 - 44.0, y as f64 + 0.5 - 16.0);
+        (x >= 4 && x < 24 && y >= 8 && y < 20) || (dx * dx + dy * dy < 100.0)
+    }).collect();
+    let trace_params = ::TraceParams {
+        use_detect_shapes: true,
+        .. ::TraceParams::default()
+    };
+    let output = ::trace_image_to_drawing(&trace_params, &image, &size, 0.75);
+    assert!(output.drawing.paths.len() == 2);
+    let mut shapes_found = [false; 2];
+    for path in &output.drawing.paths {
+        match path.meta.shape {
+            Some(Shape::Rect { .. }) => {
+                let (min, max) = path.bounds();
+                assert!(min == [4.0, 8.0] && max == [24.0, 20.0]);
+                shapes_found[0] = true;
+            },
+            Some(Shape::Ellipse { center, axes }) => {
+                assert!((center[0] - 44.0).abs() < 0.5 && (center[1] - 16.0).abs() < 0.5);
+                assert!(axes[0][1] == 0.0 && axes[1][0] == 0.0 && axes[0][0] == axes[1][1].abs());
+                shapes_found[1] = true;
+            },
+            _ => {
+                panic!("Unexpected shape: {:?}", path.meta.shape);
+            },
+        }
+    }
+    assert!(shapes_found == [true, true]);
+
+    let mut data: Vec<u8> = vec![];
+    curve_write::svg::write_drawing_filled(&mut data, &size, 1.0, &output.drawing, false).unwrap();
+    let text = String::from_utf8(data).unwrap();
+    assert!(text.contains("<rect x='4.00' y='8.00' width='20.00' height='12.00' />"));
+    assert!(text.contains("<circle "));
+
+    // Shapes are lost with non-affine transforms.
+    let mut drawing = output.drawing.clone();
+    drawing.transform(|v| [v[0], v[1] + v[0] * v[0]]);
+    assert!(drawing.paths.iter().all(|path| path.meta.shape.is_none()));
+}
+
+#[test]
+fn test_path_validate_finite() {
+    use curve_types::{
+        Path,
+    };
+    use std::f64;
+
+    let mut path = Path::from_cubic_array(false, &[
+        [[0.0, 0.0], [0.0, 0.0], [f64::NAN, 0.0]],
+        [[2.0, 0.0], [3.0, 0.0], [4.0, 0.0]],
+        [[5.0, 0.0], [6.0, 0.0], [f64::INFINITY, 0.0]],
+    ]);
+    assert!(path.validate_finite() == 1);
+    assert!((path.knots[0].handle_next[0] - 1.0).abs() < 1e-9);
+    assert!((path.knots[1].handle_prev[0] - 2.0).abs() < 1e-9);
+    assert!(path.knots[2].handle_next == [6.0, 0.0]);
+    assert!(path.validate_finite() == 0);
+}
+
+#[test]
+fn test_turn_policy_random_stable() {
+    use polys_from_raster_outline::{
+        extract_outline,
+        EdgePolicy,
+        TurnPolicy,
+    };
+
+    // Checker-board pattern, every inner vertex is ambiguous.
+    let size = [8, 8];
+    let image: Vec<bool> = (0..(size[0] * size[1]))
+        .map(|i| ((i % 8) + (i / 8)) % 2 == 0)
+        .collect();
+    let a = extract_outline(&image, &size, TurnPolicy::Random(7), EdgePolicy::Close, false);
+    let b = extract_outline(&image, &size, TurnPolicy::Random(7), EdgePolicy::Close, false);
+    assert!(a == b);
+    let c = extract_outline(&image, &size, TurnPolicy::Majority, EdgePolicy::Close, false);
+    let d = extract_outline(&image, &size, TurnPolicy::Majority, EdgePolicy::Close, false);
+    assert!(c == d);
+
+    // Another seed turns differently at some vertices, changing the outline.
+    let e = extract_outline(&image, &size, TurnPolicy::Random(8), EdgePolicy::Close, false);
+    assert!(a != e);
+    // Turns are mixed, unlike always turning one way.
+    for &turn_policy in &[TurnPolicy::Black, TurnPolicy::White] {
+        assert!(a != extract_outline(&image, &size, turn_policy, EdgePolicy::Close, false));
+    }
+
+    // The traced output is also reproducible for a seed.
+    let trace = |turn_policy_seed, seed| {
+        let trace_params = ::TraceParams {
+            turn_policy: TurnPolicy::Random(0),
+            turn_policy_seed: turn_policy_seed,
+            seed: seed,
+            .. ::TraceParams::default()
+        };
+        let output = ::trace_image_to_drawing(&trace_params, &image, &size, 0.75);
+        output.drawing.flatten(0.1)
+    };
+    assert!(trace(Some(7), 0) == trace(Some(7), 0));
+    assert!(trace(Some(7), 0) != trace(Some(8), 0));
+    // Without a seed of its own, the turn policy is seeded from `--seed`.
+    assert!(trace(None, 1) == trace(None, 1));
+    assert!(trace(None, 1) != trace(None, 99));
+    // A seed of its own overrides `--seed`.
+    assert!(trace(Some(7), 1) == trace(Some(7), 99));
+}
+
+#[test]
+fn test_edge_policy() {
+    use polys_from_raster_outline::{
+        extract_outline,
+        EdgePolicy,
+        TurnPolicy,
+    };
+
+    // A block touching the left border & a block which doesn't touch any border.
+    let size = [6, 4];
+    let image: Vec<bool> = vec![
+        1, 1, 0, 0, 0, 0,
+        1, 1, 0, 0, 1, 0,
+        1, 1, 0, 0, 1, 0,
+        0, 0, 0, 0, 0, 0,
+    ].iter().map(|v| *v != 0).collect();
+    let extract = |edge_policy| {
+        extract_outline(&image, &size, TurnPolicy::Majority, edge_policy, true)
+    };
+
+    let poly_list = extract(EdgePolicy::Close);
+    assert!(poly_list.len() == 2 && poly_list.iter().all(|p| p.0));
+
+    let poly_list = extract(EdgePolicy::Open);
+    assert!(poly_list.len() == 2 && poly_list.iter().filter(|p| !p.0).count() == 1);
+    let poly = &poly_list.iter().find(|p| !p.0).unwrap().1;
+    assert!(poly.len() == 3);
+    assert!(poly[0][0] == 0 || poly[0][1] == 0);
+    assert!(poly[2][0] == 0 || poly[2][1] == 0);
+
+    let poly_list = extract(EdgePolicy::Extend);
+    assert!(poly_list.front().unwrap().1.iter().any(|v| *v == [-2, -2]));
+    assert!(poly_list.back().unwrap().1.iter().all(|v| v[0] >= 0 && v[1] >= 0));
+
+    // A filled image has nothing left to trace.
+    let image = vec![true; 4];
+    assert!(extract_outline(
+        &image, &[2, 2], TurnPolicy::Majority, EdgePolicy::Open, true).len() == 0);
+}
+
+#[test]
+fn test_image_runs() {
+    use image_runs::ImageRuns;
+
+    let size = [5, 3];
+    let image: Vec<bool> = vec![
+        1, 1, 0, 1, 0,
+        0, 0, 0, 0, 0,
+        0, 1, 1, 1, 1,
+    ].iter().map(|v| *v != 0).collect();
+    let runs = ImageRuns::from_image(&image, &size);
+    assert!(runs.row(0) == &[[0, 2], [3, 4]]);
+    assert!(runs.row(1).is_empty());
+    assert!(runs.row(2) == &[[1, 5]]);
+    assert!(runs.runs_len() == 3 && runs.area() == 7);
+    for y in -1..4 {
+        for x in -1..6 {
+            let is_inside = x >= 0 && y >= 0 && x < 5 && y < 3;
+            assert!(runs.pixel_get(x, y) == (is_inside && image[(x + y * 5) as usize]));
+        }
+    }
+    assert!(runs.run_index(4, 2) == Some(2));
+
+    assert!(ImageRuns::from_image(&[], &[0, 2]).runs_len() == 0);
+}
+
+#[test]
+fn test_extract_outline_methods() {
+    use image_runs::ImageRuns;
+    use polys_from_raster_outline::{
+        extract_outline,
+        extract_outline_runs,
+        extract_outline_sparse,
+        EdgePolicy,
+        TurnPolicy,
+    };
+
+    // Noise of varying density (many ambiguous vertices), a checker-board & a single pixel.
+    let mut images: Vec<([usize; 2], Vec<bool>)> = vec![];
+    for &density in &[5, 30, 50, 80] {
+        let size = [37, 23];
+        let mut state: u64 = density;
+        images.push((size, (0..(size[0] * size[1])).map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 33) % 100 < density
+        }).collect()));
+    }
+    images.push(([8, 8], (0..64).map(|i| ((i % 8) + (i / 8)) % 2 == 0).collect()));
+    images.push(([1, 1], vec![true]));
+
+    for &(size, ref image) in &images {
+        let runs = ImageRuns::from_image(image, &size);
+        for &turn_policy in &[TurnPolicy::Black, TurnPolicy::White, TurnPolicy::Majority,
+                              TurnPolicy::Minority, TurnPolicy::Random(3)] {
+            for &edge_policy in &[EdgePolicy::Close, EdgePolicy::Open, EdgePolicy::Extend] {
+                for &use_simplify in &[false, true] {
+                    let poly_list =
+                        extract_outline(image, &size, turn_policy, edge_policy, use_simplify);
+                    assert!(poly_list ==
+                            extract_outline_sparse(
+                                image, &size, turn_policy, edge_policy, use_simplify));
+                    assert!(poly_list ==
+                            extract_outline_runs(&runs, turn_policy, edge_policy, use_simplify));
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_image_load_ppm_malformed() {
+    use intern::image_load::{
+        from_bytes_format,
+        ImageFormat,
+    };
+
+    let load = |data: &[u8]| from_bytes_format(data, ImageFormat::PPM);
+
+    let (size, _, pixels) = load(b"P6\n2 1\n255\n\x00\x00\x00\xff\xff\xff").unwrap();
+    assert!(size == [2, 1] && pixels.len() == 2);
+
+    assert!(load(b"").is_err());
+    assert!(load(b"P5\n2 1\n255\n").is_err());
+    // Truncated pixel data.
+    assert!(load(b"P6\n2 1\n255\n\x00\x00\x00").is_err());
+    // Sizes which overflow or would over-allocate.
+    assert!(load(b"P6\n99999999999 99999999999\n255\n").is_err());
+    assert!(load(b"P6\n100000 100000\n255\n\x00").is_err());
+    assert!(load(b"P6\n111111111111111111111111111111\n").is_err());
+    assert!(load(b"P6\n2 1\n0\n\x00\x00\x00\xff\xff\xff").is_err());
+}
+
+#[test]
+fn test_image_load_ppm_frames() {
+    use intern::image_load::{
+        from_bytes_format_frames,
+        ImageFormat,
+    };
+
+    let load = |data: &[u8]| from_bytes_format_frames(data, ImageFormat::PPM);
+
+    let frames = load(concat!(
+        "P6\n2 1\n255\n\x00\x00\x00\x7f\x7f\x7f",
+        "P6\n1 1\n255\n\x00\x00\x00\n",
+    ).as_bytes()).unwrap();
+    assert!(frames.len() == 2);
+    assert!(frames[0].0 == [2, 1] && frames[1].0 == [1, 1]);
+
+    // A single image is a single frame, malformed frames are an error.
+    assert!(load(b"P6\n1 1\n255\n\x00\x00\x00").unwrap().len() == 1);
+    assert!(load(b"P6\n1 1\n255\n\x00\x00\x00P5").is_err());
+
+    assert!(::frame_filepath("out.svg", 1) == "out_0001.svg");
+    assert!(::frame_filepath("dir/out", 12) == "dir/out_0012");
+}
+
+#[test]
+fn test_image_load_tiff() {
+    use intern::image_load::{
+        format_from_magic,
+        from_bytes_format,
+        from_bytes_format_frames,
+        ImageFormat,
+    };
+
+    // Pages of `(entries, data)`, where entries are `(tag, type, value)`,
+    // a single strip is added for the data.
+    let tiff = |pages: &[(&[(u16, u16, u32)], &[u8])], is_big_endian: bool| -> Vec<u8> {
+        let u16_bytes = |v: u16| if is_big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+        let u32_bytes = |v: u32| if is_big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+        let mut tiff: Vec<u8> = if is_big_endian { b"MM\0*".to_vec() } else { b"II*\0".to_vec() };
+        tiff.extend_from_slice(&u32_bytes(8));
+        for (i, &(entries, data)) in pages.iter().enumerate() {
+            let entries_len = entries.len() + 2;
+            let data_offset = tiff.len() + 2 + entries_len * 12 + 4;
+            let mut entries = entries.to_vec();
+            entries.push((273, 4, data_offset as u32));
+            entries.push((279, 4, data.len() as u32));
+            tiff.extend_from_slice(&u16_bytes(entries_len as u16));
+            for &(tag, value_type, value) in &entries {
+                tiff.extend_from_slice(&u16_bytes(tag));
+                tiff.extend_from_slice(&u16_bytes(value_type));
+                tiff.extend_from_slice(&u32_bytes(1));
+                if value_type == 3 {
+                    tiff.extend_from_slice(&u16_bytes(value as u16));
+                    tiff.extend_from_slice(&[0, 0]);
+                } else {
+                    tiff.extend_from_slice(&u32_bytes(value));
+                }
+            }
+            let is_last = i + 1 == pages.len();
+            let offset_next = if is_last { 0 } else { data_offset + data.len() };
+            tiff.extend_from_slice(&u32_bytes(offset_next as u32));
+            tiff.extend_from_slice(data);
+        }
+        tiff
+    };
+    let load = |data: &[u8]| from_bytes_format(data, ImageFormat::TIFF);
+
+    // Bilevel (one is black) with PackBits, a literal row then a repeated row.
+    let bilevel: &[(u16, u16, u32)] = &[(256, 3, 16), (257, 3, 2), (259, 3, 32773), (262, 3, 0)];
+    let data = tiff(&[(bilevel, &[0x01, 0x80, 0x01, 0xff, 0xff])], false);
+    assert!(format_from_magic(&data) == Some(ImageFormat::TIFF));
+    let (size, color_max, pixels) = load(&data).unwrap();
+    assert!(size == [16, 2] && color_max == 255);
+    for (i, p) in pixels.iter().enumerate() {
+        let is_black = i == 0 || i >= 15;
+        assert!(*p == if is_black { [0, 0, 0] } else { [255, 255, 255] });
+    }
+    // Truncated PackBits data.
+    assert!(load(&tiff(&[(bilevel, &[0x01, 0x80, 0x01, 0xff])], false)).is_err());
+
+    // Pages of 8 bit gray & RGB.
+    let gray: &[(u16, u16, u32)] = &[(256, 3, 2), (257, 3, 1), (258, 3, 8), (262, 3, 1)];
+    let rgb: &[(u16, u16, u32)] = &[
+        (256, 4, 1), (257, 4, 1), (258, 3, 8), (262, 3, 2), (277, 3, 3),
+    ];
+    let data = tiff(&[(gray, &[0, 255]), (rgb, &[10, 20, 30])], true);
+    assert!(format_from_magic(&data) == Some(ImageFormat::TIFF));
+    assert!(load(&data).unwrap().2 == vec![[0, 0, 0], [255, 255, 255]]);
+    let frames = from_bytes_format_frames(&data, ImageFormat::TIFF).unwrap();
+    assert!(frames.len() == 2 && frames[1].0 == [1, 1] && frames[1].2 == vec![[10, 20, 30]]);
+
+    // Truncated, unsupported compression & missing size.
+    assert!(load(&tiff(&[(gray, &[0])], true)).is_err());
+    let lzw: &[(u16, u16, u32)] = &[(256, 3, 2), (257, 3, 1), (259, 3, 5), (262, 3, 1)];
+    assert!(load(&tiff(&[(lzw, &[0, 0])], true)).is_err());
+    assert!(load(&tiff(&[(&[(262, 3, 1)], &[0])], false)).is_err());
+    assert!(load(b"II*\0").is_err());
+    assert!(load(b"II*\0\xff\xff\xff\xff").is_err());
+}
+
+#[test]
+fn test_image_load_jpeg() {
+    use intern::image_load::{
+        format_from_magic,
+        from_bytes_format,
+        ImageFormat,
+    };
+
+    // 8x8 gray, black on the left half.
+    let data: &[u8] = b"\
+        \xff\xd8\xff\xe0\x00\x10\x4a\x46\x49\x46\x00\x01\x02\x00\x00\x01\x00\x01\x00\x00\xff\xc0\
+        \x00\x0b\x08\x00\x08\x00\x08\x01\x00\x11\x00\xff\xdb\x00\x43\x00\x03\x02\x02\x03\x02\x02\
+        \x03\x03\x03\x03\x04\x03\x03\x04\x05\x08\x05\x05\x04\x04\x05\x0a\x07\x07\x06\x08\x0c\x0a\
+        \x0c\x0c\x0b\x0a\x0b\x0b\x0d\x0e\x12\x10\x0d\x0e\x11\x0e\x0b\x0b\x10\x16\x10\x11\x13\x14\
+        \x15\x15\x15\x0c\x0f\x17\x18\x16\x14\x18\x12\x14\x15\x14\xff\xdb\x00\x43\x01\x03\x04\x04\
+        \x05\x04\x05\x09\x05\x05\x09\x14\x0d\x0b\x0d\x14\x14\x14\x14\x14\x14\x14\x14\x14\x14\x14\
+        \x14\x14\x14\x14\x14\x14\x14\x14\x14\x14\x14\x14\x14\x14\x14\x14\x14\x14\x14\x14\x14\x14\
+        \x14\x14\x14\x14\x14\x14\x14\x14\x14\x14\x14\x14\x14\x14\x14\x14\x14\xff\xc4\x00\x1f\x00\
+        \x00\x01\x05\x01\x01\x01\x01\x01\x01\x00\x00\x00\x00\x00\x00\x00\x00\x01\x02\x03\x04\x05\
+        \x06\x07\x08\x09\x0a\x0b\xff\xc4\x00\xb5\x10\x00\x02\x01\x03\x03\x02\x04\x03\x05\x05\x04\
+        \x04\x00\x00\x01\x7d\x01\x02\x03\x00\x04\x11\x05\x12\x21\x31\x41\x06\x13\x51\x61\x07\x22\
+        \x71\x14\x32\x81\x91\xa1\x08\x23\x42\xb1\xc1\x15\x52\xd1\xf0\x24\x33\x62\x72\x82\x09\x0a\
+        \x16\x17\x18\x19\x1a\x25\x26\x27\x28\x29\x2a\x34\x35\x36\x37\x38\x39\x3a\x43\x44\x45\x46\
+        \x47\x48\x49\x4a\x53\x54\x55\x56\x57\x58\x59\x5a\x63\x64\x65\x66\x67\x68\x69\x6a\x73\x74\
+        \x75\x76\x77\x78\x79\x7a\x83\x84\x85\x86\x87\x88\x89\x8a\x92\x93\x94\x95\x96\x97\x98\x99\
+        \x9a\xa2\xa3\xa4\xa5\xa6\xa7\xa8\xa9\xaa\xb2\xb3\xb4\xb5\xb6\xb7\xb8\xb9\xba\xc2\xc3\xc4\
+        \xc5\xc6\xc7\xc8\xc9\xca\xd2\xd3\xd4\xd5\xd6\xd7\xd8\xd9\xda\xe1\xe2\xe3\xe4\xe5\xe6\xe7\
+        \xe8\xe9\xea\xf1\xf2\xf3\xf4\xf5\xf6\xf7\xf8\xf9\xfa\xff\xda\x00\x08\x01\x00\x00\x00\x3f\
+        \x00\x4f\xf8\x21\x8f\xfc\xd6\xcf\xfb\x82\x7f\xed\xfd\x7f\xff\xd9";
+    assert!(format_from_magic(data) == Some(ImageFormat::JPEG));
+    let result = from_bytes_format(data, ImageFormat::JPEG);
+    if cfg!(feature = "jpeg") {
+        let (size, color_max, pixels) = result.unwrap();
+        assert!(size == [8, 8] && color_max == 255);
+        let image = ::intern::image_load::mask_from_pixels(color_max, &pixels, 0.5);
+        assert!((0..64).all(|i| image[i] == ((i % 8) < 4)));
+        assert!(from_bytes_format(&data[..data.len() / 2], ImageFormat::JPEG).is_err());
+    } else {
+        assert!(result.is_err());
+    }
+}
+
+#[test]
+fn test_image_load_qoi() {
+    use intern::image_load::{
+        format_from_magic,
+        from_bytes_format,
+        ImageFormat,
+    };
+
+    let load = |size: [u8; 2], ops: &[u8]| {
+        let mut data = b"qoif\0\0\0\0\0\0\0\0\x04\0".to_vec();
+        data[7] = size[0];
+        data[11] = size[1];
+        data.extend_from_slice(ops);
+        data.extend_from_slice(b"\0\0\0\0\0\0\0\x01");
+        assert!(format_from_magic(&data) == Some(ImageFormat::QOI));
+        from_bytes_format(&data, ImageFormat::QOI)
+    };
+    let (black, white) = ([0, 0, 0], [255, 255, 255]);
+
+    // RGBA, a run (continuing onto the next row), RGB & index (of black).
+    let (size, _, pixels) = load([3, 2], &[
+        0xff, 0, 0, 0, 255, 0xc1, 0xfe, 255, 255, 255, 0x35, 0xc0,
+    ]).unwrap();
+    assert!(size == [3, 2] && pixels == vec![black, black, black, white, black, black]);
+
+    // Difference (wrapping around to white) & luma, transparent pixels are white.
+    let (_, _, pixels) = load([3, 1], &[0x55, 0x80, 0x88, 0xff, 0, 0, 0, 0]).unwrap();
+    assert!(pixels == vec![white, [223, 223, 223], white]);
+
+    // Empty, truncated (without the end marker) & invalid channels.
+    assert!(load([0, 2], &[]).is_err());
+    assert!(from_bytes_format(b"qoif\0\0\0\x03\0\0\0\x02\x04\0\xc1", ImageFormat::QOI).is_err());
+    assert!(from_bytes_format(b"qoif\0\0\0\x01\0\0\0\x01\x02\0\x00", ImageFormat::QOI).is_err());
+}
+
+#[test]
+fn test_image_load_gif() {
+    use intern::image_load::{
+        format_from_magic,
+        from_bytes_format,
+        from_bytes_format_frames,
+        ImageFormat,
+    };
+
+    // A 4 color GIF with a global color table (black, white, red & green).
+    let gif = |size: [u16; 2], blocks: &[Vec<u8>]| -> Vec<u8> {
+        let mut data = b"GIF89a".to_vec();
+        data.extend_from_slice(&size[0].to_le_bytes());
+        data.extend_from_slice(&size[1].to_le_bytes());
+        data.extend_from_slice(&[0x81, 0, 0, 0, 0, 0, 255, 255, 255, 255, 0, 0, 0, 255, 0]);
+        for block in blocks {
+            data.extend_from_slice(block);
+        }
+        data.push(0x3b);
+        data
+    };
+    // An image from 3 bit LZW codes.
+    let image = |rect: [u16; 4], flags: u8, codes: &[u8]| -> Vec<u8> {
+        let mut data = vec![0x2c];
+        for v in &rect {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        data.extend_from_slice(&[flags, 2]);
+        let mut packed: Vec<u8> = vec![0; (codes.len() * 3 + 7) / 8];
+        for (i, code) in codes.iter().enumerate() {
+            for bit in 0..3 {
+                if (code >> bit) & 1 != 0 {
+                    packed[(i * 3 + bit) / 8] |= 1 << ((i * 3 + bit) % 8);
+                }
+            }
+        }
+        data.push(packed.len() as u8);
+        data.extend_from_slice(&packed);
+        data.push(0);
+        data
+    };
+    // Codes for color indices, clearing before the code size would grow.
+    let literals = |indices: &[u8]| -> Vec<u8> {
+        let mut codes = vec![];
+        for pair in indices.chunks(2) {
+            codes.push(4);
+            codes.extend_from_slice(pair);
+        }
+        codes.push(5);
+        codes
+    };
+    let graphic_control = |flags: u8, transparent_index: u8| -> Vec<u8> {
+        vec![0x21, 0xf9, 4, flags, 0, 0, transparent_index, 0]
+    };
+    let (black, white) = ([0, 0, 0], [255, 255, 255]);
+
+    // The first image is cleared (disposed to the background) before the second is drawn,
+    // which has a transparent pixel.
+    let data = gif([3, 2], &[
+        graphic_control(0x08, 0),
+        image([0, 0, 3, 2], 0, &literals(&[0, 1, 0, 1, 0, 1])),
+        graphic_control(0x01, 2),
+        image([1, 0, 2, 1], 0, &literals(&[0, 2])),
+    ]);
+    assert!(format_from_magic(&data) == Some(ImageFormat::GIF));
+    let (size, color_max, pixels) = from_bytes_format(&data, ImageFormat::GIF).unwrap();
+    assert!(size == [3, 2] && color_max == 255);
+    assert!(pixels == vec![black, white, black, white, black, white]);
+    let frames = from_bytes_format_frames(&data, ImageFormat::GIF).unwrap();
+    assert!(frames.len() == 2 && frames[0].2 == pixels);
+    assert!(frames[1].2 == vec![white, black, white, white, white, white]);
+
+    // A code which isn't yet in the table (the previous string & its first value).
+    let data = gif([3, 1], &[image([0, 0, 3, 1], 0, &[4, 1, 6, 5])]);
+    assert!(from_bytes_format(&data, ImageFormat::GIF).unwrap().2 == vec![white; 3]);
+
+    // Interlaced rows are stored in the order: 0, 4, 2, 1, 3.
+    let data = gif([1, 5], &[image([0, 0, 1, 5], 0x40, &literals(&[0, 1, 1, 0, 0]))]);
+    assert!(from_bytes_format(&data, ImageFormat::GIF).unwrap().2 ==
+            vec![black, black, white, black, white]);
+
+    // Truncated, an invalid code, missing image & missing color table.
+    let data = gif([3, 1], &[image([0, 0, 3, 1], 0, &literals(&[0, 1, 0]))]);
+    assert!(from_bytes_format(&data[..data.len() - 4], ImageFormat::GIF).is_err());
+    let data = gif([3, 1], &[image([0, 0, 3, 1], 0, &[4, 1, 7, 5])]);
+    assert!(from_bytes_format(&data, ImageFormat::GIF).is_err());
+    assert!(from_bytes_format(&gif([3, 1], &[]), ImageFormat::GIF).is_err());
+    let mut data = gif([3, 1], &[image([0, 0, 3, 1], 0, &literals(&[0, 1, 0]))]);
+    data[10] = 0;
+    data.drain(13..25);
+    assert!(from_bytes_format(&data, ImageFormat::GIF).is_err());
+}
+
+#[test]
+fn test_image_load_png() {
+    use intern::image_load::{
+        format_from_magic,
+        from_bytes_format,
+        from_bytes_format_frames,
+        ImageFormat,
+    };
+
+    // CRC's aren't written since they aren't checked.
+    let png = |chunks: &[(&[u8], &[u8])]| -> Vec<u8> {
+        let mut data = b"\x89PNG\r\n\x1a\n".to_vec();
+        for &(chunk_type, chunk) in chunks.iter().chain(&[(&b"IEND"[..], &b""[..])]) {
+            data.extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+            data.extend_from_slice(chunk_type);
+            data.extend_from_slice(chunk);
+            data.extend_from_slice(&[0; 4]);
+        }
+        data
+    };
+    let header = |size: [u32; 2], bit_depth: u8, color_type: u8| -> Vec<u8> {
+        let mut data = vec![];
+        data.extend_from_slice(&size[0].to_be_bytes());
+        data.extend_from_slice(&size[1].to_be_bytes());
+        data.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]);
+        data
+    };
+    // The compressed data of RGBA pixels (stored blocks).
+    let rgba_data = |size: [usize; 2], pixels: &[[u8; 4]]| -> Vec<u8> {
+        let mut data = vec![];
+        ::image_write::png::write_rgba(&mut data, &size, pixels).unwrap();
+        let len = u32::from_be_bytes([data[33], data[34], data[35], data[36]]) as usize;
+        data[41..41 + len].to_vec()
+    };
+    let load = |data: &[u8]| from_bytes_format(data, ImageFormat::PNG);
+    let (black, white) = ([0, 0, 0], [255, 255, 255]);
+
+    // RGBA (as written), transparent pixels are white.
+    let mut data = vec![];
+    ::image_write::png::write_rgba(&mut data, &[2, 2], &[
+        [0, 0, 0, 255], [255, 0, 0, 255], [0, 0, 0, 0], [0, 0, 0, 128],
+    ]).unwrap();
+    assert!(format_from_magic(&data) == Some(ImageFormat::PNG));
+    let (size, color_max, pixels) = load(&data).unwrap();
+    assert!(size == [2, 2] && color_max == 255);
+    assert!(pixels == vec![black, [255, 0, 0], white, [127, 127, 127]]);
+
+    // A 1 bit palette (fixed Huffman codes).
+    let data = png(&[
+        (b"IHDR", &header([3, 2], 1, 3)),
+        (b"PLTE", b"\x00\x00\x00\xff\xff\xff"),
+        (b"IDAT", b"\x78\xda\x63\x58\xc0\xe0\x00\x00\x02\x24\x00\xe1"),
+    ]);
+    assert!(load(&data).unwrap().2 == vec![white, black, white, black, white, black]);
+
+    // 16 bit gray, the second row filtered using the row above.
+    let data = png(&[
+        (b"IHDR", &header([2, 2], 16, 0)),
+        (b"IDAT", b"\x78\xda\x63\x60\x60\xf8\xff\x9f\xe9\xff\x7f\x06\x06\x00\x14\x00\x03\xff"),
+    ]);
+    assert!(load(&data).unwrap().2 == vec![black, white, white, white]);
+
+    // 8 bit gray, unfiltered (dynamic Huffman codes) & using each filter (fixed Huffman codes).
+    let pixels_expect: Vec<[u8; 3]> = (0..(32 * 4)).map(|i| {
+        let (x, y) = (i % 32, i / 32);
+        let v = (((x * x + y) % 7) * 40) as u8;
+        [v, v, v]
+    }).collect();
+    let data = png(&[
+        (b"IHDR", &header([32, 4], 8, 0)),
+        (b"IDAT", b"\
+            \x78\xda\x75\xc5\xd1\x00\x00\x40\x10\x42\xc1\x87\xb3\x38\xe1\x84\x13\x4e\x38\x87\
+            \x70\x04\xcd\xcf\xc0\x45\xca\xcd\x38\xd5\xae\x66\xc8\x2f\x79\x9e\xe1\xd0\x92\xd9\
+            \x07\xff\xc7\x3b\x89"),
+    ]);
+    assert!(load(&data).unwrap().2 == pixels_expect);
+    let data = png(&[
+        (b"IHDR", &header([32, 4], 8, 0)),
+        (b"IDAT", b"\
+            \x78\xda\x63\x64\xd0\xa8\xd8\xc0\x10\xd0\x71\x03\x17\xc5\xa4\x41\x00\x30\xdb\x68\
+            \x04\xbc\x11\xb1\xb9\xc1\x80\x8b\x62\xd1\xd0\x10\x38\xc1\x20\xb0\x80\x01\x17\x05\
+            \x00\x51\xb2\x26\x13"),
+    ]);
+    assert!(load(&data).unwrap().2 == pixels_expect);
+
+    // APNG, the second frame is blended over the first.
+    let frame_control = |sequence: u32, size: [u32; 2], offset: [u32; 2], blend: u8| -> Vec<u8> {
+        let mut data = vec![];
+        for v in &[sequence, size[0], size[1], offset[0], offset[1]] {
+            data.extend_from_slice(&v.to_be_bytes());
+        }
+        data.extend_from_slice(&[0, 1, 0, 1, 0, blend]);
+        data
+    };
+    let mut frame_data = 2_u32.to_be_bytes().to_vec();
+    frame_data.extend_from_slice(&rgba_data([1, 1], &[[0, 0, 0, 128]]));
+    let data = png(&[
+        (b"IHDR", &header([2, 1], 8, 6)),
+        (b"acTL", &[0, 0, 0, 2, 0, 0, 0, 0]),
+        (b"fcTL", &frame_control(0, [2, 1], [0, 0], 0)),
+        (b"IDAT", &rgba_data([2, 1], &[[0, 0, 0, 255], [255, 255, 255, 255]])),
+        (b"fcTL", &frame_control(1, [1, 1], [1, 0], 1)),
+        (b"fdAT", &frame_data),
+    ]);
+    assert!(load(&data).unwrap().2 == vec![black, white]);
+    let frames = from_bytes_format_frames(&data, ImageFormat::PNG).unwrap();
+    assert!(frames.len() == 2 && frames[0].2 == vec![black, white]);
+    assert!(frames[1].2 == vec![black, [127, 127, 127]]);
+
+    // Frames outside of the image, interlaced, truncated & invalid compressed data.
+    let data_invalid = png(&[
+        (b"IHDR", &header([2, 1], 8, 6)),
+        (b"acTL", &[0, 0, 0, 1, 0, 0, 0, 0]),
+        (b"fcTL", &frame_control(0, [2, 1], [1, 0], 0)),
+        (b"IDAT", &rgba_data([2, 1], &[[0, 0, 0, 255], [255, 255, 255, 255]])),
+    ]);
+    assert!(from_bytes_format_frames(&data_invalid, ImageFormat::PNG).is_err());
+    let mut header_interlaced = header([1, 1], 8, 6);
+    header_interlaced[12] = 1;
+    assert!(load(&png(&[(b"IHDR", &header_interlaced)])).is_err());
+    assert!(load(&data[..data.len() - 20]).is_err());
+    let data = png(&[
+        (b"IHDR", &header([2, 1], 8, 0)),
+        (b"IDAT", b"\x78\xda\xff\xff\xff\xff"),
+    ]);
+    assert!(load(&data).is_err());
+}
+
+#[test]
+fn test_image_load_raw() {
+    use intern::image_load::{
+        from_bytes_raw,
+        RawFormat,
+    };
+
+    let raw = RawFormat::from_str("2x1").unwrap();
+    assert!(raw == RawFormat { size: [2, 1], depth: 8 });
+    let (size, color_max, pixels) = from_bytes_raw(b"\x00\xff", &raw).unwrap();
+    assert!(size == [2, 1] && color_max == 255);
+    assert!(pixels == vec![[0, 0, 0], [255, 255, 255]]);
+
+    // 16 bit values are most significant byte first.
+    let raw = RawFormat::from_str("2x1:16").unwrap();
+    let (_, _, pixels) = from_bytes_raw(b"\x10\x00\xff\x10", &raw).unwrap();
+    assert!(pixels == vec![[16, 16, 16], [255, 255, 255]]);
+    // Truncated pixel data.
+    assert!(from_bytes_raw(b"\x10\x00\xff", &raw).is_err());
+
+    assert!(RawFormat::from_str("2x").is_err());
+    assert!(RawFormat::from_str("0x1").is_err());
+    assert!(RawFormat::from_str("2x1:12").is_err());
+    assert!(from_bytes_raw(b"", &RawFormat::from_str("100000x100000").unwrap()).is_err());
+}
+
+#[test]
+fn test_image_load_pixel_layout() {
+    use intern::image_load::PixelLayout;
+
+    assert!(PixelLayout::Gray16.to_rgb(&[0x10, 0xff]) == [16, 16, 16]);
+    assert!(PixelLayout::RGB8.to_rgb(&[1, 2, 3]) == [1, 2, 3]);
+    // Transparent pixels are white, opaque pixels keep their color.
+    assert!(PixelLayout::RGBA8.to_rgb(&[0, 0, 0, 0]) == [255, 255, 255]);
+    assert!(PixelLayout::RGBA8.to_rgb(&[0, 10, 20, 255]) == [0, 10, 20]);
+    assert!(PixelLayout::RGBA8.to_rgb(&[0, 0, 0, 128])[0] == 127);
+    assert!(PixelLayout::CMYK8.to_rgb(&[0, 0, 0, 0]) == [255, 255, 255]);
+    assert!(PixelLayout::CMYK8.to_rgb(&[0, 0, 0, 255]) == [0, 0, 0]);
+    assert!(PixelLayout::CMYK8.to_rgb(&[255, 0, 0, 0]) == [0, 255, 255]);
+
+    let mut row = vec![];
+    PixelLayout::Gray8.row_to_rgb(&[0, 255], &mut row);
+    assert!(row == vec![[0, 0, 0], [255, 255, 255]]);
+}
+
+#[test]
+fn test_image_load_rows() {
+    use std::io::Write;
+    use intern::image_load::{
+        from_bytes_format,
+        from_filepath_rows,
+        load_mask,
+        mask_from_pixels,
+        ImageFormat,
+        RawFormat,
+        HISTOGRAM_BINS,
+    };
+
+    let data = b"P6\n2 2\n255\n\x00\x00\x00\xff\xff\xff\x10\x10\x10\xf0\xf0\xf0";
+    let filepath = ::std::env::temp_dir().join("raster_retrace_test_rows.ppm");
+    ::std::fs::File::create(&filepath).unwrap().write_all(data).unwrap();
+    let filepath = filepath.to_string_lossy().into_owned();
+
+    // Rows match the image loaded at once.
+    let mut pixels: Vec<[u8; 3]> = vec![];
+    let (size, color_max) = from_filepath_rows(&filepath, None, |size, _color_max, row| {
+        assert!(row.len() == size[0]);
+        pixels.extend_from_slice(row);
+    }).unwrap();
+    assert!((size, color_max, pixels.clone()) ==
+            from_bytes_format(data, ImageFormat::PPM).unwrap());
+
+    let (image, size, _) = load_mask(&filepath, None, 0.5, None).unwrap();
+    assert!(size == [2, 2] && image == vec![true, false, true, false]);
+    let (image, _, _) = load_mask(&filepath, None, 0.0, None).unwrap();
+    assert!(image == vec![false; 4]);
+    let mut histogram = [0; HISTOGRAM_BINS];
+    let (image, _, _) = load_mask(&filepath, None, 1.0, Some(&mut histogram)).unwrap();
+    assert!(histogram[0] == 1 && histogram[16] == 1 && histogram[240] == 1 && histogram[255] == 1);
+    assert!(histogram.iter().sum::<usize>() == 4);
+    let mut data: Vec<u8> = vec![];
+    ::write_histogram_text(&mut data, &histogram, 0.5).unwrap();
+    let text = String::from_utf8(data).unwrap();
+    assert!(text.lines().count() == 32 && text.lines().nth(16).unwrap().ends_with("threshold"));
+    let mut data: Vec<u8> = vec![];
+    ::write_histogram_svg(&mut data, &histogram, 0.5).unwrap();
+    let text = String::from_utf8(data).unwrap();
+    assert!(text.matches("fill=\"black\"").count() == 4 && text.contains("x1=\"256\""));
+    assert!(image == vec![true, false, true, true]);
+    assert!(image == mask_from_pixels(255, &pixels, 1.0));
+
+    // The same data read as headerless (truncated, since the header is 11 bytes).
+    let raw = RawFormat::from_str("5x5").unwrap();
+    assert!(from_filepath_rows(&filepath, Some(&raw), |_, _, _| {}).is_err());
+    let raw = RawFormat::from_str("5x2").unwrap();
+    let mut rows_len = 0;
+    assert!(from_filepath_rows(&filepath, Some(&raw), |_, _, _| rows_len += 1).is_ok());
+    assert!(rows_len == 2);
+    ::std::fs::remove_file(&filepath).unwrap();
+}
+
+#[test]
+fn test_skeletonize_threads_match() {
+    use image_skeletonize;
+
+    // Overlapping filled circles, tall enough to be split into multiple bands.
+    let size = [120, 400];
+    let mut image = vec![false; size[0] * size[1]];
+    for y in 0..size[1] {
+        for x in 0..size[0] {
+            for &(cx, cy, r) in &[(60.0, 80.0, 40.0), (40.0, 200.0, 30.0), (80.0, 300.0, 50.0)] {
+                let (dx, dy): (f64, f64) = (x as f64 - cx, y as f64 - cy);
+                if (dx * dx + dy * dy).sqrt() < r {
+                    image[x + y * size[0]] = true;
+                }
+            }
+        }
+    }
+
+    let mut image_single = image.clone();
+    image_skeletonize::calculate_with_threads(&mut image_single, &size, 1);
+    let mut image_multi = image.clone();
+    image_skeletonize::calculate_with_threads(&mut image_multi, &size, 4);
+    assert!(image_single != image);
+    assert!(image_single == image_multi);
+}
+
+#[test]
+fn test_skeletonize_noise_topology() {
+    use image_skeletonize;
+
+    // Number of 8-connected objects.
+    let objects_len = |image: &Vec<bool>, size: &[usize; 2]| {
+        let mut image = image.clone();
+        let mut objects_len = 0;
+        for i in 0..image.len() {
+            if !image[i] {
+                continue;
+            }
+            objects_len += 1;
+            image[i] = false;
+            let mut stack = vec![i];
+            while let Some(j) = stack.pop() {
+                let (x, y) = ((j % size[0]) as i32, (j / size[0]) as i32);
+                for &(dx, dy) in &[(-1, -1), (0, -1), (1, -1), (-1, 0),
+                                   (1, 0), (-1, 1), (0, 1), (1, 1)] {
+                    let (x, y) = (x + dx, y + dy);
+                    if x >= 0 && y >= 0 && x < size[0] as i32 && y < size[1] as i32 {
+                        let k = x as usize + y as usize * size[0];
+                        if image[k] {
+                            image[k] = false;
+                            stack.push(k);
+                        }
+                    }
+                }
+            }
+        }
+        objects_len
+    };
+
+    // Dense noise, thinning removes pixels without joining or splitting objects.
+    let size = [96, 64];
+    for &density in &[30, 50, 70] {
+        let mut state: u64 = 1;
+        let image: Vec<bool> = (0..(size[0] * size[1])).map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 33) % 100 < density
+        }).collect();
+        let mut image_thin = image.clone();
+        image_skeletonize::calculate_with_threads(&mut image_thin, &size, 1);
+        assert!(image_thin != image);
+        assert!(objects_len(&image_thin, &size) == objects_len(&image, &size));
+    }
+}
+
+#[test]
+fn test_remove_isolated_pixels() {
+    use image_filter;
+
+    let size = [4, 3];
+    let mut image = vec![
+        true,  false, false, true,
+        false, false, false, true,
+        false, true,  false, false,
+    ];
+    let pixels = image_filter::remove_isolated_pixels(&mut image, &size);
+    assert!(pixels == vec![[0, 0], [1, 2]]);
+    assert!(image.iter().filter(|v| **v).count() == 2);
+}
+
+#[test]
+fn test_image_filter_open_close() {
+    use image_filter;
+
+    // A 1 pixel wide line joined to a 4x4 square (with a single pixel hole).
+    let size = [8, 6];
+    let image_orig: Vec<bool> = vec![
+        0, 0, 0, 0, 0, 0, 0, 0,
+        0, 1, 1, 1, 1, 0, 0, 0,
+        0, 1, 0, 1, 1, 1, 1, 1,
+        0, 1, 1, 1, 1, 0, 0, 0,
+        0, 1, 1, 1, 1, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0,
+    ].iter().map(|v| *v != 0).collect();
+    let count = |image: &Vec<bool>| image.iter().filter(|v| **v).count();
+
+    let mut image = image_orig.clone();
+    image_filter::close(&mut image, &size, 2);
+    assert!(image[2 + 2 * size[0]]);
+    assert!(count(&image) == count(&image_orig) + 1);
+
+    image_filter::open(&mut image, &size, 2);
+    assert!(count(&image) == 16);
+
+    // Widths of 0 & 1 have no effect.
+    let mut image = image_orig.clone();
+    image_filter::open(&mut image, &size, 1);
+    image_filter::close(&mut image, &size, 0);
+    assert!(image == image_orig);
+}
+
+#[test]
+fn test_image_mask() {
+    use image_filter;
+
+    let size = [4, 2];
+    let image_orig = vec![true, true, false, true, false, true, true, true];
+    let mask = vec![true, false, true, false, true, true, false, true];
+    let mut image = image_orig.clone();
+    assert!(image_filter::mask(&mut image, &mask) == 3);
+    assert!(image == vec![true, false, false, false, false, true, false, true]);
+
+    // Masks of a different size are an error.
+    let mut image = image_orig.clone();
+    assert!(::image_apply_mask(&mut image, &size, &(mask.clone(), [2, 4])).is_err());
+    assert!(::image_apply_mask(&mut image, &size, &(mask, size)).is_ok());
+}
+
+#[test]
+fn test_error_relative() {
+    use intern::curve_fit_nd::ErrorRelative;
+
+    let e = ErrorRelative { factor: 0.01, min: 0.1, max: 1.0 };
+    let poly = |w: f64| vec![[0.0, 0.0], [w, 0.0], [w, w], [0.0, w]];
+    assert!(e.error_threshold_for_poly(&poly(1.0)) == 0.1);
+    let diagonal = 30.0 * 2.0_f64.sqrt();
+    assert!((e.error_threshold_for_poly(&poly(30.0)) - (diagonal * 0.01)).abs() < 1e-9);
+    assert!(e.error_threshold_for_poly(&poly(1000.0)) == 1.0);
+}
+
+#[test]
+fn test_subdivide_to_limit_adaptive() {
+    use polys_utils;
+
+    // A long straight run, followed by a sharp turn.
+    let poly = vec![[0.0, 0.0], [10.0, 0.0], [20.0, 0.0], [20.0, 10.0]];
+    let poly_uniform = polys_utils::poly_subdivide_to_limit(false, &poly, 1.0);
+    let poly_adaptive = polys_utils::poly_subdivide_to_limit_adaptive(false, &poly, 1.0);
+    // The first segment is straight, so fewer points are used.
+    let count_before = |p: &Vec<[f64; 2]>, x: f64| p.iter().filter(|v| v[0] < x).count();
+    assert!(count_before(&poly_adaptive, 10.0) < count_before(&poly_uniform, 10.0));
+    // While the segments next to the turn use more.
+    assert!(poly_adaptive.len() - count_before(&poly_adaptive, 10.0) >
+            poly_uniform.len() - count_before(&poly_uniform, 10.0));
+    assert!(poly_adaptive.first() == poly.first() && poly_adaptive.last() == poly.last());
+}
+
+#[test]
+fn test_fit_end_tangent() {
+    use intern::curve_fit_nd::{
+        fit_poly_single,
+        EndTangent,
+    };
+
+    // A straight line with a small step at the start.
+    let mut poly: Vec<[f64; 2]> = (0..40).map(|i| [i as f64 * 0.5, 0.0]).collect();
+    poly[0][1] = 0.5;
+
+    let fit = |end_tangent| {
+        fit_poly_single(&poly, false, 0.1, ::std::f64::consts::PI, false, end_tangent)
+    };
+    let slope_start = |curve: &Vec<[[f64; 2]; 3]>| {
+        let k = &curve[0];
+        ((k[2][1] - k[1][1]) / (k[2][0] - k[1][0])).abs()
+    };
+
+    let curve_natural = fit(EndTangent::Natural);
+    let curve_clamped = fit(EndTangent::Clamped);
+    assert!(slope_start(&curve_clamped) < slope_start(&curve_natural));
+
+    let curve_zero = fit(EndTangent::Zero);
+    let k_first = curve_zero.first().unwrap();
+    let k_last = curve_zero.last().unwrap();
+    assert!(k_first[2] == k_first[1] && k_last[0] == k_last[1]);
+}
+
+#[test]
+fn test_fit_cyclic_wrap() {
+    use intern::curve_fit_nd::{
+        cubic_calc_point,
+        fit_poly_single,
+        Cubic,
+        EndTangent,
+    };
+
+    // A wobbly circle, the segments which wrap past the start of the polygon
+    // must be fit within the error threshold too.
+    let error_threshold = 0.05;
+    let poly: Vec<[f64; 2]> = (0..90).map(|i| {
+        let a = (i as f64 / 90.0) * ::std::f64::consts::PI * 2.0;
+        let r = 20.0 + (a * 5.0).sin();
+        [a.cos() * r, a.sin() * r]
+    }).collect();
+    let curve = fit_poly_single(
+        &poly, true, error_threshold, ::std::f64::consts::PI, false, EndTangent::Natural);
+    assert!(curve.len() > 4 && curve.len() < poly.len());
+
+    let mut points_eval: Vec<[f64; 2]> = vec![];
+    for i in 0..curve.len() {
+        let (k0, k1) = (&curve[i], &curve[(i + 1) % curve.len()]);
+        let cubic = Cubic { p0: k0[1], p1: k0[2], p2: k1[0], p3: k1[1] };
+        points_eval.extend((0..400).map(|j| cubic_calc_point(&cubic, j as f64 / 400.0)));
+    }
+    for p in &poly {
+        let dist_min = points_eval.iter().map(|q| {
+            ((p[0] - q[0]).powi(2) + (p[1] - q[1]).powi(2)).sqrt()
+        }).fold(::std::f64::MAX, f64::min);
+        assert!(dist_min < error_threshold * 1.5);
+    }
+}
+
+#[test]
+fn test_fit_precision_f32() {
+    use std::collections::LinkedList;
+    use intern::curve_fit_nd::{
+        cubic_calc_point,
+        fit_poly_list_with_params,
+        Cubic,
+        FitParams,
+    };
+
+    // Wobbly circles (cyclic & open), far from the origin so `f32` loses precision,
+    // the curves must still be within the error threshold of the points.
+    let error_threshold = 0.05;
+    let poly: Vec<[f64; 2]> = (0..90).map(|i| {
+        let a = (i as f64 / 90.0) * ::std::f64::consts::PI * 2.0;
+        let r = 20.0 + (a * 5.0).sin();
+        [1e5 + a.cos() * r, 1e5 + a.sin() * r]
+    }).collect();
+    let poly_list: LinkedList<(bool, Vec<[f64; 2]>)> =
+        vec![(true, poly.clone()), (false, poly.clone())].into_iter().collect();
+    let params = FitParams {
+        error_threshold: error_threshold,
+        use_precision_f32: true,
+        .. Default::default()
+    };
+    let (curve_list, _, stats) = fit_poly_list_with_params(
+        poly_list, &params, None, None, None, None);
+    assert!(stats.arena_bytes_peak > 0);
+
+    for (is_cyclic, curve) in curve_list {
+        assert!(curve.len() > 4 && curve.len() < poly.len());
+        let segments_len = if is_cyclic { curve.len() } else { curve.len() - 1 };
+        let mut points_eval: Vec<[f64; 2]> = vec![];
+        for i in 0..segments_len {
+            let (k0, k1) = (&curve[i], &curve[(i + 1) % curve.len()]);
+            let cubic = Cubic { p0: k0[1], p1: k0[2], p2: k1[0], p3: k1[1] };
+            points_eval.extend((0..=400).map(|j| cubic_calc_point(&cubic, j as f64 / 400.0)));
+        }
+        for p in &poly {
+            let dist_min = points_eval.iter().map(|q| {
+                ((p[0] - q[0]).powi(2) + (p[1] - q[1]).powi(2)).sqrt()
+            }).fold(::std::f64::MAX, f64::min);
+            assert!(dist_min < error_threshold * 1.5);
+        }
+    }
+}
+
+#[test]
+fn test_fit_degenerate() {
+    use intern::curve_fit_nd::{
+        fit_poly_single,
+        EndTangent,
+    };
+
+    let fit = |poly: &Vec<[f64; 2]>, is_cyclic| {
+        fit_poly_single(poly, is_cyclic, 0.1, ::std::f64::consts::PI, false, EndTangent::Natural)
+    };
+
+    for &is_cyclic in &[false, true] {
+        assert!(fit(&vec![], is_cyclic).is_empty());
+        assert!(fit(&vec![[1.0, 2.0]], is_cyclic) == vec![[[1.0, 2.0]; 3]]);
+
+        // Straight segments, with handles along the segment.
+        let curve = fit(&vec![[0.0, 0.0], [3.0, 0.0]], is_cyclic);
+        assert!(curve.len() == 2);
+        assert!(curve[0][1] == [0.0, 0.0] && curve[0][2] == [1.0, 0.0]);
+        assert!(curve[1][1] == [3.0, 0.0] && curve[1][0] == [2.0, 0.0]);
+
+        // Coincident points collapse into a single knot.
+        for points_len in 2..5 {
+            assert!(fit(&vec![[1.0, 1.0]; points_len], is_cyclic) == vec![[[1.0, 1.0]; 3]]);
+        }
+    }
+
+    // Single row & column images (thin, barcode-like).
+    for &size in &[[32, 1], [1, 32], [1, 1]] {
+        let image: Vec<bool> = (0..(size[0] * size[1])).map(|i| (i / 3) % 2 == 0).collect();
+        for &mode in &[::curve_fit_nd::TraceMode::Outline, ::curve_fit_nd::TraceMode::Centerline] {
+            let trace_params = ::TraceParams {
+                mode: mode,
+                .. ::TraceParams::default()
+            };
+            let output = ::trace_image_to_drawing(&trace_params, &image, &size, 0.75);
+            for path in &output.drawing.paths {
+                assert!(path.knots.iter().all(|k| {
+                    [k.handle_prev, k.co, k.handle_next].iter().all(|v| {
+                        v[0].is_finite() && v[1].is_finite()
+                    })
+                }));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_path_meta_from_poly() {
+    use curve_types::PathMeta;
+
+    // A 10x2 strip with a 1x1 hole (using the winding of extracted outlines).
+    let outer = vec![[0.0, 0.0], [0.0, 2.0], [10.0, 2.0], [10.0, 0.0]];
+    let hole = vec![[2.0, 1.0], [3.0, 1.0], [3.0, 2.0], [2.0, 2.0]];
+
+    let meta = PathMeta::from_poly(3, true, &outer);
+    assert!(meta.region == 3 && meta.area == 20.0 && !meta.is_hole);
+    assert!((meta.width.unwrap() - (40.0 / 24.0)).abs() < 1e-9);
+
+    let meta = PathMeta::from_poly(4, true, &hole);
+    assert!(meta.area == 1.0 && meta.is_hole);
+
+    let meta = PathMeta::from_poly(5, false, &outer);
+    assert!(meta.area == 0.0 && meta.width.is_none());
+}
+
+#[test]
+fn test_min_heap_value_with_index() {
+    use intern::min_heap::{
+        MinHeap,
+        value_with_index,
+    };
+
+    // Equal values are popped in order of their index, whatever order they're inserted.
+    let pop_order = |indices: &[usize]| {
+        let mut heap = MinHeap::new();
+        for i in indices {
+            heap.insert(value_with_index(if *i == 3 { 0.5 } else { 1.0 }, *i, true), *i);
+        }
+        let mut order = vec![];
+        while let Some(i) = heap.pop_min() {
+            order.push(i);
+        }
+        order
+    };
+    assert!(pop_order(&[0, 1, 2, 3, 4]) == vec![3, 0, 1, 2, 4]);
+    assert!(pop_order(&[4, 2, 3, 0, 1]) == vec![3, 0, 1, 2, 4]);
+    assert!(value_with_index(1.0, 7, false) == value_with_index(1.0, 2, false));
+}
+
+#[test]
+fn test_min_heap_non_copy_data() {
+    use intern::min_heap::MinHeap;
+
+    let mut heap: MinHeap<f64, Vec<usize>> = MinHeap::new();
+    let a = heap.insert(2.0, vec![2]);
+    let b = heap.insert(1.0, vec![1]);
+    heap.insert(3.0, vec![3]);
+
+    heap.node_data_mut(a).push(20);
+    assert!(*heap.node_data(a) == vec![2, 20]);
+    assert!(heap.node_value_update_with_data(b, 4.0, vec![4]) == vec![1]);
+    assert!(heap.remove(a) == vec![2, 20]);
+
+    // Free'd nodes are reused.
+    heap.insert(0.0, vec![0]);
+    let mut order = vec![];
+    while let Some(data) = heap.pop_min() {
+        order.push(data);
+    }
+    assert!(order == vec![vec![0], vec![3], vec![4]]);
+}
+
+#[test]
+fn test_min_heap_update_compact() {
+    use intern::min_heap::{
+        MinHeap,
+        NodeHandle,
+    };
+
+    let mut heap: MinHeap<f64, usize> = MinHeap::new();
+    let mut handles: Vec<NodeHandle> = (0..8).map(|i| heap.insert(i as f64, i)).collect();
+    for i in (0..8).filter(|i| i % 3 == 0) {
+        heap.remove(handles[i]);
+        handles[i] = NodeHandle::INVALID;
+    }
+    // Small updates which keep the order, as well as ones which don't.
+    heap.node_value_update(handles[4], 4.5);
+    heap.node_value_update(handles[5], 0.5);
+    heap.node_value_update(handles[1], 7.5);
+
+    heap.compact(&mut handles);
+    for i in 0..8 {
+        if handles[i] != NodeHandle::INVALID {
+            assert!(*heap.node_data(handles[i]) == i);
+        }
+    }
+    heap.node_value_update(handles[2], 9.0);
+    let mut order = vec![];
+    while let Some(i) = heap.pop_min() {
+        order.push(i);
+    }
+    assert!(order == vec![5, 4, 7, 1, 2]);
+
+    // Many small updates (which mostly keep the order) still pop in order.
+    let mut rng = ::intern::rng::Rng::new(1);
+    let mut values: Vec<f64> = (0..64).map(|_| rng.next_f64()).collect();
+    let handles: Vec<NodeHandle> = values.iter().enumerate().map(
+        |(i, &value)| heap.insert(value, i)).collect();
+    for _ in 0..1000 {
+        let i = rng.range(values.len());
+        values[i] += (rng.next_f64() - 0.5) * 0.1;
+        heap.node_value_update(handles[i], values[i]);
+    }
+    let mut order = vec![];
+    while let Some(i) = heap.pop_min() {
+        order.push(values[i]);
+    }
+    assert!(order.windows(2).all(|pair| pair[0] <= pair[1]));
+}
+
+#[test]
+fn test_rng() {
+    use intern::rng::Rng;
+
+    // The same seed & stream give the same sequence.
+    let sequence = |mut rng: Rng| (0..8).map(|_| rng.next_u64()).collect::<Vec<u64>>();
+    assert!(sequence(Rng::new(1)) == sequence(Rng::new(1)));
+    assert!(sequence(Rng::new(1)) != sequence(Rng::new(2)));
+    assert!(Rng::with_stream(1, 0) == Rng::new(1));
+    let mut rng = Rng::new(1);
+    rng.jump();
+    rng.jump();
+    assert!(rng == Rng::with_stream(1, 2));
+    assert!(sequence(Rng::with_stream(1, 1)) != sequence(Rng::with_stream(1, 2)));
+
+    let mut rng = Rng::new(3);
+    let mut counts = [0; 5];
+    for _ in 0..1000 {
+        let value = rng.next_f64();
+        assert!(value >= 0.0 && value < 1.0);
+        counts[rng.range(5)] += 1;
+    }
+    assert!(counts.iter().all(|&count| count > 100));
+
+    let mut values: Vec<usize> = (0..32).collect();
+    rng.shuffle(&mut values);
+    assert!(values != (0..32).collect::<Vec<usize>>());
+    values.sort();
+    assert!(values == (0..32).collect::<Vec<usize>>());
+
+    let mut trace_params: ::TraceParams = Default::default();
+    trace_params.seed = 7;
+    assert!(trace_params.rng_stream(1) == Rng::with_stream(7, 1));
+}
+
+#[test]
+fn test_fit_poly_list_order() {
+    use intern::curve_fit_nd::{
+        fit_poly_list_with_params,
+        FitParams,
+    };
+    use std::collections::LinkedList;
+
+    // Lengths which differ, so sorting for threading would reorder them.
+    let mut poly_list: LinkedList<(bool, Vec<[f64; 2]>)> = LinkedList::new();
+    for len in &[3, 20, 5, 12] {
+        poly_list.push_back((false, (0..*len).map(|i| [i as f64, *len as f64]).collect()));
+    }
+    let params = FitParams {
+        error_threshold: 0.1,
+        .. Default::default()
+    };
+    let (curve_list, _, _) = fit_poly_list_with_params(poly_list, &params, None, None, None, None);
+    let ends: Vec<f64> = curve_list.iter().map(|c| c.1[0][1][1]).collect();
+    assert!(ends == vec![3.0, 20.0, 5.0, 12.0]);
+}
+
+#[test]
+fn test_fit_arena_reuse() {
+    use intern::curve_fit_nd::{
+        fit_poly_list_with_params,
+        fit_poly_single,
+        EndTangent,
+        FitParams,
+    };
+    use std::collections::LinkedList;
+
+    // Polygons of decreasing size, so the arena is reused with stale data beyond the end.
+    let mut poly_list: LinkedList<(bool, Vec<[f64; 2]>)> = LinkedList::new();
+    for len in &[64, 48, 8, 32, 16] {
+        poly_list.push_back((*len % 32 == 0, (0..*len).map(|i| {
+            let a = i as f64 * ::std::f64::consts::PI * 2.0 / *len as f64;
+            [a.cos() * *len as f64, a.sin() * 4.0]
+        }).collect()));
+    }
+    let params = FitParams {
+        error_threshold: 0.1,
+        .. Default::default()
+    };
+    let (curve_list, _, stats) = fit_poly_list_with_params(
+        poly_list.clone(), &params, None, None, None, None);
+    assert!(stats.threads_len >= 1 && stats.arena_bytes_peak > 0);
+    for (&(is_cyclic, ref poly), curve) in poly_list.iter().zip(&curve_list) {
+        assert!(curve.1 == fit_poly_single(
+            poly, is_cyclic, 0.1, ::std::f64::consts::PI, false, EndTangent::Natural));
+    }
+}
+
+#[test]
+fn test_fit_max_handle_ratio() {
+    use intern::curve_fit_nd::{
+        fit_poly_list_with_params,
+        FitParams,
+    };
+    use std::collections::LinkedList;
+
+    // A circle, fit with few knots the handles are over a third of the segment length.
+    let mut poly_list: LinkedList<(bool, Vec<[f64; 2]>)> = LinkedList::new();
+    poly_list.push_back((true, (0..64).map(|i| {
+        let a = i as f64 * ::std::f64::consts::PI * 2.0 / 64.0;
+        [a.cos() * 10.0, a.sin() * 10.0]
+    }).collect()));
+    let fit = |max_handle_ratio| fit_poly_list_with_params(
+        poly_list.clone(),
+        &FitParams {
+            error_threshold: 0.1,
+            max_handle_ratio: max_handle_ratio,
+            .. Default::default()
+        },
+        None, None, None, None,
+    ).0.pop_front().unwrap().1;
+    let ratio_max = |curve: &Vec<[[f64; 2]; 3]>| {
+        let len = |a: &[f64; 2], b: &[f64; 2]| {
+            ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)).sqrt()
+        };
+        let mut ratio_max: f64 = 0.0;
+        for i in 0..curve.len() {
+            let (k_curr, k_next) = (&curve[i], &curve[(i + 1) % curve.len()]);
+            let chord = len(&k_curr[1], &k_next[1]);
+            ratio_max = ratio_max.max(len(&k_curr[1], &k_curr[2]) / chord);
+            ratio_max = ratio_max.max(len(&k_next[1], &k_next[0]) / chord);
+        }
+        ratio_max
+    };
+    let curve = fit(0.0);
+    assert!(ratio_max(&curve) > 0.34);
+    // Limiting handles requires more knots to stay within the error.
+    let curve_clamp = fit(0.34);
+    assert!(ratio_max(&curve_clamp) <= 0.34 + 1e-9);
+    assert!(curve_clamp.len() > curve.len());
+}
+
+#[test]
+fn test_fit_solvers() {
+    use intern::curve_fit_nd::{
+        fit_poly_list_with_params,
+        FitParams,
+        Solver,
+        SOLVERS_DEFAULT,
+    };
+    use std::collections::LinkedList;
+
+    // A circle & a wave.
+    let mut poly_list: LinkedList<(bool, Vec<[f64; 2]>)> = LinkedList::new();
+    poly_list.push_back((true, (0..64).map(|i| {
+        let a = i as f64 * ::std::f64::consts::PI * 2.0 / 64.0;
+        [a.cos() * 10.0, a.sin() * 10.0]
+    }).collect()));
+    poly_list.push_back((false, (0..80).map(|i| {
+        [i as f64 * 0.5, (i as f64 * 0.2).sin() * 4.0]
+    }).collect()));
+    let fit = |solvers: &[Solver]| {
+        let params = FitParams {
+            error_threshold: 0.1,
+            solvers: solvers.to_vec(),
+            .. Default::default()
+        };
+        let (curve_list, _, stats) = fit_poly_list_with_params(
+            poly_list.clone(), &params, None, None, None, None);
+        let segments_len: usize = curve_list.iter().map(
+            |&(is_cyclic, ref curve)| if is_cyclic { curve.len() } else { curve.len() - 1 }).sum();
+        // Every segment has points between its knots, so each one is counted.
+        assert!(stats.solver_segments_len.iter().sum::<usize>() == segments_len);
+        stats.solver_segments_len
+    };
+
+    let segments_len = fit(SOLVERS_DEFAULT);
+    assert!(segments_len[Solver::Offset as usize] != 0);
+
+    // Only the solvers used (and the fallback) are counted.
+    let segments_len = fit(&[Solver::Offset]);
+    assert!(segments_len[Solver::Offset as usize] != 0);
+    assert!(segments_len[Solver::Circle as usize] == 0);
+    assert!(segments_len[Solver::LeastSquare as usize] == 0);
+    let segments_len = fit(&[]);
+    assert!(segments_len.iter().sum::<usize>() == segments_len[Solver::Fallback as usize]);
+}
+
+#[test]
+fn test_fit_solver_stats() {
+    use intern::curve_fit_nd::{
+        fit_poly_list_with_params,
+        FitParams,
+        Reparameterize,
+        Solver,
+        REPARAMETERIZE_DEFAULT,
+        SOLVERS_DEFAULT,
+    };
+    use std::collections::LinkedList;
+
+    let mut poly_list: LinkedList<(bool, Vec<[f64; 2]>)> = LinkedList::new();
+    poly_list.push_back((false, (0..80).map(|i| {
+        [i as f64 * 0.5, (i as f64 * 0.2).sin() * 4.0]
+    }).collect()));
+    let fit = |solvers: &[Solver], reparameterize: Reparameterize| {
+        let params = FitParams {
+            error_threshold: 0.1,
+            solvers: solvers.to_vec(),
+            reparameterize: reparameterize,
+            .. Default::default()
+        };
+        let (_, _, stats) = fit_poly_list_with_params(
+            poly_list.clone(), &params, None, None, None, None);
+        stats
+    };
+
+    // Every fit is counted, not only the resulting segments.
+    let stats = fit(SOLVERS_DEFAULT, REPARAMETERIZE_DEFAULT);
+    let fits_len = stats.solver_stats.wins_len.iter().sum::<usize>();
+    assert!(fits_len > stats.solver_segments_len.iter().sum::<usize>());
+    assert!(stats.solver_stats.least_square_len == fits_len);
+    assert!(stats.solver_stats.reparameterize_iterations_len != 0);
+    assert!(stats.solver_stats.reparameterize_iterations_len <=
+            fits_len * REPARAMETERIZE_DEFAULT.iterations);
+
+    let stats = fit(&[Solver::Circle], Reparameterize { iterations: 0, epsilon: 0.0 });
+    assert!(stats.solver_stats.wins_len[Solver::Circle as usize] != 0);
+    assert!(stats.solver_stats.wins_len[Solver::LeastSquare as usize] == 0);
+    assert!(stats.solver_stats.least_square_len == 0);
+
+    let stats = fit(&[Solver::LeastSquare], Reparameterize { iterations: 0, epsilon: 0.0 });
+    assert!(stats.solver_stats.least_square_len != 0);
+    assert!(stats.solver_stats.reparameterize_iterations_len == 0);
+}
+
+#[test]
+fn test_fit_exhaustive_prune() {
+    use intern::curve_fit_nd::{
+        fit_poly_list_with_params,
+        ExhaustivePrune,
+        FitParams,
+        EXHAUSTIVE_PRUNE_DEFAULT,
+    };
+    use std::collections::LinkedList;
+
+    // An irregular wobbly circle (so candidates rarely fit with equal error).
+    let mut poly_list: LinkedList<(bool, Vec<[f64; 2]>)> = LinkedList::new();
+    poly_list.push_back((true, (0..256).map(|i| {
+        let a = i as f64 * ::std::f64::consts::PI * 2.0 / 256.0;
+        let r = 64.0 + (a * 3.0 + 0.3).sin() * 4.0 + (a * 7.0 + 1.1).sin() * 1.5;
+        [a.cos() * r, a.sin() * r]
+    }).collect()));
+    let fit_with_stats = |use_optimize_exhaustive: bool, exhaustive_prune: ExhaustivePrune| {
+        let params = FitParams {
+            error_threshold: 0.25,
+            use_optimize_exhaustive: use_optimize_exhaustive,
+            exhaustive_prune: exhaustive_prune,
+            .. Default::default()
+        };
+        let (mut curve_list, _, stats) = fit_poly_list_with_params(
+            poly_list.clone(), &params, None, None, None, None);
+        let fits_len: usize = stats.solver_stats.wins_len.iter().sum();
+        (curve_list.pop_front().unwrap().1, fits_len)
+    };
+    let fit = |use_optimize_exhaustive: bool, exhaustive_prune: ExhaustivePrune| {
+        fit_with_stats(use_optimize_exhaustive, exhaustive_prune).0
+    };
+    let curve_greedy = fit(false, EXHAUSTIVE_PRUNE_DEFAULT);
+    let curve_full = fit(true, ExhaustivePrune { epsilon: 0.0, steps: 0 });
+    let curve_pruned = fit(true, EXHAUSTIVE_PRUNE_DEFAULT);
+    assert!(curve_full.len() <= curve_greedy.len());
+    assert!(curve_pruned.len() <= curve_greedy.len());
+    // Pruning is ignored unless fitting exhaustively.
+    assert!(curve_greedy == fit(false, ExhaustivePrune { epsilon: 0.0, steps: 0 }));
+    // Pruning skips candidates.
+    assert!(fit_with_stats(true, EXHAUSTIVE_PRUNE_DEFAULT).1 <
+            fit_with_stats(true, ExhaustivePrune { epsilon: 0.0, steps: 0 }).1);
+    // Testing every candidate finds the same best candidates in any order.
+    assert!(curve_full == fit(true, ExhaustivePrune { epsilon: 0.0, steps: ::std::usize::MAX }));
+}
+
+#[test]
+fn test_fit_weights() {
+    use intern::curve_fit_nd::{
+        fit_poly_list_with_params,
+        FitParams,
+    };
+    use std::collections::LinkedList;
+
+    // A square, only its corners are weighted.
+    let mut poly: Vec<[f64; 2]> = vec![];
+    for side in 0..4 {
+        let a = side as f64 * ::std::f64::consts::PI / 2.0;
+        let (dir, normal) = ([a.cos(), a.sin()], [-a.sin(), a.cos()]);
+        for i in 0..16 {
+            let t = i as f64 - 8.0;
+            poly.push([normal[0] * -8.0 + dir[0] * t, normal[1] * -8.0 + dir[1] * t]);
+        }
+    }
+    let weights = ::polys_utils::poly_corner_weights(
+        true, &poly, 30.0_f64.to_radians(), 4.0);
+    assert!(weights.iter().enumerate().all(|(i, w)| *w == if i % 16 == 0 { 4.0 } else { 1.0 }));
+
+    let mut poly_list: LinkedList<(bool, Vec<[f64; 2]>)> = LinkedList::new();
+    poly_list.push_back((true, poly));
+    let fit = |weight_list: Option<&[Vec<f64>]>| fit_poly_list_with_params(
+        poly_list.clone(), &FitParams::default(), weight_list, None, None, None,
+    ).0.pop_front().unwrap().1;
+    // Uniform weights match unweighted fitting.
+    assert!(fit(None) == fit(Some(&[vec![1.0; 64]])));
+    let curve = fit(Some(&[weights]));
+    assert!(curve.iter().all(|knot| knot.iter().all(|v| v[0].is_finite() && v[1].is_finite())));
+}
+
+#[test]
+fn test_fit_anchors() {
+    use intern::curve_fit_nd::{
+        fit_poly_list_with_params,
+        FitParams,
+    };
+    use std::collections::LinkedList;
+
+    let anchors = ::anchors_from_str("# Registration marks.\n10.5 0\n\n0, 10.5\n").unwrap();
+    assert!(anchors == vec![[10.5, 0.0], [0.0, 10.5]]);
+    assert!(::anchors_from_str("10").is_err());
+    assert!(::anchors_from_str("10 x").is_err());
+
+    // A circle, fit with few knots, anchors snap to the nearest points which are kept.
+    let mut poly_list: LinkedList<(bool, Vec<[f64; 2]>)> = LinkedList::new();
+    poly_list.push_back((true, (0..64).map(|i| {
+        let a = i as f64 * ::std::f64::consts::PI * 2.0 / 64.0;
+        [a.cos() * 10.0, a.sin() * 10.0]
+    }).collect()));
+    let anchor_list = ::polys_utils::poly_list_snap_anchors(&poly_list, &anchors);
+    assert!(anchor_list == vec![vec![0, 16]]);
+    let params = FitParams {
+        error_threshold: 0.1,
+        .. Default::default()
+    };
+    let fit = |anchor_list: Option<&[Vec<usize>]>| fit_poly_list_with_params(
+        poly_list.clone(), &params, None, anchor_list, None, None,
+    ).0.pop_front().unwrap().1;
+    let has_knot = |curve: &Vec<[[f64; 2]; 3]>, i: usize| {
+        curve.iter().any(|knot| knot[1] == poly_list.front().unwrap().1[i])
+    };
+    assert!(!has_knot(&fit(None), 16));
+    let curve = fit(Some(&anchor_list));
+    assert!(has_knot(&curve, 0) && has_knot(&curve, 16));
+}
+
+#[test]
+fn test_fit_refine_log() {
+    use intern::curve_fit_nd::{
+        fit_poly_list_with_params,
+        FitParams,
+        RefineStage,
+    };
+    use std::collections::LinkedList;
+
+    // A circle & a wave.
+    let mut poly_list: LinkedList<(bool, Vec<[f64; 2]>)> = LinkedList::new();
+    poly_list.push_back((true, (0..64).map(|i| {
+        let a = i as f64 * ::std::f64::consts::PI * 2.0 / 64.0;
+        [a.cos() * 10.0, a.sin() * 10.0]
+    }).collect()));
+    poly_list.push_back((false, (0..80).map(|i| {
+        [i as f64 * 0.5, (i as f64 * 0.2).sin() * 4.0]
+    }).collect()));
+
+    let fit = |use_refine_log| fit_poly_list_with_params(
+        poly_list.clone(),
+        &FitParams {
+            error_threshold: 0.1,
+            use_refine_log: use_refine_log,
+            .. Default::default()
+        },
+        None, None, None, None);
+    let (curve_list, refine_log, _) = fit(true);
+    assert!(fit(false).1.iter().all(|steps| steps.len() == 0));
+    // Logging doesn't change the result.
+    assert!(curve_list == fit(false).0);
+
+    // Each removal is logged (refitting may move a knot instead of removing it).
+    assert!(refine_log.len() == 2);
+    for ((poly, curve), steps) in poly_list.iter().zip(&curve_list).zip(&refine_log) {
+        let removed_len = steps.iter().filter(|step| {
+            step.stage == RefineStage::Remove || step.co_refit.is_none()
+        }).count();
+        assert!(poly.1.len() - curve.1.len() == removed_len);
+        assert!(steps.iter().all(|step| step.error <= 0.1));
+    }
+}
+
+#[test]
+fn test_fit_snapshots() {
+    use intern::curve_fit_nd::{
+        fit_poly_list_with_params,
+        FitObserver,
+        FitParams,
+        FitSnapshot,
+        FitStage,
+    };
+    use std::collections::LinkedList;
+    use std::sync::Mutex;
+
+    struct Observer(Mutex<Vec<(usize, bool, FitSnapshot)>>);
+    impl FitObserver for Observer {
+        fn snapshot(&self, index: usize, is_cyclic: bool, snapshot: &FitSnapshot) {
+            self.0.lock().unwrap().push((index, is_cyclic, snapshot.clone()));
+        }
+    }
+
+    // A square (with points along each side) & a circle.
+    let mut poly_list: LinkedList<(bool, Vec<[f64; 2]>)> = LinkedList::new();
+    poly_list.push_back((true, (0..64).map(|i| {
+        let t = (i % 16) as f64;
+        match i / 16 {
+            0 => [t, 0.0],
+            1 => [16.0, t],
+            2 => [16.0 - t, 16.0],
+            _ => [0.0, 16.0 - t],
+        }
+    }).collect()));
+    poly_list.push_back((true, (0..64).map(|i| {
+        let a = i as f64 * ::std::f64::consts::PI * 2.0 / 64.0;
+        [a.cos() * 10.0, a.sin() * 10.0]
+    }).collect()));
+
+    let observer = Observer(Mutex::new(vec![]));
+    let params = FitParams {
+        corner_angle: ::std::f64::consts::PI / 6.0,
+        .. Default::default()
+    };
+    let fit = |observer: Option<&dyn FitObserver>| fit_poly_list_with_params(
+        poly_list.clone(), &params, None, None, None, observer,
+    ).0;
+    let curve_list = fit(Some(&observer));
+    // Observing doesn't change the result.
+    assert!(curve_list == fit(None));
+
+    let mut snapshots = observer.0.into_inner().unwrap();
+    snapshots.sort_by_key(|&(index, _, _)| index);
+    assert!(snapshots.len() == 4);
+    for (i, curve) in curve_list.iter().enumerate() {
+        let stages = &snapshots[(i * 2)..(i * 2 + 2)];
+        assert!(stages.iter().all(|&(index, is_cyclic, _)| index == i && is_cyclic));
+        assert!(stages[0].2.stage == FitStage::PostRemove);
+        assert!(stages[1].2.stage == FitStage::PostCorner);
+        // Refitting only removes (or moves) knots.
+        assert!(curve.1.len() <= stages[1].2.knots.len());
+    }
+    // Removal rounds the square's corners, which are split again
+    // (a corner which was kept as a knot isn't split).
+    let corners = |i: usize| -> Vec<[f64; 2]> {
+        snapshots[i].2.knots.iter().filter(|k| k.is_corner).map(|k| k.co).collect()
+    };
+    assert!(corners(0).len() == 0 && corners(1).len() >= 3);
+    assert!(snapshots[1].2.knots.len() > snapshots[0].2.knots.len());
+    assert!(corners(1).iter().all(|co| co[0] % 16.0 == 0.0 && co[1] % 16.0 == 0.0));
+    // The circle has no corners.
+    assert!(corners(3).len() == 0);
+}
+
+#[test]
+fn test_trace_params_metadata() {
+    let trace_params = ::TraceParams {
+        turn_policy: polys_from_raster_outline::TurnPolicy::Random(4),
+        turn_policy_seed: Some(4),
+        .. ::TraceParams::default()
+    };
+    let items = trace_params.metadata_items();
+    let value = |name: &str| items.iter().find(|item| item.0 == name).unwrap().1.clone();
+    assert!(value("turnpolicy") == "RANDOM:4");
+    assert!(value("corner") == "30");
+    assert!(value("transform") == "matrix(1 0 0 1 0 0)");
+}
+
+#[test]
+fn test_glif_write() {
+    use curve_types::{
+        Drawing,
+        Path,
+    };
+    use curve_write;
+    use std::io::Read;
+
+    // A straight sided square and an open curve.
+    let mut drawing = Drawing::new();
+    drawing.paths.push_back(Path::from_cubic_array(true, &[
+        [[0.0, 0.0], [0.0, 0.0], [0.0, 0.0]],
+        [[0.0, 10.0], [0.0, 10.0], [0.0, 10.0]],
+        [[10.0, 10.0], [10.0, 10.0], [10.0, 10.0]],
+        [[10.0, 0.0], [10.0, 0.0], [10.0, 0.0]],
+    ]));
+    drawing.paths.push_back(Path::from_cubic_array(false, &[
+        [[0.0, 5.0], [0.0, 5.0], [2.0, 4.0]],
+        [[8.0, 4.0], [10.0, 5.0], [10.0, 5.0]],
+    ]));
+
+    let filepath = ::std::env::temp_dir().join("raster_retrace_test.glif");
+    {
+        let f = ::std::fs::File::create(&filepath).unwrap();
+        curve_write::glif::write_glyph(&f, "a", &[20, 10], &drawing, 1000.0, 0.0, None).unwrap();
+    }
+    let mut text = String::new();
+    ::std::fs::File::open(&filepath).unwrap().read_to_string(&mut text).unwrap();
+    ::std::fs::remove_file(&filepath).unwrap();
+
+    assert!(text.contains("<glyph name=\"a\" format=\"2\">"));
+    assert!(text.contains("<advance width=\"2000\"/>"));
+    assert!(text.matches("type=\"line\"").count() == 4);
+    assert!(text.contains("<point x=\"0\" y=\"500\" type=\"move\"/>"));
+    assert!(text.contains("<point x=\"200\" y=\"400\"/>"));
+    assert!(text.contains("<point x=\"1000\" y=\"500\" type=\"curve\"/>"));
+}
+
+#[test]
+fn test_pdf_write() {
+    use curve_types::{
+        Drawing,
+        Path,
+    };
+    use curve_write::pdf;
+
+    let mut drawing = Drawing::new();
+    drawing.paths.push_back(Path::from_cubic_array(true, &[
+        [[0.0, 0.0], [0.0, 0.0], [0.0, 0.0]],
+        [[0.0, 10.0], [0.0, 10.0], [0.0, 10.0]],
+        [[10.0, 10.0], [10.0, 10.0], [10.0, 10.0]],
+    ]));
+
+    // Offset by `view_min` & scaled (the drawing is already Y-up).
+    let mut content: Vec<u8> = vec![];
+    pdf::write_page_content(
+        &mut content, &[0.0, -10.0], 2.0, &drawing, true, &[], 0.0).unwrap();
+    let content = String::from_utf8(content).unwrap();
+    assert!(content.contains("0.00 20.00 m\n"));
+    assert!(content.contains("0.00 20.00 0.00 40.00 0.00 40.00 c\n"));
+    assert!(content.ends_with("h\nf\n"));
+
+    let pages: Vec<pdf::Page> = (0..2).map(|i| pdf::Page {
+        size: [40.0, 20.0 * (i + 1) as f64],
+        content: content.clone().into_bytes(),
+    }).collect();
+    let params = vec![("input", "(a).ppm".to_string())];
+    let mut data: Vec<u8> = vec![];
+    pdf::write_document(&mut data, &pages, Some(&params)).unwrap();
+    let text = String::from_utf8_lossy(&data).into_owned();
+    assert!(text.starts_with("%PDF-1.4\n"));
+    assert!(text.ends_with("%%EOF\n"));
+    assert!(text.contains("/Count 2 /Kids [4 0 R 6 0 R]"));
+    assert!(text.contains("/MediaBox [0 0 40.00 40.00]"));
+    assert!(text.contains("/input (\\(a\\).ppm)"));
+
+    // Each entry of the cross reference table is the offset of its object.
+    let xref_offset: usize = text.rsplit("startxref\n").next().unwrap()
+        .lines().next().unwrap().parse().unwrap();
+    // Offsets are in bytes, the header comment isn't valid UTF8.
+    let offsets: Vec<usize> = String::from_utf8_lossy(&data[xref_offset..]).lines().skip(3).take(7)
+        .map(|line| line[..10].parse().unwrap()).collect();
+    for (i, &offset) in offsets.iter().enumerate() {
+        assert!(data[offset..].starts_with(format!("{} 0 obj", i + 1).as_bytes()));
+    }
+}
+
+#[test]
+fn test_trace_images_montage() {
+    use std::io::Read;
+
+    // A filled square, traced three times into two columns.
+    let size = [8, 6];
+    let mut image = vec![false; size[0] * size[1]];
+    for y in 1..5 {
+        for x in 2..6 {
+            image[y * size[0] + x] = true;
+        }
+    }
+    let images = vec![(image.clone(), size), (image.clone(), size), (image, size)];
+
+    let filepath = ::std::env::temp_dir().join("raster_retrace_test_montage.svg");
+    let trace_params = ::TraceParams {
+        output_filepath: filepath.to_string_lossy().into_owned(),
+        output_scale: 2.0,
+        .. ::TraceParams::default()
+    };
+    ::trace_images_montage(&trace_params, &images, 2, 0.75).unwrap();
+    let mut text = String::new();
+    ::std::fs::File::open(&filepath).unwrap().read_to_string(&mut text).unwrap();
+    ::std::fs::remove_file(&filepath).unwrap();
+
+    assert!(text.contains("width='32'"));
+    assert!(text.contains("height='24'"));
+    assert!(text.contains("<g id='input-1' transform='translate(16 0)'>"));
+    assert!(text.contains("<g id='input-2' transform='translate(0 12)'>"));
+    assert!(text.matches("<path").count() == 3);
+}
+
+#[test]
+fn test_trace_image_diff() {
+    use std::io::Read;
+
+    // Filled squares, one is moved and another removed.
+    let size = [12, 6];
+    let image_from_squares = |squares: &[usize]| {
+        let mut image = vec![false; size[0] * size[1]];
+        for &x_min in squares {
+            for y in 1..5 {
+                for x in x_min..(x_min + 3) {
+                    image[y * size[0] + x] = true;
+                }
+            }
+        }
+        image
+    };
+    let image = image_from_squares(&[1, 8]);
+    let image_other = image_from_squares(&[2]);
+    let mut image_added = image_other.clone();
+    assert!(::image_filter::subtract(&mut image_added, &image) == 8);
+
+    let filepath = ::std::env::temp_dir().join("raster_retrace_test_diff.svg");
+    let trace_params = ::TraceParams {
+        output_filepath: filepath.to_string_lossy().into_owned(),
+        .. ::TraceParams::default()
+    };
+    ::trace_image_diff(&trace_params, &image, &image_other, &size, 0.75).unwrap();
+    let mut text = String::new();
+    ::std::fs::File::open(&filepath).unwrap().read_to_string(&mut text).unwrap();
+    ::std::fs::remove_file(&filepath).unwrap();
+
+    let (text_added, text_removed) = text.split_at(text.find("label='Removed'").unwrap());
+    assert!(text_added.contains("label='Added'"));
+    // A column added to the right, the left column & a square removed.
+    assert!(text_added.matches(" Z").count() == 1);
+    assert!(text_removed.matches(" Z").count() == 2);
+}
+
+#[test]
+fn test_output_origin() {
+    // A filled square in the top-left corner.
+    let size = [8, 6];
+    let image: Vec<bool> = (0..(size[0] * size[1])).map(|i| {
+        let (x, y) = (i % size[0], i / size[0]);
+        x < 4 && y < 2
+    }).collect();
+    let bounds = |use_flip_y, origin| {
+        let trace_params = ::TraceParams {
+            use_flip_y: use_flip_y,
+            origin: origin,
+            .. ::TraceParams::default()
+        };
+        let output = ::trace_image_to_drawing(&trace_params, &image, &size, 0.75);
+        output.drawing.bounds()
+    };
+    // Compare the offset from the default origin.
+    let offset = |use_flip_y, origin| {
+        let (a, b) = (bounds(use_flip_y, None).0, bounds(use_flip_y, Some(origin)).0);
+        [b[0] - a[0], b[1] - a[1]]
+    };
+    assert!(offset(false, ::Origin::TopLeft) == [0.0, 0.0]);
+    assert!(offset(false, ::Origin::BottomLeft) == [0.0, -6.0]);
+    assert!(offset(false, ::Origin::Center) == [-4.0, -3.0]);
+    assert!(offset(true, ::Origin::TopLeft) == [0.0, -6.0]);
+    assert!(offset(true, ::Origin::BottomLeft) == [0.0, 0.0]);
+    assert!(offset(true, ::Origin::Center) == [-4.0, -3.0]);
+
+    // The view is moved to show the image.
+    let trace_params = ::TraceParams {
+        origin: Some(::Origin::Center),
+        output_scale: 2.0,
+        .. ::TraceParams::default()
+    };
+    let text = ::Tracer::new(&trace_params, image, &size).trace_to_svg().unwrap();
+    assert!(text.contains("viewBox='-8 -6 16 12'"));
+}
+
+#[test]
+fn test_output_flip_y() {
+    // A filled rectangle near the top of the image (Y-down).
+    let size = [20, 20];
+    let image: Vec<bool> = (0..(size[0] * size[1])).map(|i| {
+        let (x, y) = (i % size[0], i / size[0]);
+        x >= 4 && x < 16 && y >= 2 && y < 6
+    }).collect();
+    // The Y coordinates written for each format.
+    let y_values = |output_format, use_flip_y| -> Vec<f64> {
+        let trace_params = ::TraceParams {
+            output_format: Some(output_format),
+            use_flip_y: use_flip_y,
+            use_metadata: false,
+            .. ::TraceParams::default()
+        };
+        let output = ::trace_image_to_drawing(&trace_params, &image, &size, 0.75);
+        let mut data: Vec<u8> = vec![];
+        ::write_output_to(&mut data, &trace_params, &size, &output).unwrap();
+        // PDF has binary bytes in a comment.
+        let text = String::from_utf8_lossy(&data);
+        let y_values: Vec<f64> = match output_format {
+            // Coordinates are written as `x,y` pairs.
+            ::OutputFormat::Svg => {
+                let d = text.split("<path d='").nth(1).unwrap().split('\'').next().unwrap();
+                d.split_whitespace().filter_map(|v| v.split(',').nth(1)).map(
+                    |v| v.parse().unwrap()).collect()
+            }
+            ::OutputFormat::Glif => {
+                text.split(" y=\"").skip(1).map(
+                    |v| v.split('"').next().unwrap().parse().unwrap()).collect()
+            }
+            // The operands of move-to operators.
+            ::OutputFormat::Pdf => {
+                text.lines().filter(|line| line.ends_with(" m")).map(
+                    |line| line.split(' ').nth(1).unwrap().parse().unwrap()).collect()
+            }
+            ::OutputFormat::Json => unreachable!(),
+        };
+        assert!(y_values.len() != 0);
+        y_values
+    };
+    // Whether the rectangle is in the upper half of the image, as each format is displayed.
+    let is_upper = |output_format, use_flip_y, height: f64| {
+        let y_values = y_values(output_format, use_flip_y);
+        let is_y_up = output_format != ::OutputFormat::Svg;
+        let is_low = y_values.iter().all(|&y| y < height / 2.0);
+        let is_high = y_values.iter().all(|&y| y > height / 2.0);
+        assert!(is_low != is_high);
+        is_high == is_y_up
+    };
+    // SVG is Y-down, flipping moves the rectangle to the bottom.
+    assert!(is_upper(::OutputFormat::Svg, false, 20.0));
+    assert!(!is_upper(::OutputFormat::Svg, true, 20.0));
+    // GLIF & PDF are Y-up, always flipped so the rectangle stays at the top
+    // (the image height is scaled to 1000 font units).
+    for &use_flip_y in &[false, true] {
+        assert!(is_upper(::OutputFormat::Glif, use_flip_y, 1000.0));
+        assert!(is_upper(::OutputFormat::Pdf, use_flip_y, 20.0));
+    }
+}
+
+#[test]
+fn test_trace_bytes() {
+    // A black square on a white background, with a transparent border (treated as white).
+    let size = [8, 6];
+    let mut image_data = vec![0u8; size[0] * size[1] * 4];
+    for y in 0..size[1] {
+        for x in 0..size[0] {
+            let p = &mut image_data[(y * size[0] + x) * 4..][..4];
+            if x == 0 || y == 0 {
+                continue;
+            }
+            let v = if x >= 2 && x < 6 && y >= 1 && y < 5 { 0 } else { 255 };
+            p.copy_from_slice(&[v, v, v, 255]);
+        }
+    }
+    let trace_params = ::TraceParams::default();
+    let text = ::trace_bytes(&image_data, size[0], size[1], &trace_params).unwrap();
+    assert!(text.starts_with("<?xml"));
+    assert!(text.contains("width='8'"));
+    assert!(text.matches("<path").count() == 1);
+    assert!(text.trim_end().ends_with("</svg>"));
+
+    // The wrong number of bytes is an error.
+    assert!(::trace_bytes(&image_data[1..], size[0], size[1], &trace_params).is_err());
+}
+
+#[test]
+fn test_preview_png_flip_y() {
+    use intern::image_load::{
+        from_bytes_format,
+        ImageFormat,
+    };
+
+    // A filled rectangle & an isolated pixel (written as a dot) near the top of the image.
+    let size = [20, 20];
+    let mut image: Vec<bool> = (0..(size[0] * size[1])).map(|i| {
+        let (x, y) = (i % size[0], i / size[0]);
+        x >= 8 && x < 16 && y >= 2 && y < 6
+    }).collect();
+    image[3 + (3 * size[0])] = true;
+    for &output_format in &[::OutputFormat::Svg, ::OutputFormat::Glif, ::OutputFormat::Pdf] {
+        let trace_params = ::TraceParams {
+            output_format: Some(output_format),
+            dots: ::DotMode::Circle,
+            .. ::TraceParams::default()
+        };
+        let output = ::trace_image_to_drawing(&trace_params, &image, &size, 0.75);
+        let mut data: Vec<u8> = vec![];
+        ::write_preview_png(&mut data, &trace_params, &size, &output).unwrap();
+        // The preview is displayed Y-down, like the input (composited over white).
+        let (size_preview, _, pixels) = from_bytes_format(&data, ImageFormat::PNG).unwrap();
+        assert!(size_preview == size);
+        let is_filled = |x: usize, y: usize| pixels[x + (y * size[0])][0] < 128;
+        assert!(is_filled(12, 4) && !is_filled(12, 15));
+        assert!(is_filled(3, 3) && !is_filled(3, 16));
+    }
+}
+
+#[test]
+fn test_tracer_refit() {
+    // A disc, so fitting depends on the error threshold.
+    let size = [24, 20];
+    let image: Vec<bool> = (0..(size[0] * size[1])).map(|i| {
+        let (x, y) = ((i % size[0]) as f64 - 11.5, (i / size[0]) as f64 - 9.5);
+        x * x + y * y < 64.0
+    }).collect();
+
+    let mut trace_params = ::TraceParams::default();
+    let mut tracer = ::Tracer::new(&trace_params, image.clone(), &size);
+    // Compare with tracing from the start.
+    let trace_svg = |trace_params: &::TraceParams| {
+        ::Tracer::new(trace_params, image.clone(), &size).trace_to_svg().unwrap()
+    };
+    assert!(tracer.trace_to_svg().unwrap() == trace_svg(&trace_params));
+
+    // Only fitting changes.
+    trace_params.error_threshold = 0.1;
+    trace_params.corner_threshold = 0.5;
+    assert!(tracer.set_trace_params(&trace_params) == false);
+    assert!(tracer.trace_to_svg().unwrap() == trace_svg(&trace_params));
+
+    // Extraction changes.
+    trace_params.simplify_threshold = 1.0;
+    assert!(tracer.set_trace_params(&trace_params) == true);
+    assert!(tracer.trace_to_svg().unwrap() == trace_svg(&trace_params));
+    assert!(tracer.trace().drawing.paths.len() == 1);
+}
+
+#[test]
+fn test_trace_image_lod() {
+    assert!(::lod_filepath("out.svg", 2) == "out_lod2.svg");
+
+    // A disc, so fitting depends on the error threshold.
+    let size = [24, 20];
+    let image: Vec<bool> = (0..(size[0] * size[1])).map(|i| {
+        let (x, y) = ((i % size[0]) as f64 - 11.5, (i / size[0]) as f64 - 9.5);
+        x * x + y * y < 64.0
+    }).collect();
+    let filepath = ::std::env::temp_dir().join("raster_retrace_test_lod.svg")
+        .to_string_lossy().into_owned();
+    let trace_params = ::TraceParams {
+        output_filepath: filepath.clone(),
+        lod: vec![0.1, 4.0],
+        .. ::TraceParams::default()
+    };
+
+    // Each level matches tracing with its error threshold.
+    ::trace_image_lod(&trace_params, &image, &size, 0.75).unwrap();
+    for (i, &error_threshold) in trace_params.lod.iter().enumerate() {
+        let filepath_level = ::lod_filepath(&filepath, i);
+        let text = ::std::fs::read_to_string(&filepath_level).unwrap();
+        ::std::fs::remove_file(&filepath_level).unwrap();
+        let trace_params_level = ::TraceParams {
+            error_threshold: error_threshold,
+            output_filepath: filepath_level,
+            .. trace_params.clone()
+        };
+        let output = ::trace_image_to_drawing(&trace_params_level, &image, &size, 0.75);
+        let mut data: Vec<u8> = vec![];
+        ::write_svg(&mut data, &trace_params_level, &size, &output).unwrap();
+        assert!(text == String::from_utf8(data).unwrap());
+    }
+
+    // All levels as layers of one file.
+    let trace_params_layers = ::TraceParams {
+        use_svg_layers: true,
+        .. trace_params.clone()
+    };
+    ::trace_image_lod(&trace_params_layers, &image, &size, 0.75).unwrap();
+    let text = ::std::fs::read_to_string(&filepath).unwrap();
+    ::std::fs::remove_file(&filepath).unwrap();
+    assert!(text.contains("inkscape:label='LOD-0'") && text.contains("inkscape:label='LOD-1'"));
+    assert!(text.matches("<path").count() == 2);
+}
+
+#[test]
+fn test_extract_cache() {
+    // A disc & an isolated pixel (written as a dot).
+    let size = [24, 20];
+    let mut image: Vec<bool> = (0..(size[0] * size[1])).map(|i| {
+        let (x, y) = ((i % size[0]) as f64 - 11.5, (i / size[0]) as f64 - 9.5);
+        x * x + y * y < 36.0
+    }).collect();
+    image[size[0] + 1] = true;
+    let dirpath = ::std::env::temp_dir().join("raster_retrace_test_cache");
+    let trace_params = ::TraceParams {
+        cache_dirpath: dirpath.to_string_lossy().into_owned(),
+        dots: ::DotMode::Circle,
+        .. ::TraceParams::default()
+    };
+
+    // Reading gives the same polygons as extracting.
+    let key = ::extract_cache::Key::new(&trace_params, &image, &size, 0.75);
+    assert!(::extract_cache::read(&key, image.len()).is_none());
+    let extract = ::trace_image_extract(&trace_params, &image, &size, 0.75, &());
+    ::extract_cache::write(&key, &extract).unwrap();
+    let extract_cache = ::extract_cache::read(&key, image.len()).unwrap();
+    assert!(extract_cache.image == extract.image);
+    assert!(extract_cache.dots == extract.dots && extract.dots.len() == 1);
+    assert!(extract_cache.meta_list == extract.meta_list);
+    assert!(extract_cache.measurements == extract.measurements);
+    assert!(extract_cache.poly_list_to_fit == extract.poly_list_to_fit);
+
+    // Only parameters used for extraction change the key.
+    let trace_params_fit = ::TraceParams { error_threshold: 4.0, .. trace_params.clone() };
+    let trace_params_simplify = ::TraceParams { simplify_threshold: 1.0, .. trace_params.clone() };
+    assert!(::extract_cache::Key::new(&trace_params_fit, &image, &size, 0.75).filepath ==
+            key.filepath);
+    assert!(::extract_cache::Key::new(&trace_params_simplify, &image, &size, 0.75).filepath !=
+            key.filepath);
+
+    // Invalid files are ignored.
+    let data = ::std::fs::read(&key.filepath).unwrap();
+    ::std::fs::write(&key.filepath, &data[..data.len() - 1]).unwrap();
+    assert!(::extract_cache::read(&key, image.len()).is_none());
+    ::std::fs::remove_dir_all(&dirpath).unwrap();
+}
+
+#[test]
+fn test_trace_hooks() {
+    use std::cell::Cell;
+    use std::collections::LinkedList;
+
+    // Removes holes after extraction, counts the polygons & paths at each later stage.
+    struct Hooks {
+        simplify_len: Cell<usize>,
+        fit_len: Cell<usize>,
+    }
+    impl ::TraceHooks for Hooks {
+        fn extract(&self, poly_list: &mut LinkedList<(bool, Vec<[f64; 2]>)>) {
+            *poly_list = poly_list.iter().filter(|&&(is_cyclic, ref poly)| {
+                !::curve_types::PathMeta::from_poly(0, is_cyclic, poly).is_hole
+            }).cloned().collect();
+        }
+        fn simplify(&self, poly_list: &mut LinkedList<(bool, Vec<[f64; 2]>)>) {
+            self.simplify_len.set(poly_list.len());
+        }
+        fn fit(&self, drawing: &mut ::curve_types::Drawing) {
+            self.fit_len.set(drawing.paths.len());
+        }
+    }
+
+    // A ring (with a hole).
+    let size = [8, 8];
+    let image: Vec<bool> = (0..(size[0] * size[1])).map(|i| {
+        let (x, y) = (i % size[0], i / size[0]);
+        x >= 1 && x < 7 && y >= 1 && y < 7 && !(x >= 3 && x < 5 && y >= 3 && y < 5)
+    }).collect();
+    let trace_params = ::TraceParams::default();
+    assert!(::trace_image_to_drawing(&trace_params, &image, &size, 0.75).drawing.paths.len() == 2);
+
+    let hooks = Hooks { simplify_len: Cell::new(0), fit_len: Cell::new(0) };
+    let output = ::trace_image_to_drawing_with_hooks(&trace_params, &image, &size, 0.75, &hooks);
+    assert!(output.drawing.paths.len() == 1);
+    assert!(hooks.simplify_len.get() == 1 && hooks.fit_len.get() == 1);
+
+    let tracer = ::Tracer::with_hooks(&trace_params, image, &size, hooks);
+    assert!(tracer.trace().drawing.paths.len() == 1);
+}
+
+#[test]
+fn test_trace_contours() {
+    // Two rings (each with a hole).
+    let size = [16, 8];
+    let image: Vec<bool> = (0..(size[0] * size[1])).map(|i| {
+        let (x, y) = (i % size[0], i / size[0]);
+        let x = x % 8;
+        x >= 1 && x < 7 && y >= 1 && y < 7 && !(x >= 3 && x < 5 && y >= 3 && y < 5)
+    }).collect();
+    let trace = |contours| {
+        let trace_params = ::TraceParams { contours: contours, .. ::TraceParams::default() };
+        let output = ::trace_image_to_drawing(&trace_params, &image, &size, 0.75);
+        output.drawing.paths.iter().map(|p| p.meta.is_hole).collect::<Vec<bool>>()
+    };
+    assert!(trace(::ContourMode::All).len() == 4);
+    assert!(trace(::ContourMode::Outer) == vec![false, false]);
+    assert!(trace(::ContourMode::Holes) == vec![true, true]);
+}
+
+#[test]
+fn test_trace_silhouette() {
+    use image_filter;
+
+    // A ring, where the hole is enclosed.
+    let size = [5, 5];
+    let mut image: Vec<bool> = (0..(size[0] * size[1])).map(|i| {
+        let (x, y) = (i % size[0], i / size[0]);
+        x >= 1 && x < 4 && y >= 1 && y < 4 && !(x == 2 && y == 2)
+    }).collect();
+    assert!(image_filter::fill_enclosed(&mut image, &size) == 1);
+    assert!(image_filter::fill_enclosed(&mut image, &size) == 0);
+
+    // Two rings with a 2 pixel gap are joined into a single outline.
+    let size = [18, 8];
+    let image: Vec<bool> = (0..(size[0] * size[1])).map(|i| {
+        let (x, y) = (i % size[0], i / size[0]);
+        let x = if x >= 9 { x - 9 } else { x };
+        x >= 1 && x < 8 && y >= 1 && y < 7 && !(x >= 3 && x < 6 && y >= 3 && y < 5)
+    }).collect();
+    let trace = |silhouette| {
+        let trace_params = ::TraceParams { silhouette: silhouette, .. ::TraceParams::default() };
+        let mut image = image.clone();
+        ::image_prepare_for_trace(&trace_params, &mut image, &size);
+        ::trace_image_to_drawing(&trace_params, &image, &size, 0.75).drawing.paths.len()
+    };
+    assert!(trace(0) == 4);
+    assert!(trace(3) == 1);
+}
+
+#[test]
+fn test_join_dashes() {
+    use polys_join_dashes::poly_list_join_dashes;
+    use std::collections::LinkedList;
+
+    let poly_list: LinkedList<(bool, Vec<[f64; 2]>)> = vec![
+        // A dashed line (the middle dash reversed), then a vertical line crossing it.
+        (false, vec![[0.0, 0.0], [5.0, 0.0], [10.0, 0.0]]),
+        (false, vec![[18.0, 0.0], [14.0, 0.0]]),
+        (false, vec![[20.0, 0.5], [30.0, 0.5]]),
+        (false, vec![[40.0, -10.0], [40.0, 10.0]]),
+        // Too far from the end of the line.
+        (false, vec![[50.0, 0.0], [60.0, 0.0]]),
+    ].into_iter().collect();
+    let (poly_list, dash_list) = poly_list_join_dashes(poly_list, 5.0);
+    assert!(poly_list.len() == 3 && dash_list.len() == 3);
+    let poly_list: Vec<(bool, Vec<[f64; 2]>)> = poly_list.into_iter().collect();
+    assert!(poly_list[0].1 == vec![
+        [0.0, 0.0], [5.0, 0.0], [10.0, 0.0], [14.0, 0.0], [18.0, 0.0], [20.0, 0.5], [30.0, 0.5],
+    ]);
+    let dash = dash_list[0].unwrap();
+    assert!((dash[0] - 8.0).abs() < 1e-9 && (dash[1] - 3.03).abs() < 0.01);
+    assert!(dash_list[1].is_none() && poly_list[1].1[0] == [40.0, -10.0]);
+    assert!(dash_list[2].is_none() && poly_list[2].1[0] == [50.0, 0.0]);
+}
+
+#[test]
+fn test_remove_hatching() {
+    use image_hatching;
+
+    // A hatched square with a thick border (cross-hatched on the right), and a thin line below.
+    let size = [96, 56];
+    let mut image: Vec<bool> = (0..(size[0] * size[1])).map(|i| {
+        let (x, y) = (i % size[0], i / size[0]);
+        if y == 50 || y == 51 {
+            return x >= 4 && x < 92;
+        }
+        let x_local = x % 48;
+        if x_local < 4 || x_local >= 44 || y < 4 || y >= 44 {
+            return false;
+        }
+        if x_local < 8 || x_local >= 40 || y < 8 || y >= 40 {
+            return true;
+        }
+        if x < 48 {
+            (x + y) % 7 < 2
+        } else {
+            (x + y) % 10 < 2 || (x + 100 - y) % 10 < 2
+        }
+    }).collect();
+    let image_orig = image.clone();
+    assert!(image_hatching::remove_hatching(&mut image, &size, 3) != 0);
+    for y in 0..size[1] {
+        for x in 0..size[0] {
+            let i = x + y * size[0];
+            let x_local = x % 48;
+            if y < 46 && x_local >= 11 && x_local < 37 && y >= 11 && y < 37 {
+                // Away from the border, hatching is removed.
+                assert!(!image[i]);
+            } else if y >= 46 || !(x_local >= 8 && x_local < 40 && y >= 8 && y < 40) {
+                // The border & line are kept.
+                assert!(image[i] == image_orig[i], "{} {}", x, y);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_polys_hull() {
+    use polys_hull;
+    use polys_hull::HullMode;
+
+    // An L shape, the inner corner & collinear points are removed.
+    let poly = vec![[0, 0], [0, 4], [0, 8], [2, 8], [2, 2], [6, 2], [6, 0], [3, 0]];
+    let hull = polys_hull::convex_hull(&poly);
+    assert!(hull.len() == 5);
+    assert!(!hull.contains(&[2, 2]) && !hull.contains(&[0, 4]) && !hull.contains(&[3, 0]));
+
+    // A diamond, the oriented box is smaller than the aligned box.
+    let poly = vec![[4, 0], [8, 4], [4, 8], [0, 4]];
+    let area = |poly: &Vec<[f64; 2]>| {
+        let meta = ::curve_types::PathMeta::from_poly(0, true, poly);
+        assert!(!meta.is_hole);
+        meta.area
+    };
+    assert!(area(&polys_hull::poly_hull(&poly, HullMode::AlignedBox)) == 64.0);
+    assert!((area(&polys_hull::poly_hull(&poly, HullMode::OrientedBox)) - 32.0).abs() < 1e-9);
+    assert!((area(&polys_hull::poly_hull(&poly, HullMode::Convex)) - 32.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_trace_hull() {
+    // Two rings (each with a hole), one bounding box each.
+    let size = [16, 8];
+    let image: Vec<bool> = (0..(size[0] * size[1])).map(|i| {
+        let (x, y) = (i % size[0], i / size[0]);
+        let x = x % 8;
+        x >= 1 && x < 7 && y >= 1 && y < 7 && !(x >= 3 && x < 5 && y >= 3 && y < 5)
+    }).collect();
+    let trace_params = ::TraceParams {
+        hull: Some(::polys_hull::HullMode::AlignedBox),
+        .. ::TraceParams::default()
+    };
+    let output = ::trace_image_to_drawing(&trace_params, &image, &size, 0.75);
+    assert!(output.drawing.paths.len() == 2);
+    for path in &output.drawing.paths {
+        assert!(path.cyclic && !path.meta.is_hole && path.knots.len() == 4);
+        assert!(path.meta.area == 36.0);
+    }
+}
+
+#[test]
+fn test_export_measurements() {
+    use curve_write;
+
+    // A ring (with a hole).
+    let size = [8, 8];
+    let image: Vec<bool> = (0..(size[0] * size[1])).map(|i| {
+        let (x, y) = (i % size[0], i / size[0]);
+        x >= 1 && x < 7 && y >= 1 && y < 7 && !(x >= 3 && x < 5 && y >= 3 && y < 5)
+    }).collect();
+    let output = ::trace_image_to_drawing(&::TraceParams::default(), &image, &size, 0.75);
+    let measurements = &output.measurements;
+    assert!(measurements.len() == 2);
+    for m in measurements {
+        assert!(m.is_cyclic && m.centroid == [4.0, 4.0]);
+        if m.is_hole {
+            assert!(m.area == 4.0 && m.perimeter == 8.0);
+            assert!(m.bounds_min == [3.0, 3.0] && m.bounds_max == [5.0, 5.0]);
+        } else {
+            assert!(m.area == 36.0 && m.perimeter == 24.0);
+            assert!(m.bounds_min == [1.0, 1.0] && m.bounds_max == [7.0, 7.0]);
+        }
+    }
+
+    let mut data: Vec<u8> = vec![];
+    curve_write::csv::write_measurements(&mut data, &measurements[0..1], 2.0).unwrap();
+    let text = String::from_utf8(data).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert!(lines.len() == 2);
+    assert!(lines[0].starts_with("region,cyclic,hole,area_pixels,area,"));
+    assert!(lines[1].ends_with(",true,false,36,144,48,8,8,2,2,14,14"));
+}
+
+#[test]
+fn test_pass_anim() {
+    // A black square on a white background.
+    let size = [8, 6];
+    let image: Vec<bool> = (0..(size[0] * size[1])).map(|i| {
+        let (x, y) = (i % size[0], i / size[0]);
+        x >= 2 && x < 6 && y >= 1 && y < 5
+    }).collect();
+    let trace_params = ::TraceParams {
+        debug_passes: ::debug_pass::kind::ANIM,
+        .. ::TraceParams::default()
+    };
+    let text = ::Tracer::new(&trace_params, image, &size).trace_to_svg().unwrap();
+
+    // Pixels, simplified polygons & curves, each shown for one of three steps.
+    assert!(text.matches("<animate ").count() == 3);
+    assert!(text.contains("values='1;0;0'") && text.contains("values='0;0;1'"));
+    assert!(text.contains("3: Curves</text>"));
+}
+
+#[test]
+fn test_simplify_size_classes() {
+    use polys_simplify_collapse;
+    use polys_simplify_collapse::SimplifySizeClasses;
+    use std::collections::LinkedList;
+
+    // A square with each side subdivided into 8 steps.
+    fn square(size: f64) -> Vec<[f64; 2]> {
+        let mut poly = vec![];
+        for corner in &[[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]] {
+            poly.push([corner[0] * size, corner[1] * size]);
+        }
+        let mut poly_subdiv = vec![];
+        for i in 0..poly.len() {
+            let (a, b) = (poly[i], poly[(i + 1) % poly.len()]);
+            for j in 0..8 {
+                let t = j as f64 / 8.0;
+                poly_subdiv.push([a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t]);
+            }
+        }
+        return poly_subdiv;
+    }
+    let size_classes = SimplifySizeClasses { size_cutoff: 32.0, small: 0.0, large: 2.0 };
+    assert!(size_classes.simplify_threshold_for_poly(&square(4.0)) == 0.0);
+    assert!(size_classes.simplify_threshold_for_poly(&square(40.0)) == 2.0);
+
+    let mut poly_list: LinkedList<(bool, Vec<[f64; 2]>)> = LinkedList::new();
+    poly_list.push_back((true, square(4.0)));
+    poly_list.push_back((true, square(40.0)));
+    let poly_list = polys_simplify_collapse::poly_list_simplify(
+        &poly_list, 2.0, Some(size_classes), false);
+    let mut iter = poly_list.iter();
+    assert!(iter.next().unwrap().1.len() == 32);
+    assert!(iter.next().unwrap().1.len() == 4);
+}
+
+#[test]
+fn test_poly_simplify_with_options() {
+    use curve_types;
+    use polys_simplify_collapse;
+    use polys_simplify_collapse::{
+        PolySimplify,
+        poly_simplify_with_options,
+    };
+
+    // A slightly noisy circle.
+    let poly: Vec<[f64; 2]> = (0..64).map(|i| {
+        let t = (i as f64 / 64.0) * 2.0 * ::std::f64::consts::PI;
+        let radius = 20.0 + if (i % 2) == 0 { 0.1 } else { -0.1 };
+        [t.cos() * radius, t.sin() * radius]
+    }).collect();
+
+    let result = poly_simplify_with_options(true, &poly, &PolySimplify {
+        threshold: ::std::f64::INFINITY,
+        target_len: 16,
+        use_costs: true,
+        use_deterministic: false,
+    });
+    assert!(result.poly.len() == 16);
+
+    // Removed points have a finite cost, keeping points above a threshold
+    // matches the number of points from simplifying with that threshold.
+    let costs = result.costs.unwrap();
+    assert!(costs.iter().filter(|c| c.is_finite()).count() == 64 - 16);
+    let threshold = 0.5;
+    let result_threshold = poly_simplify_with_options(true, &poly, &PolySimplify {
+        threshold: threshold,
+        target_len: 0,
+        use_costs: false,
+        use_deterministic: false,
+    });
+    assert!(result_threshold.costs.is_none());
+    assert!(costs.iter().filter(|c| **c >= threshold).count() == result_threshold.poly.len());
+
+    // Paths are simplified as polygons, keeping their meta-data.
+    let mut path = curve_types::Path::from_poly(true, &poly);
+    path.meta.region = 3;
+    let (path_simple, costs) = polys_simplify_collapse::path_simplify(
+        &path, 0.1, &PolySimplify {
+            threshold: threshold,
+            target_len: 0,
+            use_costs: false,
+            use_deterministic: false,
+        });
+    assert!(costs.is_none());
+    assert!(path_simple.knots.len() < poly.len());
+    assert!(path_simple.knots.iter().all(|k| k.handle_prev == k.co && k.handle_next == k.co));
+    assert!(path_simple.meta.region == 3);
+}
+
+#[test]
+fn test_curve_simplify_knots() {
+    use curve_simplify;
+    use curve_types::Path;
+    use intern::curve_fit_nd::{
+        fit_poly_single,
+        EndTangent,
+    };
+
+    // A slightly noisy circle, fit with a low error to create redundant knots.
+    let poly: Vec<[f64; 2]> = (0..256).map(|i| {
+        let t = (i as f64 / 256.0) * 2.0 * ::std::f64::consts::PI;
+        let radius = 50.0 + if (i % 3) == 0 { 0.05 } else { 0.0 };
+        [t.cos() * radius, t.sin() * radius]
+    }).collect();
+    let curve = fit_poly_single(
+        &poly, true, 0.02, ::std::f64::consts::PI, false, EndTangent::Natural);
+    let path = Path::from_cubic_array(true, &curve);
+
+    let threshold = 0.5;
+    let mut path_simple = path.clone();
+    let removed_len = curve_simplify::path_simplify_knots(&mut path_simple, threshold);
+    assert!(removed_len != 0);
+    assert!(path_simple.knots.len() + removed_len == path.knots.len());
+    assert!(path_simple.knots.iter().all(|k| k.is_smooth()));
+
+    // The simplified path stays within the threshold (allowing for flattening).
+    let poly_simple = path_simple.flatten(0.01);
+    let dist_to_segment = |p: &[f64; 2], a: &[f64; 2], b: &[f64; 2]| {
+        let u = [b[0] - a[0], b[1] - a[1]];
+        let t = (((p[0] - a[0]) * u[0] + (p[1] - a[1]) * u[1]) /
+                 (u[0] * u[0] + u[1] * u[1])).max(0.0).min(1.0);
+        ((p[0] - (a[0] + u[0] * t)).powi(2) + (p[1] - (a[1] + u[1] * t)).powi(2)).sqrt()
+    };
+    for p in path.flatten(0.01) {
+        let d = (0..poly_simple.len()).map(|i| {
+            dist_to_segment(&p, &poly_simple[i], &poly_simple[(i + 1) % poly_simple.len()])
+        }).fold(::std::f64::MAX, f64::min);
+        assert!(d < threshold * 1.1);
+    }
+}
+
+#[test]
+fn test_symmetry() {
+    use curve_symmetry;
+    use curve_types::{
+        Drawing,
+        Path,
+    };
+    use image_symmetry::{
+        Symmetry,
+        axis_detect,
+    };
+
+    // A triangle with a notch on the right, left/right symmetrical except for one pixel.
+    let size = [9, 6];
+    let mut image = vec![false; size[0] * size[1]];
+    for y in 0..size[1] {
+        for x in (4 - y.min(4))..(5 + y.min(4)) {
+            image[x + y * size[0]] = true;
+        }
+    }
+    image[7 + 5 * size[0]] = false;
+    let axis = axis_detect(&image, &size, Symmetry::Auto).unwrap();
+    assert!(axis.axis == 0 && axis.position == 4.5);
+    assert!(axis.score > 0.9 && axis.score < 1.0);
+    assert!(axis_detect(&image, &size, Symmetry::Y).unwrap().axis == 1);
+    assert!(axis_detect(&vec![false; 4], &[2, 2], Symmetry::Auto).is_none());
+
+    // An approximately symmetrical outline (counter-clockwise), made exactly symmetrical.
+    let mut drawing = Drawing::new();
+    drawing.paths.push_back(Path::from_cubic_array(true, &[
+        [[4.0, 0.1], [4.4, 0.1], [4.8, 0.1]],
+        [[8.9, 5.5], [9.1, 6.0], [8.5, 6.0]],
+        [[0.5, 6.0], [0.1, 6.0], [0.1, 5.6]],
+    ]));
+    let changed_len = curve_symmetry::drawing_symmetrize(&mut drawing, &axis, 1.0);
+    assert!(changed_len == 3);
+    let knots = &drawing.paths.front().unwrap().knots;
+    let is_near = |a: [f64; 2], b: [f64; 2]| (a[0] - b[0]).abs() + (a[1] - b[1]).abs() < 1e-12;
+    assert!(knots[0].co[0] == 4.5);
+    assert!(is_near(axis.mirror(&knots[1].co), knots[2].co));
+    assert!(is_near(axis.mirror(&knots[1].handle_prev), knots[2].handle_next));
+    assert!(is_near(axis.mirror(&knots[0].handle_prev), knots[0].handle_next));
+}
+
+#[test]
+fn test_curve_snap() {
+    use curve_snap;
+    use curve_types::Path;
+
+    // Knots close to the grid are snapped, a knot moving the curve too far is left as-is.
+    let mut path = Path::from_cubic_array(false, &[
+        [[0.1, 0.1], [0.1, 0.1], [3.0, 0.1]],
+        [[7.0, 0.1], [9.9, 0.1], [9.9, 0.1]],
+        [[9.9, 4.0], [9.9, 4.6], [9.9, 4.6]],
+    ]);
+    let (snapped_len, rejected_len) = curve_snap::path_snap(&mut path, 1.0, false, 0.2);
+    assert!(snapped_len == 2 && rejected_len == 1);
+    assert!(path.knots[0].co == [0.0, 0.0]);
+    assert!(path.knots[1].co == [10.0, 0.0]);
+    assert!(path.knots[2].co == [9.9, 4.6]);
+    // Handles keep their offset from the knot.
+    assert!(path.knots[0].handle_next == [3.0 - 0.1, 0.0]);
+
+    let (snapped_len, _) = curve_snap::path_snap(&mut path, 1.0, true, 1.0);
+    assert!(snapped_len == 3);
+    assert!(path.knots[0].handle_next == [3.0, 0.0]);
+    assert!(path.knots[2].co == [10.0, 5.0]);
+}
+
+#[test]
+fn test_orient_open_paths() {
+    use curve_types::{
+        Drawing,
+        Path,
+        StrokeDirection,
+    };
+
+    // A right-to-left stroke, a bottom-to-top stroke & a cyclic path (never reversed).
+    let mut drawing = Drawing::new();
+    drawing.paths.push_back(Path::from_poly(false, &[[8.0, 2.0], [2.0, 2.0]]));
+    drawing.paths.push_back(Path::from_poly(false, &[[1.0, 9.0], [1.0, 6.0]]));
+    drawing.paths.push_back(Path::from_poly(true, &[[9.0, 9.0], [0.0, 9.0], [0.0, 0.0]]));
+    let co_first = |drawing: &Drawing| -> Vec<[f64; 2]> {
+        drawing.paths.iter().map(|p| p.knots[0].co).collect()
+    };
+
+    assert!(drawing.orient_open_paths(StrokeDirection::LeftToRight, &[5.0, 5.0]) == 2);
+    assert!(co_first(&drawing) == vec![[2.0, 2.0], [1.0, 6.0], [9.0, 9.0]]);
+
+    assert!(drawing.orient_open_paths(StrokeDirection::TopToBottom, &[5.0, 5.0]) == 0);
+
+    assert!(drawing.orient_open_paths(StrokeDirection::Outward, &[6.0, 5.0]) == 1);
+    assert!(co_first(&drawing) == vec![[8.0, 2.0], [1.0, 6.0], [9.0, 9.0]]);
+}
+
+#[test]
+fn test_svg_write_lines() {
+    use curve_types::{
+        Drawing,
+        Path,
+    };
+    use curve_write;
+    use std::io::Read;
+
+    // A square with an extra point along the first side, and a curve.
+    let mut drawing = Drawing::new();
+    drawing.paths.push_back(Path::from_poly(true, &[
+        [0.0, 0.0], [5.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 10.0],
+    ]));
+    drawing.paths.push_back(Path::from_cubic_array(false, &[
+        [[0.0, 20.0], [0.0, 20.0], [5.0, 25.0]],
+        [[5.0, 25.0], [10.0, 20.0], [10.0, 20.0]],
+    ]));
+
+    let filepath = ::std::env::temp_dir().join("raster_retrace_test_lines.svg");
+    {
+        let f = ::std::fs::File::create(&filepath).unwrap();
+        curve_write::svg::write_drawing_filled(&f, &[10, 30], 1.0, &drawing, false).unwrap();
+    }
+    let mut text = String::new();
+    ::std::fs::File::open(&filepath).unwrap().read_to_string(&mut text).unwrap();
+    ::std::fs::remove_file(&filepath).unwrap();
+
+    assert!(text.contains("M 0.00,0.00 L 10.00,0.00 L 10.00,10.00 L 0.00,10.00  Z"));
+    assert!(text.contains("M 0.00,20.00 C 5.00,25.00 5.00,25.00 10.00,20.00"));
+
+    // Nothing traced, no empty path data.
+    let mut data: Vec<u8> = vec![];
+    curve_write::svg::write_drawing_filled(&mut data, &[10, 30], 1.0, &Drawing::new(), false)
+        .unwrap();
+    assert!(!String::from_utf8(data).unwrap().contains("<path"));
+}
+
+#[test]
+fn test_svg_template_splice() {
+    use curve_write::svg::template_splice;
+
+    let template = "<svg>\n  <rect id='art-bg' />\n  <g id=\"art\"/>\n</svg>\n";
+    assert!(template_splice(template, "", "<path />\n").unwrap() ==
+            "<svg>\n  <rect id='art-bg' />\n  <g id=\"art\"/>\n<path />\n</svg>\n");
+    // Self-closing elements are expanded, ids which only share a prefix are skipped.
+    assert!(template_splice(template, "#art", "<path />\n").unwrap() ==
+            "<svg>\n  <rect id='art-bg' />\n  <g id=\"art\">\n<path />\n</g>\n</svg>\n");
+    assert!(template_splice(template, "#art-bg", "<path />\n").unwrap() ==
+            "<svg>\n  <rect id='art-bg'>\n<path />\n</rect>\n  <g id=\"art\"/>\n</svg>\n");
+    assert!(template_splice("<svg><g id='a'></g></svg>", "#a", "<path />").unwrap() ==
+            "<svg><g id='a'>\n<path /></g></svg>");
+
+    assert!(template_splice(template, "#missing", "").is_err());
+    assert!(template_splice(template, "art", "").is_err());
+    assert!(template_splice("<g />", "", "").is_err());
+}
+
+#[test]
+fn test_image_rasterize() {
+    use image_rasterize::Raster;
+
+    // A square with half pixel edges & a hole (opposite winding) in the middle,
+    // the square extends past the right side of the image.
+    let size = [6, 6];
+    let mut raster = Raster::new(&size);
+    raster.add_poly(&[[0.5, 0.5], [7.0, 0.5], [7.0, 5.5], [0.5, 5.5]]);
+    raster.add_poly(&[[2.0, 2.0], [2.0, 4.0], [4.0, 4.0], [4.0, 2.0]]);
+    let coverage = raster.coverage();
+    let value = |x: usize, y: usize| coverage[x + y * size[0]];
+    assert!(value(0, 0) == 0.25 && value(1, 0) == 0.5 && value(5, 0) == 0.5);
+    assert!(value(0, 1) == 0.5 && value(1, 1) == 1.0 && value(5, 1) == 1.0);
+    assert!(value(2, 2) == 0.0 && value(3, 3) == 0.0 && value(4, 3) == 1.0);
+    assert!(coverage.iter().all(|v| *v >= 0.0 && *v <= 1.0));
+
+    // Overlapping strokes don't cancel each other out.
+    let mut raster = Raster::new(&size);
+    let mut poly_list = ::std::collections::LinkedList::new();
+    poly_list.push_back((false, vec![[0.0, 3.0], [6.0, 3.0], [3.0, 0.0], [3.0, 6.0]]));
+    raster.add_poly_list_stroke(&poly_list, 2.0);
+    let coverage = raster.coverage();
+    assert!(coverage[3 + 3 * size[0]] == 1.0 && coverage[0] == 0.0);
+}
+
+#[test]
+fn test_image_write_png() {
+    let mut data: Vec<u8> = vec![];
+    ::image_write::png::write_rgba(&mut data, &[2, 1], &[[0, 0, 0, 255], [0, 0, 0, 0]]).unwrap();
+    assert!(data.starts_with(b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR\0\0\0\x02\0\0\0\x01\x08\x06"));
+    assert!(data.ends_with(b"\0\0\0\0IEND\xae\x42\x60\x82"));
+    // The uncompressed rows (a filter byte followed by the pixels).
+    let rows: &[u8] = &[0, 0, 0, 0, 255, 0, 0, 0, 0];
+    assert!(data.windows(rows.len()).any(|w| w == rows));
+}
+
+#[test]
+fn test_trace_batch() {
+    use std::io::Write;
+
+    assert!(::batch_output_filepath("out", "scans/page.ppm", None) ==
+            ::std::path::Path::new("out").join("page.svg").to_string_lossy());
+    assert!(::batch_output_filepath("out", "page", Some(::OutputFormat::Glif)) ==
+            ::std::path::Path::new("out").join("page.glif").to_string_lossy());
+
+    // A filled square & a missing file, the batch continues past the failure.
+    let dir = ::std::env::temp_dir().join("raster_retrace_test_batch");
+    let input_filepath = ::std::env::temp_dir().join("raster_retrace_test_batch.ppm");
+    {
+        let mut f = ::std::fs::File::create(&input_filepath).unwrap();
+        f.write_all(b"P6\n4 4\n255\n").unwrap();
+        for y in 0..4 {
+            for x in 0..4 {
+                f.write_all(&[if x == 0 || y == 0 { 255 } else { 0 }; 3]).unwrap();
+            }
+        }
+    }
+    let trace_params = ::TraceParams {
+        input_filepaths: vec![
+            input_filepath.to_string_lossy().into_owned(),
+            dir.join("missing.ppm").to_string_lossy().into_owned(),
+        ],
+        output_filepath: dir.to_string_lossy().into_owned(),
+        use_batch: true,
+        .. ::TraceParams::default()
+    };
+    let batch_items = ::trace_batch(&trace_params, None);
+    assert!(batch_items.len() == 2);
+    assert!(batch_items[0].error.is_none() && batch_items[0].paths_len == 1);
+    assert!(::std::fs::metadata(&batch_items[0].output_filepath).is_ok());
+    assert!(batch_items[1].error.is_some());
+
+    // No pixels are darker than a zero threshold, the empty output is written but fails.
+    let trace_params_empty = ::TraceParams {
+        input_filepaths: vec![trace_params.input_filepaths[0].clone()],
+        threshold: 0.0,
+        use_fail_empty: true,
+        .. trace_params.clone()
+    };
+    let batch_items_empty = ::trace_batch(&trace_params_empty, None);
+    assert!(batch_items_empty[0].error.as_ref().map(String::as_str) == Some("No filled pixels"));
+    assert!(::std::fs::metadata(&batch_items_empty[0].output_filepath).is_ok());
+
+    // Inputs traced in parallel are reported in order, the same as with a single thread.
+    for &threads_len in &[1, 4] {
+        let trace_params_threads = ::TraceParams {
+            input_filepaths: trace_params.input_filepaths.iter().cycle().take(6).cloned().collect(),
+            threads_len: threads_len,
+            .. trace_params.clone()
+        };
+        let batch_items_threads = ::trace_batch(&trace_params_threads, None);
+        assert!(batch_items_threads.len() == 6);
+        for (i, item) in batch_items_threads.iter().enumerate() {
+            assert!(item.error.is_some() == batch_items[i % 2].error.is_some());
+            assert!(item.knots_len == batch_items[i % 2].knots_len);
+        }
+    }
+
+    // All inputs written as pages of one document, skipping inputs which fail.
+    let pdf_filepath = dir.join("pages.pdf").to_string_lossy().into_owned();
+    let trace_params_pdf = ::TraceParams {
+        input_filepaths: vec![
+            trace_params.input_filepaths[0].clone(),
+            trace_params.input_filepaths[1].clone(),
+            trace_params.input_filepaths[0].clone(),
+        ],
+        output_filepath: pdf_filepath.clone(),
+        use_pdf_multipage: true,
+        .. trace_params.clone()
+    };
+    let batch_items_pdf = ::trace_batch(&trace_params_pdf, None);
+    assert!(batch_items_pdf.iter().all(|item| item.output_filepath == pdf_filepath));
+    assert!(batch_items_pdf.iter().filter(|item| item.error.is_some()).count() == 1);
+    let data = ::std::fs::read(&pdf_filepath).unwrap();
+    assert!(String::from_utf8_lossy(&data).contains("/Count 2 "));
+
+    ::std::fs::remove_file(&input_filepath).unwrap();
+    ::std::fs::remove_dir_all(&dir).unwrap();
+
+    let mut data: Vec<u8> = vec![];
+    ::write_batch_report(&mut data, &batch_items).unwrap();
+    let text = String::from_utf8(data).unwrap();
+    assert!(text.contains("\"status\": \"ok\", \"paths\": 1"));
+    assert!(text.contains("\"status\": \"error\""));
+    assert!(text.contains("\"failed\": 1"));
+}
+
+#[test]
+fn test_json_read() {
+    use intern::json::{
+        from_str,
+        Value,
+    };
+
+    assert!(from_str(" {\"a\": [1, -2.5e1, true, null], \"b\": \"\\\"x\\u00e9\\ud83d\\ude00\"} ") ==
+            Ok(Value::Object(vec![
+                ("a".to_string(), Value::Array(vec![
+                    Value::Number(1.0), Value::Number(-25.0), Value::Bool(true), Value::Null,
+                ])),
+                ("b".to_string(), Value::String("\"x\u{e9}\u{1f600}".to_string())),
+            ])));
+    assert!(from_str("[]") == Ok(Value::Array(vec![])));
+    for text in &[
+        "", "{", "[1,]", "{\"a\" 1}", "\"\\x\"", "01x", ".5", "1.", "[1] 2", "\"\\ud83d\"",
+    ] {
+        assert!(from_str(text).is_err());
+    }
+    // Deep nesting is an error, not a stack overflow.
+    assert!(from_str(&"[".repeat(100_000)).is_err());
+}
+
+#[test]
+fn test_serve() {
+    use std::io::Write;
+    use std::str::FromStr;
+
+    let input_filepath = ::std::env::temp_dir().join("raster_retrace_test_serve.ppm");
+    let output_filepath = ::std::env::temp_dir().join("raster_retrace_test_serve.svg");
+    {
+        let mut f = ::std::fs::File::create(&input_filepath).unwrap();
+        f.write_all(b"P6\n4 4\n255\n").unwrap();
+        for y in 0..4 {
+            for x in 0..4 {
+                f.write_all(&[if x == 0 || y == 0 { 255 } else { 0 }; 3]).unwrap();
+            }
+        }
+    }
+
+    // Only the arguments used here.
+    let args_parse = |trace_params: &mut ::TraceParams, args: &[String]| -> Result<(), String> {
+        for arg in args.chunks(2) {
+            match arg[0].as_ref() {
+                "--input" => trace_params.input_filepaths.push(arg[1].clone()),
+                "--output" => trace_params.output_filepath = arg[1].clone(),
+                "--error" => trace_params.error_threshold = f64::from_str(&arg[1]).unwrap(),
+                _ => {
+                    return Err(format!("Error: '{}' unknown parameter!", arg[0]));
+                }
+            }
+        }
+        return Ok(());
+    };
+    let mut server = ::serve::Server::new(&::TraceParams::default(), args_parse);
+
+    let commands = format!(
+        "{{\"input\": \"{0}\", \"output\": \"{1}\"}}\n\n{{\"input\": \"{0}\", \"error\": 2}}\n\
+         {{\"input\": \"{0}\", \"mode\": \"CENTER\"}}\n[]\nnot json\n\
+         {{\"input\": \"{0}\", \"output\": \"{1}\", \"error\": []}}\n{{\"help\": true}}\n",
+        ::curve_write::json::string_escape(&input_filepath.to_string_lossy()),
+        ::curve_write::json::string_escape(&output_filepath.to_string_lossy()));
+    let mut data: Vec<u8> = vec![];
+    ::serve::serve(&mut server, commands.as_bytes(), &mut data).unwrap();
+    let text = String::from_utf8(data).unwrap();
+    let results: Vec<&str> = text.lines().collect();
+    assert!(results.len() == 7);
+    assert!(results[0].starts_with("{\"status\": \"ok\", \"paths\": 1,"));
+    assert!(::std::fs::metadata(&output_filepath).is_ok());
+    // Without an output, the result contains the document.
+    assert!(results[1].contains("\"document\": \"<?xml"));
+    assert!(results[2].contains("unknown parameter"));
+    assert!(results[3] == "{\"status\": \"error\", \"error\": \"Expected a JSON object\"}");
+    assert!(results[4] == results[3]);
+    // A missing parameter is an error (not a panic which stops the server).
+    assert!(results[5].starts_with("{\"status\": \"error\""));
+    // Help would be printed among the results.
+    assert!(results[6] ==
+            "{\"status\": \"error\", \"error\": \"'help' can't be used in commands\"}");
+
+    // Socket messages, prefixed by their length.
+    struct Stream {
+        input: ::std::io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+    impl ::std::io::Read for Stream {
+        fn read(&mut self, buf: &mut [u8]) -> ::std::io::Result<usize> {
+            return self.input.read(buf);
+        }
+    }
+    impl ::std::io::Write for Stream {
+        fn write(&mut self, buf: &[u8]) -> ::std::io::Result<usize> {
+            return self.output.write(buf);
+        }
+        fn flush(&mut self) -> ::std::io::Result<()> {
+            return Ok(());
+        }
+    }
+    let command = format!("{{\"input\": \"{}\", \"curves\": true}}",
+                          ::curve_write::json::string_escape(&input_filepath.to_string_lossy()));
+    let mut input: Vec<u8> = vec![];
+    input.extend_from_slice(&(command.len() as u32).to_le_bytes());
+    input.extend_from_slice(command.as_bytes());
+    let mut stream = Stream { input: ::std::io::Cursor::new(input), output: vec![] };
+    let server = ::std::sync::Mutex::new(server);
+    // A panic while another connection holds the lock doesn't stop the server.
+    let _ = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+        let _lock = server.lock().unwrap();
+        panic!();
+    }));
+    assert!(server.is_poisoned());
+    ::serve::serve_messages(&server, &mut stream).unwrap();
+    let len = u32::from_le_bytes([
+        stream.output[0], stream.output[1], stream.output[2], stream.output[3]]) as usize;
+    assert!(stream.output.len() == 4 + len);
+    let result = ::intern::json::from_str(
+        &String::from_utf8(stream.output[4..].to_vec()).unwrap()).unwrap();
+    let curves = match result {
+        ::intern::json::Value::Object(ref members) => &members[3],
+        _ => panic!(),
+    };
+    match *curves {
+        (ref key, ::intern::json::Value::Object(ref members)) if key == "curves" => {
+            assert!(members[0] == ("size".to_string(), ::intern::json::Value::Array(vec![
+                ::intern::json::Value::Number(4.0), ::intern::json::Value::Number(4.0)])));
+            match members[1].1 {
+                ::intern::json::Value::Array(ref paths) => assert!(paths.len() == 1),
+                _ => panic!(),
+            }
+        }
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn test_centerline_subpixel() {
+    use polys_centerline_subpixel::{
+        Coverage,
+        poly_list_centerline_subpixel,
+    };
+    use std::collections::LinkedList;
+
+    // A horizontal stroke 3 pixels wide, centered at `y = 5.25`,
+    // with anti-aliased edges (pixel centers are at integer coordinates).
+    let size = [16, 11];
+    let center = 5.25;
+    let mut data = vec![0.0_f32; size[0] * size[1]];
+    for y in 0..size[1] {
+        let y_min = (y as f64 - 0.5).max(center - 1.5);
+        let y_max = (y as f64 + 0.5).min(center + 1.5);
+        for x in 0..size[0] {
+            data[x + (y * size[0])] = (y_max - y_min).max(0.0) as f32;
+        }
+    }
+    let coverage = Coverage { data: data, size: size };
+
+    // Two center-lines on the pixel grid, meeting at a junction.
+    let mut poly_list: LinkedList<(bool, Vec<[f64; 2]>)> = LinkedList::new();
+    poly_list.push_back((false, vec![[2.0, 5.0], [8.0, 5.0]]));
+    poly_list.push_back((false, vec![[8.0, 5.0], [13.0, 5.0]]));
+    poly_list_centerline_subpixel(&mut poly_list, &coverage, 3.0);
+
+    for &(_, ref poly) in poly_list.iter() {
+        for (i, v) in poly.iter().enumerate() {
+            if v[0] == 8.0 {
+                // The junction isn't moved.
+                assert!(v[1] == 5.0);
+            } else {
+                assert!((v[1] - center).abs() < 0.01, "point {}: {:?}", i, v);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_fit_tangent_smooth() {
+    use intern::curve_fit_nd::{
+        fit_poly_list_with_params,
+        FitParams,
+    };
+    use std::collections::LinkedList;
+
+    // A stair-stepped line (as traced from pixels), the tangents at each step are diagonal.
+    let mut poly: Vec<[f64; 2]> = vec![];
+    for i in 0..16 {
+        poly.push([i as f64 * 4.0, i as f64]);
+        poly.push([i as f64 * 4.0 + 4.0, i as f64]);
+    }
+    let mut poly_list: LinkedList<(bool, Vec<[f64; 2]>)> = LinkedList::new();
+    poly_list.push_back((false, poly));
+    let fit = |tangent_smooth| fit_poly_list_with_params(
+        poly_list.clone(),
+        &FitParams {
+            tangent_smooth: tangent_smooth,
+            .. Default::default()
+        },
+        None, None, None, None,
+    ).0.pop_front().unwrap().1;
+    // The largest angle between the handles of interior knots & the direction of the line.
+    let angle_max = |curve: &Vec<[[f64; 2]; 3]>| {
+        let dir = [4.0 / 17.0_f64.sqrt(), 1.0 / 17.0_f64.sqrt()];
+        let mut angle_max: f64 = 0.0;
+        for k in &curve[1..curve.len() - 1] {
+            let d = [k[2][0] - k[0][0], k[2][1] - k[0][1]];
+            let d_len = (d[0] * d[0] + d[1] * d[1]).sqrt();
+            angle_max = angle_max.max(((d[0] * dir[0] + d[1] * dir[1]) / d_len).min(1.0).acos());
+        }
+        angle_max
+    };
+    // Without smoothing, handles follow the diagonal of each step.
+    assert!(angle_max(&fit(0.0)) > 0.5);
+    // Smoothing over multiple steps aligns handles with the line.
+    assert!(angle_max(&fit(12.0)) < 0.05);
+}
+
+#[test]
+fn test_stroke_widths() {
+    use curve_stroke_width;
+    use curve_types::{
+        Drawing,
+        Path,
+    };
+    use curve_write;
+
+    // A horizontal stroke 5 pixels wide (rows 3 to 7), thinning to 3 pixels on the right.
+    let size = [24, 11];
+    let mut image = vec![false; size[0] * size[1]];
+    for y in 3..8 {
+        for x in 2..22 {
+            if x < 12 || (y >= 4 && y < 7) {
+                image[x + (y * size[0])] = true;
+            }
+        }
+    }
+    let mut drawing = Drawing::new();
+    drawing.paths.push_back(Path::from_poly(false, &[[4.0, 5.0], [8.0, 5.0], [18.0, 5.0]]));
+    curve_stroke_width::drawing_measure_widths(&mut drawing, &image, &size);
+    assert!(drawing.paths.front().unwrap().meta.widths == Some(vec![5.0, 5.0, 3.0]));
+
+    // The outline follows the widths on either side.
+    let path = drawing.paths.front().unwrap();
+    let outline = curve_stroke_width::path_outline(
+        path, path.meta.widths.as_ref().unwrap(), 0.01);
+    assert!(outline.len() == 1);
+    assert!(outline[0].contains(&[4.0, 7.5]) && outline[0].contains(&[4.0, 2.5]));
+    assert!(outline[0].contains(&[18.0, 6.5]) && outline[0].contains(&[18.0, 3.5]));
+
+    // Widths are written as power-strokes (scaled with the output).
+    let mut data: Vec<u8> = vec![];
+    curve_write::svg::write_drawing_centerline(&mut data, &size, 2.0, &drawing, false).unwrap();
+    let text = String::from_utf8(data).unwrap();
+    assert!(text.contains("offset_points='0,5.00 | 1,5.00 | 2,3.00'"));
+    assert!(text.contains("inkscape:original-d='M 8.00,10.00 L 36.00,10.00 '"));
+
+    let mut data: Vec<u8> = vec![];
+    curve_write::json::write_strokes(&mut data, &size, 2.0, &drawing, &[[1.0, 1.0]], 0.5).unwrap();
+    let result = ::intern::json::from_str(&String::from_utf8(data).unwrap()).unwrap();
+    let strokes = match result {
+        ::intern::json::Value::Object(ref members) => &members[1],
+        _ => panic!(),
+    };
+    match *strokes {
+        (ref key, ::intern::json::Value::Array(ref strokes)) if key == "strokes" => {
+            // The stroke & the dot.
+            assert!(strokes.len() == 2);
+        }
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn test_outputs_extra() {
+    use std::io::Read;
+
+    // A suffix is only a selector when it's a list of passes (so paths may contain ':').
+    let output = ::OutputExtra::from_arg("C:debug.svg:PIXEL,TANGENT");
+    assert!(output.filepath == "C:debug.svg");
+    assert!(output.debug_passes == ::debug_pass::kind::PIXEL | ::debug_pass::kind::TANGENT);
+    assert!(::OutputExtra::from_arg("C:debug.svg") == ::OutputExtra {
+        filepath: "C:debug.svg".to_string(),
+        debug_passes: 0,
+    });
+
+    // A filled square, written with & without debug passes.
+    let size = [12, 12];
+    let mut image = vec![false; size[0] * size[1]];
+    for y in 3..9 {
+        for x in 3..9 {
+            image[x + (y * size[0])] = true;
+        }
+    }
+    let dirpath = ::std::env::temp_dir();
+    let filepaths = [
+        dirpath.join("raster_retrace_test_outputs.svg"),
+        dirpath.join("raster_retrace_test_outputs_debug.svg"),
+        dirpath.join("raster_retrace_test_outputs.json"),
+    ];
+    let filepaths: Vec<String> = filepaths.iter().map(
+        |filepath| filepath.to_string_lossy().into_owned()).collect();
+    let trace_params = ::TraceParams {
+        output_filepath: filepaths[0].clone(),
+        outputs_extra: vec![
+            ::OutputExtra::from_arg(&format!("{}:PIXEL", filepaths[1])),
+            ::OutputExtra::from_arg(&filepaths[2]),
+        ],
+        .. Default::default()
+    };
+    ::trace_image(&trace_params, &image, &size, 0.75).unwrap();
+
+    let texts: Vec<String> = filepaths.iter().map(|filepath| {
+        let mut text = String::new();
+        ::std::fs::File::open(filepath).unwrap().read_to_string(&mut text).unwrap();
+        ::std::fs::remove_file(filepath).unwrap();
+        text
+    }).collect();
+    // The pixel pass is only written to the output which selects it.
+    assert!(!texts[0].contains("value='PIXEL'") && texts[1].contains("value='PIXEL'"));
+    assert!(texts[0].matches("<path").count() + 1 == texts[1].matches("<path").count());
+    assert!(texts[2].starts_with("{\"size\": [12, 12], \"paths\": [{\"cyclic\": true"));
+}
+
+#[test]
+fn test_path_ids() {
+    use curve_write;
+    use polys_utils::poly_hash;
+
+    // Cyclic polygons hash the same from any starting point.
+    let poly = [[1.0, 1.0], [1.0, 4.0], [4.0, 4.0], [4.0, 1.0]];
+    let poly_rotate = [[4.0, 4.0], [4.0, 1.0], [1.0, 1.0], [1.0, 4.0]];
+    assert!(poly_hash(true, &poly) == poly_hash(true, &poly_rotate));
+    assert!(poly_hash(false, &poly) != poly_hash(false, &poly_rotate));
+    assert!(poly_hash(true, &poly) != poly_hash(false, &poly));
+
+    // A square with a hole & a separate square.
+    let size = [20, 10];
+    let mut image = vec![false; size[0] * size[1]];
+    for y in 1..9 {
+        for x in 1..9 {
+            if !(x >= 3 && x < 6 && y >= 3 && y < 6) {
+                image[x + (y * size[0])] = true;
+            }
+        }
+        for x in 12..18 {
+            image[x + (y * size[0])] = true;
+        }
+    }
+    let write_ids = |error_threshold: f64| {
+        let trace_params = ::TraceParams {
+            error_threshold: error_threshold,
+            .. Default::default()
+        };
+        let output = ::trace_image_to_drawing_with_hooks(&trace_params, &image, &size, 0.75, &());
+        assert!(output.drawing.paths.iter().all(|path| path.meta.hash.is_some()));
+        let mut data: Vec<u8> = vec![];
+        curve_write::svg::write_drawing_filled(&mut data, &size, 1.0, &output.drawing, true)
+            .unwrap();
+        let text = String::from_utf8(data).unwrap();
+        text.lines().filter(|line| line.contains("<path")).map(|line| {
+            assert!(line.starts_with("    <path id='path-"));
+            line[..(line.find(" data-hash").unwrap())].to_string()
+        }).collect::<Vec<String>>()
+    };
+    // The hole is written with the path containing it,
+    // IDs don't depend on the fit.
+    let ids = write_ids(0.5);
+    assert!(ids.len() == 2 && ids[0] != ids[1]);
+    assert!(ids == write_ids(4.0));
+}
+
+#[test]
+fn test_num_format() {
+    use curve_write::Num;
+
+    assert!(Num::new(12.0, 2).to_string() == "12.00");
+    // Small values are never written in scientific notation.
+    assert!(Num::new(0.00001, 6).to_string() == "0.000010");
+    assert!(Num::exact(0.00001).to_string() == "0.00001");
+    assert!(Num::exact(1e21).to_string() == "1000000000000000000000");
+    // Values which round to zero are written without a sign.
+    assert!(Num::new(-0.001, 2).to_string() == "0.00");
+    assert!(Num::new(-0.0, 2).to_string() == "0.00");
+    assert!(Num::exact(-0.0).to_string() == "0");
+    assert!(Num::new(-0.006, 2).to_string() == "-0.01");
+    assert!(Num::exact(-0.5).to_string() == "-0.5");
+}
+
+#[test]
+fn test_argparse_text_wrap() {
+    use ::intern::argparse::text_wrap;
+
+    assert!(text_wrap("one two three four", 9) == vec!["one two", "three", "four"]);
+    // Widths are measured in characters (not bytes).
+    assert!(text_wrap("über ünder", 10) == vec!["über ünder"]);
+    // Words longer than the width aren't split.
+    assert!(text_wrap("a --very-long-option b", 6) == vec!["a", "--very-long-option", "b"]);
+    assert!(text_wrap("", 6).is_empty());
+}
+
+#[test]
+fn test_argparse_rules() {
+    use ::intern::argparse;
+
+    let parse = |args: &[&str]| {
+        let mut mode = String::new();
+        let mut parser = argparse::new(&mut mode, "");
+        parser.add_argument(
+            "-m", "--mode", "", "MODE",
+            Box::new(|dest_data, my_args| {
+                *dest_data = my_args[0].clone();
+                return Ok(1);
+            }),
+            1, argparse::ARGDEF_DEFAULT, None,
+        );
+        for id in &["--fill", "--scale", "--passes", "--thin"] {
+            parser.add_argument(
+                "", id, "", "",
+                Box::new(|_dest_data, _my_args| Ok(0)),
+                0, argparse::ARGDEF_DEFAULT, None,
+            );
+        }
+        parser.add_conflicts("--fill", &["--mode"], Some("CENTER"));
+        parser.add_requires("--scale", &["--passes"], None);
+        parser.add_requires("--thin", &["--mode"], Some("CENTER"));
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        parser.parse(&args)
+    };
+
+    assert!(parse(&["--fill", "-m", "OUTLINE"]).is_ok());
+    assert!(parse(&["--fill", "-m", "CENTER"]) ==
+            Err("Error: '--fill' can't be used with '--mode CENTER'!".to_string()));
+    assert!(parse(&["--scale", "--passes"]).is_ok());
+    assert!(parse(&["--scale"]) == Err("Error: '--scale' requires '--passes'!".to_string()));
+    assert!(parse(&["--thin", "-m", "CENTER"]).is_ok());
+    for args in &[&["--thin"][..], &["--thin", "-m", "OUTLINE"][..]] {
+        assert!(parse(args) == Err("Error: '--thin' requires '--mode CENTER'!".to_string()));
+    }
+    // Missing parameters are an error (not a panic).
+    assert!(parse(&["--fill", "-m"]) ==
+            Err("Error '-m' expected 1 parameters, received 0!".to_string()));
+}
+
+#[test]
+fn test_argparse_applies() {
+    use ::intern::argparse;
+
+    let parse = |args: &[&str]| {
+        let mut scale = 0.0_f64;
+        let mut parser = argparse::new(&mut scale, "");
+        parser.add_argument(
+            "", "--scale", "", "SCALE",
+            Box::new(|dest_data, my_args| {
+                *dest_data = my_args[0].parse().unwrap();
+                return Ok(1);
+            }),
+            1, argparse::ARGDEF_DEFAULT, None,
+        );
+        parser.add_argument(
+            "", "--round", "", "",
+            Box::new(|_dest_data, _my_args| Ok(0)),
+            0, argparse::ARGDEF_DEFAULT, None,
+        );
+        parser.add_applies("--round", "without --scale", |scale| *scale != 0.0);
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        assert!(parser.parse(&args).is_ok());
+        parser.warnings().to_vec()
+    };
+
+    assert!(parse(&["--scale", "2", "--round"]).is_empty());
+    assert!(parse(&["--scale", "2"]).is_empty());
+    assert!(parse(&["--round"]) ==
+            vec!["Warning: '--round' has no effect without --scale".to_string()]);
+}
+
+#[test]
+fn test_multiscale() {
+    use polys_multiscale::{
+        curve_join_spans,
+        image_downscale,
+        poly_split_spans,
+    };
+
+    // Blocks are filled when at least half their pixels are (clipped at the bounds).
+    let image = [
+        true, true, false, true, true,
+        true, false, false, false, false,
+    ];
+    let (image_small, size_small) = image_downscale(&image, &[5, 2], 2);
+    assert!(size_small == [3, 1]);
+    assert!(image_small == [true, false, true]);
+
+    // Cyclic spans wrap around to the first split, open spans keep their end-points.
+    let poly = [0, 1, 2, 3, 4, 5];
+    assert!(poly_split_spans(true, &poly, &[1, 4]) ==
+            Some(vec![vec![1, 2, 3, 4], vec![4, 5, 0, 1]]));
+    assert!(poly_split_spans(true, &poly, &[1]) == None);
+    assert!(poly_split_spans(false, &poly, &[0, 2, 5]) ==
+            Some(vec![vec![0, 1, 2], vec![2, 3, 4, 5]]));
+    assert!(poly_split_spans(false, &poly, &[0, 5]) == None);
+
+    // Handles are aligned where spans meet, unless they form a corner.
+    let spans = vec![
+        vec![[[0.0, 0.0], [0.0, 0.0], [1.0, 0.0]], [[2.0, 0.0], [3.0, 0.0], [3.0, 0.0]]],
+        vec![[[3.0, 0.0], [3.0, 0.0], [4.0, 1.0]], [[5.0, 0.0], [6.0, 0.0], [6.0, 0.0]]],
+    ];
+    let curve = curve_join_spans(false, spans.clone(), 60.0_f64.to_radians());
+    assert!(curve.len() == 3);
+    let (h_prev, h_next) = ([3.0 - curve[1][0][0], -curve[1][0][1]],
+                            [curve[1][2][0] - 3.0, curve[1][2][1]]);
+    assert!(h_prev[1] > 0.0 && ((h_prev[0] * h_next[1]) - (h_prev[1] * h_next[0])).abs() < 1e-9);
+    let curve = curve_join_spans(false, spans, 30.0_f64.to_radians());
+    assert!(curve[1] == [[2.0, 0.0], [3.0, 0.0], [4.0, 1.0]]);
+
+    // Knots of the traced circle are on its outline.
+    let size = [64, 64];
+    let image: Vec<bool> = (0..(size[0] * size[1])).map(|i| {
+        let (x, y) = ((i % size[0]) as f64 - 31.5, (i / size[0]) as f64 - 31.5);
+        (x * x) + (y * y) < 28.0 * 28.0
+    }).collect();
+    let trace = |multiscale: usize| {
+        let trace_params = ::TraceParams {
+            multiscale: multiscale,
+            .. Default::default()
+        };
+        ::trace_image_to_drawing_with_hooks(&trace_params, &image, &size, 0.75, &()).drawing
+    };
+    let drawing = trace(4);
+    assert!(drawing.paths.len() == 1 && trace(1).paths.len() == 1);
+    let path = drawing.paths.front().unwrap();
+    assert!(path.cyclic && path.knots.len() >= 4);
+    for knot in &path.knots {
+        let radius = ((knot.co[0] - 32.0).powi(2) + (knot.co[1] - 32.0).powi(2)).sqrt();
+        assert!((radius - 28.0).abs() < 1.0);
+    }
+}
+
+#[test]
+fn test_error_eval() {
+    use std::sync::Arc;
+    use std::sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    };
+    use curve_fit_nd::{
+        Cubic,
+        ErrorEval,
+        ErrorEvalScalar,
+        WrapSlice,
+    };
+
+    // Counts each evaluation, using the default to measure the error.
+    struct ErrorEvalCount {
+        calls_len: AtomicUsize,
+    }
+    impl ErrorEval for ErrorEvalCount {
+        fn cubic_error_max_sq(
+            &self,
+            cubic: &Cubic,
+            points: WrapSlice<[f64; 2]>,
+            u: &[f64],
+            error_max_sq_limit: f64,
+        ) -> Option<(f64, usize)> {
+            self.calls_len.fetch_add(1, Ordering::Relaxed);
+            return ErrorEvalScalar.cubic_error_max_sq(cubic, points, u, error_max_sq_limit);
+        }
+    }
+    struct Hooks {
+        error_eval: Arc<ErrorEvalCount>,
+    }
+    impl ::TraceHooks for Hooks {
+        fn error_eval(&self) -> Option<Arc<dyn ErrorEval>> {
+            return Some(self.error_eval.clone());
+        }
+    }
+
+    let size = [32, 32];
+    let image: Vec<bool> = (0..(size[0] * size[1])).map(|i| {
+        let (x, y) = ((i % size[0]) as f64 - 15.5, (i / size[0]) as f64 - 15.5);
+        (x * x) + (y * 2.0).powi(2) < 14.0 * 14.0
+    }).collect();
+    let trace_params = ::TraceParams::default();
+    let hooks = Hooks { error_eval: Arc::new(ErrorEvalCount { calls_len: AtomicUsize::new(0) }) };
+    let knots_co = |drawing: ::curve_types::Drawing| {
+        drawing.paths.iter().flat_map(|path| path.knots.iter().map(|knot| knot.co))
+            .collect::<Vec<[f64; 2]>>()
+    };
+    let co_default = knots_co(::trace_image_to_drawing(&trace_params, &image, &size, 0.75).drawing);
+    let co_hooks = knots_co(
+        ::trace_image_to_drawing_with_hooks(&trace_params, &image, &size, 0.75, &hooks).drawing);
+    assert!(co_default == co_hooks);
+    assert!(hooks.error_eval.calls_len.load(Ordering::Relaxed) != 0);
+}
+
+#[test]
+fn test_report() {
+    use curve_write::html::base64_encode;
+    use std::collections::LinkedList;
+    use std::io::Read;
+
+    assert!(base64_encode(b"") == "");
+    assert!(base64_encode(b"f") == "Zg==");
+    assert!(base64_encode(b"fo") == "Zm8=");
+    assert!(base64_encode(b"foo") == "Zm9v");
+    assert!(base64_encode(b"foob") == "Zm9vYg==");
+    assert!(base64_encode(&[0xff, 0xfe]) == "//4=");
+
+    // Points on either side of a line, as well as past its end.
+    let mut poly_list_src: LinkedList<(bool, Vec<[f64; 2]>)> = LinkedList::new();
+    poly_list_src.push_back((false, vec![[0.0, 1.0], [5.0, -0.5], [12.0, 0.0], [40.0, 0.0]]));
+    let mut poly_list_dst: LinkedList<(bool, Vec<[f64; 2]>)> = LinkedList::new();
+    poly_list_dst.push_back((false, vec![[0.0, 0.0], [10.0, 0.0]]));
+    let distances = ::polys_utils::poly_list_distances(&poly_list_src, &poly_list_dst, 1.0);
+    assert!(distances == vec![1.0, 0.5, 2.0, 30.0]);
+
+    // A filled circle.
+    let size = [24, 24];
+    let image: Vec<bool> = (0..(size[0] * size[1])).map(|i| {
+        let (x, y) = ((i % size[0]) as f64 - 11.5, (i / size[0]) as f64 - 11.5);
+        (x * x) + (y * y) < 64.0
+    }).collect();
+    let dirpath = ::std::env::temp_dir();
+    let output_filepath = dirpath.join("raster_retrace_test_report.svg");
+    let report_filepath = dirpath.join("raster_retrace_test_report.html");
+    let trace_params = ::TraceParams {
+        output_filepath: output_filepath.to_string_lossy().into_owned(),
+        report_filepath: report_filepath.to_string_lossy().into_owned(),
+        .. Default::default()
+    };
+    let output = ::trace_image(&trace_params, &image, &size, 0.75).unwrap();
+    let mut text = String::new();
+    ::std::fs::File::open(&report_filepath).unwrap().read_to_string(&mut text).unwrap();
+    ::std::fs::remove_file(&output_filepath).unwrap();
+    ::std::fs::remove_file(&report_filepath).unwrap();
+
+    let stages: Vec<&str> = output.timings.iter().map(|&(name, _)| name).collect();
+    assert!(stages == vec!["extract", "fit", "post-process", "write"]);
+    assert!(!output.fit_errors.is_empty());
+    assert!(output.fit_errors.iter().all(|&error| error <= trace_params.error_threshold));
+    assert!(text.starts_with("<!DOCTYPE html>"));
+    assert!(text.contains("src='data:image/png;base64,iVBORw0KGgo"));
+    assert!(text.contains("src='data:image/svg+xml;base64,"));
+    assert!(text.contains("<tr><th>report</th>"));
+}
+
+#[test]
+fn test_error_map() {
+    use std::io::Read;
+
+    // A filled circle.
+    let size = [24, 24];
+    let image: Vec<bool> = (0..(size[0] * size[1])).map(|i| {
+        let (x, y) = ((i % size[0]) as f64 - 11.5, (i / size[0]) as f64 - 11.5);
+        (x * x) + (y * y) < 64.0
+    }).collect();
+    let dirpath = ::std::env::temp_dir();
+    let output_filepath = dirpath.join("raster_retrace_test_error_map.svg");
+    let error_map_filepath = dirpath.join("raster_retrace_test_error_map.pgm");
+    let trace_params = ::TraceParams {
+        output_filepath: output_filepath.to_string_lossy().into_owned(),
+        error_map_filepath: error_map_filepath.to_string_lossy().into_owned(),
+        .. Default::default()
+    };
+    ::trace_image(&trace_params, &image, &size, 0.75).unwrap();
+    let mut data: Vec<u8> = vec![];
+    ::std::fs::File::open(&error_map_filepath).unwrap().read_to_end(&mut data).unwrap();
+    ::std::fs::remove_file(&output_filepath).unwrap();
+    ::std::fs::remove_file(&error_map_filepath).unwrap();
+
+    let header = b"P5\n24 24\n255\n";
+    assert!(data.starts_with(header));
+    let pixels = &data[header.len()..];
+    assert!(pixels.len() == size[0] * size[1]);
+    // Only pixels along the boundary are set, some of which have an error.
+    for (i, &value) in pixels.iter().enumerate() {
+        if value != 0 {
+            let (x, y) = ((i % size[0]) as f64 - 11.5, (i / size[0]) as f64 - 11.5);
+            let radius = ((x * x) + (y * y)).sqrt();
+            assert!(radius > 6.0 && radius < 10.0);
+        }
+    }
+    assert!(pixels.iter().any(|&value| value > 1));
+}
+
+#[test]
+fn test_debug_pass_direction() {
+    use curve_types::{
+        Drawing,
+        Path,
+    };
+
+    assert!(::debug_pass::kind_from_names("TANGENT,DIRECTION") ==
+            Ok(::debug_pass::kind::TANGENT | ::debug_pass::kind::DIRECTION));
+
+    // A cyclic triangle (3 segments) & an open line (1 segment).
+    let mut drawing = Drawing::new();
+    drawing.paths.push_back(Path::from_cubic_array(true, &[
+        [[0.0, 0.0], [0.0, 0.0], [0.0, 0.0]],
+        [[0.0, 10.0], [0.0, 10.0], [0.0, 10.0]],
+        [[10.0, 10.0], [10.0, 10.0], [10.0, 10.0]],
+    ]));
+    drawing.paths.push_back(Path::from_cubic_array(false, &[
+        [[20.0, 0.0], [20.0, 0.0], [20.0, 0.0]],
+        [[30.0, 0.0], [30.0, 0.0], [30.0, 0.0]],
+    ]));
+    let mut data: Vec<u8> = vec![];
+    ::curve_write::svg::write_drawing_with_direction_info(&mut data, 1.0, &drawing, 1.0).unwrap();
+    let text = String::from_utf8(data).unwrap();
+    assert!(text.matches("<polygon").count() == 4);
+    assert!(text.matches("<circle").count() == 2);
+    // The line points along +X, centered on its middle.
+    assert!(text.contains("<polygon points='28.00,0.00 22.00,2.40 22.00,-2.40'/>"));
+    assert!(text.contains("<circle cx='20.00' cy='0.00' r='3.00'/>"));
+}